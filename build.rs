@@ -4,6 +4,20 @@ use winres::{VersionInfo, WindowsResource};
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
+/// Declares per-monitor v2 DPI awareness, so Windows reports physical pixels on every monitor instead of scaling
+/// coordinates for us, which would no longer match the pixels of capture items. Windows before 1703 only know
+/// `dpiAware`, and fall back to it.
+const MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<assembly xmlns="urn:schemas-microsoft-com:asm.v1" manifestVersion="1.0">
+  <application xmlns="urn:schemas-microsoft-com:asm.v3">
+    <windowsSettings>
+      <dpiAware xmlns="http://schemas.microsoft.com/SMI/2005/WindowsSettings">true/pm</dpiAware>
+      <dpiAwareness xmlns="http://schemas.microsoft.com/SMI/2016/WindowsSettings">PerMonitorV2</dpiAwareness>
+    </windowsSettings>
+  </application>
+</assembly>
+"#;
+
 fn normalized_version_components(version: &str) -> [u16; 4] {
     let numeric = version.split(|c| c == '-' || c == '+').next().unwrap_or(version);
     let mut parts = [0u16; 4];
@@ -46,7 +60,8 @@ fn set_windows_metadata() -> Result<()> {
         .set("ProductVersion", &version_string)
         .set("FileVersion", &version_string)
         .set_version_info(VersionInfo::PRODUCTVERSION, version_u64)
-        .set_version_info(VersionInfo::FILEVERSION, version_u64);
+        .set_version_info(VersionInfo::FILEVERSION, version_u64)
+        .set_manifest(MANIFEST);
     res.compile()?;
     Ok(())
 }
@@ -0,0 +1,297 @@
+//! Measures the preview path of the UI end to end: capture, readback, the stream, an iced message and the frame
+//! viewer uploading and drawing the frame. Shows the primary monitor for 30 seconds, switching the running
+//! stream from 60 to 120 FPS halfway through, then prints the latency between every two stages, the framerate of
+//! the preview before and after the switch and the frames the stream dropped. Moving windows around while it
+//! runs keeps frames coming. Uses the frame viewer of the app itself, in an application that does nothing but
+//! preview.
+//!
+//! Usage: `benchmark_preview [monitor index]`, by default the primary monitor. Monitors are numbered in the order
+//! of `enumerate_monitors`, which are listed at startup.
+
+#[cfg(target_os = "windows")]
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+#[cfg(target_os = "windows")]
+use bytes::Bytes;
+#[cfg(target_os = "windows")]
+use futures::{Stream, StreamExt, future, stream};
+#[cfg(target_os = "windows")]
+use iced::{Element, Subscription, Task, widget::text};
+#[cfg(target_os = "windows")]
+use loki::{
+    capture::{
+        CaptureFramerate, CaptureSession, CaptureSessionBuilder, Frame, FrameTracer, Source, Stage,
+        StreamStats, Vector2, enumerate_monitors,
+    },
+    widgets::frame_viewer::FrameViewer,
+};
+#[cfg(target_os = "windows")]
+use tokio::sync::watch;
+
+#[cfg(target_os = "windows")]
+const BENCHMARK_DURATION: Duration = Duration::from_secs(30);
+/// When the stream switches from the initial framerate to the final one.
+#[cfg(target_os = "windows")]
+const SWITCH_AFTER: Duration = Duration::from_secs(15);
+#[cfg(target_os = "windows")]
+const INITIAL_FRAMERATE: CaptureFramerate = CaptureFramerate::FPS60;
+#[cfg(target_os = "windows")]
+const FINAL_FRAMERATE: CaptureFramerate = CaptureFramerate::FPS120;
+
+/// Shared by the capture session and the viewer, which are created in different places by iced.
+#[cfg(target_os = "windows")]
+static TRACER: LazyLock<Arc<StageTracer>> = LazyLock::new(|| Arc::new(StageTracer::default()));
+/// Stats of the stream, which is moved into the subscription.
+#[cfg(target_os = "windows")]
+static STREAM_STATS: OnceLock<watch::Receiver<StreamStats>> = OnceLock::new();
+/// When the framerate of the stream was switched.
+#[cfg(target_os = "windows")]
+static SWITCHED_AT: OnceLock<Instant> = OnceLock::new();
+/// What to capture, from the command line.
+#[cfg(target_os = "windows")]
+static SOURCE: OnceLock<Source> = OnceLock::new();
+
+/// When every frame passed each stage, by sequence number.
+#[cfg(target_os = "windows")]
+#[derive(Debug, Default)]
+struct StageTracer {
+    frames: Mutex<HashMap<u64, [Option<Instant>; Stage::ALL.len()]>>,
+}
+
+#[cfg(target_os = "windows")]
+impl FrameTracer for StageTracer {
+    fn record(&self, sequence: u64, stage: Stage, at: Instant) {
+        let mut frames = self.frames.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        frames.entry(sequence).or_default()[stage as usize] = Some(at);
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl StageTracer {
+    fn report(&self) {
+        let frames = self.frames.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut drawn: Vec<Instant> =
+            frames.values().filter_map(|stages| stages[Stage::Drawn as usize]).collect();
+        drawn.sort();
+        println!("{} of {} frames drawn", drawn.len(), frames.len());
+        match SWITCHED_AT.get() {
+            Some(switched_at) => {
+                let after = drawn.partition_point(|drawn| drawn < switched_at);
+                print_framerate(&format!("  At {} FPS", INITIAL_FRAMERATE), &drawn[..after]);
+                print_framerate(&format!("  At {} FPS", FINAL_FRAMERATE), &drawn[after..]);
+            }
+            None => {
+                println!("  The framerate was never switched");
+                print_framerate(&format!("  At {} FPS", INITIAL_FRAMERATE), &drawn);
+            }
+        }
+
+        let latencies = |from: Stage, to: Stage| -> Vec<Duration> {
+            frames
+                .values()
+                .filter_map(|stages| {
+                    Some(stages[to as usize]?.saturating_duration_since(stages[from as usize]?))
+                })
+                .collect()
+        };
+        for pair in Stage::ALL.windows(2) {
+            print_latency(
+                &format!("  {:?} to {:?}", pair[0], pair[1]),
+                latencies(pair[0], pair[1]),
+            );
+        }
+        print_latency("  Arrived to Drawn", latencies(Stage::Arrived, Stage::Drawn));
+    }
+}
+
+/// The framerate of the preview over a phase of the benchmark, from the sorted times frames were drawn at.
+#[cfg(target_os = "windows")]
+fn print_framerate(label: &str, drawn: &[Instant]) {
+    match (drawn.first(), drawn.last()) {
+        (Some(first), Some(last)) if drawn.len() > 1 => {
+            let seconds = last.duration_since(*first).as_secs_f64();
+            println!(
+                "{}: {} frames drawn in {:.1} s, {:.1} fps",
+                label,
+                drawn.len(),
+                seconds,
+                (drawn.len() - 1) as f64 / seconds.max(f64::EPSILON)
+            );
+        }
+        _ => println!("{}: too few frames drawn to tell a framerate", label),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn print_latency(label: &str, mut latencies: Vec<Duration>) {
+    if latencies.is_empty() {
+        println!("{}: no frames", label);
+        return;
+    }
+    latencies.sort();
+    // Nearest rank, same as the stats of the provider.
+    let percentile = |p: f64| latencies[((latencies.len() as f64 * p).ceil() as usize).max(1) - 1];
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    println!(
+        "{}: p50 {:.2} ms, p95 {:.2} ms over {} frames",
+        label,
+        ms(percentile(0.5)),
+        ms(percentile(0.95)),
+        latencies.len()
+    );
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Clone)]
+enum Message {
+    Frame(Frame),
+    /// The stream switched to the final framerate.
+    Switched,
+    Failed(String),
+    Finished,
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Default)]
+struct Preview {
+    /// Tightly packed RGBA, the size and the sequence number of the latest frame.
+    frame: Option<(Bytes, Vector2<i32>, u64)>,
+    generation: u64,
+    error: Option<String>,
+}
+
+#[cfg(target_os = "windows")]
+impl Preview {
+    fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::Frame(frame) => {
+                let (size, sequence) = (frame.size, frame.sequence);
+                self.frame = Some((frame.into_tightly_packed_rgba(), size, sequence));
+                self.generation = self.generation.wrapping_add(1);
+                Task::none()
+            }
+            Message::Switched => Task::none(),
+            Message::Failed(err) => {
+                eprintln!("{}", err);
+                self.error = Some(err);
+                Task::none()
+            }
+            Message::Finished => {
+                TRACER.report();
+                if let Some(stats) = STREAM_STATS.get() {
+                    let stats = *stats.borrow();
+                    println!(
+                        "Stream: {} frames queued, {} dropped while full, {} dropped after closing",
+                        stats.delivered, stats.dropped_full, stats.dropped_closed
+                    );
+                }
+                iced::exit()
+            }
+        }
+    }
+
+    fn view(&self) -> Element<'_, Message> {
+        match &self.frame {
+            Some((data, size, sequence)) => {
+                FrameViewer::new(data.clone(), size.x as u32, size.y as u32, self.generation)
+                    .traced(TRACER.clone(), *sequence)
+                    .into()
+            }
+            None => text(self.error.as_deref().unwrap_or("Waiting for the first frame...")).into(),
+        }
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        Subscription::batch([
+            Subscription::run(frames),
+            iced::time::every(BENCHMARK_DURATION).map(|_| Message::Finished),
+        ])
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn frames() -> impl Stream<Item = Message> {
+    let source = SOURCE.get().cloned().unwrap_or(Source::PrimaryMonitor);
+    let session = CaptureSessionBuilder::new(source)
+        .with_framerate(INITIAL_FRAMERATE)
+        .with_frame_tracer(TRACER.clone())
+        .build();
+    match session {
+        Ok(session) => {
+            if let Some(adapter) = session.adapter_info() {
+                println!("Capturing on {}", adapter);
+            }
+            let _ = STREAM_STATS.set(session.watch_stream_stats());
+            switch_framerate_midway(session)
+                .chain(stream::once(future::ready(Message::Failed("Capture ended early".into()))))
+                .left_stream()
+        }
+        Err(err) => stream::once(future::ready(Message::Failed(format!(
+            "Failed to start capture: {}",
+            err
+        ))))
+        .right_stream(),
+    }
+}
+
+/// Yields the frames of the session, switching it to the final framerate after `SWITCH_AFTER` without creating
+/// another stream.
+#[cfg(target_os = "windows")]
+fn switch_framerate_midway(session: CaptureSession) -> impl Stream<Item = Message> {
+    let switch = Box::pin(tokio::time::sleep(SWITCH_AFTER));
+    stream::unfold((session, Some(switch)), |(mut session, mut switch)| async move {
+        let Some(pending) = switch.as_mut() else {
+            let frame = session.next().await?;
+            return Some((Message::Frame(frame), (session, None)));
+        };
+        tokio::select! {
+            _ = pending => {
+                let message = match session.set_framerate(FINAL_FRAMERATE) {
+                    Ok(()) => {
+                        let _ = SWITCHED_AT.set(Instant::now());
+                        println!("Switched from {} to {} FPS", INITIAL_FRAMERATE, FINAL_FRAMERATE);
+                        Message::Switched
+                    }
+                    Err(err) => Message::Failed(format!("Failed to switch the framerate: {}", err)),
+                };
+                Some((message, (session, None)))
+            }
+            frame = session.next() => Some((Message::Frame(frame?), (session, switch))),
+        }
+    })
+}
+
+#[cfg(target_os = "windows")]
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::WARN).init();
+
+    let monitors = enumerate_monitors();
+    for (index, monitor) in monitors.iter().enumerate() {
+        println!("Monitor {}: {}, {} x {}", index, monitor.name, monitor.size.x, monitor.size.y);
+    }
+    let source = match std::env::args().nth(1) {
+        Some(index) => {
+            let index = index.parse::<usize>()?;
+            let monitor = monitors.get(index).ok_or("There is no monitor with that index")?;
+            println!("Benchmarking monitor {}, {}", index, monitor.name);
+            Source::MonitorIndex(index)
+        }
+        None => Source::PrimaryMonitor,
+    };
+    let _ = SOURCE.set(source);
+
+    iced::application(Preview::default, Preview::update, Preview::view)
+        .subscription(Preview::subscription)
+        .title("Preview benchmark")
+        .run()?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example needs Windows.Graphics.Capture, it only runs on Windows.");
+}
@@ -0,0 +1,60 @@
+//! Captures the primary monitor at 60 FPS, switches the running stream to 120 FPS halfway through, and prints
+//! the framerate delivered before and after, showing the change applies without creating a new stream.
+//! Frames only arrive while something changes on screen, so move windows around or play a video while it runs.
+
+#[cfg(target_os = "windows")]
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "windows")]
+use futures::StreamExt;
+#[cfg(target_os = "windows")]
+use loki::capture::{CaptureFramerate, CaptureSessionBuilder, Source};
+
+#[cfg(target_os = "windows")]
+const PHASE_DURATION: Duration = Duration::from_secs(5);
+
+#[cfg(target_os = "windows")]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::WARN).init();
+
+    let mut session = CaptureSessionBuilder::new(Source::PrimaryMonitor)
+        .with_framerate(CaptureFramerate::FPS60)
+        .build()?;
+
+    for framerate in [CaptureFramerate::FPS60, CaptureFramerate::FPS120] {
+        session.set_framerate(framerate)?;
+
+        let started = Instant::now();
+        let deadline = tokio::time::sleep(PHASE_DURATION);
+        tokio::pin!(deadline);
+        let mut frames = 0u64;
+        loop {
+            tokio::select! {
+                _ = &mut deadline => break,
+                frame = session.next() => {
+                    if frame.is_none() {
+                        println!("Capture ended early: {:?}", session.end_reason());
+                        return Ok(());
+                    }
+                    frames += 1;
+                }
+            }
+        }
+
+        let seconds = started.elapsed().as_secs_f64();
+        println!(
+            "Set to {}: {} frames in {:.1} s, {:.1} fps",
+            framerate,
+            frames,
+            seconds,
+            frames as f64 / seconds
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example needs Windows.Graphics.Capture, it only runs on Windows.");
+}
@@ -0,0 +1,76 @@
+//! Captures the primary monitor into RGBA8 with the channels reordered on the CPU and then on the GPU, printing
+//! the latency distribution and the framerate of each so the two can be compared. Moving windows around while it
+//! runs keeps frames coming.
+
+#[cfg(target_os = "windows")]
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "windows")]
+use futures::StreamExt;
+#[cfg(target_os = "windows")]
+use loki::capture::{
+    CaptureFramerate, CaptureSessionBuilder, ConversionPolicy, LatencyStats, PixelFormat, Source,
+};
+
+#[cfg(target_os = "windows")]
+const CAPTURE_DURATION: Duration = Duration::from_secs(5);
+
+#[cfg(target_os = "windows")]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::WARN).init();
+
+    for (label, gpu_conversion) in [("CPU", false), ("GPU", true)] {
+        let mut session = CaptureSessionBuilder::new(Source::PrimaryMonitor)
+            .with_framerate(CaptureFramerate::FPS60)
+            .with_output_format(PixelFormat::RGBA8)
+            .with_conversion_policy(ConversionPolicy::Eager)
+            .with_gpu_conversion(gpu_conversion)
+            .build()?;
+
+        let started = Instant::now();
+        let deadline = tokio::time::sleep(CAPTURE_DURATION);
+        tokio::pin!(deadline);
+        let mut frames = 0u64;
+        loop {
+            tokio::select! {
+                _ = &mut deadline => break,
+                frame = session.next() => {
+                    if frame.is_none() {
+                        println!("Capture ended early: {:?}", session.end_reason());
+                        break;
+                    }
+                    frames += 1;
+                }
+            }
+        }
+
+        let elapsed = started.elapsed().as_secs_f64();
+        println!(
+            "{} conversion: {} frames, {:.1} fps, {} dropped",
+            label,
+            frames,
+            frames as f64 / elapsed,
+            session.dropped_frames()
+        );
+        print_latency("  FrameArrived to delivery", session.stats().delivery_latency);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn print_latency(label: &str, latency: LatencyStats) {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    println!(
+        "{}: p50 {:.2} ms, p95 {:.2} ms, max {:.2} ms",
+        label,
+        ms(latency.p50),
+        ms(latency.p95),
+        ms(latency.max)
+    );
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example needs Windows.Graphics.Capture, it only runs on Windows.");
+}
@@ -0,0 +1,106 @@
+//! Captures the primary monitor for five seconds without any UI, printing frame stats along the way and the
+//! pacing of the frames at the end.
+
+#[cfg(target_os = "windows")]
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(target_os = "windows")]
+use futures::StreamExt;
+#[cfg(target_os = "windows")]
+use loki::capture::{CaptureFramerate, CaptureSessionBuilder, Frame, Source};
+
+#[cfg(target_os = "windows")]
+const CAPTURE_DURATION: Duration = Duration::from_secs(5);
+
+#[cfg(target_os = "windows")]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::INFO).init();
+
+    let mut session = CaptureSessionBuilder::new(Source::PrimaryMonitor)
+        .with_framerate(CaptureFramerate::FPS30)
+        .build()?;
+    if let Some(info) = session.capture_item_info() {
+        println!("Capturing {}", info);
+    }
+
+    let started = Instant::now();
+    let deadline = tokio::time::sleep(CAPTURE_DURATION);
+    tokio::pin!(deadline);
+
+    let mut frames = 0u64;
+    let mut bytes = 0usize;
+    let mut previous: Option<Frame> = None;
+    let mut intervals = Vec::new();
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            frame = session.next() => {
+                let Some(frame) = frame else {
+                    println!("Capture ended early: {:?}", session.end_reason());
+                    break;
+                };
+                frames += 1;
+                bytes += frame.data.len();
+                println!(
+                    "Frame {}: {} x {} {:?}, {} bytes",
+                    frame.sequence,
+                    frame.size.x,
+                    frame.size.y,
+                    frame.format,
+                    frame.data.len()
+                );
+                if let Some(previous) = &previous {
+                    intervals.push(frame.since(previous));
+                }
+                previous = Some(frame);
+            }
+        }
+    }
+
+    let elapsed = started.elapsed().as_secs_f64();
+    println!(
+        "Received {} frames ({} MiB) in {:.2}s, {:.1} fps, {} dropped",
+        frames,
+        bytes / (1024 * 1024),
+        elapsed,
+        frames as f64 / elapsed,
+        session.dropped_frames()
+    );
+    if let Some(last) = &previous {
+        let age = SystemTime::now().duration_since(last.wall_clock()).unwrap_or_default();
+        println!("Last frame captured {:.1} ms before the end", age.as_secs_f64() * 1000.0);
+    }
+    print_pacing(intervals);
+    let stats = session.stats();
+    println!(
+        "Buffer pool: {} reused, {} allocated, {} KiB idle",
+        stats.buffer_pool_hits,
+        stats.buffer_pool_misses,
+        stats.buffer_pool_bytes / 1024
+    );
+    println!("{:?}", stats);
+    Ok(())
+}
+
+/// The time between consecutive frames, from their capture times. Jitter shows as a spread between the two.
+#[cfg(target_os = "windows")]
+fn print_pacing(mut intervals: Vec<Duration>) {
+    if intervals.is_empty() {
+        println!("Too few frames to tell the pacing");
+        return;
+    }
+    intervals.sort();
+    // Nearest rank, same as the stats of the provider.
+    let percentile = |p: f64| intervals[((intervals.len() as f64 * p).ceil() as usize).max(1) - 1];
+    println!(
+        "Frame interval: p50 {:.2} ms, p99 {:.2} ms",
+        percentile(0.5).as_secs_f64() * 1000.0,
+        percentile(0.99).as_secs_f64() * 1000.0
+    );
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example needs Windows.Graphics.Capture, it only runs on Windows.");
+}
@@ -0,0 +1,97 @@
+//! Records five seconds of whatever is playing on the default output device to `loopback.wav`.
+
+#[cfg(target_os = "windows")]
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    time::Duration,
+};
+
+#[cfg(target_os = "windows")]
+use futures::StreamExt;
+#[cfg(target_os = "windows")]
+use loki::{
+    audio_providers::{
+        AudioCaptureProvider, PlatformAudioProvider,
+        shared::{AudioEvent, SampleFormat},
+    },
+    capture::initialize_com,
+};
+
+#[cfg(target_os = "windows")]
+const RECORD_DURATION: Duration = Duration::from_secs(5);
+#[cfg(target_os = "windows")]
+const SAMPLE_RATE: u32 = 48_000;
+
+/// Writes the RIFF header of a 32-bit float WAV file. The sizes are patched in once all samples are written.
+#[cfg(target_os = "windows")]
+fn write_wav_header(out: &mut impl Write, channels: u16, data_len: u32) -> std::io::Result<()> {
+    const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+    let block_align = channels * 4;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&WAVE_FORMAT_IEEE_FLOAT.to_le_bytes())?;
+    out.write_all(&channels.to_le_bytes())?;
+    out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    out.write_all(&(SAMPLE_RATE * block_align as u32).to_le_bytes())?;
+    out.write_all(&block_align.to_le_bytes())?;
+    out.write_all(&32u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())
+}
+
+#[cfg(target_os = "windows")]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::INFO).init();
+    initialize_com()?;
+
+    let mut provider = PlatformAudioProvider::new();
+    let mut stream = provider.create_stream(SampleFormat::F32, SAMPLE_RATE)?;
+
+    let mut out = BufWriter::new(File::create("loopback.wav")?);
+    let mut channels = 2;
+    let mut data_len = 0u32;
+    write_wav_header(&mut out, channels, data_len)?;
+
+    let deadline = tokio::time::sleep(RECORD_DURATION);
+    tokio::pin!(deadline);
+    let mut first_timestamp = None;
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            event = stream.next() => match event {
+                Some(AudioEvent::Packet(packet)) => {
+                    let first = *first_timestamp.get_or_insert(packet.timestamp);
+                    println!(
+                        "Packet at +{:.1} ms: {} frames{}",
+                        (packet.timestamp - first) as f64 / 10_000.0,
+                        packet.frame_count(),
+                        if packet.discontinuity { ", discontinuity" } else { "" }
+                    );
+                    channels = packet.channels;
+                    data_len += packet.data.len() as u32;
+                    out.write_all(&packet.data)?;
+                }
+                Some(AudioEvent::Ended(err)) => {
+                    println!("Capture ended: {}", err);
+                    break;
+                }
+                None => break,
+            }
+        }
+    }
+
+    out.seek(SeekFrom::Start(0))?;
+    write_wav_header(&mut out, channels, data_len)?;
+    out.flush()?;
+    println!("Wrote {} bytes of audio to loopback.wav", data_len);
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example records through WASAPI, so it only runs on Windows.");
+}
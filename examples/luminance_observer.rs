@@ -0,0 +1,149 @@
+//! Previews the primary monitor while a frame observer computes the average luminance of every frame on the
+//! capture thread, next to the stream the preview is drawn from. The luminance is shown above the preview.
+
+#[cfg(target_os = "windows")]
+use std::sync::{
+    Arc, LazyLock,
+    atomic::{AtomicU32, Ordering},
+};
+
+#[cfg(target_os = "windows")]
+use bytes::Bytes;
+#[cfg(target_os = "windows")]
+use futures::{Stream, StreamExt, future, stream};
+#[cfg(target_os = "windows")]
+use iced::{
+    Element, Length, Subscription, Task,
+    widget::{column, text},
+};
+#[cfg(target_os = "windows")]
+use loki::{
+    capture::{CaptureFramerate, CaptureSessionBuilder, Frame, PixelFormat, Source, Vector2},
+    widgets::frame_viewer::FrameViewer,
+};
+
+/// Every this many pixels in both directions are sampled, which keeps the observer well under its budget.
+#[cfg(target_os = "windows")]
+const SAMPLE_STEP: usize = 4;
+
+/// Average luminance of the latest frame from 0 to 1, as the bits of an `f32`. Written by the observer on the
+/// capture thread and read by the UI.
+#[cfg(target_os = "windows")]
+static LUMINANCE: LazyLock<Arc<AtomicU32>> = LazyLock::new(|| Arc::new(AtomicU32::new(0)));
+
+/// Rec. 709 luma of the sampled pixels, or `None` for formats other than 8 bit RGBA and BGRA.
+#[cfg(target_os = "windows")]
+fn average_luminance(frame: &Frame) -> Option<f32> {
+    let (red, blue) = match frame.format {
+        PixelFormat::RGBA8 => (0, 2),
+        PixelFormat::BGRA8 => (2, 0),
+        _ => return None,
+    };
+    let width = frame.size.x.max(0) as usize;
+    let height = frame.size.y.max(0) as usize;
+    let mut sum = 0u64;
+    let mut samples = 0u64;
+    for y in (0..height).step_by(SAMPLE_STEP) {
+        let row = &frame.data[y * frame.stride..][..width * 4];
+        for pixel in row.chunks_exact(4).step_by(SAMPLE_STEP) {
+            // Weights scaled to add up to 10000, so the sum stays in integers.
+            sum += pixel[red] as u64 * 2126 + pixel[1] as u64 * 7152 + pixel[blue] as u64 * 722;
+            samples += 1;
+        }
+    }
+    (samples > 0).then(|| sum as f32 / (samples as f32 * 10000.0 * 255.0))
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Clone)]
+enum Message {
+    Frame(Frame),
+    Failed(String),
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Default)]
+struct Preview {
+    /// Tightly packed RGBA and the size of the latest frame.
+    frame: Option<(Bytes, Vector2<i32>)>,
+    generation: u64,
+    error: Option<String>,
+}
+
+#[cfg(target_os = "windows")]
+impl Preview {
+    fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::Frame(frame) => {
+                let size = frame.size;
+                self.frame = Some((frame.into_tightly_packed_rgba(), size));
+                self.generation = self.generation.wrapping_add(1);
+            }
+            Message::Failed(err) => {
+                eprintln!("{}", err);
+                self.error = Some(err);
+            }
+        }
+        Task::none()
+    }
+
+    fn view(&self) -> Element<'_, Message> {
+        let luminance = f32::from_bits(LUMINANCE.load(Ordering::Relaxed));
+        let preview: Element<'_, Message> = match &self.frame {
+            Some((data, size)) => {
+                FrameViewer::new(data.clone(), size.x as u32, size.y as u32, self.generation).into()
+            }
+            None => text(self.error.as_deref().unwrap_or("Waiting for the first frame...")).into(),
+        };
+        column![text(format!("Average luminance: {:.1} %", luminance * 100.0)), preview]
+            .spacing(10)
+            .padding(10)
+            .width(Length::Fill)
+            .into()
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        Subscription::run(frames)
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn frames() -> impl Stream<Item = Message> {
+    let session = CaptureSessionBuilder::new(Source::PrimaryMonitor)
+        .with_framerate(CaptureFramerate::FPS60)
+        .build();
+    match session {
+        Ok(mut session) => {
+            let luminance = LUMINANCE.clone();
+            session.provider_mut().add_frame_observer(Box::new(move |frame: &Frame| {
+                if let Some(average) = average_luminance(frame) {
+                    luminance.store(average.to_bits(), Ordering::Relaxed);
+                }
+            }));
+            session
+                .map(Message::Frame)
+                .chain(stream::once(future::ready(Message::Failed("Capture ended".into()))))
+                .left_stream()
+        }
+        Err(err) => stream::once(future::ready(Message::Failed(format!(
+            "Failed to start capture: {}",
+            err
+        ))))
+        .right_stream(),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn main() -> iced::Result {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::WARN).init();
+
+    iced::application(Preview::default, Preview::update, Preview::view)
+        .subscription(Preview::subscription)
+        .title("Luminance observer")
+        .run()
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example needs Windows.Graphics.Capture, it only runs on Windows.");
+}
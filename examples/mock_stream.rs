@@ -0,0 +1,60 @@
+//! Streams made up frames from the mock provider for five seconds, then prints how many arrived. Runs without a
+//! GPU or a desktop, to check the stream plumbing and the framerate pacing on their own.
+//! Run with `cargo run --example mock_stream --features mock-provider`.
+
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use loki::capture_providers::{
+    CaptureProvider,
+    mock::{MockCaptureItem, MockCaptureProvider, MockPattern},
+    shared::{CaptureEvent, CaptureFramerate, PixelFormat, Vector2},
+};
+
+const CAPTURE_DURATION: Duration = Duration::from_secs(5);
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::INFO).init();
+
+    let item = MockCaptureItem::new(Vector2::new(1920, 1080), PixelFormat::BGRA8)
+        .with_pattern(MockPattern::Checkerboard);
+    let mut provider = MockCaptureProvider::with_item(item)?;
+    let mut stream = provider.create_stream(CaptureFramerate::FPS60)?;
+    provider.start_capture().await?;
+
+    let started = Instant::now();
+    let deadline = tokio::time::sleep(CAPTURE_DURATION);
+    tokio::pin!(deadline);
+
+    let mut frames = 0u64;
+    let mut bytes = 0usize;
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            event = stream.next() => match event {
+                Some(CaptureEvent::Frame(frame)) => {
+                    frames += 1;
+                    bytes += frame.data.len();
+                }
+                Some(event) => println!("{:?}", event),
+                None => {
+                    println!("Stream ended early");
+                    break;
+                }
+            },
+        }
+    }
+    provider.stop_capture().await?;
+
+    let elapsed = started.elapsed().as_secs_f64();
+    println!(
+        "Received {} frames ({} MiB) in {:.2}s, {:.1} fps",
+        frames,
+        bytes / (1024 * 1024),
+        elapsed,
+        frames as f64 / elapsed
+    );
+    println!("{:?}", provider.stats());
+    Ok(())
+}
@@ -0,0 +1,78 @@
+//! Captures the primary monitor at 60 FPS and a window at 30 FPS at the same time on one provider, each source
+//! with its own session and stream, then prints the framerate each stream got. Frames only arrive while something
+//! changes, so move windows around or play a video while it runs.
+//!
+//! Usage: `multi_source [title]`. The window is the one whose title contains `title`, or the first capturable
+//! window without one.
+
+#[cfg(target_os = "windows")]
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "windows")]
+use futures::StreamExt;
+#[cfg(target_os = "windows")]
+use loki::{
+    capture::{
+        CaptureFramerate, CaptureProvider, CaptureStream, TitleMatcher, create_provider,
+        enumerate_capturable_windows,
+    },
+    capture_providers::windows::{
+        create_capture_item_for_primary_monitor, create_capture_item_for_window_title,
+    },
+};
+
+#[cfg(target_os = "windows")]
+const CAPTURE_DURATION: Duration = Duration::from_secs(5);
+
+#[cfg(target_os = "windows")]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::WARN).init();
+
+    let window = match std::env::args().nth(1) {
+        Some(title) => create_capture_item_for_window_title(TitleMatcher::Contains(title))?,
+        None => {
+            let window = enumerate_capturable_windows().into_iter().next();
+            window.ok_or("There is no window to capture")?.to_capture_item()?
+        }
+    };
+    println!("Capturing the primary monitor and {}", window.DisplayName()?);
+
+    let mut provider = create_provider()?;
+    let monitor = provider.add_source(create_capture_item_for_primary_monitor()?)?;
+    let window = provider.add_source(window)?;
+    let sources = [
+        ("Monitor", monitor, CaptureFramerate::FPS60),
+        ("Window", window, CaptureFramerate::FPS30),
+    ];
+    let mut streams = Vec::new();
+    for (label, id, framerate) in sources {
+        streams.push((label, framerate, provider.create_stream_for(id, framerate)?));
+    }
+    provider.start_capture().await?;
+
+    let started = Instant::now();
+    let counters = streams.into_iter().map(|(label, framerate, stream)| async move {
+        let frames = stream.frames_only().take_until(tokio::time::sleep(CAPTURE_DURATION));
+        (label, framerate, frames.count().await)
+    });
+    let counts = futures::future::join_all(counters).await;
+    let elapsed = started.elapsed().as_secs_f64();
+    provider.stop_capture().await?;
+
+    for (label, framerate, frames) in counts {
+        println!(
+            "{}: {} frames, {:.1} FPS of at most {}",
+            label,
+            frames,
+            frames as f64 / elapsed,
+            framerate.fps()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example needs Windows.Graphics.Capture, it only runs on Windows.");
+}
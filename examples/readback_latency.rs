@@ -0,0 +1,91 @@
+//! Captures the primary monitor with a readback depth of 1 and then 2, printing the latency distribution and
+//! framerate of each so the tradeoff between them can be compared. Moving windows around while it runs keeps
+//! frames coming.
+//!
+//! Usage: `readback_latency [pipeline depth] [channel capacity] [buffer pool size]`, by default `2 2 8`. Deeper
+//! pipelines and channels trade latency for throughput, e.g. compare `2` with `4`.
+
+#[cfg(target_os = "windows")]
+use std::time::Duration;
+
+#[cfg(target_os = "windows")]
+use futures::StreamExt;
+#[cfg(target_os = "windows")]
+use loki::capture::{CaptureFramerate, CaptureSessionBuilder, LatencyStats, Source, StreamOptions};
+
+#[cfg(target_os = "windows")]
+const CAPTURE_DURATION: Duration = Duration::from_secs(5);
+
+#[cfg(target_os = "windows")]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::WARN).init();
+
+    let mut args = std::env::args().skip(1).map(|arg| arg.parse::<usize>());
+    let pipeline_depth = args.next().transpose()?.unwrap_or(2);
+    let channel_capacity = args.next().transpose()?.unwrap_or(StreamOptions::default().capacity);
+    let buffer_pool_size = args.next().transpose()?.unwrap_or(8);
+    println!(
+        "Pipeline depth {}, channel capacity {}, buffer pool size {}",
+        pipeline_depth, channel_capacity, buffer_pool_size
+    );
+
+    for depth in [1, 2] {
+        let mut session = CaptureSessionBuilder::new(Source::PrimaryMonitor)
+            .with_framerate(CaptureFramerate::FPS60)
+            .with_readback_depth(depth)
+            .with_pipeline_depth(pipeline_depth)
+            .with_buffer_pool_size(buffer_pool_size)
+            .with_stream_options(StreamOptions {
+                capacity: channel_capacity,
+                ..StreamOptions::default()
+            })
+            .build()?;
+
+        let deadline = tokio::time::sleep(CAPTURE_DURATION);
+        tokio::pin!(deadline);
+        let mut frames = 0u64;
+        loop {
+            tokio::select! {
+                _ = &mut deadline => break,
+                frame = session.next() => {
+                    if frame.is_none() {
+                        println!("Capture ended early: {:?}", session.end_reason());
+                        break;
+                    }
+                    frames += 1;
+                }
+            }
+        }
+
+        let stats = session.stats();
+        println!(
+            "Readback depth {}: {} frames ({:.1} fps), {} readback stalls, {} dropped",
+            depth,
+            frames,
+            frames as f64 / CAPTURE_DURATION.as_secs_f64(),
+            stats.readback_stalls,
+            session.dropped_frames()
+        );
+        print_latency("  FrameArrived to delivery", stats.delivery_latency);
+        print_latency("  Display to delivery", stats.display_latency);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn print_latency(label: &str, latency: LatencyStats) {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    println!(
+        "{}: p50 {:.2} ms, p95 {:.2} ms, max {:.2} ms",
+        label,
+        ms(latency.p50),
+        ms(latency.p95),
+        ms(latency.max)
+    );
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example needs Windows.Graphics.Capture, it only runs on Windows.");
+}
@@ -0,0 +1,76 @@
+//! Captures the primary monitor with tight and then strided readback, printing the framerate, the latency from
+//! FrameArrived to delivery and the stride of the frames of each, so the cost of repacking the rows can be
+//! compared with copying the mapped texture in one go. The difference shows best on monitors whose row pitch has
+//! padding. Moving windows around while it runs keeps frames coming.
+
+#[cfg(target_os = "windows")]
+use std::time::Duration;
+
+#[cfg(target_os = "windows")]
+use futures::StreamExt;
+#[cfg(target_os = "windows")]
+use loki::{
+    capture::{
+        CaptureFramerate, CaptureProvider, CaptureStream, LatencyStats, ReadbackMode,
+        create_provider,
+    },
+    capture_providers::windows::create_capture_item_for_primary_monitor,
+};
+
+#[cfg(target_os = "windows")]
+const CAPTURE_DURATION: Duration = Duration::from_secs(5);
+
+#[cfg(target_os = "windows")]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::WARN).init();
+    loki::capture::initialize_com()?;
+
+    for mode in [ReadbackMode::Tight, ReadbackMode::Strided] {
+        let mut provider = create_provider()?;
+        // Streams take the mode they were created with.
+        provider.set_readback_mode(mode);
+        provider.set_capture_item(create_capture_item_for_primary_monitor()?)?;
+        let stream = provider.create_stream(CaptureFramerate::FPS60)?;
+        provider.start_capture().await?;
+
+        let mut frames =
+            std::pin::pin!(stream.frames_only().take_until(tokio::time::sleep(CAPTURE_DURATION)));
+        let (mut count, mut stride, mut bytes) = (0u64, 0, 0);
+        while let Some(frame) = frames.next().await {
+            count += 1;
+            stride = frame.stride;
+            bytes = frame.data.len();
+        }
+        provider.stop_capture().await?;
+
+        let stats = provider.stats();
+        println!(
+            "{:?} readback: {} frames ({:.1} fps), stride {} bytes, {} bytes per frame",
+            mode,
+            count,
+            count as f64 / CAPTURE_DURATION.as_secs_f64(),
+            stride,
+            bytes
+        );
+        print_latency("  FrameArrived to delivery", stats.delivery_latency);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn print_latency(label: &str, latency: LatencyStats) {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    println!(
+        "{}: p50 {:.2} ms, p95 {:.2} ms, max {:.2} ms",
+        label,
+        ms(latency.p50),
+        ms(latency.p95),
+        ms(latency.max)
+    );
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example needs Windows.Graphics.Capture, it only runs on Windows.");
+}
@@ -0,0 +1,70 @@
+//! Records ten seconds of the primary monitor to `out.mp4` with a hardware encoder, keeping the frames on the GPU
+//! from capture to encoding. Falls back to the software H.264 encoder without one.
+
+#[cfg(target_os = "windows")]
+use std::time::Duration;
+
+#[cfg(target_os = "windows")]
+use futures::StreamExt;
+#[cfg(target_os = "windows")]
+use loki::{
+    capture::{AdapterSelection, CaptureFramerate, CaptureProvider, create_provider},
+    capture_providers::windows::create_capture_item_for_primary_monitor,
+    recording::{MfEncoderSinkBuilder, VideoCodec},
+};
+
+#[cfg(target_os = "windows")]
+const RECORDING_DURATION: Duration = Duration::from_secs(10);
+#[cfg(target_os = "windows")]
+const FRAMERATE: CaptureFramerate = CaptureFramerate::FPS60;
+
+#[cfg(target_os = "windows")]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::INFO).init();
+    loki::capture::initialize_com()?;
+
+    let mut provider = create_provider()?;
+    provider.set_capture_item(create_capture_item_for_primary_monitor()?)?;
+    provider.start_capture().await?;
+    let info = provider.capture_item_info().ok_or("Nothing to capture")?;
+    // Shared textures only open on the adapter they were created on.
+    let adapter = provider
+        .adapter_info()
+        .map(|adapter| AdapterSelection::Luid(adapter.luid))
+        .unwrap_or_default();
+
+    let sink = MfEncoderSinkBuilder::new("out.mp4", info.size.x as u32, info.size.y as u32)
+        .with_framerate(FRAMERATE)
+        .with_codec(VideoCodec::H264)
+        .with_gop_length(FRAMERATE.fps() * 2)
+        .with_adapter(adapter)
+        .start()?;
+    println!("Recording {} with the {}", info, sink.encoder_info());
+
+    let mut frames = provider.create_texture_stream(FRAMERATE)?;
+    let deadline = tokio::time::sleep(RECORDING_DURATION);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            frame = frames.next() => {
+                let Some(frame) = frame else {
+                    println!("Capture ended early");
+                    break;
+                };
+                sink.push_frame(frame)?;
+            }
+        }
+    }
+
+    let dropped = sink.dropped_frames();
+    let written = sink.finish()?;
+    println!("Wrote {} frames to out.mp4, {} dropped", written, dropped);
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example needs Windows.Graphics.Capture, it only runs on Windows.");
+}
@@ -0,0 +1,75 @@
+//! Records the primary monitor to a file for a number of seconds, or until Ctrl-C.
+//!
+//! Usage: `record_to_file [path] [seconds] [framerate]`, by default `capture.y4m 10 30`. Paths ending in `.rgba`
+//! get raw RGBA frames, anything else a Y4M file. Both grow quickly, a minute of 1080p at 30 FPS is about 5 GiB
+//! as Y4M and twice that as RGBA.
+
+#[cfg(target_os = "windows")]
+use std::{path::PathBuf, time::Duration};
+
+#[cfg(target_os = "windows")]
+use loki::{
+    capture::{BackpressurePolicy, CaptureFramerate, CaptureSessionBuilder, Source, StreamOptions},
+    sinks::{RawRgbaWriter, Y4mWriter, record_stream},
+};
+
+#[cfg(target_os = "windows")]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::INFO).init();
+
+    let mut args = std::env::args().skip(1);
+    let path = PathBuf::from(args.next().unwrap_or_else(|| "capture.y4m".into()));
+    let seconds: u64 = args.next().map(|arg| arg.parse()).transpose()?.unwrap_or(10);
+    let framerate: CaptureFramerate =
+        args.next().map(|arg| arg.parse()).transpose()?.unwrap_or(CaptureFramerate::FPS30);
+
+    let mut session = CaptureSessionBuilder::new(Source::PrimaryMonitor)
+        .with_framerate(framerate)
+        // Waits for the writer instead of dropping frames when the disk can't keep up.
+        .with_stream_options(StreamOptions {
+            capacity: 4,
+            policy: BackpressurePolicy::Block,
+            ..StreamOptions::default()
+        })
+        .build()?;
+    if let Some(info) = session.capture_item_info() {
+        println!("Recording {} to {} for {}s", info, path.display(), seconds);
+    }
+
+    let stop = async move {
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(seconds)) => {}
+            _ = tokio::signal::ctrl_c() => println!("Stopping early"),
+        }
+    };
+    if path.extension().is_some_and(|extension| extension == "rgba") {
+        let writer = record_stream(&mut session, RawRgbaWriter::create(&path)?, stop).await?;
+        match writer.size() {
+            Some(size) => println!(
+                "Wrote {} frames, play them with `ffplay -f rawvideo -pixel_format rgba -video_size {}x{} -framerate {} {}`",
+                writer.frames_written(),
+                size.x,
+                size.y,
+                framerate.fps(),
+                path.display()
+            ),
+            None => println!("No frames arrived"),
+        }
+    } else {
+        let writer =
+            record_stream(&mut session, Y4mWriter::create(&path, framerate)?, stop).await?;
+        println!(
+            "Wrote {} frames, {} of them repeating the previous one",
+            writer.frames_written(),
+            writer.frames_repeated()
+        );
+    }
+    println!("{} frames dropped by the stream", session.dropped_frames());
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example needs Windows.Graphics.Capture, it only runs on Windows.");
+}
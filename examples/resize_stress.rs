@@ -0,0 +1,185 @@
+//! Opens a plain test window, captures it and resizes it over and over, checking after every resize that frames
+//! of the new size arrive, i.e. that the frame pool is recreated rather than the frames being stretched or cut
+//! off. Prints how long each resize took to show up and fails if one never did.
+
+#[cfg(target_os = "windows")]
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(target_os = "windows")]
+use futures::StreamExt;
+#[cfg(target_os = "windows")]
+use loki::capture::{
+    CaptureEvent, CaptureFramerate, CaptureProvider, Vector2, create_capture_item_for_window,
+    create_provider,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::{
+    Foundation::{HWND, LPARAM, LRESULT, RECT, WPARAM},
+    Graphics::{
+        Dwm::{DWMWA_EXTENDED_FRAME_BOUNDS, DwmGetWindowAttribute},
+        Gdi::{GetStockObject, HBRUSH, WHITE_BRUSH},
+    },
+    UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, MSG, PostMessageW,
+        PostQuitMessage, RegisterClassW, SWP_NOMOVE, SWP_NOZORDER, SetWindowPos, TranslateMessage,
+        WINDOW_EX_STYLE, WM_CLOSE, WM_DESTROY, WNDCLASSW, WS_POPUP, WS_VISIBLE,
+    },
+};
+#[cfg(target_os = "windows")]
+use windows_core::w;
+
+/// The sizes the window goes through in every round, growing and shrinking.
+#[cfg(target_os = "windows")]
+const SIZES: [(i32, i32); 4] = [(1280, 720), (320, 240), (1024, 768), (640, 360)];
+#[cfg(target_os = "windows")]
+const ROUNDS: usize = 5;
+/// How long a resize may take to show up in the frames.
+#[cfg(target_os = "windows")]
+const FOLLOW_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[cfg(target_os = "windows")]
+type Error = Box<dyn std::error::Error>;
+
+#[cfg(target_os = "windows")]
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::WARN).init();
+    loki::capture::initialize_com()?;
+
+    let window = spawn_test_window(Vector2::new(640, 360))?;
+    let mut provider = create_provider()?;
+    provider.set_capture_item(create_capture_item_for_window(window)?)?;
+    let mut stream = provider.create_stream(CaptureFramerate::FPS60)?;
+    provider.start_capture().await?;
+
+    let (mut missed, mut stale) = (0, 0u64);
+    for _ in 0..ROUNDS {
+        for (width, height) in SIZES {
+            unsafe { SetWindowPos(window, None, 0, 0, width, height, SWP_NOMOVE | SWP_NOZORDER)? };
+            // What WGC captures, in physical pixels whatever the DPI awareness of the process.
+            let expected = visible_size(window)?;
+            let resized = Instant::now();
+            let followed = tokio::time::timeout(FOLLOW_TIMEOUT, async {
+                while let Some(event) = stream.next().await {
+                    match event {
+                        CaptureEvent::Frame(frame) if frame.size == expected => return true,
+                        // Queued before the resize, the next ones must catch up.
+                        CaptureEvent::Frame(_) => stale += 1,
+                        CaptureEvent::Ended(reason) => {
+                            println!("Capture ended: {:?}", reason);
+                            return false;
+                        }
+                        _ => {}
+                    }
+                }
+                false
+            });
+            match followed.await {
+                Ok(true) => println!(
+                    "{} x {}: followed after {:.1} ms",
+                    expected.x,
+                    expected.y,
+                    resized.elapsed().as_secs_f64() * 1000.0
+                ),
+                _ => {
+                    missed += 1;
+                    println!("{} x {}: no frame of that size arrived", expected.x, expected.y);
+                }
+            }
+        }
+    }
+    provider.stop_capture().await?;
+    unsafe { PostMessageW(Some(window), WM_CLOSE, WPARAM(0), LPARAM(0))? };
+
+    println!(
+        "{} resizes, {} frames of the previous size arrived in between",
+        ROUNDS * SIZES.len(),
+        stale
+    );
+    match missed {
+        0 => Ok(()),
+        missed => Err(format!("The frames didn't follow {} of the resizes", missed).into()),
+    }
+}
+
+/// Creates a borderless window on a thread of its own, which pumps its messages until it is closed.
+#[cfg(target_os = "windows")]
+fn spawn_test_window(size: Vector2<i32>) -> Result<HWND, Error> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let class_name = w!("LokiResizeStress");
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            lpszClassName: class_name,
+            hbrBackground: HBRUSH(unsafe { GetStockObject(WHITE_BRUSH) }.0),
+            ..Default::default()
+        };
+        let created = unsafe {
+            RegisterClassW(&class);
+            CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                class_name,
+                w!("Resize stress"),
+                WS_POPUP | WS_VISIBLE,
+                100,
+                100,
+                size.x,
+                size.y,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+        // Window handles aren't Send, but they are valid from any thread.
+        let _ = tx.send(created.map(|window| window.0 as isize));
+
+        let mut message = MSG::default();
+        while unsafe { GetMessageW(&mut message, None, 0, 0) }.0 > 0 {
+            unsafe {
+                let _ = TranslateMessage(&message);
+                DispatchMessageW(&message);
+            }
+        }
+    });
+    let window = rx.recv()??;
+    Ok(HWND(window as *mut _))
+}
+
+#[cfg(target_os = "windows")]
+extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if message == WM_DESTROY {
+        unsafe { PostQuitMessage(0) };
+        return LRESULT(0);
+    }
+    unsafe { DefWindowProcW(window, message, wparam, lparam) }
+}
+
+/// The size of the window without the invisible resize borders, which is what its capture item has.
+#[cfg(target_os = "windows")]
+fn visible_size(window: HWND) -> Result<Vector2<i32>, Error> {
+    let mut bounds = RECT::default();
+    unsafe {
+        DwmGetWindowAttribute(
+            window,
+            DWMWA_EXTENDED_FRAME_BOUNDS,
+            &mut bounds as *mut RECT as *mut core::ffi::c_void,
+            std::mem::size_of::<RECT>() as u32,
+        )?
+    };
+    Ok(Vector2::new(bounds.right - bounds.left, bounds.bottom - bounds.top))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example needs Windows.Graphics.Capture, it only runs on Windows.");
+}
@@ -0,0 +1,176 @@
+//! Streams frames of the primary monitor that stay on the GPU and opens their shared textures on a second D3D11
+//! device, the way an encoder in another component would. Checks the description of every texture against its
+//! frame and prints how many could be opened. Moving windows around while it runs keeps frames coming.
+
+#[cfg(target_os = "windows")]
+use std::time::Duration;
+
+#[cfg(target_os = "windows")]
+use futures::StreamExt;
+#[cfg(target_os = "windows")]
+use loki::{
+    capture::{CaptureFramerate, CaptureProvider, GpuFrame, create_provider},
+    capture_providers::windows::create_capture_item_for_primary_monitor,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::{
+    Foundation::{HMODULE, LUID},
+    Graphics::{
+        Direct3D::{D3D_DRIVER_TYPE_HARDWARE, D3D_DRIVER_TYPE_UNKNOWN},
+        Direct3D11::{
+            D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_RESOURCE_MISC_SHARED_KEYEDMUTEX,
+            D3D11_RESOURCE_MISC_SHARED_NTHANDLE, D3D11_SDK_VERSION, D3D11_TEXTURE2D_DESC,
+            D3D11CreateDevice, ID3D11Device, ID3D11Device1, ID3D11Texture2D,
+        },
+        Dxgi::{
+            Common::DXGI_FORMAT, CreateDXGIFactory1, IDXGIAdapter, IDXGIFactory1, IDXGIKeyedMutex,
+        },
+    },
+};
+#[cfg(target_os = "windows")]
+use windows_core::{HRESULT, Interface};
+
+#[cfg(target_os = "windows")]
+const CAPTURE_DURATION: Duration = Duration::from_secs(5);
+/// How long to wait for the capture to let go of a texture before counting it as busy.
+#[cfg(target_os = "windows")]
+const ACQUIRE_TIMEOUT_MS: u32 = 100;
+/// `AcquireSync` reports a timeout as a success code, so it has to be checked for explicitly.
+#[cfg(target_os = "windows")]
+const WAIT_TIMEOUT: HRESULT = HRESULT(0x102);
+
+#[cfg(target_os = "windows")]
+type Error = Box<dyn std::error::Error>;
+
+#[cfg(target_os = "windows")]
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::INFO).init();
+    loki::capture::initialize_com()?;
+
+    let mut provider = create_provider()?;
+    provider.set_capture_item(create_capture_item_for_primary_monitor()?)?;
+    // Shared textures only open on the adapter they were created on.
+    let device = create_consumer_device(provider.adapter_info().map(|adapter| adapter.luid))?;
+    let mut frames = provider.create_texture_stream(CaptureFramerate::FPS30)?;
+    provider.start_capture().await?;
+
+    let (mut opened, mut busy) = (0u64, 0u64);
+    let deadline = tokio::time::sleep(CAPTURE_DURATION);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            frame = frames.next() => {
+                let Some(frame) = frame else {
+                    println!("Capture ended early");
+                    break;
+                };
+                match open_frame(&device, &frame)? {
+                    true => opened += 1,
+                    false => busy += 1,
+                }
+            }
+        }
+    }
+    provider.stop_capture().await?;
+
+    println!(
+        "Opened {} shared textures on the second device, {} were still held by the capture",
+        opened, busy
+    );
+    Ok(())
+}
+
+/// A device of its own on the adapter with the LUID, or on the default one.
+#[cfg(target_os = "windows")]
+fn create_consumer_device(luid: Option<LUID>) -> Result<ID3D11Device1, Error> {
+    let adapter = luid.map(find_adapter).transpose()?;
+    // An explicit adapter brings its own driver.
+    let driver_type = match adapter {
+        Some(_) => D3D_DRIVER_TYPE_UNKNOWN,
+        None => D3D_DRIVER_TYPE_HARDWARE,
+    };
+    let mut device: Option<ID3D11Device> = None;
+    unsafe {
+        D3D11CreateDevice(
+            adapter.as_ref(),
+            driver_type,
+            HMODULE::default(),
+            D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+            None,
+            D3D11_SDK_VERSION,
+            Some(&mut device),
+            None,
+            None,
+        )?;
+    }
+    Ok(device.ok_or("D3D11CreateDevice succeeded without a device")?.cast()?)
+}
+
+#[cfg(target_os = "windows")]
+fn find_adapter(luid: LUID) -> Result<IDXGIAdapter, Error> {
+    let factory: IDXGIFactory1 = unsafe { CreateDXGIFactory1()? };
+    let mut index = 0;
+    while let Ok(adapter) = unsafe { factory.EnumAdapters1(index) } {
+        if unsafe { adapter.GetDesc1()? }.AdapterLuid == luid {
+            return Ok(adapter.cast()?);
+        }
+        index += 1;
+    }
+    Err("The adapter of the capture is gone".into())
+}
+
+/// Opens the shared texture of a frame and checks that its description matches the frame. Returns false if the
+/// capture didn't release the texture in time.
+#[cfg(target_os = "windows")]
+fn open_frame(device: &ID3D11Device1, frame: &GpuFrame) -> Result<bool, Error> {
+    let texture: ID3D11Texture2D = unsafe { device.OpenSharedResource1(frame.shared_handle())? };
+    let mut desc = D3D11_TEXTURE2D_DESC::default();
+    unsafe { texture.GetDesc(&mut desc) };
+
+    let shared =
+        (D3D11_RESOURCE_MISC_SHARED_NTHANDLE.0 | D3D11_RESOURCE_MISC_SHARED_KEYEDMUTEX.0) as u32;
+    if desc.MiscFlags & shared != shared {
+        return Err(
+            format!("The texture isn't shared with a keyed mutex: {:#x}", desc.MiscFlags).into()
+        );
+    }
+    if desc.Format != DXGI_FORMAT::try_from(frame.format)? {
+        return Err(format!(
+            "The texture is in {:?}, the frame in {:?}",
+            desc.Format, frame.format
+        )
+        .into());
+    }
+    // The content may be smaller than the texture, but never larger.
+    if (desc.Width as i32) < frame.size.x || (desc.Height as i32) < frame.size.y {
+        return Err(format!(
+            "The texture is {} x {}, smaller than the frame of {} x {}",
+            desc.Width, desc.Height, frame.size.x, frame.size.y
+        )
+        .into());
+    }
+
+    let keyed_mutex: IDXGIKeyedMutex = texture.cast()?;
+    // Called through the vtable, as the generated wrapper hides WAIT_TIMEOUT behind Ok.
+    let result = unsafe {
+        (Interface::vtable(&keyed_mutex).AcquireSync)(
+            Interface::as_raw(&keyed_mutex),
+            GpuFrame::CONSUMER_KEY,
+            ACQUIRE_TIMEOUT_MS,
+        )
+    };
+    if result == WAIT_TIMEOUT {
+        return Ok(false);
+    }
+    result.ok()?;
+    // A consumer would read the texture here, before handing it back to the capture.
+    unsafe { keyed_mutex.ReleaseSync(GpuFrame::PRODUCER_KEY)? };
+    Ok(true)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example needs Windows.Graphics.Capture, it only runs on Windows.");
+}
@@ -0,0 +1,67 @@
+//! Takes a single frame of the primary monitor without any UI and saves it as an image. Off Windows the frame
+//! comes from the mock provider instead, which shows the same path without a desktop.
+//!
+//! Usage: `snapshot [path]`, by default `snapshot.png`. Paths ending in `.jpg` or `.jpeg` get a JPEG, anything
+//! else a PNG.
+
+use std::path::PathBuf;
+
+#[cfg(not(target_os = "windows"))]
+use futures::StreamExt;
+#[cfg(target_os = "windows")]
+use loki::{
+    capture::create_provider, capture_providers::windows::create_capture_item_for_primary_monitor,
+};
+#[cfg(not(target_os = "windows"))]
+use loki::{
+    capture::{CaptureFramerate, CaptureStream, PixelFormat, Vector2},
+    capture_providers::mock::{MockCaptureItem, MockCaptureProvider},
+};
+use loki::{
+    capture::{CaptureProvider, Frame},
+    utils::image_utils::{ImageFileFormat, encode_frame},
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::INFO).init();
+
+    let path = std::env::args().nth(1).map_or_else(|| PathBuf::from("snapshot.png"), PathBuf::from);
+    let format = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(ImageFileFormat::from_extension)
+        .unwrap_or(ImageFileFormat::Png);
+
+    let frame = snapshot().await?;
+    std::fs::write(&path, encode_frame(&frame, format)?)?;
+    println!("Saved a {} x {} snapshot to {}", frame.size.x, frame.size.y, path.display());
+    Ok(())
+}
+
+/// `capture_snapshot` blocks until the frame arrives, so it runs off the runtime.
+#[cfg(target_os = "windows")]
+async fn snapshot() -> Result<Frame, Error> {
+    tokio::task::spawn_blocking(|| {
+        loki::capture::initialize_com()?;
+        let mut provider = create_provider()?;
+        provider.set_capture_item(create_capture_item_for_primary_monitor()?)?;
+        Ok(provider.capture_snapshot()?)
+    })
+    .await?
+}
+
+/// The mock provider has no one-shot capture, so this takes the first frame of a stream.
+#[cfg(not(target_os = "windows"))]
+async fn snapshot() -> Result<Frame, Error> {
+    let item = MockCaptureItem::new(Vector2::new(1280, 720), PixelFormat::BGRA8);
+    let mut provider = MockCaptureProvider::with_item(item)?;
+    let stream = provider.create_stream(CaptureFramerate::FPS30)?;
+    let mut frames = std::pin::pin!(stream.frames_only());
+    provider.start_capture().await?;
+    let frame = frames.next().await.ok_or("The capture ended before the first frame")?;
+    provider.stop_capture().await?;
+    Ok(frame)
+}
@@ -0,0 +1,84 @@
+//! Captures the primary monitor into a 144 FPS and a 5 FPS stream of the same session, like an encoder and a
+//! thumbnail would, and fails unless each gets within 10% of its rate. Both share one readback, the slow stream
+//! has a `target_framerate` and only skips frames by their timestamps. Frames only arrive while something changes
+//! on screen, so it needs a monitor running at 144 Hz or more and a video playing at that rate, e.g. a UFO test.
+
+#[cfg(target_os = "windows")]
+use std::{num::NonZeroU32, time::Duration};
+
+#[cfg(target_os = "windows")]
+use futures::StreamExt;
+#[cfg(target_os = "windows")]
+use loki::capture::{
+    CaptureFramerate, CaptureProvider, CaptureSessionBuilder, CaptureStream, Source, StreamOptions,
+};
+
+#[cfg(target_os = "windows")]
+const CAPTURE_DURATION: Duration = Duration::from_secs(10);
+/// How far the observed rate of either stream may be off.
+#[cfg(target_os = "windows")]
+const TOLERANCE: f64 = 0.1;
+
+#[cfg(target_os = "windows")]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::WARN).init();
+
+    let fast_rate = CaptureFramerate::Custom(NonZeroU32::new(144).expect("144 is not zero"));
+    let slow_rate = CaptureFramerate::FPS5;
+    // The session itself is the fast stream, the slow one asks for the frames of the same rate but only keeps
+    // some of them.
+    let mut fast =
+        CaptureSessionBuilder::new(Source::PrimaryMonitor).with_framerate(fast_rate).build()?;
+    let slow_options =
+        StreamOptions { target_framerate: Some(slow_rate), ..StreamOptions::default() };
+    let mut slow =
+        fast.provider_mut().create_stream_with(fast_rate, slow_options)?.frames_only().boxed();
+
+    let deadline = tokio::time::sleep(CAPTURE_DURATION);
+    tokio::pin!(deadline);
+    let (mut fast_frames, mut slow_frames) = (0u64, 0u64);
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            frame = fast.next() => match frame {
+                Some(_) => fast_frames += 1,
+                None => break,
+            },
+            frame = slow.next() => match frame {
+                Some(_) => slow_frames += 1,
+                None => break,
+            },
+        }
+    }
+    fast.provider_mut().stop_capture().await?;
+
+    let seconds = CAPTURE_DURATION.as_secs_f64();
+    let (fast_fps, slow_fps) = (fast_frames as f64 / seconds, slow_frames as f64 / seconds);
+    println!("{} FPS stream: {:.1} fps", fast_rate, fast_fps);
+    println!("{} FPS stream: {:.1} fps", slow_rate, slow_fps);
+
+    let mut failed = false;
+    for (rate, fps) in [(fast_rate, fast_fps), (slow_rate, slow_fps)] {
+        let expected = rate.fps() as f64;
+        if (fps - expected).abs() > expected * TOLERANCE {
+            eprintln!(
+                "The {} FPS stream is more than {:.0}% off its rate",
+                rate,
+                TOLERANCE * 100.0
+            );
+            failed = true;
+        }
+    }
+    if failed {
+        // Too few frames on the fast stream usually mean the screen didn't change often enough to tell.
+        return Err("The streams didn't keep their rates, see above".into());
+    }
+    println!("Both streams are within {:.0}% of their rates", TOLERANCE * 100.0);
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn main() {
+    eprintln!("This example needs Windows.Graphics.Capture, it only runs on Windows.");
+}
@@ -0,0 +1,12 @@
+use crate::audio_providers::shared::SampleFormat;
+
+pub trait AudioCaptureProvider {
+    type Result<T>;
+    type Stream;
+
+    fn create_stream(
+        &mut self,
+        format: SampleFormat,
+        sample_rate: u32,
+    ) -> Self::Result<Self::Stream>;
+}
@@ -0,0 +1,18 @@
+mod audio_provider;
+pub mod shared;
+#[cfg(target_os = "windows")]
+pub mod windows;
+
+pub use audio_provider::AudioCaptureProvider;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AudioCaptureError {
+    #[cfg(target_os = "windows")]
+    #[error(transparent)]
+    WasapiError(#[from] windows::error::WasapiError),
+}
+
+#[cfg(target_os = "windows")]
+pub use windows::WasapiAudioStream as PlatformAudioStream;
+#[cfg(target_os = "windows")]
+pub use windows::WasapiLoopbackProvider as PlatformAudioProvider;
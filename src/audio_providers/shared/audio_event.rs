@@ -0,0 +1,11 @@
+use std::sync::Arc;
+
+use crate::audio_providers::{AudioCaptureError, shared::AudioPacket};
+
+/// Item yielded by audio streams.
+#[derive(Debug, Clone)]
+pub enum AudioEvent {
+    Packet(AudioPacket),
+    /// The stream will not produce any more packets.
+    Ended(Arc<AudioCaptureError>),
+}
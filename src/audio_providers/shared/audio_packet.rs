@@ -0,0 +1,34 @@
+use bytes::Bytes;
+
+use crate::{audio_providers::shared::SampleFormat, capture_providers::shared::CaptureFramerate};
+
+/// A chunk of captured audio.
+#[derive(Debug, Clone)]
+pub struct AudioPacket {
+    /// Interleaved samples of all channels.
+    pub data: Bytes,
+    pub format: SampleFormat,
+    pub channels: u16,
+    pub sample_rate: u32,
+    /// Time of the first sample in 100ns units, on the same QPC based clock as `Frame::timestamp`.
+    pub timestamp: i64,
+    /// Set if samples were lost before this packet, e.g. because the consumer fell behind.
+    pub discontinuity: bool,
+}
+
+impl AudioPacket {
+    /// Number of samples per channel.
+    pub fn frame_count(&self) -> usize {
+        let frame_bytes = self.format.bytes_per_sample() * self.channels as usize;
+        self.data.len().checked_div(frame_bytes).unwrap_or(0)
+    }
+
+    /// Duration of the packet in 100ns units.
+    pub fn duration(&self) -> i64 {
+        if self.sample_rate == 0 {
+            return 0;
+        }
+        (self.frame_count() as u64 * CaptureFramerate::TICKS_PER_SECOND / self.sample_rate as u64)
+            as i64
+    }
+}
@@ -0,0 +1,7 @@
+mod audio_event;
+mod audio_packet;
+mod sample_format;
+
+pub use audio_event::*;
+pub use audio_packet::*;
+pub use sample_format::*;
@@ -0,0 +1,17 @@
+/// Format of a single audio sample. Samples of all channels are interleaved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// 32-bit float in the range -1.0 to 1.0.
+    F32,
+    /// 16-bit signed integer PCM.
+    I16,
+}
+
+impl SampleFormat {
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            SampleFormat::F32 => 4,
+            SampleFormat::I16 => 2,
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use futures::Stream;
+use tokio::sync::mpsc::Receiver;
+
+use crate::audio_providers::shared::AudioEvent;
+
+/// Packets of loopback audio. Capture stops when the stream is dropped.
+#[derive(Debug)]
+pub struct WasapiAudioStream {
+    rx: Receiver<AudioEvent>,
+}
+
+impl WasapiAudioStream {
+    pub(super) fn new(rx: Receiver<AudioEvent>) -> Self {
+        Self { rx }
+    }
+}
+
+impl Stream for WasapiAudioStream {
+    type Item = AudioEvent;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
@@ -0,0 +1,41 @@
+use windows::Win32::Media::Audio::{
+    AUDCLNT_E_DEVICE_INVALIDATED, AUDCLNT_E_RESOURCES_INVALIDATED, AUDCLNT_E_SERVICE_NOT_RUNNING,
+    AUDCLNT_E_UNSUPPORTED_FORMAT,
+};
+
+use crate::audio_providers::shared::SampleFormat;
+
+pub type Result<T> = std::result::Result<T, WasapiError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WasapiError {
+    /// The endpoint was removed or disabled, or its format changed. A new stream has to be created.
+    #[error("Audio device invalidated: {0}")]
+    DeviceInvalidated(windows_core::Error),
+    #[error("Unsupported audio format: {format:?} at {sample_rate} Hz")]
+    UnsupportedFormat { format: SampleFormat, sample_rate: u32 },
+    #[error("Audio capture thread stopped unexpectedly")]
+    ThreadStopped,
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Unknown Windows error: {0}")]
+    UnknownWindowsError(windows_core::Error),
+}
+
+impl From<windows_core::Error> for WasapiError {
+    fn from(err: windows_core::Error) -> Self {
+        let code = err.code();
+        if code == AUDCLNT_E_DEVICE_INVALIDATED
+            || code == AUDCLNT_E_RESOURCES_INVALIDATED
+            || code == AUDCLNT_E_SERVICE_NOT_RUNNING
+        {
+            Self::DeviceInvalidated(err)
+        } else {
+            Self::UnknownWindowsError(err)
+        }
+    }
+}
+
+pub(super) fn is_unsupported_format(err: &windows_core::Error) -> bool {
+    err.code() == AUDCLNT_E_UNSUPPORTED_FORMAT
+}
@@ -0,0 +1,206 @@
+use std::{sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use tokio::sync::mpsc::{Sender, error::TrySendError};
+use windows::Win32::{
+    Media::Audio::{
+        AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY, AUDCLNT_BUFFERFLAGS_SILENT,
+        AUDCLNT_SHAREMODE_SHARED, AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM, AUDCLNT_STREAMFLAGS_LOOPBACK,
+        AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY, IAudioCaptureClient, IAudioClient,
+        IMMDeviceEnumerator, MMDeviceEnumerator, eConsole, eRender,
+    },
+    System::Com::{CLSCTX_ALL, CoCreateInstance, CoTaskMemFree},
+};
+
+use crate::{
+    audio_providers::{
+        AudioCaptureError, AudioCaptureProvider,
+        shared::{AudioEvent, AudioPacket, SampleFormat},
+        windows::{
+            WasapiAudioStream, WasapiError, error::is_unsupported_format, wave_format::wave_format,
+        },
+    },
+    capture::initialize_com,
+};
+
+/// Captures what is played on the default render endpoint through WASAPI loopback.
+#[derive(Debug, Default)]
+pub struct WasapiLoopbackProvider;
+
+/// The audio client and what packets are built from, owned by the capture thread.
+struct LoopbackCapture {
+    audio_client: IAudioClient,
+    capture_client: IAudioCaptureClient,
+    format: SampleFormat,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl WasapiLoopbackProvider {
+    /// Size of the WASAPI buffer in 100ns units, which packets have to be read within.
+    const BUFFER_DURATION: i64 = 1_000_000;
+    /// How often the buffer is drained. Loopback streams can't signal an event when data is ready.
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+    const CHANNEL_CAPACITY: usize = 64;
+
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Sets up loopback capture of the default render endpoint. Samples are converted to the requested format
+    /// and rate by WASAPI, while the channel count is taken from the endpoint.
+    fn open(format: SampleFormat, sample_rate: u32) -> super::Result<LoopbackCapture> {
+        let enumerator: IMMDeviceEnumerator =
+            unsafe { CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)? };
+        let device = unsafe { enumerator.GetDefaultAudioEndpoint(eRender, eConsole)? };
+        let audio_client: IAudioClient = unsafe { device.Activate(CLSCTX_ALL, None)? };
+
+        let channels = unsafe {
+            let mix_format = audio_client.GetMixFormat()?;
+            let channels = (*mix_format).nChannels;
+            CoTaskMemFree(Some(mix_format as *const core::ffi::c_void));
+            channels
+        };
+        tracing::debug!(
+            "Opening loopback capture: {:?}, {} Hz, {} channels",
+            format,
+            sample_rate,
+            channels
+        );
+
+        let wave_format = wave_format(format, channels, sample_rate);
+        let initialized = unsafe {
+            audio_client.Initialize(
+                AUDCLNT_SHAREMODE_SHARED,
+                AUDCLNT_STREAMFLAGS_LOOPBACK
+                    | AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM
+                    | AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY,
+                Self::BUFFER_DURATION,
+                0,
+                &wave_format,
+                None,
+            )
+        };
+        if let Err(err) = initialized {
+            if is_unsupported_format(&err) {
+                return Err(WasapiError::UnsupportedFormat { format, sample_rate });
+            }
+            return Err(err.into());
+        }
+
+        let capture_client: IAudioCaptureClient = unsafe { audio_client.GetService()? };
+        unsafe { audio_client.Start()? };
+        Ok(LoopbackCapture { audio_client, capture_client, format, channels, sample_rate })
+    }
+
+    fn run(capture: LoopbackCapture, tx: Sender<AudioEvent>) {
+        let result = Self::capture_packets(&capture, &tx);
+        if let Err(err) = unsafe { capture.audio_client.Stop() } {
+            tracing::debug!("Failed to stop audio client: {}", err);
+        }
+
+        match result {
+            Ok(()) => tracing::debug!("Audio stream dropped, stopping loopback capture."),
+            Err(err) => {
+                tracing::error!("Loopback capture failed: {}", err);
+                let event = AudioEvent::Ended(Arc::new(AudioCaptureError::from(err)));
+                if tx.blocking_send(event).is_err() {
+                    tracing::debug!("Audio stream dropped before the error could be delivered.");
+                }
+            }
+        }
+    }
+
+    /// Drains the WASAPI buffer until the stream is dropped or capture fails.
+    fn capture_packets(capture: &LoopbackCapture, tx: &Sender<AudioEvent>) -> super::Result<()> {
+        let block_align = capture.format.bytes_per_sample() * capture.channels as usize;
+        // Set when packets were dropped, so the next one that gets through can report the gap.
+        let mut dropped = false;
+
+        while !tx.is_closed() {
+            std::thread::sleep(Self::POLL_INTERVAL);
+
+            while unsafe { capture.capture_client.GetNextPacketSize()? } > 0 {
+                let mut data = std::ptr::null_mut();
+                let mut frame_count = 0;
+                let mut flags = 0;
+                let mut qpc_position = 0;
+                unsafe {
+                    capture.capture_client.GetBuffer(
+                        &mut data,
+                        &mut frame_count,
+                        &mut flags,
+                        None,
+                        Some(&mut qpc_position),
+                    )?
+                };
+
+                let len = frame_count as usize * block_align;
+                let bytes = if flags & AUDCLNT_BUFFERFLAGS_SILENT.0 as u32 != 0 {
+                    vec![0u8; len]
+                } else {
+                    unsafe { std::slice::from_raw_parts(data, len) }.to_vec()
+                };
+                unsafe { capture.capture_client.ReleaseBuffer(frame_count)? };
+
+                let packet = AudioPacket {
+                    data: Bytes::from(bytes),
+                    format: capture.format,
+                    channels: capture.channels,
+                    sample_rate: capture.sample_rate,
+                    // QPC positions are already in 100ns units, same as frame timestamps.
+                    timestamp: qpc_position as i64,
+                    discontinuity: dropped
+                        || flags & AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY.0 as u32 != 0,
+                };
+                match tx.try_send(AudioEvent::Packet(packet)) {
+                    Ok(()) => dropped = false,
+                    Err(TrySendError::Full(_)) => {
+                        tracing::debug!("Audio channel full, dropping packet.");
+                        dropped = true;
+                    }
+                    Err(TrySendError::Closed(_)) => return Ok(()),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AudioCaptureProvider for WasapiLoopbackProvider {
+    type Result<T> = super::Result<T>;
+    type Stream = WasapiAudioStream;
+
+    /// Starts capturing on a separate thread. Returns once the audio client is set up, so an unsupported
+    /// format or a missing endpoint is reported here rather than through the stream.
+    fn create_stream(
+        &mut self,
+        format: SampleFormat,
+        sample_rate: u32,
+    ) -> Self::Result<Self::Stream> {
+        let (tx, rx) = tokio::sync::mpsc::channel(Self::CHANNEL_CAPACITY);
+        let (ready_tx, ready_rx) = std::sync::mpsc::sync_channel(1);
+
+        std::thread::Builder::new().name("loki-audio-capture".into()).spawn(move || {
+            // The audio interfaces are created on and never leave this thread.
+            let capture = initialize_com()
+                .map_err(WasapiError::from)
+                .and_then(|_| Self::open(format, sample_rate));
+            match capture {
+                Ok(capture) => {
+                    ready_tx.send(Ok(())).ok();
+                    Self::run(capture, tx);
+                }
+                Err(err) => {
+                    ready_tx.send(Err(err)).ok();
+                }
+            }
+        })?;
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(WasapiAudioStream::new(rx)),
+            Ok(Err(err)) => Err(err),
+            Err(_) => Err(WasapiError::ThreadStopped),
+        }
+    }
+}
@@ -0,0 +1,8 @@
+mod audio_stream;
+pub mod error;
+mod loopback_provider;
+mod wave_format;
+
+pub use audio_stream::WasapiAudioStream;
+pub(self) use error::{Result, WasapiError};
+pub use loopback_provider::WasapiLoopbackProvider;
@@ -0,0 +1,24 @@
+use windows::Win32::Media::{
+    Audio::{WAVE_FORMAT_PCM, WAVEFORMATEX},
+    Multimedia::WAVE_FORMAT_IEEE_FLOAT,
+};
+
+use crate::audio_providers::shared::SampleFormat;
+
+/// Describes interleaved samples of the format.
+pub(super) fn wave_format(format: SampleFormat, channels: u16, sample_rate: u32) -> WAVEFORMATEX {
+    let bits_per_sample = format.bytes_per_sample() as u16 * 8;
+    let block_align = channels * format.bytes_per_sample() as u16;
+    WAVEFORMATEX {
+        wFormatTag: match format {
+            SampleFormat::F32 => WAVE_FORMAT_IEEE_FLOAT as u16,
+            SampleFormat::I16 => WAVE_FORMAT_PCM as u16,
+        },
+        nChannels: channels,
+        nSamplesPerSec: sample_rate,
+        nAvgBytesPerSec: sample_rate * block_align as u32,
+        nBlockAlign: block_align,
+        wBitsPerSample: bits_per_sample,
+        cbSize: 0,
+    }
+}
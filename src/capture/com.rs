@@ -0,0 +1,17 @@
+use windows::Win32::{
+    Foundation::RPC_E_CHANGED_MODE,
+    System::WinRT::{RO_INIT_MULTITHREADED, RoInitialize},
+};
+
+/// Initializes the Windows Runtime on the calling thread, which WGC needs before anything can be captured.
+/// Threads that already joined an apartment, e.g. a UI thread, are left as they are.
+pub fn initialize_com() -> windows_core::Result<()> {
+    match unsafe { RoInitialize(RO_INIT_MULTITHREADED) } {
+        Ok(()) => Ok(()),
+        Err(err) if err.code() == RPC_E_CHANGED_MODE => {
+            tracing::debug!("Thread is already in a single-threaded apartment.");
+            Ok(())
+        }
+        Err(err) => Err(err),
+    }
+}
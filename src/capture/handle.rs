@@ -0,0 +1,421 @@
+use std::thread;
+
+use tokio::sync::{mpsc, oneshot};
+
+#[cfg(target_os = "windows")]
+use crate::capture::com::initialize_com;
+use crate::capture_providers::{
+    CaptureError, CaptureProvider, CaptureStream, PlatformCaptureProvider,
+    shared::{CaptureFramerate, CaptureItemInfo, CaptureStats, CaptureTarget, Rect, StreamId},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum HandleError {
+    #[error("The capture thread has stopped")]
+    Closed,
+    #[error("Capture error: {0}")]
+    CaptureError(#[from] CaptureError),
+}
+
+pub type Result<T> = std::result::Result<T, HandleError>;
+
+type Reply<T> = oneshot::Sender<std::result::Result<T, CaptureError>>;
+
+enum Command<P: CaptureProvider> {
+    SetItem { item: P::CaptureItem, region: Option<Rect<i32>>, reply: Reply<()> },
+    Start { reply: Reply<()> },
+    Stop { reply: Reply<()> },
+    Pause { reply: Reply<()> },
+    Resume { reply: Reply<()> },
+    CreateStream { reply: Reply<P::Stream> },
+    CreateLatestFrameHandle { reply: Reply<P::LatestFrameHandle> },
+    SetFramerate(CaptureFramerate),
+    SetCursorCapture { enabled: bool, reply: Reply<()> },
+    SetBorderRequired { required: bool, reply: Reply<()> },
+    SetTraceFrames(bool),
+    ItemInfo { reply: oneshot::Sender<Option<CaptureItemInfo>> },
+    Target { reply: oneshot::Sender<Option<CaptureTarget>> },
+    Stats { reply: oneshot::Sender<CaptureStats> },
+}
+
+/// Controls a provider owned by a dedicated capture thread. Commands are queued and run in order, so callers
+/// never wait on each other, and nothing is blocked while the provider calls into WGC.
+/// Cloning is cheap, the thread exits once the last clone is dropped.
+pub struct CaptureHandle<P: CaptureProvider = PlatformCaptureProvider> {
+    commands: mpsc::UnboundedSender<Command<P>>,
+}
+
+impl<P: CaptureProvider> std::fmt::Debug for CaptureHandle<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CaptureHandle").finish_non_exhaustive()
+    }
+}
+
+impl<P: CaptureProvider> Clone for CaptureHandle<P> {
+    fn clone(&self) -> Self {
+        Self { commands: self.commands.clone() }
+    }
+}
+
+impl<P> CaptureHandle<P>
+where
+    P: CaptureProvider + Send + 'static,
+    P::Stream: 'static,
+    P::LatestFrameHandle: 'static,
+    P::CaptureItem: Send + 'static,
+    P::Error: Into<CaptureError>,
+{
+    /// Moves the provider to a new thread. Streams created through the handle use `framerate` until it is
+    /// changed with `set_framerate`.
+    pub fn spawn(provider: P, framerate: CaptureFramerate) -> std::io::Result<Self> {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        thread::Builder::new().name("capture".to_string()).spawn(move || {
+            CaptureActor { provider, framerate, streams: Vec::new() }.run(receiver)
+        })?;
+        Ok(Self { commands })
+    }
+
+    /// Sets the item to capture and the region of it, see `CaptureProvider::set_crop_region`.
+    /// A running capture switches to the new item.
+    pub async fn set_item(&self, item: P::CaptureItem, region: Option<Rect<i32>>) -> Result<()> {
+        self.request(|reply| Command::SetItem { item, region, reply }).await
+    }
+
+    pub async fn start(&self) -> Result<()> {
+        self.request(|reply| Command::Start { reply }).await
+    }
+
+    pub async fn stop(&self) -> Result<()> {
+        self.request(|reply| Command::Stop { reply }).await
+    }
+
+    /// See `CaptureProvider::pause_capture`. Streams stay open while paused.
+    pub async fn pause(&self) -> Result<()> {
+        self.request(|reply| Command::Pause { reply }).await
+    }
+
+    pub async fn resume(&self) -> Result<()> {
+        self.request(|reply| Command::Resume { reply }).await
+    }
+
+    pub async fn create_stream(&self) -> Result<P::Stream> {
+        self.request(|reply| Command::CreateStream { reply }).await
+    }
+
+    /// Creates a handle to the newest frame, which never lags behind like a stream can, e.g. for a preview.
+    pub async fn create_latest_frame_handle(&self) -> Result<P::LatestFrameHandle> {
+        self.request(|reply| Command::CreateLatestFrameHandle { reply }).await
+    }
+
+    /// Changes the framerate of the running streams created through the handle, and of streams created
+    /// afterwards. Streams of the provider created elsewhere keep theirs.
+    pub fn set_framerate(&self, framerate: CaptureFramerate) -> Result<()> {
+        self.send(Command::SetFramerate(framerate))
+    }
+
+    pub async fn set_cursor_capture(&self, enabled: bool) -> Result<()> {
+        self.request(|reply| Command::SetCursorCapture { enabled, reply }).await
+    }
+
+    pub async fn set_border_required(&self, required: bool) -> Result<()> {
+        self.request(|reply| Command::SetBorderRequired { required, reply }).await
+    }
+
+    pub fn set_trace_frames(&self, enabled: bool) -> Result<()> {
+        self.send(Command::SetTraceFrames(enabled))
+    }
+
+    pub async fn capture_item_info(&self) -> Result<Option<CaptureItemInfo>> {
+        let (reply, response) = oneshot::channel();
+        self.send(Command::ItemInfo { reply })?;
+        response.await.map_err(|_| HandleError::Closed)
+    }
+
+    pub async fn capture_target(&self) -> Result<Option<CaptureTarget>> {
+        let (reply, response) = oneshot::channel();
+        self.send(Command::Target { reply })?;
+        response.await.map_err(|_| HandleError::Closed)
+    }
+
+    pub async fn stats(&self) -> Result<CaptureStats> {
+        let (reply, response) = oneshot::channel();
+        self.send(Command::Stats { reply })?;
+        response.await.map_err(|_| HandleError::Closed)
+    }
+
+    fn send(&self, command: Command<P>) -> Result<()> {
+        self.commands.send(command).map_err(|_| HandleError::Closed)
+    }
+
+    async fn request<T>(&self, command: impl FnOnce(Reply<T>) -> Command<P>) -> Result<T> {
+        let (reply, response) = oneshot::channel();
+        self.send(command(reply))?;
+        Ok(response.await.map_err(|_| HandleError::Closed)??)
+    }
+}
+
+struct CaptureActor<P> {
+    provider: P,
+    framerate: CaptureFramerate,
+    /// The streams created through the handle, which `SetFramerate` applies to. Forgotten once they are dropped.
+    streams: Vec<StreamId>,
+}
+
+impl<P> CaptureActor<P>
+where
+    P: CaptureProvider,
+    P::Error: Into<CaptureError>,
+{
+    fn run(mut self, mut commands: mpsc::UnboundedReceiver<Command<P>>) {
+        #[cfg(target_os = "windows")]
+        if let Err(err) = initialize_com() {
+            tracing::error!("Failed to initialize COM on the capture thread: {}", err);
+        }
+        while let Some(command) = commands.blocking_recv() {
+            self.handle(command);
+        }
+        tracing::debug!("All capture handles dropped, stopping the capture thread.");
+    }
+
+    /// Replies are dropped silently if the caller stopped waiting for them.
+    fn handle(&mut self, command: Command<P>) {
+        match command {
+            Command::SetItem { item, region, reply } => {
+                self.provider.set_crop_region(region);
+                let _ = reply.send(self.provider.set_capture_item(item).map_err(Into::into));
+            }
+            // The provider starts and stops sessions elsewhere, e.g. on a COM thread, so this thread only waits
+            // for it. Commands sent meanwhile are run once it is done, in order.
+            Command::Start { reply } => {
+                let started = futures::executor::block_on(self.provider.start_capture());
+                let _ = reply.send(started.map_err(Into::into));
+            }
+            Command::Stop { reply } => {
+                let stopped = futures::executor::block_on(self.provider.stop_capture());
+                let _ = reply.send(stopped.map_err(Into::into));
+            }
+            Command::Pause { reply } => {
+                let _ = reply.send(self.provider.pause_capture().map_err(Into::into));
+            }
+            Command::Resume { reply } => {
+                let _ = reply.send(self.provider.resume_capture().map_err(Into::into));
+            }
+            Command::CreateStream { reply } => {
+                let stream = self.provider.create_stream(self.framerate);
+                if let Ok(stream) = &stream {
+                    self.streams.push(stream.id());
+                }
+                let _ = reply.send(stream.map_err(Into::into));
+            }
+            Command::CreateLatestFrameHandle { reply } => {
+                let handle = self.provider.create_latest_frame_handle(self.framerate);
+                if let Ok(handle) = &handle {
+                    self.streams.push(handle.id());
+                }
+                let _ = reply.send(handle.map_err(Into::into));
+            }
+            Command::SetFramerate(framerate) => {
+                self.framerate = framerate;
+                let provider = &mut self.provider;
+                self.streams.retain(|&stream| {
+                    match provider.set_stream_framerate(stream, framerate) {
+                        Ok(live) => live,
+                        Err(err) => {
+                            tracing::error!(
+                                "Failed to change the framerate of {}: {}",
+                                stream,
+                                err
+                            );
+                            true
+                        }
+                    }
+                });
+            }
+            Command::SetCursorCapture { enabled, reply } => {
+                let enabled = self.provider.set_cursor_capture(enabled);
+                let _ = reply.send(enabled.map_err(Into::into));
+            }
+            Command::SetBorderRequired { required, reply } => {
+                let required = self.provider.set_border_required(required);
+                let _ = reply.send(required.map_err(Into::into));
+            }
+            Command::SetTraceFrames(enabled) => self.provider.set_trace_frames(enabled),
+            Command::ItemInfo { reply } => {
+                let _ = reply.send(self.provider.capture_item_info());
+            }
+            Command::Target { reply } => {
+                let _ = reply.send(self.provider.capture_target());
+            }
+            Command::Stats { reply } => {
+                let _ = reply.send(self.provider.stats());
+            }
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "mock-provider", not(target_os = "windows"))))]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::capture_providers::{
+        mock::{MockCaptureError, MockCaptureItem, MockCaptureProvider},
+        shared::{Frame, PixelFormat, Vector2},
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn spawn() -> CaptureHandle<MockCaptureProvider> {
+        CaptureHandle::spawn(MockCaptureProvider::new(), CaptureFramerate::FPS60).unwrap()
+    }
+
+    /// A handle capturing a small item, and a stream of it.
+    async fn spawn_capturing()
+    -> (CaptureHandle<MockCaptureProvider>, impl futures::Stream<Item = Frame>) {
+        let handle = spawn();
+        let item = MockCaptureItem::new(Vector2::new(16, 16), PixelFormat::RGBA8);
+        handle.set_item(item, None).await.unwrap();
+        let stream = handle.create_stream().await.unwrap();
+        handle.start().await.unwrap();
+        (handle, stream.frames_only())
+    }
+
+    /// Waits until the consumer counted a few frames.
+    async fn wait_for_frames(delivered: &tokio::sync::Mutex<u64>) {
+        let waited = tokio::time::timeout(TIMEOUT, async {
+            while *delivered.lock().await < 3 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+        waited.await.expect("no frames were delivered");
+    }
+
+    // Every call is made from a runtime worker, where blocking on the provider would panic.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn frames_flow_once_capture_starts() {
+        let handle = spawn();
+        let item = MockCaptureItem::new(Vector2::new(64, 32), PixelFormat::BGRA8);
+        handle.set_item(item, None).await.unwrap();
+        let stream = handle.create_stream().await.unwrap();
+        let mut frames = std::pin::pin!(stream.frames_only());
+        handle.start().await.unwrap();
+
+        let frame = tokio::time::timeout(TIMEOUT, frames.next()).await.unwrap().unwrap();
+        assert_eq!(frame.size, Vector2::new(64, 32));
+        assert_eq!(frame.format, PixelFormat::BGRA8);
+        let info = handle.capture_item_info().await.unwrap().unwrap();
+        assert_eq!(info.size, Vector2::new(64, 32));
+        assert!(handle.stats().await.unwrap().frames_delivered >= 1);
+
+        handle.stop().await.unwrap();
+        let rest = tokio::time::timeout(TIMEOUT, frames.count()).await;
+        assert!(rest.is_ok(), "the stream didn't end after the capture stopped");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn provider_errors_are_returned_to_the_caller() {
+        let handle = spawn();
+        let result = handle.start().await;
+        assert!(matches!(
+            result,
+            Err(HandleError::CaptureError(CaptureError::MockCaptureError(
+                MockCaptureError::NoCaptureItem
+            )))
+        ));
+        // The thread keeps serving after an error.
+        assert!(handle.capture_item_info().await.unwrap().is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn clones_are_served_from_any_task() {
+        let handle = spawn();
+        handle.set_item(MockCaptureItem::default(), None).await.unwrap();
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let handle = handle.clone();
+                tokio::spawn(async move { handle.capture_item_info().await })
+            })
+            .collect();
+        for task in tasks {
+            let info = tokio::time::timeout(TIMEOUT, task).await.unwrap().unwrap();
+            assert!(info.unwrap().is_some());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stopping_while_the_consumer_holds_a_lock_doesnt_deadlock() {
+        let (handle, frames) = spawn_capturing().await;
+        let delivered = Arc::new(tokio::sync::Mutex::new(0u64));
+        let consumer = tokio::spawn({
+            let delivered = delivered.clone();
+            let handle = handle.clone();
+            async move {
+                let mut frames = std::pin::pin!(frames);
+                while frames.next().await.is_some() {
+                    let mut delivered = delivered.lock().await;
+                    *delivered += 1;
+                    // Calls back into the handle with the lock held, as a UI updating its state would.
+                    handle.stats().await.unwrap();
+                }
+            }
+        });
+
+        wait_for_frames(&delivered).await;
+        let held = delivered.lock().await;
+        let stopped = tokio::time::timeout(TIMEOUT, handle.stop()).await;
+        stopped.expect("stopping waited for the consumer").unwrap();
+        drop(held);
+        let ended = tokio::time::timeout(TIMEOUT, consumer).await;
+        ended.expect("the stream didn't end after the capture stopped").unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dropping_the_handle_while_the_consumer_holds_a_lock_ends_the_stream() {
+        let (handle, frames) = spawn_capturing().await;
+        let delivered = Arc::new(tokio::sync::Mutex::new(0u64));
+        let consumer = tokio::spawn({
+            let delivered = delivered.clone();
+            async move {
+                let mut frames = std::pin::pin!(frames);
+                while frames.next().await.is_some() {
+                    let mut delivered = delivered.lock().await;
+                    *delivered += 1;
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            }
+        });
+
+        wait_for_frames(&delivered).await;
+        let held = delivered.lock().await;
+        drop(handle);
+        drop(held);
+        let ended = tokio::time::timeout(TIMEOUT, consumer).await;
+        ended.expect("the stream didn't end after the handle was dropped").unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn racing_starts_stops_and_stream_creations_all_complete() {
+        let handle = spawn();
+        handle.set_item(MockCaptureItem::default(), None).await.unwrap();
+        let tasks: Vec<_> = (0..24)
+            .map(|i| {
+                let handle = handle.clone();
+                tokio::spawn(async move {
+                    // Starting twice or stopping while stopped fails, which is fine, as long as it returns.
+                    let _ = match i % 3 {
+                        0 => handle.create_stream().await.map(drop),
+                        1 => handle.start().await,
+                        _ => handle.stop().await,
+                    };
+                })
+            })
+            .collect();
+        for task in tasks {
+            tokio::time::timeout(TIMEOUT, task).await.expect("a command never returned").unwrap();
+        }
+        // The thread still serves afterwards.
+        assert!(handle.capture_item_info().await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,26 @@
+//! Capturing without the UI: pick a source, get a stream of frames.
+
+#[cfg(target_os = "windows")]
+mod com;
+mod handle;
+#[cfg(target_os = "windows")]
+mod session;
+
+#[cfg(target_os = "windows")]
+pub use com::initialize_com;
+pub use handle::{CaptureHandle, HandleError};
+#[cfg(target_os = "windows")]
+pub use session::{CaptureSession, CaptureSessionBuilder, SessionError, Source, create_provider};
+
+#[cfg(target_os = "windows")]
+pub use crate::capture_providers::windows::{
+    AdapterInfo, AdapterSelection, BuilderError, FrameObserver, FrameSink, LatestFrameHandle,
+    MonitorInfo, ObserverToken, ReadbackMode, SinkDelivery, SourceId, StreamStats, TitleMatcher,
+    WindowCandidate, WindowInfo, WindowsCaptureProvider, WindowsCaptureProviderBuilder,
+    WindowsCaptureStream, create_capture_item_for_target, create_capture_item_for_window,
+    enumerate_capturable_windows, enumerate_monitors, list_adapters,
+};
+pub use crate::capture_providers::{
+    CaptureError, CaptureProvider, CaptureStream, PlatformCaptureItem, PlatformCaptureProvider,
+    PlatformCaptureStream, shared::*,
+};
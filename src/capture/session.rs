@@ -0,0 +1,334 @@
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use tokio::sync::watch;
+use windows::Graphics::Capture::GraphicsCaptureItem;
+
+use crate::{
+    capture::com::initialize_com,
+    capture_providers::{
+        CaptureProvider,
+        shared::{
+            CaptureEvent, CaptureFramerate, CaptureItemInfo, CaptureStats, CaptureTarget,
+            ConversionPolicy, EndReason, Frame, FrameTracer, PixelFormat, ScaleMode, StreamOptions,
+        },
+        windows::{
+            AdapterInfo, BuilderError, SinkDelivery, StreamStats, TitleMatcher,
+            WindowsCaptureProvider, WindowsCaptureProviderBuilder, WindowsCaptureStream,
+            create_capture_item_for_primary_monitor, create_capture_item_for_target,
+            create_capture_item_for_window_title, enumerate_capturable_windows, enumerate_monitors,
+            error::WindowsCaptureError,
+        },
+    },
+    ipc::{SharedMemExporter, SharedMemoryError},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    #[error("No capture source matching {0:?}")]
+    SourceNotFound(Source),
+    #[error("Failed to create capture provider: {0}")]
+    ProviderError(#[from] BuilderError),
+    #[error("Capture error: {0}")]
+    CaptureError(#[from] WindowsCaptureError),
+    #[error("Shared memory export error: {0}")]
+    SharedMemoryError(#[from] SharedMemoryError),
+    #[error("Windows error: {0}")]
+    WindowsError(#[from] windows_core::Error),
+}
+
+/// What a session captures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    PrimaryMonitor,
+    /// A monitor in the order of `enumerate_monitors`.
+    MonitorIndex(usize),
+    /// The first capturable window whose title contains the string.
+    WindowTitleContains(String),
+    /// The only capturable window whose title matches. Fails if several do.
+    WindowTitle(TitleMatcher),
+    /// The window or monitor a target describes, e.g. one captured in a previous run.
+    Target(CaptureTarget),
+}
+
+impl Source {
+    fn to_capture_item(&self) -> Result<GraphicsCaptureItem, SessionError> {
+        match self {
+            Source::PrimaryMonitor => Ok(create_capture_item_for_primary_monitor()?),
+            Source::MonitorIndex(index) => match enumerate_monitors().get(*index) {
+                Some(monitor) => Ok(monitor.to_capture_item()?),
+                None => Err(SessionError::SourceNotFound(self.clone())),
+            },
+            Source::WindowTitleContains(title) => {
+                let window = enumerate_capturable_windows()
+                    .into_iter()
+                    .find(|window| window.name.contains(title.as_str()));
+                match window {
+                    Some(window) => Ok(window.to_capture_item()?),
+                    None => Err(SessionError::SourceNotFound(self.clone())),
+                }
+            }
+            Source::WindowTitle(matcher) => {
+                Ok(create_capture_item_for_window_title(matcher.clone())?)
+            }
+            Source::Target(target) => Ok(create_capture_item_for_target(target)?),
+        }
+    }
+}
+
+/// Sets up a capture of a single source, without any UI or picker involved.
+#[derive(Debug, Clone)]
+pub struct CaptureSessionBuilder {
+    source: Source,
+    framerate: CaptureFramerate,
+    cursor_capture_enabled: bool,
+    border_required: bool,
+    output_format: PixelFormat,
+    conversion_policy: ConversionPolicy,
+    gpu_conversion: bool,
+    scale: ScaleMode,
+    stream_options: StreamOptions,
+    readback_depth: usize,
+    pipeline_depth: usize,
+    buffer_pool_size: usize,
+    frame_tracer: Option<Arc<dyn FrameTracer>>,
+    /// Name and maximum frame size of the shared memory export, if any.
+    shared_memory: Option<(String, u32, u32)>,
+}
+
+impl CaptureSessionBuilder {
+    pub fn new(source: Source) -> Self {
+        Self {
+            source,
+            framerate: CaptureFramerate::FPS60,
+            cursor_capture_enabled: true,
+            border_required: true,
+            output_format: PixelFormat::RGBA8,
+            conversion_policy: ConversionPolicy::default(),
+            gpu_conversion: true,
+            scale: ScaleMode::Native,
+            stream_options: StreamOptions::default(),
+            readback_depth: 2,
+            pipeline_depth: 2,
+            buffer_pool_size: 8,
+            frame_tracer: None,
+            shared_memory: None,
+        }
+    }
+
+    pub fn with_framerate(mut self, framerate: CaptureFramerate) -> Self {
+        self.framerate = framerate;
+        self
+    }
+
+    pub fn with_cursor_capture(mut self, enabled: bool) -> Self {
+        self.cursor_capture_enabled = enabled;
+        self
+    }
+
+    /// Has no effect on versions of Windows that always draw the border.
+    pub fn with_border(mut self, required: bool) -> Self {
+        self.border_required = required;
+        self
+    }
+
+    pub fn with_output_format(mut self, format: PixelFormat) -> Self {
+        self.output_format = format;
+        self
+    }
+
+    /// See `WindowsCaptureProvider::set_conversion_policy`. Defaults to deferred.
+    pub fn with_conversion_policy(mut self, policy: ConversionPolicy) -> Self {
+        self.conversion_policy = policy;
+        self
+    }
+
+    /// See `WindowsCaptureProvider::set_gpu_conversion`. Defaults to on.
+    pub fn with_gpu_conversion(mut self, enabled: bool) -> Self {
+        self.gpu_conversion = enabled;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: ScaleMode) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_stream_options(mut self, options: StreamOptions) -> Self {
+        self.stream_options = options;
+        self
+    }
+
+    /// See `WindowsCaptureProviderBuilder::with_readback_depth`. 1 reads every frame back synchronously, which gives
+    /// the lowest latency, defaults to 2.
+    pub fn with_readback_depth(mut self, depth: usize) -> Self {
+        self.readback_depth = depth;
+        self
+    }
+
+    /// See `WindowsCaptureProviderBuilder::with_pipeline_depth`. At least 2, defaults to 2.
+    pub fn with_pipeline_depth(mut self, depth: usize) -> Self {
+        self.pipeline_depth = depth;
+        self
+    }
+
+    /// See `WindowsCaptureProviderBuilder::with_buffer_pool_size`. Defaults to 8.
+    pub fn with_buffer_pool_size(mut self, size: usize) -> Self {
+        self.buffer_pool_size = size;
+        self
+    }
+
+    /// See `WindowsCaptureProvider::set_frame_tracer`.
+    pub fn with_frame_tracer(mut self, tracer: Arc<dyn FrameTracer>) -> Self {
+        self.frame_tracer = Some(tracer);
+        self
+    }
+
+    /// Also exports every frame to the shared memory mapping `name`, see `SharedMemExporter`. Frames larger than
+    /// `max_width` x `max_height` are left out. The mapping goes away when the capture stops.
+    pub fn with_shared_memory_export(
+        mut self,
+        name: impl Into<String>,
+        max_width: u32,
+        max_height: u32,
+    ) -> Self {
+        self.shared_memory = Some((name.into(), max_width, max_height));
+        self
+    }
+
+    /// Initializes COM on the calling thread if needed, resolves the source and starts capturing it.
+    pub fn build(self) -> Result<CaptureSession, SessionError> {
+        initialize_com()?;
+
+        let capture_item = self.source.to_capture_item()?;
+        // Created first, so a name that is already taken fails before anything is captured.
+        let exporter = match &self.shared_memory {
+            Some((name, max_width, max_height)) => {
+                Some(SharedMemExporter::new(name, *max_width, *max_height)?)
+            }
+            None => None,
+        };
+        let mut provider = WindowsCaptureProviderBuilder::new()
+            .with_default_device()?
+            .with_capture_item(capture_item)
+            .with_cursor_capture(self.cursor_capture_enabled)
+            .with_border(self.border_required)
+            .with_pixel_format(self.output_format)
+            .with_conversion_policy(self.conversion_policy)
+            .with_readback_depth(self.readback_depth)
+            .with_pipeline_depth(self.pipeline_depth)
+            .with_buffer_pool_size(self.buffer_pool_size)
+            .build()?;
+        provider.set_output_scale(self.scale);
+        provider.set_gpu_conversion(self.gpu_conversion);
+        provider.set_frame_tracer(self.frame_tracer);
+        provider.start_sources()?;
+
+        // Streams need a running session.
+        let stream = match exporter {
+            Some(exporter) => provider.create_sink_stream(
+                self.framerate,
+                self.stream_options,
+                exporter,
+                SinkDelivery::SinkAndStream,
+            )?,
+            None => provider.create_stream_with(self.framerate, self.stream_options)?,
+        };
+        Ok(CaptureSession { provider, stream, end_reason: None })
+    }
+}
+
+/// Creates a provider on the default device without a capture item.
+pub fn create_provider() -> Result<WindowsCaptureProvider, BuilderError> {
+    WindowsCaptureProviderBuilder::new().with_default_device()?.with_default_capture_item()?.build()
+}
+
+/// A running capture. Yields frames until the source goes away or capture fails, see `end_reason`.
+#[derive(Debug)]
+pub struct CaptureSession {
+    provider: WindowsCaptureProvider,
+    stream: WindowsCaptureStream,
+    end_reason: Option<EndReason>,
+}
+
+impl CaptureSession {
+    /// Describes the captured window or monitor.
+    pub fn capture_item_info(&self) -> Option<CaptureItemInfo> {
+        self.provider.capture_item_info()
+    }
+
+    pub fn stats(&self) -> CaptureStats {
+        self.provider.stats()
+    }
+
+    /// Number of frames lost to the backpressure policy so far.
+    pub fn dropped_frames(&self) -> u64 {
+        self.stream.dropped_frames()
+    }
+
+    /// See `WindowsCaptureProvider::adapter_info`.
+    pub fn adapter_info(&self) -> Option<AdapterInfo> {
+        self.provider.adapter_info()
+    }
+
+    /// Frames queued on and dropped by the stream of the session, see `WindowsCaptureStream::stats`.
+    pub fn stream_stats(&self) -> StreamStats {
+        self.stream.stats()
+    }
+
+    /// See `WindowsCaptureStream::watch_stats`.
+    pub fn watch_stream_stats(&self) -> watch::Receiver<StreamStats> {
+        self.stream.watch_stats()
+    }
+
+    /// Changes the framerate of the stream of the session while it runs, see
+    /// `WindowsCaptureProvider::set_stream_framerate`.
+    pub fn set_framerate(&mut self, framerate: CaptureFramerate) -> Result<(), SessionError> {
+        Ok(self.provider.set_stream_framerate(self.stream.id(), framerate)?)
+    }
+
+    /// Why the stream ended, once it did.
+    pub fn end_reason(&self) -> Option<&EndReason> {
+        self.end_reason.as_ref()
+    }
+
+    /// Gives access to the provider, e.g. to change settings while capturing.
+    pub fn provider_mut(&mut self) -> &mut WindowsCaptureProvider {
+        &mut self.provider
+    }
+}
+
+impl Stream for CaptureSession {
+    type Item = Frame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(CaptureEvent::Frame(frame))) => return Poll::Ready(Some(frame)),
+                Poll::Ready(Some(CaptureEvent::Recreated)) => {
+                    tracing::info!("Capture pipeline recreated, frames will resume shortly.");
+                }
+                Poll::Ready(Some(CaptureEvent::Started)) => {
+                    tracing::debug!("Capture session produced its first frame.");
+                }
+                Poll::Ready(Some(CaptureEvent::Resized(size))) => {
+                    tracing::info!("Capture source resized to {} x {}", size.x, size.y);
+                }
+                Poll::Ready(Some(CaptureEvent::Error(err))) => {
+                    tracing::warn!("Capture session dropped a frame: {}", err);
+                }
+                Poll::Ready(Some(CaptureEvent::Ended(reason))) => {
+                    tracing::info!("Capture session ended: {}", reason);
+                    self.end_reason = Some(reason);
+                    return Poll::Ready(None);
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
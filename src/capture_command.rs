@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+
+use futures::StreamExt;
+use loki::{
+    capture::{
+        CaptureFramerate, CaptureProvider, CaptureSessionBuilder, EndReason, Frame, Vector2,
+    },
+    utils::image_utils::{ImageFileFormat, encode_rgba},
+};
+use tokio::io::{AsyncWriteExt, Stdout};
+
+use crate::cli::{CaptureArgs, ServeArgs};
+
+/// Where captured frames go.
+enum FrameSink {
+    /// Numbered PNG files in a directory.
+    Directory(PathBuf),
+    /// Raw RGBA frames, one after another.
+    Stdout { stdout: Stdout, size: Option<Vector2<i32>> },
+    /// Frames are only exported to shared memory or served.
+    Discard,
+}
+
+impl FrameSink {
+    async fn new(args: &CaptureArgs) -> crate::Result<Self> {
+        match (&args.out, args.raw) {
+            (Some(directory), _) => {
+                tokio::fs::create_dir_all(directory).await?;
+                Ok(Self::Directory(directory.clone()))
+            }
+            (None, true) => Ok(Self::Stdout { stdout: tokio::io::stdout(), size: None }),
+            (None, false) => Ok(Self::Discard),
+        }
+    }
+
+    async fn write(&mut self, frame: Frame, index: u64) -> crate::Result<()> {
+        match self {
+            Self::Directory(directory) => {
+                let path = directory.join(format!("frame_{:06}.png", index));
+                let size = frame.size;
+                let data = frame.into_tightly_packed_rgba().to_vec();
+                let encoded = tokio::task::spawn_blocking(move || {
+                    encode_rgba(data, size, ImageFileFormat::Png)
+                })
+                .await
+                .map_err(std::io::Error::other)??;
+                tokio::fs::write(path, encoded).await?;
+            }
+            Self::Stdout { stdout, size } => {
+                if size.is_some_and(|size| size != frame.size) {
+                    // Consumers of raw video are told the size up front, so they will misread everything after this.
+                    tracing::warn!(
+                        "Frame size changed to {} x {}, raw output is no longer uniform.",
+                        frame.size.x,
+                        frame.size.y
+                    );
+                }
+                *size = Some(frame.size);
+                stdout.write_all(&frame.into_tightly_packed_rgba()).await?;
+            }
+            Self::Discard => (),
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> crate::Result<()> {
+        if let Self::Stdout { stdout, .. } = self {
+            stdout.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `loki capture` on its own runtime, as there is no UI to drive one.
+pub fn run(args: CaptureArgs, trace_frames: bool, serve: ServeArgs) -> crate::Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    runtime.block_on(capture(args, trace_frames, serve))
+}
+
+async fn capture(args: CaptureArgs, trace_frames: bool, serve: ServeArgs) -> crate::Result<()> {
+    let mut sink = FrameSink::new(&args).await?;
+    let mut builder = CaptureSessionBuilder::new(args.source())
+        .with_framerate(CaptureFramerate::from_fps(args.fps))
+        .with_cursor_capture(!args.no_cursor);
+    if let Some(name) = &args.shared_memory {
+        builder = builder.with_shared_memory_export(
+            name,
+            args.shared_memory_max_width,
+            args.shared_memory_max_height,
+        );
+    }
+    let mut session = builder.build()?;
+    session.provider_mut().set_trace_frames(trace_frames);
+    if let Some(info) = session.capture_item_info() {
+        tracing::info!("Capturing {}", info);
+    }
+    // Fed from a stream of its own, so slow clients never hold back the sink.
+    #[cfg(feature = "net")]
+    let mut server = match serve.options() {
+        Some(options) => {
+            let stream = session
+                .provider_mut()
+                .create_stream(CaptureFramerate::from_fps(args.fps))
+                .map_err(loki::capture_providers::CaptureError::from)?;
+            Some(loki::net::StreamServer::start(options, stream).await?)
+        }
+        None => None,
+    };
+    #[cfg(not(feature = "net"))]
+    let _ = serve;
+
+    let duration = args.duration();
+    let deadline = async {
+        match duration {
+            Some(duration) => tokio::time::sleep(duration).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(deadline);
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    let mut frames = 0u64;
+    let result = loop {
+        tokio::select! {
+            _ = &mut deadline => break Ok(()),
+            result = &mut ctrl_c => {
+                if let Err(err) = result {
+                    tracing::error!("Failed to listen for Ctrl+C: {}", err);
+                }
+                tracing::info!("Interrupted, stopping capture.");
+                break Ok(());
+            }
+            frame = session.next() => {
+                let Some(frame) = frame else {
+                    match session.end_reason() {
+                        Some(reason @ (EndReason::Failed(_) | EndReason::SourceLost)) => {
+                            break Err(crate::Error::CaptureEnded(reason.clone()));
+                        }
+                        reason => {
+                            tracing::info!("Capture ended: {:?}", reason);
+                            break Ok(());
+                        }
+                    }
+                };
+                if let Err(err) = sink.write(frame, frames).await {
+                    break Err(err);
+                }
+                frames += 1;
+            }
+        }
+    };
+
+    if let Err(err) = session.provider_mut().stop_capture().await {
+        tracing::error!("Failed to stop capture: {}", err);
+    }
+    sink.flush().await?;
+    // Stopping the capture ended the server's stream, so this only waits for clients to be closed.
+    #[cfg(feature = "net")]
+    if let Some(server) = &mut server {
+        server.wait().await;
+    }
+    tracing::info!("Captured {} frames, {} dropped.", frames, session.dropped_frames());
+    result
+}
@@ -1,12 +1,79 @@
-use crate::capture_providers::shared::CaptureFramerate;
+use futures::{
+    Stream, StreamExt,
+    future::{self, BoxFuture},
+};
 
+use crate::capture_providers::shared::{
+    CaptureEvent, CaptureFramerate, CaptureItemInfo, CaptureStats, CaptureTarget, Frame, Rect,
+    ScaleMode, StreamId,
+};
+
+/// The events of a capture, as handed out by a `CaptureProvider`.
+pub trait CaptureStream: Stream<Item = CaptureEvent> + Send + Unpin {
+    /// Identifies the stream to its provider, see `CaptureProvider::set_stream_framerate`.
+    fn id(&self) -> StreamId;
+
+    /// Yields only the frames, ending with the stream. For consumers that don't care about the other events.
+    fn frames_only(self) -> impl Stream<Item = Frame> + Send + 'static
+    where
+        Self: Sized + 'static,
+    {
+        self.take_while(|event| future::ready(!matches!(event, CaptureEvent::Ended(_)))).filter_map(
+            |event| {
+                future::ready(match event {
+                    CaptureEvent::Frame(frame) => Some(frame),
+                    _ => None,
+                })
+            },
+        )
+    }
+}
+
+/// A source of frames, e.g. Windows.Graphics.Capture. Usable as a trait object with the associated types given,
+/// e.g. `Box<dyn CaptureProvider<Stream = _, LatestFrameHandle = _, CaptureItem = _, Error = _>>`.
 pub trait CaptureProvider {
-    type Result<T>;
-    type Stream;
+    type Stream: CaptureStream;
+    /// Yields only the newest frame whenever it gets polled, rather than every frame.
+    type LatestFrameHandle: CaptureStream;
     type CaptureItem;
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    fn create_stream(&mut self, framerate: CaptureFramerate) -> Result<Self::Stream, Self::Error>;
+    /// Creates a stream whose consumer skips the frames it didn't get to, e.g. for a preview.
+    fn create_latest_frame_handle(
+        &mut self,
+        framerate: CaptureFramerate,
+    ) -> Result<Self::LatestFrameHandle, Self::Error>;
+    /// Changes the framerate of a stream of this provider. Returns false if the stream has been dropped.
+    fn set_stream_framerate(
+        &mut self,
+        stream: StreamId,
+        framerate: CaptureFramerate,
+    ) -> Result<bool, Self::Error>;
+    fn set_capture_item(&mut self, capture_item: Self::CaptureItem) -> Result<(), Self::Error>;
+    /// Starting a session can block, the future completes once frames may arrive.
+    fn start_capture(&mut self) -> BoxFuture<'_, Result<(), Self::Error>>;
+    /// Completes once the session is stopped and its streams have ended.
+    fn stop_capture(&mut self) -> BoxFuture<'_, Result<(), Self::Error>>;
+    /// Stops delivering frames while keeping the streams open.
+    fn pause_capture(&mut self) -> Result<(), Self::Error>;
+    fn resume_capture(&mut self) -> Result<(), Self::Error>;
+
+    /// Whether the platform allows hiding the capture border, see `set_border_required`.
+    fn supports_border_toggle() -> bool
+    where
+        Self: Sized;
+    fn set_cursor_capture(&mut self, enabled: bool) -> Result<(), Self::Error>;
+    fn set_border_required(&mut self, required: bool) -> Result<(), Self::Error>;
+    fn set_output_scale(&mut self, scale: ScaleMode);
+    /// Restricts frames to a region of the capture item, in its pixels. `None` captures all of it.
+    fn set_crop_region(&mut self, region: Option<Rect<i32>>);
+    /// Sets whether every delivered frame is logged at trace level.
+    fn set_trace_frames(&mut self, enabled: bool);
 
-    fn create_stream(&mut self, framerate: CaptureFramerate) -> Self::Result<Self::Stream>;
-    fn set_capture_item(&mut self, capture_item: Self::CaptureItem) -> Self::Result<()>;
-    fn start_capture(&mut self) -> Self::Result<()>;
-    fn stop_capture(&mut self) -> Self::Result<()>;
+    /// Describes the current capture item, or `None` if no item is set.
+    fn capture_item_info(&self) -> Option<CaptureItemInfo>;
+    /// Describes the current capture item well enough to find it again in a later run, if it can be.
+    fn capture_target(&self) -> Option<CaptureTarget>;
+    fn stats(&self) -> CaptureStats;
 }
@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use futures::{FutureExt, future::BoxFuture};
+
+use crate::{
+    capture_providers::{
+        CaptureProvider,
+        mock::{MockCaptureError, MockCaptureStream, Result},
+        shared::{
+            CaptureFramerate, CaptureItemInfo, CaptureItemKind, CaptureStats, CaptureTarget,
+            PixelFormat, Rect, ScaleMode, StreamId, Vector2,
+        },
+    },
+    utils::image_utils::supports_conversion,
+};
+
+/// What the frames of a mock capture look like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MockPattern {
+    /// A gradient that scrolls to the right.
+    #[default]
+    Gradient,
+    /// Squares of 32 pixels that scroll to the right.
+    Checkerboard,
+}
+
+/// Stands in for a window or monitor, with frames of the given size and format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockCaptureItem {
+    pub name: String,
+    pub size: Vector2<i32>,
+    pub format: PixelFormat,
+    pub pattern: MockPattern,
+}
+
+impl MockCaptureItem {
+    pub fn new(size: Vector2<i32>, format: PixelFormat) -> Self {
+        Self { name: "Mock source".to_string(), size, format, pattern: MockPattern::default() }
+    }
+
+    pub fn with_pattern(mut self, pattern: MockPattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+}
+
+impl Default for MockCaptureItem {
+    fn default() -> Self {
+        Self::new(Vector2::new(1920, 1080), PixelFormat::RGBA8)
+    }
+}
+
+/// Stands in for the system picker, as if the user picked the default item right away.
+pub fn user_pick_capture_item(
+    _window: u64,
+) -> Result<impl Future<Output = Result<MockCaptureItem>>> {
+    Ok(std::future::ready(Ok(MockCaptureItem::default())))
+}
+
+/// Shared with the streams, which read the settings for every frame.
+#[derive(Debug)]
+pub(super) struct MockState {
+    pub item: Option<MockCaptureItem>,
+    pub capturing: bool,
+    /// Streams keep following the capture while paused, without frames.
+    pub paused: bool,
+    /// Bumped whenever capture starts, so streams can tell a later capture from the one they followed.
+    pub session: u64,
+    pub scale: ScaleMode,
+    pub crop: Option<Rect<i32>>,
+    pub trace_frames: bool,
+    pub stats: CaptureStats,
+    /// The framerate of every live stream, which it reads before every frame.
+    pub framerates: HashMap<StreamId, CaptureFramerate>,
+    pub next_stream_id: u64,
+}
+
+pub(super) fn lock_state(state: &Mutex<MockState>) -> MutexGuard<'_, MockState> {
+    state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Synthesizes frames at the framerate of each stream, on the tokio timer rather than a capture thread. Streams
+/// wait for `start_capture` and end with `stop_capture`, like those of the platform providers.
+#[derive(Debug)]
+pub struct MockCaptureProvider {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockCaptureProvider {
+    pub fn new() -> Self {
+        let state = MockState {
+            item: None,
+            capturing: false,
+            paused: false,
+            session: 0,
+            scale: ScaleMode::Native,
+            crop: None,
+            trace_frames: false,
+            stats: CaptureStats::default(),
+            framerates: HashMap::new(),
+            next_stream_id: 0,
+        };
+        Self { state: Arc::new(Mutex::new(state)) }
+    }
+
+    pub fn with_item(item: MockCaptureItem) -> Result<Self> {
+        let mut provider = Self::new();
+        provider.set_capture_item(item)?;
+        Ok(provider)
+    }
+}
+
+impl Default for MockCaptureProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Ends the streams, like the platform providers do when they are dropped.
+impl Drop for MockCaptureProvider {
+    fn drop(&mut self) {
+        lock_state(&self.state).capturing = false;
+    }
+}
+
+impl CaptureProvider for MockCaptureProvider {
+    type Stream = MockCaptureStream;
+    /// Frames are made up when polled, so each one is already the newest.
+    type LatestFrameHandle = MockCaptureStream;
+    type CaptureItem = MockCaptureItem;
+    type Error = MockCaptureError;
+
+    fn create_stream(&mut self, framerate: CaptureFramerate) -> Result<MockCaptureStream> {
+        Ok(MockCaptureStream::new(self.state.clone(), framerate))
+    }
+
+    fn create_latest_frame_handle(
+        &mut self,
+        framerate: CaptureFramerate,
+    ) -> Result<MockCaptureStream> {
+        self.create_stream(framerate)
+    }
+
+    fn set_stream_framerate(
+        &mut self,
+        stream: StreamId,
+        framerate: CaptureFramerate,
+    ) -> Result<bool> {
+        match lock_state(&self.state).framerates.get_mut(&stream) {
+            Some(current) => {
+                *current = framerate;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Frames are drawn in RGBA8 and converted, so the item can be in any format RGBA8 converts to.
+    fn set_capture_item(&mut self, capture_item: MockCaptureItem) -> Result<()> {
+        if !supports_conversion(PixelFormat::RGBA8, capture_item.format) {
+            return Err(MockCaptureError::UnsupportedPixelFormat(capture_item.format));
+        }
+        lock_state(&self.state).item = Some(capture_item);
+        Ok(())
+    }
+
+    fn start_capture(&mut self) -> BoxFuture<'_, Result<()>> {
+        async move {
+            let mut state = lock_state(&self.state);
+            if state.paused {
+                return Err(MockCaptureError::Paused);
+            }
+            if state.capturing {
+                return Err(MockCaptureError::AlreadyCapturing);
+            }
+            if state.item.is_none() {
+                return Err(MockCaptureError::NoCaptureItem);
+            }
+            state.capturing = true;
+            state.session += 1;
+            tracing::info!("Mock capture started.");
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn stop_capture(&mut self) -> BoxFuture<'_, Result<()>> {
+        async move {
+            let mut state = lock_state(&self.state);
+            if !state.capturing {
+                return Err(MockCaptureError::NotCapturing);
+            }
+            state.capturing = false;
+            state.paused = false;
+            tracing::info!("Mock capture stopped.");
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn pause_capture(&mut self) -> Result<()> {
+        let mut state = lock_state(&self.state);
+        if !state.capturing {
+            return Err(MockCaptureError::NotCapturing);
+        }
+        if state.paused {
+            return Err(MockCaptureError::Paused);
+        }
+        state.paused = true;
+        tracing::info!("Mock capture paused.");
+        Ok(())
+    }
+
+    fn resume_capture(&mut self) -> Result<()> {
+        let mut state = lock_state(&self.state);
+        if !state.paused {
+            return Err(MockCaptureError::NotPaused);
+        }
+        state.paused = false;
+        tracing::info!("Mock capture resumed.");
+        Ok(())
+    }
+
+    /// There is no border to begin with.
+    fn supports_border_toggle() -> bool {
+        true
+    }
+
+    fn set_cursor_capture(&mut self, _enabled: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_border_required(&mut self, _required: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_output_scale(&mut self, scale: ScaleMode) {
+        lock_state(&self.state).scale = scale;
+    }
+
+    fn set_crop_region(&mut self, region: Option<Rect<i32>>) {
+        lock_state(&self.state).crop = region;
+    }
+
+    fn set_trace_frames(&mut self, enabled: bool) {
+        lock_state(&self.state).trace_frames = enabled;
+    }
+
+    fn capture_item_info(&self) -> Option<CaptureItemInfo> {
+        let state = lock_state(&self.state);
+        let item = state.item.as_ref()?;
+        Some(CaptureItemInfo::new(
+            Some(item.name.clone()),
+            CaptureItemKind::Unknown,
+            item.size,
+            None,
+        ))
+    }
+
+    /// Mock items can't be found again.
+    fn capture_target(&self) -> Option<CaptureTarget> {
+        None
+    }
+
+    fn stats(&self) -> CaptureStats {
+        lock_state(&self.state).stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::capture_providers::CaptureStream;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Items are told apart by their width.
+    fn item(width: i32) -> MockCaptureItem {
+        MockCaptureItem::new(Vector2::new(width, 8), PixelFormat::RGBA8)
+    }
+
+    #[tokio::test]
+    async fn switching_items_while_capturing_keeps_the_stream() {
+        let mut provider = MockCaptureProvider::with_item(item(8)).unwrap();
+        let stream = provider.create_stream(CaptureFramerate::FPS60).unwrap();
+        let mut frames = std::pin::pin!(stream.frames_only());
+        provider.start_capture().await.unwrap();
+
+        let mut widths = Vec::new();
+        let first = tokio::time::timeout(TIMEOUT, frames.next()).await.unwrap().unwrap();
+        widths.push(first.size.x);
+        for width in [16, 32] {
+            provider.set_capture_item(item(width)).unwrap();
+            let switched = tokio::time::timeout(TIMEOUT, async {
+                while let Some(frame) = frames.next().await {
+                    widths.push(frame.size.x);
+                    if frame.size.x == width {
+                        return;
+                    }
+                }
+                panic!("the stream ended while switching to {}", width);
+            });
+            switched.await.expect("no frame of the new item arrived");
+        }
+
+        provider.stop_capture().await.unwrap();
+        let rest = tokio::time::timeout(TIMEOUT, frames.collect::<Vec<_>>()).await;
+        let rest = rest.expect("the stream didn't end after the capture stopped");
+        widths.extend(rest.iter().map(|frame| frame.size.x));
+        // No frame of an earlier item follows one of a later item.
+        assert!(widths.is_sorted(), "{:?}", widths);
+        assert_eq!(widths.first(), Some(&8));
+        assert_eq!(widths.last(), Some(&32));
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, ready},
+    time::Instant,
+};
+
+use futures::Stream;
+use tokio::time::{Interval, MissedTickBehavior};
+
+use crate::capture_providers::{
+    CaptureStream,
+    mock::{
+        MockCaptureItem, MockPattern,
+        capture_provider::{MockState, lock_state},
+    },
+    shared::{
+        CaptureEvent, CaptureFramerate, Frame, FrameTiming, PixelFormat, Rect, StreamId, Vector2,
+    },
+};
+
+/// Frames of a `MockCaptureProvider`, along with the same events a platform stream yields.
+pub struct MockCaptureStream {
+    state: Arc<Mutex<MockState>>,
+    id: StreamId,
+    /// The framerate of the interval, which is recreated once `MockState::framerates` has another.
+    framerate: CaptureFramerate,
+    /// Created on the first poll, as the timer needs a tokio runtime.
+    interval: Option<Interval>,
+    /// The capture this stream follows, once it started.
+    session: Option<u64>,
+    last_size: Option<Vector2<i32>>,
+    /// Events that go out ahead of the next frame.
+    queued: VecDeque<CaptureEvent>,
+    created: Instant,
+    next_sequence: u64,
+    ended: bool,
+}
+
+impl MockCaptureStream {
+    pub(super) fn new(state: Arc<Mutex<MockState>>, framerate: CaptureFramerate) -> Self {
+        let id = {
+            let mut state = lock_state(&state);
+            let id = StreamId(state.next_stream_id);
+            state.next_stream_id += 1;
+            state.framerates.insert(id, framerate);
+            id
+        };
+        Self {
+            state,
+            id,
+            framerate,
+            interval: None,
+            session: None,
+            last_size: None,
+            queued: VecDeque::new(),
+            created: Instant::now(),
+            next_sequence: 0,
+            ended: false,
+        }
+    }
+
+    /// Draws the next frame if capture is running. Returns false once the capture this stream followed stopped.
+    fn produce(&mut self) -> bool {
+        let mut state = lock_state(&self.state);
+        match self.session {
+            Some(session) if !state.capturing || session != state.session => return false,
+            Some(_) => {}
+            None if state.capturing => {
+                self.session = Some(state.session);
+                self.queued.push_back(CaptureEvent::Started);
+            }
+            None => return true,
+        }
+        if state.paused {
+            return true;
+        }
+        let Some(item) = state.item.clone() else {
+            return true;
+        };
+
+        let content = Rect::new(Vector2::new(0, 0), item.size);
+        let source = state.crop.and_then(|crop| crop.clamp_to(item.size)).unwrap_or(content);
+        let output_size = state.scale.target_size(source.size);
+        if self.last_size.replace(output_size).is_some_and(|last| last != output_size) {
+            self.queued.push_back(CaptureEvent::Resized(output_size));
+        }
+        state.stats.frames_arrived += 1;
+        state.stats.frames_delivered += 1;
+        state.stats.last_frame_size = output_size;
+        let trace_frames = state.trace_frames;
+        drop(state);
+
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        let frame = render(&item, source, output_size, sequence, self.created);
+        if trace_frames {
+            tracing::trace!(
+                target: "loki::frames",
+                "Mock frame {}: {} x {}, {} bytes",
+                frame.sequence,
+                frame.size.x,
+                frame.size.y,
+                frame.data.len()
+            );
+        }
+        self.queued.push_back(CaptureEvent::Frame(frame));
+        true
+    }
+}
+
+impl std::fmt::Debug for MockCaptureStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockCaptureStream")
+            .field("id", &self.id)
+            .field("framerate", &self.framerate)
+            .field("session", &self.session)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Stream for MockCaptureStream {
+    type Item = CaptureEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(event) = self.queued.pop_front() {
+                return Poll::Ready(Some(event));
+            }
+            if self.ended {
+                return Poll::Ready(None);
+            }
+
+            let framerate = lock_state(&self.state).framerates.get(&self.id).copied();
+            if let Some(framerate) = framerate.filter(|&framerate| framerate != self.framerate) {
+                self.framerate = framerate;
+                self.interval = None;
+            }
+            let frametime = self.framerate.to_frametime();
+            let interval = self.interval.get_or_insert_with(|| {
+                let mut interval = tokio::time::interval(frametime);
+                // A consumer that falls behind gets the current frame, not a burst of old ones.
+                interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                interval
+            });
+            ready!(interval.poll_tick(cx));
+            if !self.produce() {
+                self.ended = true;
+            }
+        }
+    }
+}
+
+impl CaptureStream for MockCaptureStream {
+    fn id(&self) -> StreamId {
+        self.id
+    }
+}
+
+impl Drop for MockCaptureStream {
+    fn drop(&mut self) {
+        lock_state(&self.state).framerates.remove(&self.id);
+    }
+}
+
+/// Draws the pattern of `item` for the `source` part of it, scaled to `output_size`, and converts it to the
+/// format of the item. The pattern moves a few pixels every frame.
+fn render(
+    item: &MockCaptureItem,
+    source: Rect<i32>,
+    output_size: Vector2<i32>,
+    sequence: u64,
+    created: Instant,
+) -> Frame {
+    let width = output_size.x.max(0) as usize;
+    let height = output_size.y.max(0) as usize;
+    let phase = (sequence * 4) as usize;
+    let item_x = |x: usize| source.position.x as usize + x * source.size.x as usize / width.max(1);
+    let item_y = |y: usize| source.position.y as usize + y * source.size.y as usize / height.max(1);
+
+    let mut data = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        let iy = item_y(y);
+        for x in 0..width {
+            let ix = item_x(x) + phase;
+            let pixel = match item.pattern {
+                MockPattern::Gradient => [(ix % 256) as u8, (iy % 256) as u8, 128, 255],
+                MockPattern::Checkerboard if (ix / 32 + iy / 32) % 2 == 0 => [230, 230, 230, 255],
+                MockPattern::Checkerboard => [40, 40, 40, 255],
+            };
+            data.extend_from_slice(&pixel);
+        }
+    }
+
+    // In 100ns units, like the timestamps of WGC.
+    let timestamp = (created.elapsed().as_nanos() / 100) as i64;
+    let timing = FrameTiming { timestamp, sequence, capture_instant: Instant::now() };
+    let dirty_rects = vec![Rect::new(Vector2::new(0, 0), output_size)];
+    Frame::new_converted(
+        data,
+        PixelFormat::RGBA8,
+        item.format,
+        output_size,
+        width * 4,
+        timing,
+        dirty_rects,
+    )
+}
@@ -0,0 +1,27 @@
+//! A capture provider that makes up its frames, for trying out consumers without a GPU or a Windows desktop.
+
+mod capture_provider;
+mod capture_stream;
+
+pub use capture_provider::*;
+pub use capture_stream::*;
+
+use crate::capture_providers::shared::PixelFormat;
+
+pub type Result<T> = std::result::Result<T, MockCaptureError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MockCaptureError {
+    #[error("Already capturing")]
+    AlreadyCapturing,
+    #[error("Not capturing")]
+    NotCapturing,
+    #[error("Capture is paused, resume it instead of starting it")]
+    Paused,
+    #[error("Capture is not paused")]
+    NotPaused,
+    #[error("No capture item available")]
+    NoCaptureItem,
+    #[error("Mock frames can't be made in {0:?}")]
+    UnsupportedPixelFormat(PixelFormat),
+}
@@ -1,11 +1,20 @@
 mod capture_provider;
+#[cfg(any(feature = "mock-provider", not(target_os = "windows")))]
+pub mod mock;
+pub mod platform;
 pub mod shared;
+#[cfg(target_os = "windows")]
 pub mod windows;
 
-pub use capture_provider::CaptureProvider;
+pub use capture_provider::{CaptureProvider, CaptureStream};
 
 #[derive(Debug, thiserror::Error)]
 pub enum CaptureError {
+    #[error("No capturable {0}")]
+    SourceNotFound(String),
+    #[cfg(any(feature = "mock-provider", not(target_os = "windows")))]
+    #[error(transparent)]
+    MockCaptureError(#[from] mock::MockCaptureError),
     #[cfg(target_os = "windows")]
     #[error(transparent)]
     WindowsCaptureError(#[from] windows::error::WindowsCaptureError),
@@ -20,3 +29,14 @@ pub use windows::user_pick_capture_item as user_pick_platform_capture_item;
 
 #[cfg(target_os = "windows")]
 pub type PlatformCaptureItem = <windows::WindowsCaptureProvider as CaptureProvider>::CaptureItem;
+
+// Frames are made up elsewhere, so code built on the platform types still runs.
+#[cfg(not(target_os = "windows"))]
+pub use mock::MockCaptureProvider as PlatformCaptureProvider;
+#[cfg(not(target_os = "windows"))]
+pub use mock::MockCaptureStream as PlatformCaptureStream;
+#[cfg(not(target_os = "windows"))]
+pub use mock::user_pick_capture_item as user_pick_platform_capture_item;
+
+#[cfg(not(target_os = "windows"))]
+pub type PlatformCaptureItem = <mock::MockCaptureProvider as CaptureProvider>::CaptureItem;
@@ -0,0 +1,163 @@
+//! Lists the monitors and windows of whatever platform the crate is built for, and creates capture items of
+//! `PlatformCaptureProvider` for them. Off Windows, there is a single monitor of the mock provider.
+
+#[cfg(not(target_os = "windows"))]
+use crate::capture_providers::mock::MockCaptureItem;
+#[cfg(target_os = "windows")]
+use crate::capture_providers::windows::{
+    IntoHWND, create_capture_item_for_target, create_capture_item_for_window,
+    enumerate_capturable_windows, enumerate_monitors, error::WindowsCaptureError,
+};
+use crate::capture_providers::{
+    CaptureError, PlatformCaptureItem,
+    shared::{CaptureTarget, Rect, Vector2},
+};
+
+/// A monitor that can be captured.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorSource {
+    /// Stays the same for a monitor between runs, e.g. `\\.\DISPLAY1` on Windows.
+    pub name: String,
+    /// Top left corner on the virtual desktop, in physical pixels.
+    pub position: Vector2<i32>,
+    /// In physical pixels, the same as the size of its capture item.
+    pub size: Vector2<i32>,
+    /// Physical pixels per logical pixel, e.g. 1.5 at 144 DPI.
+    pub scale_factor: f32,
+    pub is_primary: bool,
+}
+
+impl MonitorSource {
+    /// Where the monitor is on the virtual desktop, in physical pixels.
+    pub fn bounds(&self) -> Rect<i32> {
+        Rect::new(self.position, self.size)
+    }
+}
+
+/// A top-level window that can be captured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowSource {
+    /// The native handle of the window, only valid while it is open.
+    pub handle: u64,
+    pub title: String,
+}
+
+/// Name of the only monitor off Windows.
+#[cfg(not(target_os = "windows"))]
+const MOCK_MONITOR: &str = "Mock monitor";
+
+/// Every monitor attached to the desktop.
+#[cfg(target_os = "windows")]
+pub fn monitors() -> Vec<MonitorSource> {
+    enumerate_monitors()
+        .into_iter()
+        .map(|monitor| MonitorSource {
+            name: monitor.name,
+            position: monitor.position,
+            size: monitor.size,
+            scale_factor: monitor.scale_factor,
+            is_primary: monitor.is_primary,
+        })
+        .collect()
+}
+
+/// Every monitor attached to the desktop.
+#[cfg(not(target_os = "windows"))]
+pub fn monitors() -> Vec<MonitorSource> {
+    let size = MockCaptureItem::default().size;
+    vec![MonitorSource {
+        name: MOCK_MONITOR.to_string(),
+        position: Vector2::new(0, 0),
+        size,
+        scale_factor: 1.0,
+        is_primary: true,
+    }]
+}
+
+/// Every window that can be captured, front to back.
+#[cfg(target_os = "windows")]
+pub fn windows() -> Vec<WindowSource> {
+    enumerate_capturable_windows()
+        .into_iter()
+        .map(|window| WindowSource { handle: window.handle.0 as u64, title: window.name })
+        .collect()
+}
+
+/// Every window that can be captured, front to back.
+#[cfg(not(target_os = "windows"))]
+pub fn windows() -> Vec<WindowSource> {
+    Vec::new()
+}
+
+/// Creates a capture item for the monitor of the given `MonitorSource::name`.
+#[cfg(target_os = "windows")]
+pub fn monitor_capture_item(name: &str) -> Result<PlatformCaptureItem, CaptureError> {
+    let monitor = enumerate_monitors().into_iter().find(|monitor| monitor.name == name);
+    let Some(monitor) = monitor else {
+        return Err(CaptureError::SourceNotFound(format!("monitor {}", name)));
+    };
+    let item = monitor.to_capture_item().map_err(WindowsCaptureError::from)?;
+    Ok(item)
+}
+
+/// Creates a capture item for the monitor of the given `MonitorSource::name`.
+#[cfg(not(target_os = "windows"))]
+pub fn monitor_capture_item(name: &str) -> Result<PlatformCaptureItem, CaptureError> {
+    if name != MOCK_MONITOR {
+        return Err(CaptureError::SourceNotFound(format!("monitor {}", name)));
+    }
+    Ok(MockCaptureItem { name: name.to_string(), ..MockCaptureItem::default() })
+}
+
+/// Creates a capture item for the window of the given `WindowSource::handle`.
+#[cfg(target_os = "windows")]
+pub fn window_capture_item(handle: u64) -> Result<PlatformCaptureItem, CaptureError> {
+    Ok(create_capture_item_for_window(handle.into_hwnd())?)
+}
+
+/// Creates a capture item for the window of the given `WindowSource::handle`.
+#[cfg(not(target_os = "windows"))]
+pub fn window_capture_item(handle: u64) -> Result<PlatformCaptureItem, CaptureError> {
+    Err(CaptureError::SourceNotFound(format!("window {:#x}", handle)))
+}
+
+/// Creates a capture item for the window or monitor a target describes, e.g. one captured in an earlier run.
+#[cfg(target_os = "windows")]
+pub fn target_capture_item(target: &CaptureTarget) -> Result<PlatformCaptureItem, CaptureError> {
+    Ok(create_capture_item_for_target(target)?)
+}
+
+/// Creates a capture item for the window or monitor a target describes, e.g. one captured in an earlier run.
+#[cfg(not(target_os = "windows"))]
+pub fn target_capture_item(target: &CaptureTarget) -> Result<PlatformCaptureItem, CaptureError> {
+    match target {
+        CaptureTarget::Monitor { device_name, .. } => monitor_capture_item(device_name),
+        CaptureTarget::Window { .. } => Err(CaptureError::SourceNotFound(target.to_string())),
+    }
+}
+
+/// Physical pixels per logical pixel of the window of the given `WindowSource::handle`.
+#[cfg(target_os = "windows")]
+pub fn window_scale_factor(handle: u64) -> f32 {
+    crate::capture_providers::windows::window_scale_factor(handle.into_hwnd())
+}
+
+/// Physical pixels per logical pixel of the window of the given `WindowSource::handle`.
+#[cfg(not(target_os = "windows"))]
+pub fn window_scale_factor(_handle: u64) -> f32 {
+    1.0
+}
+
+/// Whether window and monitor coordinates are in physical pixels on every monitor, so they match the pixels of
+/// capture items.
+#[cfg(target_os = "windows")]
+pub fn is_per_monitor_dpi_aware() -> bool {
+    crate::capture_providers::windows::is_per_monitor_dpi_aware()
+}
+
+/// Whether window and monitor coordinates are in physical pixels on every monitor, so they match the pixels of
+/// capture items.
+#[cfg(not(target_os = "windows"))]
+pub fn is_per_monitor_dpi_aware() -> bool {
+    true
+}
@@ -0,0 +1,46 @@
+use std::{fmt::Display, sync::Arc};
+
+use crate::capture_providers::{
+    CaptureError,
+    shared::{Frame, Vector2},
+};
+
+/// Why a capture stream stopped producing frames.
+#[derive(Debug, Clone)]
+pub enum EndReason {
+    /// The captured window or monitor went away.
+    SourceClosed,
+    /// The session stopped delivering frames and its window or monitor no longer exists, e.g. after unplugging
+    /// a monitor. Unlike `SourceClosed`, WGC never reports this itself.
+    SourceLost,
+    /// A fatal error occurred while processing frames.
+    Failed(Arc<CaptureError>),
+}
+
+impl Display for EndReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SourceClosed => f.write_str("capture source closed"),
+            Self::SourceLost => f.write_str("capture source lost"),
+            Self::Failed(err) => write!(f, "capture failed: {}", err),
+        }
+    }
+}
+
+/// Item yielded by capture streams.
+#[derive(Debug, Clone)]
+pub enum CaptureEvent {
+    Frame(Frame),
+    /// The capture item produced its first frame, which follows right after. Sent again after switching items.
+    Started,
+    /// Frames of the capture item changed size, starting with the next frame. In physical pixels.
+    Resized(Vector2<i32>),
+    /// A frame could not be processed and was dropped. The stream carries on, and only the first of a run of
+    /// failures is reported.
+    Error(String),
+    /// The capture pipeline has been recreated, because the capture device was lost or the session stopped
+    /// delivering frames. Frames resume after a short gap.
+    Recreated,
+    /// The stream will not produce any more frames.
+    Ended(EndReason),
+}
@@ -1,15 +1,34 @@
-use std::{fmt::Display, time::Duration};
+use std::{cmp::Ordering, fmt::Display, num::NonZeroU32, str::FromStr, time::Duration};
 
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+/// Framerates compare by their exact rate rather than their variant, so `Custom(60)` equals `FPS60` and faster
+/// rates are greater, e.g. `Custom(240)` is greater than `FPS120`. `from_fps`, `fractional` and parsing return
+/// the preset where there is one.
+#[derive(Debug, Clone, Copy)]
 pub enum CaptureFramerate {
     FPS5,
     FPS24,
     FPS30,
     FPS60,
     FPS120,
+    Custom(NonZeroU32),
+    /// `frames` per `seconds`, for rates that aren't whole like NTSC film at 24000/1001, about 23.976 FPS.
+    Fractional {
+        frames: NonZeroU32,
+        seconds: NonZeroU32,
+    },
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid framerate: {0:?}")]
+pub struct ParseFramerateError(String);
+
+/// A framerate of 0 FPS, which would make for an infinite frametime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("Framerate must be above 0 FPS")]
+pub struct ZeroFramerateError;
+
 impl CaptureFramerate {
+    /// The presets offered in the UI.
     pub const ALL: [CaptureFramerate; 5] = [
         CaptureFramerate::FPS5,
         CaptureFramerate::FPS24,
@@ -18,15 +37,76 @@ impl CaptureFramerate {
         CaptureFramerate::FPS120,
     ];
 
-    pub fn to_frametime(&self) -> Duration {
+    /// Number of 100ns ticks in a second, which is the unit of WinRT TimeSpans.
+    pub const TICKS_PER_SECOND: u64 = 10_000_000;
+
+    /// Returns the preset matching the rate if there is one, otherwise a custom framerate.
+    pub fn from_fps(fps: NonZeroU32) -> Self {
+        Self::ALL.into_iter().find(|preset| preset.fps() == fps.get()).unwrap_or(Self::Custom(fps))
+    }
+
+    /// A framerate of `frames` per `seconds`, reduced to a whole one if it is.
+    pub fn fractional(frames: u32, seconds: u32) -> Result<Self, ZeroFramerateError> {
+        let frames = NonZeroU32::new(frames).ok_or(ZeroFramerateError)?;
+        let seconds = NonZeroU32::new(seconds).ok_or(ZeroFramerateError)?;
+        if frames.get() % seconds.get() == 0 {
+            let fps =
+                NonZeroU32::new(frames.get() / seconds.get()).expect("Frames are at least seconds");
+            return Ok(Self::from_fps(fps));
+        }
+        Ok(Self::Fractional { frames, seconds })
+    }
+
+    /// Whole frames per second, rounded to the nearest for fractional rates. Use `ratio` where that matters.
+    pub fn fps(&self) -> u32 {
+        match self {
+            Self::FPS5 => 5,
+            Self::FPS24 => 24,
+            Self::FPS30 => 30,
+            Self::FPS60 => 60,
+            Self::FPS120 => 120,
+            Self::Custom(fps) => fps.get(),
+            Self::Fractional { .. } => {
+                let (frames, seconds) = self.ratio();
+                ((frames + seconds / 2) / seconds).max(1) as u32
+            }
+        }
+    }
+
+    /// The exact rate as frames per seconds, both above 0.
+    pub fn ratio(&self) -> (u64, u64) {
         match self {
-            Self::FPS5 => Duration::from_secs_f32(1 as f32 / 5 as f32),
-            Self::FPS24 => Duration::from_secs_f32(1 as f32 / 24 as f32),
-            Self::FPS30 => Duration::from_secs_f32(1 as f32 / 30 as f32),
-            Self::FPS60 => Duration::from_secs_f32(1 as f32 / 60 as f32),
-            Self::FPS120 => Duration::from_secs_f32(1 as f32 / 120 as f32),
+            Self::Fractional { frames, seconds } => (frames.get() as u64, seconds.get() as u64),
+            _ => (self.fps() as u64, 1),
         }
     }
+
+    pub fn fps_f64(&self) -> f64 {
+        let (frames, seconds) = self.ratio();
+        frames as f64 / seconds as f64
+    }
+
+    pub fn to_frametime(&self) -> Duration {
+        let (frames, seconds) = self.ratio();
+        let nanos = (seconds as u128 * 1_000_000_000 + frames as u128 / 2) / frames as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+
+    /// The frametime in 100ns ticks, rounded to the nearest tick.
+    /// Computed with integer math, as going through floats turns e.g. 144 FPS into ~143.9.
+    pub fn to_frametime_ticks(&self) -> i64 {
+        let (frames, seconds) = self.ratio();
+        ((Self::TICKS_PER_SECOND * seconds + frames / 2) / frames) as i64
+    }
+}
+
+impl TryFrom<u32> for CaptureFramerate {
+    type Error = ZeroFramerateError;
+
+    /// Returns the preset matching the rate if there is one, otherwise a custom framerate.
+    fn try_from(fps: u32) -> Result<Self, Self::Error> {
+        NonZeroU32::new(fps).map(Self::from_fps).ok_or(ZeroFramerateError)
+    }
 }
 
 impl Display for CaptureFramerate {
@@ -37,6 +117,140 @@ impl Display for CaptureFramerate {
             Self::FPS30 => f.write_str("30"),
             Self::FPS60 => f.write_str("60"),
             Self::FPS120 => f.write_str("120"),
+            Self::Custom(fps) => write!(f, "FPS({})", fps),
+            Self::Fractional { frames, seconds } => write!(f, "FPS({}/{})", frames, seconds),
+        }
+    }
+}
+
+impl FromStr for CaptureFramerate {
+    type Err = ParseFramerateError;
+
+    /// Parses the output of [`Display`], i.e. either a preset like "60" or a custom rate like "FPS(90)" or
+    /// "FPS(24000/1001)".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseFramerateError(s.to_owned());
+        let trimmed = s.trim();
+
+        if let Some(custom) = trimmed.strip_prefix("FPS(").and_then(|rest| rest.strip_suffix(')')) {
+            if let Some((frames, seconds)) = custom.split_once('/') {
+                let frames = frames.trim().parse::<u32>().map_err(|_| invalid())?;
+                let seconds = seconds.trim().parse::<u32>().map_err(|_| invalid())?;
+                return Self::fractional(frames, seconds).map_err(|_| invalid());
+            }
+            let fps = custom.trim().parse::<NonZeroU32>().map_err(|_| invalid())?;
+            return Ok(Self::from_fps(fps));
+        }
+
+        let fps = trimmed.parse::<u32>().map_err(|_| invalid())?;
+        Self::ALL.into_iter().find(|preset| preset.fps() == fps).ok_or_else(invalid)
+    }
+}
+
+impl PartialEq for CaptureFramerate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for CaptureFramerate {}
+
+impl PartialOrd for CaptureFramerate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CaptureFramerate {
+    /// Compares the ratios by cross multiplying, which is exact where the frametimes would be rounded.
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (frames, seconds) = self.ratio();
+        let (other_frames, other_seconds) = other.ratio();
+        (frames * other_seconds).cmp(&(other_frames * seconds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(fps: u32) -> CaptureFramerate {
+        CaptureFramerate::Custom(NonZeroU32::new(fps).unwrap())
+    }
+
+    #[test]
+    fn frametime_ticks_are_exact() {
+        let expected = [2_000_000, 416_667, 333_333, 166_667, 83_333];
+        for (preset, ticks) in CaptureFramerate::ALL.into_iter().zip(expected) {
+            assert_eq!(preset.to_frametime_ticks(), ticks, "{}", preset);
+        }
+        assert_eq!(custom(10).to_frametime_ticks(), 1_000_000);
+        assert_eq!(custom(90).to_frametime_ticks(), 111_111);
+        assert_eq!(custom(144).to_frametime_ticks(), 69_444);
+        assert_eq!(custom(240).to_frametime_ticks(), 41_667);
+        let film = CaptureFramerate::fractional(24_000, 1001).unwrap();
+        assert_eq!(film.to_frametime_ticks(), 417_083);
+    }
+
+    #[test]
+    fn frametime_is_rounded_to_the_nanosecond() {
+        assert_eq!(custom(144).to_frametime(), Duration::from_nanos(6_944_444));
+        assert_eq!(CaptureFramerate::FPS30.to_frametime(), Duration::from_nanos(33_333_333));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let film = CaptureFramerate::fractional(24_000, 1001).unwrap();
+        for framerate in CaptureFramerate::ALL.into_iter().chain([custom(90), custom(240), film]) {
+            let parsed: CaptureFramerate = framerate.to_string().parse().unwrap();
+            assert_eq!(parsed, framerate);
+            assert_eq!(parsed.to_string(), framerate.to_string());
         }
     }
+
+    #[test]
+    fn parsing_returns_presets() {
+        assert!(matches!("FPS(60)".parse(), Ok(CaptureFramerate::FPS60)));
+        assert!(matches!("FPS(120/2)".parse(), Ok(CaptureFramerate::FPS60)));
+        assert!(matches!(" 30 ".parse(), Ok(CaptureFramerate::FPS30)));
+        assert!(matches!("FPS(90)".parse(), Ok(CaptureFramerate::Custom(fps)) if fps.get() == 90));
+        assert!(matches!(
+            CaptureFramerate::from_fps(NonZeroU32::new(24).unwrap()),
+            CaptureFramerate::FPS24
+        ));
+        assert!(matches!(CaptureFramerate::fractional(48, 2), Ok(CaptureFramerate::FPS24)));
+    }
+
+    #[test]
+    fn parsing_rejects_invalid_rates() {
+        for invalid in ["", "0", "90", "FPS(0)", "FPS(1/0)", "FPS(abc)", "FPS(60", "fps(60)"] {
+            assert!(invalid.parse::<CaptureFramerate>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn framerates_compare_by_rate() {
+        assert_eq!(custom(60), CaptureFramerate::FPS60);
+        assert_eq!(
+            CaptureFramerate::fractional(50, 4).unwrap(),
+            CaptureFramerate::fractional(25, 2).unwrap()
+        );
+        assert!(custom(240) > CaptureFramerate::FPS120);
+        assert!(custom(10) < CaptureFramerate::FPS24);
+        assert!(CaptureFramerate::fractional(24_000, 1001).unwrap() < CaptureFramerate::FPS24);
+        let mut sorted =
+            [custom(240), CaptureFramerate::FPS5, custom(10), CaptureFramerate::FPS120];
+        sorted.sort();
+        assert_eq!(
+            sorted,
+            [CaptureFramerate::FPS5, custom(10), CaptureFramerate::FPS120, custom(240)]
+        );
+    }
+
+    #[test]
+    fn zero_is_not_a_framerate() {
+        assert_eq!(CaptureFramerate::try_from(0), Err(ZeroFramerateError));
+        assert_eq!(CaptureFramerate::fractional(0, 1), Err(ZeroFramerateError));
+        assert_eq!(CaptureFramerate::fractional(1, 0), Err(ZeroFramerateError));
+    }
 }
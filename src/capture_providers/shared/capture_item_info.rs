@@ -0,0 +1,57 @@
+use std::fmt::Display;
+
+use crate::capture_providers::shared::Vector2;
+
+/// What kind of thing is being captured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureItemKind {
+    Window,
+    Monitor,
+    /// The item could not be matched to a window or monitor.
+    Unknown,
+}
+
+impl Display for CaptureItemKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Window => f.write_str("window"),
+            Self::Monitor => f.write_str("monitor"),
+            Self::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+/// Name of capture items the platform reports no name for.
+pub const UNKNOWN_DISPLAY_NAME: &str = "<unknown>";
+
+/// Describes the item a provider is capturing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureItemInfo {
+    pub display_name: String,
+    pub kind: CaptureItemKind,
+    /// In physical pixels, regardless of the scale factor of the monitor.
+    pub size: Vector2<i32>,
+    /// The platform handle of the item if it could be found, a HWND or HMONITOR on Windows depending on `kind`.
+    pub native_handle: Option<usize>,
+}
+
+impl CaptureItemInfo {
+    /// Falls back to `UNKNOWN_DISPLAY_NAME` if the platform reports no name, as is the case for some UWP windows.
+    pub fn new(
+        display_name: Option<String>,
+        kind: CaptureItemKind,
+        size: Vector2<i32>,
+        native_handle: Option<usize>,
+    ) -> Self {
+        let display_name = display_name
+            .filter(|name| !name.trim().is_empty())
+            .unwrap_or_else(|| UNKNOWN_DISPLAY_NAME.to_string());
+        Self { display_name, kind, size, native_handle }
+    }
+}
+
+impl Display for CaptureItemInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}, {}x{})", self.display_name, self.kind, self.size.x, self.size.y)
+    }
+}
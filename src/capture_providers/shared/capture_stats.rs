@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use crate::capture_providers::shared::Vector2;
+
+/// The distribution of a latency over the last frames, zero before the first one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+/// A snapshot of the capture counters of a provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaptureStats {
+    /// Frames WGC handed to the FrameArrived handlers, including ones that were skipped or dropped.
+    pub frames_arrived: u64,
+    /// Frames sent to a stream.
+    pub frames_delivered: u64,
+    /// Frames lost because a stream's queue was full, or because a newer frame was read back before them.
+    pub frames_dropped: u64,
+    /// Frames that were skipped before readback because nothing changed.
+    pub frames_skipped_unchanged: u64,
+    /// Frames that were skipped before readback because they arrived faster than the stream's framerate.
+    pub frames_skipped_rate_limit: u64,
+    /// Readback buffers that were reused and that had to be allocated. Once the stream has warmed up, nearly
+    /// every frame should be a hit.
+    pub buffer_pool_hits: u64,
+    pub buffer_pool_misses: u64,
+    /// Bytes held by idle readback buffers of all streams.
+    pub buffer_pool_bytes: u64,
+    /// Frames whose GPU copy hadn't finished when they arrived. They are read back with a later frame instead,
+    /// or dropped if a newer frame finishes first.
+    pub readback_stalls: u64,
+    /// Times the pipeline was rebuilt on a new device after the old one was lost, e.g. to a driver reset. Each
+    /// leaves a gap in the frames, and streams get a `CaptureEvent::Recreated`.
+    pub device_recoveries: u64,
+    /// Size of the last delivered frame, zero before the first one.
+    pub last_frame_size: Vector2<i32>,
+    /// From the FrameArrived handler firing to the frame being handed to the stream, i.e. the time spent in
+    /// readback and conversion.
+    pub delivery_latency: LatencyStats,
+    /// From the frame's `SystemRelativeTime`, when it was composed for the display, to it being handed to the
+    /// stream. Includes the time WGC took to deliver it.
+    pub display_latency: LatencyStats,
+}
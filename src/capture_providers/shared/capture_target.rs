@@ -0,0 +1,171 @@
+use std::fmt::Display;
+
+use crate::capture_providers::shared::Vector2;
+
+/// Describes a window or monitor well enough to find it again after its capture item is gone, e.g. in the next
+/// run. Unlike a capture item or a handle, this can be stored.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CaptureTarget {
+    Monitor {
+        /// As in `MonitorInfo::name`, e.g. `\\.\DISPLAY1`.
+        device_name: String,
+        /// Top left corner on the virtual desktop, in physical pixels.
+        position: Vector2<i32>,
+        size: Vector2<i32>,
+    },
+    Window {
+        title: String,
+        /// The window class, which stays the same for a kind of window of an application, unlike the title.
+        class_name: String,
+        /// File name of the executable the window belongs to, e.g. `notepad.exe`.
+        exe_name: String,
+    },
+}
+
+impl Display for CaptureTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Monitor { device_name, size, .. } => {
+                write!(f, "monitor {} ({}x{})", device_name, size.x, size.y)
+            }
+            Self::Window { title, exe_name, .. } => write!(f, "window {:?} of {}", title, exe_name),
+        }
+    }
+}
+
+impl CaptureTarget {
+    /// Picks the candidate this target describes, each candidate described as a target of its own.
+    ///
+    /// Device names of monitors are reassigned when displays are plugged in a different order, so the name alone
+    /// isn't trusted if another monitor has the exact geometry. The class and executable of windows have to match,
+    /// the title only picks between several such windows, as it changes with what the window shows.
+    pub fn find_in<'a, T>(&self, candidates: &'a [(CaptureTarget, T)]) -> Option<&'a T> {
+        match self {
+            Self::Monitor { device_name, position, size } => {
+                let monitors: Vec<_> = candidates
+                    .iter()
+                    .filter_map(|(target, candidate)| match target {
+                        Self::Monitor { device_name: name, position: at, size: of } => {
+                            Some((name == device_name, at == position && of == size, candidate))
+                        }
+                        Self::Window { .. } => None,
+                    })
+                    .collect();
+                monitors
+                    .iter()
+                    .find(|(same_name, same_geometry, _)| *same_name && *same_geometry)
+                    .or_else(|| monitors.iter().find(|(_, same_geometry, _)| *same_geometry))
+                    .or_else(|| monitors.iter().find(|(same_name, _, _)| *same_name))
+                    .map(|(_, _, candidate)| *candidate)
+            }
+            Self::Window { title, class_name, exe_name } => {
+                let windows: Vec<_> = candidates
+                    .iter()
+                    .filter_map(|(target, candidate)| match target {
+                        Self::Window {
+                            title: window_title,
+                            class_name: window_class,
+                            exe_name: window_exe,
+                        } if window_class == class_name
+                            && window_exe.eq_ignore_ascii_case(exe_name) =>
+                        {
+                            Some((window_title, candidate))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                windows
+                    .iter()
+                    .find(|(window_title, _)| *window_title == title)
+                    .or(windows.first())
+                    .map(|(_, candidate)| *candidate)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(device_name: &str, x: i32, width: i32) -> CaptureTarget {
+        CaptureTarget::Monitor {
+            device_name: device_name.to_string(),
+            position: Vector2::new(x, 0),
+            size: Vector2::new(width, 1080),
+        }
+    }
+
+    fn window(title: &str, class_name: &str, exe_name: &str) -> CaptureTarget {
+        CaptureTarget::Window {
+            title: title.to_string(),
+            class_name: class_name.to_string(),
+            exe_name: exe_name.to_string(),
+        }
+    }
+
+    #[test]
+    fn monitors_with_the_same_name_and_geometry_come_first() {
+        let monitors = [
+            (monitor(r"\\.\DISPLAY2", 0, 1920), 1),
+            (monitor(r"\\.\DISPLAY1", 0, 1920), 2),
+            (monitor(r"\\.\DISPLAY1", 1920, 2560), 3),
+        ];
+        assert_eq!(monitor(r"\\.\DISPLAY1", 0, 1920).find_in(&monitors), Some(&2));
+    }
+
+    #[test]
+    fn monitor_geometry_wins_over_the_name() {
+        // The displays were plugged in the other way around, so their names swapped.
+        let monitors =
+            [(monitor(r"\\.\DISPLAY1", 1920, 2560), 1), (monitor(r"\\.\DISPLAY2", 0, 1920), 2)];
+        assert_eq!(monitor(r"\\.\DISPLAY1", 0, 1920).find_in(&monitors), Some(&2));
+    }
+
+    #[test]
+    fn monitors_are_found_by_name_once_the_geometry_changed() {
+        let monitors =
+            [(monitor(r"\\.\DISPLAY1", 0, 3840), 1), (monitor(r"\\.\DISPLAY2", 3840, 1920), 2)];
+        assert_eq!(monitor(r"\\.\DISPLAY2", 1920, 1920).find_in(&monitors), Some(&2));
+        assert_eq!(monitor(r"\\.\DISPLAY3", 1920, 1920).find_in(&monitors), None);
+    }
+
+    #[test]
+    fn windows_need_the_same_class_and_executable() {
+        let windows = [
+            (window("notes.txt - Notepad", "Notepad", "notepad.exe"), 1),
+            (window("Untitled - Notepad", "Edit", "notepad.exe"), 2),
+            (window("Untitled - Notepad", "Notepad", "wordpad.exe"), 3),
+        ];
+        // The title differs, but nothing else matches the class and executable.
+        let target = window("Untitled - Notepad", "Notepad", "NOTEPAD.EXE");
+        assert_eq!(target.find_in(&windows), Some(&1));
+        assert_eq!(window("Untitled - Notepad", "Notepad", "calc.exe").find_in(&windows), None);
+    }
+
+    #[test]
+    fn window_titles_break_ties() {
+        let windows = [
+            (window("notes.txt - Notepad", "Notepad", "notepad.exe"), 1),
+            (window("Untitled - Notepad", "Notepad", "notepad.exe"), 2),
+        ];
+        assert_eq!(
+            window("Untitled - Notepad", "Notepad", "notepad.exe").find_in(&windows),
+            Some(&2)
+        );
+        // Without a window of the same title, the first one of the application is taken.
+        assert_eq!(
+            window("todo.txt - Notepad", "Notepad", "notepad.exe").find_in(&windows),
+            Some(&1)
+        );
+    }
+
+    #[test]
+    fn monitors_and_windows_never_match_each_other() {
+        let candidates = [(window(r"\\.\DISPLAY1", "Notepad", "notepad.exe"), 1)];
+        assert_eq!(monitor(r"\\.\DISPLAY1", 0, 1920).find_in(&candidates), None);
+        let candidates = [(monitor("Notepad", 0, 1920), 1)];
+        assert_eq!(window("Notepad", "Notepad", "notepad.exe").find_in(&candidates), None);
+    }
+}
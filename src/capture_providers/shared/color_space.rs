@@ -0,0 +1,26 @@
+use crate::capture_providers::shared::PixelFormat;
+
+/// How the values of a frame map to colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    /// sRGB encoded, what SDR displays show.
+    #[default]
+    Srgb,
+    /// Linear BT.709 primaries in extended range, as composed for HDR displays. 1.0 is SDR white at 80 nits,
+    /// brighter colors go above it.
+    ScRgb,
+}
+
+impl ColorSpace {
+    /// The color space data of the format is in. Only floating point frames can hold HDR.
+    pub fn of_format(format: PixelFormat) -> Self {
+        match format {
+            PixelFormat::RGBA16F => ColorSpace::ScRgb,
+            _ => ColorSpace::Srgb,
+        }
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, ColorSpace::ScRgb)
+    }
+}
@@ -0,0 +1,12 @@
+/// Where frames are converted into the output format of a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConversionPolicy {
+    /// On the capture thread, so every frame arrives in the output format.
+    Eager,
+    /// Frames that would only have their channels reordered into RGBA8 keep the captured format, BGRA8 unless
+    /// the source is HDR. Consumers that need RGBA convert them with `Frame::ensure_rgba_in_place` or
+    /// `Frame::to_rgba`, the others save a pass over every frame. Other output formats are still converted on
+    /// the capture thread.
+    #[default]
+    Deferred,
+}
@@ -0,0 +1,145 @@
+use crate::capture_providers::shared::{Rect, Vector2};
+
+/// Which pixels a position or size is measured in. Capture items, frames, crop regions and dirty rects are in
+/// physical pixels, those of the display. DPI aware UI like iced lays out in logical pixels instead, which are
+/// physical pixels divided by the scale factor of the monitor, e.g. 1.5 at 150%.
+/// Values are converted where they cross from one to the other, e.g. in the region picker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CoordinateSpace {
+    Physical,
+    Logical,
+}
+
+impl CoordinateSpace {
+    /// What values in this space are multiplied by to get them in `target` at the given scale factor.
+    pub fn factor_to(self, target: Self, scale_factor: f32) -> f32 {
+        match (self, target) {
+            (Self::Physical, Self::Logical) => 1.0 / scale_factor,
+            (Self::Logical, Self::Physical) => scale_factor,
+            _ => 1.0,
+        }
+    }
+}
+
+impl Vector2<f32> {
+    pub fn convert(self, from: CoordinateSpace, to: CoordinateSpace, scale_factor: f32) -> Self {
+        self * from.factor_to(to, scale_factor)
+    }
+
+    /// Converts logical pixels into physical ones, rounded to the nearest pixel.
+    pub fn to_physical(self, scale_factor: f32) -> Vector2<i32> {
+        self.convert(CoordinateSpace::Logical, CoordinateSpace::Physical, scale_factor)
+            .map(|value| value.round() as i32)
+    }
+}
+
+impl Vector2<i32> {
+    pub fn to_logical(self, scale_factor: f32) -> Vector2<f32> {
+        self.cast::<f32>().convert(
+            CoordinateSpace::Physical,
+            CoordinateSpace::Logical,
+            scale_factor,
+        )
+    }
+}
+
+impl Rect<f32> {
+    pub fn convert(self, from: CoordinateSpace, to: CoordinateSpace, scale_factor: f32) -> Self {
+        Rect::new(
+            self.position.convert(from, to, scale_factor),
+            self.size.convert(from, to, scale_factor),
+        )
+    }
+
+    /// Converts logical pixels into physical ones. The edges are rounded rather than the size, so rects that
+    /// touch still touch afterwards.
+    pub fn to_physical(self, scale_factor: f32) -> Rect<i32> {
+        let position = self.position.to_physical(scale_factor);
+        let end = self.end().to_physical(scale_factor);
+        Rect::new(position, end - position)
+    }
+}
+
+impl Rect<i32> {
+    pub fn to_logical(self, scale_factor: f32) -> Rect<f32> {
+        Rect::new(self.position.to_logical(scale_factor), self.size.to_logical(scale_factor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 100%, 125%, 150% and 200%.
+    const SCALE_FACTORS: [f32; 4] = [1.0, 1.25, 1.5, 2.0];
+    const ODD_VALUES: [i32; 6] = [1, 3, 7, 101, 1079, 2561];
+
+    #[test]
+    fn logical_pixels_round_trip_through_physical_ones() {
+        for scale_factor in SCALE_FACTORS {
+            for value in ODD_VALUES {
+                let logical = Vector2::new(value as f32, -value as f32);
+                let round_trip = logical.to_physical(scale_factor).to_logical(scale_factor);
+                // Physical pixels are whole, so the value is only off by up to half of one, give or take the
+                // precision of the float.
+                let error = (round_trip - logical).map(f32::abs);
+                let max_error = 0.5 / scale_factor + 1e-3;
+                assert!(
+                    error.x <= max_error && error.y <= max_error,
+                    "{} at {}",
+                    value,
+                    scale_factor
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn logical_pixels_on_the_physical_grid_round_trip_exactly() {
+        for scale_factor in SCALE_FACTORS {
+            for value in ODD_VALUES {
+                let physical = Vector2::new(value, -value);
+                let logical = physical.to_logical(scale_factor);
+                assert_eq!(
+                    logical.to_physical(scale_factor),
+                    physical,
+                    "{} at {}",
+                    value,
+                    scale_factor
+                );
+                assert_eq!(
+                    logical.to_physical(scale_factor).to_logical(scale_factor),
+                    logical,
+                    "{} at {}",
+                    value,
+                    scale_factor
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rects_round_trip_and_keep_touching() {
+        for scale_factor in SCALE_FACTORS {
+            let left = Rect::new(Vector2::new(3, 7), Vector2::new(101, 1079));
+            let right = Rect::new(Vector2::new(left.end().x, 7), Vector2::new(2561, 1079));
+            let logical = (left.to_logical(scale_factor), right.to_logical(scale_factor));
+            assert_eq!(logical.0.to_physical(scale_factor), left, "at {}", scale_factor);
+            assert_eq!(logical.1.to_physical(scale_factor), right, "at {}", scale_factor);
+
+            // Edges that don't land on a physical pixel still meet.
+            let left = Rect::new(Vector2::new(0.3, 0.0), Vector2::new(100.7, 1.0));
+            let right = Rect::new(Vector2::new(left.end().x, 0.0), Vector2::new(1.3, 1.0));
+            let (left, right) = (left.to_physical(scale_factor), right.to_physical(scale_factor));
+            assert_eq!(left.end().x, right.position.x, "at {}", scale_factor);
+        }
+    }
+
+    #[test]
+    fn converting_within_a_space_changes_nothing() {
+        let value = Vector2::new(101.5, 3.25);
+        for space in [CoordinateSpace::Physical, CoordinateSpace::Logical] {
+            assert_eq!(value.convert(space, space, 1.5), value);
+        }
+    }
+}
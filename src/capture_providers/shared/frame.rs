@@ -1,39 +1,401 @@
+use std::{
+    borrow::Cow,
+    ops::Range,
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+
 use bytes::Bytes;
 
 use crate::{
-    capture_providers::shared::{PixelFormat, Rect, Vector2},
-    utils::image_utils::ensure_image_rgba,
+    capture_providers::shared::{ColorSpace, PixelFormat, Rect, Vector2},
+    utils::image_utils::{
+        EncodeError, ImageFileFormat, bgra_to_rgba_into, convert_image, encode_frame,
+        encode_rgba_jpeg, ensure_image_rgba, pack_rows,
+    },
 };
 
-#[allow(dead_code)]
+#[derive(Debug, thiserror::Error)]
+pub enum FrameError {
+    #[error("Frame data is {actual} bytes, but its size and stride need {expected}")]
+    TruncatedData { expected: usize, actual: usize },
+    #[error("Stride of {stride} bytes is shorter than a row of {row_bytes} bytes")]
+    InvalidStride { stride: usize, row_bytes: usize },
+    #[error("Planar {0:?} frames can't be converted to RGBA")]
+    UnsupportedConversion(PixelFormat),
+    #[error("Base is {actual} bytes, but the frame the delta applies to is {expected}")]
+    BaseMismatch { expected: usize, actual: usize },
+}
+
+/// When a frame was captured, as assigned by the provider.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameTiming {
+    /// Raw system relative time in 100ns units.
+    pub timestamp: i64,
+    pub sequence: u64,
+    pub capture_instant: Instant,
+}
+
 #[derive(Debug, Clone)]
 pub struct Frame {
     pub data: Bytes,
     pub format: PixelFormat,
+    /// In physical pixels, as are the dirty rects, see `CoordinateSpace`.
     pub size: Vector2<i32>,
+    /// Number of bytes between the start of two consecutive rows. May include padding.
+    pub stride: usize,
+    /// Raw system relative time in 100ns units. Only meaningful relative to other frames.
     pub timestamp: i64,
+    /// Monotonically increasing per stream. Gaps mean frames were skipped or dropped.
+    pub sequence: u64,
+    /// The timestamp translated into this process' clock.
+    pub capture_instant: Instant,
     pub dirty_rects: Vec<Rect<i32>>,
+    /// Follows the format: HDR captures stay scRGB only if the output format is RGBA16F, otherwise they are
+    /// tone mapped to sRGB.
+    pub color_space: ColorSpace,
+    /// Set on delta frames: `data` then only holds these rows, starting with the first, and the rest of the frame
+    /// is the same as in the previous one. See `apply_delta`.
+    pub delta_rows: Option<Range<i32>>,
 }
 
 impl Frame {
+    /// Converts the data to RGBA right away. Use `new` and `ensure_rgba_in_place` to convert only when needed.
     pub fn new_ensure_rgba(
         mut data: Vec<u8>,
         mut format: PixelFormat,
         size: Vector2<i32>,
-        timestamp: i64,
+        stride: usize,
+        timing: FrameTiming,
         dirty_rects: Vec<Rect<i32>>,
     ) -> Self {
-        ensure_image_rgba(&mut data[..], &mut format);
-        Self::new(data.into(), format, size, timestamp, dirty_rects)
+        let row_bytes = format.row_bytes(size.x.max(0) as usize);
+        let mut stride = stride;
+        ensure_image_rgba(&mut data, &mut format, row_bytes, &mut stride);
+        Self::new(data.into(), format, size, stride, timing, dirty_rects)
     }
 
-    fn new(
+    /// Converts packed source data into `output_format`, which may be planar.
+    pub fn new_converted(
+        data: Vec<u8>,
+        source_format: PixelFormat,
+        output_format: PixelFormat,
+        size: Vector2<i32>,
+        stride: usize,
+        timing: FrameTiming,
+        dirty_rects: Vec<Rect<i32>>,
+    ) -> Self {
+        let (data, format, stride) =
+            convert_image(data, source_format, output_format, size, stride);
+        Self::new(data.into(), format, size, stride, timing, dirty_rects)
+    }
+
+    /// Keeps the data in the format it is in.
+    pub fn new(
         data: Bytes,
         format: PixelFormat,
         size: Vector2<i32>,
-        timestamp: i64,
+        stride: usize,
+        timing: FrameTiming,
         dirty_rects: Vec<Rect<i32>>,
     ) -> Self {
-        Frame { data, format, size, timestamp, dirty_rects }
+        Frame {
+            data,
+            format,
+            size,
+            stride,
+            timestamp: timing.timestamp,
+            sequence: timing.sequence,
+            capture_instant: timing.capture_instant,
+            dirty_rects,
+            color_space: ColorSpace::of_format(format),
+            delta_rows: None,
+        }
+    }
+
+    pub fn is_delta(&self) -> bool {
+        self.delta_rows.is_some()
+    }
+
+    /// Patches the rows of a delta frame into `base`, the tightly packed data of the frame before it. Full frames
+    /// replace `base` instead, so every frame of a stream with delta frames can be applied in order to keep the
+    /// whole image.
+    pub fn apply_delta(base: &mut Vec<u8>, delta: &Frame) -> Result<(), FrameError> {
+        let Some(rows) = &delta.delta_rows else {
+            delta.check_layout()?;
+            base.clear();
+            base.extend_from_slice(&delta.to_tightly_packed());
+            return Ok(());
+        };
+
+        let row_bytes = delta.row_bytes();
+        let height = delta.size.y.max(0) as usize;
+        if base.len() != row_bytes * height {
+            return Err(FrameError::BaseMismatch {
+                expected: row_bytes * height,
+                actual: base.len(),
+            });
+        }
+        if delta.stride < row_bytes {
+            return Err(FrameError::InvalidStride { stride: delta.stride, row_bytes });
+        }
+        let start = (rows.start.max(0) as usize).min(height);
+        let count = (rows.end.max(0) as usize).min(height).saturating_sub(start);
+        let expected = match count {
+            0 => 0,
+            count => delta.stride * (count - 1) + row_bytes,
+        };
+        if delta.data.len() < expected {
+            return Err(FrameError::TruncatedData { expected, actual: delta.data.len() });
+        }
+
+        let base_rows = base[start * row_bytes..].chunks_exact_mut(row_bytes);
+        for (base_row, row) in base_rows.zip(delta.data.chunks(delta.stride)).take(count) {
+            base_row.copy_from_slice(&row[..row_bytes]);
+        }
+        Ok(())
+    }
+
+    /// Converts the frame to RGBA8, see `ensure_rgba_in_place`.
+    pub fn to_rgba(mut self) -> Result<Frame, FrameError> {
+        self.ensure_rgba_in_place()?;
+        Ok(self)
+    }
+
+    /// Converts the frame to RGBA8, keeping its timing and dirty rects. Does nothing if it already is.
+    /// The data is only converted in place if this frame is its sole owner, shared or pooled data is copied.
+    /// Fails rather than converting part of the frame if the data is shorter than its size and stride require.
+    pub fn ensure_rgba_in_place(&mut self) -> Result<(), FrameError> {
+        if self.format == PixelFormat::RGBA8 {
+            return Ok(());
+        }
+        if self.format.is_planar() {
+            return Err(FrameError::UnsupportedConversion(self.format));
+        }
+        self.check_layout()?;
+
+        let mut data = match std::mem::take(&mut self.data).try_into_mut() {
+            Ok(data) => Vec::from(data),
+            // Swizzled while copying, rather than copied and then swizzled. Padding is swizzled along, which
+            // doesn't matter.
+            Err(data) if self.format == PixelFormat::BGRA8 => {
+                let mut converted = Vec::new();
+                bgra_to_rgba_into(&data, &mut converted);
+                self.data = converted.into();
+                self.format = PixelFormat::RGBA8;
+                self.color_space = ColorSpace::of_format(self.format);
+                return Ok(());
+            }
+            Err(data) => data.to_vec(),
+        };
+        let row_bytes = self.row_bytes();
+        ensure_image_rgba(&mut data, &mut self.format, row_bytes, &mut self.stride);
+        self.data = data.into();
+        // HDR frames are tone mapped on the way.
+        self.color_space = ColorSpace::of_format(self.format);
+        Ok(())
+    }
+
+    /// Whether the data holds every row the size and stride describe, with the last row unpadded at least.
+    fn check_layout(&self) -> Result<(), FrameError> {
+        let row_bytes = self.row_bytes();
+        if self.stride < row_bytes {
+            return Err(FrameError::InvalidStride { stride: self.stride, row_bytes });
+        }
+        let rows = self.size.y.max(0) as usize;
+        let expected = match rows {
+            0 => 0,
+            rows => self.stride * (rows - 1) + row_bytes,
+        };
+        if self.data.len() < expected {
+            return Err(FrameError::TruncatedData { expected, actual: self.data.len() });
+        }
+        Ok(())
+    }
+
+    /// The raw timestamp as a duration. Its reference point is unspecified, so only use it for differences.
+    pub fn relative_time(&self) -> Duration {
+        // Timestamps are in 100ns units.
+        Duration::from_nanos((self.timestamp.max(0) as u64).saturating_mul(100))
+    }
+
+    /// Time between the capture of `other` and this frame. Zero if `other` was captured later.
+    pub fn since(&self, other: &Frame) -> Duration {
+        self.capture_instant.saturating_duration_since(other.capture_instant)
+    }
+
+    /// When the frame was captured, by the system clock. Worked out from `capture_instant`, so adjustments of the
+    /// system clock since the capture shift it along.
+    pub fn wall_clock(&self) -> SystemTime {
+        let now = SystemTime::now();
+        now.checked_sub(self.capture_instant.elapsed()).unwrap_or(now)
+    }
+
+    /// Number of bytes of actual pixel data in each row.
+    pub fn row_bytes(&self) -> usize {
+        self.format.row_bytes(self.size.x.max(0) as usize)
+    }
+
+    pub fn is_tightly_packed(&self) -> bool {
+        self.stride == self.row_bytes()
+    }
+
+    /// Returns the pixel data without any row padding, only copying if the frame is strided.
+    pub fn to_tightly_packed(&self) -> Cow<'_, [u8]> {
+        if self.is_tightly_packed() {
+            return Cow::Borrowed(&self.data[..]);
+        }
+
+        let rows = self.size.y.max(0) as usize;
+        Cow::Owned(pack_rows(&self.data, self.row_bytes(), self.stride, rows))
+    }
+
+    /// Returns the pixel data as tightly packed RGBA, only copying if it is strided or in another format.
+    /// Planar frames can't be converted and are returned as they are.
+    pub fn to_tightly_packed_rgba(&self) -> Cow<'_, [u8]> {
+        let packed = self.to_tightly_packed();
+        if self.format == PixelFormat::RGBA8 {
+            return packed;
+        }
+
+        let mut data = packed.into_owned();
+        let mut format = self.format;
+        let row_bytes = self.row_bytes();
+        let mut stride = row_bytes;
+        ensure_image_rgba(&mut data, &mut format, row_bytes, &mut stride);
+        Cow::Owned(data)
+    }
+
+    /// Returns the pixel data without any row padding as cheaply clonable bytes.
+    pub fn into_tightly_packed(self) -> Bytes {
+        if self.is_tightly_packed() {
+            return self.data;
+        }
+        self.to_tightly_packed().into_owned().into()
+    }
+
+    /// Same as `to_tightly_packed_rgba`, without copying frames that already are.
+    pub fn into_tightly_packed_rgba(self) -> Bytes {
+        if self.format == PixelFormat::RGBA8 {
+            return self.into_tightly_packed();
+        }
+        self.to_tightly_packed_rgba().into_owned().into()
+    }
+
+    /// Encodes the frame as PNG and writes it to `path`. Blocks, so async code should use `spawn_blocking`.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), EncodeError> {
+        std::fs::write(path, encode_frame(self, ImageFileFormat::Png)?)?;
+        Ok(())
+    }
+
+    /// Encodes the frame as JPEG of the given quality from 1 to 100 and writes it to `path`. Blocks like
+    /// `save_png`.
+    pub fn save_jpeg(&self, path: impl AsRef<Path>, quality: u8) -> Result<(), EncodeError> {
+        let rgba = self.to_tightly_packed_rgba();
+        std::fs::write(path, encode_rgba_jpeg(&rgba, self.size, quality)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Every pixel differs, so rows or channels in the wrong place show in the decoded image.
+    fn rgba_pixel(x: usize, y: usize) -> [u8; 4] {
+        [(x * 40) as u8, (y * 40) as u8, (x * 7 + y * 13) as u8, 255]
+    }
+
+    /// A frame with rows padded past their pixels, whose padding holds garbage.
+    fn frame(format: PixelFormat, width: i32, height: i32) -> Frame {
+        let row_bytes = format.row_bytes(width.max(0) as usize);
+        let stride = row_bytes + 12;
+        let mut data = vec![0xEE; stride * height.max(0) as usize];
+        for y in 0..height.max(0) as usize {
+            for x in 0..width.max(0) as usize {
+                let [r, g, b, a] = rgba_pixel(x, y);
+                let pixel = match format {
+                    PixelFormat::BGRA8 => [b, g, r, a],
+                    _ => [r, g, b, a],
+                };
+                data[y * stride + x * 4..][..4].copy_from_slice(&pixel);
+            }
+        }
+        let timing = FrameTiming { timestamp: 0, sequence: 0, capture_instant: Instant::now() };
+        Frame::new(data.into(), format, Vector2::new(width, height), stride, timing, Vec::new())
+    }
+
+    /// A path in the temp folder, which is removed again on drop.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!("loki-{}-{}", std::process::id(), name)))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn png_snapshots_keep_every_pixel() {
+        for format in [PixelFormat::RGBA8, PixelFormat::BGRA8] {
+            // Odd widths leave rows that aren't a multiple of 4 or 8 bytes apart once packed.
+            for (width, height) in [(1, 1), (7, 3), (33, 2)] {
+                let file = TempFile::new(&format!("{:?}-{}x{}.png", format, width, height));
+                frame(format, width, height).save_png(&file.0).unwrap();
+
+                let image = image::open(&file.0).unwrap().into_rgba8();
+                assert_eq!(image.dimensions(), (width as u32, height as u32));
+                for (x, y, pixel) in image.enumerate_pixels() {
+                    assert_eq!(
+                        pixel.0,
+                        rgba_pixel(x as usize, y as usize),
+                        "{:?} at {}, {}",
+                        format,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn jpeg_snapshots_have_the_size_of_the_frame() {
+        for format in [PixelFormat::RGBA8, PixelFormat::BGRA8] {
+            let file = TempFile::new(&format!("{:?}.jpg", format));
+            frame(format, 17, 9).save_jpeg(&file.0, 90).unwrap();
+            let image = image::open(&file.0).unwrap();
+            assert_eq!((image.width(), image.height()), (17, 9));
+        }
+    }
+
+    #[test]
+    fn empty_frames_are_not_saved() {
+        for (width, height) in [(0, 0), (0, 4), (4, 0), (-1, 4)] {
+            let frame = frame(PixelFormat::RGBA8, width, height);
+            let file = TempFile::new(&format!("empty-{}x{}.png", width, height));
+            let result = frame.save_png(&file.0);
+            assert!(
+                matches!(result, Err(EncodeError::InvalidDimensions(..))),
+                "{}x{}",
+                width,
+                height
+            );
+            let result = frame.save_jpeg(&file.0, 90);
+            assert!(
+                matches!(result, Err(EncodeError::InvalidDimensions(..))),
+                "{}x{}",
+                width,
+                height
+            );
+            assert!(!file.0.exists());
+        }
     }
 }
@@ -0,0 +1,37 @@
+use std::time::Instant;
+
+/// A point a frame passes on its way from WGC to the screen, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    /// WGC handed the frame to the FrameArrived handler.
+    Arrived,
+    /// The frame was read back into CPU memory and converted to the output format.
+    ReadBack,
+    /// The frame was queued on the channel of a stream.
+    Queued,
+    /// The consumer took the frame off the stream.
+    Received,
+    /// A viewer made an image of the frame, which the renderer uploads when it first draws it.
+    Uploaded,
+    /// The viewer drew the image of the frame for the first time. The renderer presents it along with the rest
+    /// of the window right after.
+    Drawn,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::Arrived,
+        Stage::ReadBack,
+        Stage::Queued,
+        Stage::Received,
+        Stage::Uploaded,
+        Stage::Drawn,
+    ];
+}
+
+/// Gets told when frames pass each `Stage`, to find out where the time between capture and screen goes.
+/// Frames are identified by their sequence number. Called from the capture thread as well as the consumer's, so
+/// implementations should return quickly and must not block. Records nothing unless overridden.
+pub trait FrameTracer: Send + Sync + std::fmt::Debug {
+    fn record(&self, _sequence: u64, _stage: Stage, _at: Instant) {}
+}
@@ -0,0 +1,43 @@
+use windows::Win32::Foundation::{CloseHandle, HANDLE};
+
+use crate::capture_providers::shared::{PixelFormat, Vector2};
+
+/// A captured frame that stays on the GPU.
+///
+/// Owns an NT handle to a shared `ID3D11Texture2D` with a keyed mutex, which can be opened on any D3D11 device
+/// through `ID3D11Device1::OpenSharedResource1`. Acquire the keyed mutex with [`GpuFrame::CONSUMER_KEY`] before
+/// reading and release it with [`GpuFrame::PRODUCER_KEY`] afterwards so the texture can be reused.
+/// Textures are recycled, so a frame that is not opened promptly may contain a newer frame.
+#[derive(Debug)]
+pub struct GpuFrame {
+    handle: HANDLE,
+    pub format: PixelFormat,
+    /// Size of the captured content in physical pixels. The texture itself may be larger.
+    pub size: Vector2<i32>,
+    pub timestamp: i64,
+}
+
+impl GpuFrame {
+    pub const PRODUCER_KEY: u64 = 0;
+    pub const CONSUMER_KEY: u64 = 1;
+
+    /// Takes ownership of the handle, which is closed when the frame is dropped.
+    pub fn new(handle: HANDLE, format: PixelFormat, size: Vector2<i32>, timestamp: i64) -> Self {
+        Self { handle, format, size, timestamp }
+    }
+
+    pub fn shared_handle(&self) -> HANDLE {
+        self.handle
+    }
+}
+
+impl Drop for GpuFrame {
+    fn drop(&mut self) {
+        if let Err(err) = unsafe { CloseHandle(self.handle) } {
+            tracing::warn!("Failed to close shared texture handle: {}", err);
+        }
+    }
+}
+
+// The frame owns its NT handle, which is valid from any thread.
+unsafe impl Send for GpuFrame {}
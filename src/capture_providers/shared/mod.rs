@@ -1,11 +1,37 @@
+mod capture_event;
 mod capture_framerate;
+mod capture_item_info;
+mod capture_stats;
+mod capture_target;
+mod color_space;
+mod conversion_policy;
+mod coordinate_space;
 mod frame;
+mod frame_tracer;
+#[cfg(target_os = "windows")]
+mod gpu_frame;
 mod pixel_format;
 mod rect;
+mod scale_mode;
+mod stream_id;
+mod stream_options;
 mod vector2;
 
+pub use capture_event::*;
 pub use capture_framerate::*;
+pub use capture_item_info::*;
+pub use capture_stats::*;
+pub use capture_target::*;
+pub use color_space::*;
+pub use conversion_policy::*;
+pub use coordinate_space::*;
 pub use frame::*;
+pub use frame_tracer::*;
+#[cfg(target_os = "windows")]
+pub use gpu_frame::*;
 pub use pixel_format::*;
 pub use rect::*;
+pub use scale_mode::*;
+pub use stream_id::*;
+pub use stream_options::*;
 pub use vector2::*;
@@ -1,9 +1,66 @@
-use windows::Graphics::DirectX::DirectXPixelFormat;
+#[cfg(target_os = "windows")]
+use windows::{
+    Graphics::DirectX::DirectXPixelFormat,
+    Win32::Graphics::Dxgi::Common::{
+        DXGI_FORMAT, DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_NV12, DXGI_FORMAT_R8_UNORM,
+        DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_R16G16B16A16_FLOAT,
+    },
+};
 
-#[derive(Debug, Clone)]
+/// A format that has no counterpart on the other side of a conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum PixelFormatError {
+    #[cfg(target_os = "windows")]
+    #[error("DirectX pixel format {0:?} is not supported")]
+    UnsupportedDirectXFormat(DirectXPixelFormat),
+    #[cfg(target_os = "windows")]
+    #[error("DXGI format {0:?} is not supported")]
+    UnsupportedDxgiFormat(DXGI_FORMAT),
+    #[error("{0:?} has no DirectX equivalent")]
+    NoDirectXEquivalent(PixelFormat),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PixelFormat {
     RGBA8,
     BGRA8,
+    /// 4:2:0 with a full resolution Y plane followed by an interleaved UV plane.
+    NV12,
+    /// 4:2:0 with a full resolution Y plane followed by separate U and V planes.
+    I420,
+    /// Full range luma only, a quarter of the size of RGBA.
+    Gray8,
+    /// Half precision floats per channel, in the scRGB color space of HDR captures.
+    RGBA16F,
+}
+
+impl PixelFormat {
+    pub fn is_planar(&self) -> bool {
+        matches!(self, PixelFormat::NV12 | PixelFormat::I420)
+    }
+
+    /// Size in bytes of every plane of a tightly packed image. Chroma planes of odd sizes are rounded up.
+    pub fn plane_sizes(&self, width: usize, height: usize) -> Vec<usize> {
+        let luma = width * height;
+        let chroma = width.div_ceil(2) * height.div_ceil(2);
+        match self {
+            PixelFormat::RGBA8 | PixelFormat::BGRA8 => vec![luma * 4],
+            PixelFormat::NV12 => vec![luma, chroma * 2],
+            PixelFormat::I420 => vec![luma, chroma, chroma],
+            PixelFormat::Gray8 => vec![luma],
+            PixelFormat::RGBA16F => vec![luma * 8],
+        }
+    }
+
+    /// Size in bytes of a tightly packed row. For planar formats this is a row of the first plane.
+    pub fn row_bytes(&self, width: usize) -> usize {
+        width * self.bytes_per_pixel() as usize
+    }
+
+    /// Total size in bytes of a tightly packed image.
+    pub fn frame_bytes(&self, width: usize, height: usize) -> usize {
+        self.plane_sizes(width, height).iter().sum()
+    }
 }
 
 pub trait BytesPerPixel {
@@ -11,23 +68,146 @@ pub trait BytesPerPixel {
 }
 
 impl BytesPerPixel for PixelFormat {
+    /// For planar formats this is the size of a sample in the first plane, see `PixelFormat::plane_sizes`.
     fn bytes_per_pixel(&self) -> u32 {
         match self {
             PixelFormat::RGBA8 => 4,
             PixelFormat::BGRA8 => 4,
+            PixelFormat::NV12 => 1,
+            PixelFormat::I420 => 1,
+            PixelFormat::Gray8 => 1,
+            PixelFormat::RGBA16F => 8,
         }
     }
 }
 
+#[cfg(target_os = "windows")]
 pub trait ToDirectXPixelFormat {
     fn to_directx_pixel_format(&self) -> DirectXPixelFormat;
 }
 
+#[cfg(target_os = "windows")]
 impl ToDirectXPixelFormat for PixelFormat {
+    /// `Unknown` for formats without a DirectX equivalent, see `TryFrom<PixelFormat>` for the checked version.
     fn to_directx_pixel_format(&self) -> DirectXPixelFormat {
-        match self {
-            PixelFormat::RGBA8 => DirectXPixelFormat::R8G8B8A8UIntNormalized,
-            PixelFormat::BGRA8 => DirectXPixelFormat::B8G8R8A8UIntNormalized,
+        DirectXPixelFormat::try_from(*self).unwrap_or(DirectXPixelFormat::Unknown)
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl TryFrom<PixelFormat> for DirectXPixelFormat {
+    type Error = PixelFormatError;
+
+    fn try_from(format: PixelFormat) -> Result<Self, Self::Error> {
+        match format {
+            PixelFormat::RGBA8 => Ok(DirectXPixelFormat::R8G8B8A8UIntNormalized),
+            PixelFormat::BGRA8 => Ok(DirectXPixelFormat::B8G8R8A8UIntNormalized),
+            PixelFormat::NV12 => Ok(DirectXPixelFormat::NV12),
+            // DirectX has no three-plane format.
+            PixelFormat::I420 => Err(PixelFormatError::NoDirectXEquivalent(format)),
+            PixelFormat::Gray8 => Ok(DirectXPixelFormat::R8UIntNormalized),
+            PixelFormat::RGBA16F => Ok(DirectXPixelFormat::R16G16B16A16Float),
         }
     }
 }
+
+#[cfg(target_os = "windows")]
+impl TryFrom<DirectXPixelFormat> for PixelFormat {
+    type Error = PixelFormatError;
+
+    fn try_from(format: DirectXPixelFormat) -> Result<Self, Self::Error> {
+        match format {
+            DirectXPixelFormat::R8G8B8A8UIntNormalized => Ok(PixelFormat::RGBA8),
+            DirectXPixelFormat::B8G8R8A8UIntNormalized => Ok(PixelFormat::BGRA8),
+            DirectXPixelFormat::NV12 => Ok(PixelFormat::NV12),
+            DirectXPixelFormat::R8UIntNormalized => Ok(PixelFormat::Gray8),
+            DirectXPixelFormat::R16G16B16A16Float => Ok(PixelFormat::RGBA16F),
+            _ => Err(PixelFormatError::UnsupportedDirectXFormat(format)),
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl TryFrom<PixelFormat> for DXGI_FORMAT {
+    type Error = PixelFormatError;
+
+    /// DirectXPixelFormat values are the DXGI_FORMAT values, so this goes through the same table.
+    fn try_from(format: PixelFormat) -> Result<Self, Self::Error> {
+        Ok(DXGI_FORMAT(DirectXPixelFormat::try_from(format)?.0))
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl TryFrom<DXGI_FORMAT> for PixelFormat {
+    type Error = PixelFormatError;
+
+    fn try_from(format: DXGI_FORMAT) -> Result<Self, Self::Error> {
+        match format {
+            DXGI_FORMAT_R8G8B8A8_UNORM => Ok(PixelFormat::RGBA8),
+            DXGI_FORMAT_B8G8R8A8_UNORM => Ok(PixelFormat::BGRA8),
+            DXGI_FORMAT_NV12 => Ok(PixelFormat::NV12),
+            DXGI_FORMAT_R8_UNORM => Ok(PixelFormat::Gray8),
+            DXGI_FORMAT_R16G16B16A16_FLOAT => Ok(PixelFormat::RGBA16F),
+            _ => Err(PixelFormatError::UnsupportedDxgiFormat(format)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn planar_formats_have_quarter_size_chroma_planes() {
+        assert_eq!(PixelFormat::NV12.plane_sizes(4, 2), [8, 4]);
+        assert_eq!(PixelFormat::I420.plane_sizes(4, 2), [8, 2, 2]);
+        // Odd sizes round the chroma planes up.
+        assert_eq!(PixelFormat::NV12.plane_sizes(3, 3), [9, 8]);
+        assert_eq!(PixelFormat::I420.plane_sizes(3, 3), [9, 4, 4]);
+        assert_eq!(PixelFormat::I420.frame_bytes(1920, 1080), 1920 * 1080 * 3 / 2);
+        assert_eq!(PixelFormat::NV12.row_bytes(1920), 1920);
+    }
+
+    #[test]
+    fn strides_follow_the_bytes_per_pixel() {
+        assert_eq!(PixelFormat::RGBA8.row_bytes(1920), 7680);
+        assert_eq!(PixelFormat::BGRA8.frame_bytes(1920, 1080), 1920 * 1080 * 4);
+        assert_eq!(PixelFormat::Gray8.row_bytes(1920), 1920);
+        assert_eq!(PixelFormat::RGBA16F.row_bytes(1920), 15_360);
+        assert!(PixelFormat::NV12.is_planar() && PixelFormat::I420.is_planar());
+        assert!(!PixelFormat::RGBA8.is_planar());
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn formats_round_trip_through_directx_and_dxgi() {
+        for format in [
+            PixelFormat::RGBA8,
+            PixelFormat::BGRA8,
+            PixelFormat::NV12,
+            PixelFormat::Gray8,
+            PixelFormat::RGBA16F,
+        ] {
+            let directx = DirectXPixelFormat::try_from(format).unwrap();
+            assert_eq!(PixelFormat::try_from(directx), Ok(format));
+            let dxgi = DXGI_FORMAT::try_from(format).unwrap();
+            assert_eq!(PixelFormat::try_from(dxgi), Ok(format));
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn formats_without_a_counterpart_are_errors() {
+        assert_eq!(
+            DirectXPixelFormat::try_from(PixelFormat::I420),
+            Err(PixelFormatError::NoDirectXEquivalent(PixelFormat::I420))
+        );
+        assert_eq!(PixelFormat::I420.to_directx_pixel_format(), DirectXPixelFormat::Unknown);
+        assert_eq!(
+            PixelFormat::try_from(DirectXPixelFormat::R10G10B10A2UIntNormalized),
+            Err(PixelFormatError::UnsupportedDirectXFormat(
+                DirectXPixelFormat::R10G10B10A2UIntNormalized
+            ))
+        );
+    }
+}
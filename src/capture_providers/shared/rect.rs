@@ -1,11 +1,15 @@
+use std::ops::Add;
+
 use crate::capture_providers::shared::Vector2;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rect<N = f32> {
     pub position: Vector2<N>,
     pub size: Vector2<N>,
 }
 
+#[cfg(target_os = "windows")]
 impl From<windows::Foundation::Rect> for Rect<f32> {
     fn from(rect: windows::Foundation::Rect) -> Self {
         Rect {
@@ -15,6 +19,7 @@ impl From<windows::Foundation::Rect> for Rect<f32> {
     }
 }
 
+#[cfg(target_os = "windows")]
 impl From<Rect<f32>> for windows::Foundation::Rect {
     fn from(rect: Rect<f32>) -> Self {
         windows::Foundation::Rect {
@@ -26,6 +31,7 @@ impl From<Rect<f32>> for windows::Foundation::Rect {
     }
 }
 
+#[cfg(target_os = "windows")]
 impl From<windows::Graphics::RectInt32> for Rect<i32> {
     fn from(rect: windows::Graphics::RectInt32) -> Self {
         Rect {
@@ -35,6 +41,7 @@ impl From<windows::Graphics::RectInt32> for Rect<i32> {
     }
 }
 
+#[cfg(target_os = "windows")]
 impl From<Rect<i32>> for windows::Graphics::RectInt32 {
     fn from(rect: Rect<i32>) -> Self {
         windows::Graphics::RectInt32 {
@@ -45,3 +52,120 @@ impl From<Rect<i32>> for windows::Graphics::RectInt32 {
         }
     }
 }
+
+impl<N> Rect<N> {
+    pub fn new(position: Vector2<N>, size: Vector2<N>) -> Self {
+        Self { position, size }
+    }
+}
+
+impl<N: Copy + Add<Output = N>> Rect<N> {
+    /// The point just past the bottom right corner.
+    pub fn end(&self) -> Vector2<N> {
+        self.position + self.size
+    }
+}
+
+impl Rect<i32> {
+    /// Number of pixels covered, zero for rects with a negative size.
+    pub fn area(&self) -> u64 {
+        self.size.x.max(0) as u64 * self.size.y.max(0) as u64
+    }
+
+    /// Whether the rect is empty, i.e. covers no pixels.
+    pub fn is_empty(&self) -> bool {
+        self.size.x <= 0 || self.size.y <= 0
+    }
+
+    /// Whether the pixel at `point` lies within the rect.
+    pub fn contains(&self, point: Vector2<i32>) -> bool {
+        let end = self.end();
+        (self.position.x..end.x).contains(&point.x) && (self.position.y..end.y).contains(&point.y)
+    }
+
+    /// The part of this rect that lies within `other`, or `None` if they don't overlap.
+    pub fn intersect(&self, other: &Rect<i32>) -> Option<Rect<i32>> {
+        let (end, other_end) = (self.end(), other.end());
+        let position = Vector2::new(
+            self.position.x.max(other.position.x),
+            self.position.y.max(other.position.y),
+        );
+        let end = Vector2::new(end.x.min(other_end.x), end.y.min(other_end.y));
+        if end.x <= position.x || end.y <= position.y {
+            return None;
+        }
+        Some(Rect::new(position, end - position))
+    }
+
+    /// The smallest rect covering both. Empty rects don't count, so they don't stretch the result towards
+    /// their position.
+    pub fn union(&self, other: &Rect<i32>) -> Rect<i32> {
+        if other.is_empty() {
+            return *self;
+        }
+        if self.is_empty() {
+            return *other;
+        }
+        let (end, other_end) = (self.end(), other.end());
+        let position = Vector2::new(
+            self.position.x.min(other.position.x),
+            self.position.y.min(other.position.y),
+        );
+        let end = Vector2::new(end.x.max(other_end.x), end.y.max(other_end.y));
+        Rect::new(position, end - position)
+    }
+
+    /// The part of this rect within an image of size `bounds`, or `None` if it lies entirely outside of it.
+    pub fn clamp_to(&self, bounds: Vector2<i32>) -> Option<Rect<i32>> {
+        self.intersect(&Rect::new(Vector2::new(0, 0), bounds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> Rect<i32> {
+        Rect::new(Vector2::new(x, y), Vector2::new(width, height))
+    }
+
+    #[test]
+    fn area_and_contains_cover_the_pixels_within() {
+        let area = rect(10, 20, 30, 40);
+        assert_eq!(area.end(), Vector2::new(40, 60));
+        assert_eq!(area.area(), 1200);
+        assert_eq!(rect(0, 0, -5, 10).area(), 0);
+        assert!(rect(0, 0, 0, 10).is_empty());
+
+        assert!(area.contains(Vector2::new(10, 20)));
+        assert!(area.contains(Vector2::new(39, 59)));
+        // The end is just past the rect.
+        assert!(!area.contains(Vector2::new(40, 30)));
+        assert!(!area.contains(Vector2::new(9, 30)));
+    }
+
+    #[test]
+    fn intersect_keeps_the_overlap() {
+        assert_eq!(rect(0, 0, 10, 10).intersect(&rect(5, 5, 10, 10)), Some(rect(5, 5, 5, 5)));
+        assert_eq!(rect(0, 0, 10, 10).intersect(&rect(2, 3, 4, 5)), Some(rect(2, 3, 4, 5)));
+        // Rects that only touch don't overlap.
+        assert_eq!(rect(0, 0, 10, 10).intersect(&rect(10, 0, 10, 10)), None);
+        assert_eq!(rect(0, 0, 10, 10).intersect(&rect(20, 20, 5, 5)), None);
+    }
+
+    #[test]
+    fn union_covers_both_and_ignores_empty_rects() {
+        assert_eq!(rect(0, 0, 10, 10).union(&rect(20, 5, 10, 10)), rect(0, 0, 30, 15));
+        assert_eq!(rect(-5, -5, 2, 2).union(&rect(0, 0, 1, 1)), rect(-5, -5, 6, 6));
+        assert_eq!(rect(5, 5, 10, 10).union(&rect(100, 100, 0, 0)), rect(5, 5, 10, 10));
+        assert_eq!(rect(100, 100, 0, 0).union(&rect(5, 5, 10, 10)), rect(5, 5, 10, 10));
+    }
+
+    #[test]
+    fn clamp_to_crops_to_the_image() {
+        let bounds = Vector2::new(1920, 1080);
+        assert_eq!(rect(-10, -10, 110, 60).clamp_to(bounds), Some(rect(0, 0, 100, 50)));
+        assert_eq!(rect(1900, 1000, 100, 100).clamp_to(bounds), Some(rect(1900, 1000, 20, 80)));
+        assert_eq!(rect(2000, 0, 10, 10).clamp_to(bounds), None);
+    }
+}
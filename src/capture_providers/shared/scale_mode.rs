@@ -0,0 +1,38 @@
+use crate::capture_providers::shared::Vector2;
+
+/// How frames are scaled on the GPU before they are read back.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ScaleMode {
+    /// Frames keep the size of the captured content.
+    #[default]
+    Native,
+    /// Both dimensions are multiplied by the factor, which is clamped to at most 1.
+    Fraction(f32),
+    /// Frames are shrunk to fit within the size, preserving the aspect ratio. Smaller frames are left as they are.
+    FitWithin(Vector2<u32>),
+}
+
+impl ScaleMode {
+    /// Size of the scaled frame for content of the given size.
+    /// Scaled dimensions are rounded down to even numbers, as video encoders commonly require them.
+    pub fn target_size(&self, source: Vector2<i32>) -> Vector2<i32> {
+        let factor = match *self {
+            ScaleMode::Native => return source,
+            ScaleMode::Fraction(factor) => factor as f64,
+            ScaleMode::FitWithin(max) => {
+                let factor_x = max.x as f64 / source.x.max(1) as f64;
+                let factor_y = max.y as f64 / source.y.max(1) as f64;
+                factor_x.min(factor_y)
+            }
+        };
+        if factor.is_nan() || factor >= 1.0 {
+            return source;
+        }
+
+        let scale = |dimension: i32| {
+            let scaled = (dimension.max(0) as f64 * factor.max(0.0)) as i32;
+            (scaled & !1).max(2)
+        };
+        Vector2::new(scale(source.x), scale(source.y))
+    }
+}
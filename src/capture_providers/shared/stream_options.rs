@@ -0,0 +1,33 @@
+use crate::capture_providers::shared::CaptureFramerate;
+
+/// What a stream does with a new frame when its queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackpressurePolicy {
+    /// The new frame is dropped, keeping the queued ones.
+    #[default]
+    DropNewest,
+    /// The oldest queued frame is dropped, so the consumer always sees the most recent frames.
+    DropOldest,
+    /// The capture thread waits for the consumer, which slows down capture instead of losing frames. The thread
+    /// is shared by every stream of the source, so it gives up after 100 ms and drops frames until the consumer
+    /// takes one.
+    Block,
+}
+
+/// Queueing options for a capture stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamOptions {
+    /// Number of frames that can be queued. Clamped to at least 1.
+    pub capacity: usize,
+    pub policy: BackpressurePolicy,
+    /// Sends the stream only this many of the frames its framerate lets through, skipping the rest by their
+    /// timestamps. Unlike the framerate of the stream it doesn't make the source capture any slower, so e.g. a
+    /// thumbnail can share the readback of an encoder stream. `None` sends every frame.
+    pub target_framerate: Option<CaptureFramerate>,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self { capacity: 2, policy: BackpressurePolicy::default(), target_framerate: None }
+    }
+}
@@ -1,4 +1,7 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+use std::ops::{Add, Div, Mul, Sub};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vector2<N = f32> {
     pub x: N,
     pub y: N,
@@ -8,4 +11,156 @@ impl<N> Vector2<N> {
     pub fn new(x: N, y: N) -> Self {
         Self { x, y }
     }
+
+    /// Applies `f` to both components.
+    pub fn map<M>(self, mut f: impl FnMut(N) -> M) -> Vector2<M> {
+        Vector2 { x: f(self.x), y: f(self.y) }
+    }
+
+    /// Converts both components like `as` does, i.e. saturating floats and wrapping integers.
+    pub fn cast<M>(self) -> Vector2<M>
+    where
+        N: Cast<M>,
+    {
+        self.map(Cast::cast)
+    }
+
+    /// Converts both components, or returns `None` if either doesn't fit, e.g. a negative size into `u32`.
+    pub fn checked_cast<M>(self) -> Option<Vector2<M>>
+    where
+        N: Cast<M>,
+    {
+        Some(Vector2 { x: self.x.checked_cast()?, y: self.y.checked_cast()? })
+    }
+}
+
+impl<N: Add<Output = N>> Add for Vector2<N> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Vector2 { x: self.x + other.x, y: self.y + other.y }
+    }
+}
+
+impl<N: Sub<Output = N>> Sub for Vector2<N> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Vector2 { x: self.x - other.x, y: self.y - other.y }
+    }
+}
+
+impl<N: Mul<Output = N> + Copy> Mul<N> for Vector2<N> {
+    type Output = Self;
+
+    fn mul(self, factor: N) -> Self {
+        Vector2 { x: self.x * factor, y: self.y * factor }
+    }
+}
+
+impl<N: Div<Output = N> + Copy> Div<N> for Vector2<N> {
+    type Output = Self;
+
+    fn div(self, divisor: N) -> Self {
+        Vector2 { x: self.x / divisor, y: self.y / divisor }
+    }
+}
+
+/// Numeric conversions between the component types used for sizes and positions, see `Vector2::cast`.
+pub trait Cast<M>: Sized {
+    fn cast(self) -> M;
+    fn checked_cast(self) -> Option<M>;
+}
+
+macro_rules! impl_int_cast {
+    ($($from:ty => $to:ty),* $(,)?) => {$(
+        impl Cast<$to> for $from {
+            fn cast(self) -> $to {
+                self as $to
+            }
+
+            fn checked_cast(self) -> Option<$to> {
+                <$to>::try_from(self).ok()
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_float_cast {
+    ($($from:ty => $to:ty),* $(,)?) => {$(
+        impl Cast<$to> for $from {
+            fn cast(self) -> $to {
+                self as $to
+            }
+
+            /// Fractions are truncated, only values outside of the range or NaN fail.
+            fn checked_cast(self) -> Option<$to> {
+                let value = self.trunc();
+                // The maximum rounds up to the next power of two in f32, so the bound is exclusive.
+                (value >= <$to>::MIN as $from && value < <$to>::MAX as $from + 1.0).then(|| value as $to)
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_to_float_cast {
+    ($($from:ty => $to:ty),* $(,)?) => {$(
+        impl Cast<$to> for $from {
+            fn cast(self) -> $to {
+                self as $to
+            }
+
+            /// Always succeeds, large integers lose precision like with `as`.
+            fn checked_cast(self) -> Option<$to> {
+                Some(self as $to)
+            }
+        }
+    )*};
+}
+
+impl_int_cast!(i32 => i32, i32 => u32, u32 => i32, u32 => u32);
+impl_float_cast!(f32 => i32, f32 => u32, f64 => i32, f64 => u32);
+impl_to_float_cast!(
+    i32 => f32, i32 => f64, u32 => f32, u32 => f64, f32 => f32, f32 => f64, f64 => f32, f64 => f64
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic_works_per_component() {
+        let a = Vector2::new(3, 4);
+        let b = Vector2::new(1, -2);
+        assert_eq!(a + b, Vector2::new(4, 2));
+        assert_eq!(a - b, Vector2::new(2, 6));
+        assert_eq!(a * 2, Vector2::new(6, 8));
+        assert_eq!(a / 2, Vector2::new(1, 2));
+        assert_eq!(a.map(|component| component * 10), Vector2::new(30, 40));
+    }
+
+    #[test]
+    fn casts_behave_like_as() {
+        assert_eq!(Vector2::new(1920, 1080).cast::<u32>(), Vector2::new(1920u32, 1080));
+        assert_eq!(Vector2::new(-1, 2).cast::<u32>(), Vector2::new(u32::MAX, 2));
+        assert_eq!(Vector2::new(1.9f32, -0.5).cast::<i32>(), Vector2::new(1, 0));
+        assert_eq!(Vector2::new(-3.0f32, f32::NAN).cast::<u32>(), Vector2::new(0, 0));
+        assert_eq!(Vector2::new(640u32, 360).cast::<f32>(), Vector2::new(640.0, 360.0));
+    }
+
+    #[test]
+    fn checked_casts_reject_what_doesnt_fit() {
+        assert_eq!(Vector2::new(-1, 2).checked_cast::<u32>(), None);
+        assert_eq!(Vector2::new(u32::MAX, 0).checked_cast::<i32>(), None);
+        assert_eq!(Vector2::new(7u32, 9).checked_cast::<i32>(), Some(Vector2::new(7, 9)));
+        // Fractions are truncated, while values past the range and NaN fail.
+        assert_eq!(Vector2::new(2.7f32, -0.9).checked_cast::<i32>(), Some(Vector2::new(2, 0)));
+        assert_eq!(Vector2::new(-1.0f32, 0.0).checked_cast::<u32>(), None);
+        assert_eq!(Vector2::new(f32::NAN, 0.0).checked_cast::<i32>(), None);
+        assert_eq!(Vector2::new(2_147_483_648.0f32, 0.0).checked_cast::<i32>(), None);
+        assert_eq!(
+            Vector2::new(4_294_967_295.0f64, 0.0).checked_cast::<u32>(),
+            Some(Vector2::new(u32::MAX, 0))
+        );
+    }
 }
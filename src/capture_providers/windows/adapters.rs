@@ -0,0 +1,136 @@
+use std::fmt::Display;
+
+use windows::Win32::{
+    Foundation::LUID,
+    Graphics::{
+        Direct3D11::ID3D11Device,
+        Dxgi::{
+            CreateDXGIFactory1, DXGI_ADAPTER_DESC1, DXGI_ADAPTER_FLAG_SOFTWARE, IDXGIAdapter,
+            IDXGIAdapter1, IDXGIDevice, IDXGIFactory1,
+        },
+    },
+};
+use windows_core::{Interface, Result};
+
+use crate::capture_providers::windows::d3d11_utils::create_d3d_device;
+
+/// A GPU as listed by `list_adapters`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdapterInfo {
+    pub description: String,
+    /// Stays the same until the adapter is restarted, unlike its position in the list.
+    pub luid: LUID,
+    /// In bytes, zero for integrated GPUs that share system memory.
+    pub dedicated_video_memory: usize,
+    /// Whether this is a software adapter such as WARP.
+    pub software: bool,
+}
+
+impl AdapterInfo {
+    fn from_desc(desc: &DXGI_ADAPTER_DESC1) -> Self {
+        let len = desc.Description.iter().position(|&c| c == 0).unwrap_or(desc.Description.len());
+        Self {
+            description: String::from_utf16_lossy(&desc.Description[..len]),
+            luid: desc.AdapterLuid,
+            dedicated_video_memory: desc.DedicatedVideoMemory,
+            software: desc.Flags & DXGI_ADAPTER_FLAG_SOFTWARE.0 as u32 != 0,
+        }
+    }
+
+    /// The adapter a device was created on.
+    pub(super) fn of_device(device: &ID3D11Device) -> Result<Self> {
+        let adapter: IDXGIAdapter1 = unsafe { device.cast::<IDXGIDevice>()?.GetAdapter()?.cast()? };
+        Ok(Self::from_desc(&unsafe { adapter.GetDesc1()? }))
+    }
+}
+
+impl Display for AdapterInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({} MiB)", self.description, self.dedicated_video_memory / (1024 * 1024))?;
+        if self.software {
+            write!(f, ", software")?;
+        }
+        Ok(())
+    }
+}
+
+/// Which GPU a provider captures on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AdapterSelection {
+    /// The adapter D3D11 picks, usually the one of the primary monitor.
+    #[default]
+    Default,
+    /// The adapter at this position in `list_adapters`.
+    Index(usize),
+    Luid(LUID),
+}
+
+impl AdapterSelection {
+    /// The selected adapter, `None` for the default one.
+    pub(super) fn find(self) -> super::Result<Option<IDXGIAdapter1>> {
+        if self == Self::Default {
+            return Ok(None);
+        }
+        let factory: IDXGIFactory1 = unsafe { CreateDXGIFactory1()? };
+        let mut index = 0;
+        while let Ok(adapter) = unsafe { factory.EnumAdapters1(index) } {
+            let found = match self {
+                Self::Default => true,
+                Self::Index(wanted) => index as usize == wanted,
+                Self::Luid(luid) => unsafe { adapter.GetDesc1()? }.AdapterLuid == luid,
+            };
+            if found {
+                return Ok(Some(adapter));
+            }
+            index += 1;
+        }
+        Err(super::WindowsCaptureError::AdapterNotFound(self))
+    }
+
+    /// Creates a device on the selected adapter, see `create_d3d_device`.
+    pub(crate) fn create_device(self) -> super::Result<ID3D11Device> {
+        let adapter = self.find()?.map(|adapter| adapter.cast::<IDXGIAdapter>()).transpose()?;
+        let device = create_d3d_device(adapter.as_ref())?;
+        match AdapterInfo::of_device(&device) {
+            Ok(info) if info.software => tracing::warn!("Capturing on {}, which is slow.", info),
+            Ok(info) => tracing::info!("Capturing on {}.", info),
+            Err(err) => tracing::debug!("Failed to describe the adapter of the device: {}", err),
+        }
+        Ok(device)
+    }
+}
+
+impl Display for AdapterSelection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Default => write!(f, "default adapter"),
+            Self::Index(index) => write!(f, "adapter {}", index),
+            Self::Luid(luid) => write!(f, "adapter {:08x}:{:08x}", luid.HighPart, luid.LowPart),
+        }
+    }
+}
+
+/// Returns every GPU, in the order `AdapterSelection::Index` refers to them. Software adapters come last.
+pub fn list_adapters() -> Vec<AdapterInfo> {
+    let factory: IDXGIFactory1 = match unsafe { CreateDXGIFactory1() } {
+        Ok(factory) => factory,
+        Err(err) => {
+            tracing::error!("Failed to create DXGI factory: {}", err);
+            return Vec::new();
+        }
+    };
+    let mut adapters = Vec::new();
+    let mut index = 0;
+    while let Ok(adapter) = unsafe { factory.EnumAdapters1(index) } {
+        match unsafe { adapter.GetDesc1() } {
+            Ok(desc) => adapters.push(AdapterInfo::from_desc(&desc)),
+            Err(err) => {
+                // Ends the list rather than skipping the adapter, so the indices stay valid.
+                tracing::warn!("Failed to describe adapter {}: {}", index, err);
+                break;
+            }
+        }
+        index += 1;
+    }
+    adapters
+}
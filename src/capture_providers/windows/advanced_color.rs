@@ -0,0 +1,109 @@
+use windows::{
+    Graphics::Capture::GraphicsCaptureItem,
+    Win32::{
+        Foundation::HWND,
+        Graphics::{
+            Dxgi::{
+                Common::{DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020, DXGI_FORMAT},
+                CreateDXGIFactory1, IDXGIFactory1, IDXGIOutput6,
+            },
+            Gdi::{HMONITOR, MONITOR_DEFAULTTONEAREST, MonitorFromWindow},
+        },
+    },
+};
+use windows_core::{Interface, Result};
+
+use crate::capture_providers::{
+    shared::{CaptureItemKind, PixelFormat},
+    windows::{WindowsCaptureProvider, capture_items::capture_item_info},
+};
+
+/// The format of the frame pool for the requested output format, the only two WGC captures in. RGBA16F output is
+/// captured as scRGB floats, on SDR displays as well, everything else in BGRA8 and converted from there.
+pub(super) fn frame_pool_format(output_format: PixelFormat) -> PixelFormat {
+    match output_format {
+        PixelFormat::RGBA16F => PixelFormat::RGBA16F,
+        _ => WindowsCaptureProvider::PIXEL_FORMAT,
+    }
+}
+
+/// Logs a hint if the item is on an HDR display but captured in 8 bit, which comes out washed out.
+pub(super) fn warn_if_clipped(item: &GraphicsCaptureItem, pool_format: PixelFormat) {
+    if pool_format != PixelFormat::RGBA16F && is_advanced_color_item(item) {
+        tracing::warn!(
+            "{} is on an HDR display but captured in {:?}, RGBA16F output keeps its full range.",
+            item.DisplayName().unwrap_or("<no name>".into()),
+            pool_format
+        );
+    }
+}
+
+/// The format of captured textures, which depends on the format of the frame pool they came from.
+pub(super) fn texture_pixel_format(format: DXGI_FORMAT) -> PixelFormat {
+    PixelFormat::try_from(format).unwrap_or_else(|err| {
+        tracing::warn!(
+            "{}, treating the texture as {:?}",
+            err,
+            WindowsCaptureProvider::PIXEL_FORMAT
+        );
+        WindowsCaptureProvider::PIXEL_FORMAT
+    })
+}
+
+/// Whether the monitor showing the item is in HDR mode. Windows count as being on the monitor they overlap most.
+fn is_advanced_color_item(item: &GraphicsCaptureItem) -> bool {
+    let info = match capture_item_info(item) {
+        Ok(info) => info,
+        Err(err) => {
+            tracing::debug!("Failed to look up capture item for HDR detection: {}", err);
+            return false;
+        }
+    };
+    let monitor = match (info.kind, info.native_handle) {
+        (CaptureItemKind::Monitor, Some(handle)) => HMONITOR(handle as *mut core::ffi::c_void),
+        (CaptureItemKind::Window, Some(handle)) => unsafe {
+            MonitorFromWindow(HWND(handle as *mut core::ffi::c_void), MONITOR_DEFAULTTONEAREST)
+        },
+        _ => return false,
+    };
+
+    match is_advanced_color_monitor(monitor) {
+        Ok(advanced_color) => advanced_color,
+        Err(err) => {
+            tracing::warn!("Failed to query the advanced color state of {}: {}", info, err);
+            false
+        }
+    }
+}
+
+fn is_advanced_color_monitor(monitor: HMONITOR) -> Result<bool> {
+    let factory: IDXGIFactory1 = unsafe { CreateDXGIFactory1()? };
+    let mut adapter_index = 0;
+    while let Ok(adapter) = unsafe { factory.EnumAdapters1(adapter_index) } {
+        let mut output_index = 0;
+        while let Ok(output) = unsafe { adapter.EnumOutputs(output_index) } {
+            let desc = unsafe { output.cast::<IDXGIOutput6>()?.GetDesc1()? };
+            if desc.Monitor == monitor {
+                return Ok(desc.ColorSpace == DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020);
+            }
+            output_index += 1;
+        }
+        adapter_index += 1;
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_output_format_picks_the_frame_pool_format() {
+        assert_eq!(frame_pool_format(PixelFormat::RGBA16F), PixelFormat::RGBA16F);
+        for format in
+            [PixelFormat::RGBA8, PixelFormat::BGRA8, PixelFormat::NV12, PixelFormat::Gray8]
+        {
+            assert_eq!(frame_pool_format(format), PixelFormat::BGRA8);
+        }
+    }
+}
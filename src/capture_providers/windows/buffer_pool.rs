@@ -0,0 +1,293 @@
+use std::sync::{Arc, Mutex, Weak, atomic::Ordering};
+
+use bytes::Bytes;
+
+use crate::capture_providers::windows::capture_stats::CaptureCounters;
+
+/// Readback buffers of one stream. Handing a buffer out as `Bytes` keeps a handle to the pool, so the
+/// allocation comes back once the consumer drops the last clone of the frame data, wherever that happens.
+///
+/// Buffers are matched to the size of the frames, so after the frames change size the pool switches over to
+/// buffers of the new size rather than growing old ones or keeping them around unused.
+#[derive(Debug)]
+pub(super) struct BufferPool {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    idle: Mutex<Idle>,
+    max_idle_buffers: usize,
+    max_idle_bytes: usize,
+    counters: Arc<CaptureCounters>,
+}
+
+#[derive(Debug, Default)]
+struct Idle {
+    buffers: Vec<Vec<u8>>,
+    /// Total capacity of `buffers`.
+    bytes: usize,
+    /// Capacity asked for by the last `take`, i.e. the size of the current frames.
+    wanted: usize,
+}
+
+impl Shared {
+    fn remove(&self, idle: &mut Idle, index: usize) -> Vec<u8> {
+        let buffer = idle.buffers.swap_remove(index);
+        idle.bytes -= buffer.capacity();
+        self.counters.buffer_pool_bytes.fetch_sub(buffer.capacity() as u64, Ordering::Relaxed);
+        buffer
+    }
+
+    fn give_back(&self, buffer: Vec<u8>) {
+        let mut idle = self.idle.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let capacity = buffer.capacity();
+        // Buffers from before the frames changed size would either have to grow or waste memory.
+        let fits = capacity >= idle.wanted && capacity <= idle.wanted.saturating_mul(2);
+        // Only as many buffers as are in flight at once are ever kept, the caps guard against consumers that
+        // hoard frames and then let go of all of them.
+        let room = idle.buffers.len() < self.max_idle_buffers
+            && idle.bytes + capacity <= self.max_idle_bytes;
+        if fits && room {
+            idle.bytes += capacity;
+            self.counters.buffer_pool_bytes.fetch_add(capacity as u64, Ordering::Relaxed);
+            idle.buffers.push(buffer);
+        }
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        let idle = self.idle.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.counters.buffer_pool_bytes.fetch_sub(idle.bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// Returns its buffer to the pool on drop, if the pool still exists.
+struct PooledBuffer {
+    data: Vec<u8>,
+    pool: Weak<Shared>,
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.upgrade() {
+            pool.give_back(std::mem::take(&mut self.data));
+        }
+    }
+}
+
+impl BufferPool {
+    /// Keeps at most `max_idle_buffers` returned buffers of at most `max_idle_bytes` in total around, zero for
+    /// either disables pooling.
+    pub fn new(
+        counters: Arc<CaptureCounters>,
+        max_idle_buffers: usize,
+        max_idle_bytes: usize,
+    ) -> Self {
+        let shared = Shared {
+            idle: Mutex::new(Idle::default()),
+            max_idle_buffers,
+            max_idle_bytes,
+            counters,
+        };
+        Self { shared: Arc::new(shared) }
+    }
+
+    /// Takes a buffer with a capacity of at least `min_capacity` to read a frame into. Its contents are stale
+    /// and its length is arbitrary.
+    pub fn take(&self, min_capacity: usize) -> Vec<u8> {
+        let shared = &self.shared;
+        let mut idle = shared.idle.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        idle.wanted = min_capacity;
+        let best_fit = idle
+            .buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| buffer.capacity() >= min_capacity)
+            .min_by_key(|(_, buffer)| buffer.capacity())
+            .map(|(index, _)| index);
+        if let Some(index) = best_fit {
+            let buffer = shared.remove(&mut idle, index);
+            drop(idle);
+            shared.counters.buffer_pool_hits.fetch_add(1, Ordering::Relaxed);
+            return buffer;
+        }
+
+        // The frames grew, none of the idle buffers will fit until they shrink again.
+        while let Some(index) =
+            idle.buffers.iter().position(|buffer| buffer.capacity() < min_capacity)
+        {
+            shared.remove(&mut idle, index);
+        }
+        drop(idle);
+        shared.counters.buffer_pool_misses.fetch_add(1, Ordering::Relaxed);
+        Vec::with_capacity(min_capacity)
+    }
+
+    /// Wraps a buffer so it returns to this pool once the bytes are dropped.
+    pub fn wrap(&self, data: Vec<u8>) -> Bytes {
+        Bytes::from_owner(PooledBuffer { data, pool: Arc::downgrade(&self.shared) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    const FRAME_BYTES: usize = 1024;
+
+    fn new_pool(
+        max_idle_buffers: usize,
+        max_idle_bytes: usize,
+    ) -> (BufferPool, Arc<CaptureCounters>) {
+        let counters = Arc::new(CaptureCounters::default());
+        (BufferPool::new(counters.clone(), max_idle_buffers, max_idle_bytes), counters)
+    }
+
+    fn idle_buffers(pool: &BufferPool) -> usize {
+        pool.shared.idle.lock().unwrap().buffers.len()
+    }
+
+    fn hits_and_misses(counters: &CaptureCounters) -> (u64, u64) {
+        (
+            counters.buffer_pool_hits.load(Ordering::Relaxed),
+            counters.buffer_pool_misses.load(Ordering::Relaxed),
+        )
+    }
+
+    #[test]
+    fn returned_buffers_are_reused() {
+        let (pool, counters) = new_pool(4, usize::MAX);
+        let buffer = pool.take(FRAME_BYTES);
+        assert!(buffer.capacity() >= FRAME_BYTES);
+        let allocation = buffer.as_ptr();
+        pool.shared.give_back(buffer);
+        assert_eq!(idle_buffers(&pool), 1);
+
+        let buffer = pool.take(FRAME_BYTES);
+        assert_eq!(buffer.as_ptr(), allocation);
+        assert_eq!(idle_buffers(&pool), 0);
+        assert_eq!(hits_and_misses(&counters), (1, 1));
+    }
+
+    #[test]
+    fn buffers_of_other_sizes_are_not_kept() {
+        let (pool, counters) = new_pool(4, usize::MAX);
+        let small = pool.take(FRAME_BYTES / 2);
+        let large = pool.take(FRAME_BYTES * 4);
+        let fitting = pool.take(FRAME_BYTES);
+        // The frames are now `FRAME_BYTES` large, the others would either have to grow or waste memory.
+        pool.shared.give_back(small);
+        pool.shared.give_back(large);
+        pool.shared.give_back(fitting);
+        assert_eq!(idle_buffers(&pool), 1);
+        assert_eq!(counters.buffer_pool_bytes.load(Ordering::Relaxed), FRAME_BYTES as u64);
+    }
+
+    #[test]
+    fn growing_frames_drop_the_idle_buffers_that_no_longer_fit() {
+        let (pool, counters) = new_pool(4, usize::MAX);
+        let buffers = [pool.take(FRAME_BYTES), pool.take(FRAME_BYTES)];
+        buffers.into_iter().for_each(|buffer| pool.shared.give_back(buffer));
+        assert_eq!(idle_buffers(&pool), 2);
+
+        let buffer = pool.take(FRAME_BYTES * 2);
+        assert!(buffer.capacity() >= FRAME_BYTES * 2);
+        assert_eq!(idle_buffers(&pool), 0);
+        assert_eq!(counters.buffer_pool_bytes.load(Ordering::Relaxed), 0);
+        assert_eq!(hits_and_misses(&counters), (0, 3));
+    }
+
+    #[test]
+    fn idle_buffers_are_capped_by_count_and_bytes() {
+        let (pool, _) = new_pool(2, usize::MAX);
+        let buffers: Vec<_> = (0..3).map(|_| pool.take(FRAME_BYTES)).collect();
+        buffers.into_iter().for_each(|buffer| pool.shared.give_back(buffer));
+        assert_eq!(idle_buffers(&pool), 2);
+
+        // Room for the bytes of one buffer, but not of two.
+        let (pool, counters) = new_pool(usize::MAX, FRAME_BYTES * 3 / 2);
+        let buffers: Vec<_> = (0..3).map(|_| pool.take(FRAME_BYTES)).collect();
+        buffers.into_iter().for_each(|buffer| pool.shared.give_back(buffer));
+        assert_eq!(idle_buffers(&pool), 1);
+        assert_eq!(counters.buffer_pool_bytes.load(Ordering::Relaxed), FRAME_BYTES as u64);
+    }
+
+    #[test]
+    fn zero_limits_disable_pooling() {
+        for (max_idle_buffers, max_idle_bytes) in [(0, usize::MAX), (4, 0)] {
+            let (pool, counters) = new_pool(max_idle_buffers, max_idle_bytes);
+            let buffer = pool.take(FRAME_BYTES);
+            pool.shared.give_back(buffer);
+            assert_eq!(idle_buffers(&pool), 0);
+            let _ = pool.take(FRAME_BYTES);
+            assert_eq!(hits_and_misses(&counters), (0, 2));
+        }
+    }
+
+    #[test]
+    fn dropping_the_last_clone_of_the_bytes_returns_the_buffer() {
+        let (pool, counters) = new_pool(4, usize::MAX);
+        let mut buffer = pool.take(FRAME_BYTES);
+        buffer.resize(FRAME_BYTES, 0);
+        let bytes = pool.wrap(buffer);
+        let clone = bytes.clone();
+        assert_eq!(clone.len(), FRAME_BYTES);
+
+        drop(bytes);
+        assert_eq!(idle_buffers(&pool), 0);
+        assert_eq!(counters.buffer_pool_bytes.load(Ordering::Relaxed), 0);
+
+        drop(clone);
+        assert_eq!(idle_buffers(&pool), 1);
+        assert_eq!(counters.buffer_pool_bytes.load(Ordering::Relaxed), FRAME_BYTES as u64);
+        let _ = pool.take(FRAME_BYTES);
+        assert_eq!(hits_and_misses(&counters), (1, 1));
+    }
+
+    #[test]
+    fn bytes_outliving_the_pool_are_freed() {
+        let (pool, counters) = new_pool(4, usize::MAX);
+        let bytes = pool.wrap(pool.take(FRAME_BYTES));
+        pool.shared.give_back(pool.take(FRAME_BYTES));
+        assert_eq!(counters.buffer_pool_bytes.load(Ordering::Relaxed), FRAME_BYTES as u64);
+
+        drop(pool);
+        assert_eq!(counters.buffer_pool_bytes.load(Ordering::Relaxed), 0);
+        drop(bytes);
+        assert_eq!(counters.buffer_pool_bytes.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn steady_capture_reuses_every_buffer_after_warmup() {
+        const FRAMES: u64 = 10_000;
+        // Frames the consumer holds on to at once, e.g. the queue of the stream and the one on screen.
+        const IN_FLIGHT: usize = 3;
+        let (pool, counters) = new_pool(IN_FLIGHT + 1, usize::MAX);
+        let mut in_flight = VecDeque::new();
+        for _ in 0..FRAMES {
+            if in_flight.len() == IN_FLIGHT {
+                in_flight.pop_front();
+            }
+            let mut buffer = pool.take(FRAME_BYTES);
+            buffer.resize(FRAME_BYTES, 0);
+            let bytes = pool.wrap(buffer);
+            // The consumer keeps a clone and lets go of the frame itself, like the UI does.
+            in_flight.push_back(bytes.clone());
+        }
+
+        let (hits, misses) = hits_and_misses(&counters);
+        assert_eq!(hits + misses, FRAMES);
+        assert!(hits >= FRAMES - IN_FLIGHT as u64, "{} hits, {} misses", hits, misses);
+        assert!(idle_buffers(&pool) <= IN_FLIGHT + 1);
+    }
+}
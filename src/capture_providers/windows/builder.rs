@@ -1,9 +1,23 @@
-use windows::Graphics::{Capture::GraphicsCaptureItem, DirectX::Direct3D11::IDirect3DDevice};
+use windows::{
+    Graphics::{Capture::GraphicsCaptureItem, DirectX::Direct3D11::IDirect3DDevice},
+    Win32::Foundation::LUID,
+};
 
-use crate::capture_providers::windows::{
-    WindowsCaptureError,
-    capture_provider::WindowsCaptureProvider,
-    d3d11_utils::{create_d3d_device, native_to_winrt_d3d11device},
+use crate::{
+    capture_providers::{
+        CaptureProvider,
+        shared::{ConversionPolicy, PixelFormat, StreamOptions},
+        windows::{
+            AdapterSelection, WindowsCaptureError, advanced_color::frame_pool_format,
+            capabilities::wgc_capabilities, capture_provider::WindowsCaptureProvider,
+            d3d11_utils::native_to_winrt_d3d11device,
+        },
+    },
+    utils::{
+        com_thread::{Apartment, ComThread, ComThreadError},
+        image_utils::supports_conversion,
+        unsafe_send_wrapper::UnsafeSendWrapper,
+    },
 };
 
 type Result<T> = std::result::Result<T, BuilderError>;
@@ -12,34 +26,90 @@ type Result<T> = std::result::Result<T, BuilderError>;
 pub enum BuilderError {
     #[error("Missing device")]
     MissingDevice,
+    #[error("Pipeline depth must be at least {min} frames, got {depth}")]
+    InvalidPipelineDepth { depth: usize, min: usize },
+    #[error("Readback depth must be at least 1 frame, got 0")]
+    InvalidReadbackDepth,
+    #[error("Channel capacity must be at least 1 frame, got 0")]
+    InvalidChannelCapacity,
+    #[error("Cannot output {format:?} frames from a capture in {capture_format:?}")]
+    UnsupportedPixelFormat { format: PixelFormat, capture_format: PixelFormat },
     #[error("Initialization error: {0}")]
     InitializationError(#[from] WindowsCaptureError),
+    #[error("COM thread error: {0}")]
+    ComThreadError(#[from] ComThreadError),
     #[error("Windows error: {0}")]
     WindowsError(#[from] windows::core::Error),
 }
 
 pub struct WindowsCaptureProviderBuilder {
     device: Option<IDirect3DDevice>,
+    /// The adapter the device was created on, which device recovery sticks to.
+    adapter: AdapterSelection,
     capture_item: Option<GraphicsCaptureItem>,
+    buffer_pool_size: usize,
+    buffer_pool_max_bytes: usize,
+    pipeline_depth: usize,
+    readback_depth: usize,
+    channel_capacity: usize,
+    pixel_format: PixelFormat,
+    conversion_policy: ConversionPolicy,
+    cursor_capture_enabled: bool,
+    border_required: bool,
 }
 
 impl WindowsCaptureProviderBuilder {
+    /// WGC needs a buffer to render into while the consumer holds the last frame.
+    const MIN_PIPELINE_DEPTH: usize = 2;
+
     pub fn new() -> Self {
-        WindowsCaptureProviderBuilder { device: None, capture_item: None }
+        WindowsCaptureProviderBuilder {
+            device: None,
+            adapter: AdapterSelection::Default,
+            capture_item: None,
+            buffer_pool_size: WindowsCaptureProvider::DEFAULT_BUFFER_POOL_SIZE,
+            buffer_pool_max_bytes: WindowsCaptureProvider::DEFAULT_BUFFER_POOL_MAX_BYTES,
+            pipeline_depth: WindowsCaptureProvider::DEFAULT_PIPELINE_DEPTH,
+            readback_depth: WindowsCaptureProvider::DEFAULT_READBACK_DEPTH,
+            channel_capacity: StreamOptions::default().capacity,
+            pixel_format: PixelFormat::RGBA8,
+            conversion_policy: ConversionPolicy::default(),
+            cursor_capture_enabled: true,
+            border_required: true,
+        }
     }
 
-    #[allow(dead_code)]
     pub fn with_device(mut self, device: IDirect3DDevice) -> Self {
         tracing::debug!("Setting custom device for WindowsCaptureProviderBuilder");
         self.device = Some(device);
         self
     }
 
-    pub fn with_default_device(mut self) -> Result<Self> {
+    pub fn with_default_device(self) -> Result<Self> {
         tracing::debug!("Initializing default capture device for WindowsCaptureProviderBuilder");
-        let d3d_device = create_d3d_device()?;
-        let winrt_device = native_to_winrt_d3d11device(&d3d_device)?;
-        self.device = Some(winrt_device);
+        self.with_adapter(AdapterSelection::Default)
+    }
+
+    /// Creates the device on the adapter at `index` in `list_adapters` rather than the default one, e.g. the
+    /// GPU the captured monitor is connected to on a hybrid laptop, which saves copies between the GPUs.
+    pub fn with_adapter_index(self, index: usize) -> Result<Self> {
+        self.with_adapter(AdapterSelection::Index(index))
+    }
+
+    /// Creates the device on the adapter with the LUID, see `with_adapter_index`.
+    pub fn with_adapter_luid(self, luid: LUID) -> Result<Self> {
+        self.with_adapter(AdapterSelection::Luid(luid))
+    }
+
+    /// Creates the device on the adapter, falling back to WARP if no hardware device can be created there.
+    pub fn with_adapter(mut self, adapter: AdapterSelection) -> Result<Self> {
+        let winrt_device =
+            ComThread::shared(Apartment::MultiThreaded)?.run_blocking(move || {
+                let d3d_device = adapter.create_device()?;
+                Ok::<_, BuilderError>(UnsafeSendWrapper(native_to_winrt_d3d11device(&d3d_device)?))
+            })??;
+        self.device = Some(winrt_device.take_inner());
+        self.adapter = adapter;
         Ok(self)
     }
 
@@ -49,13 +119,280 @@ impl WindowsCaptureProviderBuilder {
         Ok(self)
     }
 
-    /// Must be called from the main thread.
+    /// The item to capture from the start. Without one, the provider waits for `set_capture_item`.
+    pub fn with_capture_item(mut self, capture_item: GraphicsCaptureItem) -> Self {
+        self.capture_item = Some(capture_item);
+        self
+    }
+
+    /// How many idle readback buffers each stream keeps for reuse. Zero allocates a new buffer for every frame.
+    /// Defaults to 8.
+    pub fn with_buffer_pool_size(mut self, size: usize) -> Self {
+        self.buffer_pool_size = size;
+        self
+    }
+
+    /// How many bytes the idle readback buffers of each stream may hold in total. Buffers returned beyond that
+    /// are freed. Defaults to 256 MiB.
+    pub fn with_buffer_pool_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.buffer_pool_max_bytes = max_bytes;
+        self
+    }
+
+    /// How many frames WGC can have in flight per source. More buffers smooth over slow consumers at the cost of
+    /// latency and GPU memory. At least 2, defaults to 2.
+    pub fn with_pipeline_depth(mut self, depth: usize) -> Self {
+        self.pipeline_depth = depth;
+        self
+    }
+
+    /// How many frames each stream can have between the GPU copy and readback. 1 waits for the copy of every
+    /// frame, trading throughput on a busy GPU for a frame less latency. At least 1, defaults to 2.
+    pub fn with_readback_depth(mut self, depth: usize) -> Self {
+        self.readback_depth = depth;
+        self
+    }
+
+    /// How many frames streams created without `StreamOptions`, e.g. by `create_stream`, can queue before the
+    /// backpressure policy applies. More absorb stalls of the consumer, fewer keep it closer to live. At least 1,
+    /// defaults to 2.
+    pub fn with_channel_capacity(mut self, capacity: usize) -> Self {
+        self.channel_capacity = capacity;
+        self
+    }
+
+    /// The format of frames coming off streams, see `WindowsCaptureProvider::set_output_format`.
+    /// Also picks the format of the frame pool, RGBA16F for RGBA16F and BGRA8 for everything else. Defaults to
+    /// RGBA8.
+    pub fn with_pixel_format(mut self, format: PixelFormat) -> Self {
+        self.pixel_format = format;
+        self
+    }
+
+    /// See `WindowsCaptureProvider::set_conversion_policy`. Defaults to deferred.
+    pub fn with_conversion_policy(mut self, policy: ConversionPolicy) -> Self {
+        self.conversion_policy = policy;
+        self
+    }
+
+    pub fn with_cursor_capture(mut self, enabled: bool) -> Self {
+        self.cursor_capture_enabled = enabled;
+        self
+    }
+
+    /// Has no effect on versions of Windows that always draw the border.
+    pub fn with_border(mut self, required: bool) -> Self {
+        self.border_required = required;
+        self
+    }
+
+    /// Checks the options that don't depend on the system. Returns the pipeline depth as WGC takes it.
+    fn validate(&self) -> Result<i32> {
+        let pipeline_depth = match i32::try_from(self.pipeline_depth) {
+            Ok(depth) if self.pipeline_depth >= Self::MIN_PIPELINE_DEPTH => depth,
+            _ => {
+                return Err(BuilderError::InvalidPipelineDepth {
+                    depth: self.pipeline_depth,
+                    min: Self::MIN_PIPELINE_DEPTH,
+                });
+            }
+        };
+        if self.readback_depth == 0 {
+            return Err(BuilderError::InvalidReadbackDepth);
+        }
+        if self.channel_capacity == 0 {
+            return Err(BuilderError::InvalidChannelCapacity);
+        }
+        check_output_format(frame_pool_format(self.pixel_format), self.pixel_format)?;
+        Ok(pipeline_depth)
+    }
+
+    /// Can be called from any thread, the provider is set up on the shared COM thread.
     pub fn build(self) -> Result<WindowsCaptureProvider> {
         tracing::info!("Building WindowsCaptureProvider");
+        if !wgc_capabilities().supported {
+            tracing::error!("Windows.Graphics.Capture is not supported on this system");
+            return Err(WindowsCaptureError::CaptureNotSupported.into());
+        }
+        let pipeline_depth = self.validate()?;
         let device = self.device.ok_or_else(|| {
             tracing::error!("Attempted to build WindowsCaptureProvider without a device");
             BuilderError::MissingDevice
         })?;
-        Ok(WindowsCaptureProvider::new(device, self.capture_item))
+
+        let Self {
+            adapter,
+            capture_item,
+            buffer_pool_size,
+            buffer_pool_max_bytes,
+            readback_depth,
+            channel_capacity,
+            pixel_format,
+            conversion_policy,
+            cursor_capture_enabled,
+            border_required,
+            ..
+        } = self;
+        let parts = UnsafeSendWrapper((device, capture_item));
+        ComThread::shared(Apartment::MultiThreaded)?.run_blocking(move || {
+            let (device, capture_item) = parts.take_inner();
+            let mut provider = WindowsCaptureProvider::new(device, None);
+            provider.set_adapter(adapter);
+            provider.set_pipeline_depth(pipeline_depth);
+            provider.set_buffer_pool_size(buffer_pool_size);
+            provider.set_buffer_pool_max_bytes(buffer_pool_max_bytes);
+            provider.set_readback_depth(readback_depth);
+            provider.set_channel_capacity(channel_capacity);
+            provider.set_output_format(pixel_format);
+            provider.set_conversion_policy(conversion_policy);
+            provider.set_cursor_capture(cursor_capture_enabled)?;
+            provider.set_border_required(border_required)?;
+            if let Some(capture_item) = capture_item {
+                provider.set_capture_item(capture_item)?;
+            }
+            Ok(provider)
+        })?
+    }
+}
+
+/// Whether frames captured in `capture_format` can be output in `format`.
+fn check_output_format(capture_format: PixelFormat, format: PixelFormat) -> Result<()> {
+    match supports_conversion(capture_format, format) {
+        true => Ok(()),
+        false => Err(BuilderError::UnsupportedPixelFormat { format, capture_format }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::capture_providers::{
+        CaptureStream, shared::CaptureFramerate, windows::create_capture_item_for_primary_monitor,
+    };
+
+    #[test]
+    fn the_defaults_are_valid() {
+        let depth = WindowsCaptureProviderBuilder::new().validate().unwrap();
+        assert_eq!(depth as usize, WindowsCaptureProvider::DEFAULT_PIPELINE_DEPTH);
+    }
+
+    #[test]
+    fn pipeline_depths_below_two_are_rejected() {
+        for depth in [0, 1] {
+            let err = WindowsCaptureProviderBuilder::new().with_pipeline_depth(depth).validate();
+            let Err(BuilderError::InvalidPipelineDepth { depth: got, min }) = err else {
+                panic!("expected an invalid pipeline depth, got {:?}", err);
+            };
+            assert_eq!((got, min), (depth, 2));
+        }
+        assert!(WindowsCaptureProviderBuilder::new().with_pipeline_depth(2).validate().is_ok());
+    }
+
+    #[test]
+    fn pipeline_depths_wgc_cant_take_are_rejected() {
+        let depth = i32::MAX as usize + 1;
+        let err = WindowsCaptureProviderBuilder::new().with_pipeline_depth(depth).validate();
+        assert!(matches!(err, Err(BuilderError::InvalidPipelineDepth { .. })), "{:?}", err);
+    }
+
+    #[test]
+    fn empty_readback_rings_and_channels_are_rejected() {
+        let err = WindowsCaptureProviderBuilder::new().with_readback_depth(0).validate();
+        assert!(matches!(err, Err(BuilderError::InvalidReadbackDepth)), "{:?}", err);
+        let err = WindowsCaptureProviderBuilder::new().with_channel_capacity(0).validate();
+        assert!(matches!(err, Err(BuilderError::InvalidChannelCapacity)), "{:?}", err);
+    }
+
+    #[test]
+    fn an_empty_buffer_pool_is_valid() {
+        let builder = WindowsCaptureProviderBuilder::new()
+            .with_buffer_pool_size(0)
+            .with_buffer_pool_max_bytes(0);
+        assert!(builder.validate().is_ok());
+    }
+
+    #[test]
+    fn every_output_format_has_a_capture_format() {
+        for format in [
+            PixelFormat::RGBA8,
+            PixelFormat::BGRA8,
+            PixelFormat::NV12,
+            PixelFormat::I420,
+            PixelFormat::Gray8,
+            PixelFormat::RGBA16F,
+        ] {
+            let builder = WindowsCaptureProviderBuilder::new().with_pixel_format(format);
+            assert!(builder.validate().is_ok(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn eight_bit_captures_cant_be_output_as_rgba16f() {
+        let err = check_output_format(PixelFormat::BGRA8, PixelFormat::RGBA16F);
+        assert!(
+            matches!(
+                err,
+                Err(BuilderError::UnsupportedPixelFormat {
+                    format: PixelFormat::RGBA16F,
+                    capture_format: PixelFormat::BGRA8,
+                })
+            ),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn invalid_options_are_reported_before_the_missing_device() {
+        let err = WindowsCaptureProviderBuilder::new().with_readback_depth(0).build();
+        assert!(
+            matches!(
+                err,
+                Err(BuilderError::InvalidReadbackDepth
+                    | BuilderError::InitializationError(WindowsCaptureError::CaptureNotSupported))
+            ),
+            "{:?}",
+            err.err()
+        );
+    }
+
+    #[test]
+    fn building_without_a_device_fails() {
+        let err = WindowsCaptureProviderBuilder::new().build();
+        // Systems without WGC fail before the device is looked at.
+        assert!(
+            matches!(
+                err,
+                Err(BuilderError::MissingDevice
+                    | BuilderError::InitializationError(WindowsCaptureError::CaptureNotSupported))
+            ),
+            "{:?}",
+            err.err()
+        );
+    }
+
+    // Nothing runs on the main thread of the test harness, and the workers never initialized COM.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn providers_are_built_and_run_from_runtime_workers() {
+        if !wgc_capabilities().supported {
+            return;
+        }
+        let task = tokio::spawn(async {
+            let mut provider =
+                WindowsCaptureProviderBuilder::new().with_default_device()?.build()?;
+            provider.set_capture_item(create_capture_item_for_primary_monitor()?)?;
+            let stream = provider.create_stream(CaptureFramerate::FPS30)?;
+            let mut frames = std::pin::pin!(stream.frames_only());
+            provider.start_capture().await?;
+            let frame = tokio::time::timeout(Duration::from_secs(5), frames.next()).await;
+            provider.stop_capture().await?;
+            Ok::<_, BuilderError>(frame)
+        });
+        let frame = task.await.unwrap().unwrap();
+        assert!(frame.expect("no frame arrived").is_some());
     }
 }
@@ -0,0 +1,64 @@
+use std::{fmt::Display, sync::OnceLock};
+
+use windows::{
+    Foundation::Metadata::ApiInformation,
+    Graphics::Capture::GraphicsCaptureSession,
+    core::{HSTRING, h},
+};
+
+/// Which parts of Windows.Graphics.Capture this version of Windows has. Newer features are only called when
+/// present, older builds just go without them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WgcCapabilities {
+    /// Whether capture works at all, which it doesn't on Windows before 1803 or without a desktop session.
+    pub supported: bool,
+    /// `GraphicsCaptureSession::IsBorderRequired`, which older builds don't have as they always draw the border.
+    pub border_toggle: bool,
+    /// `GraphicsCaptureSession::IsCursorCaptureEnabled`, from Windows 10 2004 on.
+    pub cursor_toggle: bool,
+    /// `GraphicsCaptureSession::MinUpdateInterval`, from Windows 11 on. Without it, WGC delivers frames at the
+    /// refresh rate and streams only skip down to their framerate themselves.
+    pub min_update_interval: bool,
+    /// `Direct3D11CaptureFrame::DirtyRegions`, from Windows 11 24H2 on. Without it, every frame counts as changed.
+    pub dirty_regions: bool,
+}
+
+const SESSION_CLASS: &HSTRING = h!("Windows.Graphics.Capture.GraphicsCaptureSession");
+const FRAME_CLASS: &HSTRING = h!("Windows.Graphics.Capture.Direct3D11CaptureFrame");
+
+/// Probes the features once, they can't change while running.
+pub fn wgc_capabilities() -> WgcCapabilities {
+    static CAPABILITIES: OnceLock<WgcCapabilities> = OnceLock::new();
+    *CAPABILITIES.get_or_init(|| {
+        let has_property = |class: &HSTRING, property: &HSTRING| {
+            ApiInformation::IsPropertyPresent(class, property).unwrap_or(false)
+        };
+        WgcCapabilities {
+            supported: GraphicsCaptureSession::IsSupported().unwrap_or(false),
+            border_toggle: has_property(SESSION_CLASS, h!("IsBorderRequired")),
+            cursor_toggle: has_property(SESSION_CLASS, h!("IsCursorCaptureEnabled")),
+            min_update_interval: has_property(SESSION_CLASS, h!("MinUpdateInterval")),
+            dirty_regions: has_property(FRAME_CLASS, h!("DirtyRegions")),
+        }
+    })
+}
+
+impl Display for WgcCapabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.supported {
+            return f.write_str("capture not supported");
+        }
+        let features = [
+            ("border toggle", self.border_toggle),
+            ("cursor toggle", self.cursor_toggle),
+            ("min update interval", self.min_update_interval),
+            ("dirty regions", self.dirty_regions),
+        ];
+        let present: Vec<_> =
+            features.iter().filter(|(_, present)| *present).map(|(name, _)| *name).collect();
+        match present.is_empty() {
+            true => f.write_str("capture supported, no optional features"),
+            false => write!(f, "capture supported with {}", present.join(", ")),
+        }
+    }
+}
@@ -0,0 +1,588 @@
+use windows::{
+    Graphics::Capture::GraphicsCaptureItem,
+    Win32::{
+        Foundation::{CloseHandle, HWND, LPARAM, POINT, RECT},
+        Graphics::{
+            Dwm::{DWMWA_CLOAKED, DwmGetWindowAttribute},
+            Gdi::{
+                EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITOR_DEFAULTTOPRIMARY,
+                MONITORINFO, MONITORINFOEXW, MONITORINFOF_PRIMARY, MonitorFromPoint,
+            },
+        },
+        System::{
+            Threading::{
+                OpenProcess, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+                QueryFullProcessImageNameW,
+            },
+            WinRT::Graphics::Capture::IGraphicsCaptureItemInterop,
+        },
+        UI::WindowsAndMessaging::{
+            EnumWindows, GWL_EXSTYLE, GWL_STYLE, GetClassNameW, GetWindowLongW, GetWindowRect,
+            GetWindowTextLengthW, GetWindowTextW, GetWindowThreadProcessId, IsWindow,
+            IsWindowVisible, WS_CHILD, WS_EX_TOOLWINDOW,
+        },
+    },
+};
+use windows_core::{BOOL, PWSTR, Result, factory};
+
+use crate::capture_providers::{
+    shared::{CaptureItemInfo, CaptureItemKind, CaptureTarget, Rect, Vector2},
+    windows::{WindowsCaptureError, dpi::monitor_scale_factor},
+};
+
+/// A monitor that can be captured.
+#[derive(Debug, Clone)]
+pub struct MonitorInfo {
+    pub name: String,
+    pub handle: HMONITOR,
+    /// Top left corner on the virtual desktop, in physical pixels.
+    pub position: Vector2<i32>,
+    /// In physical pixels, the same as the size of its capture item.
+    pub size: Vector2<i32>,
+    /// Physical pixels per logical pixel, e.g. 1.5 at 144 DPI.
+    pub scale_factor: f32,
+    /// Whether this is the primary monitor, whose top left corner is the origin of the virtual desktop.
+    pub is_primary: bool,
+}
+
+impl MonitorInfo {
+    /// Where the monitor is on the virtual desktop, in physical pixels.
+    pub fn bounds(&self) -> Rect<i32> {
+        Rect::new(self.position, self.size)
+    }
+
+    pub fn to_capture_item(&self) -> Result<GraphicsCaptureItem> {
+        create_capture_item_for_monitor_handle(self.handle)
+    }
+}
+
+/// A top-level window that can be captured.
+#[derive(Debug, Clone)]
+pub struct WindowInfo {
+    pub name: String,
+    pub handle: HWND,
+    pub size: Vector2<i32>,
+    /// Id of the process that created the window.
+    pub process_id: u32,
+    pub class_name: String,
+}
+
+impl WindowInfo {
+    pub fn to_capture_item(&self) -> Result<GraphicsCaptureItem> {
+        tracing::debug!("Creating capture item for window: {}", self.name);
+        let interop = factory::<GraphicsCaptureItem, IGraphicsCaptureItemInterop>()?;
+        unsafe { interop.CreateForWindow(self.handle) }
+    }
+}
+
+/// Creates a capture item for a window, e.g. one from `enumerate_capturable_windows`, without any user
+/// interaction. Fails with `WindowClosed` if the window is gone, which it can be by the time it is picked.
+pub fn create_capture_item_for_window(handle: HWND) -> super::Result<GraphicsCaptureItem> {
+    let is_window = || unsafe { IsWindow(Some(handle)) }.as_bool();
+    if !is_window() {
+        return Err(WindowsCaptureError::WindowClosed(handle.0 as usize));
+    }
+
+    tracing::debug!("Creating capture item for window: {:?}", handle);
+    let interop = factory::<GraphicsCaptureItem, IGraphicsCaptureItemInterop>()?;
+    match unsafe { interop.CreateForWindow(handle) } {
+        Ok(item) => Ok(item),
+        // The window can still close between the check and the call.
+        Err(_) if !is_window() => Err(WindowsCaptureError::WindowClosed(handle.0 as usize)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn create_capture_item_for_monitor_handle(handle: HMONITOR) -> Result<GraphicsCaptureItem> {
+    tracing::debug!("Creating capture item for monitor: {:?}", handle);
+    let interop = factory::<GraphicsCaptureItem, IGraphicsCaptureItemInterop>()?;
+    unsafe { interop.CreateForMonitor(handle) }
+}
+
+/// Creates a capture item for the primary monitor without any user interaction.
+pub fn create_capture_item_for_primary_monitor() -> Result<GraphicsCaptureItem> {
+    let handle = unsafe { MonitorFromPoint(POINT { x: 0, y: 0 }, MONITOR_DEFAULTTOPRIMARY) };
+    create_capture_item_for_monitor_handle(handle)
+}
+
+fn rect_size(rect: &RECT) -> Vector2<i32> {
+    Vector2::new(rect.right - rect.left, rect.bottom - rect.top)
+}
+
+fn utf16_to_string(buffer: &[u16]) -> String {
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    String::from_utf16_lossy(&buffer[..len])
+}
+
+unsafe extern "system" fn enum_monitors_proc(
+    handle: HMONITOR,
+    _hdc: HDC,
+    _rect: *mut RECT,
+    lparam: LPARAM,
+) -> BOOL {
+    let handles = unsafe { &mut *(lparam.0 as *mut Vec<HMONITOR>) };
+    handles.push(handle);
+    true.into()
+}
+
+/// Returns all monitors currently attached to the desktop.
+pub fn enumerate_monitors() -> Vec<MonitorInfo> {
+    let mut handles: Vec<HMONITOR> = Vec::new();
+    let ok = unsafe {
+        EnumDisplayMonitors(
+            None,
+            None,
+            Some(enum_monitors_proc),
+            LPARAM(&mut handles as *mut Vec<HMONITOR> as isize),
+        )
+    };
+    if !ok.as_bool() {
+        tracing::error!("Failed to enumerate monitors");
+        return Vec::new();
+    }
+
+    handles
+        .into_iter()
+        .filter_map(|handle| {
+            let mut info = MONITORINFOEXW::default();
+            info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+            let ok = unsafe {
+                GetMonitorInfoW(handle, &mut info as *mut MONITORINFOEXW as *mut MONITORINFO)
+            };
+            if !ok.as_bool() {
+                tracing::warn!("Failed to get monitor info for {:?}", handle);
+                return None;
+            }
+
+            let rect = info.monitorInfo.rcMonitor;
+            Some(MonitorInfo {
+                name: utf16_to_string(&info.szDevice),
+                handle,
+                position: Vector2::new(rect.left, rect.top),
+                size: rect_size(&rect),
+                scale_factor: monitor_scale_factor(handle),
+                is_primary: info.monitorInfo.dwFlags & MONITORINFOF_PRIMARY != 0,
+            })
+        })
+        .collect()
+}
+
+fn is_window_cloaked(handle: HWND) -> bool {
+    let mut cloaked = 0u32;
+    let result = unsafe {
+        DwmGetWindowAttribute(
+            handle,
+            DWMWA_CLOAKED,
+            &mut cloaked as *mut u32 as *mut core::ffi::c_void,
+            std::mem::size_of::<u32>() as u32,
+        )
+    };
+    result.is_ok() && cloaked != 0
+}
+
+/// Returns the window info if the window is something WGC can meaningfully capture.
+fn capturable_window_info(handle: HWND) -> Option<WindowInfo> {
+    if !unsafe { IsWindowVisible(handle) }.as_bool() {
+        return None;
+    }
+
+    let style = unsafe { GetWindowLongW(handle, GWL_STYLE) } as u32;
+    let ex_style = unsafe { GetWindowLongW(handle, GWL_EXSTYLE) } as u32;
+    if style & WS_CHILD.0 != 0 || ex_style & WS_EX_TOOLWINDOW.0 != 0 {
+        return None;
+    }
+
+    if is_window_cloaked(handle) {
+        return None;
+    }
+
+    let mut rect = RECT::default();
+    unsafe { GetWindowRect(handle, &mut rect) }.ok()?;
+    let size = rect_size(&rect);
+    if size.x <= 0 || size.y <= 0 {
+        return None;
+    }
+
+    let name = window_title(handle).filter(|title| !title.is_empty())?;
+    let process_id = window_process_id(handle)?;
+    let class_name = window_class_name(handle)?;
+    Some(WindowInfo { name, handle, size, process_id, class_name })
+}
+
+fn window_title(handle: HWND) -> Option<String> {
+    let title_len = unsafe { GetWindowTextLengthW(handle) };
+    if title_len < 0 {
+        return None;
+    }
+    let mut title = vec![0u16; title_len as usize + 1];
+    let copied = unsafe { GetWindowTextW(handle, &mut title) };
+    Some(utf16_to_string(&title[..copied as usize]))
+}
+
+fn window_class_name(handle: HWND) -> Option<String> {
+    // Class names are limited to 256 characters.
+    let mut class_name = [0u16; 257];
+    match unsafe { GetClassNameW(handle, &mut class_name) } {
+        0 => None,
+        copied => Some(utf16_to_string(&class_name[..copied as usize])),
+    }
+}
+
+fn window_process_id(handle: HWND) -> Option<u32> {
+    let mut process_id = 0u32;
+    match unsafe { GetWindowThreadProcessId(handle, Some(&mut process_id as *mut u32)) } {
+        0 => None,
+        _ => Some(process_id),
+    }
+}
+
+/// The file name of the executable of a process.
+fn process_exe_name(process_id: u32) -> Option<String> {
+    let process =
+        unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id) }.ok()?;
+    let mut path = vec![0u16; 1024];
+    let mut len = path.len() as u32;
+    let result = unsafe {
+        QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(path.as_mut_ptr()), &mut len)
+    };
+    if let Err(err) = unsafe { CloseHandle(process) } {
+        tracing::warn!("Failed to close process handle: {}", err);
+    }
+    result.ok()?;
+
+    let path = String::from_utf16_lossy(&path[..len as usize]);
+    let exe_name = std::path::Path::new(&path).file_name()?.to_string_lossy().into_owned();
+    Some(exe_name)
+}
+
+fn monitor_target(monitor: &MonitorInfo) -> CaptureTarget {
+    CaptureTarget::Monitor {
+        device_name: monitor.name.clone(),
+        position: monitor.position,
+        size: monitor.size,
+    }
+}
+
+fn window_target(handle: HWND) -> Option<CaptureTarget> {
+    Some(CaptureTarget::Window {
+        title: window_title(handle)?,
+        class_name: window_class_name(handle)?,
+        exe_name: process_exe_name(window_process_id(handle)?)?,
+    })
+}
+
+/// How a window title is compared to the searched text. All comparisons ignore case.
+#[derive(Debug, Clone)]
+pub enum TitleMatcher {
+    Exact(String),
+    Prefix(String),
+    Contains(String),
+    /// Matches anywhere in the title, unless anchored. See [`TitleMatcher::regex`].
+    Regex(regex::Regex),
+}
+
+impl TitleMatcher {
+    /// A matcher for titles matching `pattern`, ignoring case like the others.
+    pub fn regex(pattern: &str) -> std::result::Result<Self, regex::Error> {
+        let regex = regex::RegexBuilder::new(pattern).case_insensitive(true).build()?;
+        Ok(Self::Regex(regex))
+    }
+
+    pub fn matches(&self, title: &str) -> bool {
+        match self {
+            Self::Exact(text) => title.to_lowercase() == text.to_lowercase(),
+            Self::Prefix(text) => title.to_lowercase().starts_with(&text.to_lowercase()),
+            Self::Contains(text) => title.to_lowercase().contains(&text.to_lowercase()),
+            Self::Regex(regex) => regex.is_match(title),
+        }
+    }
+}
+
+/// Regexes are equal if their patterns are.
+impl PartialEq for TitleMatcher {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Exact(a), Self::Exact(b))
+            | (Self::Prefix(a), Self::Prefix(b))
+            | (Self::Contains(a), Self::Contains(b)) => a == b,
+            (Self::Regex(a), Self::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for TitleMatcher {}
+
+impl std::fmt::Display for TitleMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exact(text) => write!(f, "title {:?}", text),
+            Self::Prefix(text) => write!(f, "title starting with {:?}", text),
+            Self::Contains(text) => write!(f, "title containing {:?}", text),
+            Self::Regex(regex) => write!(f, "title matching {:?}", regex.as_str()),
+        }
+    }
+}
+
+/// A window that matched a title, as reported when the match is ambiguous.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowCandidate {
+    pub title: String,
+    /// The raw HWND, which can be passed to `IntoHWND`.
+    pub handle: usize,
+}
+
+impl std::fmt::Display for WindowCandidate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} ({:#x})", self.title, self.handle)
+    }
+}
+
+/// Picks the only window matching `matcher` from `(handle, title)` pairs, the handles being raw HWNDs. Separate
+/// from the enumeration, so it works on any list of windows.
+fn find_window_by_title(
+    windows: &[(usize, String)],
+    matcher: TitleMatcher,
+) -> super::Result<usize> {
+    let matching: Vec<_> = windows.iter().filter(|(_, title)| matcher.matches(title)).collect();
+    match matching.as_slice() {
+        [] => Err(WindowsCaptureError::NoMatchingWindow(matcher)),
+        [(handle, _)] => Ok(*handle),
+        _ => Err(WindowsCaptureError::AmbiguousWindowTitle {
+            matcher,
+            candidates: matching
+                .into_iter()
+                .map(|(handle, title)| WindowCandidate { title: title.clone(), handle: *handle })
+                .collect(),
+        }),
+    }
+}
+
+/// Creates a capture item for the one capturable window whose title matches, without any user interaction.
+/// Fails if no window or more than one window matches, the latter listing all candidates.
+pub fn create_capture_item_for_window_title(
+    matcher: TitleMatcher,
+) -> super::Result<GraphicsCaptureItem> {
+    let windows = enumerate_capturable_windows();
+    let titles: Vec<_> =
+        windows.iter().map(|window| (window.handle.0 as usize, window.name.clone())).collect();
+    let handle = find_window_by_title(&titles, matcher.clone())?;
+    let window = windows
+        .into_iter()
+        .find(|window| window.handle.0 as usize == handle)
+        .ok_or(WindowsCaptureError::NoMatchingWindow(matcher))?;
+    Ok(window.to_capture_item()?)
+}
+
+unsafe extern "system" fn enum_windows_proc(handle: HWND, lparam: LPARAM) -> BOOL {
+    let handles = unsafe { &mut *(lparam.0 as *mut Vec<HWND>) };
+    handles.push(handle);
+    true.into()
+}
+
+/// Returns all top-level windows that can be captured.
+/// Invisible, cloaked, zero-size and tool windows are filtered out.
+pub fn enumerate_capturable_windows() -> Vec<WindowInfo> {
+    let mut handles: Vec<HWND> = Vec::new();
+    if let Err(err) = unsafe {
+        EnumWindows(Some(enum_windows_proc), LPARAM(&mut handles as *mut Vec<HWND> as isize))
+    } {
+        tracing::error!("Failed to enumerate windows: {}", err);
+        return Vec::new();
+    }
+
+    handles.into_iter().filter_map(capturable_window_info).collect()
+}
+
+/// Describes a capture item. WGC doesn't expose what an item was created for, so the window or monitor is looked
+/// up by name and size, which works for items from the picker as well.
+pub(super) fn capture_item_info(item: &GraphicsCaptureItem) -> Result<CaptureItemInfo> {
+    let size = item.Size()?;
+    let size = Vector2::new(size.Width, size.Height);
+    let display_name = match item.DisplayName() {
+        Ok(name) => Some(name.to_string()),
+        Err(err) => {
+            tracing::warn!("Failed to get capture item display name: {}", err);
+            None
+        }
+    };
+
+    let (kind, native_handle) = match display_name.as_deref().filter(|name| !name.is_empty()) {
+        Some(name) => find_capture_item_handle(name, size),
+        None => (CaptureItemKind::Unknown, None),
+    };
+    Ok(CaptureItemInfo::new(display_name, kind, size, native_handle))
+}
+
+fn find_capture_item_handle(name: &str, size: Vector2<i32>) -> (CaptureItemKind, Option<usize>) {
+    let monitors = enumerate_monitors();
+    if let Some(monitor) = monitors.iter().find(|monitor| monitor.name == name) {
+        return (CaptureItemKind::Monitor, Some(monitor.handle.0 as usize));
+    }
+
+    // The window rect can include the invisible resize borders, so the size only breaks ties.
+    let windows: Vec<_> =
+        enumerate_capturable_windows().into_iter().filter(|window| window.name == name).collect();
+    let window = windows.iter().find(|window| window.size == size).or(windows.first());
+    if let Some(window) = window {
+        return (CaptureItemKind::Window, Some(window.handle.0 as usize));
+    }
+
+    // Monitors are named after the connected display by WGC, which only sometimes matches the device name.
+    let mut same_size = monitors.iter().filter(|monitor| monitor.size == size);
+    if let (Some(monitor), None) = (same_size.next(), same_size.next()) {
+        return (CaptureItemKind::Monitor, Some(monitor.handle.0 as usize));
+    }
+
+    (CaptureItemKind::Unknown, None)
+}
+
+/// Whether the window or monitor of an item still exists, going by the info looked up for it while it was being
+/// captured. WGC keeps the items of unplugged monitors around, their sessions just never deliver another frame.
+pub(super) fn capture_item_exists(
+    item: &GraphicsCaptureItem,
+    info: Option<&CaptureItemInfo>,
+) -> bool {
+    match item.Size() {
+        Ok(size) if size.Width > 0 && size.Height > 0 => {}
+        _ => return false,
+    }
+
+    match info.map(|info| (info.kind, info.native_handle)) {
+        Some((CaptureItemKind::Window, Some(handle))) => {
+            unsafe { IsWindow(Some(HWND(handle as *mut core::ffi::c_void))) }.as_bool()
+        }
+        Some((CaptureItemKind::Monitor, Some(handle))) => {
+            // Monitor handles can change along with the display topology, so look the item up again as well.
+            enumerate_monitors().iter().any(|monitor| monitor.handle.0 as usize == handle)
+                || capture_item_info(item).is_ok_and(|info| info.kind == CaptureItemKind::Monitor)
+        }
+        // Nothing to go by but the item itself.
+        _ => true,
+    }
+}
+
+/// Describes the window or monitor of an item so it can be found again with `create_capture_item_for_target`.
+/// None if it can't be told what the item was created for.
+pub(super) fn capture_target(item: &GraphicsCaptureItem) -> Result<Option<CaptureTarget>> {
+    let info = capture_item_info(item)?;
+    let Some(handle) = info.native_handle else {
+        return Ok(None);
+    };
+
+    let target = match info.kind {
+        CaptureItemKind::Monitor => enumerate_monitors()
+            .into_iter()
+            .find(|monitor| monitor.handle.0 as usize == handle)
+            .map(|monitor| monitor_target(&monitor)),
+        CaptureItemKind::Window => window_target(HWND(handle as *mut core::ffi::c_void)),
+        CaptureItemKind::Unknown => None,
+    };
+    Ok(target)
+}
+
+/// Creates a capture item for the window or monitor a target describes, e.g. the one captured in a previous run,
+/// without any user interaction.
+pub fn create_capture_item_for_target(
+    target: &CaptureTarget,
+) -> super::Result<GraphicsCaptureItem> {
+    match target {
+        CaptureTarget::Monitor { .. } => {
+            let monitors: Vec<_> = enumerate_monitors()
+                .into_iter()
+                .map(|monitor| (monitor_target(&monitor), monitor))
+                .collect();
+            match target.find_in(&monitors) {
+                Some(monitor) => Ok(monitor.to_capture_item()?),
+                None => Err(WindowsCaptureError::NoMatchingTarget(target.clone())),
+            }
+        }
+        CaptureTarget::Window { .. } => {
+            let windows: Vec<_> = enumerate_capturable_windows()
+                .into_iter()
+                .filter_map(|window| {
+                    let described = CaptureTarget::Window {
+                        title: window.name.clone(),
+                        class_name: window.class_name.clone(),
+                        exe_name: process_exe_name(window.process_id)?,
+                    };
+                    Some((described, window))
+                })
+                .collect();
+            match target.find_in(&windows) {
+                Some(window) => Ok(window.to_capture_item()?),
+                None => Err(WindowsCaptureError::NoMatchingTarget(target.clone())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn windows() -> Vec<(usize, String)> {
+        [
+            (0x10, "OBS 30.1 - Profile: Untitled"),
+            (0x20, "Untitled - Notepad"),
+            (0x30, "notes.txt - Notepad"),
+            (0x40, "Notepad"),
+        ]
+        .into_iter()
+        .map(|(handle, title)| (handle, title.to_string()))
+        .collect()
+    }
+
+    fn find(matcher: TitleMatcher) -> std::result::Result<usize, WindowsCaptureError> {
+        find_window_by_title(&windows(), matcher)
+    }
+
+    #[test]
+    fn exact_titles_match_only_the_whole_title() {
+        assert!(TitleMatcher::Exact("Notepad".into()).matches("Notepad"));
+        assert!(!TitleMatcher::Exact("Notepad".into()).matches("Untitled - Notepad"));
+        assert_eq!(find(TitleMatcher::Exact("Notepad".into())).unwrap(), 0x40);
+    }
+
+    #[test]
+    fn titles_are_matched_ignoring_case() {
+        assert!(TitleMatcher::Exact("NOTEPAD".into()).matches("notepad"));
+        assert!(TitleMatcher::Prefix("obs".into()).matches("OBS 30.1"));
+        assert!(TitleMatcher::Contains("NOTES".into()).matches("notes.txt - Notepad"));
+        assert!(TitleMatcher::regex("^UNTITLED").unwrap().matches("Untitled - Notepad"));
+        assert_eq!(find(TitleMatcher::Exact("untitled - NOTEPAD".into())).unwrap(), 0x20);
+    }
+
+    #[test]
+    fn prefixes_and_substrings_match_part_of_the_title() {
+        assert_eq!(find(TitleMatcher::Prefix("OBS".into())).unwrap(), 0x10);
+        assert_eq!(find(TitleMatcher::Contains("notes.txt".into())).unwrap(), 0x30);
+        assert!(!TitleMatcher::Prefix("Notepad".into()).matches("Untitled - Notepad"));
+    }
+
+    #[test]
+    fn regexes_match_anywhere_unless_anchored() {
+        assert_eq!(find(TitleMatcher::regex(r"OBS \d+\.\d+").unwrap()).unwrap(), 0x10);
+        assert_eq!(find(TitleMatcher::regex("^notepad$").unwrap()).unwrap(), 0x40);
+        assert!(TitleMatcher::regex("(").is_err());
+    }
+
+    #[test]
+    fn no_matching_window_is_an_error() {
+        let err = find(TitleMatcher::Contains("Calculator".into())).unwrap_err();
+        assert!(matches!(err, WindowsCaptureError::NoMatchingWindow(TitleMatcher::Contains(_))));
+        assert!(find_window_by_title(&[], TitleMatcher::Exact("Notepad".into())).is_err());
+    }
+
+    #[test]
+    fn several_matching_windows_are_listed() {
+        let err = find(TitleMatcher::regex("notepad$").unwrap()).unwrap_err();
+        let WindowsCaptureError::AmbiguousWindowTitle { matcher, candidates } = err else {
+            panic!("expected an ambiguous title, got {:?}", err);
+        };
+        assert_eq!(matcher, TitleMatcher::regex("notepad$").unwrap());
+        let handles: Vec<_> = candidates.iter().map(|candidate| candidate.handle).collect();
+        assert_eq!(handles, [0x20, 0x30, 0x40]);
+        assert_eq!(candidates[0].title, "Untitled - Notepad");
+    }
+}
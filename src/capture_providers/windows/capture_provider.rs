@@ -1,269 +1,828 @@
 use std::{
-    mem::MaybeUninit,
-    ops::Div,
+    collections::BTreeMap,
+    ops::Range,
     sync::{
-        Arc,
-        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, Weak,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc,
     },
+    time::{Duration, Instant},
 };
 
-use tokio::sync::RwLock;
+use futures::{
+    FutureExt,
+    future::{self, BoxFuture},
+};
 use windows::{
-    Foundation::TypedEventHandler,
+    Foundation::{TimeSpan, TypedEventHandler},
     Graphics::{Capture::*, DirectX::Direct3D11::*},
-    Win32::{Graphics::Direct3D11::*, System::WinRT::Direct3D11::IDirect3DDxgiInterfaceAccess},
+    Win32::Graphics::{
+        Direct3D11::*,
+        Dxgi::Common::{DXGI_FORMAT, DXGI_FORMAT_R8G8B8A8_UNORM},
+    },
     core::*,
 };
 
-use crate::capture_providers::{
-    CaptureProvider,
-    shared::{BytesPerPixel, CaptureFramerate, Frame, PixelFormat, ToDirectXPixelFormat, Vector2},
-    windows::{WindowsCaptureStream, d3d11_utils::read_texture, error::WindowsCaptureError},
+use crate::{
+    capture_providers::{
+        CaptureError, CaptureProvider,
+        shared::{
+            CaptureEvent, CaptureFramerate, CaptureItemInfo, CaptureStats, CaptureTarget,
+            ConversionPolicy, EndReason, Frame, FrameTiming, FrameTracer, GpuFrame, PixelFormat,
+            Rect, ScaleMode, Stage, StreamId, StreamOptions, ToDirectXPixelFormat, Vector2,
+        },
+        windows::{
+            SourceId, WindowsCaptureStream, WindowsTextureStream,
+            adapters::{AdapterInfo, AdapterSelection},
+            advanced_color::{frame_pool_format, texture_pixel_format},
+            buffer_pool::BufferPool,
+            capabilities::wgc_capabilities,
+            capture_items::{capture_item_exists, capture_item_info, capture_target},
+            capture_source::{
+                CaptureSource, FrameCallback, SessionSettings, apply_border_required,
+            },
+            capture_stats::CaptureCounters,
+            capture_stream::StreamToken,
+            d3d11_utils::{
+                detect_device_loss, frame_to_texture, native_to_winrt_d3d11device, read_texture,
+                winrt_to_native_d3d11device,
+            },
+            device_recovery::DeviceRecovery,
+            error::WindowsCaptureError,
+            frame_channel::{FrameSender, SendError, frame_channel},
+            frame_limiter::FrameRateLimiter,
+            frame_observer::{FrameObserver, FrameObservers, ObserverToken},
+            frame_sink::{FrameSink, SinkDelivery, SinkSlot},
+            gpu_scaler::GpuScaler,
+            latest_frame::LatestFrameHandle,
+            qpc_clock::QpcClock,
+            shared_texture::SharedTextureRing,
+            source_watchdog::{SourceProgress, SourceWatchdog},
+            staging_ring::{Readback, StagingRing},
+            unchanged_filter::UnchangedFrameFilter,
+        },
+    },
+    utils::{
+        com_thread::{Apartment, ComThread},
+        image_utils::{DEFAULT_SDR_WHITE_LEVEL, convert_image, rgba16f_to_rgba8},
+        unsafe_send_wrapper::UnsafeSendWrapper,
+    },
 };
 
+/// How captured textures are copied into CPU memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadbackMode {
+    /// Rows are repacked so that the stride equals `width * bytes_per_pixel`.
+    #[default]
+    Tight,
+    /// The mapped data is copied in one go including row padding, with the stride set to the D3D11 row pitch.
+    Strided,
+}
+
+/// Options for how streams process frames. Captured when a stream is created.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FrameOptions {
+    readback_mode: ReadbackMode,
+    output_format: PixelFormat,
+    conversion_policy: ConversionPolicy,
+    scale: ScaleMode,
+    /// Taken from the provider for every frame, see `set_crop_region`.
+    crop: Option<Rect<i32>>,
+    skip_unchanged_frames: bool,
+    unchanged_pixel_threshold: u64,
+    unchanged_keepalive: Duration,
+    /// Brightness in nits that becomes white when HDR frames are tone mapped.
+    sdr_white_level: f32,
+    /// Idle readback buffers kept per stream.
+    buffer_pool_size: usize,
+    /// Most bytes that idle readback buffers of a stream may hold.
+    buffer_pool_max_bytes: usize,
+    /// Staging textures per stream, see `set_readback_depth`.
+    readback_depth: usize,
+    /// Whether only the rows that changed are read back and sent, see `set_delta_mode`.
+    delta_mode: bool,
+    /// Whether eager RGBA8 conversion happens on the GPU, see `set_gpu_conversion`.
+    gpu_conversion: bool,
+}
+
+impl Default for FrameOptions {
+    fn default() -> Self {
+        Self {
+            readback_mode: ReadbackMode::default(),
+            output_format: PixelFormat::RGBA8,
+            conversion_policy: ConversionPolicy::default(),
+            scale: ScaleMode::Native,
+            crop: None,
+            skip_unchanged_frames: false,
+            unchanged_pixel_threshold: 0,
+            unchanged_keepalive: Duration::from_secs(1),
+            sdr_white_level: DEFAULT_SDR_WHITE_LEVEL,
+            buffer_pool_size: WindowsCaptureProvider::DEFAULT_BUFFER_POOL_SIZE,
+            buffer_pool_max_bytes: WindowsCaptureProvider::DEFAULT_BUFFER_POOL_MAX_BYTES,
+            readback_depth: WindowsCaptureProvider::DEFAULT_READBACK_DEPTH,
+            delta_mode: false,
+            gpu_conversion: true,
+        }
+    }
+}
+
+/// Everything the FrameArrived handler of a source needs, as it can't hold on to the provider itself. Streams of
+/// a source with the same frame options share one, so every frame is only read back once however many streams
+/// receive it.
+struct PipelineContext {
+    source: SourceId,
+    options: FrameOptions,
+    crop_region: Arc<Mutex<Option<Rect<i32>>>>,
+    unchanged_filter: Option<UnchangedFrameFilter>,
+    /// Created on the first scaled frame, and recreated when the content size changes.
+    scaler: Mutex<Option<GpuScaler>>,
+    /// Set while the source is paused, see `CaptureSource::pause`.
+    paused: Arc<AtomicBool>,
+    /// Starts out as `options.gpu_conversion`, and is cleared once converting on the GPU failed.
+    gpu_conversion: AtomicBool,
+    /// Copies of frames the GPU might still be working on, with the streams they are for.
+    staging: Mutex<StagingRing<(PendingFrame, Vec<Arc<Subscriber>>)>>,
+    counters: Arc<CaptureCounters>,
+    trace_frames: Arc<AtomicBool>,
+    frame_tracer: Option<Arc<dyn FrameTracer>>,
+    observers: Arc<FrameObservers>,
+    clock: QpcClock,
+    /// Shared by the streams, so they see the same sequence numbers for the same frames.
+    next_sequence: AtomicU64,
+    /// Weak, as the resources own the handler this context lives in.
+    resources: Weak<Mutex<CaptureResources>>,
+    recovery: Arc<DeviceRecovery>,
+    failed: AtomicBool,
+    buffer_pool: BufferPool,
+    delta: Mutex<DeltaState>,
+    subscribers: Mutex<Vec<Arc<Subscriber>>>,
+}
+
+/// What a pipeline with delta frames remembers between frames, so every delta covers all changes since the frame
+/// before it.
+#[derive(Debug, Default)]
+struct DeltaState {
+    /// Dirty regions of frames that weren't read back, in pixels of the item.
+    skipped_dirty: Vec<Rect<i32>>,
+    /// Whether a frame that wasn't read back had no dirty regions to tell what changed.
+    skipped_unknown: bool,
+    /// Generation and output size of the last frame queued for readback.
+    last_frame: Option<(u64, Vector2<i32>)>,
+    /// Delta frames queued since the last full one.
+    since_full: u32,
+}
+
+impl PipelineContext {
+    /// The streams that are still being consumed. Streams whose receiver was dropped are removed for good.
+    fn live_subscribers(&self) -> Vec<Arc<Subscriber>> {
+        let mut subscribers =
+            self.subscribers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        subscribers.retain(|subscriber| !subscriber.tx.is_closed());
+        subscribers.clone()
+    }
+}
+
+impl std::fmt::Debug for PipelineContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PipelineContext").field("source", &self.source).finish_non_exhaustive()
+    }
+}
+
+/// A stream receiving the frames of a pipeline, at its own framerate and with its own queue.
+struct Subscriber {
+    id: u64,
+    tx: FrameSender,
+    frame_limiter: FrameRateLimiter,
+    /// Thins out the frames the limiter lets through, see `StreamOptions::target_framerate`.
+    decimator: Option<FrameRateLimiter>,
+    /// Whether the last frame failed to process, so a run of failures is only reported once.
+    error_reported: AtomicBool,
+    /// Generation and size of the last frame, to report `Started` and `Resized`.
+    last_frame: Mutex<Option<(u64, Vector2<i32>)>>,
+    /// Whether the stream missed a frame, so it can't apply the next delta and needs a full frame.
+    needs_full_frame: AtomicBool,
+    sink: Option<SinkSlot>,
+}
+
+impl Subscriber {
+    /// Whether the stream skips the frame of the given timestamp, either for its framerate or for its target
+    /// framerate. Frames that aren't skipped count towards both.
+    fn should_skip(&self, timestamp: i64) -> bool {
+        self.frame_limiter.should_skip(timestamp)
+            || self.decimator.as_ref().is_some_and(|decimator| decimator.should_skip(timestamp))
+    }
+}
+
+/// A frame cropped and scaled on the GPU, ready to be copied into a staging texture.
+struct PreparedFrame {
+    texture: ID3D11Texture2D,
+    /// The part of `texture` to copy, `None` for all of it. Crops that aren't scaled are copied straight out of
+    /// the captured texture, which the staging texture is sized for.
+    region: Option<Rect<i32>>,
+    staging_desc: D3D11_TEXTURE2D_DESC,
+    device: ID3D11Device,
+    context: ID3D11DeviceContext,
+    pending: PendingFrame,
+}
+
+/// What is known about a frame before its data is read back.
+struct PendingFrame {
+    /// When the FrameArrived handler fired for it.
+    arrived: Instant,
+    timing: FrameTiming,
+    dirty_regions: Vec<Rect<i32>>,
+    capture_format: PixelFormat,
+    output_size: Vector2<i32>,
+    /// The part of the item the frame shows, in pixels of the item.
+    source: Rect<i32>,
+}
+
+/// The device and every source captured with it. Shared with the device recovery thread, so everything can be
+/// rebuilt in place.
+#[derive(Debug)]
+struct CaptureResources {
+    device: IDirect3DDevice, /* Free-threaded object */
+    /// Where a new device is created after the device was lost.
+    adapter: AdapterSelection,
+    sources: BTreeMap<SourceId, CaptureSource>,
+    session_settings: SessionSettings,
+    /// Number of frame pool buffers of sources added from now on.
+    pipeline_depth: i32,
+    next_source_id: u64,
+    /// The readback pipelines of all sources with the id of their FrameArrived handler, to add streams to. The
+    /// strong reference is held by the handler, so pipelines go away once their source stops.
+    pipelines: Vec<(u64, Weak<PipelineContext>)>,
+    /// Texture streams have a FrameArrived handler each instead of a pipeline.
+    texture_streams: Vec<TextureSubscriber>,
+    next_stream_id: u64,
+}
+
+/// A texture stream, with the id of its FrameArrived handler.
+#[derive(Debug)]
+struct TextureSubscriber {
+    token: StreamToken,
+    handler: u64,
+    frame_limiter: Arc<FrameRateLimiter>,
+}
+
+impl CaptureResources {
+    fn source_mut(&mut self, id: SourceId) -> super::Result<&mut CaptureSource> {
+        self.sources.get_mut(&id).ok_or(WindowsCaptureError::UnknownSource(id))
+    }
+}
+
+// Same as the provider, the COM objects are agile but hold raw pointers.
+unsafe impl Send for CaptureResources {}
+
+fn lock_resources(resources: &Mutex<CaptureResources>) -> MutexGuard<'_, CaptureResources> {
+    resources.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn same_tracer(a: &Option<Arc<dyn FrameTracer>>, b: &Option<Arc<dyn FrameTracer>>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Runs `f` on the shared multithreaded COM thread. Sessions and frame pools are created there, so the
+/// apartment of the calling thread doesn't matter.
+fn on_com_thread<R>(f: impl FnOnce() -> super::Result<R> + Send + 'static) -> super::Result<R>
+where
+    R: Send + 'static,
+{
+    ComThread::shared(Apartment::MultiThreaded)?.run_blocking(f)?
+}
+
+/// Same as `on_com_thread`, but waits without blocking the calling thread.
+async fn on_com_thread_async<R>(
+    f: impl FnOnce() -> super::Result<R> + Send + 'static,
+) -> super::Result<R>
+where
+    R: Send + 'static,
+{
+    ComThread::shared(Apartment::MultiThreaded)?.run_on(f).await?
+}
+
+/// Starts every source that isn't running yet, see `WindowsCaptureProvider::start_sources`.
+fn start_all(resources: &Mutex<CaptureResources>) -> super::Result<()> {
+    let mut resources = lock_resources(resources);
+    if resources.sources.is_empty() {
+        tracing::error!("No capture item set!");
+        return Err(WindowsCaptureError::NoCaptureItem);
+    }
+    if resources.sources.values().all(CaptureSource::is_capturing) {
+        return match resources.sources.values().any(CaptureSource::is_paused) {
+            true => Err(WindowsCaptureError::Paused),
+            false => Err(WindowsCaptureError::AlreadyCapturing),
+        };
+    }
+
+    let settings = resources.session_settings;
+    for source in resources.sources.values_mut().filter(|source| !source.is_capturing()) {
+        source.start(settings)?;
+    }
+    Ok(())
+}
+
+/// Stops every running source, see `WindowsCaptureProvider::stop_sources`.
+fn stop_all(resources: &Mutex<CaptureResources>) -> super::Result<()> {
+    let mut resources = lock_resources(resources);
+    if !resources.sources.values().any(CaptureSource::is_capturing) {
+        return Err(WindowsCaptureError::NotCapturing);
+    }
+
+    for source in resources.sources.values_mut().filter(|source| source.is_capturing()) {
+        source.stop()?;
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct WindowsCaptureProvider {
-    device: IDirect3DDevice,                        /* Free-threaded object */
-    frame_pool: Option<Direct3D11CaptureFramePool>, /* Free-threaded object */
-    capture_item: Option<GraphicsCaptureItem>,      /* Free-threaded object */
-    session: Option<GraphicsCaptureSession>,        /* Free-threaded object */
-    staging_texture: Arc<RwLock<Option<ID3D11Texture2D>>>, /* Free-threaded object */
-
-    active_handlers: Vec<i64>,
-    capturing: bool,
+    resources: Arc<Mutex<CaptureResources>>,
+    /// The source used by the single item API of `CaptureProvider`.
+    default_source: Option<SourceId>,
+    frame_options: FrameOptions,
+    /// Queue capacity of streams created without `StreamOptions`, see `set_channel_capacity`.
+    channel_capacity: usize,
+    crop_region: Arc<Mutex<Option<Rect<i32>>>>,
+    counters: Arc<CaptureCounters>,
+    trace_frames: Arc<AtomicBool>,
+    frame_tracer: Option<Arc<dyn FrameTracer>>,
+    recovery: Arc<DeviceRecovery>,
+    watchdog: Arc<SourceWatchdog>,
+    observers: Arc<FrameObservers>,
+    /// Tokens of dropped streams, see `detach_closed_streams`.
+    closed_tx: mpsc::Sender<StreamToken>,
+    closed_rx: Mutex<mpsc::Receiver<StreamToken>>,
 }
 
 impl WindowsCaptureProvider {
-    const FRAME_COUNT: i32 = 2;
-    const PIXEL_FORMAT: PixelFormat = PixelFormat::BGRA8;
+    /// Frame pool buffers per source. Two let WGC render the next frame while the last one is being read.
+    pub(super) const DEFAULT_PIPELINE_DEPTH: usize = 2;
+    /// Only as many buffers as are in flight at once are ever needed, see `BufferPool`.
+    pub(super) const DEFAULT_BUFFER_POOL_SIZE: usize = 8;
+    /// A few frames of 4K RGBA16F.
+    pub(super) const DEFAULT_BUFFER_POOL_MAX_BYTES: usize = 256 * 1024 * 1024;
+    /// Two staging textures let the GPU finish the copy of one frame while the handler returns, at the cost of
+    /// delivering it with the next frame when the GPU is busy.
+    pub(super) const DEFAULT_READBACK_DEPTH: usize = 2;
+    pub(super) const PIXEL_FORMAT: PixelFormat = PixelFormat::BGRA8;
+    const SNAPSHOT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+    /// Delta frames changing more of the rows than this are sent in full, which is barely larger.
+    const DELTA_MAX_ROW_SHARE: f64 = 0.6;
+    /// With delta frames, a full frame is still sent at least this often, so consumers catch up on anything a
+    /// delta was missing.
+    const DELTA_FULL_FRAME_INTERVAL: u32 = 120;
+    /// Dirty regions kept of frames that weren't read back, before the next frame is sent in full instead.
+    const DELTA_MAX_SKIPPED_REGIONS: usize = 256;
 
     pub fn new(device: IDirect3DDevice, item: Option<GraphicsCaptureItem>) -> Self {
-        Self {
+        let resources = CaptureResources {
             device,
-            frame_pool: None,
-            capture_item: item,
-            session: None,
-            staging_texture: Arc::new(RwLock::new(None)),
-            active_handlers: Vec::new(),
-            capturing: false,
+            adapter: AdapterSelection::Default,
+            sources: BTreeMap::new(),
+            session_settings: SessionSettings::default(),
+            pipeline_depth: Self::DEFAULT_PIPELINE_DEPTH as i32,
+            next_source_id: 0,
+            pipelines: Vec::new(),
+            texture_streams: Vec::new(),
+            next_stream_id: 0,
+        };
+        let (closed_tx, closed_rx) = mpsc::channel();
+        let mut provider = Self {
+            resources: Arc::new(Mutex::new(resources)),
+            default_source: None,
+            frame_options: FrameOptions::default(),
+            channel_capacity: StreamOptions::default().capacity,
+            crop_region: Arc::new(Mutex::new(None)),
+            counters: Arc::new(CaptureCounters::default()),
+            trace_frames: Arc::new(AtomicBool::new(false)),
+            frame_tracer: None,
+            recovery: Arc::new(DeviceRecovery::new()),
+            watchdog: Arc::new(SourceWatchdog::new()),
+            observers: Arc::new(FrameObservers::new()),
+            closed_tx,
+            closed_rx: Mutex::new(closed_rx),
+        };
+
+        let watched = Arc::downgrade(&provider.resources);
+        let recovery = provider.recovery.clone();
+        let mut progress = BTreeMap::new();
+        provider.watchdog.spawn(move |watchdog, displays_changed| {
+            Self::watch_sources(&watched, &recovery, watchdog, &mut progress, displays_changed)
+        });
+
+        if let Some(item) = item
+            && let Err(err) = provider.set_capture_item(item)
+        {
+            tracing::error!("Failed to set initial capture item: {}", err);
         }
+        provider
     }
 
-    fn process_frame(
-        frame: Direct3D11CaptureFrame,
-        staging_tex_arc: Arc<RwLock<Option<ID3D11Texture2D>>>,
-        tx: tokio::sync::mpsc::Sender<Frame>,
-    ) -> super::Result<()> {
-        // Direct3D11CaptureFrame → IDirect3DSurface
-        let surface = match frame.Surface() {
-            Ok(surface) => surface,
-            Err(err) => {
-                tracing::error!("Failed to get surface: {}", err);
-                return Ok(());
-            }
-        };
+    /// Sets the number of frame pool buffers. Takes effect for sources added after this call, so the builder
+    /// sets it before the first one.
+    pub(super) fn set_pipeline_depth(&mut self, depth: i32) {
+        tracing::debug!("Setting pipeline depth: {}", depth);
+        lock_resources(&self.resources).pipeline_depth = depth;
+    }
 
-        // IDirect3DSurface → IDirect3DDxgiInterfaceAccess
-        let access: IDirect3DDxgiInterfaceAccess = match surface.cast() {
-            Ok(access) => access,
-            Err(err) => {
-                tracing::error!("Failed to cast surface to access: {}", err);
-                return Ok(());
-            }
-        };
+    /// Sets how many frames streams created without `StreamOptions` can queue. Takes effect for streams created
+    /// after this call.
+    pub(super) fn set_channel_capacity(&mut self, capacity: usize) {
+        tracing::debug!("Setting channel capacity: {}", capacity);
+        self.channel_capacity = capacity;
+    }
 
-        // IDirect3DDxgiInterfaceAccess → ID3D11Texture2D
-        let texture: ID3D11Texture2D = match unsafe { access.GetInterface() } {
-            Ok(texture) => texture,
-            Err(err) => {
-                tracing::error!("Failed to cast access to texture: {}", err);
-                return Ok(());
-            }
+    /// Sets how many idle readback buffers each stream keeps. Takes effect for streams created after this call.
+    pub(super) fn set_buffer_pool_size(&mut self, size: usize) {
+        tracing::debug!("Setting buffer pool size: {}", size);
+        self.frame_options.buffer_pool_size = size;
+    }
+
+    /// Sets how many bytes the idle readback buffers of each stream may hold. Takes effect for streams created
+    /// after this call.
+    pub(super) fn set_buffer_pool_max_bytes(&mut self, max_bytes: usize) {
+        tracing::debug!("Setting buffer pool limit: {} bytes", max_bytes);
+        self.frame_options.buffer_pool_max_bytes = max_bytes;
+    }
+
+    /// Sends delta frames, which only hold the rows that changed since the previous frame, rather than reading
+    /// back and sending every frame whole. Consumers patch them into the previous frame with
+    /// `Frame::apply_delta`. Frames are sent in full when their dirty regions are unknown or cover most of the
+    /// frame, after a resize, when a stream missed a frame, and every now and then regardless. Streams with a
+    /// lower framerate than the source miss frames all the time, so they get mostly full frames. Planar output
+    /// formats are always sent in full. Takes effect for streams created after this call.
+    pub fn set_delta_mode(&mut self, enabled: bool) {
+        tracing::debug!("Setting delta mode: {}", enabled);
+        self.frame_options.delta_mode = enabled;
+    }
+
+    /// Sets whether eager conversion to RGBA8 reorders the channels on the GPU while the frame is copied for
+    /// readback, instead of on the capture thread afterwards. Falls back to the capture thread for the rest of
+    /// the stream if the GPU can't do it. Defaults to on. Takes effect for streams created after this call.
+    pub fn set_gpu_conversion(&mut self, enabled: bool) {
+        tracing::debug!("Setting GPU conversion: {}", enabled);
+        self.frame_options.gpu_conversion = enabled;
+    }
+
+    /// Sets how frames are read back from the GPU. Takes effect for streams created after this call.
+    pub fn set_readback_mode(&mut self, mode: ReadbackMode) {
+        tracing::debug!("Setting readback mode: {:?}", mode);
+        self.frame_options.readback_mode = mode;
+    }
+
+    /// Sets the pixel format of frames coming off streams, converted on the capture thread unless the conversion
+    /// is deferred, see `set_conversion_policy`. Defaults to RGBA8.
+    /// Planar formats are always tightly packed, regardless of the readback mode.
+    /// Also picks the format of the frame pools of sources added afterwards, RGBA16F for RGBA16F output and BGRA8
+    /// for everything else, regardless of the display. Streams of a source captured in RGBA16F are tone mapped to
+    /// any other format, see `set_sdr_white_level`.
+    /// Takes effect for streams created after this call.
+    pub fn set_output_format(&mut self, format: PixelFormat) {
+        tracing::debug!("Setting output format: {:?}", format);
+        self.frame_options.output_format = format;
+    }
+
+    /// Sets how many frames each stream can have in flight between the GPU copy and readback. 1 reads every
+    /// frame back as soon as it arrives, waiting for the GPU, which gives the lowest latency. More let the handler
+    /// move on while the GPU is busy. Takes effect for streams created after this call.
+    pub(super) fn set_readback_depth(&mut self, depth: usize) {
+        tracing::debug!("Setting readback depth: {}", depth);
+        self.frame_options.readback_depth = depth;
+    }
+
+    /// Sets whether RGBA8 output is converted on the capture thread or left to consumers. Defaults to deferred.
+    /// Takes effect for streams created after this call.
+    pub fn set_conversion_policy(&mut self, policy: ConversionPolicy) {
+        tracing::debug!("Setting conversion policy: {:?}", policy);
+        self.frame_options.conversion_policy = policy;
+    }
+
+    /// Sets the brightness in nits that HDR frames are tone mapped to white at, see `rgba16f_to_rgba8`.
+    /// Match it to the SDR content brightness in the Windows display settings for SDR content to look unchanged.
+    /// Defaults to 80 nits. Takes effect for streams created after this call.
+    pub fn set_sdr_white_level(&mut self, nits: f32) {
+        tracing::debug!("Setting SDR white level: {} nits", nits);
+        self.frame_options.sdr_white_level = nits;
+    }
+
+    /// Sets whether frames without changes are skipped before readback, saving both the GPU copy and the map.
+    /// Takes effect for streams created after this call.
+    pub fn set_skip_unchanged_frames(&mut self, skip: bool) {
+        tracing::debug!("Setting skip unchanged frames: {}", skip);
+        self.frame_options.skip_unchanged_frames = skip;
+    }
+
+    /// Frames whose total dirty area is below this number of pixels count as unchanged. Defaults to 0, meaning
+    /// only frames without any dirty regions are skipped. Takes effect for streams created after this call.
+    pub fn set_unchanged_pixel_threshold(&mut self, pixels: u64) {
+        self.frame_options.unchanged_pixel_threshold = pixels;
+    }
+
+    /// Sets how often a frame is delivered even if nothing changed, so consumers don't appear frozen.
+    /// Defaults to one second. Takes effect for streams created after this call.
+    pub fn set_unchanged_keepalive(&mut self, keepalive: Duration) {
+        self.frame_options.unchanged_keepalive = keepalive;
+    }
+
+    /// Sets whether the capture pipeline is rebuilt on a new D3D11 device when the current one is lost,
+    /// e.g. after a driver reset or a GPU switch. Streams keep going after a short gap. Defaults to true.
+    pub fn set_auto_recover(&mut self, enabled: bool) {
+        tracing::debug!("Setting auto recover: {}", enabled);
+        self.recovery.set_enabled(enabled);
+    }
+
+    /// Sets how long a running source can go without frames before its session is considered stalled, which
+    /// happens after the system sleeps or displays are plugged in or out. Stalled sessions are recreated if their
+    /// window or monitor still exists, otherwise their streams end with `EndReason::SourceLost`. Changes to the
+    /// displays are checked for right away. `None` only reacts to display changes, defaults to 5 seconds.
+    pub fn set_stall_timeout(&mut self, timeout: Option<Duration>) {
+        tracing::debug!("Setting stall timeout: {:?}", timeout);
+        self.watchdog.set_stall_timeout(timeout);
+    }
+
+    /// Sets what gets told when frames are read back and queued, and when streams hand them out, see
+    /// `FrameTracer`. Takes effect for streams created after this call.
+    pub fn set_frame_tracer(&mut self, tracer: Option<Arc<dyn FrameTracer>>) {
+        self.frame_tracer = tracer;
+    }
+
+    /// Describes the item captured by a source.
+    pub fn source_info(&self, id: SourceId) -> super::Result<CaptureItemInfo> {
+        let capture_item = {
+            let resources = lock_resources(&self.resources);
+            let source =
+                resources.sources.get(&id).ok_or(WindowsCaptureError::UnknownSource(id))?;
+            source.capture_item.clone()
         };
+        // Looking up the window or monitor enumerates them, so the lock isn't held for it.
+        Ok(capture_item_info(&capture_item)?)
+    }
 
-        let size = match frame.ContentSize() {
-            Ok(size) => size,
-            Err(err) => {
-                tracing::error!("Failed to get content size: {}", err);
-                return Ok(());
-            }
+    /// Describes the window or monitor of the default source so it can be captured again later, e.g. in the next
+    /// run, with `create_capture_item_for_target`. `None` if no item is set or it can't be told what it is.
+    pub fn capture_target(&self) -> Option<CaptureTarget> {
+        let capture_item = {
+            let resources = lock_resources(&self.resources);
+            let source = resources.sources.get(&self.default_source?)?;
+            source.capture_item.clone()
         };
+        capture_target(&capture_item).unwrap_or_else(|err| {
+            tracing::warn!("Failed to describe the capture target: {}", err);
+            None
+        })
+    }
 
-        tracing::trace!("Frame: {} x {}, ptr={:?}", size.Width, size.Height, texture.as_raw());
+    /// Adds another window or monitor to capture alongside the existing ones.
+    /// It is started with the next `start_capture`, or right away through `start_source`.
+    pub fn add_source(&mut self, capture_item: GraphicsCaptureItem) -> super::Result<SourceId> {
+        tracing::info!(
+            "Adding capture source: {}",
+            capture_item.DisplayName().unwrap_or("<no name>".into())
+        );
 
-        let device = unsafe {
-            match texture.GetDevice() {
-                Ok(device) => device,
-                Err(err) => {
-                    tracing::error!("Failed to get device: {}", err);
-                    return Ok(());
-                }
+        let resources = self.resources.clone();
+        let capture_item = UnsafeSendWrapper(capture_item);
+        let pixel_format = frame_pool_format(self.frame_options.output_format);
+        on_com_thread(move || {
+            let mut resources = lock_resources(&resources);
+            let source = CaptureSource::new(
+                &resources.device,
+                capture_item.take_inner(),
+                pixel_format,
+                resources.pipeline_depth,
+            )?;
+            let id = SourceId(resources.next_source_id);
+            resources.next_source_id += 1;
+            resources.sources.insert(id, source);
+            Ok(id)
+        })
+    }
+
+    /// Stops and removes a source. Its streams simply stop receiving frames.
+    pub fn remove_source(&mut self, id: SourceId) -> super::Result<()> {
+        tracing::info!("Removing capture source: {:?}", id);
+        let removed = lock_resources(&self.resources).sources.remove(&id);
+        if removed.is_none() {
+            return Err(WindowsCaptureError::UnknownSource(id));
+        }
+        if self.default_source == Some(id) {
+            self.default_source = None;
+        }
+        Ok(())
+    }
+
+    pub fn start_source(&mut self, id: SourceId) -> super::Result<()> {
+        self.detach_closed_streams();
+        let resources = self.resources.clone();
+        on_com_thread(move || {
+            let mut resources = lock_resources(&resources);
+            let settings = resources.session_settings;
+            resources.source_mut(id)?.start(settings)
+        })
+    }
+
+    pub fn stop_source(&mut self, id: SourceId) -> super::Result<()> {
+        self.detach_closed_streams();
+        lock_resources(&self.resources).source_mut(id)?.stop()
+    }
+
+    fn default_source(&self) -> super::Result<SourceId> {
+        match self.default_source {
+            Some(id) => Ok(id),
+            None => {
+                tracing::error!("No capture item set!");
+                Err(WindowsCaptureError::NoCaptureItem)
             }
-        };
+        }
+    }
 
-        let desc = unsafe {
-            let mut d = std::mem::zeroed::<D3D11_TEXTURE2D_DESC>();
-            texture.GetDesc(&mut d);
-            d.BindFlags = 0;
-            d.MiscFlags = 0;
-            d.CPUAccessFlags = D3D11_CPU_ACCESS_READ.0 as u32;
-            d.Usage = D3D11_USAGE_STAGING;
-            d.MipLevels = 1;
-            d.ArraySize = 1;
-            d.SampleDesc.Count = 1;
-            d.SampleDesc.Quality = 0;
-            d
-        };
+    /// Creates a stream of frames that stay on the GPU, skipping the readback into CPU memory entirely.
+    /// Every frame carries a shared handle that can be opened on any D3D11 device.
+    pub fn create_texture_stream(
+        &mut self,
+        framerate: CaptureFramerate,
+    ) -> super::Result<WindowsTextureStream> {
+        let id = self.default_source()?;
+        self.create_texture_stream_for(id, framerate)
+    }
 
-        let staging_tex = { staging_tex_arc.blocking_read().clone() };
-        let staging_tex = match staging_tex {
-            Some(staging_tex) => staging_tex,
-            None => unsafe {
-                let mut tex = MaybeUninit::<Option<ID3D11Texture2D>>::uninit();
-                match device.CreateTexture2D(&desc, None, Some(tex.as_mut_ptr())) {
-                    Ok(_) => (),
-                    Err(err) => {
-                        tracing::error!("Failed to create staging texture: {}", err);
+    /// Creates a texture stream of the default source with custom queueing options.
+    pub fn create_texture_stream_with(
+        &mut self,
+        framerate: CaptureFramerate,
+        stream_options: StreamOptions,
+    ) -> super::Result<WindowsTextureStream> {
+        let id = self.default_source()?;
+        self.create_texture_stream_for_with(id, framerate, stream_options)
+    }
+
+    /// Creates a texture stream for a specific source, see `create_texture_stream`.
+    pub fn create_texture_stream_for(
+        &mut self,
+        id: SourceId,
+        framerate: CaptureFramerate,
+    ) -> super::Result<WindowsTextureStream> {
+        let options = StreamOptions { capacity: self.channel_capacity, ..StreamOptions::default() };
+        self.create_texture_stream_for_with(id, framerate, options)
+    }
+
+    /// Creates a texture stream for a specific source with custom queueing options. Shared textures are
+    /// recycled, so frames queued for longer than it takes to capture a few more may show a newer frame.
+    pub fn create_texture_stream_for_with(
+        &mut self,
+        id: SourceId,
+        framerate: CaptureFramerate,
+        stream_options: StreamOptions,
+    ) -> super::Result<WindowsTextureStream> {
+        self.detach_closed_streams();
+        let (tx, rx) = frame_channel(stream_options);
+        let texture_ring = Arc::new(std::sync::Mutex::new(None));
+        let resources = Arc::downgrade(&self.resources);
+        let recovery = self.recovery.clone();
+        let frame_limiter = Arc::new(FrameRateLimiter::new(framerate));
+        let decimator = stream_options.target_framerate.map(FrameRateLimiter::new);
+        let counters = self.counters.clone();
+
+        let limiter = frame_limiter.clone();
+        let callback: FrameCallback =
+            Arc::new(move |frame: super::Result<Direct3D11CaptureFrame>, generation: u64| {
+                if recovery.in_progress() {
+                    return;
+                }
+                let result = frame.and_then(|frame| {
+                    let timestamp = frame.SystemRelativeTime().map(|time| time.Duration);
+                    let skip = |timestamp| {
+                        limiter.should_skip(timestamp)
+                            || decimator
+                                .as_ref()
+                                .is_some_and(|decimator| decimator.should_skip(timestamp))
+                    };
+                    if timestamp.is_ok_and(skip) {
+                        counters.frames_skipped_rate_limit.fetch_add(1, Ordering::Relaxed);
                         return Ok(());
                     }
+                    Self::process_texture_frame(frame, generation, &texture_ring, &tx, &counters)
+                });
+
+                match result {
+                    Ok(()) => (),
+                    Err(err @ WindowsCaptureError::DeviceLost(_)) => {
+                        // The shared textures belong to the lost device.
+                        *texture_ring.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) =
+                            None;
+                        if !Self::try_recover(&recovery, &resources) {
+                            tracing::error!("Failed to process texture frame: {}", err);
+                        }
+                    }
+                    Err(err) => tracing::error!("Failed to process texture frame: {}", err),
                 }
+            });
 
-                let staging_tex = tex.assume_init().expect("Failed to create staging texture!");
-                *staging_tex_arc.blocking_write() = Some(staging_tex.clone());
-                staging_tex
-            },
-        };
+        let mut resources = lock_resources(&self.resources);
+        let token = StreamToken { source: id, id: resources.next_stream_id };
+        resources.next_stream_id += 1;
+        let handler = resources.source_mut(id)?.register_frame_arrived(callback)?;
+        resources.texture_streams.push(TextureSubscriber { token, handler, frame_limiter });
+        Self::refresh_update_interval(&mut resources, id)?;
 
-        let context = unsafe {
-            match device.GetImmediateContext() {
-                Ok(context) => context,
-                Err(err) => {
-                    tracing::error!("Failed to get immediate context: {}", err);
-                    return Ok(());
-                }
-            }
-        };
+        Ok(WindowsTextureStream::new(rx, token, self.closed_tx.clone()))
+    }
 
-        let data = read_texture(
-            &context,
-            texture,
-            staging_tex,
-            &desc,
-            Self::PIXEL_FORMAT.bytes_per_pixel(),
-        )
-        .expect("Unable to read texture into byte array!");
+    fn process_texture_frame(
+        frame: Direct3D11CaptureFrame,
+        generation: u64,
+        texture_ring: &std::sync::Mutex<Option<SharedTextureRing>>,
+        tx: &FrameSender<GpuFrame>,
+        counters: &CaptureCounters,
+    ) -> super::Result<()> {
+        let texture = frame_to_texture(&frame)?;
+        let size = frame.ContentSize()?;
+        let timestamp = frame.SystemRelativeTime()?.Duration;
 
-        let sys_time = match frame.SystemRelativeTime() {
-            Ok(time) => time,
-            Err(err) => {
-                tracing::error!("Failed to get system relative time: {}", err);
-                return Ok(());
-            }
-        };
+        let device = unsafe { texture.GetDevice()? };
+        let context = unsafe { device.GetImmediateContext()? };
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { texture.GetDesc(&mut desc) };
 
-        let dirty_regions = match frame.DirtyRegions() {
-            Ok(regions) => regions.into_iter().map(Into::into).collect(),
-            Err(err) => {
-                tracing::warn!("Failed to get dirty regions: {}", err);
-                Vec::new() // Dirty regions are currently not used, and should generally be safe to skip in this case.
+        let mut texture_ring = texture_ring.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if !texture_ring.as_ref().is_some_and(|ring| ring.matches(&desc)) {
+            *texture_ring = Some(SharedTextureRing::new(&device, &desc)?);
+        }
+        let texture_ring = texture_ring.as_mut().expect("Shared texture ring was just created");
+
+        let copied = texture_ring
+            .copy_from(&context, &texture)
+            .map_err(|err| detect_device_loss(&device, err.into()));
+        let handle = match copied? {
+            Some(handle) => handle,
+            None => {
+                tracing::debug!("All shared textures are in use, dropping frame.");
+                return Ok(());
             }
         };
 
-        let frame = Frame::new_ensure_rgba(
-            data,
-            crate::capture_providers::shared::PixelFormat::BGRA8,
+        let gpu_frame = GpuFrame::new(
+            handle,
+            texture_pixel_format(desc.Format),
             Vector2 { x: size.Width, y: size.Height },
-            sys_time.Duration,
-            dirty_regions,
+            timestamp,
         );
 
-        match tx.try_send(frame) {
-            Ok(_) => (),
-            Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => {
-                tracing::warn!("Frame sender closed whilst trying to send frame.");
+        match tx.send_frame(generation, gpu_frame) {
+            Ok(evicted) => {
+                counters.frames_dropped.fetch_add(evicted, Ordering::Relaxed);
             }
-            Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => {
-                tracing::debug!("Frame channel full, dropping frame.");
+            Err(SendError::Closed) => {
+                tracing::debug!("Texture stream dropped whilst trying to send frame.");
+            }
+            Err(SendError::Full) => {
+                counters.frames_dropped.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("Texture channel full, dropping frame.");
+            }
+            Err(SendError::Stale) => {
+                tracing::debug!("Dropping late texture of the previous capture item.");
             }
         }
 
         Ok(())
     }
 
-    fn unregister_frame_arrived(&mut self, token: i64) -> super::Result<()> {
-        // Check if the handler is still active (it might have been removed by stop_capture already)
-        if let Some(pos) = self.active_handlers.iter().position(|&t| t == token) {
-            self.active_handlers.remove(pos);
+    /// Captures a single frame of the default capture item using a one-shot session.
+    /// Blocks until the frame arrives, so it should not be called from an async context.
+    pub fn capture_snapshot(&self) -> super::Result<Frame> {
+        let id = self.default_source()?;
 
-            let frame_pool = match &self.frame_pool {
-                Some(frame_pool) => frame_pool,
-                None => {
-                    return Ok(());
-                }
+        // Don't hold the lock while waiting for the frame, the streams might need it.
+        let (device, capture_item, session_settings) = {
+            let resources = lock_resources(&self.resources);
+            let source = match resources.sources.get(&id) {
+                Some(source) => source,
+                None => return Err(WindowsCaptureError::UnknownSource(id)),
             };
-
-            frame_pool.RemoveFrameArrived(token)?;
-        }
-        Ok(())
-    }
-}
-
-impl CaptureProvider for WindowsCaptureProvider {
-    type Result<T> = super::Result<T>;
-    type Stream = WindowsCaptureStream;
-    type CaptureItem = GraphicsCaptureItem;
-
-    /// Creates a new stream for receiving frames.
-    fn create_stream(&mut self, framerate: CaptureFramerate) -> Self::Result<Self::Stream> {
-        let (tx, rx) = tokio::sync::mpsc::channel(2);
-
-        // We can't send self raw to the closure, so we need to just copy the staging texture which is inside an Arc Mutex.
-        let staging_tex_ptr = self.staging_texture.clone();
-
-        #[cfg(debug_assertions)]
-        let frame_counter = Arc::new(AtomicUsize::new(0));
-        #[cfg(debug_assertions)]
-        let frame_counter_weak = Arc::downgrade(&frame_counter);
-
-        #[cfg(debug_assertions)]
-        tokio::spawn(async move {
-            let mut last_count = 0;
-            loop {
-                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-                if let Some(counter) = frame_counter_weak.upgrade() {
-                    let current_count = counter.load(Ordering::Relaxed);
-                    let fps = current_count.wrapping_sub(last_count);
-                    tracing::debug!("Capture stream FPS: {}", fps);
-                    last_count = current_count;
-                } else {
-                    tracing::debug!("Capture stream FPS task exited.");
-                    break;
-                }
-            }
-        });
-
-        let frame_pool = match &self.frame_pool {
-            Some(frame_pool) => frame_pool,
-            None => {
-                tracing::error!("No frame pool available!");
-                return Err(WindowsCaptureError::NoFramePool);
-            }
+            (resources.device.clone(), source.capture_item.clone(), resources.session_settings)
         };
 
-        if let Err(err) =
-            self.session.as_ref().unwrap().SetMinUpdateInterval(windows::Foundation::TimeSpan {
-                Duration: framerate.to_frametime().as_nanos().div(100) as i64,
-            })
-        {
-            tracing::error!("Failed to set min update interval: {}", err);
-            return Err(WindowsCaptureError::SetMinUpdateIntervalFailed(err));
-        }
+        tracing::info!("Capturing snapshot...");
+        let frame_pool = Direct3D11CaptureFramePool::CreateFreeThreaded(
+            &device,
+            frame_pool_format(self.frame_options.output_format).to_directx_pixel_format(),
+            1,
+            capture_item.Size()?,
+        )?;
+        let session = frame_pool.CreateCaptureSession(&capture_item)?;
+        session_settings.apply(&session)?;
 
+        // The snapshot gets its own staging texture, as the item size might differ from the one used by streams.
+        let staging_tex_ptr = Arc::new(Mutex::new(None));
+        let clock = QpcClock::now();
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
         let frame_arrived_token =
             frame_pool.FrameArrived(&TypedEventHandler::new(move |sender, _args| {
-                #[cfg(debug_assertions)]
-                frame_counter.fetch_add(1, Ordering::Relaxed);
-
                 let sender = match &*sender {
                     Some(sender) => sender,
                     None => {
@@ -281,101 +840,1454 @@ impl CaptureProvider for WindowsCaptureProvider {
                     }
                 };
 
-                if let Err(err) = Self::process_frame(frame, staging_tex_ptr.clone(), tx.clone()) {
-                    tracing::error!("Failed to process frame: {}", err);
+                let frame = Self::read_frame(
+                    frame,
+                    staging_tex_ptr.clone(),
+                    &mut None,
+                    &FrameOptions::default(),
+                    &clock,
+                    0,
+                );
+                match frame {
+                    // Only the first frame is needed, the rest are simply discarded.
+                    Ok(frame) => {
+                        tx.try_send(frame).ok();
+                    }
+                    Err(err) => tracing::error!("Failed to read snapshot frame: {}", err),
                 }
 
                 Ok(())
             }))?;
 
-        self.active_handlers.push(frame_arrived_token);
-
-        let stream = WindowsCaptureStream::new(rx);
-
-        Ok(stream)
-    }
-
-    fn set_capture_item(&mut self, capture_item: Self::CaptureItem) -> Self::Result<()> {
-        tracing::info!(
-            "Setting capture item: {}",
-            capture_item.DisplayName().unwrap_or("<no name>".into())
-        );
-
-        let size = capture_item.Size()?;
-        self.capture_item = Some(capture_item);
+        session.StartCapture()?;
+        let result = rx.recv_timeout(Self::SNAPSHOT_TIMEOUT);
 
-        let frame_pool = Direct3D11CaptureFramePool::CreateFreeThreaded(
-            &self.device,
-            Self::PIXEL_FORMAT.to_directx_pixel_format(),
-            Self::FRAME_COUNT,
-            size,
-        )?;
-        self.frame_pool = Some(frame_pool);
+        if let Err(err) = frame_pool.RemoveFrameArrived(frame_arrived_token) {
+            tracing::warn!("Failed to remove snapshot frame handler: {}", err);
+        }
+        session.Close()?;
+        frame_pool.Close()?;
 
-        // Disabled for testing reasons, uncomment later
-        //session.SetIsCursorCaptureEnabled(true)?;
-        //session.SetIsBorderRequired(false)?;
-        Ok(())
+        match result {
+            Ok(frame) => {
+                tracing::info!("Snapshot captured: {} x {}", frame.size.x, frame.size.y);
+                Ok(frame)
+            }
+            Err(_) => {
+                tracing::error!("Timed out waiting for snapshot frame.");
+                Err(WindowsCaptureError::SnapshotTimedOut)
+            }
+        }
     }
 
-    fn start_capture(&mut self) -> Self::Result<()> {
-        if self.capturing {
-            return Err(WindowsCaptureError::AlreadyCapturing);
+    /// Scales the texture to the output size in the output format, recreating the scaler if the sizes, the
+    /// formats or the device changed.
+    fn scale_texture(
+        device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        scaler: &mut Option<GpuScaler>,
+        texture: &ID3D11Texture2D,
+        output_format: DXGI_FORMAT,
+        source: Rect<i32>,
+        output_size: Vector2<i32>,
+    ) -> super::Result<ID3D11Texture2D> {
+        let mut format_desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { texture.GetDesc(&mut format_desc) };
+        let reusable = scaler.as_ref().is_some_and(|scaler| {
+            scaler.matches(device, format_desc.Format, output_format, source, output_size)
+        });
+        if !reusable {
+            *scaler = Some(GpuScaler::new(
+                device,
+                context,
+                &format_desc,
+                output_format,
+                source,
+                output_size,
+            )?);
         }
 
-        let frame_pool = match &self.frame_pool {
-            Some(frame_pool) => frame_pool,
-            None => {
-                tracing::error!("No frame pool set!");
-                return Err(WindowsCaptureError::NoFramePool);
-            }
-        };
+        let scaler = scaler.as_ref().expect("Scaler was just created");
+        Ok(scaler.scale(texture)?)
+    }
 
-        let capture_item = match &self.capture_item {
-            Some(capture_item) => capture_item,
-            None => {
-                tracing::error!("No capture item set!");
-                return Err(WindowsCaptureError::NoCaptureItem);
-            }
-        };
+    /// Reads a captured frame back into CPU memory, waiting for the GPU to finish the copy.
+    fn read_frame(
+        frame: Direct3D11CaptureFrame,
+        staging_tex_arc: Arc<Mutex<Option<ID3D11Texture2D>>>,
+        scaler: &mut Option<GpuScaler>,
+        options: &FrameOptions,
+        clock: &QpcClock,
+        sequence: u64,
+    ) -> super::Result<Frame> {
+        let gpu_conversion = AtomicBool::new(options.gpu_conversion);
+        let prepared = Self::prepare_frame(
+            frame,
+            Instant::now(),
+            scaler,
+            &gpu_conversion,
+            options,
+            clock,
+            sequence,
+        )?;
+        let PreparedFrame { texture, region, staging_desc, device, context, pending } = prepared;
 
-        let session = match &self.session {
-            Some(session) => session,
+        // A staging texture of a different size is left over from before the scale target changed.
+        let staging_tex =
+            staging_tex_arc.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+        let staging_tex = staging_tex.filter(|staging_tex| {
+            let mut desc = D3D11_TEXTURE2D_DESC::default();
+            unsafe { staging_tex.GetDesc(&mut desc) };
+            desc.Width == staging_desc.Width
+                && desc.Height == staging_desc.Height
+                && desc.Format == staging_desc.Format
+        });
+        let staging_tex = match staging_tex {
+            Some(staging_tex) => staging_tex,
             None => {
-                let new_session = frame_pool.CreateCaptureSession(capture_item)?;
-                self.session = Some(new_session);
-                self.session.as_ref().unwrap()
+                let mut tex = None;
+                unsafe { device.CreateTexture2D(&staging_desc, None, Some(&mut tex)) }
+                    .map_err(|err| detect_device_loss(&device, err.into()))?;
+                let staging_tex = tex.expect("Failed to create staging texture!");
+                *staging_tex_arc.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) =
+                    Some(staging_tex.clone());
+                staging_tex
             }
         };
 
-        session.StartCapture()?;
-        self.capturing = true;
+        // Sized from the texture that is actually read, which can differ from the size of the captured item.
+        let mut data = Vec::new();
+        let (_, stride) = read_texture(
+            &context,
+            texture,
+            region,
+            staging_tex,
+            pending.capture_format,
+            options.readback_mode,
+            &mut data,
+        )
+        .map_err(|err| detect_device_loss(&device, err))?;
 
-        Ok(())
+        Ok(Self::finish_frame(pending, data, stride, None, options, None))
     }
 
-    fn stop_capture(&mut self) -> Self::Result<()> {
-        if !self.capturing {
+    /// Crops and scales a captured frame on the GPU, and collects what is needed to turn it into a frame once it
+    /// has been read back. Reorders BGRA to RGBA on the GPU as well while `gpu_conversion` is set, which is
+    /// cleared if the GPU can't do it.
+    fn prepare_frame(
+        frame: Direct3D11CaptureFrame,
+        arrived: Instant,
+        scaler: &mut Option<GpuScaler>,
+        gpu_conversion: &AtomicBool,
+        options: &FrameOptions,
+        clock: &QpcClock,
+        sequence: u64,
+    ) -> super::Result<PreparedFrame> {
+        let texture = frame_to_texture(&frame)?;
+        let size = frame.ContentSize()?;
+
+        let device = unsafe { texture.GetDevice()? };
+        let context = unsafe { device.GetImmediateContext()? };
+
+        let mut texture_desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { texture.GetDesc(&mut texture_desc) };
+        let capture_format = texture_pixel_format(texture_desc.Format);
+        // Until the frame pool has been recreated after the item grew, the content is cut off at the texture size.
+        let content_size = Vector2 {
+            x: size.Width.min(texture_desc.Width as i32),
+            y: size.Height.min(texture_desc.Height as i32),
+        };
+
+        // A crop outside of the content, e.g. after a window shrank, is limited to the part that is left.
+        let content = Rect::new(Vector2::new(0, 0), content_size);
+        let source = options.crop.and_then(|crop| crop.clamp_to(content_size)).unwrap_or(content);
+
+        // Recomputed on every frame, so the target follows the source when it is resized.
+        let output_size = options.scale.target_size(source.size);
+        // Only eager RGBA8 output reorders the channels at all, deferred frames keep BGRA.
+        let swizzle = capture_format == PixelFormat::BGRA8
+            && options.output_format == PixelFormat::RGBA8
+            && options.conversion_policy == ConversionPolicy::Eager
+            && gpu_conversion.load(Ordering::Relaxed);
+        let (texture, region, capture_format) = if swizzle {
+            let converted = Self::scale_texture(
+                &device,
+                &context,
+                scaler,
+                &texture,
+                DXGI_FORMAT_R8G8B8A8_UNORM,
+                source,
+                output_size,
+            );
+            match converted {
+                Ok(converted) => (converted, None, PixelFormat::RGBA8),
+                Err(err) => {
+                    let err = detect_device_loss(&device, err);
+                    if let WindowsCaptureError::DeviceLost(_) = err {
+                        return Err(err);
+                    }
+                    tracing::warn!(
+                        "Failed to convert frames on the GPU, converting on the CPU: {}",
+                        err
+                    );
+                    gpu_conversion.store(false, Ordering::Relaxed);
+                    return Self::prepare_frame(
+                        frame,
+                        arrived,
+                        scaler,
+                        gpu_conversion,
+                        options,
+                        clock,
+                        sequence,
+                    );
+                }
+            }
+        } else if source == content && output_size == content_size {
+            (texture, None, capture_format)
+        } else if output_size == source.size {
+            (texture, Some(source), capture_format)
+        } else {
+            let scaled = Self::scale_texture(
+                &device,
+                &context,
+                scaler,
+                &texture,
+                texture_desc.Format,
+                source,
+                output_size,
+            )
+            .map_err(|err| detect_device_loss(&device, err))?;
+            (scaled, None, capture_format)
+        };
+
+        let staging_desc = unsafe {
+            let mut d = std::mem::zeroed::<D3D11_TEXTURE2D_DESC>();
+            texture.GetDesc(&mut d);
+            d.BindFlags = 0;
+            d.MiscFlags = 0;
+            d.CPUAccessFlags = D3D11_CPU_ACCESS_READ.0 as u32;
+            d.Usage = D3D11_USAGE_STAGING;
+            d.MipLevels = 1;
+            d.ArraySize = 1;
+            d.SampleDesc.Count = 1;
+            d.SampleDesc.Quality = 0;
+            if let Some(region) = region {
+                d.Width = region.size.x as u32;
+                d.Height = region.size.y as u32;
+            }
+            d
+        };
+
+        let timestamp = frame.SystemRelativeTime()?.Duration;
+        let timing =
+            FrameTiming { timestamp, sequence, capture_instant: clock.to_instant(timestamp) };
+
+        // Dirty regions are in pixels of the item, so they follow the crop and scale of the frame.
+        let dirty_regions = match frame.DirtyRegions() {
+            _ if !wgc_capabilities().dirty_regions => Vec::new(),
+            Ok(regions) => regions
+                .into_iter()
+                .filter_map(|region| Self::to_output_rect(region.into(), source, output_size))
+                .collect(),
+            Err(err) => {
+                tracing::warn!("Failed to get dirty regions: {}", err);
+                Vec::new() // Consumers treat missing dirty regions as unknown, so this is safe to skip.
+            }
+        };
+
+        let pending =
+            PendingFrame { arrived, timing, dirty_regions, capture_format, output_size, source };
+        Ok(PreparedFrame { texture, region, staging_desc, device, context, pending })
+    }
+
+    /// Turns data read back from the staging texture into a frame in the output format. Only `rows` of the frame
+    /// were read back if given, which makes it a delta frame.
+    fn finish_frame(
+        pending: PendingFrame,
+        mut data: Vec<u8>,
+        stride: usize,
+        rows: Option<Range<usize>>,
+        options: &FrameOptions,
+        buffer_pool: Option<&BufferPool>,
+    ) -> Frame {
+        let PendingFrame { timing, dirty_regions, capture_format, output_size, .. } = pending;
+        let data_size = match &rows {
+            Some(rows) => Vector2::new(output_size.x, rows.len() as i32),
+            None => output_size,
+        };
+
+        // Tone mapped here rather than in `convert_image`, which only knows the default white level.
+        let (data, capture_format, stride) = if capture_format == PixelFormat::RGBA16F
+            && options.output_format != PixelFormat::RGBA16F
+        {
+            let width = data_size.x.max(0) as usize;
+            let height = data_size.y.max(0) as usize;
+            rgba16f_to_rgba8(&mut data, width, height, stride, options.sdr_white_level);
+            (data, PixelFormat::RGBA8, PixelFormat::RGBA8.row_bytes(width))
+        } else {
+            (data, capture_format, stride)
+        };
+        // Deferred frames keep the captured channel order, consumers reorder them if they need to.
+        let output_format = match options.conversion_policy {
+            ConversionPolicy::Deferred if options.output_format == PixelFormat::RGBA8 => {
+                capture_format
+            }
+            _ => options.output_format,
+        };
+        let (data, format, stride) =
+            convert_image(data, capture_format, output_format, data_size, stride);
+        let data = match buffer_pool {
+            Some(buffer_pool) => buffer_pool.wrap(data),
+            None => data.into(),
+        };
+        let mut frame = Frame::new(data, format, output_size, stride, timing, dirty_regions);
+        frame.delta_rows = rows.map(|rows| rows.start as i32..rows.end as i32);
+        frame
+    }
+
+    /// Keeps what changed in a frame that isn't read back, for the next delta frame.
+    fn remember_skipped(context: &PipelineContext, frame: &Direct3D11CaptureFrame) {
+        if !context.options.delta_mode {
+            return;
+        }
+        let regions = frame
+            .DirtyRegions()
+            .map(|regions| regions.into_iter().map(Into::into).collect::<Vec<Rect<i32>>>());
+        let mut delta = context.delta.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match regions {
+            Ok(regions)
+                if !regions.is_empty()
+                    && delta.skipped_dirty.len() + regions.len()
+                        <= Self::DELTA_MAX_SKIPPED_REGIONS =>
+            {
+                delta.skipped_dirty.extend(regions)
+            }
+            _ => delta.skipped_unknown = true,
+        }
+    }
+
+    /// The rows a delta frame of `pending` has to hold, `None` if it has to be sent in full.
+    fn delta_rows(
+        context: &PipelineContext,
+        pending: &PendingFrame,
+        generation: u64,
+        recipients: &[Arc<Subscriber>],
+    ) -> Option<Range<usize>> {
+        let mut delta = context.delta.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let skipped = std::mem::take(&mut delta.skipped_dirty);
+        let skipped_unknown = std::mem::take(&mut delta.skipped_unknown);
+        let this_frame = (generation, pending.output_size);
+        let resized = delta.last_frame.replace(this_frame) != Some(this_frame);
+        let missed =
+            recipients.iter().any(|subscriber| subscriber.needs_full_frame.load(Ordering::Relaxed));
+        // Without dirty regions nothing is known about what changed, and planar formats can't be split into rows.
+        if resized
+            || skipped_unknown
+            || missed
+            || pending.dirty_regions.is_empty()
+            || context.options.output_format.is_planar()
+            || delta.since_full >= Self::DELTA_FULL_FRAME_INTERVAL
+        {
+            delta.since_full = 0;
+            return None;
+        }
+
+        let height = pending.output_size.y;
+        let skipped = skipped
+            .into_iter()
+            .filter_map(|rect| Self::to_output_rect(rect, pending.source, pending.output_size));
+        let (start, end) = pending
+            .dirty_regions
+            .iter()
+            .copied()
+            .chain(skipped)
+            .fold((height, 0), |(start, end), rect| {
+                (start.min(rect.position.y), end.max(rect.end().y))
+            });
+        let start = start.clamp(0, height);
+        let end = end.clamp(start, height);
+        if (end - start) as f64 > height as f64 * Self::DELTA_MAX_ROW_SHARE {
+            delta.since_full = 0;
+            return None;
+        }
+        delta.since_full += 1;
+        Some(start as usize..end as usize)
+    }
+
+    /// Maps a rect in pixels of the item onto a frame cropped to `source` and scaled to `output_size`.
+    /// Rounded outwards, so the result still covers every pixel the rect touches.
+    fn to_output_rect(
+        rect: Rect<i32>,
+        source: Rect<i32>,
+        output_size: Vector2<i32>,
+    ) -> Option<Rect<i32>> {
+        let rect = rect.intersect(&source)?;
+        let scale_x = output_size.x as f64 / source.size.x as f64;
+        let scale_y = output_size.y as f64 / source.size.y as f64;
+        let scale = |point: Vector2<i32>, round: fn(f64) -> f64| {
+            let point = (point - source.position).cast::<f64>();
+            Vector2::new(round(point.x * scale_x), round(point.y * scale_y)).cast::<i32>()
+        };
+        let start = scale(rect.position, f64::floor);
+        let end = scale(rect.end(), f64::ceil);
+        Some(Rect::new(start, end - start))
+    }
+
+    /// Called for every frame of a pipeline, or the error taking it from the frame pool. Errors are reported to
+    /// each stream the pipeline serves.
+    fn on_frame_arrived(
+        context: &PipelineContext,
+        frame: super::Result<Direct3D11CaptureFrame>,
+        generation: u64,
+    ) {
+        let arrived = Instant::now();
+        context.counters.frames_arrived.fetch_add(1, Ordering::Relaxed);
+
+        // Frames arriving during recovery still come from the lost device.
+        if context.failed.load(Ordering::Relaxed) || context.recovery.in_progress() {
+            return;
+        }
+
+        let err = match frame
+            .and_then(|frame| Self::process_frame(context, frame, arrived, generation))
+        {
+            Ok(()) => return,
+            Err(err) => err,
+        };
+        let subscribers = context.live_subscribers();
+        for subscriber in &subscribers {
+            if let Some(sink) = &subscriber.sink {
+                sink.on_error(&err);
+            }
+        }
+        if err.is_fatal() {
+            Self::handle_fatal_error(context, err);
+        } else {
+            tracing::warn!("Failed to process frame, dropping it: {}", err);
+            for subscriber in &subscribers {
+                if !subscriber.error_reported.swap(true, Ordering::Relaxed) {
+                    let _ = subscriber.tx.send_event(CaptureEvent::Error(err.to_string()));
+                }
+            }
+        }
+    }
+
+    fn process_frame(
+        context: &PipelineContext,
+        frame: Direct3D11CaptureFrame,
+        arrived: Instant,
+        generation: u64,
+    ) -> super::Result<()> {
+        let timestamp = frame.SystemRelativeTime()?.Duration;
+        // Assigned before filtering, so skipped frames show up as gaps.
+        let sequence = context.next_sequence.fetch_add(1, Ordering::Relaxed);
+
+        // Paused sources keep their session and streams, their frames are dropped before any D3D work.
+        if context.paused.load(Ordering::Relaxed) {
+            Self::remember_skipped(context, &frame);
+            return Ok(());
+        }
+
+        // Filtered before the limiters, which count every frame they let through towards their framerate.
+        if let Some(unchanged_filter) = &context.unchanged_filter
+            // Frames without dirty region support are always treated as changed.
+            && let Ok(regions) = frame.DirtyRegions()
+        {
+            let dirty_rects: Vec<_> = regions.into_iter().map(Into::into).collect();
+            if unchanged_filter.should_skip(&dirty_rects, timestamp) {
+                context.counters.frames_skipped_unchanged.fetch_add(1, Ordering::Relaxed);
+                Self::remember_skipped(context, &frame);
+                return Ok(());
+            }
+        }
+
+        // Every stream decimates to its own framerate, the frame is only read back if any of them wants it.
+        let recipients: Vec<_> = context
+            .live_subscribers()
+            .into_iter()
+            .filter(|subscriber| !subscriber.should_skip(timestamp))
+            .collect();
+        if recipients.is_empty() {
+            context.counters.frames_skipped_rate_limit.fetch_add(1, Ordering::Relaxed);
+            Self::remember_skipped(context, &frame);
+            return Ok(());
+        }
+
+        let options = FrameOptions {
+            crop: *context.crop_region.lock().unwrap_or_else(|poisoned| poisoned.into_inner()),
+            ..context.options
+        };
+        let mut scaler = context.scaler.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let prepared = Self::prepare_frame(
+            frame,
+            arrived,
+            &mut scaler,
+            &context.gpu_conversion,
+            &options,
+            &context.clock,
+            sequence,
+        )?;
+        drop(scaler);
+        let PreparedFrame { texture, region, staging_desc, device, context: d3d_context, pending } =
+            prepared;
+
+        // The copy is only queued here, the frame is read back once the GPU got to it, which might be with the
+        // next frame. Until then the handler returns rather than waiting on the GPU.
+        let mut staging = context.staging.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let format = pending.capture_format;
+        let rows = match options.delta_mode {
+            true => Self::delta_rows(context, &pending, generation, &recipients),
+            false => None,
+        };
+        let evicted = staging
+            .queue_copy(
+                &device,
+                &d3d_context,
+                &texture,
+                region,
+                &staging_desc,
+                format,
+                rows,
+                (pending, recipients),
+            )
+            .map_err(|err| detect_device_loss(&device, err))?;
+        let frame_bytes =
+            format.frame_bytes(staging_desc.Width as usize, staging_desc.Height as usize);
+        let mut data = context.buffer_pool.take(frame_bytes);
+        let readback = staging
+            .read(&d3d_context, options.readback_mode, &mut data)
+            .map_err(|err| detect_device_loss(&device, err))?;
+        drop(staging);
+
+        let dropped = evicted as u64 + readback.as_ref().map_or(0, |readback| readback.superseded);
+        context.counters.frames_dropped.fetch_add(dropped, Ordering::Relaxed);
+        let Some(Readback { frame: (frame, recipients), rows, stride, .. }) = readback else {
+            // Hands the buffer back to the pool.
+            drop(context.buffer_pool.wrap(data));
+            context.counters.readback_stalls.fetch_add(1, Ordering::Relaxed);
+            tracing::debug!("GPU is still copying frame {}, reading it back later.", sequence);
+            return Ok(());
+        };
+        // The frame read back might be an earlier one than the frame that just arrived.
+        let arrived = frame.arrived;
+        let frame =
+            Self::finish_frame(frame, data, stride, rows, &options, Some(&context.buffer_pool));
+        if let Some(tracer) = &context.frame_tracer {
+            tracer.record(frame.sequence, Stage::Arrived, arrived);
+            tracer.record(frame.sequence, Stage::ReadBack, Instant::now());
+        }
+
+        if context.trace_frames.load(Ordering::Relaxed) {
+            tracing::trace!(
+                target: "loki::frames",
+                "Frame {}: {} x {}, {} bytes with a stride of {}",
+                frame.sequence,
+                frame.size.x,
+                frame.size.y,
+                frame.data.len(),
+                frame.stride
+            );
+        }
+
+        context.observers.notify(&frame);
+
+        // The data is reference counted, so every stream gets the same buffer rather than a copy.
+        let mut delivered = false;
+        for subscriber in &recipients {
+            delivered |= Self::deliver(context, subscriber, generation, frame.clone());
+        }
+        // Streams that skipped this frame can't apply a delta on top of it.
+        if options.delta_mode {
+            for subscriber in context.live_subscribers() {
+                if !recipients.iter().any(|recipient| Arc::ptr_eq(recipient, &subscriber)) {
+                    subscriber.needs_full_frame.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        if delivered {
+            Self::mark_delivered(context, &frame, arrived);
+        }
+        Ok(())
+    }
+
+    /// Hands a frame to one stream. Returns whether it reached the stream or its sink.
+    fn deliver(
+        context: &PipelineContext,
+        subscriber: &Subscriber,
+        generation: u64,
+        frame: Frame,
+    ) -> bool {
+        if subscriber.tx.is_stale(generation) {
+            return false;
+        }
+        Self::report_lifecycle(subscriber, generation, frame.size);
+        subscriber.error_reported.store(false, Ordering::Relaxed);
+        let full_frame = !frame.is_delta();
+
+        if let Some(sink) = &subscriber.sink {
+            sink.on_frame(&frame);
+            if sink.delivery == SinkDelivery::SinkOnly {
+                if full_frame {
+                    subscriber.needs_full_frame.store(false, Ordering::Relaxed);
+                }
+                return true;
+            }
+        }
+
+        let sequence = frame.sequence;
+        match subscriber.tx.send_frame(generation, CaptureEvent::Frame(frame)) {
+            Ok(evicted) => {
+                context.counters.frames_dropped.fetch_add(evicted, Ordering::Relaxed);
+                // An evicted frame leaves a gap no delta bridges.
+                if evicted > 0 {
+                    subscriber.needs_full_frame.store(true, Ordering::Relaxed);
+                } else if full_frame {
+                    subscriber.needs_full_frame.store(false, Ordering::Relaxed);
+                }
+                if let Some(tracer) = &context.frame_tracer {
+                    tracer.record(sequence, Stage::Queued, Instant::now());
+                }
+                true
+            }
+            Err(SendError::Closed) => {
+                tracing::debug!("Stream dropped whilst trying to send frame.");
+                false
+            }
+            Err(SendError::Full) => {
+                context.counters.frames_dropped.fetch_add(1, Ordering::Relaxed);
+                subscriber.needs_full_frame.store(true, Ordering::Relaxed);
+                tracing::debug!("Frame channel full, dropping frame.");
+                false
+            }
+            Err(SendError::Stale) => {
+                tracing::debug!("Dropping late frame of the previous capture item.");
+                false
+            }
+        }
+    }
+
+    /// Tells the stream when an item starts producing frames and when their size changes, ahead of the frame.
+    fn report_lifecycle(subscriber: &Subscriber, generation: u64, size: Vector2<i32>) {
+        let mut last_frame =
+            subscriber.last_frame.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let event = match *last_frame {
+            Some((last_generation, last_size)) if last_generation == generation => {
+                if last_size == size {
+                    return;
+                }
+                CaptureEvent::Resized(size)
+            }
+            _ => CaptureEvent::Started,
+        };
+        *last_frame = Some((generation, size));
+        if subscriber.tx.send_event(event).is_err() {
+            tracing::debug!("Stream receiver dropped before the frame was sent.");
+        }
+    }
+
+    /// Counted once per frame, however many streams it went to.
+    fn mark_delivered(context: &PipelineContext, frame: &Frame, arrived: Instant) {
+        context.counters.frames_delivered.fetch_add(1, Ordering::Relaxed);
+        context.counters.set_last_frame_size(frame.size);
+        context.counters.delivery_latency.record(arrived.elapsed());
+        context.counters.display_latency.record(frame.capture_instant.elapsed());
+        if let Some(unchanged_filter) = &context.unchanged_filter {
+            unchanged_filter.mark_delivered(frame.timestamp);
+        }
+    }
+
+    fn handle_fatal_error(context: &PipelineContext, err: WindowsCaptureError) {
+        if matches!(err, WindowsCaptureError::DeviceLost(_))
+            && Self::try_recover(&context.recovery, &context.resources)
+        {
+            tracing::warn!("Capture device lost, recovering: {}", err);
+            return;
+        }
+        Self::fail_pipeline(context, err);
+    }
+
+    /// Stops the streams of a pipeline after a fatal error and lets their consumers know why.
+    fn fail_pipeline(context: &PipelineContext, err: WindowsCaptureError) {
+        tracing::error!("Fatal capture error, ending stream: {}", err);
+        context.failed.store(true, Ordering::Relaxed);
+
+        // Only try, as the provider might be in the middle of removing this very handler.
+        if let Some(resources) = context.resources.upgrade()
+            && let Ok(resources) = resources.try_lock()
+        {
+            let session = resources.sources.get(&context.source).and_then(CaptureSource::session);
+            if let Some(session) = session
+                && let Err(err) = session.Close()
+            {
+                tracing::warn!("Failed to close capture session after fatal error: {}", err);
+            }
+        }
+
+        let err = Arc::new(CaptureError::from(err));
+        for subscriber in context.live_subscribers() {
+            let reason = EndReason::Failed(err.clone());
+            if subscriber.tx.send_event(CaptureEvent::Ended(reason)).is_err() {
+                tracing::debug!(
+                    "Stream receiver dropped before the fatal error could be delivered."
+                );
+            }
+        }
+    }
+
+    /// Starts rebuilding the pipeline on a new device if auto recovery is enabled.
+    /// Returns false if the device loss has to be handled as a regular fatal error.
+    fn try_recover(
+        recovery: &Arc<DeviceRecovery>,
+        resources: &Weak<Mutex<CaptureResources>>,
+    ) -> bool {
+        if !recovery.is_enabled() {
+            return false;
+        }
+        let resources = match resources.upgrade() {
+            Some(resources) => resources,
+            None => return false,
+        };
+
+        let give_up_resources = resources.clone();
+        recovery.spawn(
+            move || Self::recreate_device(&resources),
+            move |err| Self::fail_all_streams(&give_up_resources, err),
+        )
+    }
+
+    /// Creates a fresh D3D11 device and rebuilds the frame pool and session of every source on it. Streams
+    /// recreate their staging textures once frames arrive from the new device.
+    fn recreate_device(resources: &Mutex<CaptureResources>) -> super::Result<()> {
+        let adapter = lock_resources(resources).adapter;
+        // The adapter may have gone away along with the device, e.g. an external GPU that was unplugged.
+        let d3d_device = match adapter.create_device() {
+            Err(WindowsCaptureError::AdapterNotFound(_)) => {
+                tracing::warn!("The {} is gone, recovering on the default adapter.", adapter);
+                AdapterSelection::Default.create_device()?
+            }
+            device => device?,
+        };
+        let device = native_to_winrt_d3d11device(&d3d_device)?;
+
+        let mut guard = lock_resources(resources);
+        if !guard.sources.values().any(CaptureSource::has_frame_handlers) {
+            tracing::info!("Capture was stopped during device recovery, nothing left to recover.");
+            return Ok(());
+        }
+
+        guard.device = device;
+        let CaptureResources { device, sources, session_settings, .. } = &mut *guard;
+        let mut senders = Vec::new();
+        for source in sources.values_mut() {
+            source.recreate_pipeline(device, *session_settings)?;
+            senders.extend(source.stream_senders.iter().cloned());
+        }
+        drop(guard);
+
+        tracing::info!("Capture pipeline recreated on a new device.");
+        for sender in senders {
+            if sender.send_event(CaptureEvent::Recreated).is_err() {
+                tracing::debug!(
+                    "Stream receiver dropped before the recovery notice was delivered."
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks for sources whose session stopped delivering frames, see `SourceWatchdog`. Sessions of items that
+    /// still exist are recreated, the streams of the others end with `EndReason::SourceLost`.
+    /// Returns false once the provider is gone.
+    fn watch_sources(
+        resources: &Weak<Mutex<CaptureResources>>,
+        recovery: &DeviceRecovery,
+        watchdog: &SourceWatchdog,
+        progress: &mut BTreeMap<SourceId, SourceProgress>,
+        displays_changed: bool,
+    ) -> bool {
+        let Some(resources) = resources.upgrade() else {
+            return false;
+        };
+        // Recovery recreates every session anyway, and frames don't arrive while it runs.
+        if recovery.in_progress() {
+            return true;
+        }
+
+        let timeout = watchdog.stall_timeout();
+        let mut watched = Vec::new();
+        {
+            let resources = lock_resources(&resources);
+            let is_watched =
+                |source: &CaptureSource| source.is_capturing() && source.has_frame_handlers();
+            progress.retain(|id, _| resources.sources.get(id).is_some_and(is_watched));
+            for (&id, source) in &resources.sources {
+                if !is_watched(source) {
+                    continue;
+                }
+                let (generation, frames_arrived) = (source.generation(), source.frames_arrived());
+                let entry = progress
+                    .entry(id)
+                    .or_insert_with(|| SourceProgress::new(generation, frames_arrived));
+                entry.update(generation, frames_arrived);
+                let stalled = entry.is_stalled(timeout, displays_changed);
+                watched.push((id, generation, source.capture_item.clone(), stalled));
+            }
+        }
+
+        // Looking up the window or monitor enumerates them, so the lock isn't held for it.
+        let mut checked = Vec::new();
+        for (id, generation, capture_item, stalled) in watched {
+            let entry = progress.get_mut(&id).expect("Progress was just recorded");
+            if entry.info.is_none() {
+                entry.info = capture_item_info(&capture_item).ok();
+            }
+            if stalled {
+                checked.push((
+                    id,
+                    generation,
+                    capture_item_exists(&capture_item, entry.info.as_ref()),
+                ));
+            }
+        }
+        if checked.is_empty() {
+            return true;
+        }
+
+        let mut events = Vec::new();
+        let mut guard = lock_resources(&resources);
+        let CaptureResources { device, sources, session_settings, .. } = &mut *guard;
+        for (id, generation, exists) in checked {
+            // The source might have been stopped or switched to another item in the meantime.
+            let Some(source) = sources
+                .get_mut(&id)
+                .filter(|source| source.is_capturing() && source.generation() == generation)
+            else {
+                continue;
+            };
+
+            if exists {
+                tracing::warn!(
+                    "Capture source {:?} stopped delivering frames, recreating its session.",
+                    id
+                );
+                match source.recreate_pipeline(device, *session_settings) {
+                    Ok(()) => {
+                        if let Some(entry) = progress.get_mut(&id) {
+                            entry.mark_recreated();
+                        }
+                        for sender in &source.stream_senders {
+                            events.push((sender.clone(), CaptureEvent::Recreated));
+                        }
+                        continue;
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            "Failed to recreate the session of capture source {:?}: {}",
+                            id,
+                            err
+                        );
+                    }
+                }
+            }
+
+            tracing::warn!("Capture source {:?} no longer exists, ending its streams.", id);
+            for sender in &source.stream_senders {
+                events.push((sender.clone(), CaptureEvent::Ended(EndReason::SourceLost)));
+            }
+            if let Err(err) = source.stop() {
+                tracing::warn!("Failed to stop lost capture source {:?}: {}", id, err);
+            }
+            progress.remove(&id);
+        }
+        drop(guard);
+
+        for (sender, event) in events {
+            if sender.send_event(event).is_err() {
+                tracing::debug!(
+                    "Stream receiver dropped before the watchdog notice was delivered."
+                );
+            }
+        }
+        true
+    }
+
+    /// Ends every stream after recovery gave up.
+    fn fail_all_streams(resources: &Mutex<CaptureResources>, err: WindowsCaptureError) {
+        tracing::error!("Fatal capture error, ending all streams: {}", err);
+
+        let mut senders = Vec::new();
+        for source in lock_resources(resources).sources.values_mut() {
+            source.close_pipeline();
+            senders.append(&mut source.stream_senders);
+        }
+
+        let err = Arc::new(CaptureError::from(err));
+        for sender in senders {
+            let reason = EndReason::Failed(err.clone());
+            if sender.send_event(CaptureEvent::Ended(reason)).is_err() {
+                tracing::debug!(
+                    "Stream receiver dropped before the fatal error could be delivered."
+                );
+            }
+        }
+    }
+
+    /// The GPU frames are captured on, which is a software adapter if no hardware device could be created.
+    pub fn adapter_info(&self) -> Option<AdapterInfo> {
+        let device = lock_resources(&self.resources).device.clone();
+        let info =
+            winrt_to_native_d3d11device(&device).and_then(|device| AdapterInfo::of_device(&device));
+        match info {
+            Ok(info) => Some(info),
+            Err(err) => {
+                tracing::warn!("Failed to describe the capture adapter: {}", err);
+                None
+            }
+        }
+    }
+
+    pub(super) fn set_adapter(&mut self, adapter: AdapterSelection) {
+        lock_resources(&self.resources).adapter = adapter;
+    }
+
+    /// Calls `observer` with every frame on the capture thread, after it has been read back and before it is
+    /// queued on any stream, for consumers that process frames in place rather than through a stream. Frames
+    /// are only captured for streams, and an observer sees the frames of all of them, once for every distinct
+    /// set of frame options, including delta frames. Observers hold up every stream, so they must be quick and
+    /// hand anything slow off to another thread. Calls over 2 ms are logged.
+    pub fn add_frame_observer(&mut self, observer: FrameObserver) -> ObserverToken {
+        self.observers.add(observer)
+    }
+
+    /// Frames that are already being observed still reach the observer. Returns whether it was still there.
+    pub fn remove_frame_observer(&mut self, token: ObserverToken) -> bool {
+        self.observers.remove(token)
+    }
+
+    /// Starts every source that isn't running yet, blocking until their sessions are started on the COM thread.
+    /// The same as `CaptureProvider::start_capture`, for callers that aren't async.
+    pub fn start_sources(&mut self) -> super::Result<()> {
+        if !wgc_capabilities().supported {
+            return Err(WindowsCaptureError::CaptureNotSupported);
+        }
+        self.detach_closed_streams();
+        let resources = self.resources.clone();
+        on_com_thread(move || start_all(&resources))
+    }
+
+    /// Stops every running source and ends their streams, blocking until they are stopped. The same as
+    /// `CaptureProvider::stop_capture`, for callers that aren't async, like `Drop`.
+    pub fn stop_sources(&mut self) -> super::Result<()> {
+        self.detach_closed_streams();
+        stop_all(&self.resources)
+    }
+
+    /// Stops delivering frames of every running source while keeping their sessions and streams alive, so
+    /// `resume_capture` picks up where it left off without consumers reconnecting. Streams see a gap in the
+    /// sequence numbers, and `start_capture` fails with `Paused` until capture is resumed or stopped.
+    pub fn pause_capture(&mut self) -> super::Result<()> {
+        let mut resources = lock_resources(&self.resources);
+        let sources = &mut resources.sources;
+        if !sources.values().any(CaptureSource::is_capturing) {
             return Err(WindowsCaptureError::NotCapturing);
         }
+        if sources.values().filter(|source| source.is_capturing()).all(CaptureSource::is_paused) {
+            return Err(WindowsCaptureError::Paused);
+        }
+
+        for source in
+            sources.values_mut().filter(|source| source.is_capturing() && !source.is_paused())
+        {
+            source.pause()?;
+        }
+        tracing::info!("Capture paused.");
+        Ok(())
+    }
+
+    /// Delivers frames of every paused source again.
+    pub fn resume_capture(&mut self) -> super::Result<()> {
+        let mut resources = lock_resources(&self.resources);
+        if !resources.sources.values().any(CaptureSource::is_paused) {
+            return Err(WindowsCaptureError::NotPaused);
+        }
+        for source in resources.sources.values_mut().filter(|source| source.is_paused()) {
+            source.resume()?;
+        }
+        tracing::info!("Capture resumed.");
+        Ok(())
+    }
+
+    /// Whether any source is paused, see `pause_capture`.
+    pub fn is_paused(&self) -> bool {
+        lock_resources(&self.resources).sources.values().any(CaptureSource::is_paused)
+    }
 
-        for token in self.active_handlers.clone() {
-            if let Err(e) = self.unregister_frame_arrived(token) {
-                tracing::warn!("Failed to remove frame handler during stop: {}", e);
+    /// Changes the framerate of a running stream, texture stream or latest frame handle without recreating
+    /// anything. The update interval of its source follows, as it is that of the fastest stream of the source.
+    pub fn set_stream_framerate(
+        &mut self,
+        stream: StreamId,
+        framerate: CaptureFramerate,
+    ) -> super::Result<()> {
+        tracing::debug!("Setting framerate of {}: {}", stream, framerate);
+        self.detach_closed_streams();
+        let mut resources = lock_resources(&self.resources);
+        let subscriber =
+            resources.pipelines.iter().filter_map(|(_, pipeline)| pipeline.upgrade()).find_map(
+                |pipeline| {
+                    let subscriber = pipeline
+                        .live_subscribers()
+                        .into_iter()
+                        .find(|subscriber| subscriber.id == stream.0)?;
+                    Some((pipeline.source, subscriber))
+                },
+            );
+        let source = match subscriber {
+            Some((source, subscriber)) => {
+                subscriber.frame_limiter.set_framerate(framerate);
+                source
             }
+            None => {
+                let texture_stream = resources
+                    .texture_streams
+                    .iter()
+                    .find(|texture_stream| texture_stream.token.id == stream.0)
+                    .ok_or(WindowsCaptureError::UnknownStream(stream))?;
+                texture_stream.frame_limiter.set_framerate(framerate);
+                texture_stream.token.source
+            }
+        };
+        Self::refresh_update_interval(&mut resources, source)
+    }
+
+    /// WGC only has one update interval per session, so it follows the fastest stream of the source and the
+    /// others decimate. A source without streams keeps its interval, it has nobody to deliver frames to anyway.
+    fn refresh_update_interval(
+        resources: &mut CaptureResources,
+        id: SourceId,
+    ) -> super::Result<()> {
+        let fastest = resources
+            .pipelines
+            .iter()
+            .filter_map(|(_, pipeline)| pipeline.upgrade())
+            .filter(|pipeline| pipeline.source == id)
+            .flat_map(|pipeline| pipeline.live_subscribers())
+            .map(|subscriber| subscriber.frame_limiter.framerate())
+            .chain(
+                resources
+                    .texture_streams
+                    .iter()
+                    .filter(|texture_stream| texture_stream.token.source == id)
+                    .map(|texture_stream| texture_stream.frame_limiter.framerate()),
+            )
+            .map(|framerate| framerate.to_frametime_ticks())
+            .min();
+        let Some(ticks) = fastest else {
+            return Ok(());
+        };
+        resources.source_mut(id)?.set_min_update_interval(TimeSpan { Duration: ticks })
+    }
+
+    /// Creates a stream of frames of the default source with custom queueing options.
+    pub fn create_stream_with(
+        &mut self,
+        framerate: CaptureFramerate,
+        stream_options: StreamOptions,
+    ) -> super::Result<WindowsCaptureStream> {
+        let id = self.default_source()?;
+        self.create_stream_for_with(id, framerate, stream_options)
+    }
+
+    /// Creates a stream of frames for a specific source.
+    pub fn create_stream_for(
+        &mut self,
+        id: SourceId,
+        framerate: CaptureFramerate,
+    ) -> super::Result<WindowsCaptureStream> {
+        let options = StreamOptions { capacity: self.channel_capacity, ..StreamOptions::default() };
+        self.create_stream_for_with(id, framerate, options)
+    }
+
+    /// Creates a stream of frames for a specific source with custom queueing options.
+    pub fn create_stream_for_with(
+        &mut self,
+        id: SourceId,
+        framerate: CaptureFramerate,
+        stream_options: StreamOptions,
+    ) -> super::Result<WindowsCaptureStream> {
+        self.create_stream_inner(id, framerate, stream_options, None)
+    }
+
+    /// Creates a stream of the default source whose frames are handed to `sink` on the capture thread, saving
+    /// the trip through the queue. With `SinkDelivery::SinkOnly` the stream still yields the other events.
+    pub fn create_sink_stream(
+        &mut self,
+        framerate: CaptureFramerate,
+        stream_options: StreamOptions,
+        sink: impl FrameSink + 'static,
+        delivery: SinkDelivery,
+    ) -> super::Result<WindowsCaptureStream> {
+        let id = self.default_source()?;
+        self.create_sink_stream_for(id, framerate, stream_options, sink, delivery)
+    }
+
+    /// Same as `create_sink_stream`, for a specific source.
+    pub fn create_sink_stream_for(
+        &mut self,
+        id: SourceId,
+        framerate: CaptureFramerate,
+        stream_options: StreamOptions,
+        sink: impl FrameSink + 'static,
+        delivery: SinkDelivery,
+    ) -> super::Result<WindowsCaptureStream> {
+        let sink = SinkSlot::new(Box::new(sink), delivery);
+        self.create_stream_inner(id, framerate, stream_options, Some(sink))
+    }
+
+    /// Creates a handle to the newest frame of the default source, for consumers that don't need every frame.
+    /// Unlike with a stream, frames the consumer didn't get to are replaced rather than queued.
+    pub fn create_latest_frame_handle(
+        &mut self,
+        framerate: CaptureFramerate,
+    ) -> super::Result<LatestFrameHandle> {
+        let id = self.default_source()?;
+        self.create_latest_frame_handle_for(id, framerate)
+    }
+
+    /// Same as `create_latest_frame_handle`, for a specific source.
+    pub fn create_latest_frame_handle_for(
+        &mut self,
+        id: SourceId,
+        framerate: CaptureFramerate,
+    ) -> super::Result<LatestFrameHandle> {
+        let (sink, latest) = LatestFrameHandle::sink();
+        // Nothing but the other events is queued on the stream.
+        let events = self.create_sink_stream_for(
+            id,
+            framerate,
+            StreamOptions::default(),
+            sink,
+            SinkDelivery::SinkOnly,
+        )?;
+        Ok(LatestFrameHandle::new(latest, events))
+    }
+
+    fn create_stream_inner(
+        &mut self,
+        id: SourceId,
+        framerate: CaptureFramerate,
+        stream_options: StreamOptions,
+        sink: Option<SinkSlot>,
+    ) -> super::Result<WindowsCaptureStream> {
+        self.detach_closed_streams();
+        let (tx, rx) = frame_channel(stream_options);
+
+        let mut resources = lock_resources(&self.resources);
+        let token = StreamToken { source: id, id: resources.next_stream_id };
+        resources.next_stream_id += 1;
+        let subscriber = Arc::new(Subscriber {
+            id: token.id,
+            tx: tx.clone(),
+            frame_limiter: FrameRateLimiter::new(framerate),
+            decimator: stream_options.target_framerate.map(FrameRateLimiter::new),
+            error_reported: AtomicBool::new(false),
+            last_frame: Mutex::new(None),
+            needs_full_frame: AtomicBool::new(true),
+            sink,
+        });
+
+        resources.pipelines.retain(|(_, pipeline)| pipeline.strong_count() > 0);
+        let options = self.frame_options;
+        let pipeline =
+            resources.pipelines.iter().filter_map(|(_, pipeline)| pipeline.upgrade()).find(
+                |pipeline| {
+                    pipeline.source == id
+                        && pipeline.options == options
+                        && same_tracer(&pipeline.frame_tracer, &self.frame_tracer)
+                },
+            );
+        let source = resources.source_mut(id)?;
+
+        match pipeline {
+            Some(pipeline) => {
+                tracing::debug!("Adding a {} stream to an existing pipeline.", framerate);
+                let mut subscribers =
+                    pipeline.subscribers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                subscribers.push(subscriber);
+            }
+            None => {
+                // We can't send self raw to the closure, so everything the handler needs is bundled up instead.
+                let context = Arc::new(PipelineContext {
+                    source: id,
+                    options,
+                    crop_region: self.crop_region.clone(),
+                    unchanged_filter: options.skip_unchanged_frames.then(|| {
+                        UnchangedFrameFilter::new(
+                            options.unchanged_pixel_threshold,
+                            options.unchanged_keepalive,
+                        )
+                    }),
+                    paused: source.paused_flag(),
+                    scaler: Mutex::new(None),
+                    gpu_conversion: AtomicBool::new(options.gpu_conversion),
+                    staging: Mutex::new(StagingRing::new(options.readback_depth)),
+                    counters: self.counters.clone(),
+                    trace_frames: self.trace_frames.clone(),
+                    frame_tracer: self.frame_tracer.clone(),
+                    observers: self.observers.clone(),
+                    clock: source.clock,
+                    next_sequence: AtomicU64::new(0),
+                    resources: Arc::downgrade(&self.resources),
+                    recovery: self.recovery.clone(),
+                    failed: AtomicBool::new(false),
+                    delta: Mutex::new(DeltaState::default()),
+                    buffer_pool: BufferPool::new(
+                        self.counters.clone(),
+                        options.buffer_pool_size,
+                        options.buffer_pool_max_bytes,
+                    ),
+                    subscribers: Mutex::new(vec![subscriber]),
+                });
+                let pipeline = Arc::downgrade(&context);
+                let handler = source.register_frame_arrived(Arc::new(
+                    move |frame: super::Result<Direct3D11CaptureFrame>, generation: u64| {
+                        Self::on_frame_arrived(&context, frame, generation);
+                    },
+                ))?;
+                resources.pipelines.push((handler, pipeline));
+            }
+        }
+
+        Self::refresh_update_interval(&mut resources, id)?;
+        let source = resources.source_mut(id)?;
+        source.stream_senders.push(tx.clone());
+        source.register_item_closed(tx)?;
+
+        Ok(WindowsCaptureStream::new(rx, token, self.closed_tx.clone(), self.frame_tracer.clone()))
+    }
+
+    /// Removes the streams that were dropped since the last call from their pipelines, and the FrameArrived
+    /// handlers of pipelines that have no streams left. Until then those handlers return before any D3D work.
+    /// Called from the provider's entry points, as streams can be dropped on any thread.
+    fn detach_closed_streams(&mut self) {
+        let tokens: Vec<_> = {
+            let closed_rx = self.closed_rx.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            closed_rx.try_iter().collect()
+        };
+        if tokens.is_empty() {
+            return;
+        }
+
+        let mut resources = lock_resources(&self.resources);
+        let CaptureResources { sources, pipelines, texture_streams, .. } = &mut *resources;
+        for &token in &tokens {
+            tracing::debug!("Detaching dropped stream {} of {:?}", token.id, token.source);
+            if let Some(index) =
+                texture_streams.iter().position(|texture_stream| texture_stream.token == token)
+            {
+                let texture_stream = texture_streams.remove(index);
+                if let Some(source) = sources.get_mut(&token.source) {
+                    source.unregister_frame_arrived(texture_stream.handler);
+                }
+                continue;
+            }
+            pipelines.retain(|(handler, pipeline)| {
+                let Some(pipeline) = pipeline.upgrade() else {
+                    return false;
+                };
+                if pipeline.source != token.source {
+                    return true;
+                }
+                let mut subscribers =
+                    pipeline.subscribers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                subscribers
+                    .retain(|subscriber| subscriber.id != token.id && !subscriber.tx.is_closed());
+                if !subscribers.is_empty() {
+                    return true;
+                }
+                if let Some(source) = sources.get_mut(&token.source) {
+                    source.unregister_frame_arrived(*handler);
+                }
+                false
+            });
+            if let Some(source) = sources.get_mut(&token.source) {
+                source.stream_senders.retain(|sender| !sender.is_closed());
+            }
+        }
+
+        // The dropped streams may have been the fastest of their sources.
+        let mut affected: Vec<_> = tokens.iter().map(|token| token.source).collect();
+        affected.sort();
+        affected.dedup();
+        for id in affected {
+            if !resources.sources.contains_key(&id) {
+                continue;
+            }
+            if let Err(err) = Self::refresh_update_interval(&mut resources, id) {
+                tracing::warn!("Failed to update the interval of {:?}: {}", id, err);
+            }
+        }
+    }
+}
+
+impl CaptureProvider for WindowsCaptureProvider {
+    type Stream = WindowsCaptureStream;
+    type LatestFrameHandle = LatestFrameHandle;
+    type CaptureItem = GraphicsCaptureItem;
+    type Error = WindowsCaptureError;
+
+    /// Creates a new stream for receiving frames of the default source. Streams of a source share one readback,
+    /// each skipping frames down to its own framerate.
+    fn create_stream(&mut self, framerate: CaptureFramerate) -> super::Result<Self::Stream> {
+        let id = self.default_source()?;
+        self.create_stream_for(id, framerate)
+    }
+
+    /// See `WindowsCaptureProvider::create_latest_frame_handle`.
+    fn create_latest_frame_handle(
+        &mut self,
+        framerate: CaptureFramerate,
+    ) -> super::Result<LatestFrameHandle> {
+        WindowsCaptureProvider::create_latest_frame_handle(self, framerate)
+    }
+
+    /// See `WindowsCaptureProvider::set_stream_framerate`, which fails with `UnknownStream` instead.
+    fn set_stream_framerate(
+        &mut self,
+        stream: StreamId,
+        framerate: CaptureFramerate,
+    ) -> super::Result<bool> {
+        match WindowsCaptureProvider::set_stream_framerate(self, stream, framerate) {
+            Ok(()) => Ok(true),
+            Err(WindowsCaptureError::UnknownStream(_)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Replaces the item of the default source. If it is capturing, it switches over right away and its streams
+    /// carry on with frames of the new item.
+    fn set_capture_item(&mut self, capture_item: Self::CaptureItem) -> super::Result<()> {
+        self.detach_closed_streams();
+        let Some(id) = self.default_source else {
+            self.default_source = Some(self.add_source(capture_item)?);
+            return Ok(());
+        };
+
+        tracing::info!(
+            "Switching capture item to: {}",
+            capture_item.DisplayName().unwrap_or("<no name>".into())
+        );
+        let resources = self.resources.clone();
+        let capture_item = UnsafeSendWrapper(capture_item);
+        on_com_thread(move || {
+            let mut resources = lock_resources(&resources);
+            let CaptureResources { device, sources, session_settings, .. } = &mut *resources;
+            let source = sources.get_mut(&id).ok_or(WindowsCaptureError::UnknownSource(id))?;
+            source.replace_item(device, capture_item.take_inner(), *session_settings)
+        })
+    }
+
+    /// Starts every source that isn't running yet, see `start_sources`. The sessions are started on the COM
+    /// thread while the caller waits on the future, without blocking its thread.
+    fn start_capture(&mut self) -> BoxFuture<'_, super::Result<()>> {
+        if !wgc_capabilities().supported {
+            return future::ready(Err(WindowsCaptureError::CaptureNotSupported)).boxed();
         }
-        self.active_handlers.clear();
+        self.detach_closed_streams();
+        let resources = self.resources.clone();
+        on_com_thread_async(move || start_all(&resources)).boxed()
+    }
+
+    /// Stops every running source on the COM thread, see `stop_sources`.
+    fn stop_capture(&mut self) -> BoxFuture<'_, super::Result<()>> {
+        self.detach_closed_streams();
+        let resources = self.resources.clone();
+        on_com_thread_async(move || stop_all(&resources)).boxed()
+    }
+
+    fn pause_capture(&mut self) -> super::Result<()> {
+        WindowsCaptureProvider::pause_capture(self)
+    }
+
+    fn resume_capture(&mut self) -> super::Result<()> {
+        WindowsCaptureProvider::resume_capture(self)
+    }
+
+    /// Whether this version of Windows allows disabling the yellow capture border.
+    fn supports_border_toggle() -> bool {
+        wgc_capabilities().border_toggle
+    }
 
-        self.session.take(); // Drop the old session
-        self.capturing = false;
+    /// Sets whether the cursor is included in captured frames.
+    /// Applied immediately to running sessions, otherwise when the next session is created.
+    fn set_cursor_capture(&mut self, enabled: bool) -> super::Result<()> {
+        tracing::debug!("Setting cursor capture enabled: {}", enabled);
+        let mut resources = lock_resources(&self.resources);
+        resources.session_settings.cursor_capture_enabled = enabled;
+        if !wgc_capabilities().cursor_toggle {
+            tracing::warn!("Cursor toggle is not supported on this version of Windows.");
+            return Ok(());
+        }
+        for session in resources.sources.values().filter_map(CaptureSource::session) {
+            session.SetIsCursorCaptureEnabled(enabled)?;
+        }
+        Ok(())
+    }
 
+    /// Sets whether the yellow capture border is drawn around the captured items.
+    /// Applied immediately to running sessions, otherwise when the next session is created.
+    fn set_border_required(&mut self, required: bool) -> super::Result<()> {
+        tracing::debug!("Setting border required: {}", required);
+        let mut resources = lock_resources(&self.resources);
+        resources.session_settings.border_required = required;
+        for session in resources.sources.values().filter_map(CaptureSource::session) {
+            apply_border_required(session, required);
+        }
         Ok(())
     }
+
+    /// Sets how frames are scaled on the GPU before readback, which makes readback and conversion of large
+    /// captures a lot cheaper. `Frame::size` is the scaled size. Takes effect for streams created after this call.
+    fn set_output_scale(&mut self, scale: ScaleMode) {
+        tracing::debug!("Setting output scale: {:?}", scale);
+        self.frame_options.scale = scale;
+    }
+
+    /// Restricts frames to a region of the item, in its pixels, before they are scaled. A region reaching past
+    /// the item is limited to the part within it, and frame sizes follow. Without scaling, only the region is
+    /// copied off the GPU. Applies to running streams, but not to texture streams.
+    fn set_crop_region(&mut self, region: Option<Rect<i32>>) {
+        tracing::debug!("Setting crop region: {:?}", region);
+        *self.crop_region.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = region;
+    }
+
+    /// Sets whether every delivered frame is logged at trace level under the `loki::frames` target.
+    /// Off by default, as logging at the capture rate measurably slows capture down. Applies to running streams.
+    fn set_trace_frames(&mut self, enabled: bool) {
+        tracing::debug!("Setting frame tracing: {}", enabled);
+        self.trace_frames.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns a snapshot of the counters across all streams of this provider.
+    fn stats(&self) -> CaptureStats {
+        CaptureStats { device_recoveries: self.recovery.recovered(), ..self.counters.snapshot() }
+    }
+
+    /// Describes the item of the default source, or `None` if no item is set.
+    fn capture_item_info(&self) -> Option<CaptureItemInfo> {
+        let id = self.default_source?;
+        self.source_info(id).ok()
+    }
+
+    /// See `WindowsCaptureProvider::capture_target`.
+    fn capture_target(&self) -> Option<CaptureTarget> {
+        WindowsCaptureProvider::capture_target(self)
+    }
 }
 
 impl Drop for WindowsCaptureProvider {
     fn drop(&mut self) {
-        self.stop_capture().ok();
+        self.stop_sources().ok();
     }
 }
 
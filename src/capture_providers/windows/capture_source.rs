@@ -0,0 +1,560 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use windows::{
+    Foundation::{TimeSpan, TypedEventHandler},
+    Graphics::{Capture::*, DirectX::Direct3D11::*, SizeInt32},
+};
+
+use crate::capture_providers::{
+    CaptureProvider,
+    shared::{CaptureEvent, EndReason, PixelFormat, ToDirectXPixelFormat},
+    windows::{
+        WindowsCaptureError, WindowsCaptureProvider,
+        advanced_color::warn_if_clipped,
+        capabilities::wgc_capabilities,
+        d3d11_utils::{frame_to_texture, native_to_winrt_d3d11device},
+        frame_channel::FrameSender,
+        qpc_clock::QpcClock,
+    },
+};
+
+/// Identifies one of the capture sources of a provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SourceId(pub(super) u64);
+
+/// Session options that are applied when a session is created, and live while one is running.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct SessionSettings {
+    pub cursor_capture_enabled: bool,
+    pub border_required: bool,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self { cursor_capture_enabled: true, border_required: true }
+    }
+}
+
+impl SessionSettings {
+    pub fn apply(&self, session: &GraphicsCaptureSession) -> super::Result<()> {
+        if wgc_capabilities().cursor_toggle {
+            session.SetIsCursorCaptureEnabled(self.cursor_capture_enabled)?;
+        }
+        apply_border_required(session, self.border_required);
+        Ok(())
+    }
+}
+
+pub(super) fn apply_border_required(session: &GraphicsCaptureSession, required: bool) {
+    if !WindowsCaptureProvider::supports_border_toggle() {
+        tracing::warn!("Border toggle is not supported on this version of Windows.");
+        return;
+    }
+
+    if let Err(err) = session.SetIsBorderRequired(required) {
+        tracing::warn!("Failed to set border required: {}", err);
+    }
+}
+
+/// Skipped where the session has no MinUpdateInterval, the streams then skip down to their own framerate.
+fn apply_min_update_interval(
+    session: &GraphicsCaptureSession,
+    interval: TimeSpan,
+) -> super::Result<()> {
+    if !wgc_capabilities().min_update_interval {
+        tracing::warn!("Min update interval is not supported on this version of Windows.");
+        return Ok(());
+    }
+    session.SetMinUpdateInterval(interval).map_err(WindowsCaptureError::SetMinUpdateIntervalFailed)
+}
+
+/// Called with every new frame and the generation of the capture item it came from, see `CaptureSource::replace_item`.
+/// Gets the error instead when the frame couldn't be taken from the frame pool.
+pub(super) type FrameCallback =
+    Arc<dyn Fn(super::Result<Direct3D11CaptureFrame>, u64) + Send + Sync>;
+
+/// A registered FrameArrived handler. The callback is kept so it can be registered again on a new frame pool.
+struct FrameHandler {
+    /// Stays the same when the handler is registered on a new frame pool, unlike the token.
+    id: u64,
+    token: i64,
+    callback: FrameCallback,
+}
+
+impl std::fmt::Debug for FrameHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrameHandler")
+            .field("id", &self.id)
+            .field("token", &self.token)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A single captured window or monitor, with its own frame pool and session.
+#[derive(Debug)]
+pub(super) struct CaptureSource {
+    pub capture_item: GraphicsCaptureItem, /* Free-threaded object */
+    frame_pool: Option<Direct3D11CaptureFramePool>, /* Free-threaded object */
+    /// Format of the frame pool, see `frame_pool_format`.
+    pixel_format: PixelFormat,
+    /// Number of buffers in the frame pool.
+    pipeline_depth: i32,
+    /// Size of the frame pool buffers. Shared with the frame handlers, which recreate the pool on resize.
+    pool_size: Arc<Mutex<FramePoolSize>>,
+    session: Option<GraphicsCaptureSession>, /* Free-threaded object */
+
+    frame_handlers: Vec<FrameHandler>,
+    next_handler_id: u64,
+    item_closed_handlers: Vec<i64>,
+    /// Streams that get told about device recovery.
+    pub stream_senders: Vec<FrameSender>,
+    min_update_interval: Option<TimeSpan>,
+    /// Baseline for translating frame times, taken when the session starts.
+    pub clock: QpcClock,
+    capturing: bool,
+    /// Set while the session runs but its frames are dropped, shared with the pipelines of this source.
+    paused: Arc<AtomicBool>,
+    /// Incremented whenever the capture item is replaced, so frames of the previous item can be told apart.
+    generation: u64,
+    /// Frames that arrived on any frame pool of this source, for telling when a session went quiet.
+    frames_arrived: Arc<AtomicU64>,
+}
+
+impl CaptureSource {
+    pub fn new(
+        device: &IDirect3DDevice,
+        capture_item: GraphicsCaptureItem,
+        pixel_format: PixelFormat,
+        pipeline_depth: i32,
+    ) -> super::Result<Self> {
+        let pool_size = capture_item.Size()?;
+        warn_if_clipped(&capture_item, pixel_format);
+        let frame_pool = create_frame_pool(device, pool_size, pixel_format, pipeline_depth)?;
+        Ok(Self {
+            capture_item,
+            frame_pool: Some(frame_pool),
+            pixel_format,
+            pipeline_depth,
+            pool_size: Arc::new(Mutex::new(FramePoolSize::new(pool_size))),
+            session: None,
+            frame_handlers: Vec::new(),
+            next_handler_id: 0,
+            item_closed_handlers: Vec::new(),
+            stream_senders: Vec::new(),
+            min_update_interval: None,
+            clock: QpcClock::now(),
+            capturing: false,
+            paused: Arc::new(AtomicBool::new(false)),
+            generation: 0,
+            frames_arrived: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capturing
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// The flag the pipelines of this source check before handling a frame.
+    pub fn paused_flag(&self) -> Arc<AtomicBool> {
+        self.paused.clone()
+    }
+
+    pub fn has_frame_handlers(&self) -> bool {
+        !self.frame_handlers.is_empty()
+    }
+
+    pub fn session(&self) -> Option<&GraphicsCaptureSession> {
+        self.session.as_ref()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn frames_arrived(&self) -> u64 {
+        self.frames_arrived.load(Ordering::Relaxed)
+    }
+
+    pub fn start(&mut self, settings: SessionSettings) -> super::Result<()> {
+        if self.capturing {
+            return match self.is_paused() {
+                true => Err(WindowsCaptureError::Paused),
+                false => Err(WindowsCaptureError::AlreadyCapturing),
+            };
+        }
+
+        let frame_pool = match &self.frame_pool {
+            Some(frame_pool) => frame_pool,
+            None => {
+                tracing::error!("No frame pool set!");
+                return Err(WindowsCaptureError::NoFramePool);
+            }
+        };
+
+        let session = match &self.session {
+            Some(session) => session,
+            None => {
+                let new_session = frame_pool.CreateCaptureSession(&self.capture_item)?;
+                settings.apply(&new_session)?;
+                if let Some(interval) = self.min_update_interval {
+                    apply_min_update_interval(&new_session, interval)?;
+                }
+                self.session = Some(new_session);
+                self.session.as_ref().unwrap()
+            }
+        };
+
+        session.StartCapture()?;
+        self.clock = QpcClock::now();
+        self.capturing = true;
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> super::Result<()> {
+        if !self.capturing {
+            return Err(WindowsCaptureError::NotCapturing);
+        }
+
+        if let Some(frame_pool) = &self.frame_pool {
+            for handler in &self.frame_handlers {
+                if let Err(e) = frame_pool.RemoveFrameArrived(handler.token) {
+                    tracing::warn!("Failed to remove frame handler during stop: {}", e);
+                }
+            }
+        }
+        self.frame_handlers.clear();
+        // Consumers awaiting the next frame would otherwise wait until COM releases the handlers.
+        for sender in self.stream_senders.drain(..) {
+            sender.close();
+        }
+        self.unregister_item_closed_handlers();
+
+        self.session.take(); // Drop the old session
+        self.capturing = false;
+        self.paused.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Drops frames from now on, keeping the session, the frame pool and the streams alive.
+    pub fn pause(&mut self) -> super::Result<()> {
+        if !self.capturing {
+            return Err(WindowsCaptureError::NotCapturing);
+        }
+        if self.paused.swap(true, Ordering::Relaxed) {
+            return Err(WindowsCaptureError::Paused);
+        }
+        Ok(())
+    }
+
+    pub fn resume(&mut self) -> super::Result<()> {
+        if !self.capturing {
+            return Err(WindowsCaptureError::NotCapturing);
+        }
+        if !self.paused.swap(false, Ordering::Relaxed) {
+            return Err(WindowsCaptureError::NotPaused);
+        }
+        Ok(())
+    }
+
+    /// Applied right away to a running session, otherwise when the next one is created.
+    pub fn set_min_update_interval(&mut self, interval: TimeSpan) -> super::Result<()> {
+        self.min_update_interval = Some(interval);
+        let Some(session) = &self.session else {
+            return Ok(());
+        };
+        apply_min_update_interval(session, interval).inspect_err(|err| {
+            tracing::error!("Failed to set min update interval: {}", err);
+        })
+    }
+
+    /// Registers a FrameArrived handler on the frame pool, which gets called with every new frame.
+    /// Returns an id to remove the handler with, see `unregister_frame_arrived`.
+    pub fn register_frame_arrived(&mut self, callback: FrameCallback) -> super::Result<u64> {
+        let frame_pool = match &self.frame_pool {
+            Some(frame_pool) => frame_pool,
+            None => {
+                tracing::error!("No frame pool available!");
+                return Err(WindowsCaptureError::NoFramePool);
+            }
+        };
+
+        let token = add_frame_arrived(
+            frame_pool,
+            self.pool_size.clone(),
+            self.pixel_format,
+            self.pipeline_depth,
+            callback.clone(),
+            self.generation,
+            self.frames_arrived.clone(),
+        )?;
+        let id = self.next_handler_id;
+        self.next_handler_id += 1;
+        self.frame_handlers.push(FrameHandler { id, token, callback });
+        Ok(id)
+    }
+
+    /// Removes a FrameArrived handler, so it is neither called anymore nor registered again on a new frame pool.
+    /// Handlers that are already gone, e.g. because the source was stopped, are ignored.
+    pub fn unregister_frame_arrived(&mut self, id: u64) {
+        let Some(index) = self.frame_handlers.iter().position(|handler| handler.id == id) else {
+            return;
+        };
+        let handler = self.frame_handlers.remove(index);
+        if let Some(frame_pool) = &self.frame_pool
+            && let Err(err) = frame_pool.RemoveFrameArrived(handler.token)
+        {
+            tracing::warn!("Failed to remove frame handler: {}", err);
+        }
+    }
+
+    /// Lets the consumer know when the captured window or monitor goes away, as frames just stop arriving otherwise.
+    pub fn register_item_closed(&mut self, tx: FrameSender) -> super::Result<()> {
+        let closed_token =
+            self.capture_item.Closed(&TypedEventHandler::new(move |_item, _args| {
+                tracing::info!("Capture item closed.");
+                if tx.send_event(CaptureEvent::Ended(EndReason::SourceClosed)).is_err() {
+                    tracing::debug!("Stream receiver dropped before capture item closed.");
+                }
+                Ok(())
+            }))?;
+        self.item_closed_handlers.push(closed_token);
+        Ok(())
+    }
+
+    fn unregister_item_closed_handlers(&mut self) {
+        for token in std::mem::take(&mut self.item_closed_handlers) {
+            if let Err(err) = self.capture_item.RemoveClosed(token) {
+                tracing::warn!("Failed to remove capture item closed handler: {}", err);
+            }
+        }
+    }
+
+    /// Tears down the frame pool and session, keeping the frame handlers around to register them again.
+    pub fn close_pipeline(&mut self) {
+        if let Some(frame_pool) = self.frame_pool.take() {
+            for handler in &self.frame_handlers {
+                if let Err(err) = frame_pool.RemoveFrameArrived(handler.token) {
+                    tracing::debug!("Failed to remove frame handler from old frame pool: {}", err);
+                }
+            }
+            if let Err(err) = frame_pool.Close() {
+                tracing::debug!("Failed to close old frame pool: {}", err);
+            }
+        }
+
+        if let Some(session) = self.session.take()
+            && let Err(err) = session.Close()
+        {
+            tracing::debug!("Failed to close old capture session: {}", err);
+        }
+    }
+
+    /// Recreates the frame pool and session on the given device, registering every frame handler again.
+    pub fn recreate_pipeline(
+        &mut self,
+        device: &IDirect3DDevice,
+        settings: SessionSettings,
+    ) -> super::Result<()> {
+        self.close_pipeline();
+
+        // The item may have been resized, or be a different item altogether.
+        let pool_size = self.capture_item.Size()?;
+        warn_if_clipped(&self.capture_item, self.pixel_format);
+        let frame_pool =
+            create_frame_pool(device, pool_size, self.pixel_format, self.pipeline_depth)?;
+        *lock_pool_size(&self.pool_size) = FramePoolSize::new(pool_size);
+        for handler in &mut self.frame_handlers {
+            handler.token = add_frame_arrived(
+                &frame_pool,
+                self.pool_size.clone(),
+                self.pixel_format,
+                self.pipeline_depth,
+                handler.callback.clone(),
+                self.generation,
+                self.frames_arrived.clone(),
+            )?;
+        }
+        self.frame_pool = Some(frame_pool);
+
+        if self.capturing {
+            let frame_pool = self.frame_pool.as_ref().expect("Frame pool was just created");
+            let session = frame_pool.CreateCaptureSession(&self.capture_item)?;
+            settings.apply(&session)?;
+            if let Some(interval) = self.min_update_interval {
+                apply_min_update_interval(&session, interval)?;
+            }
+            session.StartCapture()?;
+            self.session = Some(session);
+        }
+
+        Ok(())
+    }
+
+    /// Switches to another window or monitor, keeping the streams and frame handlers of this source.
+    /// Streams drop queued and late frames of the old item, so they never see one after a frame of the new item.
+    pub fn replace_item(
+        &mut self,
+        device: &IDirect3DDevice,
+        capture_item: GraphicsCaptureItem,
+        settings: SessionSettings,
+    ) -> super::Result<()> {
+        self.generation += 1;
+        for sender in &self.stream_senders {
+            sender.advance_generation(self.generation);
+        }
+
+        self.unregister_item_closed_handlers();
+        self.capture_item = capture_item;
+        self.recreate_pipeline(device, settings)?;
+
+        for sender in self.stream_senders.clone() {
+            self.register_item_closed(sender)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CaptureSource {
+    fn drop(&mut self) {
+        if self.capturing {
+            self.stop().ok();
+        }
+        self.unregister_item_closed_handlers();
+    }
+}
+
+// Same as the provider, the COM objects are agile but hold raw pointers.
+unsafe impl Send for CaptureSource {}
+
+fn create_frame_pool(
+    device: &IDirect3DDevice,
+    size: SizeInt32,
+    pixel_format: PixelFormat,
+    pipeline_depth: i32,
+) -> super::Result<Direct3D11CaptureFramePool> {
+    let frame_pool = Direct3D11CaptureFramePool::CreateFreeThreaded(
+        device,
+        pixel_format.to_directx_pixel_format(),
+        pipeline_depth,
+        size,
+    )?;
+    Ok(frame_pool)
+}
+
+/// Size of the frame pool buffers, and when the frame handler last recreated them.
+#[derive(Debug, Clone, Copy)]
+struct FramePoolSize {
+    size: SizeInt32,
+    resized_at: Option<Instant>,
+}
+
+impl FramePoolSize {
+    /// While a window is being dragged to a new size, it changes with nearly every frame. The pool follows at most
+    /// this often, frames in between are cut to their content.
+    const RESIZE_DEBOUNCE: Duration = Duration::from_millis(100);
+
+    fn new(size: SizeInt32) -> Self {
+        Self { size, resized_at: None }
+    }
+}
+
+fn lock_pool_size(pool_size: &Mutex<FramePoolSize>) -> std::sync::MutexGuard<'_, FramePoolSize> {
+    pool_size.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Recreates the frame pool buffers if the content no longer fits them, e.g. after the window was resized.
+/// Frames keep the old size until the new buffers are used, so the frame that noticed is still delivered.
+fn resize_frame_pool_if_needed(
+    frame_pool: &Direct3D11CaptureFramePool,
+    pool_size: &Mutex<FramePoolSize>,
+    pixel_format: PixelFormat,
+    pipeline_depth: i32,
+    frame: &Direct3D11CaptureFrame,
+) -> super::Result<()> {
+    let content_size = frame.ContentSize()?;
+    let mut pool_size = lock_pool_size(pool_size);
+    if pool_size.size == content_size {
+        return Ok(());
+    }
+    if pool_size
+        .resized_at
+        .is_some_and(|resized_at| resized_at.elapsed() < FramePoolSize::RESIZE_DEBOUNCE)
+    {
+        return Ok(());
+    }
+
+    tracing::debug!(
+        "Capture item resized: {} x {} -> {} x {}",
+        pool_size.size.Width,
+        pool_size.size.Height,
+        content_size.Width,
+        content_size.Height
+    );
+    // The frame pool doesn't expose its device, but the frame textures come from it.
+    let device = unsafe { frame_to_texture(frame)?.GetDevice()? };
+    frame_pool.Recreate(
+        &native_to_winrt_d3d11device(&device)?,
+        pixel_format.to_directx_pixel_format(),
+        pipeline_depth,
+        content_size,
+    )?;
+    *pool_size = FramePoolSize { size: content_size, resized_at: Some(Instant::now()) };
+    Ok(())
+}
+
+fn add_frame_arrived(
+    frame_pool: &Direct3D11CaptureFramePool,
+    pool_size: Arc<Mutex<FramePoolSize>>,
+    pixel_format: PixelFormat,
+    pipeline_depth: i32,
+    on_frame: FrameCallback,
+    generation: u64,
+    frames_arrived: Arc<AtomicU64>,
+) -> super::Result<i64> {
+    let frame_arrived_token =
+        frame_pool.FrameArrived(&TypedEventHandler::new(move |sender, _args| {
+            let sender = match &*sender {
+                Some(sender) => sender,
+                None => {
+                    tracing::error!("No sender provided with FrameArrived!");
+                    return Ok(());
+                }
+            };
+            let sender: &Direct3D11CaptureFramePool = sender;
+            frames_arrived.fetch_add(1, Ordering::Relaxed);
+
+            let frame = match sender.TryGetNextFrame() {
+                Ok(frame) => frame,
+                Err(err) => {
+                    tracing::error!("Failed to get next frame: {}", err);
+                    on_frame(Err(err.into()), generation);
+                    return Ok(());
+                }
+            };
+
+            if let Err(err) = resize_frame_pool_if_needed(
+                sender,
+                &pool_size,
+                pixel_format,
+                pipeline_depth,
+                &frame,
+            ) {
+                tracing::error!("Failed to recreate frame pool after resize: {}", err);
+            }
+
+            on_frame(Ok(frame), generation);
+            Ok(())
+        }))?;
+
+    Ok(frame_arrived_token)
+}
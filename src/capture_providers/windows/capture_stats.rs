@@ -0,0 +1,89 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::capture_providers::shared::{CaptureStats, LatencyStats, Vector2};
+
+/// Counters updated from the FrameArrived handlers of all streams of a provider.
+#[derive(Debug, Default)]
+pub(super) struct CaptureCounters {
+    pub frames_arrived: AtomicU64,
+    pub frames_delivered: AtomicU64,
+    pub frames_dropped: AtomicU64,
+    pub frames_skipped_unchanged: AtomicU64,
+    pub frames_skipped_rate_limit: AtomicU64,
+    pub buffer_pool_hits: AtomicU64,
+    pub buffer_pool_misses: AtomicU64,
+    pub buffer_pool_bytes: AtomicU64,
+    pub readback_stalls: AtomicU64,
+    pub delivery_latency: LatencyWindow,
+    pub display_latency: LatencyWindow,
+    /// Size of the last delivered frame, packed as width in the high and height in the low 32 bits.
+    last_frame_size: AtomicU64,
+}
+
+impl CaptureCounters {
+    pub fn set_last_frame_size(&self, size: Vector2<i32>) {
+        let packed = ((size.x as u32 as u64) << 32) | size.y as u32 as u64;
+        self.last_frame_size.store(packed, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> CaptureStats {
+        let packed = self.last_frame_size.load(Ordering::Relaxed);
+        CaptureStats {
+            frames_arrived: self.frames_arrived.load(Ordering::Relaxed),
+            frames_delivered: self.frames_delivered.load(Ordering::Relaxed),
+            frames_dropped: self.frames_dropped.load(Ordering::Relaxed),
+            frames_skipped_unchanged: self.frames_skipped_unchanged.load(Ordering::Relaxed),
+            frames_skipped_rate_limit: self.frames_skipped_rate_limit.load(Ordering::Relaxed),
+            buffer_pool_hits: self.buffer_pool_hits.load(Ordering::Relaxed),
+            buffer_pool_misses: self.buffer_pool_misses.load(Ordering::Relaxed),
+            buffer_pool_bytes: self.buffer_pool_bytes.load(Ordering::Relaxed),
+            readback_stalls: self.readback_stalls.load(Ordering::Relaxed),
+            // Counted by the device recovery, which the provider fills in.
+            device_recoveries: 0,
+            last_frame_size: Vector2::new((packed >> 32) as u32 as i32, packed as u32 as i32),
+            delivery_latency: self.delivery_latency.stats(),
+            display_latency: self.display_latency.stats(),
+        }
+    }
+}
+
+/// The latencies of the last delivered frames, so the stats follow changes in load rather than averaging over
+/// the whole capture.
+#[derive(Debug, Default)]
+pub(super) struct LatencyWindow {
+    samples: Mutex<VecDeque<Duration>>,
+}
+
+impl LatencyWindow {
+    /// A few seconds worth of frames at common framerates.
+    const LEN: usize = 256;
+
+    pub fn record(&self, latency: Duration) {
+        let mut samples = self.samples.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if samples.len() == Self::LEN {
+            samples.pop_front();
+        }
+        samples.push_back(latency);
+    }
+
+    pub fn stats(&self) -> LatencyStats {
+        let mut sorted: Vec<Duration> = {
+            let samples = self.samples.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            samples.iter().copied().collect()
+        };
+        if sorted.is_empty() {
+            return LatencyStats::default();
+        }
+        sorted.sort_unstable();
+        // Nearest rank, so the percentiles are always a latency that was actually measured.
+        let percentile = |percent: usize| sorted[(sorted.len() * percent).div_ceil(100).max(1) - 1];
+        LatencyStats { p50: percentile(50), p95: percentile(95), max: sorted[sorted.len() - 1] }
+    }
+}
@@ -1,25 +1,97 @@
+use std::{
+    sync::{Arc, mpsc::Sender},
+    task::Poll,
+    time::Instant,
+};
+
 use futures::Stream;
+use tokio::sync::watch;
+
+use crate::capture_providers::{
+    CaptureStream,
+    shared::{CaptureEvent, FrameTracer, Stage, StreamId},
+    windows::{
+        SourceId,
+        frame_channel::{FrameReceiver, StreamStats},
+    },
+};
 
-use crate::capture_providers::shared::Frame;
+/// Identifies a stream to the provider, which detaches it once the stream is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct StreamToken {
+    pub source: SourceId,
+    pub id: u64,
+}
 
+/// Ends when the capture is stopped, after yielding the events queued before `stop_capture`.
 #[derive(Debug)]
 pub struct WindowsCaptureStream {
-    channel: tokio::sync::mpsc::Receiver<Frame>,
+    channel: FrameReceiver,
+    token: StreamToken,
+    /// Where the token goes when the stream is dropped.
+    closed: Sender<StreamToken>,
+    frame_tracer: Option<Arc<dyn FrameTracer>>,
 }
 
 impl WindowsCaptureStream {
-    pub fn new(channel: tokio::sync::mpsc::Receiver<Frame>) -> Self {
-        Self { channel }
+    pub(super) fn new(
+        channel: FrameReceiver,
+        token: StreamToken,
+        closed: Sender<StreamToken>,
+        frame_tracer: Option<Arc<dyn FrameTracer>>,
+    ) -> Self {
+        Self { channel, token, closed, frame_tracer }
+    }
+
+    /// Identifies the stream to the provider, see `WindowsCaptureProvider::set_stream_framerate`.
+    pub fn id(&self) -> StreamId {
+        StreamId(self.token.id)
+    }
+
+    /// Number of frames lost to the backpressure policy so far.
+    pub fn dropped_frames(&self) -> u64 {
+        self.channel.dropped_frames()
+    }
+
+    /// Frames queued and dropped so far, see `StreamStats`.
+    pub fn stats(&self) -> StreamStats {
+        self.channel.stats()
+    }
+
+    /// The stats of the stream as they change. Watchers are only woken when a frame is dropped, e.g. to tell the
+    /// user the consumer is too slow, but the value is always current. Keeps working after the stream is
+    /// dropped, so frames dropped for a closed stream show up too.
+    pub fn watch_stats(&self) -> watch::Receiver<StreamStats> {
+        self.channel.watch_stats()
     }
 }
 
 impl Stream for WindowsCaptureStream {
-    type Item = Frame;
+    type Item = CaptureEvent;
 
     fn poll_next(
         mut self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Self::Item>> {
-        self.channel.poll_recv(cx)
+    ) -> Poll<Option<Self::Item>> {
+        let poll = self.channel.poll_recv(cx);
+        if let (Some(tracer), Poll::Ready(Some(CaptureEvent::Frame(frame)))) =
+            (&self.frame_tracer, &poll)
+        {
+            tracer.record(frame.sequence, Stage::Received, Instant::now());
+        }
+        poll
+    }
+}
+
+impl CaptureStream for WindowsCaptureStream {
+    fn id(&self) -> StreamId {
+        WindowsCaptureStream::id(self)
+    }
+}
+
+impl Drop for WindowsCaptureStream {
+    fn drop(&mut self) {
+        // Nothing left to detach from if the provider is gone.
+        let _ = self.closed.send(self.token);
     }
 }
@@ -1,23 +1,25 @@
-use std::mem::MaybeUninit;
+use std::{mem::MaybeUninit, ops::Range};
 
 use windows::{
     Graphics::{
-        Capture::{GraphicsCaptureItem, GraphicsCapturePicker},
+        Capture::{Direct3D11CaptureFrame, GraphicsCaptureItem, GraphicsCapturePicker},
         DirectX::Direct3D11::IDirect3DDevice,
     },
     Win32::{
         Foundation::{HMODULE, HWND},
         Graphics::{
             Direct3D::{
-                D3D_DRIVER_TYPE_HARDWARE, D3D_FEATURE_LEVEL, D3D_FEATURE_LEVEL_10_0,
+                D3D_DRIVER_TYPE, D3D_DRIVER_TYPE_HARDWARE, D3D_DRIVER_TYPE_UNKNOWN,
+                D3D_DRIVER_TYPE_WARP, D3D_FEATURE_LEVEL, D3D_FEATURE_LEVEL_10_0,
                 D3D_FEATURE_LEVEL_10_1, D3D_FEATURE_LEVEL_11_0, D3D_FEATURE_LEVEL_11_1,
             },
             Direct3D11::{
-                D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_MAP_READ, D3D11_SDK_VERSION,
+                D3D11_BOX, D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_CREATE_DEVICE_VIDEO_SUPPORT,
+                D3D11_MAP_FLAG_DO_NOT_WAIT, D3D11_MAP_READ, D3D11_SDK_VERSION,
                 D3D11_TEXTURE2D_DESC, D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext,
                 ID3D11Texture2D,
             },
-            Dxgi::IDXGIDevice,
+            Dxgi::{DXGI_ERROR_WAS_STILL_DRAWING, IDXGIAdapter, IDXGIDevice},
         },
         System::WinRT::Direct3D11::{
             CreateDirect3D11DeviceFromDXGIDevice, IDirect3DDxgiInterfaceAccess,
@@ -27,9 +29,43 @@ use windows::{
 };
 use windows_core::*;
 
-pub(super) fn create_d3d_device() -> Result<ID3D11Device> {
+use crate::{
+    capture_providers::{
+        shared::{PixelFormat, Rect, Vector2},
+        windows::ReadbackMode,
+    },
+    utils::{
+        com_thread::{Apartment, ComThread},
+        unsafe_send_wrapper::UnsafeSendWrapper,
+    },
+};
+
+/// Creates a device on the adapter, or on the default one. Falls back to WARP if no hardware device can be
+/// created, e.g. without a working GPU driver, which captures but is slow.
+pub(super) fn create_d3d_device(adapter: Option<&IDXGIAdapter>) -> Result<ID3D11Device> {
     tracing::debug!("Creating D3D11 device...");
-    // We’ll request hardware device with default feature levels.
+    // An explicit adapter brings its own driver.
+    let driver_type = match adapter {
+        Some(_) => D3D_DRIVER_TYPE_UNKNOWN,
+        None => D3D_DRIVER_TYPE_HARDWARE,
+    };
+    match create_d3d_device_with(adapter, driver_type) {
+        Ok(device) => Ok(device),
+        Err(err) => {
+            tracing::warn!(
+                "Failed to create a hardware D3D11 device, falling back to WARP: {}",
+                err
+            );
+            create_d3d_device_with(None, D3D_DRIVER_TYPE_WARP).map_err(|_| err)
+        }
+    }
+}
+
+fn create_d3d_device_with(
+    adapter: Option<&IDXGIAdapter>,
+    driver_type: D3D_DRIVER_TYPE,
+) -> Result<ID3D11Device> {
+    // We’ll request default feature levels.
     const FEATURE_LEVELS: &[D3D_FEATURE_LEVEL] = &[
         D3D_FEATURE_LEVEL_11_1,
         D3D_FEATURE_LEVEL_11_0,
@@ -43,11 +79,12 @@ pub(super) fn create_d3d_device() -> Result<ID3D11Device> {
 
     unsafe {
         D3D11CreateDevice(
-            None, // adapter
-            D3D_DRIVER_TYPE_HARDWARE,
-            HMODULE(std::ptr::null_mut()),    // no software rasterizer
-            D3D11_CREATE_DEVICE_BGRA_SUPPORT, // flags
-            Some(FEATURE_LEVELS),             // feature levels
+            adapter,
+            driver_type,
+            HMODULE(std::ptr::null_mut()), // no software rasterizer
+            // Video support is needed for scaling frames with the video processor.
+            D3D11_CREATE_DEVICE_BGRA_SUPPORT | D3D11_CREATE_DEVICE_VIDEO_SUPPORT,
+            Some(FEATURE_LEVELS), // feature levels
             D3D11_SDK_VERSION,
             Some(&mut device),
             Some(&mut chosen_level),
@@ -55,7 +92,11 @@ pub(super) fn create_d3d_device() -> Result<ID3D11Device> {
         )?;
     }
 
-    tracing::info!("D3D11 device created successfully with feature level: {:?}", chosen_level);
+    tracing::info!(
+        "D3D11 device created successfully with driver type {:?} and feature level: {:?}",
+        driver_type,
+        chosen_level
+    );
 
     Ok(device.expect("ID3D11Device"))
 }
@@ -66,7 +107,6 @@ pub(super) fn native_to_winrt_d3d11device(device: &ID3D11Device) -> Result<IDire
     unsafe { CreateDirect3D11DeviceFromDXGIDevice(&dxgi_device)?.cast() }
 }
 
-#[allow(dead_code)]
 pub(super) fn winrt_to_native_d3d11device(device: &IDirect3DDevice) -> Result<ID3D11Device> {
     tracing::trace!("Converting WinRT D3D11 device to native D3D11 device");
     // WinRT device implements IDirect3DDxgiInterfaceAccess, which lets you retrieve
@@ -80,7 +120,29 @@ pub(super) fn winrt_to_native_d3d11device(device: &IDirect3DDevice) -> Result<ID
     }
 }
 
-pub(crate) trait IntoHWND {
+/// Gets the texture backing a captured frame.
+pub(super) fn frame_to_texture(frame: &Direct3D11CaptureFrame) -> Result<ID3D11Texture2D> {
+    // Direct3D11CaptureFrame → IDirect3DSurface → IDirect3DDxgiInterfaceAccess → ID3D11Texture2D
+    let access: IDirect3DDxgiInterfaceAccess = frame.Surface()?.cast()?;
+    unsafe { access.GetInterface() }
+}
+
+/// Reclassifies an error as device loss if the device has been removed, as failed D3D11 calls don't always
+/// report the removal themselves.
+pub(super) fn detect_device_loss(
+    device: &ID3D11Device,
+    err: super::WindowsCaptureError,
+) -> super::WindowsCaptureError {
+    match unsafe { device.GetDeviceRemovedReason() } {
+        Ok(()) => err,
+        Err(reason) => {
+            tracing::warn!("D3D11 device removed, reason: {}", reason);
+            super::WindowsCaptureError::DeviceLost(reason)
+        }
+    }
+}
+
+pub trait IntoHWND {
     fn into_hwnd(self) -> HWND;
 }
 
@@ -98,17 +160,22 @@ impl IntoHWND for u64 {
 
 /// Shows a dialog in the specified window to pick an item to capture.
 /// Returned future completes when the user picks an item or cancels the dialog.
+/// The picker is a UI object, so it lives on the shared single-threaded COM thread whatever the caller's thread.
 pub fn user_pick_capture_item(
     window: impl IntoHWND,
-) -> Result<impl Future<Output = Result<GraphicsCaptureItem>>> {
-    tracing::info!("Initializing GraphicsCapturePicker...");
-    let picker = GraphicsCapturePicker::new()?;
-    let init_with_window: IInitializeWithWindow = picker.cast()?;
-    unsafe { init_with_window.Initialize(window.into_hwnd())? };
+) -> super::Result<impl Future<Output = Result<GraphicsCaptureItem>>> {
+    let window = UnsafeSendWrapper(window.into_hwnd());
+    let operation = ComThread::shared(Apartment::SingleThreaded)?.run_blocking(move || {
+        tracing::info!("Initializing GraphicsCapturePicker...");
+        let picker = GraphicsCapturePicker::new()?;
+        let init_with_window: IInitializeWithWindow = picker.cast()?;
+        unsafe { init_with_window.Initialize(window.take_inner())? };
+        picker.PickSingleItemAsync().map(UnsafeSendWrapper)
+    })??;
 
     tracing::info!("Waiting for user to pick capture item...");
     let item_future = async move {
-        let result = picker.PickSingleItemAsync()?.await;
+        let result = operation.take_inner().await;
         match &result {
             Ok(item) => tracing::info!(
                 "User picked capture item: {:?}",
@@ -121,35 +188,239 @@ pub fn user_pick_capture_item(
     Ok(item_future)
 }
 
+/// Queues a GPU copy of `region` of the source texture into the top left corner of the destination, or of all of
+/// it if `None`. Only the region is copied, so a crop costs less than the full frame rather than more.
+pub(super) fn copy_texture_region(
+    context: &ID3D11DeviceContext,
+    dest_tex: &ID3D11Texture2D,
+    source_tex: &ID3D11Texture2D,
+    region: Option<Rect<i32>>,
+) {
+    match region {
+        None => unsafe { context.CopyResource(dest_tex, source_tex) },
+        Some(region) => {
+            let end = region.end();
+            let source_box = D3D11_BOX {
+                left: region.position.x as u32,
+                top: region.position.y as u32,
+                front: 0,
+                right: end.x as u32,
+                bottom: end.y as u32,
+                back: 1,
+            };
+            unsafe {
+                context.CopySubresourceRegion(
+                    dest_tex,
+                    0,
+                    0,
+                    0,
+                    0,
+                    source_tex,
+                    0,
+                    Some(&source_box as *const D3D11_BOX),
+                )
+            };
+        }
+    }
+}
+
+/// Copies the source texture, or `region` of it, into CPU memory through the staging texture, waiting for the
+/// GPU to finish the copy. `dst` is resized to fit what was copied, and the number of bytes written is returned
+/// together with the row stride.
 pub(super) fn read_texture(
     context: &ID3D11DeviceContext,
     source_tex: ID3D11Texture2D,
+    region: Option<Rect<i32>>,
     staging_tex: ID3D11Texture2D,
-    tex_desc: &D3D11_TEXTURE2D_DESC,
-    bytes_per_pixel: u32,
-) -> super::Result<Vec<u8>> {
+    format: PixelFormat,
+    mode: ReadbackMode,
+    dst: &mut Vec<u8>,
+) -> super::Result<(usize, usize)> {
+    // The dimensions come from the textures themselves, as callers may only know the size of the content.
+    let mut source_desc = D3D11_TEXTURE2D_DESC::default();
+    let mut staging_desc = D3D11_TEXTURE2D_DESC::default();
     unsafe {
-        context.CopyResource(&staging_tex, &source_tex);
+        source_tex.GetDesc(&mut source_desc);
+        staging_tex.GetDesc(&mut staging_desc);
+    }
+    let copied = match region {
+        Some(region) => region.size.cast::<u32>(),
+        None => Vector2::new(source_desc.Width, source_desc.Height),
+    };
+    if (copied.x, copied.y) != (staging_desc.Width, staging_desc.Height) {
+        // CopyResource silently does nothing for textures of different sizes.
+        return Err(super::WindowsCaptureError::TextureSizeMismatch {
+            captured: copied,
+            staging: Vector2::new(staging_desc.Width, staging_desc.Height),
+        });
+    }
 
+    copy_texture_region(context, &staging_tex, &source_tex, region);
+    let stride = read_staging_texture(context, &staging_tex, format, mode, None, true, dst)?
+        .expect("Waiting maps always complete");
+    Ok((dst.len(), stride))
+}
+
+/// Sets the length of a buffer that is about to be overwritten completely. Pooled buffers keep the length and
+/// contents of their last frame, so only bytes beyond that are zeroed, rather than the whole buffer every frame.
+fn resize_for_overwrite(dst: &mut Vec<u8>, len: usize) {
+    if dst.len() >= len {
+        dst.truncate(len);
+    } else {
+        dst.resize(len, 0);
+    }
+}
+
+/// Copies a staging texture the GPU copied a frame into to CPU memory, and returns the row stride of `dst`.
+/// Only `rows` are copied if given, down to the bottom of the texture. Without `wait`, returns `None` instead of blocking if the GPU hasn't finished that copy yet. Any other
+/// failure to map the texture is an error.
+pub(super) fn read_staging_texture(
+    context: &ID3D11DeviceContext,
+    staging_tex: &ID3D11Texture2D,
+    format: PixelFormat,
+    mode: ReadbackMode,
+    rows: Option<Range<usize>>,
+    wait: bool,
+    dst: &mut Vec<u8>,
+) -> super::Result<Option<usize>> {
+    let mut staging_desc = D3D11_TEXTURE2D_DESC::default();
+    unsafe { staging_tex.GetDesc(&mut staging_desc) };
+    let flags = if wait { 0 } else { D3D11_MAP_FLAG_DO_NOT_WAIT.0 as u32 };
+
+    unsafe {
         let mut mapped = MaybeUninit::uninit();
-        context.Map(&staging_tex, 0, D3D11_MAP_READ, 0, Some(mapped.as_mut_ptr()))?;
+        match context.Map(staging_tex, 0, D3D11_MAP_READ, flags, Some(mapped.as_mut_ptr())) {
+            Ok(()) => (),
+            Err(err) if !wait && err.code() == DXGI_ERROR_WAS_STILL_DRAWING => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
         let mapped = mapped.assume_init_ref();
 
-        let height = tex_desc.Height as usize;
-        let bytes_per_row = tex_desc.Width as usize * bytes_per_pixel as usize;
+        let texture_height = staging_desc.Height as usize;
+        let rows = rows.map_or(0..texture_height, |rows| {
+            rows.start.min(texture_height)..rows.end.min(texture_height)
+        });
+        let height = rows.len();
+        let bytes_per_row = format.row_bytes(staging_desc.Width as usize);
         let row_pitch = mapped.RowPitch as usize;
-        let total_bytes = bytes_per_row * height;
-
-        let mut frame_bytes = vec![0u8; total_bytes];
-        for y in 0..height {
-            let src_row = mapped.pData.add(y * row_pitch);
-            let dst_row_start = y * bytes_per_row;
-            let dst_row_end = (y + 1) * bytes_per_row;
-            let dst_row = &mut frame_bytes[dst_row_start..dst_row_end];
-            std::ptr::copy_nonoverlapping(src_row.cast(), dst_row.as_mut_ptr(), bytes_per_row);
+        let first_row: *const u8 = mapped.pData.cast::<u8>().add(rows.start * row_pitch);
+
+        let stride = match mode {
+            ReadbackMode::Tight => {
+                resize_for_overwrite(dst, bytes_per_row * height);
+                for y in 0..height {
+                    let src_row = first_row.add(y * row_pitch);
+                    let dst_row_start = y * bytes_per_row;
+                    let dst_row_end = (y + 1) * bytes_per_row;
+                    let dst_row = &mut dst[dst_row_start..dst_row_end];
+                    std::ptr::copy_nonoverlapping(
+                        src_row.cast(),
+                        dst_row.as_mut_ptr(),
+                        bytes_per_row,
+                    );
+                }
+                bytes_per_row
+            }
+            ReadbackMode::Strided => {
+                // The padding is copied along with the pixels, which turns the copy into a single memcpy.
+                let total_bytes = row_pitch * height;
+                resize_for_overwrite(dst, total_bytes);
+                std::ptr::copy_nonoverlapping(first_row, dst.as_mut_ptr(), total_bytes);
+                row_pitch
+            }
+        };
+        context.Unmap(staging_tex, 0);
+
+        Ok(Some(stride))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::Graphics::{
+        Direct3D11::{D3D11_CPU_ACCESS_READ, D3D11_USAGE_DEFAULT, D3D11_USAGE_STAGING},
+        Dxgi::Common::{DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_SAMPLE_DESC},
+    };
+
+    use super::*;
+    use crate::capture_providers::windows::WindowsCaptureError;
+
+    /// A BGRA8 texture of the given size, to copy from or, as a staging texture, to read back.
+    fn texture(device: &ID3D11Device, size: Vector2<u32>, staging: bool) -> ID3D11Texture2D {
+        let desc = D3D11_TEXTURE2D_DESC {
+            Width: size.x,
+            Height: size.y,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: DXGI_FORMAT_B8G8R8A8_UNORM,
+            SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
+            Usage: if staging { D3D11_USAGE_STAGING } else { D3D11_USAGE_DEFAULT },
+            BindFlags: 0,
+            CPUAccessFlags: if staging { D3D11_CPU_ACCESS_READ.0 as u32 } else { 0 },
+            MiscFlags: 0,
+        };
+        let mut texture = None;
+        unsafe { device.CreateTexture2D(&desc, None, Some(&mut texture)) }.unwrap();
+        texture.unwrap()
+    }
+
+    #[test]
+    fn errors_of_a_working_device_are_kept() {
+        // WARP runs without a GPU, so this works on any machine.
+        let device = create_d3d_device_with(None, D3D_DRIVER_TYPE_WARP).unwrap();
+        let err = detect_device_loss(&device, WindowsCaptureError::NoFramePool);
+        assert!(matches!(err, WindowsCaptureError::NoFramePool));
+    }
+
+    #[test]
+    fn readback_is_sized_from_the_texture_not_the_item() {
+        let device = create_d3d_device_with(None, D3D_DRIVER_TYPE_WARP).unwrap();
+        let context = unsafe { device.GetImmediateContext() }.unwrap();
+        // The item is 32 x 16, but its content grew to a wider texture.
+        let item_size = Vector2::new(32usize, 16);
+        let frame_size = Vector2::new(48, 16);
+        let source = texture(&device, frame_size, false);
+        let staging = texture(&device, frame_size, true);
+
+        for mode in [ReadbackMode::Tight, ReadbackMode::Strided] {
+            let mut dst = vec![0; PixelFormat::BGRA8.frame_bytes(item_size.x, item_size.y)];
+            let (written, stride) = read_texture(
+                &context,
+                source.clone(),
+                None,
+                staging.clone(),
+                PixelFormat::BGRA8,
+                mode,
+                &mut dst,
+            )
+            .unwrap();
+            assert_eq!(written, dst.len(), "{:?}", mode);
+            assert_eq!(written, stride * frame_size.y as usize, "{:?}", mode);
+            assert!(stride >= PixelFormat::BGRA8.row_bytes(frame_size.x as usize), "{:?}", mode);
         }
-        context.Unmap(&staging_tex, 0);
+    }
+
+    #[test]
+    fn staging_textures_of_another_size_are_rejected() {
+        let device = create_d3d_device_with(None, D3D_DRIVER_TYPE_WARP).unwrap();
+        let context = unsafe { device.GetImmediateContext() }.unwrap();
+        let source = texture(&device, Vector2::new(48, 16), false);
+        // Left over from when the frames were the size of the item.
+        let staging = texture(&device, Vector2::new(32, 16), true);
 
-        Ok(frame_bytes)
+        let mut dst = Vec::new();
+        let err = read_texture(
+            &context,
+            source,
+            None,
+            staging,
+            PixelFormat::BGRA8,
+            ReadbackMode::Tight,
+            &mut dst,
+        );
+        let Err(WindowsCaptureError::TextureSizeMismatch { captured, staging }) = err else {
+            panic!("expected a size mismatch, got {:?}", err);
+        };
+        assert_eq!((captured, staging), (Vector2::new(48, 16), Vector2::new(32, 16)));
     }
 }
@@ -0,0 +1,120 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use super::{Result, WindowsCaptureError};
+
+/// Coordinates rebuilding the capture pipeline after the D3D11 device is lost, e.g. after a driver reset (TDR)
+/// or switching GPUs. Only one recovery runs at a time, no matter how many streams notice the loss.
+#[derive(Debug)]
+pub(super) struct DeviceRecovery {
+    enabled: AtomicBool,
+    in_progress: AtomicBool,
+    /// Recoveries that got the pipeline running again.
+    recovered: AtomicU64,
+    max_attempts: u32,
+    initial_backoff: Duration,
+}
+
+impl DeviceRecovery {
+    const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+    const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+
+    pub fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(true),
+            in_progress: AtomicBool::new(false),
+            recovered: AtomicU64::new(0),
+            max_attempts: Self::DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: Self::DEFAULT_INITIAL_BACKOFF,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Whether a recovery is currently running. Frames arriving in the meantime come from the lost device.
+    pub fn in_progress(&self) -> bool {
+        self.in_progress.load(Ordering::Acquire)
+    }
+
+    /// How often the pipeline was rebuilt on a new device since the provider was created.
+    pub fn recovered(&self) -> u64 {
+        self.recovered.load(Ordering::Relaxed)
+    }
+
+    /// Starts recovering on a separate thread, as the FrameArrived handler that noticed the loss must not block
+    /// while the frame pool it belongs to is torn down. `recover` is retried with exponential backoff, and
+    /// `on_give_up` is called with the last error once every attempt failed.
+    /// Returns false if no recovery is running afterwards, because the thread could not be started.
+    pub fn spawn(
+        self: &Arc<Self>,
+        mut recover: impl FnMut() -> Result<()> + Send + 'static,
+        on_give_up: impl FnOnce(WindowsCaptureError) + Send + 'static,
+    ) -> bool {
+        if self
+            .in_progress
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return true;
+        }
+
+        let this = self.clone();
+        let spawned =
+            std::thread::Builder::new().name("loki-device-recovery".into()).spawn(move || {
+                let mut backoff = this.initial_backoff;
+                let mut last_error = None;
+
+                for attempt in 1..=this.max_attempts {
+                    std::thread::sleep(backoff);
+                    tracing::info!(
+                        "Attempting device recovery ({}/{})...",
+                        attempt,
+                        this.max_attempts
+                    );
+
+                    match recover() {
+                        Ok(()) => {
+                            tracing::info!(
+                                "Capture device recreated after {} attempt(s).",
+                                attempt
+                            );
+                            this.recovered.fetch_add(1, Ordering::Relaxed);
+                            this.in_progress.store(false, Ordering::Release);
+                            return;
+                        }
+                        Err(err) => {
+                            tracing::warn!("Device recovery attempt {} failed: {}", attempt, err);
+                            last_error = Some(err);
+                            backoff *= 2;
+                        }
+                    }
+                }
+
+                tracing::error!(
+                    "Giving up on device recovery after {} attempts.",
+                    this.max_attempts
+                );
+                // The loop runs at least once, so there is always an error here.
+                on_give_up(last_error.expect("Device recovery gave up without an error"));
+                this.in_progress.store(false, Ordering::Release);
+            });
+
+        if let Err(err) = spawned {
+            tracing::error!("Failed to spawn device recovery thread: {}", err);
+            self.in_progress.store(false, Ordering::Release);
+            return false;
+        }
+        true
+    }
+}
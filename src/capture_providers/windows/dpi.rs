@@ -0,0 +1,41 @@
+use windows::Win32::{
+    Foundation::HWND,
+    Graphics::Gdi::HMONITOR,
+    UI::HiDpi::{
+        DPI_AWARENESS_PER_MONITOR_AWARE, GetAwarenessFromDpiAwarenessContext, GetDpiForMonitor,
+        GetDpiForWindow, GetThreadDpiAwarenessContext, MDT_EFFECTIVE_DPI,
+    },
+};
+
+/// The DPI of 100% scaling.
+pub const DEFAULT_DPI: u32 = 96;
+
+/// Physical pixels per logical pixel on a monitor, e.g. 1.5 at 150%. Falls back to 1 if the DPI can't be read.
+/// Only accurate in a per-monitor DPI aware process, see `is_per_monitor_dpi_aware`.
+pub fn monitor_scale_factor(handle: HMONITOR) -> f32 {
+    let (mut dpi_x, mut dpi_y) = (DEFAULT_DPI, DEFAULT_DPI);
+    if let Err(err) = unsafe { GetDpiForMonitor(handle, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y) }
+    {
+        tracing::warn!("Failed to get the DPI of monitor {:?}: {}", handle, err);
+        return 1.0;
+    }
+    dpi_x as f32 / DEFAULT_DPI as f32
+}
+
+/// Physical pixels per logical pixel of a window, which is that of the monitor it is on.
+pub fn window_scale_factor(handle: HWND) -> f32 {
+    match unsafe { GetDpiForWindow(handle) } {
+        0 => {
+            tracing::warn!("Failed to get the DPI of window {:?}", handle);
+            1.0
+        }
+        dpi => dpi as f32 / DEFAULT_DPI as f32,
+    }
+}
+
+/// Whether the calling thread sees physical pixels on every monitor. Otherwise Windows scales window and monitor
+/// coordinates behind its back, and they no longer match the pixels of capture items.
+pub fn is_per_monitor_dpi_aware() -> bool {
+    let awareness = unsafe { GetAwarenessFromDpiAwarenessContext(GetThreadDpiAwarenessContext()) };
+    awareness == DPI_AWARENESS_PER_MONITOR_AWARE
+}
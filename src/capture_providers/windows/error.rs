@@ -1,17 +1,120 @@
+use windows::Win32::{
+    Foundation::E_ACCESSDENIED,
+    Graphics::Dxgi::{DXGI_ERROR_DEVICE_REMOVED, DXGI_ERROR_DEVICE_RESET},
+};
+
+use super::{AdapterSelection, SourceId, TitleMatcher, WindowCandidate};
+use crate::{
+    capture_providers::shared::{CaptureTarget, StreamId, Vector2},
+    utils::com_thread::ComThreadError,
+};
+
 pub type Result<T> = std::result::Result<T, WindowsCaptureError>;
 
 #[derive(Debug, thiserror::Error)]
 pub enum WindowsCaptureError {
+    #[error("Windows.Graphics.Capture is not supported on this system")]
+    CaptureNotSupported,
     #[error("Already capturing")]
     AlreadyCapturing,
     #[error("Not capturing")]
     NotCapturing,
+    #[error("Capture is paused, resume it instead of starting it")]
+    Paused,
+    #[error("Capture is not paused")]
+    NotPaused,
     #[error("No frame pool available")]
     NoFramePool,
     #[error("No capture item available")]
     NoCaptureItem,
+    #[error("Unknown capture source: {0:?}")]
+    UnknownSource(SourceId),
+    #[error("Unknown {0}, it may have been dropped")]
+    UnknownStream(StreamId),
+    #[error("Captured texture is {captured:?}, but the staging texture is {staging:?}")]
+    TextureSizeMismatch { captured: Vector2<u32>, staging: Vector2<u32> },
+    #[error("Timed out waiting for a snapshot frame")]
+    SnapshotTimedOut,
+    #[error("No capturable window with a {0}")]
+    NoMatchingWindow(TitleMatcher),
+    #[error("{} windows with a {matcher}: {}", candidates.len(), list_candidates(candidates))]
+    AmbiguousWindowTitle { matcher: TitleMatcher, candidates: Vec<WindowCandidate> },
+    #[error("Window {0:#x} no longer exists")]
+    WindowClosed(usize),
+    #[error("No capturable {0}")]
+    NoMatchingTarget(CaptureTarget),
+    #[error("No GPU {0}")]
+    AdapterNotFound(AdapterSelection),
     #[error("Failed to set min update interval: {0}")]
     SetMinUpdateIntervalFailed(windows_core::Error),
+    #[error("COM thread error: {0}")]
+    ComThreadError(#[from] ComThreadError),
+    #[error("D3D11 device lost: {0}")]
+    DeviceLost(windows_core::Error),
     #[error("Unknown Windows error: {0}")]
-    UnknownWindowsError(#[from] windows_core::Error),
+    UnknownWindowsError(windows_core::Error),
+}
+
+impl WindowsCaptureError {
+    /// Whether capture can't continue after this error, as opposed to just losing the current frame.
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Self::DeviceLost(_) => true,
+            // Capture access can be revoked, e.g. after a UAC prompt.
+            Self::UnknownWindowsError(err) => err.code() == E_ACCESSDENIED,
+            _ => false,
+        }
+    }
+}
+
+fn list_candidates(candidates: &[WindowCandidate]) -> String {
+    candidates.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+impl From<windows_core::Error> for WindowsCaptureError {
+    fn from(err: windows_core::Error) -> Self {
+        let code = err.code();
+        if code == DXGI_ERROR_DEVICE_REMOVED || code == DXGI_ERROR_DEVICE_RESET {
+            Self::DeviceLost(err)
+        } else {
+            Self::UnknownWindowsError(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::{
+        Foundation::{E_FAIL, E_OUTOFMEMORY},
+        Graphics::Dxgi::DXGI_ERROR_DEVICE_HUNG,
+    };
+    use windows_core::HRESULT;
+
+    use super::*;
+
+    fn error(code: HRESULT) -> WindowsCaptureError {
+        windows_core::Error::from_hresult(code).into()
+    }
+
+    #[test]
+    fn removed_and_reset_devices_are_device_loss() {
+        for code in [DXGI_ERROR_DEVICE_REMOVED, DXGI_ERROR_DEVICE_RESET] {
+            let err = error(code);
+            assert!(matches!(&err, WindowsCaptureError::DeviceLost(err) if err.code() == code));
+            assert!(err.is_fatal());
+        }
+    }
+
+    #[test]
+    fn other_errors_only_lose_the_frame() {
+        // A hung device is reported as removed by the calls after it, which `detect_device_loss` picks up.
+        for code in [E_FAIL, E_OUTOFMEMORY, DXGI_ERROR_DEVICE_HUNG] {
+            let err = error(code);
+            assert!(matches!(err, WindowsCaptureError::UnknownWindowsError(_)), "{:?}", err);
+            assert!(!err.is_fatal());
+        }
+        // Capture access being revoked ends the capture too, without a device to recover.
+        assert!(error(E_ACCESSDENIED).is_fatal());
+        assert!(!WindowsCaptureError::NoFramePool.is_fatal());
+    }
 }
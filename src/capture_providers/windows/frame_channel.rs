@@ -0,0 +1,598 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard,
+        atomic::{AtomicI64, AtomicU64, Ordering},
+    },
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::watch;
+
+use crate::capture_providers::shared::{BackpressurePolicy, CaptureEvent, GpuFrame, StreamOptions};
+
+/// What happened to the frames sent to a stream so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StreamStats {
+    /// Frames queued on the stream.
+    pub delivered: u64,
+    /// Frames lost to the backpressure policy, because the consumer didn't keep up.
+    pub dropped_full: u64,
+    /// Frames that arrived after the stream was dropped.
+    pub dropped_closed: u64,
+    /// `Frame::timestamp` of the last queued frame, 0 before the first.
+    pub last_frame_timestamp: i64,
+}
+
+/// Why a frame was not queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SendError {
+    /// The queue was full and the policy drops new frames.
+    Full,
+    /// The receiver is gone.
+    Closed,
+    /// The frame is from a capture item that has since been replaced.
+    Stale,
+}
+
+/// What a frame channel queues. Only frames count towards the capacity and are subject to the policy.
+pub(super) trait ChannelItem: Send {
+    /// The timestamp of the frame, `None` for the other events.
+    fn frame_timestamp(&self) -> Option<i64>;
+
+    fn is_frame(&self) -> bool {
+        self.frame_timestamp().is_some()
+    }
+
+    /// Whether this ends the stream, which is never dropped to make room for other events.
+    fn is_end(&self) -> bool {
+        false
+    }
+}
+
+impl ChannelItem for CaptureEvent {
+    fn frame_timestamp(&self) -> Option<i64> {
+        match self {
+            CaptureEvent::Frame(frame) => Some(frame.timestamp),
+            _ => None,
+        }
+    }
+
+    fn is_end(&self) -> bool {
+        matches!(self, CaptureEvent::Ended(_))
+    }
+}
+
+impl ChannelItem for GpuFrame {
+    fn frame_timestamp(&self) -> Option<i64> {
+        Some(self.timestamp)
+    }
+}
+
+#[derive(Debug)]
+struct State<T> {
+    queue: VecDeque<T>,
+    /// Number of queued frames. Other events don't count towards the capacity.
+    queued_frames: usize,
+    senders: usize,
+    receiver_alive: bool,
+    /// Set by `FrameSender::close`, the stream ends once the queue is drained.
+    closed: bool,
+    waker: Option<Waker>,
+    /// Frames of older generations are rejected, see `FrameSender::advance_generation`.
+    generation: u64,
+    /// Set when `Block` gave up waiting for the consumer. Frames are dropped right away until the consumer takes
+    /// one, so a stuck consumer holds up the capture thread once rather than for every frame.
+    stalled: bool,
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    space_available: Condvar,
+    options: StreamOptions,
+    delivered: AtomicU64,
+    dropped_full: AtomicU64,
+    dropped_closed: AtomicU64,
+    last_frame_timestamp: AtomicI64,
+    /// Kept up to date with every frame, but watchers are only woken when one is dropped.
+    stats_tx: watch::Sender<StreamStats>,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn stats(&self) -> StreamStats {
+        StreamStats {
+            delivered: self.delivered.load(Ordering::Relaxed),
+            dropped_full: self.dropped_full.load(Ordering::Relaxed),
+            dropped_closed: self.dropped_closed.load(Ordering::Relaxed),
+            last_frame_timestamp: self.last_frame_timestamp.load(Ordering::Relaxed),
+        }
+    }
+
+    fn publish_stats(&self, notify: bool) {
+        let stats = self.stats();
+        self.stats_tx.send_if_modified(|current| {
+            *current = stats;
+            notify
+        });
+    }
+
+    fn count_dropped(&self, counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.publish_stats(true);
+    }
+}
+
+/// Creates the queue between the FrameArrived handler and a capture stream.
+/// Unlike a plain mpsc channel, this can evict the oldest frame or block the capture thread when full.
+pub(super) fn frame_channel<T: ChannelItem>(
+    mut options: StreamOptions,
+) -> (FrameSender<T>, FrameReceiver<T>) {
+    options.capacity = options.capacity.max(1);
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::with_capacity(options.capacity),
+            queued_frames: 0,
+            senders: 1,
+            receiver_alive: true,
+            closed: false,
+            waker: None,
+            generation: 0,
+            stalled: false,
+        }),
+        space_available: Condvar::new(),
+        options,
+        delivered: AtomicU64::new(0),
+        dropped_full: AtomicU64::new(0),
+        dropped_closed: AtomicU64::new(0),
+        last_frame_timestamp: AtomicI64::new(0),
+        stats_tx: watch::Sender::new(StreamStats::default()),
+    });
+    (FrameSender { shared: shared.clone() }, FrameReceiver { shared })
+}
+
+#[derive(Debug)]
+pub(super) struct FrameSender<T = CaptureEvent> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: ChannelItem> FrameSender<T> {
+    /// How long `Block` waits for the consumer before dropping the frame. The FrameArrived thread is shared by
+    /// every stream of a source, so one slow consumer must not stall the others for longer than that.
+    pub const BLOCK_TIMEOUT: Duration = Duration::from_millis(100);
+    /// Events other than frames that can be queued, e.g. during a run of frame errors while nobody polls the
+    /// stream. The oldest give way to new ones, except the end of the stream.
+    pub const MAX_QUEUED_EVENTS: usize = 64;
+
+    /// Queues a frame of the given capture item generation according to the backpressure policy.
+    /// With `Block` this waits for the consumer, up to `BLOCK_TIMEOUT`. Returns how many older frames were
+    /// evicted to make room.
+    pub fn send_frame(&self, generation: u64, frame: T) -> Result<u64, SendError> {
+        let options = self.shared.options;
+        let mut state = self.shared.lock();
+        if generation < state.generation {
+            return Err(SendError::Stale);
+        }
+        let mut evicted = 0;
+        let deadline = Instant::now() + Self::BLOCK_TIMEOUT;
+
+        while state.receiver_alive && !state.closed && state.queued_frames >= options.capacity {
+            match options.policy {
+                BackpressurePolicy::DropNewest => {
+                    self.shared.count_dropped(&self.shared.dropped_full);
+                    return Err(SendError::Full);
+                }
+                BackpressurePolicy::DropOldest => {
+                    // Events other than frames are never evicted.
+                    if let Some(index) = state.queue.iter().position(ChannelItem::is_frame) {
+                        state.queue.remove(index);
+                        state.queued_frames -= 1;
+                        self.shared.count_dropped(&self.shared.dropped_full);
+                        evicted += 1;
+                    }
+                }
+                BackpressurePolicy::Block => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if state.stalled || remaining.is_zero() {
+                        if !state.stalled {
+                            tracing::debug!(
+                                "Stream consumer stalled for {:?}, dropping frames until it catches up.",
+                                Self::BLOCK_TIMEOUT
+                            );
+                        }
+                        state.stalled = true;
+                        self.shared.count_dropped(&self.shared.dropped_full);
+                        return Err(SendError::Full);
+                    }
+                    state = self
+                        .shared
+                        .space_available
+                        .wait_timeout(state, remaining)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0;
+                }
+            }
+        }
+
+        if !state.receiver_alive {
+            self.shared.count_dropped(&self.shared.dropped_closed);
+            return Err(SendError::Closed);
+        }
+        if state.closed {
+            return Err(SendError::Closed);
+        }
+        // The item might have been replaced while waiting for space.
+        if generation < state.generation {
+            return Err(SendError::Stale);
+        }
+        if let Some(timestamp) = frame.frame_timestamp() {
+            self.shared.last_frame_timestamp.store(timestamp, Ordering::Relaxed);
+        }
+        self.shared.delivered.fetch_add(1, Ordering::Relaxed);
+        self.shared.publish_stats(false);
+        state.queued_frames += 1;
+        Self::push(&mut state, frame);
+        Ok(evicted)
+    }
+
+    /// Queues an event regardless of the capacity for frames. Past `MAX_QUEUED_EVENTS`, the oldest event that
+    /// doesn't end the stream is dropped to make room, so the end of the stream is never lost.
+    pub fn send_event(&self, event: T) -> Result<(), SendError> {
+        let mut state = self.shared.lock();
+        if !state.receiver_alive || state.closed {
+            return Err(SendError::Closed);
+        }
+        if state.queue.len() - state.queued_frames >= Self::MAX_QUEUED_EVENTS {
+            let oldest = state.queue.iter().position(|item| !item.is_frame() && !item.is_end());
+            if let Some(index) = oldest {
+                state.queue.remove(index);
+            }
+        }
+        Self::push(&mut state, event);
+        Ok(())
+    }
+
+    /// Ends the stream without waiting for every sender to be dropped, which for the ones held by the FrameArrived
+    /// handler only happens once COM releases it. The stream still yields what was queued before, then ends.
+    /// Anything sent afterwards is rejected, and a capture thread blocked on a full queue gives up.
+    pub fn close(&self) {
+        let mut state = self.shared.lock();
+        state.closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self.shared.space_available.notify_all();
+    }
+
+    /// Whether the stream was dropped, so nothing sent will be received anymore.
+    pub fn is_closed(&self) -> bool {
+        !self.shared.lock().receiver_alive
+    }
+
+    /// Whether frames of the generation would be rejected, because the capture item has been replaced since.
+    pub fn is_stale(&self, generation: u64) -> bool {
+        generation < self.shared.lock().generation
+    }
+
+    /// Drops the queued frames of older generations and rejects any that are still on their way.
+    /// Checked under the same lock as queueing, so no old frame can follow a frame of the new generation.
+    pub fn advance_generation(&self, generation: u64) {
+        let mut state = self.shared.lock();
+        state.generation = generation;
+        let queued = state.queue.len();
+        state.queue.retain(|item| !item.is_frame());
+        let removed = queued - state.queue.len();
+        state.queued_frames -= removed;
+        if removed > 0 {
+            state.stalled = false;
+            self.shared.space_available.notify_all();
+        }
+    }
+
+    fn push(state: &mut State<T>, event: T) {
+        state.queue.push_back(event);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Clone for FrameSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl<T> Drop for FrameSender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders > 0 {
+            return;
+        }
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct FrameReceiver<T = CaptureEvent> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: ChannelItem> FrameReceiver<T> {
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.shared.lock();
+        match state.queue.pop_front() {
+            Some(event) => {
+                if event.is_frame() {
+                    state.queued_frames -= 1;
+                    state.stalled = false;
+                    self.shared.space_available.notify_one();
+                }
+                Poll::Ready(Some(event))
+            }
+            None if state.senders == 0 || state.closed => Poll::Ready(None),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    pub fn dropped_frames(&self) -> u64 {
+        self.shared.dropped_full.load(Ordering::Relaxed)
+    }
+
+    pub fn stats(&self) -> StreamStats {
+        self.shared.stats()
+    }
+
+    pub fn watch_stats(&self) -> watch::Receiver<StreamStats> {
+        self.shared.stats_tx.subscribe()
+    }
+}
+
+impl<T> Drop for FrameReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.lock().receiver_alive = false;
+        // Wake up a capture thread blocked on a full queue.
+        self.shared.space_available.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{task::Waker, thread};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Item {
+        Frame(i64),
+        Event(usize),
+        End,
+    }
+
+    impl ChannelItem for Item {
+        fn frame_timestamp(&self) -> Option<i64> {
+            match self {
+                Item::Frame(timestamp) => Some(*timestamp),
+                _ => None,
+            }
+        }
+
+        fn is_end(&self) -> bool {
+            *self == Item::End
+        }
+    }
+
+    fn channel(
+        capacity: usize,
+        policy: BackpressurePolicy,
+    ) -> (FrameSender<Item>, FrameReceiver<Item>) {
+        frame_channel(StreamOptions { capacity, policy, ..StreamOptions::default() })
+    }
+
+    /// What the receiver has queued right now, without waiting for more.
+    fn drain(receiver: &mut FrameReceiver<Item>) -> Vec<Item> {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut items = Vec::new();
+        while let Poll::Ready(Some(item)) = receiver.poll_recv(&mut cx) {
+            items.push(item);
+        }
+        items
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_queued_frames() {
+        let (tx, mut rx) = channel(2, BackpressurePolicy::DropNewest);
+        assert_eq!(tx.send_frame(0, Item::Frame(1)), Ok(0));
+        assert_eq!(tx.send_frame(0, Item::Frame(2)), Ok(0));
+        assert_eq!(tx.send_frame(0, Item::Frame(3)), Err(SendError::Full));
+        assert_eq!(drain(&mut rx), [Item::Frame(1), Item::Frame(2)]);
+        assert_eq!(rx.dropped_frames(), 1);
+        assert_eq!(rx.stats().last_frame_timestamp, 2);
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_latest_frames() {
+        let (tx, mut rx) = channel(2, BackpressurePolicy::DropOldest);
+        for timestamp in 1..=4 {
+            tx.send_frame(0, Item::Frame(timestamp)).unwrap();
+        }
+        assert_eq!(drain(&mut rx), [Item::Frame(3), Item::Frame(4)]);
+        assert_eq!(rx.dropped_frames(), 2);
+    }
+
+    #[test]
+    fn drop_oldest_never_evicts_events() {
+        let (tx, mut rx) = channel(1, BackpressurePolicy::DropOldest);
+        tx.send_frame(0, Item::Frame(1)).unwrap();
+        tx.send_event(Item::Event(0)).unwrap();
+        assert_eq!(tx.send_frame(0, Item::Frame(2)), Ok(1));
+        assert_eq!(drain(&mut rx), [Item::Event(0), Item::Frame(2)]);
+    }
+
+    #[test]
+    fn events_dont_count_towards_the_capacity() {
+        let (tx, mut rx) = channel(1, BackpressurePolicy::DropNewest);
+        for index in 0..3 {
+            tx.send_event(Item::Event(index)).unwrap();
+        }
+        assert_eq!(tx.send_frame(0, Item::Frame(1)), Ok(0));
+        assert_eq!(drain(&mut rx).len(), 4);
+    }
+
+    #[test]
+    fn block_waits_for_the_consumer() {
+        let (tx, mut rx) = channel(1, BackpressurePolicy::Block);
+        tx.send_frame(0, Item::Frame(1)).unwrap();
+        let sender = thread::spawn(move || tx.send_frame(0, Item::Frame(2)));
+        thread::sleep(FrameSender::<Item>::BLOCK_TIMEOUT / 4);
+        assert_eq!(drain(&mut rx), [Item::Frame(1)]);
+        assert_eq!(sender.join().unwrap(), Ok(0));
+        assert_eq!(drain(&mut rx), [Item::Frame(2)]);
+        assert_eq!(rx.dropped_frames(), 0);
+    }
+
+    #[test]
+    fn block_gives_up_on_a_stalled_consumer_until_it_catches_up() {
+        let (tx, mut rx) = channel(1, BackpressurePolicy::Block);
+        tx.send_frame(0, Item::Frame(1)).unwrap();
+
+        let started = Instant::now();
+        assert_eq!(tx.send_frame(0, Item::Frame(2)), Err(SendError::Full));
+        assert!(started.elapsed() >= FrameSender::<Item>::BLOCK_TIMEOUT);
+
+        // Once stalled, frames are dropped without waiting again.
+        let started = Instant::now();
+        assert_eq!(tx.send_frame(0, Item::Frame(3)), Err(SendError::Full));
+        assert!(started.elapsed() < FrameSender::<Item>::BLOCK_TIMEOUT);
+
+        assert_eq!(drain(&mut rx), [Item::Frame(1)]);
+        assert_eq!(tx.send_frame(0, Item::Frame(4)), Ok(0));
+        assert_eq!(drain(&mut rx), [Item::Frame(4)]);
+        assert_eq!(rx.dropped_frames(), 2);
+    }
+
+    #[test]
+    fn block_gives_up_when_the_receiver_is_dropped() {
+        let (tx, rx) = channel(1, BackpressurePolicy::Block);
+        tx.send_frame(0, Item::Frame(1)).unwrap();
+        let sender = thread::spawn(move || tx.send_frame(0, Item::Frame(2)));
+        drop(rx);
+        assert_eq!(sender.join().unwrap(), Err(SendError::Closed));
+    }
+
+    #[test]
+    fn events_past_the_cap_drop_the_oldest_but_never_the_end() {
+        let max = FrameSender::<Item>::MAX_QUEUED_EVENTS;
+        let (tx, mut rx) = channel(1, BackpressurePolicy::DropNewest);
+        for index in 0..max + 10 {
+            tx.send_event(Item::Event(index)).unwrap();
+        }
+        tx.send_event(Item::End).unwrap();
+
+        let items = drain(&mut rx);
+        assert_eq!(items.len(), max);
+        assert_eq!(items[0], Item::Event(11));
+        assert_eq!(items.last(), Some(&Item::End));
+    }
+
+    #[test]
+    fn frames_of_older_generations_are_stale() {
+        let (tx, mut rx) = channel(4, BackpressurePolicy::DropNewest);
+        tx.send_frame(0, Item::Frame(1)).unwrap();
+        tx.send_event(Item::Event(0)).unwrap();
+        tx.advance_generation(1);
+        assert!(tx.is_stale(0));
+        assert_eq!(tx.send_frame(0, Item::Frame(2)), Err(SendError::Stale));
+        assert_eq!(tx.send_frame(1, Item::Frame(3)), Ok(0));
+        // Queued frames of the old generation are gone, the events stay.
+        assert_eq!(drain(&mut rx), [Item::Event(0), Item::Frame(3)]);
+    }
+
+    #[test]
+    fn advancing_the_generation_makes_room() {
+        let (tx, mut rx) = channel(1, BackpressurePolicy::DropNewest);
+        tx.send_frame(0, Item::Frame(1)).unwrap();
+        tx.advance_generation(1);
+        assert_eq!(tx.send_frame(1, Item::Frame(2)), Ok(0));
+        assert_eq!(drain(&mut rx), [Item::Frame(2)]);
+    }
+
+    #[test]
+    fn closing_yields_what_was_queued_then_ends() {
+        let (tx, mut rx) = channel(2, BackpressurePolicy::DropNewest);
+        tx.send_frame(0, Item::Frame(1)).unwrap();
+        tx.close();
+        assert_eq!(tx.send_frame(0, Item::Frame(2)), Err(SendError::Closed));
+        assert_eq!(tx.send_event(Item::End), Err(SendError::Closed));
+
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(rx.poll_recv(&mut cx), Poll::Ready(Some(Item::Frame(1))));
+        assert_eq!(rx.poll_recv(&mut cx), Poll::Ready(None));
+    }
+
+    #[test]
+    fn closing_ends_the_stream_for_a_waiting_consumer() {
+        let (tx, mut rx) = channel(2, BackpressurePolicy::DropNewest);
+        // Another sender stays around, as the ones of the FrameArrived handlers do.
+        let handler = tx.clone();
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx.close();
+            Instant::now()
+        });
+
+        let item = futures::executor::block_on(std::future::poll_fn(|cx| rx.poll_recv(cx)));
+        let ended = Instant::now();
+        assert_eq!(item, None);
+        assert!(ended.duration_since(stopper.join().unwrap()) < Duration::from_millis(100));
+        drop(handler);
+    }
+
+    #[test]
+    fn closing_wakes_a_blocked_sender() {
+        let (tx, _rx) = channel(1, BackpressurePolicy::Block);
+        tx.send_frame(0, Item::Frame(1)).unwrap();
+        let handler = tx.clone();
+        let started = Instant::now();
+        let sender = thread::spawn(move || handler.send_frame(0, Item::Frame(2)));
+        thread::sleep(FrameSender::<Item>::BLOCK_TIMEOUT / 4);
+        tx.close();
+        assert_eq!(sender.join().unwrap(), Err(SendError::Closed));
+        assert!(started.elapsed() < FrameSender::<Item>::BLOCK_TIMEOUT);
+    }
+
+    #[test]
+    fn frames_for_a_dropped_receiver_count_as_dropped_closed() {
+        let (tx, rx) = channel(2, BackpressurePolicy::DropNewest);
+        let stats = rx.watch_stats();
+        drop(rx);
+        assert!(tx.is_closed());
+        assert_eq!(tx.send_frame(0, Item::Frame(1)), Err(SendError::Closed));
+        assert_eq!(stats.borrow().dropped_closed, 1);
+    }
+
+    #[test]
+    fn the_stream_ends_once_every_sender_is_gone() {
+        let (tx, mut rx) = channel(2, BackpressurePolicy::DropNewest);
+        let other = tx.clone();
+        drop(tx);
+        other.send_event(Item::Event(0)).unwrap();
+        drop(other);
+
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(rx.poll_recv(&mut cx), Poll::Ready(Some(Item::Event(0))));
+        assert_eq!(rx.poll_recv(&mut cx), Poll::Ready(None));
+    }
+}
@@ -0,0 +1,201 @@
+use std::sync::Mutex;
+
+use crate::capture_providers::shared::CaptureFramerate;
+
+/// Skips frames that arrive faster than the framerate of a stream. WGC only treats `SetMinUpdateInterval` as a
+/// hint, and some drivers keep firing FrameArrived at the refresh rate of the monitor regardless.
+#[derive(Debug)]
+pub(super) struct FrameRateLimiter {
+    state: Mutex<LimiterState>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    framerate: CaptureFramerate,
+    /// The framerate as frames per seconds, so fractional rates are exact.
+    ratio: (u64, u64),
+    tolerance_ticks: i64,
+    schedule: Option<Schedule>,
+}
+
+/// The n-th frame after the anchor is due at `anchor + n * TICKS_PER_SECOND / fps`. Deadlines are computed from
+/// the count rather than added up, so the rounded interval never accumulates into drift.
+#[derive(Debug, Clone, Copy)]
+struct Schedule {
+    anchor: i64,
+    frames: u64,
+}
+
+impl FrameRateLimiter {
+    /// Frames up to this fraction of an interval early still count as on time, so jitter in the timestamps
+    /// doesn't push them to the next source frame.
+    const TOLERANCE_DIVISOR: i64 = 10;
+
+    pub fn new(framerate: CaptureFramerate) -> Self {
+        Self { state: Mutex::new(LimiterState::new(framerate)) }
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, LimiterState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Switches to another framerate. The schedule starts over with the next frame.
+    pub fn set_framerate(&self, framerate: CaptureFramerate) {
+        *self.lock_state() = LimiterState::new(framerate);
+    }
+
+    pub fn framerate(&self) -> CaptureFramerate {
+        self.lock_state().framerate
+    }
+
+    /// Whether the frame can be skipped, and if not, counts it towards the framerate. `timestamp` is the frame's
+    /// system relative time, which unlike the time the handler runs at doesn't depend on scheduling.
+    pub fn should_skip(&self, timestamp: i64) -> bool {
+        let mut state = self.lock_state();
+        let Some(current) = state.schedule else {
+            state.schedule = Some(Schedule { anchor: timestamp, frames: 1 });
+            return false;
+        };
+
+        if timestamp < state.deadline(current, current.frames) - state.tolerance_ticks {
+            return true;
+        }
+        // After a pause, e.g. while nothing changed on screen, catching up on the missed deadlines would let a
+        // burst of frames through, so the schedule starts over instead.
+        state.schedule = Some(if timestamp >= state.deadline(current, current.frames + 1) {
+            Schedule { anchor: timestamp, frames: 1 }
+        } else {
+            Schedule { frames: current.frames + 1, ..current }
+        });
+        false
+    }
+}
+
+impl LimiterState {
+    fn new(framerate: CaptureFramerate) -> Self {
+        Self {
+            framerate,
+            ratio: framerate.ratio(),
+            tolerance_ticks: framerate.to_frametime_ticks() / FrameRateLimiter::TOLERANCE_DIVISOR,
+            schedule: None,
+        }
+    }
+
+    fn deadline(&self, schedule: Schedule, frames: u64) -> i64 {
+        let (per_frames, per_seconds) = self.ratio;
+        let offset = frames as u128 * (CaptureFramerate::TICKS_PER_SECOND * per_seconds) as u128
+            / per_frames as u128;
+        schedule.anchor.saturating_add(offset as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+
+    const TICKS_PER_SECOND: i64 = CaptureFramerate::TICKS_PER_SECOND as i64;
+
+    fn custom(fps: u32) -> CaptureFramerate {
+        CaptureFramerate::Custom(NonZeroU32::new(fps).unwrap())
+    }
+
+    /// Timestamps of a source that delivers at `fps` for `seconds`, each off by up to `jitter` ticks.
+    fn source(fps: i64, seconds: i64, jitter: i64) -> Vec<i64> {
+        (0..fps * seconds)
+            .map(|frame| {
+                frame * TICKS_PER_SECOND / fps + (frame * 7919) % (2 * jitter + 1) - jitter
+            })
+            .collect()
+    }
+
+    fn assert_within_ten_percent(frames: usize, fps: usize, seconds: i64) {
+        let expected = fps * seconds as usize;
+        assert!(frames.abs_diff(expected) * 10 <= expected, "{} frames at {} FPS", frames, fps);
+    }
+
+    #[test]
+    fn decimating_a_144_hz_source_keeps_both_rates() {
+        let seconds = 10;
+        let stream = FrameRateLimiter::new(custom(144));
+        let decimator = FrameRateLimiter::new(CaptureFramerate::FPS5);
+        let (mut full_rate, mut decimated) = (0, 0);
+        for timestamp in source(144, seconds, 2_000) {
+            if stream.should_skip(timestamp) {
+                continue;
+            }
+            full_rate += 1;
+            if !decimator.should_skip(timestamp) {
+                decimated += 1;
+            }
+        }
+        assert_within_ten_percent(full_rate, 144, seconds);
+        assert_within_ten_percent(decimated, 5, seconds);
+    }
+
+    #[test]
+    fn decimation_follows_the_timestamps() {
+        // Checking the same timestamps again keeps the same frames, it doesn't matter when the handler runs.
+        let timestamps = source(144, 2, 2_000);
+        let kept = || {
+            let decimator = FrameRateLimiter::new(CaptureFramerate::FPS5);
+            timestamps
+                .iter()
+                .copied()
+                .filter(|&timestamp| !decimator.should_skip(timestamp))
+                .collect::<Vec<_>>()
+        };
+        let first = kept();
+        assert_within_ten_percent(first.len(), 5, 2);
+        assert_eq!(first, kept());
+    }
+
+    #[test]
+    fn frames_within_the_tolerance_count_as_on_time() {
+        // A third of a second is 333_333 ticks, a tenth of that early is still on time.
+        let limiter = FrameRateLimiter::new(CaptureFramerate::FPS30);
+        assert!(!limiter.should_skip(0));
+        assert!(limiter.should_skip(299_999));
+        assert!(!limiter.should_skip(300_000));
+        // The early frame doesn't move the schedule, the next one is still due two intervals after the first.
+        assert!(limiter.should_skip(633_332));
+        assert!(!limiter.should_skip(633_333));
+    }
+
+    #[test]
+    fn the_schedule_does_not_drift() {
+        // 144 doesn't divide into 60, so every kept frame is a little late. Those don't add up.
+        let seconds = 100;
+        let limiter = FrameRateLimiter::new(CaptureFramerate::FPS60);
+        let kept = source(144, seconds, 0)
+            .into_iter()
+            .filter(|&timestamp| !limiter.should_skip(timestamp))
+            .count();
+        assert!(kept.abs_diff(60 * seconds as usize) <= 1, "{} frames", kept);
+    }
+
+    #[test]
+    fn the_schedule_starts_over_after_a_gap() {
+        let limiter = FrameRateLimiter::new(CaptureFramerate::FPS30);
+        assert!(!limiter.should_skip(0));
+        assert!(!limiter.should_skip(333_333));
+        // Nothing arrived for a second. Rather than letting the missed frames through in a burst, the next
+        // frame is due an interval after the one that ended the gap.
+        assert!(!limiter.should_skip(TICKS_PER_SECOND + 333_333));
+        assert!(limiter.should_skip(TICKS_PER_SECOND + 400_000));
+        assert!(!limiter.should_skip(TICKS_PER_SECOND + 666_666));
+    }
+
+    #[test]
+    fn changing_the_framerate_starts_over() {
+        let limiter = FrameRateLimiter::new(CaptureFramerate::FPS5);
+        assert!(!limiter.should_skip(0));
+        assert!(limiter.should_skip(100_000));
+        limiter.set_framerate(CaptureFramerate::FPS60);
+        assert_eq!(limiter.framerate(), CaptureFramerate::FPS60);
+        assert!(!limiter.should_skip(100_000));
+        assert!(limiter.should_skip(200_000));
+        assert!(!limiter.should_skip(266_667));
+    }
+}
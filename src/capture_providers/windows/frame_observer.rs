@@ -0,0 +1,91 @@
+use std::{
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::capture_providers::shared::Frame;
+
+/// Called with every frame on the capture thread, see `WindowsCaptureProvider::add_frame_observer`.
+pub type FrameObserver = Box<dyn Fn(&Frame) + Send + Sync>;
+
+/// Identifies an observer to remove it again with `WindowsCaptureProvider::remove_frame_observer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverToken(u64);
+
+/// The observers of a provider, shared with the FrameArrived handlers of all its pipelines.
+pub(super) struct FrameObservers {
+    /// Reference counted, so the list can be copied out and the lock isn't held while observers run.
+    observers: RwLock<Vec<(ObserverToken, Arc<dyn Fn(&Frame) + Send + Sync>)>>,
+    next_token: AtomicU64,
+    last_slow_warning: Mutex<Option<Instant>>,
+}
+
+impl FrameObservers {
+    /// Calls taking longer than this are logged. Observers run before the frame is queued on any stream, so
+    /// they delay every consumer.
+    const SLOW_CALL_THRESHOLD: Duration = Duration::from_millis(2);
+    /// Slow calls are usually slow in a row, so they are only logged this often.
+    const SLOW_WARNING_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new() -> Self {
+        Self {
+            observers: RwLock::new(Vec::new()),
+            next_token: AtomicU64::new(0),
+            last_slow_warning: Mutex::new(None),
+        }
+    }
+
+    pub fn add(&self, observer: FrameObserver) -> ObserverToken {
+        let token = ObserverToken(self.next_token.fetch_add(1, Ordering::Relaxed));
+        let mut observers = self.observers.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        observers.push((token, Arc::from(observer)));
+        token
+    }
+
+    /// Returns whether the observer was still there.
+    pub fn remove(&self, token: ObserverToken) -> bool {
+        let mut observers = self.observers.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let len = observers.len();
+        observers.retain(|(observer_token, _)| *observer_token != token);
+        observers.len() != len
+    }
+
+    /// Calls every observer with the frame, in the order they were added.
+    pub fn notify(&self, frame: &Frame) {
+        let observers =
+            self.observers.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+        for (token, observer) in observers {
+            let started = Instant::now();
+            observer(frame);
+            let elapsed = started.elapsed();
+            if elapsed > Self::SLOW_CALL_THRESHOLD {
+                self.warn_slow(token, elapsed);
+            }
+        }
+    }
+
+    fn warn_slow(&self, token: ObserverToken, elapsed: Duration) {
+        let mut last_warning =
+            self.last_slow_warning.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = Instant::now();
+        if last_warning.is_some_and(|last| now.duration_since(last) < Self::SLOW_WARNING_INTERVAL) {
+            return;
+        }
+        *last_warning = Some(now);
+        tracing::warn!(
+            "Frame observer {} took {:.1} ms, which delays every stream. Observers should hand frames off quickly.",
+            token.0,
+            elapsed.as_secs_f64() * 1000.0
+        );
+    }
+}
+
+impl std::fmt::Debug for FrameObservers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let observers = self.observers.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        f.debug_struct("FrameObservers").field("count", &observers.len()).finish_non_exhaustive()
+    }
+}
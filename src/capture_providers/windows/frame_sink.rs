@@ -0,0 +1,135 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::capture_providers::{shared::Frame, windows::WindowsCaptureError};
+
+/// Receives frames directly on the capture thread, without going through the stream's queue.
+/// Calls block the capture of the next frame, so they must return quickly. Slow calls are logged.
+pub trait FrameSink: Send {
+    /// Called with every frame before it is queued on the stream.
+    /// The frame is only borrowed, so anything that outlives the call has to be copied out.
+    fn on_frame(&mut self, frame: &Frame);
+
+    /// Called when processing a frame failed. Fatal errors also end the stream afterwards.
+    fn on_error(&mut self, _err: &WindowsCaptureError) {}
+}
+
+impl<F> FrameSink for F
+where
+    F: FnMut(&Frame) + Send,
+{
+    fn on_frame(&mut self, frame: &Frame) {
+        self(frame)
+    }
+}
+
+/// Whether frames handed to a sink are still queued on its stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SinkDelivery {
+    /// The stream yields every frame as well.
+    #[default]
+    SinkAndStream,
+    /// The stream only yields the other events, such as the end of the capture.
+    SinkOnly,
+}
+
+/// A sink as owned by the FrameArrived handler of a stream.
+pub(super) struct SinkSlot {
+    sink: Mutex<Box<dyn FrameSink>>,
+    pub delivery: SinkDelivery,
+    last_slow_warning: Mutex<Option<Instant>>,
+}
+
+impl SinkSlot {
+    /// Calls taking longer than this are logged, as they eat into the time between frames.
+    const SLOW_CALL_THRESHOLD: Duration = Duration::from_millis(4);
+    /// Slow calls are usually slow in a row, so they are only logged this often.
+    const SLOW_WARNING_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new(sink: Box<dyn FrameSink>, delivery: SinkDelivery) -> Self {
+        Self { sink: Mutex::new(sink), delivery, last_slow_warning: Mutex::new(None) }
+    }
+
+    pub fn on_frame(&self, frame: &Frame) {
+        let started = Instant::now();
+        self.sink.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).on_frame(frame);
+        let elapsed = started.elapsed();
+        if elapsed > Self::SLOW_CALL_THRESHOLD {
+            self.warn_slow(elapsed);
+        }
+    }
+
+    pub fn on_error(&self, err: &WindowsCaptureError) {
+        self.sink.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).on_error(err);
+    }
+
+    fn warn_slow(&self, elapsed: Duration) {
+        let mut last_warning =
+            self.last_slow_warning.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = Instant::now();
+        if last_warning.is_some_and(|last| now.duration_since(last) < Self::SLOW_WARNING_INTERVAL) {
+            return;
+        }
+        *last_warning = Some(now);
+        tracing::warn!(
+            "Frame sink took {:.1} ms, which delays the next frame. Sinks should hand frames off quickly.",
+            elapsed.as_secs_f64() * 1000.0
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::capture_providers::{
+        CaptureProvider, CaptureStream,
+        shared::{BackpressurePolicy, CaptureFramerate, StreamOptions},
+        windows::{
+            WindowsCaptureProviderBuilder, create_capture_item_for_primary_monitor,
+            wgc_capabilities,
+        },
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sinks_see_the_same_frames_as_their_stream() {
+        if !wgc_capabilities().supported {
+            return;
+        }
+        let mut provider =
+            WindowsCaptureProviderBuilder::new().with_default_device().unwrap().build().unwrap();
+        provider.set_capture_item(create_capture_item_for_primary_monitor().unwrap()).unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let seen = seen.clone();
+            move |frame: &Frame| seen.lock().unwrap().push(frame.sequence)
+        };
+        // Frames stay queued rather than being dropped, so the stream gets every frame the sink got.
+        let options =
+            StreamOptions { capacity: 64, policy: BackpressurePolicy::Block, ..Default::default() };
+        let stream = provider
+            .create_sink_stream(CaptureFramerate::FPS30, options, sink, SinkDelivery::SinkAndStream)
+            .unwrap();
+        let mut frames = std::pin::pin!(stream.frames_only());
+        provider.start_capture().await.unwrap();
+
+        // WGC only sends frames when the screen changes, so only the first one is sure to arrive.
+        let first = tokio::time::timeout(TIMEOUT, frames.next()).await.expect("no frame arrived");
+        let mut sequences = vec![first.expect("the stream ended").sequence];
+        provider.stop_capture().await.unwrap();
+        let rest = tokio::time::timeout(TIMEOUT, frames.collect::<Vec<_>>()).await;
+        let rest = rest.expect("the stream didn't end after the capture stopped");
+        sequences.extend(rest.iter().map(|frame| frame.sequence));
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), sequences.len());
+        assert_eq!(*seen, sequences);
+    }
+}
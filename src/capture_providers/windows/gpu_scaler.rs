@@ -0,0 +1,222 @@
+use std::mem::ManuallyDrop;
+
+use windows::Win32::{
+    Foundation::{E_NOTIMPL, RECT},
+    Graphics::{
+        Direct3D11::{
+            D3D11_BIND_RENDER_TARGET, D3D11_TEX2D_VPIV, D3D11_TEX2D_VPOV, D3D11_TEXTURE2D_DESC,
+            D3D11_USAGE_DEFAULT, D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
+            D3D11_VIDEO_PROCESSOR_CONTENT_DESC, D3D11_VIDEO_PROCESSOR_FORMAT_SUPPORT_OUTPUT,
+            D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC, D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC_0,
+            D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC, D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC_0,
+            D3D11_VIDEO_PROCESSOR_STREAM, D3D11_VIDEO_USAGE_PLAYBACK_NORMAL,
+            D3D11_VPIV_DIMENSION_TEXTURE2D, D3D11_VPOV_DIMENSION_TEXTURE2D, ID3D11Device,
+            ID3D11DeviceContext, ID3D11Texture2D, ID3D11VideoContext, ID3D11VideoDevice,
+            ID3D11VideoProcessor, ID3D11VideoProcessorEnumerator, ID3D11VideoProcessorInputView,
+            ID3D11VideoProcessorOutputView,
+        },
+        Dxgi::Common::{DXGI_FORMAT, DXGI_RATIONAL},
+    },
+};
+use windows_core::{Error, Interface, Result};
+
+use crate::capture_providers::shared::{Rect, Vector2};
+
+/// Downscales captured textures with the D3D11 video processor, so only the scaled pixels have to be read back.
+/// Also crops them, as only the source rect is read, and can write them in another format, e.g. to reorder BGRA
+/// to RGBA while the texture is being copied anyway.
+pub(super) struct GpuScaler {
+    device: ID3D11Device,
+    video_device: ID3D11VideoDevice,
+    video_context: ID3D11VideoContext,
+    enumerator: ID3D11VideoProcessorEnumerator,
+    processor: ID3D11VideoProcessor,
+    output: ID3D11Texture2D,
+    output_view: ID3D11VideoProcessorOutputView,
+    format: DXGI_FORMAT,
+    output_format: DXGI_FORMAT,
+    source: Rect<i32>,
+    output_size: Vector2<i32>,
+}
+
+impl GpuScaler {
+    /// Creates a scaler from the `source` rect of the content to `output_size`, both in pixels, which writes
+    /// `output_format` textures. Fails if the video processor can't write that format.
+    pub fn new(
+        device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        format_desc: &D3D11_TEXTURE2D_DESC,
+        output_format: DXGI_FORMAT,
+        source: Rect<i32>,
+        output_size: Vector2<i32>,
+    ) -> Result<Self> {
+        tracing::debug!(
+            "Creating GPU scaler: {} x {} at {}, {} -> {} x {}, {:?} -> {:?}",
+            source.size.x,
+            source.size.y,
+            source.position.x,
+            source.position.y,
+            output_size.x,
+            output_size.y,
+            format_desc.Format,
+            output_format
+        );
+        let input_size = source.end();
+
+        let video_device: ID3D11VideoDevice = device.cast()?;
+        let video_context: ID3D11VideoContext = context.cast()?;
+
+        // Frame rates are only hints for the driver, and frames are never converted in time.
+        let frame_rate = DXGI_RATIONAL { Numerator: 60, Denominator: 1 };
+        let content_desc = D3D11_VIDEO_PROCESSOR_CONTENT_DESC {
+            InputFrameFormat: D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
+            InputFrameRate: frame_rate,
+            InputWidth: input_size.x as u32,
+            InputHeight: input_size.y as u32,
+            OutputFrameRate: frame_rate,
+            OutputWidth: output_size.x as u32,
+            OutputHeight: output_size.y as u32,
+            Usage: D3D11_VIDEO_USAGE_PLAYBACK_NORMAL,
+        };
+        let enumerator = unsafe { video_device.CreateVideoProcessorEnumerator(&content_desc)? };
+        let support = unsafe { enumerator.CheckVideoProcessorFormat(output_format)? };
+        if support & D3D11_VIDEO_PROCESSOR_FORMAT_SUPPORT_OUTPUT.0 as u32 == 0 {
+            return Err(Error::new(E_NOTIMPL, "The video processor can't output this format"));
+        }
+        let processor = unsafe { video_device.CreateVideoProcessor(&enumerator, 0)? };
+
+        let mut desc = *format_desc;
+        desc.Format = output_format;
+        desc.Width = output_size.x as u32;
+        desc.Height = output_size.y as u32;
+        desc.MipLevels = 1;
+        desc.ArraySize = 1;
+        desc.SampleDesc.Count = 1;
+        desc.SampleDesc.Quality = 0;
+        desc.Usage = D3D11_USAGE_DEFAULT;
+        desc.BindFlags = D3D11_BIND_RENDER_TARGET.0 as u32;
+        desc.CPUAccessFlags = 0;
+        desc.MiscFlags = 0;
+
+        let mut output = None;
+        unsafe { device.CreateTexture2D(&desc, None, Some(&mut output))? };
+        let output = output.expect("Failed to create scaler output texture!");
+
+        let output_view_desc = D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC {
+            ViewDimension: D3D11_VPOV_DIMENSION_TEXTURE2D,
+            Anonymous: D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC_0 {
+                Texture2D: D3D11_TEX2D_VPOV { MipSlice: 0 },
+            },
+        };
+        let mut output_view = None;
+        unsafe {
+            video_device.CreateVideoProcessorOutputView(
+                &output,
+                &enumerator,
+                &output_view_desc,
+                Some(&mut output_view),
+            )?
+        };
+        let output_view = output_view.expect("Failed to create scaler output view!");
+
+        let scaler = Self {
+            device: device.clone(),
+            video_device,
+            video_context,
+            enumerator,
+            processor,
+            output,
+            output_view,
+            format: format_desc.Format,
+            output_format,
+            source,
+            output_size,
+        };
+        scaler.set_rects();
+        Ok(scaler)
+    }
+
+    /// Whether this scaler converts between the given rects and formats on the device, otherwise it has to be
+    /// recreated.
+    pub fn matches(
+        &self,
+        device: &ID3D11Device,
+        format: DXGI_FORMAT,
+        output_format: DXGI_FORMAT,
+        source: Rect<i32>,
+        output_size: Vector2<i32>,
+    ) -> bool {
+        self.device == *device
+            && self.format == format
+            && self.output_format == output_format
+            && self.source == source
+            && self.output_size == output_size
+    }
+
+    fn set_rects(&self) {
+        let source_end = self.source.end();
+        let source_rect = RECT {
+            left: self.source.position.x,
+            top: self.source.position.y,
+            right: source_end.x,
+            bottom: source_end.y,
+        };
+        let output_rect =
+            RECT { left: 0, top: 0, right: self.output_size.x, bottom: self.output_size.y };
+
+        unsafe {
+            let context = &self.video_context;
+            context.VideoProcessorSetStreamFrameFormat(
+                &self.processor,
+                0,
+                D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
+            );
+            // The frame pool texture can be larger than the content, e.g. after the window shrunk.
+            context.VideoProcessorSetStreamSourceRect(&self.processor, 0, true, Some(&source_rect));
+            context.VideoProcessorSetStreamDestRect(&self.processor, 0, true, Some(&output_rect));
+            context.VideoProcessorSetOutputTargetRect(&self.processor, true, Some(&output_rect));
+        }
+    }
+
+    /// Scales the texture into the output texture, which is returned.
+    pub fn scale(&self, texture: &ID3D11Texture2D) -> Result<ID3D11Texture2D> {
+        let input_view_desc = D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC {
+            FourCC: 0,
+            ViewDimension: D3D11_VPIV_DIMENSION_TEXTURE2D,
+            Anonymous: D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC_0 {
+                Texture2D: D3D11_TEX2D_VPIV { MipSlice: 0, ArraySlice: 0 },
+            },
+        };
+        let mut input_view: Option<ID3D11VideoProcessorInputView> = None;
+        unsafe {
+            self.video_device.CreateVideoProcessorInputView(
+                texture,
+                &self.enumerator,
+                &input_view_desc,
+                Some(&mut input_view),
+            )?
+        };
+
+        let mut stream = D3D11_VIDEO_PROCESSOR_STREAM {
+            Enable: true.into(),
+            pInputSurface: ManuallyDrop::new(input_view),
+            ..Default::default()
+        };
+        let result = unsafe {
+            self.video_context.VideoProcessorBlt(
+                &self.processor,
+                &self.output_view,
+                0,
+                std::slice::from_ref(&stream),
+            )
+        };
+        // The stream holds a reference to the input view that would be leaked otherwise.
+        unsafe { ManuallyDrop::drop(&mut stream.pInputSurface) };
+        result?;
+
+        Ok(self.output.clone())
+    }
+}
+
+// The D3D11 interfaces are only used from the FrameArrived handler of one stream at a time.
+unsafe impl Send for GpuScaler {}
@@ -0,0 +1,112 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{
+    Stream, StreamExt,
+    stream::{self, BoxStream},
+};
+use tokio::sync::watch;
+
+use crate::capture_providers::{
+    CaptureStream,
+    shared::{CaptureEvent, Frame, StreamId},
+    windows::{FrameSink, WindowsCaptureStream},
+};
+
+/// The newest frame of a capture, for previews and other consumers that only ever show the latest one.
+/// Frames replace each other rather than queueing, so a consumer that can't keep up skips frames instead of
+/// falling behind. As a stream, yields the newest frame whenever there is one it hasn't yielded yet, along with
+/// the other events of the capture, and ends with the capture.
+pub struct LatestFrameHandle {
+    latest: watch::Receiver<Option<Frame>>,
+    frames: BoxStream<'static, Frame>,
+    /// Sink-only, so it carries everything but the frames.
+    events: WindowsCaptureStream,
+}
+
+impl LatestFrameHandle {
+    /// The sink that keeps handles up to date, and the receiving end to create the handle with.
+    pub(super) fn sink() -> (LatestFrameSink, watch::Receiver<Option<Frame>>) {
+        let (tx, rx) = watch::channel(None);
+        (LatestFrameSink(tx), rx)
+    }
+
+    pub(super) fn new(
+        latest: watch::Receiver<Option<Frame>>,
+        events: WindowsCaptureStream,
+    ) -> Self {
+        let frames = stream::unfold(latest.clone(), |mut latest| async move {
+            loop {
+                latest.changed().await.ok()?;
+                let frame = latest.borrow_and_update().clone();
+                if let Some(frame) = frame {
+                    return Some((frame, latest));
+                }
+            }
+        })
+        .boxed();
+        Self { latest, frames, events }
+    }
+
+    /// Identifies the handle to the provider like a stream, see `WindowsCaptureProvider::set_stream_framerate`.
+    pub fn id(&self) -> StreamId {
+        self.events.id()
+    }
+
+    /// The newest frame, `None` until the first one arrived.
+    pub fn latest(&self) -> Option<Frame> {
+        self.latest.borrow().clone()
+    }
+
+    /// Waits for a frame newer than the last one returned by this or `latest`. Returns `None` once the capture
+    /// can't deliver frames to this handle anymore. Separate from the frames the handle yields as a stream.
+    pub async fn changed(&mut self) -> Option<Frame> {
+        loop {
+            self.latest.changed().await.ok()?;
+            if let Some(frame) = self.latest.borrow_and_update().clone() {
+                return Some(frame);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for LatestFrameHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LatestFrameHandle").field("events", &self.events).finish_non_exhaustive()
+    }
+}
+
+impl Stream for LatestFrameHandle {
+    type Item = CaptureEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Events come first, so a frame can't be yielded after the end of the capture.
+        match self.events.poll_next_unpin(cx) {
+            Poll::Ready(event) => return Poll::Ready(event),
+            Poll::Pending => {}
+        }
+        match self.frames.poll_next_unpin(cx) {
+            Poll::Ready(Some(frame)) => Poll::Ready(Some(CaptureEvent::Frame(frame))),
+            // The frames only stop along with the events.
+            Poll::Ready(None) | Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl CaptureStream for LatestFrameHandle {
+    fn id(&self) -> StreamId {
+        LatestFrameHandle::id(self)
+    }
+}
+
+/// Replaces the frame of a `LatestFrameHandle` on the capture thread.
+pub(super) struct LatestFrameSink(watch::Sender<Option<Frame>>);
+
+impl FrameSink for LatestFrameSink {
+    fn on_frame(&mut self, frame: &Frame) {
+        // The data is reference counted, so this doesn't copy the frame.
+        self.0.send_replace(Some(frame.clone()));
+    }
+}
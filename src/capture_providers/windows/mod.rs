@@ -1,11 +1,47 @@
+mod adapters;
+mod advanced_color;
+mod buffer_pool;
 mod builder;
+mod capabilities;
+mod capture_items;
 mod capture_provider;
+mod capture_source;
+mod capture_stats;
 mod capture_stream;
 mod d3d11_utils;
-pub(super) mod error;
+mod device_recovery;
+mod dpi;
+pub mod error;
+mod frame_channel;
+mod frame_limiter;
+mod frame_observer;
+mod frame_sink;
+mod gpu_scaler;
+mod latest_frame;
+mod qpc_clock;
+mod shared_texture;
+mod source_watchdog;
+mod staging_ring;
+mod texture_stream;
+mod unchanged_filter;
 
+pub use adapters::{AdapterInfo, AdapterSelection, list_adapters};
 pub use builder::{BuilderError, WindowsCaptureProviderBuilder};
-pub use capture_provider::WindowsCaptureProvider;
+pub use capabilities::{WgcCapabilities, wgc_capabilities};
+pub use capture_items::{
+    MonitorInfo, TitleMatcher, WindowCandidate, WindowInfo,
+    create_capture_item_for_primary_monitor, create_capture_item_for_target,
+    create_capture_item_for_window, create_capture_item_for_window_title,
+    enumerate_capturable_windows, enumerate_monitors,
+};
+pub use capture_provider::{ReadbackMode, WindowsCaptureProvider};
+pub use capture_source::SourceId;
 pub use capture_stream::WindowsCaptureStream;
-pub use d3d11_utils::user_pick_capture_item;
+pub use d3d11_utils::{IntoHWND, user_pick_capture_item};
+pub use dpi::{DEFAULT_DPI, is_per_monitor_dpi_aware, monitor_scale_factor, window_scale_factor};
 pub(self) use error::{Result, WindowsCaptureError};
+pub use frame_channel::StreamStats;
+pub use frame_observer::{FrameObserver, ObserverToken};
+pub use frame_sink::{FrameSink, SinkDelivery};
+pub use latest_frame::LatestFrameHandle;
+pub use texture_stream::WindowsTextureStream;
@@ -0,0 +1,47 @@
+use std::time::{Duration, Instant};
+
+use windows::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
+
+use crate::capture_providers::shared::CaptureFramerate;
+
+/// Translates system relative times, which are QPC readings in 100ns units, into `Instant`s.
+/// The baseline pairs a QPC reading with an `Instant` taken at the same moment.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct QpcClock {
+    baseline_instant: Instant,
+    baseline_ticks: i64,
+}
+
+impl QpcClock {
+    pub fn now() -> Self {
+        let baseline_ticks = query_qpc_ticks();
+        Self { baseline_instant: Instant::now(), baseline_ticks }
+    }
+
+    pub fn to_instant(&self, ticks: i64) -> Instant {
+        let delta = Duration::from_nanos(ticks.abs_diff(self.baseline_ticks).saturating_mul(100));
+        let instant = if ticks >= self.baseline_ticks {
+            self.baseline_instant.checked_add(delta)
+        } else {
+            self.baseline_instant.checked_sub(delta)
+        };
+        instant.unwrap_or(self.baseline_instant)
+    }
+}
+
+/// Reads the performance counter in 100ns units, the same unit WGC uses for frame times.
+fn query_qpc_ticks() -> i64 {
+    let mut counter = 0;
+    let mut frequency = 0;
+    let result = unsafe {
+        QueryPerformanceCounter(&mut counter)
+            .and_then(|_| QueryPerformanceFrequency(&mut frequency))
+    };
+    if let Err(err) = result {
+        // Can't fail on anything since Windows XP, but frames would all map onto the baseline if it did.
+        tracing::error!("Failed to query performance counter: {}", err);
+        return 0;
+    }
+
+    (counter as i128 * CaptureFramerate::TICKS_PER_SECOND as i128 / frequency as i128) as i64
+}
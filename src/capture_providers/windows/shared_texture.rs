@@ -0,0 +1,142 @@
+use windows::Win32::{
+    Foundation::{CloseHandle, DUPLICATE_SAME_ACCESS, DuplicateHandle, HANDLE},
+    Graphics::{
+        Direct3D11::{
+            D3D11_BIND_RENDER_TARGET, D3D11_BIND_SHADER_RESOURCE,
+            D3D11_RESOURCE_MISC_SHARED_KEYEDMUTEX, D3D11_RESOURCE_MISC_SHARED_NTHANDLE,
+            D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT, ID3D11Device, ID3D11DeviceContext,
+            ID3D11Texture2D,
+        },
+        Dxgi::{
+            DXGI_SHARED_RESOURCE_READ, DXGI_SHARED_RESOURCE_WRITE, IDXGIKeyedMutex, IDXGIResource1,
+        },
+    },
+    System::Threading::GetCurrentProcess,
+};
+use windows_core::{HRESULT, Interface, PCWSTR, Result};
+
+use crate::capture_providers::shared::GpuFrame;
+
+/// `AcquireSync` reports a timeout as a success code, so it has to be checked for explicitly.
+const WAIT_TIMEOUT: HRESULT = HRESULT(0x102);
+
+struct SharedTexture {
+    texture: ID3D11Texture2D,
+    keyed_mutex: IDXGIKeyedMutex,
+    handle: HANDLE,
+}
+
+impl Drop for SharedTexture {
+    fn drop(&mut self) {
+        if let Err(err) = unsafe { CloseHandle(self.handle) } {
+            tracing::warn!("Failed to close shared texture handle: {}", err);
+        }
+    }
+}
+
+/// A small ring of shareable textures that captured frames are copied into.
+pub(super) struct SharedTextureRing {
+    textures: Vec<SharedTexture>,
+    next: usize,
+    width: u32,
+    height: u32,
+}
+
+impl SharedTextureRing {
+    const TEXTURE_COUNT: usize = 3;
+
+    pub fn new(device: &ID3D11Device, source_desc: &D3D11_TEXTURE2D_DESC) -> Result<Self> {
+        tracing::debug!(
+            "Creating shared texture ring: {} x {}",
+            source_desc.Width,
+            source_desc.Height
+        );
+
+        let mut desc = *source_desc;
+        desc.MipLevels = 1;
+        desc.ArraySize = 1;
+        desc.SampleDesc.Count = 1;
+        desc.SampleDesc.Quality = 0;
+        desc.Usage = D3D11_USAGE_DEFAULT;
+        desc.BindFlags = (D3D11_BIND_SHADER_RESOURCE.0 | D3D11_BIND_RENDER_TARGET.0) as u32;
+        desc.CPUAccessFlags = 0;
+        desc.MiscFlags = (D3D11_RESOURCE_MISC_SHARED_NTHANDLE.0
+            | D3D11_RESOURCE_MISC_SHARED_KEYEDMUTEX.0) as u32;
+
+        let mut textures = Vec::with_capacity(Self::TEXTURE_COUNT);
+        for _ in 0..Self::TEXTURE_COUNT {
+            let mut texture = None;
+            unsafe { device.CreateTexture2D(&desc, None, Some(&mut texture))? };
+            let texture = texture.expect("Failed to create shared texture!");
+
+            let keyed_mutex: IDXGIKeyedMutex = texture.cast()?;
+            let resource: IDXGIResource1 = texture.cast()?;
+            let handle = unsafe {
+                resource.CreateSharedHandle(
+                    None,
+                    DXGI_SHARED_RESOURCE_READ | DXGI_SHARED_RESOURCE_WRITE,
+                    PCWSTR::null(),
+                )?
+            };
+
+            textures.push(SharedTexture { texture, keyed_mutex, handle });
+        }
+
+        Ok(Self { textures, next: 0, width: source_desc.Width, height: source_desc.Height })
+    }
+
+    pub fn matches(&self, desc: &D3D11_TEXTURE2D_DESC) -> bool {
+        self.width == desc.Width && self.height == desc.Height
+    }
+
+    /// Copies the source into the next texture not held by a consumer, returning an owned duplicate of its handle.
+    /// Returns `None` if every texture is currently held by a consumer.
+    pub fn copy_from(
+        &mut self,
+        context: &ID3D11DeviceContext,
+        source: &ID3D11Texture2D,
+    ) -> Result<Option<HANDLE>> {
+        for _ in 0..self.textures.len() {
+            let shared = &self.textures[self.next];
+            self.next = (self.next + 1) % self.textures.len();
+
+            // A texture whose frame was never opened by a consumer can simply be overwritten.
+            if !try_acquire(&shared.keyed_mutex, GpuFrame::PRODUCER_KEY)?
+                && !try_acquire(&shared.keyed_mutex, GpuFrame::CONSUMER_KEY)?
+            {
+                continue;
+            }
+
+            unsafe {
+                context.CopyResource(&shared.texture, source);
+                shared.keyed_mutex.ReleaseSync(GpuFrame::CONSUMER_KEY)?;
+            }
+            return duplicate_handle(shared.handle).map(Some);
+        }
+
+        Ok(None)
+    }
+}
+
+// The textures are free-threaded and the handles are owned NT handles.
+unsafe impl Send for SharedTextureRing {}
+
+fn try_acquire(keyed_mutex: &IDXGIKeyedMutex, key: u64) -> Result<bool> {
+    // Called through the vtable, as the generated wrapper hides WAIT_TIMEOUT behind Ok.
+    let result = unsafe {
+        (Interface::vtable(keyed_mutex).AcquireSync)(Interface::as_raw(keyed_mutex), key, 0)
+    };
+    if result == WAIT_TIMEOUT {
+        return Ok(false);
+    }
+    result.ok().map(|_| true)
+}
+
+fn duplicate_handle(handle: HANDLE) -> Result<HANDLE> {
+    let mut duplicate = HANDLE::default();
+    unsafe {
+        let process = GetCurrentProcess();
+        DuplicateHandle(process, handle, process, &mut duplicate, 0, false, DUPLICATE_SAME_ACCESS)?;
+    }
+    Ok(duplicate)
+}
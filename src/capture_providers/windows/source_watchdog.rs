@@ -0,0 +1,125 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::capture_providers::{
+    shared::{CaptureItemInfo, Vector2},
+    windows::capture_items::enumerate_monitors,
+};
+
+/// Notices sessions that silently stopped delivering frames. WGC does that after the system sleeps or the set of
+/// displays changes, without closing the item or reporting an error.
+#[derive(Debug)]
+pub(super) struct SourceWatchdog {
+    /// In milliseconds, 0 when stalls aren't looked for.
+    stall_timeout: AtomicU64,
+}
+
+impl SourceWatchdog {
+    const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(5);
+    /// How often the displays are compared and the sources looked at.
+    const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+    pub fn new() -> Self {
+        Self { stall_timeout: AtomicU64::new(Self::DEFAULT_STALL_TIMEOUT.as_millis() as u64) }
+    }
+
+    pub fn stall_timeout(&self) -> Option<Duration> {
+        match self.stall_timeout.load(Ordering::Relaxed) {
+            0 => None,
+            millis => Some(Duration::from_millis(millis)),
+        }
+    }
+
+    pub fn set_stall_timeout(&self, timeout: Option<Duration>) {
+        // Timeouts below a millisecond would read as disabled.
+        let millis = timeout.map_or(0, |timeout| (timeout.as_millis() as u64).max(1));
+        self.stall_timeout.store(millis, Ordering::Relaxed);
+    }
+
+    /// Starts watching on a separate thread. `check` is called every interval with whether the displays changed
+    /// since the last call, and returns false once there is nothing left to watch, which ends the thread.
+    pub fn spawn(self: &Arc<Self>, mut check: impl FnMut(&Self, bool) -> bool + Send + 'static) {
+        let this = self.clone();
+        let spawned =
+            std::thread::Builder::new().name("loki-source-watchdog".into()).spawn(move || {
+                // The provider has no window to receive WM_DISPLAYCHANGE with, so the monitors are compared instead.
+                let mut displays = display_layout();
+                loop {
+                    std::thread::sleep(Self::CHECK_INTERVAL);
+                    let current = display_layout();
+                    let displays_changed = current != displays;
+                    if displays_changed {
+                        tracing::info!("Display configuration changed, checking capture sources.");
+                        displays = current;
+                    }
+                    if !check(&this, displays_changed) {
+                        return;
+                    }
+                }
+            });
+
+        if let Err(err) = spawned {
+            tracing::error!("Failed to spawn source watchdog thread, stalls go unnoticed: {}", err);
+        }
+    }
+}
+
+fn display_layout() -> Vec<(usize, Vector2<i32>, Vector2<i32>)> {
+    enumerate_monitors()
+        .into_iter()
+        .map(|monitor| (monitor.handle.0 as usize, monitor.position, monitor.size))
+        .collect()
+}
+
+/// What the watchdog last saw of a source.
+#[derive(Debug)]
+pub(super) struct SourceProgress {
+    generation: u64,
+    frames_arrived: u64,
+    since: Instant,
+    /// The window or monitor of the item, looked up once the watchdog first sees it.
+    pub info: Option<CaptureItemInfo>,
+    /// The frame count when the session was last recreated. The first frame of the new session comes regardless of
+    /// whether the source is idle, so idle sources are only recreated once instead of at every timeout.
+    recreated_at: Option<u64>,
+}
+
+impl SourceProgress {
+    pub fn new(generation: u64, frames_arrived: u64) -> Self {
+        Self { generation, frames_arrived, since: Instant::now(), info: None, recreated_at: None }
+    }
+
+    /// Records the frame count of the source. A replaced item starts over.
+    pub fn update(&mut self, generation: u64, frames_arrived: u64) {
+        if generation != self.generation {
+            *self = Self::new(generation, frames_arrived);
+            return;
+        }
+        if frames_arrived == self.frames_arrived {
+            return;
+        }
+        self.frames_arrived = frames_arrived;
+        if self.recreated_at.is_some_and(|recreated_at| frames_arrived <= recreated_at + 1) {
+            return;
+        }
+        self.recreated_at = None;
+        self.since = Instant::now();
+    }
+
+    /// Whether the source should be checked, because it went quiet for `timeout` or the displays just changed.
+    pub fn is_stalled(&self, timeout: Option<Duration>, displays_changed: bool) -> bool {
+        displays_changed
+            || (self.recreated_at.is_none()
+                && timeout.is_some_and(|timeout| self.since.elapsed() >= timeout))
+    }
+
+    pub fn mark_recreated(&mut self) {
+        self.recreated_at = Some(self.frames_arrived);
+        self.since = Instant::now();
+    }
+}
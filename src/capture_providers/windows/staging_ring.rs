@@ -0,0 +1,403 @@
+use std::ops::Range;
+
+use windows::Win32::Graphics::Direct3D11::{
+    D3D11_TEXTURE2D_DESC, ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D,
+};
+
+use crate::capture_providers::{
+    shared::{PixelFormat, Rect},
+    windows::{
+        ReadbackMode,
+        d3d11_utils::{copy_texture_region, read_staging_texture},
+    },
+};
+
+/// The staging textures of a stream. The FrameArrived handler queues the GPU copy of every frame into one and
+/// reads back whichever copy finished, so a busy GPU doesn't block the handler until its copy is done.
+/// With a depth of 1 the readback is synchronous: there is no other texture to queue the next frame into, so
+/// every frame waits for its own copy.
+/// `T` is what the handler needs to turn the data back into a frame.
+pub(super) struct StagingRing<T> {
+    slots: Vec<Slot>,
+    /// Which slot holds which frame, kept apart from the textures so it works the same without a GPU.
+    pipeline: Pipeline<T>,
+    /// Number of staging textures.
+    depth: usize,
+    /// Textures are tied to the device they were created on, which changes when the device is recovered.
+    device: Option<ID3D11Device>,
+}
+
+struct Slot {
+    texture: ID3D11Texture2D,
+    desc: D3D11_TEXTURE2D_DESC,
+}
+
+/// The frames in flight, one entry per staging texture.
+struct Pipeline<T> {
+    pending: Vec<Option<Pending<T>>>,
+    /// Copies queued so far, to tell which pending frame is the newest.
+    copies: u64,
+}
+
+struct Pending<T> {
+    order: u64,
+    format: PixelFormat,
+    /// The rows to read back, `None` for all of them.
+    rows: Option<Range<usize>>,
+    frame: T,
+}
+
+/// Where the next copy goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Claim {
+    Free(usize),
+    /// There is no free slot yet, but the ring may grow by one.
+    Grow,
+    /// Every slot is in flight, the oldest one has to be dropped.
+    Evict(usize),
+}
+
+/// A frame that finished its copy and was read back.
+pub(super) struct Readback<T> {
+    pub frame: T,
+    /// The rows that were read back, `None` if all of them were.
+    pub rows: Option<Range<usize>>,
+    pub stride: usize,
+    /// Older frames that were still in flight and were dropped, as they would only arrive out of order.
+    pub superseded: u64,
+}
+
+impl<T> StagingRing<T> {
+    pub fn new(depth: usize) -> Self {
+        let depth = depth.max(1);
+        Self { slots: Vec::with_capacity(depth), pipeline: Pipeline::new(), depth, device: None }
+    }
+
+    /// Queues the copy of `texture`, or `region` of it, into a free staging texture, creating one of the right size
+    /// if needed. Only `rows` of it are read back, along with the rows of frames that are dropped before they are,
+    /// so the data that is read back always covers every change since the last frame that was.
+    /// Returns whether the oldest frame still in flight had to be dropped to make room.
+    pub fn queue_copy(
+        &mut self,
+        device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        texture: &ID3D11Texture2D,
+        region: Option<Rect<i32>>,
+        desc: &D3D11_TEXTURE2D_DESC,
+        format: PixelFormat,
+        mut rows: Option<Range<usize>>,
+        frame: T,
+    ) -> super::Result<bool> {
+        if self.device.as_ref() != Some(device) {
+            // Whatever changed in frames that were still in flight is lost with them.
+            if self.pipeline.in_flight() > 0 {
+                rows = None;
+            }
+            self.slots.clear();
+            self.pipeline = Pipeline::new();
+            self.device = Some(device.clone());
+        }
+
+        let fits = |index: usize| {
+            let slot = &self.slots[index].desc;
+            (slot.Width, slot.Height, slot.Format) == (desc.Width, desc.Height, desc.Format)
+        };
+        let (index, evicted) = match self.pipeline.claim(self.depth, fits) {
+            Claim::Free(index) => (index, false),
+            Claim::Grow => {
+                self.slots.push(Slot { texture: Self::create(device, desc)?, desc: *desc });
+                self.pipeline.pending.push(None);
+                (self.slots.len() - 1, false)
+            }
+            Claim::Evict(index) => {
+                rows = union_rows(rows, self.pipeline.evict(index));
+                (index, true)
+            }
+        };
+
+        let slot = &mut self.slots[index];
+        if (slot.desc.Width, slot.desc.Height, slot.desc.Format)
+            != (desc.Width, desc.Height, desc.Format)
+        {
+            slot.texture = Self::create(device, desc)?;
+            slot.desc = *desc;
+        }
+        copy_texture_region(context, &slot.texture, texture, region);
+        self.pipeline.push(index, format, rows, frame);
+        Ok(evicted)
+    }
+
+    /// Reads back the newest frame whose copy finished into `dst`, `None` if none has yet. Every pending copy is
+    /// checked once without waiting, newest first, so when the newest is still in flight the previous one that is
+    /// ready is read instead. Frames that are left are tried again with the next call.
+    pub fn read(
+        &mut self,
+        context: &ID3D11DeviceContext,
+        mode: ReadbackMode,
+        dst: &mut Vec<u8>,
+    ) -> super::Result<Option<Readback<T>>> {
+        let wait = self.depth == 1;
+        let slots = &self.slots;
+        self.pipeline.read_newest_ready(|index, format, rows| {
+            let read = read_staging_texture(
+                context,
+                &slots[index].texture,
+                format,
+                mode,
+                rows,
+                wait,
+                dst,
+            )?;
+            if read.is_none() {
+                tracing::debug!("Staging texture {} is still being copied to.", index);
+            }
+            Ok(read)
+        })
+    }
+
+    fn create(
+        device: &ID3D11Device,
+        desc: &D3D11_TEXTURE2D_DESC,
+    ) -> super::Result<ID3D11Texture2D> {
+        let mut texture = None;
+        unsafe { device.CreateTexture2D(desc, None, Some(&mut texture))? };
+        Ok(texture.expect("CreateTexture2D succeeded without a texture"))
+    }
+}
+
+impl<T> Pipeline<T> {
+    fn new() -> Self {
+        Self { pending: Vec::new(), copies: 0 }
+    }
+
+    fn in_flight(&self) -> usize {
+        self.pending.iter().flatten().count()
+    }
+
+    /// Picks a free slot, preferring one whose texture `fits` the frame, then growing up to `depth` slots, and
+    /// only then the oldest frame in flight.
+    fn claim(&self, depth: usize, fits: impl Fn(usize) -> bool) -> Claim {
+        let free = |index: &usize| self.pending[*index].is_none();
+        let mut indices = 0..self.pending.len();
+        if let Some(index) = indices.clone().filter(free).find(|&index| fits(index)) {
+            return Claim::Free(index);
+        }
+        if let Some(index) = indices.find(free) {
+            return Claim::Free(index);
+        }
+        if self.pending.len() < depth {
+            return Claim::Grow;
+        }
+        let oldest = (0..self.pending.len())
+            .min_by_key(|&index| self.order(index))
+            .expect("The ring is never empty when full");
+        Claim::Evict(oldest)
+    }
+
+    /// Drops the frame of the slot, returning its rows. The frames left in flight read them back along with
+    /// their own, as they are the next ones to be read back.
+    fn evict(&mut self, index: usize) -> Option<Range<usize>> {
+        let lost = self.pending[index].take().expect("Evicted a pending slot").rows;
+        for pending in self.pending.iter_mut().flatten() {
+            pending.rows = union_rows(pending.rows.take(), lost.clone());
+        }
+        lost
+    }
+
+    fn push(&mut self, index: usize, format: PixelFormat, rows: Option<Range<usize>>, frame: T) {
+        self.pending[index] = Some(Pending { order: self.copies, format, rows, frame });
+        self.copies += 1;
+    }
+
+    /// Tries `read` on every slot in flight, newest first, until one of them is ready. `read` gets the slot, the
+    /// format of its frame and the rows to read back, and returns the stride or `None` if the copy isn't done.
+    fn read_newest_ready(
+        &mut self,
+        mut read: impl FnMut(usize, PixelFormat, Option<Range<usize>>) -> super::Result<Option<usize>>,
+    ) -> super::Result<Option<Readback<T>>> {
+        let mut in_flight: Vec<usize> =
+            (0..self.pending.len()).filter(|&index| self.pending[index].is_some()).collect();
+        in_flight.sort_by_key(|&index| std::cmp::Reverse(self.order(index)));
+
+        for index in in_flight {
+            // Reading a frame drops every older one, whose changes have to be read back along with it.
+            let order = self.order(index);
+            let rows = self
+                .pending
+                .iter()
+                .flatten()
+                .filter(|pending| pending.order <= order)
+                .try_fold(0..0, |rows, pending| union_rows(Some(rows), pending.rows.clone()));
+            let format = self.pending[index].as_ref().expect("Slot was pending").format;
+            let Some(stride) = read(index, format, rows.clone())? else {
+                continue;
+            };
+
+            let frame = self.pending[index].take().expect("Slot was pending").frame;
+            let mut superseded = 0;
+            for pending in &mut self.pending {
+                if pending.as_ref().is_some_and(|pending| pending.order < order) {
+                    *pending = None;
+                    superseded += 1;
+                }
+            }
+            return Ok(Some(Readback { frame, rows, stride, superseded }));
+        }
+        Ok(None)
+    }
+
+    fn order(&self, index: usize) -> u64 {
+        self.pending[index].as_ref().map_or(0, |pending| pending.order)
+    }
+}
+
+/// The rows covering both, where `None` is all of them.
+fn union_rows(a: Option<Range<usize>>, b: Option<Range<usize>>) -> Option<Range<usize>> {
+    match (a?, b?) {
+        (a, b) if a.is_empty() => Some(b),
+        (a, b) if b.is_empty() => Some(a),
+        (a, b) => Some(a.start.min(b.start)..a.end.max(b.end)),
+    }
+}
+
+// Only used from the FrameArrived handler of one stream at a time, same as the scaler.
+unsafe impl<T: Send> Send for StagingRing<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the ring does with the room it got, without the textures.
+    fn queue(
+        pipeline: &mut Pipeline<u64>,
+        depth: usize,
+        rows: Option<Range<usize>>,
+        frame: u64,
+    ) -> bool {
+        let (index, rows, evicted) = match pipeline.claim(depth, |_| true) {
+            Claim::Free(index) => (index, rows, false),
+            Claim::Grow => {
+                pipeline.pending.push(None);
+                (pipeline.pending.len() - 1, rows, false)
+            }
+            Claim::Evict(index) => {
+                let lost = pipeline.evict(index);
+                (index, union_rows(rows, lost), true)
+            }
+        };
+        pipeline.push(index, PixelFormat::BGRA8, rows, frame);
+        evicted
+    }
+
+    /// Reads back with the copies of the given frames still running.
+    fn read(pipeline: &mut Pipeline<u64>, stalled: &[u64]) -> Option<Readback<u64>> {
+        let frames: Vec<Option<u64>> = pipeline
+            .pending
+            .iter()
+            .map(|pending| pending.as_ref().map(|pending| pending.frame))
+            .collect();
+        pipeline
+            .read_newest_ready(|index, _, _| {
+                let frame = frames[index].expect("Read a free slot");
+                Ok((!stalled.contains(&frame)).then_some(4))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn union_rows_covers_both() {
+        assert_eq!(union_rows(Some(2..4), Some(6..8)), Some(2..8));
+        assert_eq!(union_rows(Some(0..0), Some(6..8)), Some(6..8));
+        assert_eq!(union_rows(Some(2..4), Some(3..3)), Some(2..4));
+        assert_eq!(union_rows(None, Some(6..8)), None);
+        assert_eq!(union_rows(Some(2..4), None), None);
+    }
+
+    #[test]
+    fn reads_every_frame_without_stalls() {
+        let mut pipeline = Pipeline::new();
+        for frame in 0..5 {
+            assert!(!queue(&mut pipeline, 2, None, frame));
+            let readback = read(&mut pipeline, &[]).expect("Nothing stalled");
+            assert_eq!((readback.frame, readback.superseded), (frame, 0));
+            assert_eq!(pipeline.in_flight(), 0);
+        }
+        // Nothing was in flight, so the first slot was reused every time.
+        assert_eq!(pipeline.pending.len(), 1);
+    }
+
+    #[test]
+    fn falls_back_to_the_previous_ready_frame() {
+        let mut pipeline = Pipeline::new();
+        queue(&mut pipeline, 3, None, 0);
+        assert!(read(&mut pipeline, &[0]).is_none());
+        queue(&mut pipeline, 3, None, 1);
+        let readback = read(&mut pipeline, &[1]).expect("Frame 0 is ready");
+        assert_eq!((readback.frame, readback.superseded), (0, 0));
+        // Frame 1 is left in flight and read with the next call.
+        queue(&mut pipeline, 3, None, 2);
+        let readback = read(&mut pipeline, &[2]).expect("Frame 1 is ready");
+        assert_eq!((readback.frame, readback.superseded), (1, 0));
+        let readback = read(&mut pipeline, &[]).expect("Frame 2 is ready");
+        assert_eq!(readback.frame, 2);
+        assert_eq!(pipeline.in_flight(), 0);
+    }
+
+    #[test]
+    fn reading_a_newer_frame_supersedes_older_ones() {
+        let mut pipeline = Pipeline::new();
+        queue(&mut pipeline, 3, Some(0..2), 0);
+        queue(&mut pipeline, 3, Some(8..10), 1);
+        let readback = read(&mut pipeline, &[]).expect("Frame 1 is ready");
+        assert_eq!((readback.frame, readback.superseded), (1, 1));
+        // The changes of the dropped frame are read back along with it.
+        assert_eq!(readback.rows, Some(0..10));
+        assert_eq!(pipeline.in_flight(), 0);
+    }
+
+    #[test]
+    fn evicts_the_oldest_frame_when_every_copy_stalls() {
+        let mut pipeline = Pipeline::new();
+        assert!(!queue(&mut pipeline, 2, Some(0..1), 0));
+        assert!(!queue(&mut pipeline, 2, Some(4..5), 1));
+        assert!(read(&mut pipeline, &[0, 1]).is_none());
+        assert!(queue(&mut pipeline, 2, Some(8..9), 2));
+        assert_eq!(pipeline.pending.len(), 2);
+        assert!(read(&mut pipeline, &[1, 2]).is_none());
+        // Frame 0 was dropped, its rows went to both frames that are left.
+        let readback = read(&mut pipeline, &[]).expect("Nothing stalled");
+        assert_eq!((readback.frame, readback.superseded), (2, 1));
+        assert_eq!(readback.rows, Some(0..9));
+    }
+
+    #[test]
+    fn indices_stay_consistent_under_stall_patterns() {
+        for depth in 1..=4 {
+            for pattern in [0b1010_1010u64, 0b1100_1100, 0b1110_0111, 0b0001_0000, u64::MAX, 0] {
+                let mut pipeline = Pipeline::new();
+                let (mut read_back, mut dropped, mut last) = (0u64, 0u64, None);
+                for frame in 0..64u64 {
+                    dropped += queue(&mut pipeline, depth, None, frame) as u64;
+                    assert!(pipeline.pending.len() <= depth);
+                    let stalled: Vec<u64> = (0..=frame)
+                        .filter(|stalled| pattern >> ((stalled + frame) % 64) & 1 == 1)
+                        .collect();
+                    if let Some(readback) = read(&mut pipeline, &stalled) {
+                        // Frames only ever come out in the order they went in.
+                        assert!(last < Some(readback.frame));
+                        last = Some(readback.frame);
+                        read_back += 1;
+                        dropped += readback.superseded;
+                    }
+                }
+                // Every frame is read back, dropped or still in flight.
+                assert_eq!(
+                    read_back + dropped + pipeline.in_flight() as u64,
+                    64,
+                    "depth {}",
+                    depth
+                );
+            }
+        }
+    }
+}
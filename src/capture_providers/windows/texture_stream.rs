@@ -0,0 +1,73 @@
+use std::{
+    sync::mpsc::Sender,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use tokio::sync::watch;
+
+use crate::capture_providers::{
+    shared::{GpuFrame, StreamId},
+    windows::{
+        capture_stream::StreamToken,
+        frame_channel::{FrameReceiver, StreamStats},
+    },
+};
+
+/// Frames that stay on the GPU, see `WindowsCaptureProvider::create_texture_stream`. Queued like the frames of a
+/// `WindowsCaptureStream`, so the same `StreamOptions` apply.
+#[derive(Debug)]
+pub struct WindowsTextureStream {
+    channel: FrameReceiver<GpuFrame>,
+    token: StreamToken,
+    /// Where the token goes when the stream is dropped.
+    closed: Sender<StreamToken>,
+}
+
+impl WindowsTextureStream {
+    pub(super) fn new(
+        channel: FrameReceiver<GpuFrame>,
+        token: StreamToken,
+        closed: Sender<StreamToken>,
+    ) -> Self {
+        Self { channel, token, closed }
+    }
+
+    /// Identifies the stream to the provider, see `WindowsCaptureProvider::set_stream_framerate`.
+    pub fn id(&self) -> StreamId {
+        StreamId(self.token.id)
+    }
+
+    /// Number of frames lost to the backpressure policy so far.
+    pub fn dropped_frames(&self) -> u64 {
+        self.channel.dropped_frames()
+    }
+
+    /// Frames queued and dropped so far, see `StreamStats`.
+    pub fn stats(&self) -> StreamStats {
+        self.channel.stats()
+    }
+
+    /// See `WindowsCaptureStream::watch_stats`.
+    pub fn watch_stats(&self) -> watch::Receiver<StreamStats> {
+        self.channel.watch_stats()
+    }
+}
+
+impl Stream for WindowsTextureStream {
+    type Item = GpuFrame;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.channel.poll_recv(cx)
+    }
+}
+
+impl Drop for WindowsTextureStream {
+    fn drop(&mut self) {
+        // Nothing left to detach from if the provider is gone.
+        let _ = self.closed.send(self.token);
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    sync::atomic::{AtomicI64, Ordering},
+    time::Duration,
+};
+
+use crate::capture_providers::shared::Rect;
+
+/// Decides whether a frame can be skipped because its dirty regions show (almost) nothing changed.
+/// A frame is still let through every keepalive interval so consumers don't appear frozen.
+#[derive(Debug)]
+pub(super) struct UnchangedFrameFilter {
+    pixel_threshold: u64,
+    keepalive_ticks: i64,
+    last_delivered: AtomicI64,
+}
+
+impl UnchangedFrameFilter {
+    pub fn new(pixel_threshold: u64, keepalive: Duration) -> Self {
+        Self {
+            pixel_threshold,
+            // TimeSpans are in 100ns ticks.
+            keepalive_ticks: (keepalive.as_nanos() / 100) as i64,
+            last_delivered: AtomicI64::new(i64::MIN),
+        }
+    }
+
+    fn dirty_area(dirty_rects: &[Rect<i32>]) -> u64 {
+        dirty_rects.iter().map(Rect::area).sum()
+    }
+
+    /// Whether the frame can be skipped. `timestamp` is the frame's system relative time.
+    pub fn should_skip(&self, dirty_rects: &[Rect<i32>], timestamp: i64) -> bool {
+        let unchanged =
+            dirty_rects.is_empty() || Self::dirty_area(dirty_rects) < self.pixel_threshold;
+        if !unchanged {
+            return false;
+        }
+
+        let last_delivered = self.last_delivered.load(Ordering::Relaxed);
+        timestamp.saturating_sub(last_delivered) < self.keepalive_ticks
+    }
+
+    pub fn mark_delivered(&self, timestamp: i64) {
+        self.last_delivered.store(timestamp, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture_providers::shared::Vector2;
+
+    /// One second in 100ns ticks.
+    const SECOND: i64 = 10_000_000;
+
+    fn rect(width: i32, height: i32) -> Rect<i32> {
+        Rect { position: Vector2 { x: 0, y: 0 }, size: Vector2 { x: width, y: height } }
+    }
+
+    fn filter(pixel_threshold: u64) -> UnchangedFrameFilter {
+        let filter = UnchangedFrameFilter::new(pixel_threshold, Duration::from_secs(1));
+        filter.mark_delivered(0);
+        filter
+    }
+
+    #[test]
+    fn frames_without_dirty_regions_are_skipped() {
+        assert!(filter(0).should_skip(&[], SECOND / 2));
+    }
+
+    #[test]
+    fn frames_below_the_threshold_are_skipped() {
+        // 2 * 10 + 3 * 3 = 29 pixels.
+        assert!(filter(30).should_skip(&[rect(2, 10), rect(3, 3)], SECOND / 2));
+    }
+
+    #[test]
+    fn frames_at_or_above_the_threshold_are_kept() {
+        let filter = filter(30);
+        assert!(!filter.should_skip(&[rect(5, 6)], SECOND / 2));
+        assert!(!filter.should_skip(&[rect(2, 10), rect(4, 4)], SECOND / 2));
+    }
+
+    #[test]
+    fn unchanged_frames_are_kept_once_the_keepalive_is_due() {
+        let filter = filter(30);
+        assert!(filter.should_skip(&[], SECOND - 1));
+        assert!(!filter.should_skip(&[], SECOND));
+        filter.mark_delivered(SECOND);
+        assert!(filter.should_skip(&[rect(1, 1)], SECOND + 1));
+    }
+
+    #[test]
+    fn the_first_frame_is_always_kept() {
+        let filter = UnchangedFrameFilter::new(30, Duration::from_secs(1));
+        assert!(!filter.should_skip(&[], 0));
+    }
+}
@@ -0,0 +1,145 @@
+use std::{num::NonZeroU32, path::PathBuf, time::Duration};
+
+#[cfg(target_os = "windows")]
+use clap::ValueEnum;
+use clap::{Args, Parser, Subcommand};
+#[cfg(target_os = "windows")]
+use loki::capture::{Source, TitleMatcher};
+#[cfg(feature = "net")]
+use loki::net::StreamServerOptions;
+use tracing::Level;
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Maximum level of log messages, e.g. error, warn, info, debug or trace.
+    /// Overrides the LOKI_LOG and RUST_LOG filters, INFO is used if none are set.
+    #[arg(long, global = true)]
+    pub log_level: Option<Level>,
+
+    /// Logs every captured frame to the log file, along with everything else at trace level.
+    #[arg(long, global = true)]
+    pub trace_frames: bool,
+
+    #[command(flatten)]
+    pub serve: ServeArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Serving the capture to other machines, for the preview window and headless captures alike.
+/// Empty without the net feature.
+#[derive(Debug, Clone, Args)]
+pub struct ServeArgs {
+    /// Serves the capture on this port, at /stream.mjpeg for browsers and /ws for WebSocket clients.
+    #[cfg(feature = "net")]
+    #[arg(long, global = true)]
+    pub serve: Option<u16>,
+
+    /// Quality of the served JPEG frames, from 1 to 100.
+    #[cfg(feature = "net")]
+    #[arg(
+        long,
+        global = true,
+        default_value_t = StreamServerOptions::DEFAULT_JPEG_QUALITY,
+        value_parser = clap::value_parser!(u8).range(1..=100),
+    )]
+    pub serve_quality: u8,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Opens the preview window. This is the default.
+    Gui,
+    /// Captures a monitor or window without opening any window. Only on Windows, like headless sessions.
+    #[cfg(target_os = "windows")]
+    Capture(CaptureArgs),
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Args)]
+pub struct CaptureArgs {
+    /// Index of the monitor to capture. The primary monitor is captured if neither this nor --window is given.
+    #[arg(long, conflicts_with = "window")]
+    pub monitor: Option<usize>,
+
+    /// Captures the window whose title matches this text, ignoring case. Fails if several windows match.
+    #[arg(long)]
+    pub window: Option<String>,
+
+    /// How --window is compared to window titles.
+    #[arg(long, value_enum, default_value_t = TitleMatch::Contains, requires = "window")]
+    pub title_match: TitleMatch,
+
+    #[arg(long, default_value = "60")]
+    pub fps: NonZeroU32,
+
+    /// Stops after this many seconds. Runs until Ctrl+C or until the source closes otherwise.
+    #[arg(long)]
+    pub duration: Option<f64>,
+
+    /// Directory to write numbered PNG frames to.
+    #[cfg_attr(
+        feature = "net",
+        arg(long, required_unless_present_any = ["raw", "shared_memory", "serve"])
+    )]
+    #[cfg_attr(not(feature = "net"), arg(long, required_unless_present_any = ["raw", "shared_memory"]))]
+    pub out: Option<PathBuf>,
+
+    /// Writes tightly packed RGBA frames to stdout instead, e.g. for piping into ffmpeg.
+    #[arg(long, conflicts_with = "out")]
+    pub raw: bool,
+
+    /// Also publishes frames in the shared memory mapping of this name, e.g. Local\loki, for other processes.
+    #[arg(long)]
+    pub shared_memory: Option<String>,
+
+    /// Frames wider than this are not exported to shared memory, as the mapping is sized up front.
+    #[arg(long, default_value = "3840", requires = "shared_memory")]
+    pub shared_memory_max_width: u32,
+
+    /// Frames taller than this are not exported to shared memory.
+    #[arg(long, default_value = "2160", requires = "shared_memory")]
+    pub shared_memory_max_height: u32,
+
+    /// Leaves the cursor out of the captured frames.
+    #[arg(long)]
+    pub no_cursor: bool,
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TitleMatch {
+    Exact,
+    Prefix,
+    Contains,
+}
+
+#[cfg(target_os = "windows")]
+impl CaptureArgs {
+    pub fn source(&self) -> Source {
+        match (&self.window, self.monitor) {
+            (Some(title), _) => Source::WindowTitle(match self.title_match {
+                TitleMatch::Exact => TitleMatcher::Exact(title.clone()),
+                TitleMatch::Prefix => TitleMatcher::Prefix(title.clone()),
+                TitleMatch::Contains => TitleMatcher::Contains(title.clone()),
+            }),
+            (None, Some(index)) => Source::MonitorIndex(index),
+            (None, None) => Source::PrimaryMonitor,
+        }
+    }
+
+    /// Negative, infinite or NaN durations mean no limit.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration.and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+    }
+}
+
+#[cfg(feature = "net")]
+impl ServeArgs {
+    /// `None` if serving wasn't asked for.
+    pub fn options(&self) -> Option<StreamServerOptions> {
+        self.serve.map(|port| StreamServerOptions::new(port).with_jpeg_quality(self.serve_quality))
+    }
+}
@@ -0,0 +1,5 @@
+//! Handing captured frames to other processes.
+
+mod shared_memory;
+
+pub use shared_memory::{SharedFrame, SharedMemExporter, SharedMemImporter, SharedMemoryError};
@@ -0,0 +1,585 @@
+use std::{
+    sync::atomic::{AtomicI64, AtomicU32, AtomicU64, Ordering, fence},
+    time::Duration,
+};
+
+use windows::Win32::{
+    Foundation::{
+        CloseHandle, ERROR_ALREADY_EXISTS, GetLastError, HANDLE, INVALID_HANDLE_VALUE,
+        WAIT_OBJECT_0, WAIT_TIMEOUT,
+    },
+    System::{
+        Memory::{
+            CreateFileMappingW, FILE_MAP_ALL_ACCESS, FILE_MAP_READ, MEMORY_MAPPED_VIEW_ADDRESS,
+            MapViewOfFile, OpenFileMappingW, PAGE_READWRITE, UnmapViewOfFile,
+        },
+        Threading::{
+            CreateEventW, OpenEventW, SYNCHRONIZATION_SYNCHRONIZE, SetEvent, WaitForSingleObject,
+        },
+    },
+};
+use windows_core::HSTRING;
+
+use crate::capture_providers::{
+    shared::{Frame, PixelFormat},
+    windows::FrameSink,
+};
+
+/// "LOKI" in little endian, marks a mapping written by `SharedMemExporter`.
+const MAGIC: u32 = u32::from_le_bytes(*b"LOKI");
+/// Bumped whenever the layout changes, readers refuse mappings of other versions.
+const LAYOUT_VERSION: u32 = 1;
+const SLOT_COUNT: usize = 2;
+/// Headers are padded to this, so the pixel data of every slot starts on a cache line.
+const ALIGNMENT: usize = 64;
+/// `control.latest_slot` before the first frame.
+const NO_SLOT: u32 = u32::MAX;
+/// How often a reader tries again when the writer keeps overwriting the slot it reads.
+const READ_ATTEMPTS: u32 = 8;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SharedMemoryError {
+    #[error("Frames of {0} x {1} don't fit into a mapping")]
+    InvalidSize(u32, u32),
+    #[error("A shared memory mapping named {0:?} already exists")]
+    AlreadyExists(String),
+    #[error("The mapping is not a loki frame export, or of another version")]
+    IncompatibleLayout,
+    #[error("Frame of {actual} bytes exceeds the capacity of {capacity} bytes")]
+    FrameTooLarge { actual: usize, capacity: usize },
+    #[error("The frame was overwritten while being read, too many times in a row")]
+    Contended,
+    #[error("Windows error: {0}")]
+    WindowsError(#[from] windows_core::Error),
+}
+
+pub type Result<T> = std::result::Result<T, SharedMemoryError>;
+
+/// Start of the mapping, followed by `SLOT_COUNT` slots.
+///
+/// This and `SlotHeader` are the layout seen by readers in other processes, e.g. an OBS plugin. All fields are
+/// little endian, and every slot is `slot_stride` bytes from the previous one.
+#[repr(C)]
+struct ControlBlock {
+    magic: u32,
+    version: u32,
+    max_width: u32,
+    max_height: u32,
+    /// Bytes of pixel data a slot can hold.
+    slot_capacity: u64,
+    /// Bytes from the start of one slot to the next, including its header.
+    slot_stride: u64,
+    /// The slot holding the newest frame, `NO_SLOT` before the first.
+    latest_slot: AtomicU32,
+    /// Cleared when the exporter goes away, so readers can tell a paused capture from a finished one.
+    writer_alive: AtomicU32,
+}
+
+/// Start of every slot, followed by the pixel data at `ALIGNMENT`.
+///
+/// Slots are guarded by a sequence lock: `seq` is odd while the writer fills the slot, and changes with every
+/// write. A reader copies the slot and only keeps the copy if `seq` was even and unchanged throughout.
+#[repr(C)]
+struct SlotHeader {
+    seq: AtomicU64,
+    sequence: AtomicU64,
+    timestamp: AtomicI64,
+    width: AtomicU32,
+    height: AtomicU32,
+    stride: AtomicU32,
+    format: AtomicU32,
+    data_len: AtomicU64,
+}
+
+const fn align(size: usize) -> usize {
+    size.div_ceil(ALIGNMENT) * ALIGNMENT
+}
+
+const CONTROL_SIZE: usize = align(size_of::<ControlBlock>());
+const SLOT_HEADER_SIZE: usize = align(size_of::<SlotHeader>());
+
+/// Stable numbers of the pixel formats, as formats are stored in the mapping.
+fn format_code(format: PixelFormat) -> u32 {
+    match format {
+        PixelFormat::RGBA8 => 0,
+        PixelFormat::BGRA8 => 1,
+        PixelFormat::NV12 => 2,
+        PixelFormat::I420 => 3,
+        PixelFormat::Gray8 => 4,
+        PixelFormat::RGBA16F => 5,
+    }
+}
+
+fn format_from_code(code: u32) -> Option<PixelFormat> {
+    match code {
+        0 => Some(PixelFormat::RGBA8),
+        1 => Some(PixelFormat::BGRA8),
+        2 => Some(PixelFormat::NV12),
+        3 => Some(PixelFormat::I420),
+        4 => Some(PixelFormat::Gray8),
+        5 => Some(PixelFormat::RGBA16F),
+        _ => None,
+    }
+}
+
+/// A mapped view and the mapping handle behind it. The mapping goes away with its last handle, in whichever
+/// process that is.
+struct Mapping {
+    handle: HANDLE,
+    view: MEMORY_MAPPED_VIEW_ADDRESS,
+}
+
+impl Mapping {
+    fn control(&self) -> &ControlBlock {
+        // SAFETY: The view starts with a control block, validated by the reader before use.
+        unsafe { &*self.view.Value.cast::<ControlBlock>() }
+    }
+
+    fn slot_offset(&self, slot: usize) -> usize {
+        CONTROL_SIZE + slot * self.control().slot_stride as usize
+    }
+
+    fn slot(&self, slot: usize) -> &SlotHeader {
+        // SAFETY: Slots lie within the view, see `SharedMemExporter::new`.
+        unsafe { &*self.view.Value.cast::<u8>().add(self.slot_offset(slot)).cast::<SlotHeader>() }
+    }
+
+    fn slot_data(&self, slot: usize) -> *mut u8 {
+        // SAFETY: Same as `slot`, the data follows its header.
+        unsafe { self.view.Value.cast::<u8>().add(self.slot_offset(slot) + SLOT_HEADER_SIZE) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(err) = UnmapViewOfFile(self.view) {
+                tracing::warn!("Failed to unmap shared memory view: {}", err);
+            }
+            if let Err(err) = CloseHandle(self.handle) {
+                tracing::warn!("Failed to close shared memory mapping: {}", err);
+            }
+        }
+    }
+}
+
+// The view is plain memory, every access to shared state goes through atomics or the seqlock.
+unsafe impl Send for Mapping {}
+
+struct Event(HANDLE);
+
+impl Drop for Event {
+    fn drop(&mut self) {
+        if let Err(err) = unsafe { CloseHandle(self.0) } {
+            tracing::warn!("Failed to close frame event: {}", err);
+        }
+    }
+}
+
+unsafe impl Send for Event {}
+
+/// The event signalled after every frame. Auto-reset, so it wakes a single reader.
+fn event_name(name: &str) -> HSTRING {
+    HSTRING::from(format!("{}.frame", name))
+}
+
+/// Publishes frames in a named shared memory mapping for other processes to read.
+/// Two slots are written in turn, so the newest complete frame can always be read while the next is written.
+pub struct SharedMemExporter {
+    mapping: Mapping,
+    event: Event,
+    capacity: usize,
+    /// Whether the last frame was too large, so a run of them is only logged once.
+    warned_too_large: bool,
+}
+
+impl SharedMemExporter {
+    /// Creates the mapping `name`, e.g. `Local\loki`, sized for frames up to `max_width` x `max_height` in any
+    /// format of at most 4 bytes per pixel. Readers are woken through the event `<name>.frame`.
+    pub fn new(name: &str, max_width: u32, max_height: u32) -> Result<Self> {
+        let capacity = (max_width as usize)
+            .checked_mul(max_height as usize)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .filter(|capacity| *capacity > 0)
+            .ok_or(SharedMemoryError::InvalidSize(max_width, max_height))?;
+        let slot_stride = SLOT_HEADER_SIZE + align(capacity);
+        let size = (CONTROL_SIZE + SLOT_COUNT * slot_stride) as u64;
+
+        let handle = unsafe {
+            CreateFileMappingW(
+                INVALID_HANDLE_VALUE,
+                None,
+                PAGE_READWRITE,
+                (size >> 32) as u32,
+                size as u32,
+                &HSTRING::from(name),
+            )?
+        };
+        // Opening an existing mapping succeeds, but it might be of another size, or still in use by another exporter.
+        if unsafe { GetLastError() } == ERROR_ALREADY_EXISTS {
+            unsafe {
+                let _ = CloseHandle(handle);
+            }
+            return Err(SharedMemoryError::AlreadyExists(name.to_string()));
+        }
+        let view = unsafe { MapViewOfFile(handle, FILE_MAP_ALL_ACCESS, 0, 0, 0) };
+        if view.Value.is_null() {
+            let err = windows_core::Error::from_win32();
+            unsafe {
+                let _ = CloseHandle(handle);
+            }
+            return Err(err.into());
+        }
+        let mapping = Mapping { handle, view };
+
+        // SAFETY: The view is fresh and zeroed, and nobody can have validated it yet as the magic is written last.
+        unsafe {
+            mapping.view.Value.cast::<ControlBlock>().write(ControlBlock {
+                magic: 0,
+                version: LAYOUT_VERSION,
+                max_width,
+                max_height,
+                slot_capacity: capacity as u64,
+                slot_stride: slot_stride as u64,
+                latest_slot: AtomicU32::new(NO_SLOT),
+                writer_alive: AtomicU32::new(1),
+            });
+            fence(Ordering::Release);
+            (*mapping.view.Value.cast::<ControlBlock>()).magic = MAGIC;
+        }
+
+        let event = Event(unsafe { CreateEventW(None, false, false, &event_name(name))? });
+        tracing::info!("Exporting frames to shared memory {:?}, {} bytes", name, size);
+        Ok(Self { mapping, event, capacity, warned_too_large: false })
+    }
+
+    /// Writes the frame into the slot not holding the newest frame, then makes it the newest.
+    /// Rows are tightly packed in the mapping, whatever the stride of the frame.
+    pub fn export(&mut self, frame: &Frame) -> Result<()> {
+        let data = frame.to_tightly_packed();
+        if data.len() > self.capacity {
+            return Err(SharedMemoryError::FrameTooLarge {
+                actual: data.len(),
+                capacity: self.capacity,
+            });
+        }
+
+        let control = self.mapping.control();
+        let slot = match control.latest_slot.load(Ordering::Relaxed) {
+            NO_SLOT => 0,
+            latest => (latest as usize + 1) % SLOT_COUNT,
+        };
+        let header = self.mapping.slot(slot);
+
+        let seq = header.seq.load(Ordering::Relaxed);
+        header.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        header.sequence.store(frame.sequence, Ordering::Relaxed);
+        header.timestamp.store(frame.timestamp, Ordering::Relaxed);
+        header.width.store(frame.size.x.max(0) as u32, Ordering::Relaxed);
+        header.height.store(frame.size.y.max(0) as u32, Ordering::Relaxed);
+        header.stride.store(frame.row_bytes() as u32, Ordering::Relaxed);
+        header.format.store(format_code(frame.format), Ordering::Relaxed);
+        header.data_len.store(data.len() as u64, Ordering::Relaxed);
+        // SAFETY: The slot holds `capacity` bytes, and readers discard whatever they copy while `seq` is odd.
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), self.mapping.slot_data(slot), data.len());
+        }
+
+        header.seq.store(seq.wrapping_add(2), Ordering::Release);
+        control.latest_slot.store(slot as u32, Ordering::Release);
+        unsafe { SetEvent(self.event.0)? };
+        Ok(())
+    }
+}
+
+impl FrameSink for SharedMemExporter {
+    fn on_frame(&mut self, frame: &Frame) {
+        match self.export(frame) {
+            Ok(()) => self.warned_too_large = false,
+            Err(err @ SharedMemoryError::FrameTooLarge { .. }) => {
+                if !std::mem::replace(&mut self.warned_too_large, true) {
+                    tracing::warn!("Not exporting frames to shared memory: {}", err);
+                }
+            }
+            Err(err) => tracing::error!("Failed to export frame to shared memory: {}", err),
+        }
+    }
+}
+
+impl Drop for SharedMemExporter {
+    fn drop(&mut self) {
+        self.mapping.control().writer_alive.store(0, Ordering::Release);
+        // Wakes a waiting reader, so it notices right away.
+        if let Err(err) = unsafe { SetEvent(self.event.0) } {
+            tracing::debug!("Failed to signal the end of the shared memory export: {}", err);
+        }
+    }
+}
+
+/// A frame copied out of the mapping.
+#[derive(Debug, Clone)]
+pub struct SharedFrame {
+    pub sequence: u64,
+    /// Raw system relative time in 100ns units, see `Frame::timestamp`.
+    pub timestamp: i64,
+    pub width: u32,
+    pub height: u32,
+    /// Rows are always tightly packed, so this is the size of a row in bytes.
+    pub stride: u32,
+    pub format: PixelFormat,
+    pub data: Vec<u8>,
+}
+
+/// Reads frames published by a `SharedMemExporter`, possibly in another process.
+pub struct SharedMemImporter {
+    mapping: Mapping,
+    event: Event,
+    last_sequence: Option<u64>,
+}
+
+impl SharedMemImporter {
+    pub fn open(name: &str) -> Result<Self> {
+        let handle = unsafe { OpenFileMappingW(FILE_MAP_READ.0, false, &HSTRING::from(name))? };
+        let view = unsafe { MapViewOfFile(handle, FILE_MAP_READ, 0, 0, 0) };
+        if view.Value.is_null() {
+            let err = windows_core::Error::from_win32();
+            unsafe {
+                let _ = CloseHandle(handle);
+            }
+            return Err(err.into());
+        }
+        let mapping = Mapping { handle, view };
+
+        let control = mapping.control();
+        if control.magic != MAGIC || control.version != LAYOUT_VERSION {
+            return Err(SharedMemoryError::IncompatibleLayout);
+        }
+        fence(Ordering::Acquire);
+
+        let event =
+            Event(unsafe { OpenEventW(SYNCHRONIZATION_SYNCHRONIZE, false, &event_name(name))? });
+        Ok(Self { mapping, event, last_sequence: None })
+    }
+
+    /// Whether the exporter is still around. Frames already exported can still be read after it is gone.
+    pub fn is_writer_alive(&self) -> bool {
+        self.mapping.control().writer_alive.load(Ordering::Acquire) != 0
+    }
+
+    /// Waits until a frame is exported or the exporter goes away. Returns false on timeout.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let millis = timeout.as_millis().min(u32::MAX as u128 - 1) as u32;
+        match unsafe { WaitForSingleObject(self.event.0, millis) } {
+            WAIT_OBJECT_0 => true,
+            WAIT_TIMEOUT => false,
+            other => {
+                tracing::warn!("Waiting for a shared memory frame failed: {:?}", other);
+                false
+            }
+        }
+    }
+
+    /// Copies the newest frame, or returns `None` if there is none yet or it was already read.
+    pub fn read_latest(&mut self) -> Result<Option<SharedFrame>> {
+        let control = self.mapping.control();
+        for _ in 0..READ_ATTEMPTS {
+            let slot = match control.latest_slot.load(Ordering::Acquire) {
+                NO_SLOT => return Ok(None),
+                slot => slot as usize % SLOT_COUNT,
+            };
+            let header = self.mapping.slot(slot);
+
+            let seq = header.seq.load(Ordering::Acquire);
+            if seq % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let sequence = header.sequence.load(Ordering::Relaxed);
+            if self.last_sequence == Some(sequence) {
+                return Ok(None);
+            }
+            let data_len = (header.data_len.load(Ordering::Relaxed) as usize)
+                .min(control.slot_capacity as usize);
+            let mut data = vec![0; data_len];
+            // SAFETY: The slot holds `slot_capacity` bytes. A copy racing with the writer is thrown away below.
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    self.mapping.slot_data(slot),
+                    data.as_mut_ptr(),
+                    data_len,
+                );
+            }
+            let timestamp = header.timestamp.load(Ordering::Relaxed);
+            let width = header.width.load(Ordering::Relaxed);
+            let height = header.height.load(Ordering::Relaxed);
+            let stride = header.stride.load(Ordering::Relaxed);
+            let format = header.format.load(Ordering::Relaxed);
+
+            fence(Ordering::Acquire);
+            if header.seq.load(Ordering::Relaxed) != seq {
+                continue;
+            }
+            let format = format_from_code(format).ok_or(SharedMemoryError::IncompatibleLayout)?;
+            self.last_sequence = Some(sequence);
+            return Ok(Some(SharedFrame {
+                sequence,
+                timestamp,
+                width,
+                height,
+                stride,
+                format,
+                data,
+            }));
+        }
+        Err(SharedMemoryError::Contended)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::AtomicUsize,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::capture_providers::shared::{FrameTiming, Vector2};
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 32;
+
+    /// A mapping name no other test uses, even when tests of several runs overlap.
+    fn unique_name() -> String {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let index = NEXT.fetch_add(1, Ordering::Relaxed);
+        format!(r"Local\loki-test-{}-{}", std::process::id(), index)
+    }
+
+    /// A BGRA8 frame whose pixels all hold the low byte of `sequence`, with rows padded by `padding` bytes.
+    fn frame(sequence: u64, size: Vector2<i32>, padding: usize) -> Frame {
+        let row_bytes = size.x as usize * 4;
+        let stride = row_bytes + padding;
+        let mut data = vec![0xEE; stride * size.y as usize];
+        data.chunks_mut(stride).for_each(|row| row[..row_bytes].fill(sequence as u8));
+        let timing = FrameTiming {
+            timestamp: sequence as i64 * 10,
+            sequence,
+            capture_instant: Instant::now(),
+        };
+        Frame::new(data.into(), PixelFormat::BGRA8, size, stride, timing, Vec::new())
+    }
+
+    #[test]
+    fn exported_frames_are_read_once_and_tightly_packed() {
+        let name = unique_name();
+        let mut exporter = SharedMemExporter::new(&name, WIDTH, HEIGHT).unwrap();
+        let mut importer = SharedMemImporter::open(&name).unwrap();
+        assert!(importer.read_latest().unwrap().is_none());
+
+        exporter.export(&frame(7, Vector2::new(10, 4), 24)).unwrap();
+        assert!(importer.wait(Duration::from_secs(1)));
+        let shared = importer.read_latest().unwrap().unwrap();
+        assert_eq!(shared.sequence, 7);
+        assert_eq!(shared.timestamp, 70);
+        assert_eq!((shared.width, shared.height, shared.stride), (10, 4, 40));
+        assert_eq!(shared.format, PixelFormat::BGRA8);
+        assert_eq!(shared.data, vec![7; 40 * 4]);
+        assert!(importer.read_latest().unwrap().is_none());
+
+        // Only the newest of several frames is read.
+        exporter.export(&frame(8, Vector2::new(10, 4), 0)).unwrap();
+        exporter.export(&frame(9, Vector2::new(10, 4), 0)).unwrap();
+        assert_eq!(importer.read_latest().unwrap().unwrap().sequence, 9);
+    }
+
+    #[test]
+    fn frames_larger_than_the_mapping_are_errors() {
+        let mut exporter = SharedMemExporter::new(&unique_name(), WIDTH, HEIGHT).unwrap();
+        let result = exporter.export(&frame(0, Vector2::new(WIDTH as i32, HEIGHT as i32 + 1), 0));
+        assert!(matches!(result, Err(SharedMemoryError::FrameTooLarge { .. })));
+        exporter.export(&frame(0, Vector2::new(WIDTH as i32, HEIGHT as i32), 0)).unwrap();
+
+        let result = SharedMemExporter::new(&unique_name(), 0, HEIGHT);
+        assert!(matches!(result, Err(SharedMemoryError::InvalidSize(0, HEIGHT))));
+    }
+
+    #[test]
+    fn names_can_only_be_exported_to_once() {
+        let name = unique_name();
+        let exporter = SharedMemExporter::new(&name, WIDTH, HEIGHT).unwrap();
+        let result = SharedMemExporter::new(&name, WIDTH, HEIGHT);
+        assert!(matches!(result, Err(SharedMemoryError::AlreadyExists(_))));
+
+        // The mapping goes away with its last handle, so the name is free again after that.
+        drop(exporter);
+        SharedMemExporter::new(&name, WIDTH, HEIGHT).unwrap();
+    }
+
+    #[test]
+    fn readers_notice_the_exporter_going_away() {
+        let name = unique_name();
+        let mut exporter = SharedMemExporter::new(&name, WIDTH, HEIGHT).unwrap();
+        let mut importer = SharedMemImporter::open(&name).unwrap();
+        exporter.export(&frame(1, Vector2::new(4, 4), 0)).unwrap();
+        assert!(importer.is_writer_alive());
+
+        drop(exporter);
+        assert!(!importer.is_writer_alive());
+        // The last frame is still there, it was never read.
+        assert_eq!(importer.read_latest().unwrap().unwrap().sequence, 1);
+    }
+
+    #[test]
+    fn concurrent_reads_see_whole_frames_in_order() {
+        const FRAMES: u64 = 2000;
+        let name = unique_name();
+        let mut exporter = SharedMemExporter::new(&name, WIDTH, HEIGHT).unwrap();
+        let mut importer = SharedMemImporter::open(&name).unwrap();
+
+        let writer = thread::spawn(move || {
+            for sequence in 1..=FRAMES {
+                // Sizes change between frames, so a torn read would mix up the header and the data too.
+                let size = Vector2::new(WIDTH as i32 - (sequence % 8) as i32, HEIGHT as i32);
+                exporter.export(&frame(sequence, size, 16)).unwrap();
+            }
+        });
+
+        let mut last_sequence = 0;
+        loop {
+            let alive = importer.is_writer_alive();
+            match importer.read_latest() {
+                Ok(Some(shared)) => {
+                    assert!(
+                        shared.sequence > last_sequence,
+                        "{} after {}",
+                        shared.sequence,
+                        last_sequence
+                    );
+                    last_sequence = shared.sequence;
+
+                    let width = WIDTH - (shared.sequence % 8) as u32;
+                    assert_eq!(
+                        (shared.width, shared.height, shared.stride),
+                        (width, HEIGHT, width * 4)
+                    );
+                    assert_eq!(shared.data.len(), (width * HEIGHT * 4) as usize);
+                    assert!(shared.data.iter().all(|byte| *byte == shared.sequence as u8));
+                }
+                // Nothing new yet, or the writer kept overwriting the slot. Either way the next frame will do.
+                Ok(None) | Err(SharedMemoryError::Contended) => {
+                    if !alive {
+                        break;
+                    }
+                    importer.wait(Duration::from_millis(10));
+                }
+                Err(err) => panic!("{}", err),
+            }
+        }
+        writer.join().unwrap();
+
+        assert_eq!(last_sequence, FRAMES);
+    }
+}
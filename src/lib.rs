@@ -0,0 +1,51 @@
+//! Screen capture on Windows through Windows.Graphics.Capture, with the frames handed out as async streams.
+//!
+//! [`capture`] is the place to start, the other modules build on it. The loki app is a user of this crate like
+//! any other, it only adds the UI.
+//!
+//! The example runs on the mock provider, which makes up frames without a GPU. On Windows, `CaptureSessionBuilder`
+//! or `WindowsCaptureProvider` stream the frames of a real monitor or window the same way.
+//!
+//! ```
+//! # #[cfg(any(feature = "mock-provider", not(target_os = "windows")))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use futures::StreamExt;
+//! use loki::capture::{CaptureFramerate, CaptureProvider, CaptureStream, PixelFormat, Vector2};
+//! use loki::capture_providers::mock::{MockCaptureItem, MockCaptureProvider};
+//!
+//! let runtime = tokio::runtime::Runtime::new()?;
+//! runtime.block_on(async {
+//!     let item = MockCaptureItem::new(Vector2::new(640, 360), PixelFormat::BGRA8);
+//!     let mut provider = MockCaptureProvider::with_item(item)?;
+//!     let stream = provider.create_stream(CaptureFramerate::FPS30)?;
+//!     let mut frames = std::pin::pin!(stream.frames_only());
+//!     provider.start_capture().await?;
+//!
+//!     let frame = frames.next().await.expect("the stream ended before the first frame");
+//!     assert_eq!(frame.size, Vector2::new(640, 360));
+//!     assert_eq!(frame.format, PixelFormat::BGRA8);
+//!
+//!     provider.stop_capture().await?;
+//!     Ok::<_, Box<dyn std::error::Error>>(())
+//! })
+//! # }
+//! # #[cfg(not(any(feature = "mock-provider", not(target_os = "windows"))))]
+//! # fn main() {}
+//! ```
+
+pub mod audio_providers;
+pub mod capture;
+pub mod capture_providers;
+#[cfg(target_os = "windows")]
+pub mod ipc;
+#[cfg(feature = "net")]
+pub mod net;
+#[cfg(all(feature = "recording", target_os = "windows"))]
+pub mod recording;
+pub mod sinks;
+pub mod utils;
+pub mod widgets;
+
+pub use capture::{CaptureFramerate, CaptureProvider, Frame, PixelFormat};
+#[cfg(target_os = "windows")]
+pub use capture::{CaptureSession, CaptureSessionBuilder, WindowsCaptureProviderBuilder};
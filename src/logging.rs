@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+
+use tracing::Level;
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry, fmt, layer::SubscriberExt, reload, util::SubscriberInitExt,
+};
+
+use crate::settings;
+
+/// Target of the per-frame logs. They are only ever written to the file, as the console can't keep up.
+const FRAMES_TARGET: &str = "loki::frames";
+const DEFAULT_DIRECTIVES: &str = "info";
+const MAX_LOG_FILES: usize = 7;
+
+/// Logs to stderr and to a daily rotated file in `%APPDATA%/loki/logs`.
+/// The file verbosity can be raised while running, the console stays as configured.
+#[derive(Debug)]
+pub struct Logging {
+    file_filter: reload::Handle<EnvFilter, Registry>,
+    directives: String,
+    /// Flushes the file on drop, so it has to live until exit.
+    _file_guard: Option<WorkerGuard>,
+}
+
+impl Logging {
+    /// Installs the global subscriber. `level` takes precedence over `LOKI_LOG`, which takes precedence over
+    /// `RUST_LOG`. Without any of them INFO is used.
+    pub fn init(level: Option<Level>) -> Self {
+        let directives = level
+            .map(|level| level.to_string())
+            .or_else(|| std::env::var("LOKI_LOG").ok())
+            .or_else(|| std::env::var("RUST_LOG").ok())
+            .unwrap_or_else(|| DEFAULT_DIRECTIVES.to_string());
+        // Invalid directives can only be reported once the subscriber is installed.
+        let (directives, invalid_directives) = match EnvFilter::try_new(&directives) {
+            Ok(_) => (directives, None),
+            Err(err) => (DEFAULT_DIRECTIVES.to_string(), Some((directives, err))),
+        };
+
+        let console_filter = EnvFilter::new(format!("{},{}=off", directives, FRAMES_TARGET));
+        let console_layer = fmt::layer().with_writer(std::io::stderr).with_filter(console_filter);
+
+        let (file_filter, file_filter_handle) = reload::Layer::new(EnvFilter::new(&directives));
+        let (file_layer, file_guard, log_dir) = match Self::file_appender() {
+            Ok((appender, log_dir)) => {
+                let (writer, guard) = tracing_appender::non_blocking(appender);
+                let layer =
+                    fmt::layer().with_ansi(false).with_writer(writer).with_filter(file_filter);
+                (Some(layer), Some(guard), Ok(log_dir))
+            }
+            Err(err) => (None, None, Err(err)),
+        };
+
+        tracing_subscriber::registry().with(file_layer).with(console_layer).init();
+
+        if let Some((directives, err)) = invalid_directives {
+            tracing::warn!("Ignoring invalid log filter {:?}: {}", directives, err);
+        }
+        match log_dir {
+            Ok(log_dir) => tracing::info!("Logging to {}", log_dir.display()),
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to set up the log file, logging to the console only: {}",
+                    err
+                )
+            }
+        }
+
+        Self { file_filter: file_filter_handle, directives, _file_guard: file_guard }
+    }
+
+    fn file_appender() -> Result<(RollingFileAppender, PathBuf), Box<dyn std::error::Error>> {
+        let log_dir = settings::data_dir()?.join("logs");
+        let appender = RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix("loki")
+            .filename_suffix("log")
+            .max_log_files(MAX_LOG_FILES)
+            .build(&log_dir)?;
+        Ok((appender, log_dir))
+    }
+
+    /// Switches the file between the configured verbosity and trace level for everything of loki.
+    pub fn set_verbose(&self, verbose: bool) {
+        let directives = if verbose {
+            format!("{},loki=trace", self.directives)
+        } else {
+            self.directives.clone()
+        };
+        match self.file_filter.reload(EnvFilter::new(&directives)) {
+            Ok(()) => tracing::info!("File log filter set to {:?}", directives),
+            Err(err) => tracing::error!("Failed to change the log filter: {}", err),
+        }
+    }
+}
@@ -1,12 +1,16 @@
 use std::sync::Arc;
 
-use tokio::sync::Mutex;
-use tracing::Level;
-use tracing_subscriber::FmtSubscriber;
+use clap::Parser;
+use loki::capture_providers::{self, CaptureProvider};
 
-mod capture_providers;
+use crate::logging::Logging;
+
+#[cfg(target_os = "windows")]
+mod capture_command;
+mod cli;
+mod logging;
+mod settings;
 mod ui;
-mod utils;
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -14,14 +18,27 @@ type Result<T> = std::result::Result<T, Error>;
 enum Error {
     #[error("Capture error: {0}")]
     CaptureError(#[from] capture_providers::CaptureError),
+    #[cfg(target_os = "windows")]
     #[error("Windows capture builder error: {0}")]
     WindowsCaptureBuilderError(#[from] capture_providers::windows::BuilderError),
+    #[cfg(target_os = "windows")]
     #[error("Windows capture error: {0}")]
     WindowsError(#[from] windows_core::Error),
+    #[cfg(target_os = "windows")]
+    #[error("Capture session error: {0}")]
+    SessionError(#[from] loki::capture::SessionError),
+    #[cfg(target_os = "windows")]
+    #[error("{0}")]
+    CaptureEnded(capture_providers::shared::EndReason),
+    #[error("Image encoding error: {0}")]
+    EncodeError(#[from] loki::utils::image_utils::EncodeError),
     #[error("UI error: {0}")]
     UiError(#[from] iced::Error),
     #[error("UI window management error: {0}")]
     UiWindowMgmtError(#[from] iced_winit::Error),
+    #[cfg(feature = "net")]
+    #[error("Stream server error: {0}")]
+    StreamServerError(#[from] loki::net::StreamServerError),
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
     #[error("Other error: {0}")]
@@ -29,22 +46,43 @@ enum Error {
 }
 
 fn main() -> Result<()> {
-    let subscriber = FmtSubscriber::builder().with_max_level(Level::TRACE).finish();
+    let cli = cli::Cli::parse();
 
-    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+    // Console logs go to stderr, so stdout stays free for raw frames.
+    let logging = Arc::new(Logging::init(cli.log_level));
+    if cli.trace_frames {
+        logging.set_verbose(true);
+    }
 
     tracing::info!("Starting up...");
+    if !capture_providers::platform::is_per_monitor_dpi_aware() {
+        tracing::warn!("Not per-monitor DPI aware, regions will be off on scaled monitors.");
+    }
+    #[cfg(target_os = "windows")]
+    tracing::info!("Capture capabilities: {}", capture_providers::windows::wgc_capabilities());
+
+    match cli.command.unwrap_or(cli::Command::Gui) {
+        cli::Command::Gui => run_gui(logging, cli.trace_frames, cli.serve),
+        #[cfg(target_os = "windows")]
+        cli::Command::Capture(args) => capture_command::run(args, cli.trace_frames, cli.serve),
+    }
+}
+
+fn run_gui(logging: Arc<Logging>, trace_frames: bool, serve: cli::ServeArgs) -> Result<()> {
+    tracing::info!("Initializing capture provider...");
+    // Same as headless sessions, except that the item is picked in the UI later.
+    #[cfg(target_os = "windows")]
+    let mut provider = loki::capture::create_provider()?;
+    // Off Windows the mock provider makes up the frames, which is enough to work on the UI.
+    #[cfg(not(target_os = "windows"))]
+    let mut provider = capture_providers::PlatformCaptureProvider::new();
+    provider.set_trace_frames(trace_frames);
+    tracing::info!("Capture provider initialized.");
 
-    tracing::info!("Initializing windows capture provider...");
-    let windows_capture = capture_providers::windows::WindowsCaptureProviderBuilder::new()
-        .with_default_device()?
-        .with_default_capture_item()?
-        .build()?;
-    let windows_capture = Arc::new(Mutex::new(windows_capture));
-    tracing::info!("Windows capture provider initialized.");
+    let settings = settings::Settings::load_or_default();
 
     tracing::info!("Initializing UI...");
-    let app = ui::app::App::new(windows_capture)?;
+    let app = ui::app::App::new(provider, settings, logging, trace_frames, serve)?;
     tracing::info!("UI initialized.");
 
     tracing::info!("Running app...");
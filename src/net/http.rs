@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Requests are only a request line and a few headers, anything longer is not a client of ours.
+const MAX_HEAD_LEN: usize = 8 * 1024;
+/// Connections that don't send a request in time are dropped, so they don't linger after shutdown.
+const HEAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The parts of an HTTP request the server routes on.
+#[derive(Debug)]
+pub(super) struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// The first header of this name, which is compared ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The value of `key` in the query string, e.g. `raw` for `format` in `/ws?format=raw`.
+    pub fn query_param(&self, key: &str) -> Option<&str> {
+        self.query.as_deref()?.split('&').find_map(|pair| match pair.split_once('=') {
+            Some((name, value)) if name == key => Some(value),
+            _ => None,
+        })
+    }
+
+    /// Whether this is a WebSocket handshake, see RFC 6455 section 4.2.1.
+    pub fn is_websocket_upgrade(&self) -> bool {
+        let upgrade =
+            self.header("Upgrade").is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+        let connection = self.header("Connection").is_some_and(|value| {
+            value.split(',').any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+        });
+        upgrade && connection && self.header("Sec-WebSocket-Key").is_some()
+    }
+
+    fn parse(head: &str) -> Option<Self> {
+        let mut lines = head.split("\r\n");
+        let mut request_line = lines.next()?.split(' ');
+        let method = request_line.next()?.to_string();
+        let target = request_line.next()?;
+        if !request_line.next()?.starts_with("HTTP/1.") {
+            return None;
+        }
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path.to_string(), Some(query.to_string())),
+            None => (target.to_string(), None),
+        };
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+        Some(Self { method, path, query, headers })
+    }
+}
+
+/// Reads the head of a request. Clients don't send anything past it before they get a response, so nothing
+/// is lost for WebSocket connections taking over the socket afterwards.
+pub(super) async fn read_request(socket: &mut TcpStream) -> std::io::Result<Request> {
+    let read = async {
+        let mut head = Vec::with_capacity(1024);
+        let mut chunk = [0u8; 1024];
+        loop {
+            let read = socket.read(&mut chunk).await?;
+            if read == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            head.extend_from_slice(&chunk[..read]);
+            if let Some(end) = head.windows(4).position(|window| window == b"\r\n\r\n") {
+                head.truncate(end);
+                break;
+            }
+            if head.len() > MAX_HEAD_LEN {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Request head too long",
+                ));
+            }
+        }
+        let head = String::from_utf8_lossy(&head);
+        Request::parse(&head).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "Malformed request")
+        })
+    };
+    tokio::time::timeout(HEAD_TIMEOUT, read)
+        .await
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))?
+}
+
+/// Writes a complete response and closes the connection.
+pub(super) async fn write_response(
+    socket: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    socket.write_all(head.as_bytes()).await?;
+    socket.write_all(body).await?;
+    socket.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_split_into_their_parts() {
+        let request = Request::parse(
+            "GET /ws?format=raw&x=1 HTTP/1.1\r\nHost: localhost\r\nupgrade:  websocket ",
+        )
+        .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/ws");
+        assert_eq!(request.query_param("format"), Some("raw"));
+        assert_eq!(request.query_param("x"), Some("1"));
+        assert_eq!(request.query_param("missing"), None);
+        assert_eq!(request.header("UPGRADE"), Some("websocket"));
+        assert_eq!(request.header("Host"), Some("localhost"));
+
+        let request = Request::parse("GET / HTTP/1.0").unwrap();
+        assert_eq!((request.path.as_str(), request.query), ("/", None));
+    }
+
+    #[test]
+    fn malformed_requests_are_rejected() {
+        assert!(Request::parse("").is_none());
+        assert!(Request::parse("GET /").is_none());
+        assert!(Request::parse("GET / SPDY/3").is_none());
+    }
+
+    #[test]
+    fn websocket_upgrades_need_every_header() {
+        let head = "GET /ws HTTP/1.1\r\nUpgrade: WebSocket\r\nConnection: keep-alive, Upgrade\r\n\
+                    Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==";
+        assert!(Request::parse(head).unwrap().is_websocket_upgrade());
+        let without_key = head.rsplit_once("\r\n").unwrap().0;
+        assert!(!Request::parse(without_key).unwrap().is_websocket_upgrade());
+        let without_upgrade = head.replace("keep-alive, Upgrade", "keep-alive");
+        assert!(!Request::parse(&without_upgrade).unwrap().is_websocket_upgrade());
+    }
+}
@@ -0,0 +1,8 @@
+//! Serving the capture to other machines.
+
+mod http;
+mod stream_server;
+
+pub use stream_server::{
+    StreamServer, StreamServerError, StreamServerOptions, WS_HEADER_LEN, WsFrameFormat,
+};
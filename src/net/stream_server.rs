@@ -0,0 +1,732 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, Stream, StreamExt};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, error::TrySendError},
+        watch,
+    },
+    task::JoinHandle,
+};
+use tokio_tungstenite::{
+    WebSocketStream,
+    tungstenite::{Message, handshake::derive_accept_key, protocol::Role},
+};
+
+use crate::{
+    capture_providers::{CaptureStream, shared::Frame},
+    net::http::{self, Request},
+    utils::image_utils::{EncodeError, encode_rgba_jpeg},
+};
+
+/// Length of the header in front of every WebSocket message. All fields are little endian:
+///
+/// | Offset | Size | Field                                      |
+/// |--------|------|--------------------------------------------|
+/// | 0      | 1    | Payload format, see `WsFrameFormat`        |
+/// | 1      | 3    | Reserved, zero                             |
+/// | 4      | 4    | Width in pixels, u32                       |
+/// | 8      | 4    | Height in pixels, u32                      |
+/// | 12     | 8    | Sequence of the frame, u64                 |
+/// | 20     | 8    | Timestamp of the frame in 100ns units, i64 |
+pub const WS_HEADER_LEN: usize = 28;
+
+const MJPEG_BOUNDARY: &str = "lokiframe";
+const INDEX_HTML: &str = "<!DOCTYPE html><html><head><title>loki</title></head>\
+    <body style=\"margin:0;background:#000\">\
+    <img src=\"/stream.mjpeg\" style=\"width:100vw;height:100vh;object-fit:contain\">\
+    </body></html>";
+
+#[derive(Debug, thiserror::Error)]
+pub enum StreamServerError {
+    #[error("Invalid JPEG quality {0}, expected 1 to 100")]
+    InvalidJpegQuality(u8),
+    #[error("Client queues need room for at least one frame")]
+    InvalidQueueLength,
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, StreamServerError>;
+
+/// What WebSocket messages carry after the header. Clients pick one with the `format` query parameter,
+/// `/ws?format=jpeg`, the default, or `/ws?format=raw`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum WsFrameFormat {
+    Jpeg = 0,
+    /// Tightly packed RGBA8 rows.
+    Rgba = 1,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StreamServerOptions {
+    /// Where to listen. All interfaces by default, so other machines on the network can connect.
+    pub address: SocketAddr,
+    /// Quality of JPEG frames, from 1 to 100.
+    pub jpeg_quality: u8,
+    /// Frames queued for a client before further frames are dropped for it. Kept short, so slow clients skip
+    /// ahead to fresh frames rather than falling behind.
+    pub client_queue_len: usize,
+}
+
+impl StreamServerOptions {
+    pub const DEFAULT_JPEG_QUALITY: u8 = 80;
+    const DEFAULT_CLIENT_QUEUE_LEN: usize = 2;
+
+    /// Listens on all interfaces on `port`, 0 for any free port.
+    pub fn new(port: u16) -> Self {
+        Self {
+            address: SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)),
+            jpeg_quality: Self::DEFAULT_JPEG_QUALITY,
+            client_queue_len: Self::DEFAULT_CLIENT_QUEUE_LEN,
+        }
+    }
+
+    pub fn with_jpeg_quality(mut self, quality: u8) -> Self {
+        self.jpeg_quality = quality;
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(1..=100).contains(&self.jpeg_quality) {
+            return Err(StreamServerError::InvalidJpegQuality(self.jpeg_quality));
+        }
+        if self.client_queue_len == 0 {
+            return Err(StreamServerError::InvalidQueueLength);
+        }
+        Ok(())
+    }
+}
+
+/// Serves the frames of a capture stream over HTTP: `/stream.mjpeg` as a multipart/x-mixed-replace stream of
+/// JPEGs that browsers show as a video, and `/ws` as binary WebSocket messages of `WS_HEADER_LEN` bytes of
+/// header followed by the frame.
+/// Frames are only encoded into the formats connected clients want. Every client has a short queue, and frames
+/// are dropped for clients that can't keep up, so a slow client never holds back the capture or other clients.
+/// The server stops by itself once the stream ends, or when it is shut down or dropped.
+#[derive(Debug)]
+pub struct StreamServer {
+    local_addr: SocketAddr,
+    shutdown: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+impl StreamServer {
+    /// Starts listening and serving the frames of `stream` on a tokio task. Has to be called on a tokio runtime.
+    pub async fn start(
+        options: StreamServerOptions,
+        stream: impl CaptureStream + 'static,
+    ) -> Result<Self> {
+        Self::start_with_frames(options, stream.frames_only()).await
+    }
+
+    /// Same as `start`, for frames from anywhere else.
+    pub async fn start_with_frames(
+        options: StreamServerOptions,
+        frames: impl Stream<Item = Frame> + Send + 'static,
+    ) -> Result<Self> {
+        options.validate()?;
+        let listener = TcpListener::bind(options.address).await?;
+        let local_addr = listener.local_addr()?;
+        let (shutdown, stopped) = watch::channel(false);
+        let task = tokio::spawn(run(listener, frames, options, stopped));
+        tracing::info!(
+            "Streaming on http://{}/stream.mjpeg and ws://{}/ws",
+            local_addr,
+            local_addr
+        );
+        Ok(Self { local_addr, shutdown, task })
+    }
+
+    /// The address the server listens on, which tells the port if it was picked by the system.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops serving without waiting for the stream to end. Connected clients are disconnected.
+    pub fn shutdown(&self) {
+        let _ = self.shutdown.send(true);
+    }
+
+    /// Waits until the server stopped.
+    pub async fn wait(&mut self) {
+        // JoinHandles must not be polled again once they completed.
+        if self.task.is_finished() {
+            return;
+        }
+        if let Err(err) = (&mut self.task).await {
+            tracing::error!("Stream server task failed: {}", err);
+        }
+    }
+}
+
+impl Drop for StreamServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+async fn run(
+    listener: TcpListener,
+    frames: impl Stream<Item = Frame>,
+    options: StreamServerOptions,
+    mut stopped: watch::Receiver<bool>,
+) {
+    let clients = Arc::new(Clients::default());
+    tokio::select! {
+        () = accept_connections(listener, clients.clone(), options.client_queue_len) => (),
+        () = pump_frames(frames, &clients, options.jpeg_quality) => {
+            tracing::info!("Capture stream ended, stopping the stream server.");
+        }
+        _ = stopped.wait_for(|stopped| *stopped) => tracing::info!("Stream server shut down."),
+    }
+    // Closing the queues lets every connection finish its response and close.
+    clients.close();
+}
+
+async fn accept_connections(listener: TcpListener, clients: Arc<Clients>, queue_len: usize) {
+    loop {
+        match listener.accept().await {
+            Ok((socket, peer)) => {
+                let clients = clients.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = handle_connection(socket, peer, &clients, queue_len).await {
+                        tracing::debug!("Stream client {} disconnected: {}", peer, err);
+                    }
+                });
+            }
+            Err(err) => tracing::warn!("Failed to accept stream client: {}", err),
+        }
+    }
+}
+
+async fn pump_frames(frames: impl Stream<Item = Frame>, clients: &Clients, jpeg_quality: u8) {
+    let mut frames = std::pin::pin!(frames);
+    while let Some(frame) = frames.next().await {
+        let wanted = clients.wanted_formats();
+        if wanted.is_empty() {
+            continue;
+        }
+        let encoded = tokio::task::spawn_blocking(move || {
+            EncodedFrame::encode(&frame, &wanted, jpeg_quality)
+        })
+        .await;
+        match encoded {
+            Ok(Ok(encoded)) => clients.broadcast(&encoded),
+            Ok(Err(err)) => tracing::warn!("Failed to encode frame for streaming: {}", err),
+            Err(err) => tracing::error!("Stream encoding task failed: {}", err),
+        }
+    }
+}
+
+async fn handle_connection(
+    mut socket: TcpStream,
+    peer: SocketAddr,
+    clients: &Clients,
+    queue_len: usize,
+) -> std::io::Result<()> {
+    // Frames are written whole, so there is nothing to gain from delaying partial packets.
+    let _ = socket.set_nodelay(true);
+    let request = http::read_request(&mut socket).await?;
+    if request.method != "GET" {
+        return http::write_response(&mut socket, "405 Method Not Allowed", "text/plain", b"")
+            .await;
+    }
+
+    let format = match request.path.as_str() {
+        "/" => {
+            return http::write_response(
+                &mut socket,
+                "200 OK",
+                "text/html; charset=utf-8",
+                INDEX_HTML.as_bytes(),
+            )
+            .await;
+        }
+        "/stream.mjpeg" => WsFrameFormat::Jpeg,
+        "/ws" if !request.is_websocket_upgrade() => {
+            return http::write_response(&mut socket, "426 Upgrade Required", "text/plain", b"")
+                .await;
+        }
+        "/ws" => match request.query_param("format") {
+            None | Some("jpeg") => WsFrameFormat::Jpeg,
+            Some("raw") => WsFrameFormat::Rgba,
+            Some(_) => {
+                return http::write_response(
+                    &mut socket,
+                    "400 Bad Request",
+                    "text/plain",
+                    b"Unknown format, expected jpeg or raw",
+                )
+                .await;
+            }
+        },
+        _ => return http::write_response(&mut socket, "404 Not Found", "text/plain", b"").await,
+    };
+
+    let Some(queue) = clients.register(peer, format, queue_len) else {
+        return http::write_response(&mut socket, "503 Service Unavailable", "text/plain", b"")
+            .await;
+    };
+    tracing::info!("Stream client {} connected to {}", peer, request.path);
+    if request.path == "/ws" {
+        serve_websocket(socket, &request, queue).await
+    } else {
+        serve_mjpeg(socket, queue).await
+    }
+}
+
+async fn serve_mjpeg(
+    mut socket: TcpStream,
+    mut queue: mpsc::Receiver<Bytes>,
+) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\n\
+         Cache-Control: no-cache, no-store\r\nPragma: no-cache\r\nConnection: close\r\n\r\n",
+        MJPEG_BOUNDARY
+    );
+    socket.write_all(head.as_bytes()).await?;
+    while let Some(message) = queue.recv().await {
+        let jpeg = message.slice(WS_HEADER_LEN..);
+        let part = format!(
+            "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            MJPEG_BOUNDARY,
+            jpeg.len()
+        );
+        socket.write_all(part.as_bytes()).await?;
+        socket.write_all(&jpeg).await?;
+        socket.write_all(b"\r\n").await?;
+    }
+    socket.write_all(format!("--{}--\r\n", MJPEG_BOUNDARY).as_bytes()).await?;
+    socket.shutdown().await
+}
+
+async fn serve_websocket(
+    mut socket: TcpStream,
+    request: &Request,
+    mut queue: mpsc::Receiver<Bytes>,
+) -> std::io::Result<()> {
+    let key = request.header("Sec-WebSocket-Key").unwrap_or_default();
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    );
+    socket.write_all(response.as_bytes()).await?;
+
+    let websocket = WebSocketStream::from_raw_socket(socket, Role::Server, None).await;
+    let (mut sender, mut incoming) = websocket.split();
+    loop {
+        tokio::select! {
+            message = queue.recv() => match message {
+                Some(message) => {
+                    sender.send(Message::Binary(message)).await.map_err(std::io::Error::other)?;
+                }
+                None => break,
+            },
+            // Pings are answered by tungstenite, everything else clients send is ignored.
+            message = incoming.next() => match message {
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Ok(_)) => (),
+                Some(Err(err)) => return Err(std::io::Error::other(err)),
+            },
+        }
+    }
+    sender.close().await.map_err(std::io::Error::other)
+}
+
+/// A frame encoded into the formats clients asked for, as complete WebSocket messages. MJPEG clients send the
+/// part after the header.
+#[derive(Debug, Default)]
+struct EncodedFrame {
+    jpeg: Option<Bytes>,
+    rgba: Option<Bytes>,
+}
+
+impl EncodedFrame {
+    fn encode(
+        frame: &Frame,
+        formats: &[WsFrameFormat],
+        jpeg_quality: u8,
+    ) -> std::result::Result<Self, EncodeError> {
+        let rgba = frame.to_tightly_packed_rgba();
+        let mut encoded = Self::default();
+        for &format in formats {
+            let message = match format {
+                WsFrameFormat::Jpeg => Self::message(
+                    frame,
+                    format,
+                    &encode_rgba_jpeg(&rgba, frame.size, jpeg_quality)?,
+                ),
+                WsFrameFormat::Rgba => Self::message(frame, format, &rgba),
+            };
+            *encoded.slot(format) = Some(message);
+        }
+        Ok(encoded)
+    }
+
+    fn message(frame: &Frame, format: WsFrameFormat, payload: &[u8]) -> Bytes {
+        let mut message = Vec::with_capacity(WS_HEADER_LEN + payload.len());
+        message.extend_from_slice(&[format as u8, 0, 0, 0]);
+        message.extend_from_slice(&(frame.size.x.max(0) as u32).to_le_bytes());
+        message.extend_from_slice(&(frame.size.y.max(0) as u32).to_le_bytes());
+        message.extend_from_slice(&frame.sequence.to_le_bytes());
+        message.extend_from_slice(&frame.timestamp.to_le_bytes());
+        message.extend_from_slice(payload);
+        message.into()
+    }
+
+    fn slot(&mut self, format: WsFrameFormat) -> &mut Option<Bytes> {
+        match format {
+            WsFrameFormat::Jpeg => &mut self.jpeg,
+            WsFrameFormat::Rgba => &mut self.rgba,
+        }
+    }
+
+    fn get(&self, format: WsFrameFormat) -> Option<&Bytes> {
+        match format {
+            WsFrameFormat::Jpeg => self.jpeg.as_ref(),
+            WsFrameFormat::Rgba => self.rgba.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Client {
+    peer: SocketAddr,
+    format: WsFrameFormat,
+    queue: mpsc::Sender<Bytes>,
+    dropped_frames: u64,
+}
+
+/// Connected clients, `None` once the server stopped.
+#[derive(Debug)]
+struct Clients(Mutex<Option<Vec<Client>>>);
+
+impl Default for Clients {
+    fn default() -> Self {
+        Self(Mutex::new(Some(Vec::new())))
+    }
+}
+
+impl Clients {
+    /// Adds a client and returns the queue its frames arrive on, `None` if the server stopped.
+    fn register(
+        &self,
+        peer: SocketAddr,
+        format: WsFrameFormat,
+        queue_len: usize,
+    ) -> Option<mpsc::Receiver<Bytes>> {
+        let mut clients = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let clients = clients.as_mut()?;
+        let (queue, receiver) = mpsc::channel(queue_len);
+        clients.push(Client { peer, format, queue, dropped_frames: 0 });
+        Some(receiver)
+    }
+
+    fn wanted_formats(&self) -> Vec<WsFrameFormat> {
+        let clients = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut formats = Vec::with_capacity(2);
+        for client in clients.iter().flatten() {
+            if !formats.contains(&client.format) {
+                formats.push(client.format);
+            }
+        }
+        formats
+    }
+
+    fn broadcast(&self, encoded: &EncodedFrame) {
+        let mut clients = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(clients) = clients.as_mut() else {
+            return;
+        };
+        clients.retain_mut(|client| {
+            // Clients that connected while the frame was encoded get the next one.
+            let Some(message) = encoded.get(client.format) else {
+                return true;
+            };
+            match client.queue.try_send(message.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    client.dropped_frames += 1;
+                    tracing::trace!("Stream client {} is behind, dropped a frame", client.peer);
+                    true
+                }
+                Err(TrySendError::Closed(_)) => {
+                    tracing::info!(
+                        "Stream client {} left, {} frames were dropped for it",
+                        client.peer,
+                        client.dropped_frames
+                    );
+                    false
+                }
+            }
+        });
+    }
+
+    fn close(&self) {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use futures::channel::mpsc::UnboundedSender;
+    use tokio::{io::AsyncReadExt, sync::oneshot};
+
+    use super::*;
+    use crate::capture_providers::shared::{FrameTiming, PixelFormat, Vector2};
+
+    const SIZE: Vector2<i32> = Vector2 { x: 16, y: 8 };
+    const BGRA: [u8; 4] = [10, 20, 30, 255];
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// A BGRA8 frame of `SIZE` in one color, with padded rows.
+    fn frame(sequence: u64) -> Frame {
+        let stride = SIZE.x as usize * 4 + 8;
+        let mut data = vec![0; stride * SIZE.y as usize];
+        for row in data.chunks_mut(stride) {
+            row[..SIZE.x as usize * 4].chunks_mut(4).for_each(|pixel| pixel.copy_from_slice(&BGRA));
+        }
+        let timing = FrameTiming { timestamp: -5, sequence, capture_instant: Instant::now() };
+        Frame::new(data.into(), PixelFormat::BGRA8, SIZE, stride, timing, Vec::new())
+    }
+
+    fn peer() -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 1234))
+    }
+
+    #[test]
+    fn messages_start_with_the_header() {
+        let mut frame = frame(0x0102_0304_0506_0708);
+        frame.size = Vector2::new(640, 360);
+        let message = EncodedFrame::message(&frame, WsFrameFormat::Rgba, b"payload");
+        assert_eq!(message.len(), WS_HEADER_LEN + 7);
+        assert_eq!(message[..4], [1, 0, 0, 0]);
+        assert_eq!(message[4..8], 640u32.to_le_bytes());
+        assert_eq!(message[8..12], 360u32.to_le_bytes());
+        assert_eq!(message[12..20], 0x0102_0304_0506_0708u64.to_le_bytes());
+        assert_eq!(message[20..28], (-5i64).to_le_bytes());
+        assert_eq!(&message[WS_HEADER_LEN..], b"payload");
+    }
+
+    #[test]
+    fn frames_are_only_encoded_into_the_wanted_formats() {
+        let encoded = EncodedFrame::encode(&frame(0), &[WsFrameFormat::Jpeg], 80).unwrap();
+        assert!(encoded.rgba.is_none());
+        let jpeg = &encoded.jpeg.unwrap()[WS_HEADER_LEN..];
+        assert!(jpeg.starts_with(&[0xFF, 0xD8]) && jpeg.ends_with(&[0xFF, 0xD9]));
+
+        let encoded = EncodedFrame::encode(&frame(0), &[WsFrameFormat::Rgba], 80).unwrap();
+        assert!(encoded.jpeg.is_none());
+        // Tightly packed and swizzled to RGBA.
+        let rgba = &encoded.rgba.unwrap()[WS_HEADER_LEN..];
+        assert_eq!(rgba.len(), (SIZE.x * SIZE.y * 4) as usize);
+        assert!(rgba.chunks(4).all(|pixel| pixel == [30, 20, 10, 255]));
+    }
+
+    #[test]
+    fn slow_clients_drop_frames_instead_of_queueing_them() {
+        let clients = Clients::default();
+        let mut slow = clients.register(peer(), WsFrameFormat::Jpeg, 2).unwrap();
+        for index in 0..5u8 {
+            let encoded = EncodedFrame { jpeg: Some(Bytes::from(vec![index])), rgba: None };
+            clients.broadcast(&encoded);
+        }
+        // The frames that fit are kept, the rest are dropped rather than waited for.
+        assert_eq!(slow.try_recv().unwrap(), Bytes::from_static(&[0]));
+        assert_eq!(slow.try_recv().unwrap(), Bytes::from_static(&[1]));
+        assert!(slow.try_recv().is_err());
+        let dropped = |clients: &Clients| {
+            let clients = clients.0.lock().unwrap();
+            clients.as_ref().unwrap().iter().map(|client| client.dropped_frames).collect::<Vec<_>>()
+        };
+        assert_eq!(dropped(&clients), [3]);
+
+        // Clients of other formats keep their place until a frame of their format comes along.
+        let _raw = clients.register(peer(), WsFrameFormat::Rgba, 2).unwrap();
+        assert_eq!(clients.wanted_formats(), [WsFrameFormat::Jpeg, WsFrameFormat::Rgba]);
+        drop(slow);
+        clients.broadcast(&EncodedFrame { jpeg: Some(Bytes::new()), rgba: None });
+        assert_eq!(clients.wanted_formats(), [WsFrameFormat::Rgba]);
+
+        clients.close();
+        assert!(clients.register(peer(), WsFrameFormat::Jpeg, 2).is_none());
+        assert!(clients.wanted_formats().is_empty());
+    }
+
+    #[test]
+    fn invalid_options_are_errors() {
+        for quality in [0, 101] {
+            let options = StreamServerOptions::new(0).with_jpeg_quality(quality);
+            assert!(matches!(options.validate(), Err(StreamServerError::InvalidJpegQuality(_))));
+        }
+        let options = StreamServerOptions { client_queue_len: 0, ..StreamServerOptions::new(0) };
+        assert!(matches!(options.validate(), Err(StreamServerError::InvalidQueueLength)));
+    }
+
+    /// A server on a free port of the loopback interface, with frames sent until `stop` fires.
+    async fn start_server() -> (StreamServer, oneshot::Sender<()>) {
+        let (frames, receiver) = futures::channel::mpsc::unbounded();
+        let options = StreamServerOptions {
+            address: SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            ..StreamServerOptions::new(0)
+        };
+        let server = StreamServer::start_with_frames(options, receiver).await.unwrap();
+        let (stop, stopped) = oneshot::channel();
+        tokio::spawn(send_frames(frames, stopped));
+        (server, stop)
+    }
+
+    /// Frames are only encoded while clients are connected, so they keep coming until the test is done.
+    async fn send_frames(frames: UnboundedSender<Frame>, mut stopped: oneshot::Receiver<()>) {
+        let mut interval = tokio::time::interval(Duration::from_millis(5));
+        for sequence in 0.. {
+            tokio::select! {
+                _ = &mut stopped => break,
+                _ = interval.tick() => {
+                    if frames.unbounded_send(frame(sequence)).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads from a socket up to delimiters or lengths, keeping what was read past them.
+    struct Reader {
+        socket: TcpStream,
+        buffer: Vec<u8>,
+    }
+
+    impl Reader {
+        async fn fill(&mut self) -> bool {
+            let mut chunk = [0; 4096];
+            let read = self.socket.read(&mut chunk).await.unwrap();
+            self.buffer.extend_from_slice(&chunk[..read]);
+            read > 0
+        }
+
+        /// Everything up to and including `delimiter`.
+        async fn until(&mut self, delimiter: &[u8]) -> String {
+            loop {
+                let end =
+                    self.buffer.windows(delimiter.len()).position(|window| window == delimiter);
+                if let Some(end) = end {
+                    let part: Vec<_> = self.buffer.drain(..end + delimiter.len()).collect();
+                    return String::from_utf8(part).unwrap();
+                }
+                assert!(self.fill().await, "the connection closed before {:?}", delimiter);
+            }
+        }
+
+        async fn take(&mut self, len: usize) -> Vec<u8> {
+            while self.buffer.len() < len {
+                assert!(self.fill().await, "the connection closed early");
+            }
+            self.buffer.drain(..len).collect()
+        }
+
+        async fn rest(mut self) -> Vec<u8> {
+            while self.fill().await {}
+            self.buffer
+        }
+    }
+
+    async fn get(server: &StreamServer, path: &str) -> Reader {
+        let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        socket.write_all(request.as_bytes()).await.unwrap();
+        Reader { socket, buffer: Vec::new() }
+    }
+
+    #[tokio::test]
+    async fn mjpeg_clients_receive_jpeg_parts_until_the_stream_ends() {
+        let test = async {
+            let (mut server, stop) = start_server().await;
+            let mut client = get(&server, "/stream.mjpeg").await;
+            let head = client.until(b"\r\n\r\n").await;
+            assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+            assert!(
+                head.contains("Content-Type: multipart/x-mixed-replace; boundary=lokiframe\r\n")
+            );
+
+            for _ in 0..3 {
+                let part = client.until(b"\r\n\r\n").await;
+                assert!(
+                    part.starts_with("--lokiframe\r\nContent-Type: image/jpeg\r\n"),
+                    "{}",
+                    part
+                );
+                let len = part
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .and_then(|len| len.parse().ok())
+                    .unwrap();
+                let jpeg = client.take(len).await;
+                assert!(jpeg.starts_with(&[0xFF, 0xD8]) && jpeg.ends_with(&[0xFF, 0xD9]));
+                assert_eq!(client.take(2).await, b"\r\n");
+            }
+
+            // The end of the stream stops the server, which ends the response with the closing boundary.
+            stop.send(()).unwrap();
+            assert!(client.rest().await.ends_with(b"--lokiframe--\r\n"));
+            server.wait().await;
+        };
+        tokio::time::timeout(TIMEOUT, test).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn websocket_clients_receive_raw_frames() {
+        let test = async {
+            let (mut server, _stop) = start_server().await;
+            let socket = TcpStream::connect(server.local_addr()).await.unwrap();
+            let url = format!("ws://{}/ws?format=raw", server.local_addr());
+            let (mut websocket, _) = tokio_tungstenite::client_async(url, socket).await.unwrap();
+
+            let Some(Ok(Message::Binary(message))) = websocket.next().await else {
+                panic!("expected a binary message");
+            };
+            assert_eq!(message[0], WsFrameFormat::Rgba as u8);
+            assert_eq!(message[4..8], (SIZE.x as u32).to_le_bytes());
+            assert_eq!(message[8..12], (SIZE.y as u32).to_le_bytes());
+            assert_eq!(message.len(), WS_HEADER_LEN + (SIZE.x * SIZE.y * 4) as usize);
+
+            // Shutting down closes the connection.
+            server.shutdown();
+            server.wait().await;
+            while let Some(message) = websocket.next().await {
+                match message {
+                    Ok(Message::Binary(_)) => (),
+                    Ok(Message::Close(_)) | Err(_) => break,
+                    Ok(other) => panic!("unexpected message {:?}", other),
+                }
+            }
+        };
+        tokio::time::timeout(TIMEOUT, test).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn other_requests_get_error_statuses() {
+        let test = async {
+            let (server, _stop) = start_server().await;
+            for (path, status) in
+                [("/missing", "404 Not Found"), ("/ws", "426 Upgrade Required"), ("/", "200 OK")]
+            {
+                let mut client = get(&server, path).await;
+                let head = client.until(b"\r\n").await;
+                assert_eq!(head, format!("HTTP/1.1 {}\r\n", status));
+            }
+        };
+        tokio::time::timeout(TIMEOUT, test).await.unwrap();
+    }
+}
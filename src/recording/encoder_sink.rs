@@ -0,0 +1,219 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+    },
+    thread::JoinHandle,
+};
+
+use crate::{
+    capture_providers::{
+        shared::{CaptureFramerate, GpuFrame},
+        windows::AdapterSelection,
+    },
+    recording::{
+        EncoderInfo, GpuEncoderSettings, RecordingError, Result, VideoCodec,
+        gpu_encoder::GpuEncoder,
+    },
+};
+
+pub struct MfEncoderSinkBuilder {
+    path: PathBuf,
+    settings: GpuEncoderSettings,
+}
+
+impl MfEncoderSinkBuilder {
+    /// Frames from one keyframe to the next when not set, two seconds at 30 FPS.
+    pub const DEFAULT_GOP_LENGTH: u32 = 60;
+
+    /// Records to the file at the size, with a bitrate suitable for screen content. Odd sizes are rounded down to
+    /// even ones.
+    pub fn new(path: impl Into<PathBuf>, width: u32, height: u32) -> Self {
+        let (width, height) = (width & !1, height & !1);
+        let framerate = CaptureFramerate::FPS30;
+        Self {
+            path: path.into(),
+            settings: GpuEncoderSettings {
+                width,
+                height,
+                framerate,
+                bitrate: default_bitrate(width, height, framerate),
+                gop_length: Self::DEFAULT_GOP_LENGTH,
+                codec: VideoCodec::default(),
+                require_hardware: false,
+                adapter: AdapterSelection::Default,
+            },
+        }
+    }
+
+    /// Also adjusts the bitrate, unless it was set explicitly before.
+    pub fn with_framerate(mut self, framerate: CaptureFramerate) -> Self {
+        let GpuEncoderSettings { width, height, bitrate, .. } = self.settings;
+        if bitrate == default_bitrate(width, height, self.settings.framerate) {
+            self.settings.bitrate = default_bitrate(width, height, framerate);
+        }
+        self.settings.framerate = framerate;
+        self
+    }
+
+    /// Average bitrate in bits per second.
+    pub fn with_bitrate(mut self, bitrate: u32) -> Self {
+        self.settings.bitrate = bitrate;
+        self
+    }
+
+    pub fn with_gop_length(mut self, frames: u32) -> Self {
+        self.settings.gop_length = frames;
+        self
+    }
+
+    pub fn with_codec(mut self, codec: VideoCodec) -> Self {
+        self.settings.codec = codec;
+        self
+    }
+
+    /// Fails to start with `NoHardwareEncoder` instead of falling back to the software H.264 encoder.
+    pub fn with_hardware_required(mut self, required: bool) -> Self {
+        self.settings.require_hardware = required;
+        self
+    }
+
+    /// The adapter the frames are captured on, see `WindowsCaptureProvider::adapter_info`. Defaults to the
+    /// default adapter, like the provider.
+    pub fn with_adapter(mut self, adapter: AdapterSelection) -> Self {
+        self.settings.adapter = adapter;
+        self
+    }
+
+    /// Starts the encoder. Returns once it is set up, so setup errors, like a missing encoder, are reported here.
+    pub fn start(self) -> Result<MfEncoderSink> {
+        MfEncoderSink::start(self.path, self.settings)
+    }
+}
+
+/// Around 0.1 bits per pixel like `RecorderSettings::new`, which keeps text readable.
+fn default_bitrate(width: u32, height: u32, framerate: CaptureFramerate) -> u32 {
+    let bitrate = width as u64 * height as u64 * framerate.fps() as u64 / 10;
+    bitrate.clamp(1_000_000, 50_000_000) as u32
+}
+
+/// Records frames of a `WindowsTextureStream` to an MP4 file with a hardware encoder, without ever reading them
+/// back into CPU memory. Encodes on a separate thread like `RecordingHandle`, see `MfEncoderSinkBuilder`.
+#[derive(Debug)]
+pub struct MfEncoderSink {
+    path: PathBuf,
+    info: EncoderInfo,
+    tx: Option<SyncSender<GpuFrame>>,
+    thread: Option<JoinHandle<Result<u64>>>,
+    dropped_frames: Arc<AtomicU64>,
+}
+
+impl MfEncoderSink {
+    /// Frames queued for the encoder before new ones are dropped. The capture only has a few shared textures, so
+    /// queueing more would only hold frames it already overwrote.
+    const QUEUE_CAPACITY: usize = 2;
+
+    fn start(path: PathBuf, settings: GpuEncoderSettings) -> Result<Self> {
+        let (tx, rx) = mpsc::sync_channel(Self::QUEUE_CAPACITY);
+        let (ready_tx, ready_rx) = mpsc::sync_channel(1);
+
+        let thread_path = path.clone();
+        let thread = std::thread::Builder::new().name("loki-encoder".into()).spawn(move || {
+            // The Media Foundation objects stay on this thread, as they are not free-threaded.
+            let encoder = match GpuEncoder::new(&thread_path, settings) {
+                Ok(encoder) => {
+                    ready_tx.send(Ok(encoder.info())).ok();
+                    encoder
+                }
+                Err(err) => {
+                    ready_tx.send(Err(err)).ok();
+                    return Ok(0);
+                }
+            };
+            Self::run(encoder, rx)
+        })?;
+
+        match ready_rx.recv() {
+            Ok(Ok(info)) => Ok(Self {
+                path,
+                info,
+                tx: Some(tx),
+                thread: Some(thread),
+                dropped_frames: Arc::new(AtomicU64::new(0)),
+            }),
+            Ok(Err(err)) => Err(err),
+            Err(_) => Err(RecordingError::ThreadStopped),
+        }
+    }
+
+    fn run(mut encoder: GpuEncoder, rx: Receiver<GpuFrame>) -> Result<u64> {
+        for frame in rx {
+            match encoder.push_frame(&frame) {
+                Ok(()) => (),
+                Err(err) if err.is_frame_error() => {
+                    tracing::warn!("Skipping frame at {} in recording: {}", frame.timestamp, err);
+                }
+                Err(err) => {
+                    tracing::error!("GPU recording failed: {}", err);
+                    return Err(err);
+                }
+            }
+        }
+        // The sender is gone, so the recording was stopped.
+        let frames_written = encoder.frames_written();
+        encoder.finalize()?;
+        Ok(frames_written)
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// The encoder in use, which is the software H.264 one if no hardware encoder was found.
+    pub fn encoder_info(&self) -> EncoderInfo {
+        self.info
+    }
+
+    /// Queues a frame for encoding. Frames are dropped if the encoder can't keep up.
+    /// Fails if the encoder thread stopped, in which case `finish` returns the reason.
+    pub fn push_frame(&self, frame: GpuFrame) -> Result<()> {
+        let tx = self.tx.as_ref().ok_or(RecordingError::ThreadStopped)?;
+        match tx.try_send(frame) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("Encoder queue full, dropping frame.");
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => Err(RecordingError::ThreadStopped),
+        }
+    }
+
+    /// Number of frames dropped because the encoder couldn't keep up.
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    /// Stops recording and waits for the file to be finalized. Returns the number of frames in the file.
+    pub fn finish(mut self) -> Result<u64> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<u64> {
+        self.tx.take();
+        match self.thread.take() {
+            Some(thread) => thread.join().map_err(|_| RecordingError::ThreadStopped)?,
+            None => Ok(0),
+        }
+    }
+}
+
+impl Drop for MfEncoderSink {
+    fn drop(&mut self) {
+        if let Err(err) = self.stop() {
+            tracing::error!("Failed to finish GPU recording: {}", err);
+        }
+    }
+}
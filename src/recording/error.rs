@@ -0,0 +1,45 @@
+use crate::{
+    capture_providers::{
+        shared::{PixelFormat, Vector2},
+        windows::error::WindowsCaptureError,
+    },
+    recording::VideoCodec,
+};
+
+pub type Result<T> = std::result::Result<T, RecordingError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecordingError {
+    #[error("Invalid recorder settings: {0}")]
+    InvalidSettings(String),
+    #[error("Frame is {actual:?}, but the recording is {expected:?}")]
+    FrameSizeMismatch { expected: Vector2<i32>, actual: Vector2<i32> },
+    #[error("Frame timestamp {timestamp} is before the start of the recording")]
+    TimestampBeforeStart { timestamp: i64 },
+    #[error("Recording thread stopped unexpectedly")]
+    ThreadStopped,
+    #[error("Unsupported pixel format for recording: {0:?}")]
+    UnsupportedFormat(PixelFormat),
+    #[error("No hardware {0} encoder found")]
+    NoHardwareEncoder(VideoCodec),
+    #[error("No {0} encoder found, and no software H.264 encoder to fall back to")]
+    NoEncoder(VideoCodec),
+    #[error("Failed to create the encoder device: {0}")]
+    DeviceError(#[from] WindowsCaptureError),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Media Foundation error: {0}")]
+    MediaFoundationError(#[from] windows_core::Error),
+}
+
+impl RecordingError {
+    /// Whether the recording can go on after this error, only losing the frame that caused it.
+    pub fn is_frame_error(&self) -> bool {
+        matches!(
+            self,
+            Self::FrameSizeMismatch { .. }
+                | Self::TimestampBeforeStart { .. }
+                | Self::UnsupportedFormat(_)
+        )
+    }
+}
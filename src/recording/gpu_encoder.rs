@@ -0,0 +1,421 @@
+use std::{fmt::Display, path::Path};
+
+use windows::Win32::{
+    Graphics::{
+        Direct3D11::{
+            D3D11_BIND_RENDER_TARGET, D3D11_BIND_SHADER_RESOURCE, D3D11_BOX, D3D11_TEXTURE2D_DESC,
+            D3D11_USAGE_DEFAULT, ID3D11Device, ID3D11Device1, ID3D11DeviceContext,
+            ID3D11Multithread, ID3D11Texture2D,
+        },
+        Dxgi::{
+            Common::{DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_SAMPLE_DESC},
+            IDXGIKeyedMutex,
+        },
+    },
+    Media::MediaFoundation::{
+        IMF2DBuffer, IMFActivate, IMFAttributes, IMFDXGIDeviceManager, IMFMediaType, IMFSinkWriter,
+        MF_MT_AVG_BITRATE, MF_MT_FRAME_RATE, MF_MT_FRAME_SIZE, MF_MT_INTERLACE_MODE,
+        MF_MT_MAJOR_TYPE, MF_MT_MAX_KEYFRAME_SPACING, MF_MT_PIXEL_ASPECT_RATIO, MF_MT_SUBTYPE,
+        MF_READWRITE_ENABLE_HARDWARE_TRANSFORMS, MF_SINK_WRITER_D3D_MANAGER, MF_VERSION,
+        MFCreateAttributes, MFCreateDXGIDeviceManager, MFCreateDXGISurfaceBuffer,
+        MFCreateMediaType, MFCreateSample, MFCreateSinkWriterFromURL, MFMediaType_Video,
+        MFSTARTUP_FULL, MFShutdown, MFStartup, MFT_CATEGORY_VIDEO_ENCODER, MFT_ENUM_FLAG,
+        MFT_ENUM_FLAG_HARDWARE, MFT_ENUM_FLAG_SORTANDFILTER, MFT_ENUM_FLAG_SYNCMFT,
+        MFT_REGISTER_TYPE_INFO, MFTEnumEx, MFVideoFormat_ARGB32, MFVideoFormat_H264,
+        MFVideoFormat_HEVC, MFVideoInterlace_Progressive,
+    },
+    System::Com::CoTaskMemFree,
+};
+use windows_core::{GUID, HRESULT, HSTRING, Interface};
+
+use crate::{
+    capture::initialize_com,
+    capture_providers::{
+        shared::{CaptureFramerate, GpuFrame, PixelFormat, Vector2},
+        windows::AdapterSelection,
+    },
+    recording::{RecordingError, Result},
+};
+
+/// `AcquireSync` reports a timeout as a success code, so it has to be checked for explicitly.
+const WAIT_TIMEOUT: HRESULT = HRESULT(0x102);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VideoCodec {
+    #[default]
+    H264,
+    /// H.265, about half the bitrate of H.264 for the same quality. Software encoding needs the HEVC extension
+    /// from the Microsoft Store, so without a hardware encoder this usually falls back to H.264.
+    Hevc,
+}
+
+impl VideoCodec {
+    fn subtype(self) -> GUID {
+        match self {
+            Self::H264 => MFVideoFormat_H264,
+            Self::Hevc => MFVideoFormat_HEVC,
+        }
+    }
+}
+
+impl Display for VideoCodec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::H264 => f.write_str("H.264"),
+            Self::Hevc => f.write_str("HEVC"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpuEncoderSettings {
+    pub width: u32,
+    pub height: u32,
+    /// Nominal framerate of the file. Frames keep their own timestamps, so pacing may vary around it.
+    pub framerate: CaptureFramerate,
+    /// Average bitrate in bits per second.
+    pub bitrate: u32,
+    /// Frames from one keyframe to the next. Shorter makes seeking faster and files larger.
+    pub gop_length: u32,
+    pub codec: VideoCodec,
+    /// Fail with `NoHardwareEncoder` rather than falling back to the software H.264 encoder.
+    pub require_hardware: bool,
+    /// Must be the adapter the frames are captured on, as shared textures can't cross adapters.
+    pub adapter: AdapterSelection,
+}
+
+impl GpuEncoderSettings {
+    fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(RecordingError::InvalidSettings("Size must not be zero".into()));
+        }
+        // Both codecs encode 4:2:0 chroma, which covers 2x2 blocks.
+        if !self.width.is_multiple_of(2) || !self.height.is_multiple_of(2) {
+            return Err(RecordingError::InvalidSettings(format!(
+                "Size must be even, got {} x {}",
+                self.width, self.height
+            )));
+        }
+        if self.bitrate == 0 {
+            return Err(RecordingError::InvalidSettings("Bitrate must not be zero".into()));
+        }
+        if self.gop_length == 0 {
+            return Err(RecordingError::InvalidSettings("GOP length must not be zero".into()));
+        }
+        Ok(())
+    }
+}
+
+/// The encoder a recording ended up with, which may differ from the one asked for after a fallback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderInfo {
+    pub codec: VideoCodec,
+    pub hardware: bool,
+}
+
+impl Display for EncoderInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = if self.hardware { "hardware" } else { "software" };
+        write!(f, "{} {} encoder", kind, self.codec)
+    }
+}
+
+/// Encodes frames that stay on the GPU and writes them to an MP4 file with the Media Foundation sink writer.
+///
+/// Frames are copied from their shared texture into one owned by the encoder and handed over as DXGI surfaces,
+/// so the conversion to NV12 and the encoding never leave the GPU when a hardware encoder is used.
+pub(super) struct GpuEncoder {
+    device: ID3D11Device1,
+    context: ID3D11DeviceContext,
+    writer: IMFSinkWriter,
+    stream_index: u32,
+    settings: GpuEncoderSettings,
+    info: EncoderInfo,
+    /// Timestamp of the first frame, which becomes sample time zero.
+    first_timestamp: Option<i64>,
+    last_sample_time: Option<i64>,
+    frames_written: u64,
+    finalized: bool,
+}
+
+impl GpuEncoder {
+    /// How long to wait for the capture to let go of a shared texture before skipping its frame.
+    const ACQUIRE_TIMEOUT_MS: u32 = 100;
+
+    pub fn new(path: &Path, settings: GpuEncoderSettings) -> Result<Self> {
+        tracing::info!("Starting GPU recording to {}: {:?}", path.display(), settings);
+        settings.validate()?;
+
+        initialize_com()?;
+        let device = settings.adapter.create_device()?;
+        // Media Foundation uses the device from its own threads.
+        unsafe {
+            let _ = device.cast::<ID3D11Multithread>()?.SetMultithreadProtected(true);
+        }
+        let context = unsafe { device.GetImmediateContext()? };
+        let device1: ID3D11Device1 = device.cast()?;
+
+        unsafe { MFStartup(MF_VERSION, MFSTARTUP_FULL)? };
+        // Once the encoder exists, Drop pairs MFStartup with MFShutdown.
+        let created = Self::pick_encoder(&settings)
+            .and_then(|info| Ok((info, Self::create_writer(path, &device, &settings, info)?)));
+        match created {
+            Ok((info, (writer, stream_index))) => {
+                tracing::info!("Recording with the {}.", info);
+                Ok(Self {
+                    device: device1,
+                    context,
+                    writer,
+                    stream_index,
+                    settings,
+                    info,
+                    first_timestamp: None,
+                    last_sample_time: None,
+                    frames_written: 0,
+                    finalized: false,
+                })
+            }
+            Err(err) => {
+                if let Err(err) = unsafe { MFShutdown() } {
+                    tracing::warn!("Failed to shut down Media Foundation: {}", err);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn pick_encoder(settings: &GpuEncoderSettings) -> Result<EncoderInfo> {
+        let codec = settings.codec;
+        if has_encoder(codec, MFT_ENUM_FLAG_HARDWARE)? {
+            return Ok(EncoderInfo { codec, hardware: true });
+        }
+        if settings.require_hardware {
+            return Err(RecordingError::NoHardwareEncoder(codec));
+        }
+        if has_encoder(codec, MFT_ENUM_FLAG_SYNCMFT)? {
+            tracing::warn!("No hardware {} encoder, encoding in software.", codec);
+            return Ok(EncoderInfo { codec, hardware: false });
+        }
+        if codec != VideoCodec::H264 && has_encoder(VideoCodec::H264, MFT_ENUM_FLAG_SYNCMFT)? {
+            tracing::warn!("No {} encoder at all, falling back to software H.264.", codec);
+            return Ok(EncoderInfo { codec: VideoCodec::H264, hardware: false });
+        }
+        Err(RecordingError::NoEncoder(codec))
+    }
+
+    fn create_writer(
+        path: &Path,
+        device: &ID3D11Device,
+        settings: &GpuEncoderSettings,
+        info: EncoderInfo,
+    ) -> Result<(IMFSinkWriter, u32)> {
+        let mut reset_token = 0;
+        let mut manager: Option<IMFDXGIDeviceManager> = None;
+        unsafe { MFCreateDXGIDeviceManager(&mut reset_token, &mut manager)? };
+        let manager = manager.expect("Failed to create DXGI device manager!");
+        unsafe { manager.ResetDevice(device, reset_token)? };
+
+        let mut attributes: Option<IMFAttributes> = None;
+        unsafe { MFCreateAttributes(&mut attributes, 2)? };
+        let attributes = attributes.expect("Failed to create sink writer attributes!");
+        unsafe {
+            attributes.SetUINT32(&MF_READWRITE_ENABLE_HARDWARE_TRANSFORMS, info.hardware as u32)?;
+            attributes.SetUnknown(&MF_SINK_WRITER_D3D_MANAGER, &manager)?;
+        }
+
+        let url = HSTRING::from(path.as_os_str());
+        let writer = unsafe { MFCreateSinkWriterFromURL(&url, None, &attributes)? };
+
+        let output_type = Self::create_video_type(settings, &info.codec.subtype())?;
+        unsafe {
+            output_type.SetUINT32(&MF_MT_AVG_BITRATE, settings.bitrate)?;
+            output_type.SetUINT32(&MF_MT_MAX_KEYFRAME_SPACING, settings.gop_length)?;
+        }
+        let stream_index = unsafe { writer.AddStream(&output_type)? };
+
+        // BGRA, which the sink writer converts to NV12 with the video processor on the GPU.
+        let input_type = Self::create_video_type(settings, &MFVideoFormat_ARGB32)?;
+        unsafe {
+            writer.SetInputMediaType(stream_index, &input_type, None)?;
+            writer.BeginWriting()?;
+        }
+
+        Ok((writer, stream_index))
+    }
+
+    fn create_video_type(settings: &GpuEncoderSettings, subtype: &GUID) -> Result<IMFMediaType> {
+        let (frames, seconds) = settings.framerate.ratio();
+        let media_type = unsafe { MFCreateMediaType()? };
+        unsafe {
+            media_type.SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Video)?;
+            media_type.SetGUID(&MF_MT_SUBTYPE, subtype)?;
+            media_type.SetUINT32(&MF_MT_INTERLACE_MODE, MFVideoInterlace_Progressive.0 as u32)?;
+            media_type
+                .SetUINT64(&MF_MT_FRAME_SIZE, pack_u32_pair(settings.width, settings.height))?;
+            media_type
+                .SetUINT64(&MF_MT_FRAME_RATE, pack_u32_pair(frames as u32, seconds as u32))?;
+            media_type.SetUINT64(&MF_MT_PIXEL_ASPECT_RATIO, pack_u32_pair(1, 1))?;
+        }
+        Ok(media_type)
+    }
+
+    pub fn info(&self) -> EncoderInfo {
+        self.info
+    }
+
+    pub fn frames_written(&self) -> u64 {
+        self.frames_written
+    }
+
+    /// Encodes a frame, with its sample time the distance of its timestamp to the first frame like `Recorder`.
+    /// Frames one row or column larger than the recording are cropped.
+    pub fn push_frame(&mut self, frame: &GpuFrame) -> Result<()> {
+        if frame.format != PixelFormat::BGRA8 {
+            return Err(RecordingError::UnsupportedFormat(frame.format));
+        }
+        let expected = Vector2::new(self.settings.width as i32, self.settings.height as i32);
+        let cropped = Vector2::new(frame.size.x & !1, frame.size.y & !1);
+        if cropped != expected {
+            return Err(RecordingError::FrameSizeMismatch { expected, actual: frame.size });
+        }
+
+        let first_timestamp = *self.first_timestamp.get_or_insert(frame.timestamp);
+        if frame.timestamp < first_timestamp {
+            return Err(RecordingError::TimestampBeforeStart { timestamp: frame.timestamp });
+        }
+        // Samples must have increasing times, which frames with equal timestamps would break.
+        let sample_time = match self.last_sample_time {
+            Some(last) => (frame.timestamp - first_timestamp).max(last + 1),
+            None => 0,
+        };
+
+        let Some(texture) = self.copy_frame(frame)? else {
+            tracing::debug!("Shared texture still held by the capture, skipping frame.");
+            return Ok(());
+        };
+        self.write_sample(&texture, sample_time)?;
+        self.last_sample_time = Some(sample_time);
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    /// Copies the frame out of its shared texture, so the capture can reuse it right away. `None` if the capture
+    /// didn't release it in time.
+    ///
+    /// Every frame gets a new texture, as the sink writer holds on to samples until the encoder is done with them.
+    fn copy_frame(&self, frame: &GpuFrame) -> Result<Option<ID3D11Texture2D>> {
+        let shared: ID3D11Texture2D =
+            unsafe { self.device.OpenSharedResource1(frame.shared_handle())? };
+        let keyed_mutex: IDXGIKeyedMutex = shared.cast()?;
+        // Called through the vtable, as the generated wrapper hides WAIT_TIMEOUT behind Ok.
+        let result = unsafe {
+            (Interface::vtable(&keyed_mutex).AcquireSync)(
+                Interface::as_raw(&keyed_mutex),
+                GpuFrame::CONSUMER_KEY,
+                Self::ACQUIRE_TIMEOUT_MS,
+            )
+        };
+        if result == WAIT_TIMEOUT {
+            return Ok(None);
+        }
+        result.ok()?;
+
+        let desc = D3D11_TEXTURE2D_DESC {
+            Width: self.settings.width,
+            Height: self.settings.height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: DXGI_FORMAT_B8G8R8A8_UNORM,
+            SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: (D3D11_BIND_SHADER_RESOURCE.0 | D3D11_BIND_RENDER_TARGET.0) as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: 0,
+        };
+        let region = D3D11_BOX {
+            left: 0,
+            top: 0,
+            front: 0,
+            right: self.settings.width,
+            bottom: self.settings.height,
+            back: 1,
+        };
+        let copied = unsafe {
+            let mut texture = None;
+            let created = self.device.CreateTexture2D(&desc, None, Some(&mut texture));
+            if created.is_ok() {
+                let texture = texture.as_ref().expect("Failed to create encoder texture!");
+                self.context.CopySubresourceRegion(texture, 0, 0, 0, 0, &shared, 0, Some(&region));
+            }
+            // Released even if the copy failed, or the capture would wait for this texture forever.
+            keyed_mutex.ReleaseSync(GpuFrame::PRODUCER_KEY)?;
+            created.map(|()| texture)
+        };
+        Ok(copied?)
+    }
+
+    fn write_sample(&self, texture: &ID3D11Texture2D, sample_time: i64) -> Result<()> {
+        let duration = self.settings.framerate.to_frametime_ticks();
+        unsafe {
+            let buffer = MFCreateDXGISurfaceBuffer(&ID3D11Texture2D::IID, texture, 0, false)?;
+            let length = buffer.cast::<IMF2DBuffer>()?.GetContiguousLength()?;
+            buffer.SetCurrentLength(length)?;
+
+            let sample = MFCreateSample()?;
+            sample.AddBuffer(&buffer)?;
+            sample.SetSampleTime(sample_time)?;
+            sample.SetSampleDuration(duration)?;
+            self.writer.WriteSample(self.stream_index, &sample)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the encoder and finishes the file. Without this, the file is not playable.
+    pub fn finalize(mut self) -> Result<()> {
+        tracing::info!("Finalizing GPU recording after {} frames.", self.frames_written);
+        self.finalized = true;
+        unsafe { self.writer.Finalize()? };
+        Ok(())
+    }
+}
+
+impl Drop for GpuEncoder {
+    fn drop(&mut self) {
+        if !self.finalized {
+            tracing::warn!("Encoder dropped without being finalized, the file will be incomplete.");
+        }
+        if let Err(err) = unsafe { MFShutdown() } {
+            tracing::warn!("Failed to shut down Media Foundation: {}", err);
+        }
+    }
+}
+
+/// Whether an encoder MFT for the codec is registered, among hardware ones or synchronous software ones.
+fn has_encoder(codec: VideoCodec, kind: MFT_ENUM_FLAG) -> Result<bool> {
+    let output =
+        MFT_REGISTER_TYPE_INFO { guidMajorType: MFMediaType_Video, guidSubtype: codec.subtype() };
+    let mut activates: *mut Option<IMFActivate> = std::ptr::null_mut();
+    let mut count = 0;
+    unsafe {
+        MFTEnumEx(
+            MFT_CATEGORY_VIDEO_ENCODER,
+            kind | MFT_ENUM_FLAG_SORTANDFILTER,
+            None,
+            Some(&output as *const _),
+            &mut activates,
+            &mut count,
+        )?;
+        if !activates.is_null() {
+            // The array owns a reference to every activation object, which dropping them releases.
+            for index in 0..count as usize {
+                drop(activates.add(index).read());
+            }
+            CoTaskMemFree(Some(activates as *const _));
+        }
+    }
+    Ok(count > 0)
+}
+
+fn pack_u32_pair(high: u32, low: u32) -> u64 {
+    ((high as u64) << 32) | low as u64
+}
@@ -0,0 +1,16 @@
+//! Recording captured frames to H.264 in an MP4 container through Media Foundation, either from CPU frames with
+//! `RecordingHandle` or from frames that stay on the GPU with `MfEncoderSink`.
+
+mod encoder_sink;
+mod error;
+mod gpu_encoder;
+mod output_path;
+mod recorder;
+mod recording_thread;
+
+pub use encoder_sink::{MfEncoderSink, MfEncoderSinkBuilder};
+pub use error::{RecordingError, Result};
+pub use gpu_encoder::{EncoderInfo, GpuEncoderSettings, VideoCodec};
+pub use output_path::default_recording_path;
+pub use recorder::{Recorder, RecorderSettings};
+pub use recording_thread::RecordingHandle;
@@ -0,0 +1,13 @@
+use std::path::PathBuf;
+
+use windows::Win32::UI::Shell::FOLDERID_Videos;
+
+use crate::{
+    recording::Result,
+    utils::output_path::{known_folder, timestamped_file_name},
+};
+
+/// A timestamped file in the Videos folder of the user, e.g. `loki-2025-01-31_18-04-05.mp4`.
+pub fn default_recording_path() -> Result<PathBuf> {
+    Ok(known_folder(&FOLDERID_Videos)?.join(timestamped_file_name("mp4")))
+}
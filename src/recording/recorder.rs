@@ -0,0 +1,243 @@
+use std::path::Path;
+
+use windows::Win32::Media::MediaFoundation::{
+    IMFAttributes, IMFMediaType, IMFSinkWriter, MF_MT_AVG_BITRATE, MF_MT_FRAME_RATE,
+    MF_MT_FRAME_SIZE, MF_MT_INTERLACE_MODE, MF_MT_MAJOR_TYPE, MF_MT_PIXEL_ASPECT_RATIO,
+    MF_MT_SUBTYPE, MF_READWRITE_ENABLE_HARDWARE_TRANSFORMS, MF_VERSION, MFCreateAttributes,
+    MFCreateMediaType, MFCreateMemoryBuffer, MFCreateSample, MFCreateSinkWriterFromURL,
+    MFMediaType_Video, MFSTARTUP_FULL, MFShutdown, MFStartup, MFVideoFormat_H264,
+    MFVideoFormat_NV12, MFVideoInterlace_Progressive,
+};
+use windows_core::{GUID, HSTRING};
+
+use crate::{
+    capture::initialize_com,
+    capture_providers::shared::{CaptureFramerate, Frame, PixelFormat, Vector2},
+    recording::{RecordingError, Result},
+    utils::image_utils::{bgra_to_nv12, rgba_to_nv12},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecorderSettings {
+    pub width: u32,
+    pub height: u32,
+    /// Nominal framerate of the file. Frames keep their own timestamps, so pacing may vary around it.
+    pub fps: u32,
+    /// Average bitrate in bits per second.
+    pub bitrate: u32,
+}
+
+impl RecorderSettings {
+    /// Settings with a bitrate suitable for screen content of the size. Odd sizes are rounded down to even ones.
+    pub fn new(width: u32, height: u32, fps: u32) -> Self {
+        let (width, height) = (width & !1, height & !1);
+        // Around 0.1 bits per pixel, which keeps text readable.
+        let bitrate = (width as u64 * height as u64 * fps as u64 / 10).clamp(1_000_000, 50_000_000);
+        Self { width, height, fps, bitrate: bitrate as u32 }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(RecordingError::InvalidSettings("Size must not be zero".into()));
+        }
+        // H.264 encodes 4:2:0 chroma, which covers 2x2 blocks.
+        if !self.width.is_multiple_of(2) || !self.height.is_multiple_of(2) {
+            return Err(RecordingError::InvalidSettings(format!(
+                "Size must be even, got {} x {}",
+                self.width, self.height
+            )));
+        }
+        if self.fps == 0 {
+            return Err(RecordingError::InvalidSettings("Framerate must not be zero".into()));
+        }
+        if self.bitrate == 0 {
+            return Err(RecordingError::InvalidSettings("Bitrate must not be zero".into()));
+        }
+        Ok(())
+    }
+}
+
+/// Encodes frames to H.264 and writes them to an MP4 file with the Media Foundation sink writer.
+/// Frames are converted to NV12 before they are handed to the encoder.
+pub struct Recorder {
+    writer: IMFSinkWriter,
+    stream_index: u32,
+    settings: RecorderSettings,
+    /// Timestamp of the first frame, which becomes sample time zero.
+    first_timestamp: Option<i64>,
+    last_sample_time: Option<i64>,
+    nv12: Vec<u8>,
+    frames_written: u64,
+    finalized: bool,
+}
+
+impl Recorder {
+    pub fn new(path: impl AsRef<Path>, settings: RecorderSettings) -> Result<Self> {
+        let path = path.as_ref();
+        tracing::info!("Starting recording to {}: {:?}", path.display(), settings);
+        settings.validate()?;
+
+        initialize_com()?;
+        unsafe { MFStartup(MF_VERSION, MFSTARTUP_FULL)? };
+
+        // Once the recorder exists, Drop pairs MFStartup with MFShutdown.
+        match Self::create_writer(path, &settings) {
+            Ok((writer, stream_index)) => Ok(Self {
+                writer,
+                stream_index,
+                settings,
+                first_timestamp: None,
+                last_sample_time: None,
+                nv12: Vec::new(),
+                frames_written: 0,
+                finalized: false,
+            }),
+            Err(err) => {
+                if let Err(err) = unsafe { MFShutdown() } {
+                    tracing::warn!("Failed to shut down Media Foundation: {}", err);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn create_writer(path: &Path, settings: &RecorderSettings) -> Result<(IMFSinkWriter, u32)> {
+        let mut attributes: Option<IMFAttributes> = None;
+        unsafe { MFCreateAttributes(&mut attributes, 1)? };
+        let attributes = attributes.expect("Failed to create sink writer attributes!");
+        unsafe { attributes.SetUINT32(&MF_READWRITE_ENABLE_HARDWARE_TRANSFORMS, 1)? };
+
+        let url = HSTRING::from(path.as_os_str());
+        let writer = unsafe { MFCreateSinkWriterFromURL(&url, None, &attributes)? };
+
+        let output_type = Self::create_video_type(settings, &MFVideoFormat_H264)?;
+        unsafe { output_type.SetUINT32(&MF_MT_AVG_BITRATE, settings.bitrate)? };
+        let stream_index = unsafe { writer.AddStream(&output_type)? };
+
+        let input_type = Self::create_video_type(settings, &MFVideoFormat_NV12)?;
+        unsafe {
+            writer.SetInputMediaType(stream_index, &input_type, None)?;
+            writer.BeginWriting()?;
+        }
+
+        Ok((writer, stream_index))
+    }
+
+    fn create_video_type(settings: &RecorderSettings, subtype: &GUID) -> Result<IMFMediaType> {
+        let media_type = unsafe { MFCreateMediaType()? };
+        unsafe {
+            media_type.SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Video)?;
+            media_type.SetGUID(&MF_MT_SUBTYPE, subtype)?;
+            media_type.SetUINT32(&MF_MT_INTERLACE_MODE, MFVideoInterlace_Progressive.0 as u32)?;
+            // Sizes and ratios are packed into the high and low halves, which MFSetAttributeSize does in C++.
+            media_type
+                .SetUINT64(&MF_MT_FRAME_SIZE, pack_u32_pair(settings.width, settings.height))?;
+            media_type.SetUINT64(&MF_MT_FRAME_RATE, pack_u32_pair(settings.fps, 1))?;
+            media_type.SetUINT64(&MF_MT_PIXEL_ASPECT_RATIO, pack_u32_pair(1, 1))?;
+        }
+        Ok(media_type)
+    }
+
+    pub fn settings(&self) -> &RecorderSettings {
+        &self.settings
+    }
+
+    pub fn frames_written(&self) -> u64 {
+        self.frames_written
+    }
+
+    /// Encodes a frame. Its sample time is the distance of its timestamp to the first frame, so variable
+    /// frame pacing is kept. The frame must have the size of the recording, give or take a row or column.
+    pub fn push_frame(&mut self, frame: &Frame) -> Result<()> {
+        let expected = Vector2::new(self.settings.width as i32, self.settings.height as i32);
+        // Packed frames of odd sizes lose their last row or column, as the encoder needs even sizes.
+        let cropped = Vector2::new(frame.size.x & !1, frame.size.y & !1);
+        if cropped != expected || (frame.format.is_planar() && frame.size != expected) {
+            return Err(RecordingError::FrameSizeMismatch { expected, actual: frame.size });
+        }
+
+        let first_timestamp = *self.first_timestamp.get_or_insert(frame.timestamp);
+        if frame.timestamp < first_timestamp {
+            return Err(RecordingError::TimestampBeforeStart { timestamp: frame.timestamp });
+        }
+        // Samples must have increasing times, which frames with equal timestamps would break.
+        let sample_time = match self.last_sample_time {
+            Some(last) => (frame.timestamp - first_timestamp).max(last + 1),
+            None => 0,
+        };
+
+        self.convert_to_nv12(frame)?;
+        self.write_sample(sample_time)?;
+        self.last_sample_time = Some(sample_time);
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    fn convert_to_nv12(&mut self, frame: &Frame) -> Result<()> {
+        let width = self.settings.width as usize;
+        let height = self.settings.height as usize;
+        match frame.format {
+            PixelFormat::RGBA8 => {
+                rgba_to_nv12(&frame.data, width, height, frame.stride, &mut self.nv12)
+            }
+            PixelFormat::BGRA8 => {
+                bgra_to_nv12(&frame.data, width, height, frame.stride, &mut self.nv12)
+            }
+            PixelFormat::NV12 => {
+                // Planar frames are always tightly packed.
+                self.nv12.clear();
+                self.nv12.extend_from_slice(&frame.data);
+            }
+            PixelFormat::I420 | PixelFormat::Gray8 | PixelFormat::RGBA16F => {
+                return Err(RecordingError::UnsupportedFormat(frame.format));
+            }
+        }
+        Ok(())
+    }
+
+    fn write_sample(&self, sample_time: i64) -> Result<()> {
+        let length = self.nv12.len() as u32;
+        let duration = CaptureFramerate::TICKS_PER_SECOND as i64 / self.settings.fps as i64;
+
+        unsafe {
+            let buffer = MFCreateMemoryBuffer(length)?;
+            let mut data = std::ptr::null_mut();
+            buffer.Lock(&mut data, None, None)?;
+            std::ptr::copy_nonoverlapping(self.nv12.as_ptr(), data, self.nv12.len());
+            buffer.Unlock()?;
+            buffer.SetCurrentLength(length)?;
+
+            let sample = MFCreateSample()?;
+            sample.AddBuffer(&buffer)?;
+            sample.SetSampleTime(sample_time)?;
+            sample.SetSampleDuration(duration)?;
+            self.writer.WriteSample(self.stream_index, &sample)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the encoder and finishes the file. Without this, the file is not playable.
+    pub fn finalize(mut self) -> Result<()> {
+        tracing::info!("Finalizing recording after {} frames.", self.frames_written);
+        self.finalized = true;
+        unsafe { self.writer.Finalize()? };
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if !self.finalized {
+            tracing::warn!(
+                "Recorder dropped without being finalized, the file will be incomplete."
+            );
+        }
+        if let Err(err) = unsafe { MFShutdown() } {
+            tracing::warn!("Failed to shut down Media Foundation: {}", err);
+        }
+    }
+}
+
+fn pack_u32_pair(high: u32, low: u32) -> u64 {
+    ((high as u64) << 32) | low as u64
+}
@@ -0,0 +1,125 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+    },
+    thread::JoinHandle,
+};
+
+use crate::{
+    capture_providers::shared::Frame,
+    recording::{Recorder, RecorderSettings, RecordingError, Result},
+};
+
+/// Records on a separate thread, so converting and encoding frames doesn't hold up the caller.
+#[derive(Debug)]
+pub struct RecordingHandle {
+    path: PathBuf,
+    tx: Option<SyncSender<Frame>>,
+    thread: Option<JoinHandle<Result<()>>>,
+    dropped_frames: Arc<AtomicU64>,
+}
+
+impl RecordingHandle {
+    /// Frames queued for the encoder before new ones are dropped.
+    const QUEUE_CAPACITY: usize = 8;
+
+    /// Starts recording to the file. Returns once the encoder is set up, so setup errors are reported here.
+    pub fn start(path: PathBuf, settings: RecorderSettings) -> Result<Self> {
+        let (tx, rx) = mpsc::sync_channel(Self::QUEUE_CAPACITY);
+        let (ready_tx, ready_rx) = mpsc::sync_channel(1);
+
+        let thread_path = path.clone();
+        let thread =
+            std::thread::Builder::new().name("loki-recording".into()).spawn(move || {
+                // The Media Foundation objects stay on this thread, as they are not free-threaded.
+                let recorder = match Recorder::new(&thread_path, settings) {
+                    Ok(recorder) => {
+                        ready_tx.send(Ok(())).ok();
+                        recorder
+                    }
+                    Err(err) => {
+                        ready_tx.send(Err(err)).ok();
+                        return Ok(());
+                    }
+                };
+                Self::run(recorder, rx)
+            })?;
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self {
+                path,
+                tx: Some(tx),
+                thread: Some(thread),
+                dropped_frames: Arc::new(AtomicU64::new(0)),
+            }),
+            Ok(Err(err)) => Err(err),
+            Err(_) => Err(RecordingError::ThreadStopped),
+        }
+    }
+
+    fn run(mut recorder: Recorder, rx: Receiver<Frame>) -> Result<()> {
+        for frame in rx {
+            match recorder.push_frame(&frame) {
+                Ok(()) => (),
+                Err(err) if err.is_frame_error() => {
+                    tracing::warn!("Skipping frame {} in recording: {}", frame.sequence, err);
+                }
+                Err(err) => {
+                    tracing::error!("Recording failed: {}", err);
+                    return Err(err);
+                }
+            }
+        }
+        // The sender is gone, so the recording was stopped.
+        recorder.finalize()
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Queues a frame for recording. Frames are dropped if the encoder can't keep up.
+    /// Fails if the recording thread stopped, in which case `finish` returns the reason.
+    pub fn push_frame(&self, frame: Frame) -> Result<()> {
+        let tx = self.tx.as_ref().ok_or(RecordingError::ThreadStopped)?;
+        match tx.try_send(frame) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("Recording queue full, dropping frame.");
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => Err(RecordingError::ThreadStopped),
+        }
+    }
+
+    /// Number of frames dropped because the encoder couldn't keep up.
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    /// Stops recording and waits for the file to be finalized. Blocks while the encoder drains.
+    pub fn finish(mut self) -> Result<PathBuf> {
+        self.stop()?;
+        Ok(std::mem::take(&mut self.path))
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.tx.take();
+        match self.thread.take() {
+            Some(thread) => thread.join().map_err(|_| RecordingError::ThreadStopped)?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for RecordingHandle {
+    fn drop(&mut self) {
+        if let Err(err) = self.stop() {
+            tracing::error!("Failed to finish recording: {}", err);
+        }
+    }
+}
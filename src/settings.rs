@@ -0,0 +1,451 @@
+use std::path::{Path, PathBuf};
+
+use loki::{
+    capture_providers::shared::{CaptureFramerate, CaptureTarget, Rect, ScaleMode, Vector2},
+    widgets::frame_viewer::FrameFit,
+};
+use serde::{Deserialize, Serialize};
+
+pub type Result<T> = std::result::Result<T, SettingsError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SettingsError {
+    #[error("Failed to find the settings folder")]
+    NoSettingsFolder,
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Invalid settings file: {0}")]
+    InvalidFile(#[from] toml::de::Error),
+    #[error("Failed to write the settings: {0}")]
+    SerializeFailed(#[from] toml::ser::Error),
+}
+
+/// Where logs are kept, e.g. `%APPDATA%/loki` on Windows and `~/.local/share/loki` on Linux.
+pub fn data_dir() -> Result<PathBuf> {
+    Ok(dirs::data_dir().ok_or(SettingsError::NoSettingsFolder)?.join("loki"))
+}
+
+/// Where the settings are kept, e.g. `%APPDATA%/loki` on Windows and `~/.config/loki` on Linux.
+pub fn config_dir() -> Result<PathBuf> {
+    Ok(dirs::config_dir().ok_or(SettingsError::NoSettingsFolder)?.join("loki"))
+}
+
+/// Size and position of the main window, in logical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub width: f32,
+    pub height: f32,
+    /// Unknown until the window has been moved once.
+    pub position: Option<(f32, f32)>,
+}
+
+/// A region of a monitor picked with the region picker, in the monitor's physical pixels.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureRegion {
+    /// Device name of the monitor, as in `MonitorSource::name`.
+    pub monitor: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl CaptureRegion {
+    pub fn rect(&self) -> Rect<i32> {
+        Rect::new(Vector2::new(self.x, self.y), Vector2::new(self.width, self.height))
+    }
+}
+
+/// How [`ScaleMode`] is stored, so the library types don't need to know about serde.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ScaleSetting {
+    #[default]
+    Native,
+    Fraction {
+        factor: f32,
+    },
+    FitWithin {
+        width: u32,
+        height: u32,
+    },
+}
+
+impl From<ScaleMode> for ScaleSetting {
+    fn from(mode: ScaleMode) -> Self {
+        match mode {
+            ScaleMode::Native => Self::Native,
+            ScaleMode::Fraction(factor) => Self::Fraction { factor },
+            ScaleMode::FitWithin(size) => Self::FitWithin { width: size.x, height: size.y },
+        }
+    }
+}
+
+impl From<ScaleSetting> for ScaleMode {
+    fn from(setting: ScaleSetting) -> Self {
+        match setting {
+            ScaleSetting::Native => Self::Native,
+            ScaleSetting::Fraction { factor } => Self::Fraction(factor),
+            ScaleSetting::FitWithin { width, height } => {
+                Self::FitWithin(Vector2::new(width, height))
+            }
+        }
+    }
+}
+
+/// How the [`FrameFit`] of the preview is stored, so the viewer the examples share doesn't need to know about
+/// serde.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FitSetting {
+    #[default]
+    Fit,
+    Fill,
+    Stretch,
+    ActualSize,
+}
+
+impl From<FrameFit> for FitSetting {
+    fn from(fit: FrameFit) -> Self {
+        match fit {
+            FrameFit::Fit => Self::Fit,
+            FrameFit::Fill => Self::Fill,
+            FrameFit::Stretch => Self::Stretch,
+            FrameFit::ActualSize => Self::ActualSize,
+        }
+    }
+}
+
+impl From<FitSetting> for FrameFit {
+    fn from(setting: FitSetting) -> Self {
+        match setting {
+            FitSetting::Fit => Self::Fit,
+            FitSetting::Fill => Self::Fill,
+            FitSetting::Stretch => Self::Stretch,
+            FitSetting::ActualSize => Self::ActualSize,
+        }
+    }
+}
+
+/// How a [`CaptureTarget`] is stored, for resuming the last capture in the next run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TargetSetting {
+    Monitor { device_name: String, x: i32, y: i32, width: i32, height: i32 },
+    Window { title: String, class_name: String, exe_name: String },
+}
+
+impl From<CaptureTarget> for TargetSetting {
+    fn from(target: CaptureTarget) -> Self {
+        match target {
+            CaptureTarget::Monitor { device_name, position, size } => Self::Monitor {
+                device_name,
+                x: position.x,
+                y: position.y,
+                width: size.x,
+                height: size.y,
+            },
+            CaptureTarget::Window { title, class_name, exe_name } => {
+                Self::Window { title, class_name, exe_name }
+            }
+        }
+    }
+}
+
+impl From<TargetSetting> for CaptureTarget {
+    fn from(setting: TargetSetting) -> Self {
+        match setting {
+            TargetSetting::Monitor { device_name, x, y, width, height } => Self::Monitor {
+                device_name,
+                position: Vector2::new(x, y),
+                size: Vector2::new(width, height),
+            },
+            TargetSetting::Window { title, class_name, exe_name } => {
+                Self::Window { title, class_name, exe_name }
+            }
+        }
+    }
+}
+
+/// User preferences that are kept between runs.
+/// Missing fields take their default and unknown ones are ignored, so files from other versions still load.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Format version of the file, see [`Settings::migrate`].
+    pub version: u32,
+    /// As written by the `Display` of [`CaptureFramerate`], e.g. "60" or "FPS(24000/1001)", see
+    /// [`Settings::framerate`].
+    pub framerate: String,
+    pub cursor_capture_enabled: bool,
+    pub border_required: bool,
+    pub window: Option<WindowGeometry>,
+    pub scale_mode: ScaleSetting,
+    /// How the preview places the frame.
+    pub preview_fit: FitSetting,
+    /// For repeating the last region capture.
+    pub last_region: Option<CaptureRegion>,
+    /// The window or monitor captured last, resumed on startup.
+    pub last_target: Option<TargetSetting>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: Self::CURRENT_VERSION,
+            framerate: CaptureFramerate::FPS60.to_string(),
+            cursor_capture_enabled: true,
+            border_required: true,
+            window: None,
+            scale_mode: ScaleSetting::Native,
+            preview_fit: FitSetting::Fit,
+            last_region: None,
+            last_target: None,
+        }
+    }
+}
+
+impl Settings {
+    pub const CURRENT_VERSION: u32 = 2;
+    const FILE_NAME: &'static str = "settings.toml";
+
+    /// `settings.toml` in the [`config_dir`].
+    pub fn path() -> Result<PathBuf> {
+        Ok(config_dir()?.join(Self::FILE_NAME))
+    }
+
+    /// Loads the settings, falling back to the defaults if the file is missing or can't be read.
+    pub fn load_or_default() -> Self {
+        Self::or_default(Self::load())
+    }
+
+    fn or_default(loaded: Result<Option<Self>>) -> Self {
+        match loaded {
+            Ok(Some(settings)) => settings,
+            Ok(None) => {
+                tracing::info!("No settings file found, using defaults.");
+                Self::default()
+            }
+            Err(err) => {
+                tracing::warn!("Failed to load settings, using defaults: {}", err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Returns `None` if there is no settings file yet.
+    pub fn load() -> Result<Option<Self>> {
+        Self::load_from(&Self::path()?)
+    }
+
+    fn load_from(path: &Path) -> Result<Option<Self>> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        Self::from_toml(&contents).map(Some)
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let table: toml::Table = toml::from_str(contents)?;
+        let settings = toml::Value::Table(Self::migrate(table)).try_into()?;
+        Ok(settings)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// Upgrades the raw contents of a file written by an older version to the current format.
+    /// Files without a version predate it and are treated as version 1.
+    fn migrate(mut table: toml::Table) -> toml::Table {
+        let version = table.get("version").and_then(toml::Value::as_integer).unwrap_or(1);
+        if version > Self::CURRENT_VERSION as i64 {
+            tracing::warn!(
+                "Settings file is from a newer version ({}), unknown fields are ignored.",
+                version
+            );
+        }
+        // Migrations from older versions go here, each one bumping the version by one.
+        if version < 2 {
+            // Version 1 only knew whole framerates, as `fps`.
+            let fps = table.remove("fps").and_then(|fps| fps.as_integer());
+            let framerate = fps
+                .and_then(|fps| u32::try_from(fps).ok())
+                .and_then(|fps| CaptureFramerate::try_from(fps).ok());
+            if let Some(framerate) = framerate {
+                table.insert("framerate".into(), framerate.to_string().into());
+            }
+        }
+        table.insert("version".into(), i64::from(Self::CURRENT_VERSION).into());
+        table
+    }
+
+    /// Writes the settings, replacing the file in one step so a crash can't leave half of it behind.
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&Self::path()?)
+    }
+
+    fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let contents = self.to_toml()?;
+        let temp_path = path.with_extension("toml.tmp");
+        std::fs::write(&temp_path, contents)?;
+        std::fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// Falls back to 60 FPS if the stored framerate can't be read, rather than failing to load the rest.
+    pub fn framerate(&self) -> CaptureFramerate {
+        self.framerate.parse().unwrap_or_else(|err| {
+            tracing::warn!("{} in the settings, using 60 FPS.", err);
+            CaptureFramerate::FPS60
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = Settings {
+            framerate: CaptureFramerate::fractional(24000, 1001).unwrap().to_string(),
+            cursor_capture_enabled: false,
+            border_required: false,
+            window: Some(WindowGeometry {
+                width: 1280.0,
+                height: 720.0,
+                position: Some((10.0, 20.0)),
+            }),
+            scale_mode: ScaleSetting::FitWithin { width: 1920, height: 1080 },
+            preview_fit: FitSetting::ActualSize,
+            last_region: Some(CaptureRegion {
+                monitor: r"\\.\DISPLAY2".to_string(),
+                x: 100,
+                y: 50,
+                width: 640,
+                height: 480,
+            }),
+            last_target: Some(TargetSetting::Window {
+                title: "Untitled - Notepad".to_string(),
+                class_name: "Notepad".to_string(),
+                exe_name: "notepad.exe".to_string(),
+            }),
+            ..Settings::default()
+        };
+
+        let contents = settings.to_toml().unwrap();
+        let loaded = Settings::from_toml(&contents).unwrap();
+        assert_eq!(loaded, settings);
+        assert_eq!(loaded.framerate(), CaptureFramerate::fractional(24000, 1001).unwrap());
+    }
+
+    #[test]
+    fn custom_framerates_are_kept() {
+        let custom = CaptureFramerate::Custom(NonZeroU32::new(144).unwrap());
+        let settings = Settings { framerate: custom.to_string(), ..Settings::default() };
+        let contents = settings.to_toml().unwrap();
+        assert_eq!(Settings::from_toml(&contents).unwrap().framerate(), custom);
+    }
+
+    #[test]
+    fn version_1_files_are_migrated() {
+        let loaded = Settings::from_toml("version = 1\nfps = 30\nborder_required = false").unwrap();
+        assert_eq!(loaded.version, Settings::CURRENT_VERSION);
+        assert_eq!(loaded.framerate(), CaptureFramerate::FPS30);
+        assert!(!loaded.border_required);
+        // Keys the file didn't have yet take their defaults.
+        let defaults = Settings::default();
+        assert_eq!(loaded.cursor_capture_enabled, defaults.cursor_capture_enabled);
+        assert_eq!(loaded.preview_fit, defaults.preview_fit);
+        assert_eq!(loaded.scale_mode, defaults.scale_mode);
+        assert_eq!(loaded.last_target, None);
+    }
+
+    #[test]
+    fn files_without_a_version_are_version_1() {
+        let loaded = Settings::from_toml("fps = 90").unwrap();
+        assert_eq!(loaded.version, Settings::CURRENT_VERSION);
+        assert_eq!(loaded.framerate(), CaptureFramerate::Custom(NonZeroU32::new(90).unwrap()));
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        let loaded =
+            Settings::from_toml("version = 99\nframerate = \"120\"\nadded_later = [1, 2]").unwrap();
+        assert_eq!(loaded.framerate(), CaptureFramerate::FPS120);
+    }
+
+    #[test]
+    fn unreadable_framerates_fall_back_to_60_fps() {
+        let loaded = Settings::from_toml(r#"framerate = "fast""#).unwrap();
+        assert_eq!(loaded.framerate(), CaptureFramerate::FPS60);
+    }
+
+    #[test]
+    fn corrupt_files_are_errors() {
+        assert!(Settings::from_toml("framerate = ").is_err());
+        assert!(Settings::from_toml(r#"cursor_capture_enabled = "yes""#).is_err());
+    }
+
+    /// A path in a folder of its own under the temp folder, which is removed again on drop.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(test: &str) -> Self {
+            let folder =
+                std::env::temp_dir().join(format!("loki-settings-{}-{}", test, std::process::id()));
+            Self(folder.join(Settings::FILE_NAME))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            if let Some(folder) = self.0.parent() {
+                let _ = std::fs::remove_dir_all(folder);
+            }
+        }
+    }
+
+    #[test]
+    fn saved_files_load_again() {
+        let file = TempFile::new("saved");
+        let settings = Settings {
+            framerate: CaptureFramerate::FPS120.to_string(),
+            border_required: false,
+            ..Settings::default()
+        };
+        settings.save_to(&file.0).unwrap();
+        assert_eq!(Settings::load_from(&file.0).unwrap(), Some(settings));
+        // Nothing but the settings is left behind.
+        let files = std::fs::read_dir(file.0.parent().unwrap()).unwrap().count();
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    fn missing_files_fall_back_to_the_defaults() {
+        let file = TempFile::new("missing");
+        let loaded = Settings::load_from(&file.0);
+        assert!(matches!(loaded, Ok(None)));
+        assert_eq!(Settings::or_default(loaded), Settings::default());
+    }
+
+    #[test]
+    fn corrupt_files_fall_back_to_the_defaults() {
+        let file = TempFile::new("corrupt");
+        std::fs::create_dir_all(file.0.parent().unwrap()).unwrap();
+        for contents in ["[window", "42", "version = ", "border_required = fal"] {
+            std::fs::write(&file.0, contents).unwrap();
+            let loaded = Settings::load_from(&file.0);
+            assert!(matches!(loaded, Err(SettingsError::InvalidFile(_))), "{:?}", contents);
+            assert_eq!(Settings::or_default(loaded), Settings::default());
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use crate::capture_providers::shared::{PixelFormat, Vector2};
+
+pub type Result<T> = std::result::Result<T, SinkError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SinkError {
+    #[error("Frame is {actual:?}, but the file is {expected:?}")]
+    FrameSizeMismatch { expected: Vector2<i32>, actual: Vector2<i32> },
+    #[error("Unsupported pixel format for the file: {0:?}")]
+    UnsupportedFormat(PixelFormat),
+    #[error("Delta frames can't be written, the stream has to deliver full frames")]
+    DeltaFrame,
+    #[error("Writer task stopped unexpectedly")]
+    WriterStopped,
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+impl SinkError {
+    /// Whether writing can go on after this error, only losing the frame that caused it.
+    pub fn is_frame_error(&self) -> bool {
+        matches!(
+            self,
+            Self::FrameSizeMismatch { .. } | Self::UnsupportedFormat(_) | Self::DeltaFrame
+        )
+    }
+}
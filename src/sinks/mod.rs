@@ -0,0 +1,11 @@
+//! Writing captured frames to files for offline inspection, as Y4M video or as a sequence of raw RGBA frames.
+
+mod error;
+mod raw_rgba;
+mod record;
+mod y4m;
+
+pub use error::{Result, SinkError};
+pub use raw_rgba::RawRgbaWriter;
+pub use record::{FrameSink, record_stream};
+pub use y4m::Y4mWriter;
@@ -0,0 +1,82 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    capture_providers::shared::{Frame, Vector2},
+    sinks::{FrameSink, Result, SinkError},
+};
+
+/// Writes frames to a file as tightly packed RGBA, one after the other without any header, e.g. to play with
+/// `ffplay -f rawvideo -pixel_format rgba -video_size 1920x1080 capture.rgba`. Unlike `Y4mWriter`, every frame
+/// is written exactly once as it arrived, so the timing of the capture is lost.
+///
+/// The size is that of the first frame, frames of other sizes are skipped. Planar frames are skipped as well.
+pub struct RawRgbaWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    size: Option<Vector2<i32>>,
+    frames_written: u64,
+}
+
+impl RawRgbaWriter {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        tracing::info!("Writing raw RGBA frames to {}", path.display());
+        Ok(Self { file: BufWriter::new(File::create(&path)?), path, size: None, frames_written: 0 })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Size of the frames in the file, `None` until the first one was written.
+    pub fn size(&self) -> Option<Vector2<i32>> {
+        self.size
+    }
+
+    pub fn frames_written(&self) -> u64 {
+        self.frames_written
+    }
+}
+
+impl FrameSink for RawRgbaWriter {
+    fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        if frame.is_delta() {
+            return Err(SinkError::DeltaFrame);
+        }
+        if frame.format.is_planar() {
+            return Err(SinkError::UnsupportedFormat(frame.format));
+        }
+        let size = *self.size.get_or_insert(frame.size);
+        if frame.size != size {
+            return Err(SinkError::FrameSizeMismatch { expected: size, actual: frame.size });
+        }
+
+        self.file.write_all(&frame.to_tightly_packed_rgba())?;
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.file.flush()?;
+        tracing::info!(
+            "Finished raw RGBA file {} with {} frames.",
+            self.path.display(),
+            self.frames_written
+        );
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for RawRgbaWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RawRgbaWriter")
+            .field("path", &self.path)
+            .field("size", &self.size)
+            .field("frames_written", &self.frames_written)
+            .finish_non_exhaustive()
+    }
+}
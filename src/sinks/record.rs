@@ -0,0 +1,72 @@
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc;
+
+use crate::{
+    capture_providers::shared::Frame,
+    sinks::{Result, SinkError},
+};
+
+/// Writes frames to a file one after the other. Runs on a blocking thread, see `record_stream`.
+/// Unlike the `FrameSink` of the Windows provider, it consumes a stream and never runs on the capture thread.
+pub trait FrameSink: Send + 'static {
+    fn write_frame(&mut self, frame: &Frame) -> Result<()>;
+
+    /// Flushes everything written so far, after which the file is complete. Called once, after the last frame.
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// Frames queued for the writer before `record_stream` waits for it.
+const QUEUE_CAPACITY: usize = 4;
+
+/// Writes the frames of `stream` with `writer` until the stream ends or `stop` completes, then finishes the file
+/// and returns the writer. The stream is usually `CaptureStream::frames_only` or a `CaptureSession`.
+///
+/// The writer runs on a blocking task. Frames are queued for it, and a full queue is waited on rather than
+/// dropping frames, so a slow writer holds up the stream and its `BackpressurePolicy` decides what happens to the
+/// capture, e.g. `Block` to not lose any frames at all.
+pub async fn record_stream<W: FrameSink>(
+    stream: impl Stream<Item = Frame>,
+    mut writer: W,
+    stop: impl Future<Output = ()>,
+) -> Result<W> {
+    let (tx, mut rx) = mpsc::channel::<Frame>(QUEUE_CAPACITY);
+    let writer_task = tokio::task::spawn_blocking(move || {
+        while let Some(frame) = rx.blocking_recv() {
+            match writer.write_frame(&frame) {
+                Ok(()) => (),
+                Err(err) if err.is_frame_error() => {
+                    tracing::warn!("Skipping frame {} in file: {}", frame.sequence, err);
+                }
+                Err(err) => {
+                    tracing::error!("Writing frames failed: {}", err);
+                    return Err(err);
+                }
+            }
+        }
+        writer.finish()?;
+        Ok(writer)
+    });
+
+    tokio::pin!(stream);
+    tokio::pin!(stop);
+    loop {
+        let frame = tokio::select! {
+            frame = stream.next() => frame,
+            () = &mut stop => {
+                tracing::info!("Stopped writing frames.");
+                break;
+            }
+        };
+        let Some(frame) = frame else {
+            tracing::info!("Stream ended, finishing the file.");
+            break;
+        };
+        // Only fails once the writer gave up, its error is returned below.
+        if tx.send(frame).await.is_err() {
+            break;
+        }
+    }
+
+    drop(tx);
+    writer_task.await.map_err(|_| SinkError::WriterStopped)?
+}
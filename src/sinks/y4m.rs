@@ -0,0 +1,170 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    capture_providers::shared::{CaptureFramerate, Frame, PixelFormat, Vector2},
+    sinks::{FrameSink, Result, SinkError},
+    utils::image_utils::{bgra_to_i420, convert_image, rgba_to_i420},
+};
+
+/// Writes frames to a Y4M file, which most video tools read as they are, e.g.
+/// `ffplay capture.y4m`. Frames are converted to I420 unless they already are.
+///
+/// Y4M has a fixed framerate and size. Frames are placed by their timestamp at `framerate`: gaps, such as the ones
+/// WGC leaves while nothing on screen changes, are filled by repeating the previous frame, and frames arriving
+/// faster than the framerate are skipped. The size is that of the first frame, frames of other sizes are
+/// skipped.
+pub struct Y4mWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    framerate: CaptureFramerate,
+    /// Size of the frames and timestamp of the first one, known once it arrived.
+    first: Option<(Vector2<i32>, i64)>,
+    /// The last frame written, to repeat it across gaps.
+    i420: Vec<u8>,
+    frames_written: u64,
+    frames_repeated: u64,
+}
+
+impl Y4mWriter {
+    pub fn create(path: impl AsRef<Path>, framerate: CaptureFramerate) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        tracing::info!("Writing Y4M file {} at {}", path.display(), framerate);
+        Ok(Self {
+            file: BufWriter::new(File::create(&path)?),
+            path,
+            framerate,
+            first: None,
+            i420: Vec::new(),
+            frames_written: 0,
+            frames_repeated: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Frames in the file, including repeated ones.
+    pub fn frames_written(&self) -> u64 {
+        self.frames_written
+    }
+
+    /// Frames written again to fill gaps in the capture.
+    pub fn frames_repeated(&self) -> u64 {
+        self.frames_repeated
+    }
+
+    fn write_header(&mut self, size: Vector2<i32>) -> Result<()> {
+        let (frames, seconds) = self.framerate.ratio();
+        // The conversion is BT.601 at limited range, averaging each 2x2 block for its chroma sample.
+        writeln!(
+            self.file,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420jpeg XCOLORRANGE=LIMITED",
+            size.x, size.y, frames, seconds
+        )?;
+        Ok(())
+    }
+
+    /// Index of the frame slot the timestamp falls into, rounded to the nearest.
+    fn slot(&self, first_timestamp: i64, timestamp: i64) -> u64 {
+        let (frames, seconds) = self.framerate.ratio();
+        let elapsed = timestamp.saturating_sub(first_timestamp).max(0) as u128;
+        let per_slot = (seconds * CaptureFramerate::TICKS_PER_SECOND) as u128;
+        ((elapsed * frames as u128 + per_slot / 2) / per_slot) as u64
+    }
+
+    fn convert(&mut self, frame: &Frame) -> Result<()> {
+        let width = frame.size.x.max(0) as usize;
+        let height = frame.size.y.max(0) as usize;
+        match frame.format {
+            PixelFormat::I420 => {
+                // Planar frames are always tightly packed.
+                self.i420.clear();
+                self.i420.extend_from_slice(&frame.data);
+            }
+            PixelFormat::RGBA8 => {
+                rgba_to_i420(&frame.data, width, height, frame.stride, &mut self.i420)
+            }
+            PixelFormat::BGRA8 => {
+                bgra_to_i420(&frame.data, width, height, frame.stride, &mut self.i420)
+            }
+            PixelFormat::RGBA16F => {
+                let (converted, _, _) = convert_image(
+                    frame.data.to_vec(),
+                    frame.format,
+                    PixelFormat::I420,
+                    frame.size,
+                    frame.stride,
+                );
+                self.i420 = converted;
+            }
+            format => return Err(SinkError::UnsupportedFormat(format)),
+        }
+        Ok(())
+    }
+
+    fn write_i420(&mut self) -> Result<()> {
+        self.file.write_all(b"FRAME\n")?;
+        self.file.write_all(&self.i420)?;
+        self.frames_written += 1;
+        Ok(())
+    }
+}
+
+impl FrameSink for Y4mWriter {
+    fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        if frame.is_delta() {
+            return Err(SinkError::DeltaFrame);
+        }
+        let (size, first_timestamp) = match self.first {
+            Some(first) => first,
+            None => (frame.size, frame.timestamp),
+        };
+        if frame.size != size {
+            return Err(SinkError::FrameSizeMismatch { expected: size, actual: frame.size });
+        }
+
+        let slot = self.slot(first_timestamp, frame.timestamp);
+        if self.first.is_some() && slot < self.frames_written {
+            tracing::trace!("Frame {} is faster than the framerate, skipping it.", frame.sequence);
+            return Ok(());
+        }
+        // The previous frame fills the gap before this one replaces it in the buffer.
+        while self.first.is_some() && self.frames_written < slot {
+            self.write_i420()?;
+            self.frames_repeated += 1;
+        }
+
+        self.convert(frame)?;
+        if self.first.is_none() {
+            self.write_header(size)?;
+            self.first = Some((size, first_timestamp));
+        }
+        self.write_i420()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.file.flush()?;
+        tracing::info!(
+            "Finished Y4M file {} with {} frames, {} of them repeated.",
+            self.path.display(),
+            self.frames_written,
+            self.frames_repeated
+        );
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Y4mWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Y4mWriter")
+            .field("path", &self.path)
+            .field("framerate", &self.framerate)
+            .field("frames_written", &self.frames_written)
+            .finish_non_exhaustive()
+    }
+}
@@ -1,29 +1,61 @@
 use std::{
     hash::{Hash, Hasher},
+    path::PathBuf,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use bytes::Bytes;
+#[cfg(feature = "net")]
+use futures::{FutureExt, stream::BoxStream};
+use futures::{
+    StreamExt,
+    future::Either,
+    stream::{self, Stream},
+};
 use iced::{
-    Element, Length, Program, Subscription, Task, executor,
-    widget::{self, button, column, container, pick_list, row, text},
+    Color, Element, Length, Program, Rectangle, Subscription, Task, executor,
+    widget::{self, button, checkbox, column, container, pick_list, row, stack, text},
     window,
 };
-use tokio::sync::Mutex;
-
-use crate::{
+#[cfg(all(feature = "recording", target_os = "windows"))]
+use loki::recording::{RecorderSettings, RecordingHandle, default_recording_path};
+use loki::{
+    capture::{CaptureHandle, HandleError},
     capture_providers::{
-        CaptureProvider, PlatformCaptureItem, PlatformCaptureProvider, PlatformCaptureStream,
-        shared::{CaptureFramerate, Frame, PixelFormat, Vector2},
+        CaptureProvider, PlatformCaptureItem, PlatformCaptureProvider,
+        platform::{self, MonitorSource},
+        shared::{
+            CaptureEvent, CaptureFramerate, CaptureItemInfo, CaptureStats, CaptureTarget,
+            EndReason, Frame, PixelFormat, Rect, ScaleMode, Vector2,
+        },
         user_pick_platform_capture_item,
     },
-    ui::frame_viewer,
+    utils::{
+        clipboard,
+        image_utils::{ImageFileFormat, encode_rgba, pack_rows},
+        output_path::default_snapshot_path,
+    },
+    widgets::frame_viewer::{self, FrameFit},
+};
+#[cfg(feature = "net")]
+use loki::{
+    capture_providers::PlatformCaptureStream,
+    net::{StreamServer, StreamServerOptions},
+};
+
+#[cfg(all(feature = "global-hotkey", target_os = "windows"))]
+use crate::ui::global_hotkey;
+use crate::{
+    cli::ServeArgs,
+    logging::Logging,
+    settings::{CaptureRegion, Settings, WindowGeometry},
+    ui::{region_picker, stats_pane::StatsPane},
 };
 
 #[derive(Debug, Clone)]
 struct FrameReceiverSubData {
-    capture: Arc<Mutex<PlatformCaptureProvider>>,
-    framerate: CaptureFramerate,
+    capture: CaptureHandle,
     stream_name: &'static str,
 }
 
@@ -35,54 +67,476 @@ impl Hash for FrameReceiverSubData {
 
 #[derive(Debug, Clone)]
 pub enum Message {
+    /// Lets the user pick the item to capture with the Windows picker.
     StartCapture,
-    CaptureStarted,
+    /// Starts capturing when not capturing, otherwise stops.
+    ToggleCapture,
+    /// With the target to resume next time, unless only a region of it is captured.
+    CaptureStarted(Option<CaptureItemInfo>, Option<CaptureTarget>),
     StopCapture,
     CaptureStopped,
+    /// Pauses a running capture, or resumes a paused one, keeping the streams open either way.
+    TogglePause,
+    CapturePaused(bool),
 
     PlatformUserPickedCaptureItem(Result<PlatformCaptureItem, String>),
-    TryStartCapture(PlatformCaptureItem),
+    /// Starts capturing the item, or switches to it. Only the region of the item is captured if one is given.
+    TryStartCapture(PlatformCaptureItem, Option<Rect<i32>>),
     TryStopCapture,
     FrameReceived(Frame),
+    /// The capture item produced its first frame.
+    CaptureProducing,
+    SourceResized(Vector2<i32>),
+    CaptureEnded(EndReason),
+    CaptureRecreated,
+    /// A frame was dropped, the capture keeps going.
+    CaptureFrameError(String),
     FrameRateSelected(CaptureFramerate),
+    FrameFitSelected(FrameFit),
+    PreviewZoomed(f32),
+    /// Applied to the running capture right away and kept for later ones.
+    CursorCaptureToggled(bool),
+    /// Applied like `CursorCaptureToggled`, only where `CaptureProvider::supports_border_toggle`.
+    BorderToggled(bool),
+    ToggleVerboseLogging(bool),
+    /// Asks where to save the frame, F10 opens the same dialog.
+    SaveSnapshot,
+    /// Saves the frame to the Pictures folder right away, without asking where. Bound to Ctrl+S.
+    TakeScreenshot,
+    SnapshotSaved(PathBuf),
+    CopyFrameToClipboard,
+    FrameCopied,
+    ToggleStats,
+    StatsTick,
+    StatsReceived(CaptureStats, Instant),
+    SaveSettings(u64),
+    PickRegion,
+    RegionDragged(window::Id, Rectangle),
+    RegionSelected(window::Id, Rectangle),
+    CancelRegionPick,
+    StartRegionCapture(CaptureRegion),
+    RefreshSources,
+    SourcesEnumerated(Vec<SourceOption>),
+    /// Captures a source from the list instead of picking one with the Windows picker.
+    SourceSelected(SourceOption),
+    RepeatLastRegion,
+    /// Captures the window or monitor that was captured last, e.g. in the previous run.
+    ResumeLastCapture,
+    #[cfg(all(feature = "recording", target_os = "windows"))]
+    StartRecording,
+    #[cfg(all(feature = "recording", target_os = "windows"))]
+    StopRecording,
+    #[cfg(all(feature = "recording", target_os = "windows"))]
+    RecordingStopped(PathBuf),
+    /// The stream server stopped along with the capture, or failed to start.
+    #[cfg(feature = "net")]
+    StreamServerStopped(Option<String>),
+    /// The stream the server sends from dropped a frame, with the number dropped so far.
+    #[cfg(feature = "net")]
+    StreamServerDroppingFrames(u64),
 
     WindowOpened(window::Id),
     WindowIdFetched(u64),
+    WindowResized(window::Id, iced::Size),
+    WindowMoved(window::Id, iced::Point),
 
     Error(String),
 }
 
+impl From<CaptureEvent> for Message {
+    fn from(event: CaptureEvent) -> Self {
+        match event {
+            CaptureEvent::Frame(frame) => Message::FrameReceived(frame),
+            CaptureEvent::Started => Message::CaptureProducing,
+            CaptureEvent::Resized(size) => Message::SourceResized(size),
+            CaptureEvent::Recreated => Message::CaptureRecreated,
+            CaptureEvent::Ended(reason) => Message::CaptureEnded(reason),
+            CaptureEvent::Error(err) => Message::CaptureFrameError(err),
+        }
+    }
+}
+
+/// A monitor or window in the list of capture sources. Only what identifies it is kept, its capture item is
+/// created once it is selected, as it may be gone by then.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceOption {
+    Monitor { name: String, size: Vector2<i32>, is_primary: bool },
+    Window { handle: u64, title: String },
+}
+
+impl SourceOption {
+    /// Monitors first, then windows in the order the system lists them, which is front to back.
+    /// `own_window` is left out, capturing the app itself only shows the capture inside the capture.
+    fn enumerate(own_window: Option<u64>) -> Vec<Self> {
+        let monitors = platform::monitors().into_iter().map(|monitor| Self::Monitor {
+            name: monitor.name,
+            size: monitor.size,
+            is_primary: monitor.is_primary,
+        });
+        let windows = platform::windows()
+            .into_iter()
+            .filter(|window| Some(window.handle) != own_window)
+            .map(|window| Self::Window { handle: window.handle, title: window.title });
+        monitors.chain(windows).collect()
+    }
+
+    fn to_capture_item(&self) -> Result<PlatformCaptureItem, String> {
+        match self {
+            Self::Monitor { name, .. } => platform::monitor_capture_item(name)
+                .map_err(|err| format!("Failed to capture monitor {}: {}", name, err)),
+            Self::Window { handle, title } => platform::window_capture_item(*handle)
+                .map_err(|err| format!("Failed to capture window {:?}: {}", title, err)),
+        }
+    }
+}
+
+impl std::fmt::Display for SourceOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Monitor { name, size, is_primary } => {
+                write!(f, "Monitor {} ({} x {})", name, size.x, size.y)?;
+                if *is_primary {
+                    f.write_str(", primary")?;
+                }
+                Ok(())
+            }
+            Self::Window { title, .. } => write!(f, "Window {:?}", title),
+        }
+    }
+}
+
+/// A fullscreen window of the region picker. One is opened on every monitor, so each uses its monitor's scale.
+#[derive(Debug)]
+pub(crate) struct RegionOverlay {
+    pub window: window::Id,
+    pub monitor_name: String,
+    pub scale_factor: f32,
+    /// The selection while dragging, in logical pixels.
+    pub selection: Option<Rectangle>,
+}
+
+impl RegionOverlay {
+    /// Translates a selection into the physical pixels of the monitor, which are the pixels of its capture item.
+    fn to_region(&self, selection: Rectangle) -> CaptureRegion {
+        let selection = Rect::new(
+            Vector2::new(selection.x, selection.y),
+            Vector2::new(selection.width, selection.height),
+        );
+        let region = selection.to_physical(self.scale_factor);
+        CaptureRegion {
+            monitor: self.monitor_name.clone(),
+            x: region.position.x,
+            y: region.position.y,
+            width: region.size.x,
+            height: region.size.y,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct MutableState {
     pub active_window_handle: Option<u64>,
     pub capturing: bool,
+    /// Whether the running capture is paused, which keeps showing the last frame.
+    pub paused: bool,
+    /// Whether the current capture item delivered its first frame yet.
+    pub producing_frames: bool,
+    /// Size of the captured frames, taken from the first frame and kept up to date by `SourceResized`.
+    pub source_size: Option<Vector2<i32>>,
     pub capture_frame_rate: CaptureFramerate,
+    pub cursor_capture_enabled: bool,
+    pub border_required: bool,
+    pub supports_border_toggle: bool,
+    pub capture_item_info: Option<CaptureItemInfo>,
+    /// The part of the item that is captured, `None` for all of it.
+    pub capture_region: Option<Rect<i32>>,
+    pub region_overlays: Vec<RegionOverlay>,
+    pub last_region: Option<CaptureRegion>,
+    pub last_target: Option<CaptureTarget>,
+    /// Monitors and windows to pick from without the Windows picker, as of the last refresh.
+    pub sources: Vec<SourceOption>,
+    pub selected_source: Option<SourceOption>,
+    #[cfg(all(feature = "recording", target_os = "windows"))]
+    pub recording: Option<RecordingHandle>,
+    /// Whether the stream server is serving the current capture. It stops by itself with the capture.
+    #[cfg(feature = "net")]
+    pub stream_server_running: bool,
+    pub error_message: Option<String>,
+    pub show_stats: bool,
+    pub stats: StatsPane,
+    pub verbose_logging: bool,
+    pub scale_mode: ScaleMode,
+    /// How the preview places the frame in the window.
+    pub frame_fit: FrameFit,
+    /// Zoom of the preview relative to its fit, shown over it.
+    pub preview_zoom: f32,
+    /// Of the main window, kept up to date as it moves between monitors.
+    pub window_scale_factor: f32,
+    pub window_geometry: Option<WindowGeometry>,
+    /// Incremented with every settings change, so only the last of a burst of changes is saved.
+    pub settings_revision: u64,
 
     pub frame_data: Option<Bytes>,
     pub frame_dimensions: Vector2<i32>,
+    /// Bytes from one row of `frame_data` to the next. The viewer crops any padding, everything else packs the
+    /// rows first, see `packed_frame_data`.
+    pub frame_stride: usize,
+    /// Incremented with every received frame, so the viewer knows when to upload a new image.
+    pub frame_generation: u64,
     pub frame_format: PixelFormat,
+    /// What changed since the previous frame, `None` if unknown. See `FrameViewer::dirty_rects`.
+    pub frame_dirty_rects: Option<Vec<Rect<i32>>>,
+    /// Sequence of the last received frame, to tell whether the dirty rects follow on from the shown frame.
+    pub frame_sequence: Option<u64>,
+}
+
+impl MutableState {
+    /// The shown frame as tightly packed RGBA rows, e.g. for saving it.
+    fn packed_frame_data(&self) -> Option<Bytes> {
+        let frame_data = self.frame_data.as_ref()?;
+        let row_bytes = PixelFormat::RGBA8.row_bytes(self.frame_dimensions.x.max(0) as usize);
+        if self.frame_stride <= row_bytes {
+            return Some(frame_data.clone());
+        }
+        let rows = self.frame_dimensions.y.max(0) as usize;
+        Some(pack_rows(frame_data, row_bytes, self.frame_stride, rows).into())
+    }
 }
 
 #[derive(Debug)]
 pub(crate) struct App {
-    capture: Arc<Mutex<PlatformCaptureProvider>>,
+    capture: CaptureHandle,
+    settings: Settings,
+    logging: Arc<Logging>,
+    /// Whether verbose logging was requested on the command line.
+    verbose_logging: bool,
+    /// Serves every capture if set.
+    #[cfg(feature = "net")]
+    serve: Option<StreamServerOptions>,
 }
 
 impl App {
     const APP_TITLE: &'static str = "loki";
+    const STATS_INTERVAL: Duration = Duration::from_millis(500);
+    const SETTINGS_SAVE_DELAY: Duration = Duration::from_secs(1);
 
+    /// Applies the settings to the provider and hands it to the capture thread.
     pub fn new(
-        capture: Arc<Mutex<PlatformCaptureProvider>>,
+        mut provider: PlatformCaptureProvider,
+        settings: Settings,
+        logging: Arc<Logging>,
+        verbose_logging: bool,
+        serve: ServeArgs,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self { capture })
+        provider.set_cursor_capture(settings.cursor_capture_enabled)?;
+        if PlatformCaptureProvider::supports_border_toggle() {
+            provider.set_border_required(settings.border_required)?;
+        }
+        provider.set_output_scale(settings.scale_mode.into());
+        let capture = CaptureHandle::spawn(provider, settings.framerate())?;
+        #[cfg(not(feature = "net"))]
+        let _ = serve;
+        Ok(Self {
+            capture,
+            settings,
+            logging,
+            verbose_logging,
+            #[cfg(feature = "net")]
+            serve: serve.options(),
+        })
+    }
+
+    fn create_frame_receiver_subscription(
+        data: &FrameReceiverSubData,
+    ) -> impl Stream<Item = CaptureEvent> + use<> {
+        tracing::info!("Creating frame receiver sub: {}", data.stream_name);
+        let capture = data.capture.clone();
+        // The preview only ever shows the newest frame, so frames it didn't get to are skipped rather than queued.
+        let created = async move { capture.create_latest_frame_handle().await };
+        stream::once(created).flat_map(|created| match created {
+            Ok(stream) => Either::Left(stream),
+            Err(err) => {
+                tracing::error!("Failed to create stream: {}", err);
+                // Ending the capture reports the error, and leaves the UI ready to start over.
+                let reason = match err {
+                    HandleError::CaptureError(err) => EndReason::Failed(Arc::new(err)),
+                    HandleError::Closed => EndReason::SourceClosed,
+                };
+                Either::Right(stream::iter([CaptureEvent::Ended(reason)]))
+            }
+        })
+    }
+
+    /// Serves the capture from a stream of its own until the capture stops, reporting frames the clients of the
+    /// server are too slow for along the way.
+    #[cfg(feature = "net")]
+    fn serve_capture(capture: CaptureHandle, options: StreamServerOptions) -> Task<Message> {
+        let started = async move {
+            let stream = match capture.create_stream().await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    return Err(format!("Failed to create stream to serve: {}", err));
+                }
+            };
+            let drops = Self::dropped_frames(&stream);
+            match StreamServer::start(options, stream).await {
+                Ok(server) => Ok((server, drops)),
+                Err(err) => Err(format!("Failed to start stream server: {}", err)),
+            }
+        };
+        Task::stream(stream::once(started).flat_map(|started| match started {
+            Ok((mut server, drops)) => {
+                let stopped = async move { server.wait().await }.boxed().shared();
+                let stopped_message = stopped.clone().map(|()| Message::StreamServerStopped(None));
+                Either::Left(drops.take_until(stopped).chain(stream::once(stopped_message)))
+            }
+            Err(err) => Either::Right(stream::iter([Message::StreamServerStopped(Some(err))])),
+        }))
+    }
+
+    /// Reports every frame the stream had to drop because its consumer was too slow.
+    #[cfg(all(feature = "net", target_os = "windows"))]
+    fn dropped_frames(stream: &PlatformCaptureStream) -> BoxStream<'static, Message> {
+        stream::unfold(stream.watch_stats(), |mut stats| async move {
+            stats.changed().await.ok()?;
+            let dropped = stats.borrow_and_update().dropped_full;
+            Some((Message::StreamServerDroppingFrames(dropped), stats))
+        })
+        .boxed()
+    }
+
+    /// The mock provider doesn't keep stream stats, so there is nothing to report.
+    #[cfg(all(feature = "net", not(target_os = "windows")))]
+    fn dropped_frames(_stream: &PlatformCaptureStream) -> BoxStream<'static, Message> {
+        stream::empty().boxed()
+    }
+
+    /// What is being captured, e.g. "Capturing: Firefox — 1920×1080", at the size of the latest frame once one
+    /// arrived.
+    fn capture_status(state: &MutableState, info: &CaptureItemInfo) -> String {
+        let size = state.source_size.unwrap_or(info.size);
+        let status = format!("Capturing: {} — {}×{}", info.display_name, size.x, size.y);
+        match state.capture_region {
+            Some(region) => format!(
+                "{}, region {}×{} at {}, {}",
+                status, region.size.x, region.size.y, region.position.x, region.position.y
+            ),
+            None => status,
+        }
+    }
+
+    async fn save_snapshot(path: PathBuf, frame_data: Bytes, size: Vector2<i32>) -> Message {
+        let format = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(ImageFileFormat::from_extension)
+            .unwrap_or(ImageFileFormat::Png);
+
+        let encoded =
+            tokio::task::spawn_blocking(move || encode_rgba(frame_data.to_vec(), size, format))
+                .await;
+        let encoded = match encoded {
+            Ok(Ok(encoded)) => encoded,
+            Ok(Err(err)) => return Message::Error(format!("Failed to encode snapshot: {}", err)),
+            Err(err) => return Message::Error(format!("Snapshot encoding task failed: {}", err)),
+        };
+
+        match tokio::fs::write(&path, encoded).await {
+            Ok(()) => Message::SnapshotSaved(path),
+            Err(err) => Message::Error(format!("Failed to write snapshot: {}", err)),
+        }
+    }
+
+    fn current_settings(state: &MutableState) -> Settings {
+        Settings {
+            version: Settings::CURRENT_VERSION,
+            framerate: state.capture_frame_rate.to_string(),
+            cursor_capture_enabled: state.cursor_capture_enabled,
+            border_required: state.border_required,
+            window: state.window_geometry,
+            scale_mode: state.scale_mode.into(),
+            preview_fit: state.frame_fit.into(),
+            last_region: state.last_region.clone(),
+            last_target: state.last_target.clone().map(Into::into),
+        }
+    }
+
+    /// The presets, and the current framerate if it is a custom one, e.g. from the settings file.
+    fn framerate_options(current: CaptureFramerate) -> Vec<CaptureFramerate> {
+        let mut options = CaptureFramerate::ALL.to_vec();
+        if !options.contains(&current) {
+            options.push(current);
+            options.sort_by_key(CaptureFramerate::to_frametime_ticks);
+            options.reverse();
+        }
+        options
     }
 
-    fn create_frame_receiver_subscription(data: &FrameReceiverSubData) -> PlatformCaptureStream {
-        tracing::info!("Creating frame receiver sub with framerate: {}", data.framerate);
-        data.capture
-            .blocking_lock()
-            .create_stream(data.framerate)
-            .expect("Failed to create stream!")
+    fn is_region_overlay(state: &MutableState, window: window::Id) -> bool {
+        state.region_overlays.iter().any(|overlay| overlay.window == window)
+    }
+
+    fn region_overlay_settings(monitor: &MonitorSource) -> window::Settings {
+        // Logical coordinates of the monitor's own scale. Only where the window opens matters, as it goes
+        // fullscreen on that monitor right after.
+        let size = monitor.size.to_logical(monitor.scale_factor);
+        let position = monitor.position.to_logical(monitor.scale_factor);
+        window::Settings {
+            size: iced::Size::new(size.x, size.y),
+            position: window::Position::Specific(iced::Point::new(position.x, position.y)),
+            decorations: false,
+            transparent: true,
+            resizable: false,
+            level: window::Level::AlwaysOnTop,
+            exit_on_close_request: false,
+            ..window::Settings::default()
+        }
+    }
+
+    fn close_region_overlays(state: &mut MutableState) -> Task<Message> {
+        Task::batch(state.region_overlays.drain(..).map(|overlay| window::close(overlay.window)))
+    }
+
+    fn region_overlay_view(overlay: &RegionOverlay) -> Element<'_, Message> {
+        let hint = match overlay.selection {
+            Some(selection) => {
+                let region = overlay.to_region(selection);
+                format!("{} x {}", region.width, region.height)
+            }
+            None => "Drag to select a region, Esc to cancel".to_string(),
+        };
+        let window = overlay.window;
+        stack![
+            region_picker::region_picker(
+                move |selection| Message::RegionDragged(window, selection),
+                move |selection| Message::RegionSelected(window, selection),
+                Message::CancelRegionPick,
+            ),
+            container(container(text(hint)).padding(8).style(container::dark))
+                .padding(20)
+                .center_x(Length::Fill),
+        ]
+        .into()
+    }
+
+    /// Saves the settings once they stop changing for a moment, as window events come in bursts.
+    fn schedule_settings_save(state: &mut MutableState) -> Task<Message> {
+        state.settings_revision = state.settings_revision.wrapping_add(1);
+        let revision = state.settings_revision;
+        Task::future(async move {
+            tokio::time::sleep(Self::SETTINGS_SAVE_DELAY).await;
+            Message::SaveSettings(revision)
+        })
+    }
+
+    #[cfg(all(feature = "recording", target_os = "windows"))]
+    fn record_button(state: &MutableState) -> widget::Button<'_, Message> {
+        match &state.recording {
+            Some(_) => button("Stop Recording").on_press(Message::StopRecording),
+            None => button("Record").on_press_maybe(
+                (state.capturing && state.frame_data.is_some()).then_some(Message::StartRecording),
+            ),
+        }
     }
 
     pub fn run(self) -> crate::Result<()> {
@@ -107,20 +561,75 @@ impl Program for App {
     }
 
     fn window(&self) -> Option<window::Settings> {
-        Some(window::Settings::default())
+        let mut settings = window::Settings::default();
+        if let Some(geometry) = self.settings.window {
+            settings.size = iced::Size::new(geometry.width, geometry.height);
+            if let Some((x, y)) = geometry.position {
+                settings.position = window::Position::Specific(iced::Point::new(x, y));
+            }
+        }
+        Some(settings)
+    }
+
+    fn theme(&self, state: &Self::State, window: window::Id) -> Option<Self::Theme> {
+        // Overlays are transparent, so the screen shows through everything the region picker doesn't dim.
+        Self::is_region_overlay(state, window).then(|| {
+            iced::Theme::custom(
+                "Region picker".to_string(),
+                iced::theme::Palette {
+                    background: Color::TRANSPARENT,
+                    ..iced::Theme::Dark.palette()
+                },
+            )
+        })
     }
 
     fn boot(&self) -> (Self::State, Task<Self::Message>) {
         (
             MutableState {
                 capturing: false,
+                paused: false,
+                producing_frames: false,
+                source_size: None,
                 active_window_handle: None,
-                capture_frame_rate: CaptureFramerate::FPS60,
+                capture_frame_rate: self.settings.framerate(),
+                cursor_capture_enabled: self.settings.cursor_capture_enabled,
+                border_required: self.settings.border_required,
+                supports_border_toggle: PlatformCaptureProvider::supports_border_toggle(),
+                capture_item_info: None,
+                capture_region: None,
+                region_overlays: Vec::new(),
+                last_region: self.settings.last_region.clone(),
+                last_target: self.settings.last_target.clone().map(Into::into),
+                sources: Vec::new(),
+                selected_source: None,
+                #[cfg(all(feature = "recording", target_os = "windows"))]
+                recording: None,
+                #[cfg(feature = "net")]
+                stream_server_running: false,
+                error_message: None,
+                show_stats: false,
+                stats: StatsPane::default(),
+                verbose_logging: self.verbose_logging,
+                scale_mode: self.settings.scale_mode.into(),
+                frame_fit: self.settings.preview_fit.into(),
+                preview_zoom: 1.0,
+                window_scale_factor: 1.0,
+                window_geometry: self.settings.window,
+                settings_revision: 0,
                 frame_data: None,
                 frame_dimensions: Vector2::new(0, 0),
+                frame_stride: 0,
+                frame_generation: 0,
                 frame_format: PixelFormat::BGRA8,
+                frame_dirty_rects: None,
+                frame_sequence: None,
+            },
+            if self.settings.last_target.is_some() {
+                Task::done(Message::ResumeLastCapture)
+            } else {
+                Task::none()
             },
-            Task::none(),
         )
     }
 
@@ -129,24 +638,61 @@ impl Program for App {
 
         if state.capturing {
             subscriptions.push(
-                Subscription::<Frame>::run_with(
+                Subscription::<CaptureEvent>::run_with(
                     FrameReceiverSubData {
                         capture: self.capture.clone(),
-                        framerate: state.capture_frame_rate,
                         stream_name: "frame-receiver",
                     },
                     Self::create_frame_receiver_subscription,
                 )
-                .map(Message::FrameReceived),
+                .map(Message::from),
             );
+
+            if state.show_stats {
+                subscriptions
+                    .push(iced::time::every(Self::STATS_INTERVAL).map(|_| Message::StatsTick));
+            }
         }
         subscriptions.push(iced::window::open_events().map(Message::WindowOpened));
+        subscriptions.push(iced::event::listen_with(|event, _status, window| match event {
+            iced::Event::Window(window::Event::Resized(size)) => {
+                Some(Message::WindowResized(window, size))
+            }
+            iced::Event::Window(window::Event::Moved(position)) => {
+                Some(Message::WindowMoved(window, position))
+            }
+            _ => None,
+        }));
+        // Only fires for key presses that nothing else, like a text input, took.
+        subscriptions.push(iced::keyboard::on_key_press(|key, modifiers| {
+            use iced::keyboard::{Key, key::Named};
+
+            match key.as_ref() {
+                Key::Character(c) if modifiers.command() && c.eq_ignore_ascii_case("c") => {
+                    Some(Message::CopyFrameToClipboard)
+                }
+                Key::Character(c) if modifiers.command() && c.eq_ignore_ascii_case("s") => {
+                    Some(Message::TakeScreenshot)
+                }
+                Key::Named(Named::F9) => Some(Message::ToggleCapture),
+                Key::Named(Named::F10) => Some(Message::SaveSnapshot),
+                Key::Named(Named::Escape) => Some(Message::StopCapture),
+                _ => None,
+            }
+        }));
+        #[cfg(all(feature = "global-hotkey", target_os = "windows"))]
+        subscriptions.push(
+            Subscription::run(global_hotkey::toggle_capture_presses)
+                .map(|()| Message::ToggleCapture),
+        );
 
         Subscription::batch(subscriptions)
     }
 
     fn update(&self, state: &mut Self::State, message: Self::Message) -> Task<Self::Message> {
         match message {
+            // The picker is parented to the main window, so the handles of overlays are of no use.
+            Message::WindowOpened(id) if Self::is_region_overlay(state, id) => Task::none(),
             Message::WindowOpened(id) => {
                 let fetch_id_task =
                     iced::window::raw_id::<Message>(id).map(Message::WindowIdFetched);
@@ -154,8 +700,33 @@ impl Program for App {
             }
             Message::WindowIdFetched(id) => {
                 state.active_window_handle = Some(id);
+                state.window_scale_factor = platform::window_scale_factor(id);
+                // Listed once the app's own window is known, so it can be left out.
+                Task::done(Message::RefreshSources)
+            }
+            Message::WindowResized(id, _) | Message::WindowMoved(id, _)
+                if Self::is_region_overlay(state, id) =>
+            {
                 Task::none()
             }
+            Message::WindowResized(_, size) => {
+                let position = state.window_geometry.and_then(|geometry| geometry.position);
+                state.window_geometry =
+                    Some(WindowGeometry { width: size.width, height: size.height, position });
+                Self::schedule_settings_save(state)
+            }
+            Message::WindowMoved(_, position) => {
+                let geometry = state.window_geometry.get_or_insert_with(|| {
+                    let size = window::Settings::default().size;
+                    WindowGeometry { width: size.width, height: size.height, position: None }
+                });
+                geometry.position = Some((position.x, position.y));
+                // The window may have moved to a monitor with a different scale.
+                if let Some(handle) = state.active_window_handle {
+                    state.window_scale_factor = platform::window_scale_factor(handle);
+                }
+                Self::schedule_settings_save(state)
+            }
             Message::StartCapture => {
                 let window_handle = match state.active_window_handle {
                     Some(handle) => handle,
@@ -188,77 +759,556 @@ impl Program for App {
                     }
                 };
 
-                Task::done(Message::TryStartCapture(capture_item))
+                // Picked outside the list, even if it happens to be in it.
+                state.selected_source = None;
+                Task::done(Message::TryStartCapture(capture_item, None))
             }
-            Message::TryStartCapture(capture_item) => match self.capture.try_lock() {
-                Ok(mut capture) => {
-                    // Lock acquired on main thread. It's safe to call COM methods.
-                    if let Err(err) = capture.set_capture_item(capture_item) {
+            Message::TryStartCapture(capture_item, region) => {
+                state.capture_region = region;
+                // Reset here rather than once started, as the first frame can arrive before the start completes.
+                state.producing_frames = false;
+                // The preview is replaced until then, which loses its zoom.
+                state.preview_zoom = 1.0;
+                state.source_size = None;
+                let capture = self.capture.clone();
+                // A running capture switches to the new item by itself, keeping the stream alive.
+                let start = !state.capturing;
+                Task::future(async move {
+                    if let Err(err) = capture.set_item(capture_item, region).await {
+                        return Message::Error(format!("Failed to set capture item: {}", err));
+                    }
+                    let started = if start { capture.start().await } else { Ok(()) };
+                    if let Err(err) = started {
+                        return Message::Error(format!("Failed to start capture: {}", err));
+                    }
+                    let info = match capture.capture_item_info().await {
+                        Ok(info) => info,
+                        Err(err) => {
+                            return Message::Error(format!("Failed to start capture: {}", err));
+                        }
+                    };
+                    // Region captures are repeated through their own setting.
+                    let target = match region {
+                        Some(_) => None,
+                        None => capture.capture_target().await.ok().flatten(),
+                    };
+                    Message::CaptureStarted(info, target)
+                })
+            }
+            Message::CaptureStarted(capture_item_info, target) => {
+                if let Some(info) = &capture_item_info {
+                    tracing::info!("Capturing {}, native handle: {:?}", info, info.native_handle);
+                }
+                state.capturing = true;
+                state.paused = false;
+                state.capture_item_info = capture_item_info;
+                state.error_message = None;
+                state.stats.reset();
+                let save = match target {
+                    Some(target) if state.last_target.as_ref() != Some(&target) => {
+                        state.last_target = Some(target);
+                        Self::schedule_settings_save(state)
+                    }
+                    _ => Task::none(),
+                };
+                #[cfg(feature = "net")]
+                if let Some(options) = self.serve.filter(|_| !state.stream_server_running) {
+                    state.stream_server_running = true;
+                    return Task::batch([save, Self::serve_capture(self.capture.clone(), options)]);
+                }
+                save
+            }
+            Message::ToggleCapture => match state.capturing {
+                true => Task::done(Message::StopCapture),
+                false => Task::done(Message::StartCapture),
+            },
+            // Esc stops capture, but is also pressed when nothing is being captured.
+            Message::StopCapture if !state.capturing => Task::none(),
+            Message::StopCapture => Task::done(Message::TryStopCapture),
+            Message::TryStopCapture => {
+                let capture = self.capture.clone();
+                Task::future(async move {
+                    if let Err(err) = capture.stop().await {
+                        tracing::error!("Failed to stop capture: {}", err);
+                    }
+                    Message::CaptureStopped
+                })
+            }
+            Message::TogglePause => {
+                let capture = self.capture.clone();
+                let pause = !state.paused;
+                Task::future(async move {
+                    let (result, action) = match pause {
+                        true => (capture.pause().await, "pause"),
+                        false => (capture.resume().await, "resume"),
+                    };
+                    match result {
+                        Ok(()) => Message::CapturePaused(pause),
+                        Err(err) => {
+                            Message::Error(format!("Failed to {} capture: {}", action, err))
+                        }
+                    }
+                })
+            }
+            Message::CapturePaused(paused) => {
+                state.paused = paused;
+                Task::none()
+            }
+            Message::CaptureStopped => {
+                state.capturing = false;
+                state.paused = false;
+                state.capture_item_info = None;
+                #[cfg(all(feature = "recording", target_os = "windows"))]
+                if state.recording.is_some() {
+                    return Task::done(Message::StopRecording);
+                }
+                Task::none()
+            }
+            Message::FrameRateSelected(rate) => {
+                if let Err(err) = self.capture.set_framerate(rate) {
+                    return Task::done(Message::Error(format!("Failed to set framerate: {}", err)));
+                }
+                state.capture_frame_rate = rate;
+                Self::schedule_settings_save(state)
+            }
+            Message::FrameFitSelected(fit) => {
+                // The viewer resets its zoom along with the fit.
+                state.frame_fit = fit;
+                state.preview_zoom = 1.0;
+                Self::schedule_settings_save(state)
+            }
+            Message::PreviewZoomed(zoom) => {
+                state.preview_zoom = zoom;
+                Task::none()
+            }
+            Message::CursorCaptureToggled(enabled) => {
+                // The provider keeps the setting even if applying it to the running capture fails.
+                state.cursor_capture_enabled = enabled;
+                let capture = self.capture.clone();
+                let apply = Task::future(async move {
+                    match capture.set_cursor_capture(enabled).await {
+                        Ok(()) => None,
+                        Err(err) => {
+                            Some(Message::Error(format!("Failed to set cursor capture: {}", err)))
+                        }
+                    }
+                })
+                .and_then(Task::done);
+                Task::batch([apply, Self::schedule_settings_save(state)])
+            }
+            Message::BorderToggled(required) => {
+                state.border_required = required;
+                let capture = self.capture.clone();
+                let apply = Task::future(async move {
+                    match capture.set_border_required(required).await {
+                        Ok(()) => None,
+                        Err(err) => Some(Message::Error(format!("Failed to set border: {}", err))),
+                    }
+                })
+                .and_then(Task::done);
+                Task::batch([apply, Self::schedule_settings_save(state)])
+            }
+            Message::ToggleVerboseLogging(verbose) => {
+                if let Err(err) = self.capture.set_trace_frames(verbose) {
+                    return Task::done(Message::Error(format!(
+                        "Failed to set frame tracing: {}",
+                        err
+                    )));
+                }
+                self.logging.set_verbose(verbose);
+                state.verbose_logging = verbose;
+                Task::none()
+            }
+            Message::SaveSnapshot => {
+                let frame_data = match state.packed_frame_data() {
+                    Some(frame_data) => frame_data,
+                    None => {
+                        return Task::done(Message::Error(format!("No frame available to save")));
+                    }
+                };
+                let frame_dimensions = state.frame_dimensions;
+
+                Task::future(async move {
+                    let file = rfd::AsyncFileDialog::new()
+                        .set_title("Save snapshot")
+                        .set_file_name("snapshot.png")
+                        .add_filter("PNG image", &["png"])
+                        .add_filter("JPEG image", &["jpg", "jpeg"])
+                        .save_file()
+                        .await?; // The user cancelled the dialog
+                    Some(
+                        Self::save_snapshot(
+                            file.path().to_path_buf(),
+                            frame_data,
+                            frame_dimensions,
+                        )
+                        .await,
+                    )
+                })
+                .and_then(Task::done)
+            }
+            Message::TakeScreenshot => {
+                let frame_data = match state.packed_frame_data() {
+                    Some(frame_data) => frame_data,
+                    None => {
+                        return Task::done(Message::Error(
+                            "No frame available to save".to_string(),
+                        ));
+                    }
+                };
+                let path = match default_snapshot_path(ImageFileFormat::Png) {
+                    Ok(path) => path,
+                    Err(err) => {
                         return Task::done(Message::Error(format!(
-                            "Failed to set capture item: {}",
+                            "Failed to find the Pictures folder: {}",
                             err
                         )));
                     }
-                    if let Err(err) = capture.start_capture() {
-                        return Task::done(Message::Error(format!(
-                            "Failed to start capture: {}",
+                };
+                Task::future(Self::save_snapshot(path, frame_data, state.frame_dimensions))
+            }
+            Message::SnapshotSaved(path) => {
+                tracing::info!("Snapshot saved to {}", path.display());
+                Task::none()
+            }
+            Message::CopyFrameToClipboard => {
+                let frame_data = match state.packed_frame_data() {
+                    Some(frame_data) => frame_data,
+                    None => {
+                        return Task::done(Message::Error(
+                            "No frame available to copy".to_string(),
+                        ));
+                    }
+                };
+                let owner = match state.active_window_handle {
+                    Some(handle) => handle,
+                    None => {
+                        return Task::done(Message::Error("No active window handle".to_string()));
+                    }
+                };
+                let frame_dimensions = state.frame_dimensions;
+
+                Task::future(async move {
+                    let copied = tokio::task::spawn_blocking(move || {
+                        clipboard::copy_rgba_image(owner, &frame_data, frame_dimensions)
+                    })
+                    .await;
+                    match copied {
+                        Ok(Ok(())) => Message::FrameCopied,
+                        Ok(Err(err)) => Message::Error(format!(
+                            "Failed to copy frame to the clipboard: {}",
                             err
-                        )));
+                        )),
+                        Err(err) => Message::Error(format!("Clipboard task failed: {}", err)),
                     }
+                })
+            }
+            Message::FrameCopied => {
+                tracing::info!("Frame copied to the clipboard");
+                Task::none()
+            }
+            Message::SaveSettings(revision) => {
+                if revision != state.settings_revision {
+                    // Changed again in the meantime, the newer save takes care of it.
+                    return Task::none();
+                }
 
-                    Task::done(Message::CaptureStarted)
+                let settings = Self::current_settings(state);
+                Task::future(async move {
+                    match tokio::task::spawn_blocking(move || settings.save()).await {
+                        Ok(Ok(())) => {
+                            tracing::debug!("Settings saved.");
+                            None
+                        }
+                        Ok(Err(err)) => {
+                            Some(Message::Error(format!("Failed to save settings: {}", err)))
+                        }
+                        Err(err) => Some(Message::Error(format!("Settings task failed: {}", err))),
+                    }
+                })
+                .and_then(Task::done)
+            }
+            Message::PickRegion => {
+                if !state.region_overlays.is_empty() {
+                    return Task::none();
                 }
-                Err(_) => {
-                    // Could not get lock, wait for it to be free and try again.
-                    let capture_arc = self.capture.clone();
-                    Task::future(async move {
-                        // Asynchronously wait for lock to become available.
-                        let _lock = capture_arc.lock().await;
+                let monitors = platform::monitors();
+                if monitors.is_empty() {
+                    return Task::done(Message::Error(
+                        "No monitors to pick a region on".to_string(),
+                    ));
+                }
+                let tasks: Vec<_> = monitors
+                    .into_iter()
+                    .map(|monitor| {
+                        let (id, open) = window::open(Self::region_overlay_settings(&monitor));
+                        state.region_overlays.push(RegionOverlay {
+                            window: id,
+                            monitor_name: monitor.name,
+                            scale_factor: monitor.scale_factor,
+                            selection: None,
+                        });
+                        open.discard()
+                            .chain(window::set_mode(id, window::Mode::Fullscreen))
+                            .chain(window::gain_focus(id))
                     })
-                    .map(move |_| Message::TryStartCapture(capture_item.clone()))
+                    .collect();
+                Task::batch(tasks)
+            }
+            Message::RegionDragged(id, selection) => {
+                // Only one selection at a time, so dragging on another monitor replaces it.
+                for overlay in &mut state.region_overlays {
+                    overlay.selection = (overlay.window == id).then_some(selection);
                 }
-            },
-            Message::CaptureStarted => {
-                state.capturing = true;
                 Task::none()
             }
-            Message::StopCapture => Task::done(Message::TryStopCapture),
-            Message::TryStopCapture => match self.capture.try_lock() {
-                Ok(mut capture) => {
-                    if let Err(err) = capture.stop_capture() {
-                        tracing::error!("Failed to stop capture: {}", err);
+            Message::RegionSelected(id, selection) => {
+                let Some(overlay) =
+                    state.region_overlays.iter().find(|overlay| overlay.window == id)
+                else {
+                    return Task::none();
+                };
+                let region = overlay.to_region(selection);
+                Self::close_region_overlays(state)
+                    .chain(Task::done(Message::StartRegionCapture(region)))
+            }
+            Message::CancelRegionPick => Self::close_region_overlays(state),
+            Message::StartRegionCapture(region) => {
+                // Monitors are looked up again, as the saved region may be from an earlier run.
+                let capture_item = match platform::monitor_capture_item(&region.monitor) {
+                    Ok(item) => item,
+                    Err(err) => {
+                        return Task::done(Message::Error(format!(
+                            "Failed to create capture item for {}: {}",
+                            region.monitor, err
+                        )));
                     }
+                };
 
-                    Task::done(Message::CaptureStopped)
+                let rect = region.rect();
+                state.last_region = Some(region);
+                state.selected_source = None;
+                Task::batch([
+                    Task::done(Message::TryStartCapture(capture_item, Some(rect))),
+                    Self::schedule_settings_save(state),
+                ])
+            }
+            Message::RepeatLastRegion => match state.last_region.clone() {
+                Some(region) => Task::done(Message::StartRegionCapture(region)),
+                None => Task::none(),
+            },
+            Message::RefreshSources => {
+                let own_window = state.active_window_handle;
+                // Looking up every window's process can take a moment.
+                Task::future(async move {
+                    match tokio::task::spawn_blocking(move || SourceOption::enumerate(own_window))
+                        .await
+                    {
+                        Ok(sources) => Message::SourcesEnumerated(sources),
+                        Err(err) => {
+                            Message::Error(format!("Failed to list capture sources: {}", err))
+                        }
+                    }
+                })
+            }
+            Message::SourcesEnumerated(sources) => {
+                tracing::debug!("Found {} capture sources", sources.len());
+                state.sources = sources;
+                Task::none()
+            }
+            Message::SourceSelected(source) => match source.to_capture_item() {
+                Ok(capture_item) => {
+                    state.selected_source = Some(source);
+                    Task::done(Message::TryStartCapture(capture_item, None))
                 }
-                Err(_) => {
-                    // Could not get lock, wait for it to be free and try again.
-                    let capture_arc = self.capture.clone();
-                    Task::future(async move {
-                        // Asynchronously wait for lock to become available.
-                        let _lock = capture_arc.lock().await;
-                    })
-                    .map(move |_| Message::TryStopCapture)
+                // The source may have gone away since the list was refreshed.
+                Err(err) => {
+                    tracing::warn!("{}", err);
+                    state.error_message = Some(err);
+                    state.selected_source = None;
+                    Task::done(Message::RefreshSources)
                 }
             },
-            Message::CaptureStopped => {
-                state.capturing = false;
+            Message::ResumeLastCapture => {
+                let Some(target) = &state.last_target else {
+                    return Task::none();
+                };
+                match platform::target_capture_item(target) {
+                    Ok(capture_item) => {
+                        state.selected_source = None;
+                        Task::done(Message::TryStartCapture(capture_item, None))
+                    }
+                    // Windows close and monitors get unplugged between runs, which is no reason to fail.
+                    Err(err) => {
+                        tracing::warn!("Failed to resume the last capture: {}", err);
+                        state.error_message = Some(format!(
+                            "Could not find the last captured {}, pick it again.",
+                            target
+                        ));
+                        Task::none()
+                    }
+                }
+            }
+            Message::ToggleStats => {
+                state.show_stats = !state.show_stats;
                 Task::none()
             }
-            Message::FrameRateSelected(rate) => {
-                state.capture_frame_rate = rate;
+            Message::StatsTick => {
+                let capture = self.capture.clone();
+                Task::future(async move { capture.stats().await.ok() })
+                    .and_then(|stats| Task::done(Message::StatsReceived(stats, Instant::now())))
+            }
+            Message::StatsReceived(stats, received) => {
+                state.stats.update(stats, received);
+                tracing::debug!("Capture FPS: {:.1}", state.stats.capture_fps());
                 Task::none()
             }
-            Message::FrameReceived(frame) => {
-                // Frame is already ensured to be RGBA by the provider
-                state.frame_format = frame.format;
+            #[cfg(all(feature = "recording", target_os = "windows"))]
+            Message::StartRecording => {
+                if state.frame_data.is_none() {
+                    return Task::done(Message::Error(format!("No frames to record yet")));
+                }
+
+                let path = match default_recording_path() {
+                    Ok(path) => path,
+                    Err(err) => {
+                        return Task::done(Message::Error(format!(
+                            "Failed to find the videos folder: {}",
+                            err
+                        )));
+                    }
+                };
+                let size = state.frame_dimensions;
+                let settings = RecorderSettings::new(
+                    size.x as u32,
+                    size.y as u32,
+                    state.capture_frame_rate.fps(),
+                );
+                match RecordingHandle::start(path, settings) {
+                    Ok(recording) => {
+                        tracing::info!("Recording to {}", recording.path().display());
+                        state.recording = Some(recording);
+                        Task::none()
+                    }
+                    Err(err) => {
+                        Task::done(Message::Error(format!("Failed to start recording: {}", err)))
+                    }
+                }
+            }
+            #[cfg(all(feature = "recording", target_os = "windows"))]
+            Message::StopRecording => {
+                let recording = match state.recording.take() {
+                    Some(recording) => recording,
+                    None => return Task::none(),
+                };
+
+                // Finalizing drains the encoder, which shouldn't block the UI.
+                Task::future(async move {
+                    match tokio::task::spawn_blocking(move || recording.finish()).await {
+                        Ok(Ok(path)) => Message::RecordingStopped(path),
+                        Ok(Err(err)) => Message::Error(format!("Recording failed: {}", err)),
+                        Err(err) => Message::Error(format!("Recording task failed: {}", err)),
+                    }
+                })
+            }
+            #[cfg(all(feature = "recording", target_os = "windows"))]
+            Message::RecordingStopped(path) => {
+                tracing::info!("Recording saved to {}", path.display());
+                Task::none()
+            }
+            #[cfg(feature = "net")]
+            Message::StreamServerStopped(error) => {
+                state.stream_server_running = false;
+                match error {
+                    Some(err) => Task::done(Message::Error(err)),
+                    None => Task::none(),
+                }
+            }
+            #[cfg(feature = "net")]
+            Message::StreamServerDroppingFrames(dropped) => {
+                state.error_message = Some(format!(
+                    "Stream server is dropping frames, its clients are too slow ({} dropped)",
+                    dropped
+                ));
+                Task::none()
+            }
+            Message::FrameReceived(mut frame) => {
+                state.stats.record_frame(&frame, Instant::now());
+
+                #[cfg(all(feature = "recording", target_os = "windows"))]
+                let recording_failed = state
+                    .recording
+                    .as_ref()
+                    .is_some_and(|recording| recording.push_frame(frame.clone()).is_err());
+
+                // Dirty rects only describe the changes since the previous frame of the stream. After a gap, or
+                // without any, the viewer has to assume everything changed.
+                let follows_on = state.frame_sequence == frame.sequence.checked_sub(1)
+                    && state.frame_dimensions == frame.size;
+                let dirty_rects = std::mem::take(&mut frame.dirty_rects);
+                state.frame_dirty_rects =
+                    (follows_on && !dirty_rects.is_empty()).then_some(dirty_rects);
+                state.frame_sequence = Some(frame.sequence);
+
+                state.source_size.get_or_insert(frame.size);
                 state.frame_dimensions = frame.size;
-                state.frame_data = Some(frame.data);
+                state.frame_generation = state.frame_generation.wrapping_add(1);
+                // The viewer takes RGBA rows and crops their padding itself. The provider outputs RGBA unless
+                // configured otherwise, e.g. for Gray8, in which case the frame is expanded here.
+                if frame.format == PixelFormat::RGBA8 {
+                    state.frame_stride = frame.stride;
+                    state.frame_data = Some(frame.data);
+                } else {
+                    state.frame_stride = PixelFormat::RGBA8.row_bytes(frame.size.x.max(0) as usize);
+                    state.frame_data = Some(frame.into_tightly_packed_rgba());
+                }
+                state.frame_format = PixelFormat::RGBA8;
 
+                #[cfg(all(feature = "recording", target_os = "windows"))]
+                if recording_failed {
+                    // The recording thread stopped, finishing it reports why.
+                    return Task::done(Message::StopRecording);
+                }
+                Task::none()
+            }
+            Message::CaptureProducing => {
+                state.producing_frames = true;
+                Task::none()
+            }
+            Message::SourceResized(size) => {
+                tracing::info!("Capture source resized to {} x {}", size.x, size.y);
+                state.source_size = Some(size);
+                Task::none()
+            }
+            Message::CaptureFrameError(err) => {
+                tracing::warn!("Capture dropped a frame: {}", err);
+                state.error_message = Some(format!("Failed to process a frame: {}", err));
+                Task::none()
+            }
+            Message::CaptureEnded(reason) => {
+                tracing::warn!("Capture ended: {}", reason);
+                state.capturing = false;
+                state.producing_frames = false;
+                state.error_message = Some(format!("Capture ended: {}", reason));
+                if matches!(reason, EndReason::SourceClosed | EndReason::SourceLost) {
+                    // The last frame shows a window or monitor that isn't there anymore.
+                    state.frame_data = None;
+                    state.frame_dimensions = Vector2::new(0, 0);
+                    state.frame_dirty_rects = None;
+                    state.frame_sequence = None;
+                    state.source_size = None;
+                    state.capture_region = None;
+                }
+                // The provider still considers itself capturing, so stop it properly.
+                Task::done(Message::TryStopCapture)
+            }
+            Message::CaptureRecreated => {
+                tracing::info!("Capture pipeline recreated, frames will resume shortly.");
                 Task::none()
             }
             Message::Error(err) => {
                 tracing::error!("Error: {}", err);
+                state.error_message = Some(err);
                 Task::none()
             }
         }
@@ -267,51 +1317,135 @@ impl Program for App {
     fn view<'a>(
         &self,
         state: &'a Self::State,
-        _window: window::Id,
+        window: window::Id,
     ) -> Element<'a, Self::Message, Self::Theme, Self::Renderer> {
-        let control_row: Element<'a, Self::Message, Self::Theme, Self::Renderer> = container(
-            row([
-                if state.capturing {
-                    // Render a disabled button that shows the current value
-                    button(text(state.capture_frame_rate.to_string()))
-                        .style(iced::widget::button::secondary)
-                        .into()
-                } else {
-                    pick_list(
-                        CaptureFramerate::ALL,
-                        Some(state.capture_frame_rate),
-                        Message::FrameRateSelected,
-                    )
-                    .into()
-                },
-                button("Start Capture")
-                    .on_press_maybe(if state.capturing {
-                        None
-                    } else {
-                        Some(Message::StartCapture)
-                    })
-                    .into(),
-                button("Stop Capture")
-                    .on_press_maybe(if state.capturing { Some(Message::StopCapture) } else { None })
-                    .into(),
-            ])
-            .spacing(10),
-        )
-        .padding(10)
-        .center_x(Length::Fill)
-        .into();
-
-        let screen_share_preview = match &state.frame_data {
-            Some(frame_data) => container(frame_viewer::frame_viewer(
-                frame_data.clone(),
-                state.frame_dimensions.x as u32,
-                state.frame_dimensions.y as u32,
-            ))
-            .center(Length::Fill)
+        if let Some(overlay) = state.region_overlays.iter().find(|overlay| overlay.window == window)
+        {
+            return Self::region_overlay_view(overlay);
+        }
+
+        let controls = row([
+            // Takes effect right away, also while capturing.
+            pick_list(
+                Self::framerate_options(state.capture_frame_rate),
+                Some(state.capture_frame_rate),
+                Message::FrameRateSelected,
+            )
+            .into(),
+            pick_list(FrameFit::ALL, Some(state.frame_fit), Message::FrameFitSelected).into(),
+            pick_list(
+                state.sources.as_slice(),
+                state.selected_source.as_ref(),
+                Message::SourceSelected,
+            )
+            .placeholder("Capture source")
             .into(),
-            None => container(widget::text("No preview available.")).center(Length::Fill).into(),
+            button("Refresh").on_press(Message::RefreshSources).into(),
+            button("Pick Manually…").on_press(Message::StartCapture).into(),
+            button("Pick Region")
+                .on_press_maybe(state.region_overlays.is_empty().then_some(Message::PickRegion))
+                .into(),
+            button("Repeat Region")
+                .on_press_maybe(state.last_region.as_ref().map(|_| Message::RepeatLastRegion))
+                .into(),
+            button("Resume Capture")
+                .on_press_maybe(
+                    state
+                        .last_target
+                        .as_ref()
+                        .filter(|_| !state.capturing)
+                        .map(|_| Message::ResumeLastCapture),
+                )
+                .into(),
+            button("Stop Capture")
+                .on_press_maybe(if state.capturing { Some(Message::StopCapture) } else { None })
+                .into(),
+            button(if state.paused { "Resume" } else { "Pause" })
+                .on_press_maybe(state.capturing.then_some(Message::TogglePause))
+                .into(),
+            button("Save Snapshot")
+                .on_press_maybe(state.frame_data.as_ref().map(|_| Message::SaveSnapshot))
+                .into(),
+            button("Screenshot")
+                .on_press_maybe(state.frame_data.as_ref().map(|_| Message::TakeScreenshot))
+                .into(),
+            button("Copy Frame")
+                .on_press_maybe(state.frame_data.as_ref().map(|_| Message::CopyFrameToClipboard))
+                .into(),
+        ]);
+        #[cfg(all(feature = "recording", target_os = "windows"))]
+        let controls = controls.push(Self::record_button(state));
+        let on_border_toggled = state.supports_border_toggle.then_some(Message::BorderToggled);
+        let controls = controls
+            .push(
+                checkbox("Capture cursor", state.cursor_capture_enabled)
+                    .on_toggle(Message::CursorCaptureToggled),
+            )
+            .push(checkbox("Show border", state.border_required).on_toggle_maybe(on_border_toggled))
+            .push(
+                checkbox("Verbose logs", state.verbose_logging)
+                    .on_toggle(Message::ToggleVerboseLogging),
+            )
+            .push(
+                button(if state.show_stats { "Hide Stats" } else { "Show Stats" })
+                    .on_press(Message::ToggleStats),
+            );
+
+        let controls = match &state.capture_item_info {
+            Some(info) => controls.push(text(Self::capture_status(state, info))),
+            None => controls,
         };
 
-        column([control_row, screen_share_preview]).into()
+        let control_row: Element<'a, Self::Message, Self::Theme, Self::Renderer> =
+            container(controls.spacing(10).align_y(iced::Alignment::Center))
+                .padding(10)
+                .center_x(Length::Fill)
+                .into();
+
+        let screen_share_preview: Element<'a, Self::Message, Self::Theme, Self::Renderer> =
+            match &state.frame_data {
+                _ if state.capturing && !state.producing_frames => {
+                    container(widget::text("Waiting for first frame…")).center(Length::Fill).into()
+                }
+                Some(frame_data) => stack![
+                    container(
+                        frame_viewer::frame_viewer(
+                            frame_data.clone(),
+                            state.frame_dimensions.x as u32,
+                            state.frame_dimensions.y as u32,
+                            state.frame_generation,
+                            state.frame_fit,
+                        )
+                        .stride(state.frame_stride)
+                        .scale_factor(state.window_scale_factor)
+                        .dirty_rects(state.frame_dirty_rects.clone())
+                        .zoom_enabled(true)
+                        .on_zoom(Message::PreviewZoomed),
+                    )
+                    .center(Length::Fill),
+                    container(
+                        container(text(format!("{:.0}%", state.preview_zoom * 100.0)))
+                            .padding(4)
+                            .style(container::dark)
+                    )
+                    .padding(8)
+                    .align_right(Length::Fill)
+                    .align_bottom(Length::Fill),
+                ]
+                .into(),
+                None => {
+                    container(widget::text("No preview available.")).center(Length::Fill).into()
+                }
+            };
+
+        let mut content = column([control_row]);
+        if let Some(error_message) = &state.error_message {
+            content = content.push(container(text(error_message)).center_x(Length::Fill));
+        }
+
+        if state.show_stats && state.capturing {
+            return content.push(row([screen_share_preview, state.stats.view()])).into();
+        }
+        content.push(screen_share_preview).into()
     }
 }
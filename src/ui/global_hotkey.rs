@@ -0,0 +1,58 @@
+use futures::{Stream, channel::mpsc};
+use windows::Win32::UI::{
+    Input::KeyboardAndMouse::{
+        MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, RegisterHotKey, UnregisterHotKey, VK_F9,
+    },
+    WindowsAndMessaging::{GetMessageW, MSG, WM_HOTKEY},
+};
+
+/// The system-wide hotkey toggling capture. Plain F9 only works while the app has focus, so this one needs
+/// modifiers to stay out of the way of other apps.
+pub const TOGGLE_CAPTURE: &str = "Ctrl+Shift+F9";
+
+const HOTKEY_ID: i32 = 1;
+
+/// Yields on every press of the hotkey, wherever the focus is. If the hotkey can't be registered, e.g. because
+/// another app already has it, this only warns and the stream ends without yielding.
+pub fn toggle_capture_presses() -> impl Stream<Item = ()> {
+    let (tx, rx) = mpsc::unbounded();
+    let spawned =
+        std::thread::Builder::new().name("loki-global-hotkey".into()).spawn(move || listen(tx));
+    if let Err(err) = spawned {
+        tracing::warn!(
+            "Failed to spawn global hotkey thread, {} won't work: {}",
+            TOGGLE_CAPTURE,
+            err
+        );
+    }
+    rx
+}
+
+fn listen(tx: mpsc::UnboundedSender<()>) {
+    // Without a window, WM_HOTKEY goes to the queue of the thread that registered the hotkey.
+    let modifiers = MOD_CONTROL | MOD_SHIFT | MOD_NOREPEAT;
+    if let Err(err) = unsafe { RegisterHotKey(None, HOTKEY_ID, modifiers, VK_F9.0 as u32) } {
+        tracing::warn!(
+            "Failed to register the {} hotkey, it may be taken: {}",
+            TOGGLE_CAPTURE,
+            err
+        );
+        return;
+    }
+    tracing::info!("Registered the {} hotkey to toggle capture.", TOGGLE_CAPTURE);
+
+    let mut msg = MSG::default();
+    // Returns 0 for WM_QUIT and -1 on errors.
+    while unsafe { GetMessageW(&mut msg, None, 0, 0) }.0 > 0 {
+        if msg.message != WM_HOTKEY || msg.wParam.0 != HOTKEY_ID as usize {
+            continue;
+        }
+        if tx.unbounded_send(()).is_err() {
+            break;
+        }
+    }
+
+    if let Err(err) = unsafe { UnregisterHotKey(None, HOTKEY_ID) } {
+        tracing::warn!("Failed to unregister the {} hotkey: {}", TOGGLE_CAPTURE, err);
+    }
+}
@@ -1,2 +1,5 @@
 pub mod app;
-pub mod frame_viewer;
+#[cfg(all(feature = "global-hotkey", target_os = "windows"))]
+pub mod global_hotkey;
+pub mod region_picker;
+pub mod stats_pane;
@@ -0,0 +1,233 @@
+use iced::{
+    Border, Color, Element, Event, Length, Point, Rectangle, Size, advanced,
+    advanced::{
+        Clipboard, Shell, Widget,
+        layout::{self, Layout},
+        mouse, renderer,
+        widget::{Tree, tree},
+    },
+    keyboard,
+};
+
+/// Selections smaller than this in either direction, in logical pixels, are treated as accidental clicks.
+const MIN_SELECTION: f32 = 4.0;
+const DIM_COLOR: Color = Color::from_rgba(0.0, 0.0, 0.0, 0.4);
+const SELECTION_BORDER_COLOR: Color = Color::from_rgb(0.2, 0.6, 1.0);
+const SELECTION_BORDER_WIDTH: f32 = 2.0;
+
+/// Lets the user drag out a rectangle, dimming everything outside of it. Meant to fill a transparent overlay
+/// window. Rectangles are reported relative to the widget, in logical pixels.
+pub struct RegionPicker<'a, Message> {
+    on_drag: Box<dyn Fn(Rectangle) -> Message + 'a>,
+    on_select: Box<dyn Fn(Rectangle) -> Message + 'a>,
+    on_cancel: Message,
+}
+
+impl<'a, Message> RegionPicker<'a, Message> {
+    /// `on_drag` is called whenever the selection changes while dragging, `on_select` once the mouse is
+    /// released. Escape and right clicks call `on_cancel`.
+    pub fn new(
+        on_drag: impl Fn(Rectangle) -> Message + 'a,
+        on_select: impl Fn(Rectangle) -> Message + 'a,
+        on_cancel: Message,
+    ) -> Self {
+        Self { on_drag: Box::new(on_drag), on_select: Box::new(on_select), on_cancel }
+    }
+}
+
+pub fn region_picker<'a, Message>(
+    on_drag: impl Fn(Rectangle) -> Message + 'a,
+    on_select: impl Fn(Rectangle) -> Message + 'a,
+    on_cancel: Message,
+) -> RegionPicker<'a, Message> {
+    RegionPicker::new(on_drag, on_select, on_cancel)
+}
+
+#[derive(Default)]
+struct State {
+    drag_origin: Option<Point>,
+    /// Relative to the widget bounds.
+    selection: Option<Rectangle>,
+}
+
+impl State {
+    /// The rectangle spanned by the drag origin and `position`, limited to the bounds.
+    fn span(origin: Point, position: Point, bounds: Rectangle) -> Rectangle {
+        let clamp = |point: Point| {
+            Point::new(
+                (point.x - bounds.x).clamp(0.0, bounds.width),
+                (point.y - bounds.y).clamp(0.0, bounds.height),
+            )
+        };
+        let (start, end) = (clamp(origin), clamp(position));
+        Rectangle::new(
+            Point::new(start.x.min(end.x), start.y.min(end.y)),
+            Size::new((start.x - end.x).abs(), (start.y - end.y).abs()),
+        )
+    }
+}
+
+impl<Theme, Message, Renderer> Widget<Message, Theme, Renderer> for RegionPicker<'_, Message>
+where
+    Message: Clone,
+    Renderer: advanced::Renderer,
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn size(&self) -> Size<Length> {
+        Size::new(Length::Fill, Length::Fill)
+    }
+
+    fn update(
+        &mut self,
+        tree: &mut Tree,
+        event: &Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _renderer: &Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        _viewport: &Rectangle,
+    ) {
+        let state = tree.state.downcast_mut::<State>();
+        let bounds = layout.bounds();
+
+        match event {
+            Event::Keyboard(keyboard::Event::KeyPressed {
+                key: keyboard::Key::Named(keyboard::key::Named::Escape),
+                ..
+            })
+            | Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {
+                shell.publish(self.on_cancel.clone());
+                shell.capture_event();
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let Some(position) = cursor.position_over(bounds) else {
+                    return;
+                };
+                state.drag_origin = Some(position);
+                state.selection = None;
+                shell.capture_event();
+                shell.request_redraw();
+            }
+            Event::Mouse(mouse::Event::CursorMoved { position }) => {
+                let Some(origin) = state.drag_origin else {
+                    return;
+                };
+                let selection = State::span(origin, *position, bounds);
+                state.selection = Some(selection);
+                shell.publish((self.on_drag)(selection));
+                shell.request_redraw();
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                if state.drag_origin.take().is_none() {
+                    return;
+                }
+                match state.selection {
+                    Some(selection)
+                        if selection.width >= MIN_SELECTION
+                            && selection.height >= MIN_SELECTION =>
+                    {
+                        shell.publish((self.on_select)(selection));
+                    }
+                    _ => state.selection = None,
+                }
+                shell.capture_event();
+                shell.request_redraw();
+            }
+            _ => (),
+        }
+    }
+
+    fn mouse_interaction(
+        &self,
+        _tree: &Tree,
+        _layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &Renderer,
+    ) -> mouse::Interaction {
+        mouse::Interaction::Crosshair
+    }
+
+    fn layout(
+        &mut self,
+        _tree: &mut Tree,
+        _renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        layout::Node::new(limits.max())
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        _theme: &Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+    ) {
+        let bounds = layout.bounds();
+        let mut dim = |rect: Rectangle| {
+            if rect.width > 0.0 && rect.height > 0.0 {
+                renderer
+                    .fill_quad(renderer::Quad { bounds: rect, ..Default::default() }, DIM_COLOR);
+            }
+        };
+
+        let Some(selection) = tree.state.downcast_ref::<State>().selection else {
+            dim(bounds);
+            return;
+        };
+        let selection = selection + iced::Vector::new(bounds.x, bounds.y);
+
+        // Everything but the selection is dimmed, so the selected region shows as it will be captured.
+        let bottom = selection.y + selection.height;
+        let right = selection.x + selection.width;
+        dim(Rectangle::new(bounds.position(), Size::new(bounds.width, selection.y - bounds.y)));
+        dim(Rectangle::new(
+            Point::new(bounds.x, bottom),
+            Size::new(bounds.width, bounds.y + bounds.height - bottom),
+        ));
+        dim(Rectangle::new(
+            Point::new(bounds.x, selection.y),
+            Size::new(selection.x - bounds.x, selection.height),
+        ));
+        dim(Rectangle::new(
+            Point::new(right, selection.y),
+            Size::new(bounds.x + bounds.width - right, selection.height),
+        ));
+
+        renderer.fill_quad(
+            renderer::Quad {
+                bounds: selection,
+                border: Border {
+                    color: SELECTION_BORDER_COLOR,
+                    width: SELECTION_BORDER_WIDTH,
+                    ..Border::default()
+                },
+                ..Default::default()
+            },
+            Color::TRANSPARENT,
+        );
+    }
+}
+
+impl<'a, Message, Theme, Renderer> From<RegionPicker<'a, Message>>
+    for Element<'a, Message, Theme, Renderer>
+where
+    Message: Clone + 'a,
+    Renderer: advanced::Renderer + 'a,
+{
+    fn from(widget: RegionPicker<'a, Message>) -> Self {
+        Self::new(widget)
+    }
+}
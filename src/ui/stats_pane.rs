@@ -0,0 +1,130 @@
+use std::time::{Duration, Instant};
+
+use iced::{
+    Element, Length,
+    widget::{column, container, text},
+};
+use loki::capture_providers::shared::{CaptureStats, Frame, PixelFormat};
+
+use crate::ui::app::Message;
+
+/// Turns the cumulative counters of the provider into rates for the stats pane.
+#[derive(Debug, Default)]
+pub(crate) struct StatsPane {
+    last_sample: Option<(Instant, CaptureStats)>,
+    stats: CaptureStats,
+    capture_fps: f32,
+    delivered_fps: f32,
+    latency: Option<Duration>,
+    /// When the UI received the last frame, and the average time between received frames.
+    last_frame: Option<Instant>,
+    frame_interval: Option<Duration>,
+    /// Of the frames as captured, before the UI converts them for the preview.
+    pixel_format: Option<PixelFormat>,
+}
+
+impl StatsPane {
+    /// Weight of the newest latency and frame interval measurement, so the shown values don't flicker with every
+    /// frame.
+    const SMOOTHING: f64 = 0.1;
+
+    pub fn update(&mut self, stats: CaptureStats, now: Instant) {
+        if let Some((last_time, last_stats)) = self.last_sample {
+            let elapsed = now.saturating_duration_since(last_time).as_secs_f32();
+            if elapsed > 0.0 {
+                let rate = |current: u64, last: u64| current.saturating_sub(last) as f32 / elapsed;
+                self.capture_fps = rate(stats.frames_arrived, last_stats.frames_arrived);
+                self.delivered_fps = rate(stats.frames_delivered, last_stats.frames_delivered);
+            }
+        }
+        self.last_sample = Some((now, stats));
+        self.stats = stats;
+    }
+
+    /// Records a frame as the UI receives it, before it is converted for the preview.
+    pub fn record_frame(&mut self, frame: &Frame, now: Instant) {
+        self.latency =
+            Some(Self::smooth(self.latency, now.saturating_duration_since(frame.capture_instant)));
+        if let Some(last_frame) = self.last_frame {
+            let interval = now.saturating_duration_since(last_frame);
+            self.frame_interval = Some(Self::smooth(self.frame_interval, interval));
+        }
+        self.last_frame = Some(now);
+        self.pixel_format = Some(frame.format);
+    }
+
+    fn smooth(average: Option<Duration>, value: Duration) -> Duration {
+        match average {
+            Some(average) => {
+                average.mul_f64(1.0 - Self::SMOOTHING) + value.mul_f64(Self::SMOOTHING)
+            }
+            None => value,
+        }
+    }
+
+    pub fn capture_fps(&self) -> f32 {
+        self.capture_fps
+    }
+
+    /// Forgets the previous sample, so a new capture doesn't compute rates across the time in between.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let stats = &self.stats;
+        let latency = match self.latency {
+            Some(latency) => format!("{:.1} ms", latency.as_secs_f64() * 1000.0),
+            None => "-".to_string(),
+        };
+        let received_fps = match self.frame_interval {
+            Some(interval) if !interval.is_zero() => format!("{:.1}", 1.0 / interval.as_secs_f64()),
+            _ => "-".to_string(),
+        };
+        let pixel_format = match self.pixel_format {
+            Some(format) => format!("{:?}", format),
+            None => "-".to_string(),
+        };
+        let resolution = match stats.last_frame_size {
+            size if size.x > 0 && size.y > 0 => format!("{}x{}", size.x, size.y),
+            _ => "-".to_string(),
+        };
+
+        container(
+            column([
+                text("Capture statistics").into(),
+                text(format!("Capture FPS: {:.1}", self.capture_fps)).into(),
+                text(format!("Delivered FPS: {:.1}", self.delivered_fps)).into(),
+                text(format!("Received FPS: {}", received_fps)).into(),
+                text(format!("Delivered: {}", stats.frames_delivered)).into(),
+                text(format!("Dropped: {}", stats.frames_dropped)).into(),
+                text(format!("Skipped unchanged: {}", stats.frames_skipped_unchanged)).into(),
+                text(format!("Skipped over rate: {}", stats.frames_skipped_rate_limit)).into(),
+                text(format!(
+                    "Buffers reused: {} / {}",
+                    stats.buffer_pool_hits,
+                    stats.buffer_pool_hits + stats.buffer_pool_misses
+                ))
+                .into(),
+                text(format!("Idle buffers: {} MiB", stats.buffer_pool_bytes / (1024 * 1024)))
+                    .into(),
+                text(format!("Readback stalls: {}", stats.readback_stalls)).into(),
+                text(format!("Device recoveries: {}", stats.device_recoveries)).into(),
+                text(format!("Latency: {}", latency)).into(),
+                text(format!(
+                    "Delivery latency p50 / p95 / max: {:.1} / {:.1} / {:.1} ms",
+                    stats.delivery_latency.p50.as_secs_f64() * 1000.0,
+                    stats.delivery_latency.p95.as_secs_f64() * 1000.0,
+                    stats.delivery_latency.max.as_secs_f64() * 1000.0
+                ))
+                .into(),
+                text(format!("Resolution: {}", resolution)).into(),
+                text(format!("Pixel format: {}", pixel_format)).into(),
+            ])
+            .spacing(4),
+        )
+        .padding(10)
+        .width(Length::Shrink)
+        .into()
+    }
+}
@@ -0,0 +1,217 @@
+#[cfg(target_os = "windows")]
+use std::{thread, time::Duration};
+
+#[cfg(target_os = "windows")]
+use windows::Win32::{
+    Foundation::{HANDLE, HGLOBAL, HWND},
+    Graphics::Gdi::{BI_BITFIELDS, BITMAPV5HEADER},
+    System::{
+        DataExchange::{
+            CloseClipboard, EmptyClipboard, OpenClipboard, RegisterClipboardFormatW,
+            SetClipboardData,
+        },
+        Memory::{GMEM_MOVEABLE, GlobalAlloc, GlobalFree, GlobalLock, GlobalUnlock},
+        Ole::CF_DIBV5,
+    },
+};
+#[cfg(target_os = "windows")]
+use windows_core::w;
+
+use crate::capture_providers::shared::Vector2;
+#[cfg(target_os = "windows")]
+use crate::{
+    capture_providers::{shared::PixelFormat, windows::IntoHWND},
+    utils::image_utils::{ImageFileFormat, encode_rgba},
+};
+
+/// `LCS_sRGB`, the color space of captured frames.
+#[cfg(target_os = "windows")]
+const LCS_SRGB: u32 = u32::from_be_bytes(*b"sRGB");
+/// Another application holding the clipboard usually lets go within a few milliseconds.
+#[cfg(target_os = "windows")]
+const OPEN_ATTEMPTS: u32 = 5;
+#[cfg(target_os = "windows")]
+const OPEN_BACKOFF: Duration = Duration::from_millis(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClipboardError {
+    #[error("Cannot copy an image of size {0:?}")]
+    InvalidSize(Vector2<i32>),
+    #[error("Image data is shorter than its size requires")]
+    TruncatedData,
+    #[error("The clipboard is in use by another application")]
+    Busy,
+    #[cfg(not(target_os = "windows"))]
+    #[error("Copying images to the clipboard is only supported on Windows")]
+    Unsupported,
+    #[cfg(target_os = "windows")]
+    #[error("Windows error: {0}")]
+    WindowsError(#[from] windows_core::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ClipboardError>;
+
+/// Builds a top-down CF_DIBV5 of tightly packed RGBA pixels: the header followed by BGRA rows.
+/// Rows of 32 bit pixels are always DWORD aligned, so there is no padding.
+/// Alpha is kept straight, which is what readers of a DIBV5 with an alpha mask expect. Captured frames are
+/// opaque anyway, where straight and premultiplied alpha are the same.
+#[cfg(target_os = "windows")]
+pub fn rgba_to_dibv5(data: &[u8], size: Vector2<i32>) -> Result<Vec<u8>> {
+    if size.x <= 0 || size.y <= 0 {
+        return Err(ClipboardError::InvalidSize(size));
+    }
+    let pixels_len = PixelFormat::RGBA8.frame_bytes(size.x as usize, size.y as usize);
+    let pixels = data.get(..pixels_len).ok_or(ClipboardError::TruncatedData)?;
+
+    let header = BITMAPV5HEADER {
+        bV5Size: size_of::<BITMAPV5HEADER>() as u32,
+        bV5Width: size.x,
+        // Negative heights mark top-down bitmaps, which is the row order of our frames.
+        bV5Height: -size.y,
+        bV5Planes: 1,
+        bV5BitCount: 32,
+        bV5Compression: BI_BITFIELDS,
+        bV5SizeImage: pixels_len as u32,
+        bV5RedMask: 0x00FF_0000,
+        bV5GreenMask: 0x0000_FF00,
+        bV5BlueMask: 0x0000_00FF,
+        bV5AlphaMask: 0xFF00_0000,
+        bV5CSType: LCS_SRGB,
+        ..Default::default()
+    };
+
+    let mut dib = Vec::with_capacity(size_of::<BITMAPV5HEADER>() + pixels_len);
+    // SAFETY: BITMAPV5HEADER is a plain C struct without padding, so all of its bytes are initialized.
+    dib.extend_from_slice(unsafe {
+        std::slice::from_raw_parts(
+            (&header as *const BITMAPV5HEADER).cast::<u8>(),
+            size_of::<BITMAPV5HEADER>(),
+        )
+    });
+    for pixel in pixels.chunks_exact(4) {
+        dib.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+    }
+    Ok(dib)
+}
+
+/// Replaces the contents of the clipboard with an image of tightly packed RGBA pixels.
+/// Windows synthesizes CF_DIB and CF_BITMAP from it for applications that don't read CF_DIBV5. A PNG is added
+/// under the registered "PNG" format, which browsers and image editors prefer as it keeps alpha reliably. Only
+/// the DIB is required, failing to add the PNG is just logged.
+/// Blocks while retrying if another application has the clipboard open, so call it off the UI thread.
+#[cfg(target_os = "windows")]
+pub fn copy_rgba_image(owner: impl IntoHWND, data: &[u8], size: Vector2<i32>) -> Result<()> {
+    let owner = owner.into_hwnd();
+    let dib = rgba_to_dibv5(data, size)?;
+    let dib_memory = global_copy(&dib)?;
+    // Encoded before opening the clipboard, so it isn't held any longer than needed.
+    let pixels_len = PixelFormat::RGBA8.frame_bytes(size.x as usize, size.y as usize);
+    let png_memory = match encode_rgba(data[..pixels_len].to_vec(), size, ImageFileFormat::Png) {
+        Ok(png) => global_copy(&png)
+            .inspect_err(|err| {
+                tracing::warn!("Failed to allocate the PNG for the clipboard: {}", err)
+            })
+            .ok(),
+        Err(err) => {
+            tracing::warn!("Failed to encode the PNG for the clipboard: {}", err);
+            None
+        }
+    };
+
+    let result = with_clipboard(owner, || unsafe {
+        EmptyClipboard()?;
+        SetClipboardData(CF_DIBV5.0 as u32, Some(HANDLE(dib_memory.0)))?;
+        let Some(png_memory) = png_memory else {
+            return Ok(false);
+        };
+        let png_format = RegisterClipboardFormatW(w!("PNG"));
+        if png_format == 0 {
+            tracing::warn!(
+                "Failed to register the PNG clipboard format: {}",
+                windows_core::Error::from_win32()
+            );
+            return Ok(false);
+        }
+        match SetClipboardData(png_format, Some(HANDLE(png_memory.0))) {
+            Ok(_) => Ok(true),
+            Err(err) => {
+                tracing::warn!("Failed to put the PNG on the clipboard: {}", err);
+                Ok(false)
+            }
+        }
+    });
+    // The clipboard only takes ownership of the memory that was set.
+    let png_set = match result {
+        Ok(png_set) => png_set,
+        Err(_) => {
+            free_global(dib_memory);
+            false
+        }
+    };
+    if let (false, Some(png_memory)) = (png_set, png_memory) {
+        free_global(png_memory);
+    }
+    result.map(|_| ())
+}
+
+/// Copies the data into movable global memory, which is what the clipboard takes.
+#[cfg(target_os = "windows")]
+fn global_copy(data: &[u8]) -> Result<HGLOBAL> {
+    let memory = unsafe { GlobalAlloc(GMEM_MOVEABLE, data.len())? };
+    if let Err(err) = write_global(memory, data) {
+        free_global(memory);
+        return Err(err);
+    }
+    Ok(memory)
+}
+
+#[cfg(target_os = "windows")]
+fn free_global(memory: HGLOBAL) {
+    unsafe {
+        let _ = GlobalFree(Some(memory));
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn write_global(memory: HGLOBAL, data: &[u8]) -> Result<()> {
+    unsafe {
+        let ptr = GlobalLock(memory);
+        if ptr.is_null() {
+            return Err(windows_core::Error::from_win32().into());
+        }
+        std::ptr::copy_nonoverlapping(data.as_ptr(), ptr.cast::<u8>(), data.len());
+        // Fails with ERROR_SUCCESS once the lock count reaches zero, which is the expected outcome here.
+        let _ = GlobalUnlock(memory);
+    }
+    Ok(())
+}
+
+/// Opens the clipboard, retrying with an increasing delay while another application holds it.
+#[cfg(target_os = "windows")]
+fn with_clipboard<T>(owner: HWND, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let mut delay = OPEN_BACKOFF;
+    for attempt in 1..=OPEN_ATTEMPTS {
+        match unsafe { OpenClipboard(Some(owner)) } {
+            Ok(()) => {
+                let result = f();
+                if let Err(err) = unsafe { CloseClipboard() } {
+                    tracing::warn!("Failed to close the clipboard: {}", err);
+                }
+                return result;
+            }
+            Err(err) if attempt < OPEN_ATTEMPTS => {
+                tracing::debug!("Clipboard busy ({}), retrying in {:?}", err, delay);
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(_) => break,
+        }
+    }
+    Err(ClipboardError::Busy)
+}
+
+/// Copying images needs the Windows clipboard, elsewhere this always fails.
+#[cfg(not(target_os = "windows"))]
+pub fn copy_rgba_image(_owner: u64, _data: &[u8], _size: Vector2<i32>) -> Result<()> {
+    Err(ClipboardError::Unsupported)
+}
@@ -0,0 +1,162 @@
+use std::{
+    sync::{Mutex, mpsc},
+    thread::{self, ThreadId},
+    time::Duration,
+};
+
+use tokio::sync::oneshot;
+use windows::Win32::{
+    System::WinRT::{RO_INIT_MULTITHREADED, RO_INIT_SINGLETHREADED, RoInitialize},
+    UI::WindowsAndMessaging::{DispatchMessageW, MSG, PM_REMOVE, PeekMessageW, TranslateMessage},
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ComThreadError {
+    #[error("Failed to spawn COM thread: {0}")]
+    SpawnFailed(std::io::Error),
+    #[error("Failed to initialize the Windows Runtime: {0}")]
+    InitializationFailed(windows_core::Error),
+    #[error("The COM thread has stopped")]
+    Stopped,
+}
+
+pub type Result<T> = std::result::Result<T, ComThreadError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Apartment {
+    /// What WGC and Media Foundation expect. Objects created here can be used from any thread.
+    MultiThreaded,
+    /// For UI objects such as the capture picker. The thread pumps window messages between jobs.
+    SingleThreaded,
+}
+
+/// A thread that joined a COM apartment and runs jobs sent to it in order. Keeps the apartment requirements
+/// of WinRT calls away from callers, who may be on any thread, including tokio workers.
+/// Cloning is cheap, the thread exits once the last clone is dropped.
+#[derive(Debug, Clone)]
+pub struct ComThread {
+    jobs: mpsc::Sender<Job>,
+    thread: ThreadId,
+}
+
+impl ComThread {
+    /// How long a single-threaded apartment waits for jobs before pumping messages again.
+    const PUMP_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// Spawns a thread and waits until it joined the apartment.
+    pub fn spawn(name: &str, apartment: Apartment) -> Result<Self> {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let (initialized, initialization) = mpsc::sync_channel(1);
+        let handle = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                let result = apartment.initialize();
+                let failed = result.is_err();
+                let _ = initialized.send(result);
+                if failed {
+                    return;
+                }
+                match apartment {
+                    Apartment::MultiThreaded => {
+                        while let Ok(job) = receiver.recv() {
+                            job();
+                        }
+                    }
+                    Apartment::SingleThreaded => Self::run_pumping(receiver),
+                }
+            })
+            .map_err(ComThreadError::SpawnFailed)?;
+
+        match initialization.recv() {
+            Ok(Ok(())) => Ok(Self { jobs, thread: handle.thread().id() }),
+            Ok(Err(err)) => Err(ComThreadError::InitializationFailed(err)),
+            Err(_) => Err(ComThreadError::Stopped),
+        }
+    }
+
+    /// The process wide thread of the apartment, spawned on first use.
+    pub fn shared(apartment: Apartment) -> Result<Self> {
+        static MULTI_THREADED: Mutex<Option<ComThread>> = Mutex::new(None);
+        static SINGLE_THREADED: Mutex<Option<ComThread>> = Mutex::new(None);
+
+        let (shared, name) = match apartment {
+            Apartment::MultiThreaded => (&MULTI_THREADED, "com-mta"),
+            Apartment::SingleThreaded => (&SINGLE_THREADED, "com-sta"),
+        };
+        let mut shared = shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(thread) = &*shared {
+            return Ok(thread.clone());
+        }
+        let thread = Self::spawn(name, apartment)?;
+        *shared = Some(thread.clone());
+        Ok(thread)
+    }
+
+    /// Runs `f` on the thread and completes with its result. Never blocks the caller.
+    pub fn run_on<R, F>(&self, f: F) -> impl Future<Output = Result<R>> + use<R, F>
+    where
+        R: Send + 'static,
+        F: FnOnce() -> R + Send + 'static,
+    {
+        let (reply, response) = oneshot::channel();
+        let sent = self.send(move || {
+            let _ = reply.send(f());
+        });
+        async move {
+            sent?;
+            response.await.map_err(|_| ComThreadError::Stopped)
+        }
+    }
+
+    /// Runs `f` on the thread and blocks until it returns. Runs it right away if called from the thread itself,
+    /// so jobs can call back into code that uses the thread.
+    pub fn run_blocking<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce() -> R + Send + 'static,
+    {
+        if thread::current().id() == self.thread {
+            return Ok(f());
+        }
+        let (reply, response) = mpsc::sync_channel(1);
+        self.send(move || {
+            let _ = reply.send(f());
+        })?;
+        response.recv().map_err(|_| ComThreadError::Stopped)
+    }
+
+    fn send(&self, job: impl FnOnce() + Send + 'static) -> Result<()> {
+        self.jobs.send(Box::new(job)).map_err(|_| ComThreadError::Stopped)
+    }
+
+    /// Objects of a single-threaded apartment are called through window messages, so they have to be pumped
+    /// while waiting for jobs.
+    fn run_pumping(receiver: mpsc::Receiver<Job>) {
+        loop {
+            let mut message = MSG::default();
+            unsafe {
+                while PeekMessageW(&mut message, None, 0, 0, PM_REMOVE).as_bool() {
+                    let _ = TranslateMessage(&message);
+                    DispatchMessageW(&message);
+                }
+            }
+            match receiver.recv_timeout(Self::PUMP_INTERVAL) {
+                Ok(job) => job(),
+                Err(mpsc::RecvTimeoutError::Timeout) => (),
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+}
+
+impl Apartment {
+    fn initialize(self) -> windows_core::Result<()> {
+        let init_type = match self {
+            Apartment::MultiThreaded => RO_INIT_MULTITHREADED,
+            Apartment::SingleThreaded => RO_INIT_SINGLETHREADED,
+        };
+        unsafe { RoInitialize(init_type) }
+    }
+}
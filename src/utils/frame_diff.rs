@@ -0,0 +1,313 @@
+use crate::capture_providers::shared::{BytesPerPixel, Frame, PixelFormat, Rect, Vector2};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FrameDiffError {
+    #[error("Frames differ in size: {0:?} and {1:?}")]
+    SizeMismatch(Vector2<i32>, Vector2<i32>),
+    #[error("Frames differ in format: {0:?} and {1:?}")]
+    FormatMismatch(PixelFormat, PixelFormat),
+    #[error("Comparing {0:?} frames is not supported")]
+    UnsupportedFormat(PixelFormat),
+    #[error("Frame data is shorter than its size and stride require")]
+    TruncatedData,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    /// Largest difference of a single channel that still counts as equal, to allow for compression or dithering.
+    pub tolerance: u8,
+    /// Regions that are never compared, e.g. clocks or the cursor.
+    pub ignore_regions: Vec<Rect<i32>>,
+    /// Only compares the dirty rects of the frames if both have any. Dirty rects describe the changes since the
+    /// previous frame of the stream, so this is only accurate for consecutive frames.
+    pub use_dirty_rects: bool,
+    /// Whether to produce `FrameDiff::heatmap`.
+    pub heatmap: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self { tolerance: 0, ignore_regions: Vec::new(), use_dirty_rects: true, heatmap: false }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameDiff {
+    /// Pixels with at least one channel differing by more than the tolerance.
+    pub changed_pixels: u64,
+    /// Pixels that were looked at, less than the whole frame with dirty rects or ignored regions.
+    pub compared_pixels: u64,
+    /// Smallest rect containing every changed pixel, `None` if nothing changed.
+    pub bounds: Option<Rect<i32>>,
+    /// The largest channel difference of every pixel, one byte per pixel and tightly packed.
+    /// Pixels that weren't compared are zero.
+    pub heatmap: Option<Vec<u8>>,
+}
+
+impl FrameDiff {
+    pub fn is_identical(&self) -> bool {
+        self.changed_pixels == 0
+    }
+}
+
+/// Compares two frames of the same size and packed format pixel by pixel.
+pub fn diff_frames(a: &Frame, b: &Frame, opts: &DiffOptions) -> Result<FrameDiff, FrameDiffError> {
+    if a.size != b.size {
+        return Err(FrameDiffError::SizeMismatch(a.size, b.size));
+    }
+    if a.format != b.format {
+        return Err(FrameDiffError::FormatMismatch(a.format, b.format));
+    }
+    if a.format.is_planar() {
+        return Err(FrameDiffError::UnsupportedFormat(a.format));
+    }
+
+    let width = a.size.x.max(0);
+    let height = a.size.y.max(0);
+    for frame in [a, b] {
+        let required = match height {
+            0 => 0,
+            _ => frame.stride * (height as usize - 1) + frame.row_bytes(),
+        };
+        if frame.data.len() < required {
+            return Err(FrameDiffError::TruncatedData);
+        }
+    }
+
+    let frame_rect = Rect::new(Vector2::new(0, 0), Vector2::new(width, height));
+    let regions: Vec<_> =
+        if opts.use_dirty_rects && !a.dirty_rects.is_empty() && !b.dirty_rects.is_empty() {
+            a.dirty_rects
+                .iter()
+                .chain(&b.dirty_rects)
+                .filter_map(|rect| rect.intersect(&frame_rect))
+                .collect()
+        } else {
+            vec![frame_rect]
+        };
+
+    let bytes_per_pixel = a.format.bytes_per_pixel() as usize;
+    let mut diff = FrameDiff {
+        heatmap: opts.heatmap.then(|| vec![0; width as usize * height as usize]),
+        ..FrameDiff::default()
+    };
+    let (mut min, mut max) = (Vector2::new(width, height), Vector2::new(-1, -1));
+
+    for y in 0..height {
+        let row_a = &a.data[y as usize * a.stride..];
+        let row_b = &b.data[y as usize * b.stride..];
+        for (start, end) in
+            subtract_spans(row_spans(&regions, y), row_spans(&opts.ignore_regions, y))
+        {
+            diff.compared_pixels += (end - start) as u64;
+            for x in start..end {
+                let offset = x as usize * bytes_per_pixel;
+                let pixel_a = &row_a[offset..offset + bytes_per_pixel];
+                let pixel_b = &row_b[offset..offset + bytes_per_pixel];
+                let difference =
+                    pixel_a.iter().zip(pixel_b).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0);
+                if let Some(heatmap) = &mut diff.heatmap {
+                    heatmap[(y * width + x) as usize] = difference;
+                }
+                if difference > opts.tolerance {
+                    diff.changed_pixels += 1;
+                    min = Vector2::new(min.x.min(x), min.y.min(y));
+                    max = Vector2::new(max.x.max(x), max.y.max(y));
+                }
+            }
+        }
+    }
+
+    if diff.changed_pixels > 0 {
+        diff.bounds = Some(Rect::new(min, max - min + Vector2::new(1, 1)));
+    }
+    Ok(diff)
+}
+
+/// The sorted, non-overlapping x ranges covered by the rects in row `y`, with exclusive ends.
+fn row_spans(rects: &[Rect<i32>], y: i32) -> Vec<(i32, i32)> {
+    let mut spans: Vec<_> = rects
+        .iter()
+        .filter(|rect| rect.position.y <= y && y < rect.end().y && rect.size.x > 0)
+        .map(|rect| (rect.position.x, rect.end().x))
+        .collect();
+    spans.sort_unstable();
+
+    let mut merged: Vec<(i32, i32)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Removes the `ignored` ranges from `spans`. Both have to be sorted and non-overlapping, as from `row_spans`.
+fn subtract_spans(spans: Vec<(i32, i32)>, ignored: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+    if ignored.is_empty() {
+        return spans;
+    }
+
+    let mut result = Vec::with_capacity(spans.len());
+    for (mut start, end) in spans {
+        for &(ignored_start, ignored_end) in &ignored {
+            if ignored_end <= start || ignored_start >= end {
+                continue;
+            }
+            if ignored_start > start {
+                result.push((start, ignored_start));
+            }
+            start = start.max(ignored_end);
+        }
+        if start < end {
+            result.push((start, end));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::capture_providers::shared::FrameTiming;
+
+    const SIZE: Vector2<i32> = Vector2 { x: 8, y: 6 };
+
+    /// A BGRA8 frame of `SIZE` in one color, with rows padded to `stride`.
+    fn frame(
+        stride: usize,
+        dirty_rects: Vec<Rect<i32>>,
+        pixels: &[((i32, i32), [u8; 4])],
+    ) -> Frame {
+        let mut data = vec![0xAA; stride * SIZE.y as usize];
+        for y in 0..SIZE.y as usize {
+            for x in 0..SIZE.x as usize {
+                data[y * stride + x * 4..][..4].copy_from_slice(&[50, 100, 150, 255]);
+            }
+        }
+        for &((x, y), pixel) in pixels {
+            data[y as usize * stride + x as usize * 4..][..4].copy_from_slice(&pixel);
+        }
+        let timing = FrameTiming { timestamp: 0, sequence: 0, capture_instant: Instant::now() };
+        Frame::new(data.into(), PixelFormat::BGRA8, SIZE, stride, timing, dirty_rects)
+    }
+
+    fn plain(pixels: &[((i32, i32), [u8; 4])]) -> Frame {
+        frame(SIZE.x as usize * 4, Vec::new(), pixels)
+    }
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> Rect<i32> {
+        Rect::new(Vector2::new(x, y), Vector2::new(width, height))
+    }
+
+    const PIXELS: u64 = (SIZE.x * SIZE.y) as u64;
+
+    #[test]
+    fn identical_frames_have_no_changes() {
+        let diff = diff_frames(&plain(&[]), &plain(&[]), &DiffOptions::default()).unwrap();
+        assert!(diff.is_identical());
+        assert_eq!(diff.compared_pixels, PIXELS);
+        assert_eq!(diff.bounds, None);
+        assert_eq!(diff.heatmap, None);
+    }
+
+    #[test]
+    fn changes_are_counted_and_bounded() {
+        let changed = plain(&[((2, 1), [0, 100, 150, 255]), ((5, 4), [50, 100, 150, 0])]);
+        let diff = diff_frames(&plain(&[]), &changed, &DiffOptions::default()).unwrap();
+        assert_eq!(diff.changed_pixels, 2);
+        assert_eq!(diff.bounds, Some(rect(2, 1, 4, 4)));
+    }
+
+    #[test]
+    fn differences_within_the_tolerance_are_equal() {
+        let changed = plain(&[((0, 0), [53, 97, 150, 255]), ((7, 5), [50, 100, 154, 255])]);
+        let opts = DiffOptions { tolerance: 3, ..DiffOptions::default() };
+        let diff = diff_frames(&plain(&[]), &changed, &opts).unwrap();
+        assert_eq!(diff.changed_pixels, 1);
+        assert_eq!(diff.bounds, Some(rect(7, 5, 1, 1)));
+    }
+
+    #[test]
+    fn the_heatmap_holds_the_largest_channel_difference() {
+        let changed = plain(&[((3, 2), [60, 100, 130, 255])]);
+        let opts = DiffOptions { heatmap: true, ..DiffOptions::default() };
+        let heatmap = diff_frames(&plain(&[]), &changed, &opts).unwrap().heatmap.unwrap();
+        assert_eq!(heatmap.len(), PIXELS as usize);
+        for (index, &difference) in heatmap.iter().enumerate() {
+            let expected = if index == (2 * SIZE.x + 3) as usize { 20 } else { 0 };
+            assert_eq!(difference, expected, "pixel {}", index);
+        }
+    }
+
+    #[test]
+    fn ignored_regions_are_not_compared() {
+        let changed = plain(&[((1, 1), [0; 4]), ((6, 4), [0; 4])]);
+        let opts = DiffOptions {
+            ignore_regions: vec![rect(0, 0, 3, 3), rect(2, 0, 2, 2)],
+            ..DiffOptions::default()
+        };
+        let diff = diff_frames(&plain(&[]), &changed, &opts).unwrap();
+        assert_eq!(diff.changed_pixels, 1);
+        assert_eq!(diff.bounds, Some(rect(6, 4, 1, 1)));
+        // The regions overlap in two pixels, which are only left out once.
+        assert_eq!(diff.compared_pixels, PIXELS - 9 - 2);
+    }
+
+    #[test]
+    fn only_the_dirty_rects_are_compared_if_both_frames_have_them() {
+        let stride = SIZE.x as usize * 4;
+        let before = frame(stride, vec![rect(0, 0, 2, 2)], &[]);
+        let after = frame(stride, vec![rect(4, 4, 8, 8)], &[((1, 1), [0; 4]), ((3, 3), [0; 4])]);
+        let diff = diff_frames(&before, &after, &DiffOptions::default()).unwrap();
+        assert_eq!(diff.changed_pixels, 1);
+        // The second rect is clipped to the frame.
+        assert_eq!(diff.compared_pixels, 4 + 4 * 2);
+
+        let opts = DiffOptions { use_dirty_rects: false, ..DiffOptions::default() };
+        let diff = diff_frames(&before, &after, &opts).unwrap();
+        assert_eq!(diff.changed_pixels, 2);
+        assert_eq!(diff.compared_pixels, PIXELS);
+
+        let without_dirty_rects = frame(stride, Vec::new(), &[]);
+        let diff = diff_frames(&without_dirty_rects, &after, &DiffOptions::default()).unwrap();
+        assert_eq!(diff.changed_pixels, 2);
+    }
+
+    #[test]
+    fn row_padding_is_not_compared() {
+        let mut padded = frame(SIZE.x as usize * 4 + 12, Vec::new(), &[]);
+        let mut data = padded.data.to_vec();
+        data.iter_mut().skip(SIZE.x as usize * 4).step_by(padded.stride).for_each(|byte| *byte = 0);
+        padded.data = data.into();
+        let diff = diff_frames(&plain(&[]), &padded, &DiffOptions::default()).unwrap();
+        assert!(diff.is_identical());
+    }
+
+    #[test]
+    fn frames_that_cannot_be_compared_are_errors() {
+        let opts = DiffOptions::default();
+        let mut other_size = plain(&[]);
+        other_size.size = Vector2::new(SIZE.x, SIZE.y - 1);
+        let result = diff_frames(&plain(&[]), &other_size, &opts);
+        assert!(matches!(result, Err(FrameDiffError::SizeMismatch(..))));
+
+        let mut other_format = plain(&[]);
+        other_format.format = PixelFormat::RGBA8;
+        let result = diff_frames(&plain(&[]), &other_format, &opts);
+        assert!(matches!(result, Err(FrameDiffError::FormatMismatch(..))));
+
+        let mut planar = [plain(&[]), plain(&[])];
+        planar.iter_mut().for_each(|frame| frame.format = PixelFormat::NV12);
+        let result = diff_frames(&planar[0], &planar[1], &opts);
+        assert!(matches!(result, Err(FrameDiffError::UnsupportedFormat(PixelFormat::NV12))));
+
+        let mut truncated = plain(&[]);
+        truncated.data = truncated.data.slice(..truncated.data.len() - 1);
+        let result = diff_frames(&plain(&[]), &truncated, &opts);
+        assert!(matches!(result, Err(FrameDiffError::TruncatedData)));
+    }
+}
@@ -1,15 +1,956 @@
-use crate::capture_providers::shared::PixelFormat;
+use std::{io::Cursor, num::NonZeroUsize, sync::OnceLock, thread};
 
-pub fn ensure_image_rgba(bytes: &mut [u8], image_format: &mut PixelFormat) {
+use image::{DynamicImage, ExtendedColorType, RgbaImage, codecs::jpeg::JpegEncoder};
+
+use crate::capture_providers::shared::{Frame, PixelFormat, Vector2};
+
+/// Brightness of 1.0 in scRGB, in nits.
+pub const SCRGB_WHITE_NITS: f32 = 80.0;
+/// Brightness that SDR white is mapped to when tone mapping HDR frames, unless configured otherwise.
+/// Windows composes SDR content at the SDR white level of the display, 80 nits unless the user raised it.
+pub const DEFAULT_SDR_WHITE_LEVEL: f32 = SCRGB_WHITE_NITS;
+/// Data above this size is swizzled on several threads, smaller data is done before they would have started.
+const PARALLEL_SWIZZLE_BYTES: usize = 4 * 1024 * 1024;
+/// Swizzling is bound by memory bandwidth, which a few threads already saturate.
+const MAX_SWIZZLE_THREADS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFileFormat {
+    Png,
+    Jpeg,
+}
+
+impl ImageFileFormat {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EncodeError {
+    #[error("Image data does not match dimensions {0} x {1}")]
+    InvalidDimensions(i32, i32),
+    #[error("Image encoding error: {0}")]
+    ImageError(#[from] image::ImageError),
+    #[error("Failed to write image: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+/// Converts the image to RGBA in place.
+/// Only the first `row_bytes` of every `stride` bytes are converted, so row padding is left untouched.
+/// Gray8 images are expanded into tightly packed RGBA, which updates `stride`.
+/// Planar images can't be converted in place and are left as they are.
+pub fn ensure_image_rgba(
+    bytes: &mut Vec<u8>,
+    image_format: &mut PixelFormat,
+    row_bytes: usize,
+    stride: &mut usize,
+) {
     match image_format {
         PixelFormat::RGBA8 => (),
-        PixelFormat::BGRA8 => bgra_to_rgba(bytes),
+        PixelFormat::BGRA8 => swap_red_blue(bytes, row_bytes, *stride),
+        PixelFormat::Gray8 => {
+            let height = if *stride == 0 { 0 } else { bytes.len().div_ceil(*stride) };
+            gray8_to_rgba(bytes, row_bytes, height, *stride);
+            *stride = row_bytes * 4;
+        }
+        PixelFormat::RGBA16F => {
+            let width = row_bytes / 8;
+            let height = if *stride == 0 { 0 } else { bytes.len().div_ceil(*stride) };
+            rgba16f_to_rgba8(bytes, width, height, *stride, DEFAULT_SDR_WHITE_LEVEL);
+            *stride = width * 4;
+        }
+        PixelFormat::NV12 | PixelFormat::I420 => {
+            tracing::warn!("Can't convert planar {:?} image to RGBA in place.", image_format);
+            return;
+        }
     };
     *image_format = PixelFormat::RGBA8;
 }
 
+/// Swaps the R and B channels of every pixel, converting between RGBA and BGRA.
+fn swap_red_blue(bytes: &mut [u8], row_bytes: usize, stride: usize) {
+    if stride == row_bytes {
+        bgra_to_rgba(bytes);
+        return;
+    }
+    for row in bytes.chunks_mut(stride) {
+        let len = row_bytes.min(row.len());
+        bgra_to_rgba(&mut row[..len]);
+    }
+}
+
+/// Swaps B and R of every whole pixel in place, trailing bytes short of a pixel are left as they are.
+/// Uses SSSE3 or AVX2 where the CPU has them, and splits frames of several megabytes across threads.
 pub fn bgra_to_rgba(bytes: &mut [u8]) {
-    for pixel in bytes.chunks_exact_mut(4) {
-        pixel.swap(0, 2); // swap B and R
+    let Some(chunk_len) = swizzle_chunk_len(bytes.len()) else {
+        swizzle_in_place(bytes);
+        return;
+    };
+    thread::scope(|scope| {
+        let mut chunks = bytes.chunks_mut(chunk_len);
+        let first = chunks.next();
+        for chunk in chunks {
+            scope.spawn(move || swizzle_in_place(chunk));
+        }
+        if let Some(first) = first {
+            swizzle_in_place(first);
+        }
+    });
+}
+
+/// Same as `bgra_to_rgba`, writing the swizzled pixels of `src` into `dst` instead, which is resized to fit.
+/// Saves the copy when the source has to be kept, as with frames whose conversion was deferred.
+pub fn bgra_to_rgba_into(src: &[u8], dst: &mut Vec<u8>) {
+    let len = src.len() - src.len() % 4;
+    let src = &src[..len];
+    // Only grows are zeroed, so reusing a buffer of the same size costs nothing extra.
+    dst.truncate(len);
+    dst.resize(len, 0);
+    let Some(chunk_len) = swizzle_chunk_len(len) else {
+        swizzle_into(src, dst);
+        return;
+    };
+    thread::scope(|scope| {
+        let mut chunks = src.chunks(chunk_len).zip(dst.chunks_mut(chunk_len));
+        let first = chunks.next();
+        for (src, dst) in chunks {
+            scope.spawn(move || swizzle_into(src, dst));
+        }
+        if let Some((src, dst)) = first {
+            swizzle_into(src, dst);
+        }
+    });
+}
+
+/// Bytes each thread swizzles, `None` if the data is too small to be worth spreading across threads.
+/// Chunks are a multiple of the SIMD block size, so every chunk but the last is processed without a tail.
+fn swizzle_chunk_len(len: usize) -> Option<usize> {
+    if len < PARALLEL_SWIZZLE_BYTES {
+        return None;
+    }
+    let threads =
+        thread::available_parallelism().map_or(1, NonZeroUsize::get).min(MAX_SWIZZLE_THREADS);
+    (threads > 1).then(|| len.div_ceil(threads).next_multiple_of(64))
+}
+
+fn swizzle_in_place(bytes: &mut [u8]) {
+    let len = bytes.len();
+    let ptr = bytes.as_mut_ptr();
+    // SAFETY: Source and destination are the same buffer of `len` bytes, which the kernels allow.
+    let done = unsafe { simd_swizzle(ptr, ptr, len) };
+    swizzle_scalar_in_place(&mut bytes[done..]);
+}
+
+fn swizzle_into(src: &[u8], dst: &mut [u8]) {
+    let len = src.len().min(dst.len());
+    // SAFETY: Both are valid for `len` bytes, and a shared and a mutable borrow can't overlap.
+    let done = unsafe { simd_swizzle(src.as_ptr(), dst.as_mut_ptr(), len) };
+    swizzle_scalar_into(&src[done..len], &mut dst[done..len]);
+}
+
+/// Swizzles the leading bytes the CPU has a SIMD path for, returning how many. The rest is left to the scalar code.
+/// # Safety
+/// `src` and `dst` must be valid for `len` bytes, and either be the same pointer or not overlap.
+#[cfg(target_arch = "x86_64")]
+unsafe fn simd_swizzle(src: *const u8, dst: *mut u8, len: usize) -> usize {
+    if is_x86_feature_detected!("avx2") {
+        return unsafe { x86_swizzle::avx2(src, dst, len) };
+    }
+    if is_x86_feature_detected!("ssse3") {
+        return unsafe { x86_swizzle::ssse3(src, dst, len) };
+    }
+    0
+}
+
+/// No SIMD path off x86_64, everything is left to the scalar code.
+/// # Safety
+/// Always safe, it only has the signature of the x86_64 version.
+#[cfg(not(target_arch = "x86_64"))]
+unsafe fn simd_swizzle(_src: *const u8, _dst: *mut u8, _len: usize) -> usize {
+    0
+}
+
+/// Swaps B and R of a pixel read as a little endian u32.
+#[inline(always)]
+fn swap_red_blue_pixel(pixel: u32) -> u32 {
+    (pixel & 0xFF00_FF00) | ((pixel >> 16) & 0xFF) | ((pixel & 0xFF) << 16)
+}
+
+/// Four pixels at a time, which the compiler keeps in registers.
+fn swizzle_scalar_in_place(bytes: &mut [u8]) {
+    let mut blocks = bytes.chunks_exact_mut(16);
+    for block in &mut blocks {
+        for pixel in block.chunks_exact_mut(4) {
+            let swapped = swap_red_blue_pixel(u32::from_le_bytes(pixel.try_into().unwrap()));
+            pixel.copy_from_slice(&swapped.to_le_bytes());
+        }
+    }
+    for pixel in blocks.into_remainder().chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+}
+
+fn swizzle_scalar_into(src: &[u8], dst: &mut [u8]) {
+    let mut src_blocks = src.chunks_exact(16);
+    let mut dst_blocks = dst.chunks_exact_mut(16);
+    for (src, dst) in (&mut src_blocks).zip(&mut dst_blocks) {
+        for (from, to) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+            let swapped = swap_red_blue_pixel(u32::from_le_bytes(from.try_into().unwrap()));
+            to.copy_from_slice(&swapped.to_le_bytes());
+        }
+    }
+    let remainder = src_blocks.remainder().chunks_exact(4);
+    for (from, to) in remainder.zip(dst_blocks.into_remainder().chunks_exact_mut(4)) {
+        to.copy_from_slice(&[from[2], from[1], from[0], from[3]]);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86_swizzle {
+    use std::arch::x86_64::{
+        __m128i, __m256i, _mm_loadu_si128, _mm_shuffle_epi8, _mm_storeu_si128,
+        _mm256_broadcastsi128_si256, _mm256_loadu_si256, _mm256_shuffle_epi8, _mm256_storeu_si256,
+    };
+
+    /// Byte order of four swizzled pixels, as indices into the source.
+    const SHUFFLE: [u8; 16] = [2, 1, 0, 3, 6, 5, 4, 7, 10, 9, 8, 11, 14, 13, 12, 15];
+
+    /// Whole blocks of 32 bytes, returns how many bytes were swizzled. Shuffles within 128 bit lanes, which
+    /// never split a pixel.
+    /// # Safety
+    /// See `simd_swizzle`, and the CPU has to support AVX2.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn avx2(src: *const u8, dst: *mut u8, len: usize) -> usize {
+        let mut offset = 0;
+        unsafe {
+            let shuffle = _mm256_broadcastsi128_si256(_mm_loadu_si128(SHUFFLE.as_ptr().cast()));
+            while offset + 32 <= len {
+                let pixels = _mm256_loadu_si256(src.add(offset).cast::<__m256i>());
+                _mm256_storeu_si256(
+                    dst.add(offset).cast::<__m256i>(),
+                    _mm256_shuffle_epi8(pixels, shuffle),
+                );
+                offset += 32;
+            }
+        }
+        offset
+    }
+
+    /// Whole blocks of 16 bytes, returns how many bytes were swizzled.
+    /// # Safety
+    /// See `simd_swizzle`, and the CPU has to support SSSE3.
+    #[target_feature(enable = "ssse3")]
+    pub unsafe fn ssse3(src: *const u8, dst: *mut u8, len: usize) -> usize {
+        let mut offset = 0;
+        unsafe {
+            let shuffle = _mm_loadu_si128(SHUFFLE.as_ptr().cast());
+            while offset + 16 <= len {
+                let pixels = _mm_loadu_si128(src.add(offset).cast::<__m128i>());
+                _mm_storeu_si128(
+                    dst.add(offset).cast::<__m128i>(),
+                    _mm_shuffle_epi8(pixels, shuffle),
+                );
+                offset += 16;
+            }
+        }
+        offset
+    }
+}
+
+/// Copies the first `row_bytes` of every `stride` bytes, dropping the row padding. Rows missing at the end of
+/// `data` are left out.
+pub fn pack_rows(data: &[u8], row_bytes: usize, stride: usize, rows: usize) -> Vec<u8> {
+    let mut packed = Vec::with_capacity(row_bytes * rows);
+    for row in data.chunks(stride.max(1)).take(rows) {
+        packed.extend_from_slice(&row[..row_bytes.min(row.len())]);
+    }
+    packed
+}
+
+/// Expands Gray8 rows of `stride` bytes into tightly packed, opaque RGBA8 in place.
+pub fn gray8_to_rgba(data: &mut Vec<u8>, width: usize, height: usize, stride: usize) {
+    let gray_len = data.len();
+    data.resize(PixelFormat::RGBA8.frame_bytes(width, height), 0);
+    // With a stride of up to four times the width, every pixel moves to an offset at least as large as its own,
+    // so going backwards never overwrites a pixel that is still to be read.
+    for y in (0..height).rev() {
+        for x in (0..width).rev() {
+            let source = y * stride + x;
+            let gray = if source < gray_len { data[source] } else { 0 };
+            let target = (y * width + x) * 4;
+            data[target..target + 4].copy_from_slice(&[gray, gray, gray, u8::MAX]);
+        }
+    }
+}
+
+/// Converts a half precision float into a single precision one.
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exponent = ((bits >> 10) & 0x1F) as u32;
+    let mantissa = (bits & 0x03FF) as u32;
+    let magnitude = match exponent {
+        0 => {
+            // Subnormals are multiples of 2^-24, which single precision floats hold exactly.
+            let value = mantissa as f32 / (1 << 24) as f32;
+            return if sign == 0 { value } else { -value };
+        }
+        // Infinity and NaN.
+        0x1F => (0xFF << 23) | (mantissa << 13),
+        _ => ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(sign | magnitude)
+}
+
+/// Entries of the linear to sRGB lookup table. Fine enough that dark values, where the curve is steepest,
+/// still get every 8 bit level.
+const SRGB_LUT_SIZE: usize = 1 << 14;
+
+/// Encodes linear light between 0 and 1 with the sRGB transfer function.
+fn linear_to_srgb8(value: f32) -> u8 {
+    static LUT: OnceLock<Vec<u8>> = OnceLock::new();
+    let lut = LUT.get_or_init(|| {
+        (0..SRGB_LUT_SIZE)
+            .map(|index| {
+                let linear = index as f32 / (SRGB_LUT_SIZE - 1) as f32;
+                let encoded = if linear <= 0.003_130_8 {
+                    linear * 12.92
+                } else {
+                    1.055 * linear.powf(1.0 / 2.4) - 0.055
+                };
+                (encoded * 255.0).round() as u8
+            })
+            .collect()
+    });
+    // NaN ends up at 0 as well.
+    let index = (value.clamp(0.0, 1.0) * (SRGB_LUT_SIZE - 1) as f32).round() as usize;
+    lut[index.min(SRGB_LUT_SIZE - 1)]
+}
+
+/// Tone maps scRGB RGBA16F rows of `stride` bytes into tightly packed RGBA8 in place, shrinking `data` to fit.
+/// Colors are scaled so `sdr_white_level` nits become white, then clipped. SDR content looks as it would on an
+/// SDR display that way, only highlights brighter than SDR white lose detail.
+pub fn rgba16f_to_rgba8(
+    data: &mut Vec<u8>,
+    width: usize,
+    height: usize,
+    stride: usize,
+    sdr_white_level: f32,
+) {
+    let scale = SCRGB_WHITE_NITS / sdr_white_level.max(1.0);
+    let height = height.min(if stride == 0 { 0 } else { data.len().div_ceil(stride) });
+    let channel = |data: &[u8], offset: usize| {
+        f16_to_f32(u16::from_le_bytes([data[offset], data[offset + 1]]))
+    };
+    // Every pixel shrinks from 8 to 4 bytes, so writing never overtakes reading.
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = y * stride + x * 8;
+            let rgba8 = [
+                linear_to_srgb8(channel(data, pixel) * scale),
+                linear_to_srgb8(channel(data, pixel + 2) * scale),
+                linear_to_srgb8(channel(data, pixel + 4) * scale),
+                // Alpha is linear and unaffected by brightness.
+                (channel(data, pixel + 6).clamp(0.0, 1.0) * 255.0).round() as u8,
+            ];
+            let target = (y * width + x) * 4;
+            data[target..target + 4].copy_from_slice(&rgba8);
+        }
+    }
+    data.truncate(PixelFormat::RGBA8.frame_bytes(width, height));
+}
+
+/// Whether `convert_image` can turn `source` data into `target`.
+pub fn supports_conversion(source: PixelFormat, target: PixelFormat) -> bool {
+    match (source, target) {
+        _ if source == target => true,
+        (PixelFormat::RGBA16F, _) => supports_conversion(PixelFormat::RGBA8, target),
+        (
+            PixelFormat::RGBA8 | PixelFormat::BGRA8,
+            PixelFormat::RGBA8
+            | PixelFormat::BGRA8
+            | PixelFormat::NV12
+            | PixelFormat::I420
+            | PixelFormat::Gray8,
+        ) => true,
+        _ => false,
+    }
+}
+
+/// Converts packed RGBA or BGRA data into `target`.
+/// Returns the data with its format and stride, planar and Gray8 output is always tightly packed.
+/// RGBA16F is tone mapped at the default SDR white level first, unless it is the target.
+pub fn convert_image(
+    mut data: Vec<u8>,
+    source: PixelFormat,
+    target: PixelFormat,
+    size: Vector2<i32>,
+    stride: usize,
+) -> (Vec<u8>, PixelFormat, usize) {
+    let width = size.x.max(0) as usize;
+    let height = size.y.max(0) as usize;
+    let row_bytes = source.row_bytes(width);
+
+    match (source, target) {
+        _ if source == target => (data, source, stride),
+        (PixelFormat::RGBA16F, _) => {
+            rgba16f_to_rgba8(&mut data, width, height, stride, DEFAULT_SDR_WHITE_LEVEL);
+            convert_image(
+                data,
+                PixelFormat::RGBA8,
+                target,
+                size,
+                PixelFormat::RGBA8.row_bytes(width),
+            )
+        }
+        (PixelFormat::RGBA8 | PixelFormat::BGRA8, PixelFormat::RGBA8 | PixelFormat::BGRA8) => {
+            swap_red_blue(&mut data, row_bytes, stride);
+            (data, target, stride)
+        }
+        (PixelFormat::RGBA8 | PixelFormat::BGRA8, PixelFormat::NV12 | PixelFormat::I420) => {
+            let mut converted = Vec::new();
+            match (source, target) {
+                (PixelFormat::RGBA8, PixelFormat::NV12) => {
+                    rgba_to_nv12(&data, width, height, stride, &mut converted)
+                }
+                (PixelFormat::BGRA8, PixelFormat::NV12) => {
+                    bgra_to_nv12(&data, width, height, stride, &mut converted)
+                }
+                (PixelFormat::RGBA8, _) => {
+                    rgba_to_i420(&data, width, height, stride, &mut converted)
+                }
+                _ => bgra_to_i420(&data, width, height, stride, &mut converted),
+            }
+            (converted, target, width)
+        }
+        (PixelFormat::RGBA8, PixelFormat::Gray8) => {
+            rgba_to_gray8(&mut data, width, height, stride);
+            (data, target, width)
+        }
+        (PixelFormat::BGRA8, PixelFormat::Gray8) => {
+            bgra_to_gray8(&mut data, width, height, stride);
+            (data, target, width)
+        }
+        _ => {
+            tracing::warn!("Conversion from {:?} to {:?} is not supported.", source, target);
+            (data, source, stride)
+        }
+    }
+}
+
+/// Byte offsets of the color channels within a packed 4 byte pixel.
+#[derive(Debug, Clone, Copy)]
+struct ChannelOrder {
+    r: usize,
+    g: usize,
+    b: usize,
+}
+
+const RGBA_ORDER: ChannelOrder = ChannelOrder { r: 0, g: 1, b: 2 };
+const BGRA_ORDER: ChannelOrder = ChannelOrder { r: 2, g: 1, b: 0 };
+
+// BT.601 limited range in 8 bit fixed point.
+fn luma(r: i32, g: i32, b: i32) -> u8 {
+    (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8
+}
+
+// BT.601 weights at full range, so black and white stay 0 and 255.
+fn gray(r: i32, g: i32, b: i32) -> u8 {
+    ((77 * r + 150 * g + 29 * b + 128) >> 8) as u8
+}
+
+fn chroma(r: i32, g: i32, b: i32) -> (u8, u8) {
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    (u.clamp(0, 255) as u8, v.clamp(0, 255) as u8)
+}
+
+fn write_luma_plane(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    order: ChannelOrder,
+    luma_plane: &mut [u8],
+) {
+    for (row, dst) in src.chunks(stride).take(height).zip(luma_plane.chunks_exact_mut(width)) {
+        for (pixel, y) in row[..width * 4].chunks_exact(4).zip(dst) {
+            *y = luma(pixel[order.r] as i32, pixel[order.g] as i32, pixel[order.b] as i32);
+        }
+    }
+}
+
+/// Averages every 2x2 block into one chroma sample. Blocks on an odd edge reuse the last row or column.
+fn write_chroma_samples(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    order: ChannelOrder,
+    mut store: impl FnMut(usize, u8, u8),
+) {
+    let chroma_width = width.div_ceil(2);
+    for chroma_y in 0..height.div_ceil(2) {
+        let top = &src[chroma_y * 2 * stride..][..width * 4];
+        let bottom = &src[(chroma_y * 2 + 1).min(height - 1) * stride..][..width * 4];
+
+        for chroma_x in 0..chroma_width {
+            let left = chroma_x * 2 * 4;
+            let right = (chroma_x * 2 + 1).min(width - 1) * 4;
+
+            let (mut r, mut g, mut b) = (0, 0, 0);
+            for pixel in [&top[left..], &top[right..], &bottom[left..], &bottom[right..]] {
+                r += pixel[order.r] as i32;
+                g += pixel[order.g] as i32;
+                b += pixel[order.b] as i32;
+            }
+            let (u, v) = chroma((r + 2) >> 2, (g + 2) >> 2, (b + 2) >> 2);
+            store(chroma_y * chroma_width + chroma_x, u, v);
+        }
+    }
+}
+
+fn packed_to_nv12(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    order: ChannelOrder,
+    dst: &mut Vec<u8>,
+) {
+    dst.resize(PixelFormat::NV12.frame_bytes(width, height), 0);
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let (luma_plane, uv_plane) = dst.split_at_mut(width * height);
+    write_luma_plane(src, width, height, stride, order, luma_plane);
+    write_chroma_samples(src, width, height, stride, order, |index, u, v| {
+        uv_plane[index * 2] = u;
+        uv_plane[index * 2 + 1] = v;
+    });
+}
+
+fn packed_to_i420(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    order: ChannelOrder,
+    dst: &mut Vec<u8>,
+) {
+    dst.resize(PixelFormat::I420.frame_bytes(width, height), 0);
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let (luma_plane, chroma_planes) = dst.split_at_mut(width * height);
+    let (u_plane, v_plane) = chroma_planes.split_at_mut(chroma_planes.len() / 2);
+    write_luma_plane(src, width, height, stride, order, luma_plane);
+    write_chroma_samples(src, width, height, stride, order, |index, u, v| {
+        u_plane[index] = u;
+        v_plane[index] = v;
+    });
+}
+
+/// Every pixel is written to an offset no larger than the one it was read from, so the conversion can reuse the
+/// source buffer.
+fn packed_to_gray8(
+    data: &mut Vec<u8>,
+    width: usize,
+    height: usize,
+    stride: usize,
+    order: ChannelOrder,
+) {
+    let height = height.min(if stride == 0 { 0 } else { data.len().div_ceil(stride) });
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = y * stride + x * 4;
+            data[y * width + x] = gray(
+                data[pixel + order.r] as i32,
+                data[pixel + order.g] as i32,
+                data[pixel + order.b] as i32,
+            );
+        }
+    }
+    data.truncate(width * height);
+}
+
+/// Converts RGBA8 rows of `stride` bytes into tightly packed Gray8 in place, shrinking `data` to fit.
+pub fn rgba_to_gray8(data: &mut Vec<u8>, width: usize, height: usize, stride: usize) {
+    packed_to_gray8(data, width, height, stride, RGBA_ORDER);
+}
+
+/// Converts BGRA8 rows of `stride` bytes into tightly packed Gray8 in place, shrinking `data` to fit.
+pub fn bgra_to_gray8(data: &mut Vec<u8>, width: usize, height: usize, stride: usize) {
+    packed_to_gray8(data, width, height, stride, BGRA_ORDER);
+}
+
+/// Converts RGBA8 rows of `stride` bytes into NV12, resizing `dst` to fit.
+pub fn rgba_to_nv12(src: &[u8], width: usize, height: usize, stride: usize, dst: &mut Vec<u8>) {
+    packed_to_nv12(src, width, height, stride, RGBA_ORDER, dst);
+}
+
+/// Converts BGRA8 rows of `stride` bytes into NV12, resizing `dst` to fit.
+pub fn bgra_to_nv12(src: &[u8], width: usize, height: usize, stride: usize, dst: &mut Vec<u8>) {
+    packed_to_nv12(src, width, height, stride, BGRA_ORDER, dst);
+}
+
+/// Converts RGBA8 rows of `stride` bytes into I420, resizing `dst` to fit.
+pub fn rgba_to_i420(src: &[u8], width: usize, height: usize, stride: usize, dst: &mut Vec<u8>) {
+    packed_to_i420(src, width, height, stride, RGBA_ORDER, dst);
+}
+
+/// Converts BGRA8 rows of `stride` bytes into I420, resizing `dst` to fit.
+pub fn bgra_to_i420(src: &[u8], width: usize, height: usize, stride: usize, dst: &mut Vec<u8>) {
+    packed_to_i420(src, width, height, stride, BGRA_ORDER, dst);
+}
+
+/// Encodes a frame into an image file, converting it to RGBA first if needed.
+pub fn encode_frame(frame: &Frame, format: ImageFileFormat) -> Result<Vec<u8>, EncodeError> {
+    encode_rgba(frame.to_tightly_packed_rgba().into_owned(), frame.size, format)
+}
+
+/// Neither image format can hold an empty image.
+fn image_dimensions(size: Vector2<i32>) -> Result<(u32, u32), EncodeError> {
+    match (u32::try_from(size.x), u32::try_from(size.y)) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(EncodeError::InvalidDimensions(size.x, size.y)),
+    }
+}
+
+/// Encodes tightly packed RGBA8 data into an image file.
+pub fn encode_rgba(
+    data: Vec<u8>,
+    size: Vector2<i32>,
+    format: ImageFileFormat,
+) -> Result<Vec<u8>, EncodeError> {
+    let (width, height) = image_dimensions(size)?;
+    let image = RgbaImage::from_raw(width, height, data)
+        .ok_or(EncodeError::InvalidDimensions(size.x, size.y))?;
+
+    let mut encoded = Cursor::new(Vec::new());
+    match format {
+        ImageFileFormat::Png => image.write_to(&mut encoded, image::ImageFormat::Png)?,
+        // JPEG has no alpha channel, so it has to be dropped first.
+        ImageFileFormat::Jpeg => DynamicImage::ImageRgba8(image)
+            .to_rgb8()
+            .write_to(&mut encoded, image::ImageFormat::Jpeg)?,
+    }
+    Ok(encoded.into_inner())
+}
+
+/// Encodes tightly packed RGBA8 data as a JPEG of `quality` between 1 and 100, dropping alpha.
+/// Borrows the data, unlike `encode_rgba`, as streaming encodes every frame.
+pub fn encode_rgba_jpeg(
+    data: &[u8],
+    size: Vector2<i32>,
+    quality: u8,
+) -> Result<Vec<u8>, EncodeError> {
+    let (width, height) = image_dimensions(size)?;
+    let pixels = data
+        .get(..width as usize * height as usize * 4)
+        .ok_or(EncodeError::InvalidDimensions(size.x, size.y))?;
+
+    let mut rgb = Vec::with_capacity(pixels.len() / 4 * 3);
+    for pixel in pixels.chunks_exact(4) {
+        rgb.extend_from_slice(&pixel[..3]);
+    }
+    let mut encoded = Vec::new();
+    JpegEncoder::new_with_quality(&mut encoded, quality.clamp(1, 100)).encode(
+        &rgb,
+        width,
+        height,
+        ExtendedColorType::Rgb8,
+    )?;
+    Ok(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes that differ from their neighbours, so any channel that ends up in the wrong place shows.
+    fn pixel_bytes(len: usize) -> Vec<u8> {
+        (0..len).map(|index| (index * 7 % 251) as u8).collect()
+    }
+
+    /// What the SIMD and threaded paths have to match.
+    fn swapped(data: &[u8]) -> Vec<u8> {
+        let mut swapped = data.to_vec();
+        for pixel in swapped.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+        swapped
+    }
+
+    #[test]
+    fn swizzling_matches_a_plain_swap_at_every_length() {
+        // Covers the SIMD blocks, the scalar blocks of 16 bytes, single pixels and trailing partial pixels.
+        for len in 0..200 {
+            let data = pixel_bytes(len);
+            let mut in_place = data.clone();
+            bgra_to_rgba(&mut in_place);
+            assert_eq!(in_place, swapped(&data), "{} bytes", len);
+
+            let mut into = Vec::new();
+            bgra_to_rgba_into(&data, &mut into);
+            assert_eq!(into, swapped(&data)[..len - len % 4], "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn large_frames_swizzle_the_same_on_several_threads() {
+        // Above the threshold, and not a multiple of the chunk size.
+        let data = pixel_bytes(PARALLEL_SWIZZLE_BYTES + 4 * 1001);
+        let mut in_place = data.clone();
+        bgra_to_rgba(&mut in_place);
+        assert_eq!(in_place, swapped(&data));
+
+        // A reused buffer of another size is resized to fit.
+        let mut into = vec![0xAA; 16];
+        bgra_to_rgba_into(&data, &mut into);
+        assert_eq!(into, in_place);
+    }
+
+    #[test]
+    fn swizzling_twice_restores_the_data() {
+        let data = pixel_bytes(4 * 37);
+        let mut twice = data.clone();
+        bgra_to_rgba(&mut twice);
+        bgra_to_rgba(&mut twice);
+        assert_eq!(twice, data);
+    }
+
+    #[test]
+    fn rgba_conversion_leaves_the_row_padding_alone() {
+        // Two rows of a pixel each, padded to 6 bytes.
+        let mut data = vec![1, 2, 3, 4, 0xEE, 0xEE, 5, 6, 7, 8, 0xEE, 0xEE];
+        let mut format = PixelFormat::BGRA8;
+        let mut stride = 6;
+        ensure_image_rgba(&mut data, &mut format, 4, &mut stride);
+        assert_eq!(data, [3, 2, 1, 4, 0xEE, 0xEE, 7, 6, 5, 8, 0xEE, 0xEE]);
+        assert_eq!((format, stride), (PixelFormat::RGBA8, 6));
+    }
+
+    #[test]
+    fn converting_between_bgra_and_rgba_round_trips() {
+        let data = pixel_bytes(2 * 3 * 4);
+        let size = Vector2::new(2, 3);
+        let (rgba, format, stride) =
+            convert_image(data.clone(), PixelFormat::BGRA8, PixelFormat::RGBA8, size, 8);
+        assert_eq!((format, stride), (PixelFormat::RGBA8, 8));
+        assert_eq!(rgba, swapped(&data));
+
+        let (bgra, format, _) =
+            convert_image(rgba, PixelFormat::RGBA8, PixelFormat::BGRA8, size, 8);
+        assert_eq!((bgra, format), (data, PixelFormat::BGRA8));
+    }
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+    /// Four by two pixels, red on the left half and blue on the right, so each half is one chroma sample. Rows
+    /// are padded to `stride` bytes.
+    fn red_and_blue(stride: usize) -> Vec<u8> {
+        let row = [RED, RED, BLUE, BLUE].concat();
+        let mut data = Vec::new();
+        for _ in 0..2 {
+            data.extend_from_slice(&row);
+            data.resize(data.len() + stride - row.len(), 0xEE);
+        }
+        data
+    }
+
+    // BT.601 limited range: red is Y 82, U 90, V 240 and blue is Y 41, U 240, V 110.
+    const RED_AND_BLUE_LUMA: [u8; 8] = [82, 82, 41, 41, 82, 82, 41, 41];
+
+    #[test]
+    fn nv12_holds_bt601_values() {
+        let mut nv12 = Vec::new();
+        rgba_to_nv12(&red_and_blue(16), 4, 2, 16, &mut nv12);
+        assert_eq!(nv12, [&RED_AND_BLUE_LUMA[..], &[90, 240, 240, 110]].concat());
+
+        // The channel order and the row padding don't change the result.
+        let mut bgra = red_and_blue(20);
+        swap_red_blue(&mut bgra, 16, 20);
+        let mut from_bgra = Vec::new();
+        bgra_to_nv12(&bgra, 4, 2, 20, &mut from_bgra);
+        assert_eq!(from_bgra, nv12);
+    }
+
+    #[test]
+    fn i420_holds_bt601_values() {
+        let mut i420 = Vec::new();
+        rgba_to_i420(&red_and_blue(16), 4, 2, 16, &mut i420);
+        assert_eq!(i420, [&RED_AND_BLUE_LUMA[..], &[90, 240], &[240, 110]].concat());
+
+        let mut bgra = red_and_blue(16);
+        swap_red_blue(&mut bgra, 16, 16);
+        let mut from_bgra = Vec::new();
+        bgra_to_i420(&bgra, 4, 2, 16, &mut from_bgra);
+        assert_eq!(from_bgra, i420);
+    }
+
+    #[test]
+    fn odd_sizes_round_the_chroma_planes_up() {
+        // White is Y 235 with neutral chroma, the edge samples reuse the last row and column.
+        let white = WHITE.repeat(9);
+        let mut nv12 = Vec::new();
+        rgba_to_nv12(&white, 3, 3, 12, &mut nv12);
+        assert_eq!(nv12, [vec![235; 9], vec![128; 8]].concat());
+
+        let mut i420 = Vec::new();
+        rgba_to_i420(&white[..12], 3, 1, 12, &mut i420);
+        assert_eq!(i420, [vec![235; 3], vec![128; 4]].concat());
+
+        rgba_to_nv12(&[], 0, 0, 0, &mut nv12);
+        assert!(nv12.is_empty());
+    }
+
+    #[test]
+    fn conversion_to_planar_formats_packs_the_rows() {
+        let (nv12, format, stride) = convert_image(
+            red_and_blue(20),
+            PixelFormat::RGBA8,
+            PixelFormat::NV12,
+            Vector2::new(4, 2),
+            20,
+        );
+        assert_eq!((format, stride), (PixelFormat::NV12, 4));
+        assert_eq!(nv12.len(), PixelFormat::NV12.frame_bytes(4, 2));
+    }
+
+    #[test]
+    fn gray8_uses_bt601_weights_at_full_range() {
+        // Red, green, blue, white and black, with a padding byte after each row of one pixel.
+        let pixels: [[u8; 4]; 5] = [RED, [0, 255, 0, 255], BLUE, WHITE, [0, 0, 0, 255]];
+        let padded =
+            pixels.iter().flat_map(|pixel| [&pixel[..], &[0xEE]].concat()).collect::<Vec<_>>();
+        let expected = [77, 149, 29, 255, 0];
+
+        let mut gray = padded.clone();
+        rgba_to_gray8(&mut gray, 1, 5, 5);
+        assert_eq!(gray, expected);
+
+        let mut bgra = padded;
+        swap_red_blue(&mut bgra, 4, 5);
+        bgra_to_gray8(&mut bgra, 1, 5, 5);
+        assert_eq!(bgra, expected);
+    }
+
+    #[test]
+    fn gray8_expands_to_opaque_rgba() {
+        // Two rows of two pixels, padded to three bytes.
+        let mut data = vec![0, 255, 0xEE, 77, 29];
+        let mut format = PixelFormat::Gray8;
+        let mut stride = 3;
+        ensure_image_rgba(&mut data, &mut format, 2, &mut stride);
+        assert_eq!(
+            data,
+            [[0, 0, 0, 255], [255, 255, 255, 255], [77, 77, 77, 255], [29, 29, 29, 255]].concat()
+        );
+        assert_eq!((format, stride), (PixelFormat::RGBA8, 8));
+    }
+
+    #[test]
+    fn packing_rows_drops_the_padding() {
+        // Two rows of two bytes, each padded to three.
+        let data = [1, 2, 0, 3, 4, 0];
+        assert_eq!(pack_rows(&data, 2, 3, 2), [1, 2, 3, 4]);
+        // The last row may lack its padding, missing rows are left out.
+        assert_eq!(pack_rows(&data[..5], 2, 3, 3), [1, 2, 3, 4]);
+        assert_eq!(pack_rows(&data, 3, 3, 2), data);
+    }
+
+    #[test]
+    fn f16_converts_zeros_and_normal_values() {
+        assert_eq!(f16_to_f32(0x0000).to_bits(), 0.0f32.to_bits());
+        assert_eq!(f16_to_f32(0x8000).to_bits(), (-0.0f32).to_bits());
+        assert_eq!(f16_to_f32(0x3C00), 1.0);
+        assert_eq!(f16_to_f32(0xBC00), -1.0);
+        assert_eq!(f16_to_f32(0x3800), 0.5);
+        // Values above 1, up to the largest f16.
+        assert_eq!(f16_to_f32(0x4500), 5.0);
+        assert_eq!(f16_to_f32(0x7BFF), 65504.0);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+    }
+
+    #[test]
+    fn f16_subnormals_keep_their_value() {
+        let smallest = 2.0f32.powi(-24);
+        assert_eq!(f16_to_f32(0x0001), smallest);
+        assert_eq!(f16_to_f32(0x8001), -smallest);
+        assert_eq!(f16_to_f32(0x03FF), 1023.0 * smallest);
+        // The largest subnormal is followed by the smallest normal.
+        assert_eq!(f16_to_f32(0x0400), 1024.0 * smallest);
+    }
+
+    #[test]
+    fn f16_infinities_and_nans_stay_what_they_are() {
+        assert_eq!(f16_to_f32(0x7C00), f32::INFINITY);
+        assert_eq!(f16_to_f32(0xFC00), f32::NEG_INFINITY);
+        assert!(f16_to_f32(0x7E00).is_nan());
+        assert!(f16_to_f32(0xFE01).is_nan());
+    }
+
+    /// A row of RGBA16F pixels, each channel as f16 bits.
+    fn rgba16f_row(pixels: &[[u16; 4]]) -> Vec<u8> {
+        pixels.iter().flatten().flat_map(|channel| channel.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn rgba16f_pixels_are_encoded_as_srgb() {
+        // Red at 1.0, green at 0.5, blue at 0.0 and alpha at 0.5.
+        let mut data = rgba16f_row(&[[0x3C00, 0x3800, 0x0000, 0x3800]]);
+        rgba16f_to_rgba8(&mut data, 1, 1, 8, SCRGB_WHITE_NITS);
+        assert_eq!(data, [255, 188, 0, 128]);
+    }
+
+    #[test]
+    fn rgba16f_values_outside_of_0_to_1_are_clamped() {
+        let mut data = rgba16f_row(&[
+            // 2.0, 65504.0 and infinity, with an alpha above 1.
+            [0x4000, 0x7BFF, 0x7C00, 0x4000],
+            // -1.0, negative infinity and NaN, with a negative alpha.
+            [0xBC00, 0xFC00, 0x7E00, 0xBC00],
+            // Subnormals are all but black.
+            [0x0001, 0x8001, 0x03FF, 0x3C00],
+        ]);
+        rgba16f_to_rgba8(&mut data, 3, 1, 24, SCRGB_WHITE_NITS);
+        assert_eq!(data, [[255, 255, 255, 255], [0, 0, 0, 0], [0, 0, 0, 255]].concat());
+    }
+
+    #[test]
+    fn rgba16f_is_scaled_to_the_sdr_white_level() {
+        // At twice the white level of scRGB, 1.0 is only half as bright.
+        let mut data = rgba16f_row(&[[0x3C00, 0x4000, 0x3800, 0x3C00]]);
+        rgba16f_to_rgba8(&mut data, 1, 1, 8, 2.0 * SCRGB_WHITE_NITS);
+        assert_eq!(data, [188, 255, 137, 255]);
+    }
+
+    #[test]
+    fn rgba16f_rows_lose_their_padding() {
+        let padding = [0xEE; 8];
+        let mut data = [
+            rgba16f_row(&[[0x3C00, 0x0000, 0x0000, 0x3C00]]),
+            padding.to_vec(),
+            rgba16f_row(&[[0x0000, 0x0000, 0x3C00, 0x3C00]]),
+        ]
+        .concat();
+        rgba16f_to_rgba8(&mut data, 1, 2, 16, SCRGB_WHITE_NITS);
+        assert_eq!(data, [255, 0, 0, 255, 0, 0, 255, 255]);
     }
 }
@@ -1,4 +1,9 @@
-pub(crate) mod image_utils;
+pub mod clipboard;
+#[cfg(target_os = "windows")]
+pub mod com_thread;
+pub mod frame_diff;
+pub mod image_utils;
+pub mod output_path;
 
-#[allow(dead_code)]
-mod unsafe_send_wrapper;
+#[cfg(target_os = "windows")]
+pub(crate) mod unsafe_send_wrapper;
@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+#[cfg(not(target_os = "windows"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(target_os = "windows")]
+use windows::Win32::{
+    System::{Com::CoTaskMemFree, SystemInformation::GetLocalTime},
+    UI::Shell::{FOLDERID_Pictures, KF_FLAG_DEFAULT, SHGetKnownFolderPath},
+};
+#[cfg(target_os = "windows")]
+use windows_core::GUID;
+
+use crate::utils::image_utils::ImageFileFormat;
+
+/// A folder of the user like `FOLDERID_Pictures`.
+#[cfg(target_os = "windows")]
+pub fn known_folder(id: &GUID) -> std::io::Result<PathBuf> {
+    let folder = unsafe {
+        let path = SHGetKnownFolderPath(id, KF_FLAG_DEFAULT, None)?;
+        let folder = path.to_string();
+        CoTaskMemFree(Some(path.as_ptr() as *const core::ffi::c_void));
+        folder.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?
+    };
+    Ok(PathBuf::from(folder))
+}
+
+/// A file name from the local time, e.g. `loki-2025-01-31_18-04-05.png`, which sorts in the order of creation.
+#[cfg(target_os = "windows")]
+pub fn timestamped_file_name(extension: &str) -> String {
+    let time = unsafe { GetLocalTime() };
+    format!(
+        "loki-{:04}-{:02}-{:02}_{:02}-{:02}-{:02}.{}",
+        time.wYear, time.wMonth, time.wDay, time.wHour, time.wMinute, time.wSecond, extension
+    )
+}
+
+/// A file name from the time in UTC, e.g. `loki-2025-01-31_18-04-05.png`, which sorts in the order of creation.
+/// The local time zone isn't known without the platform, so unlike on Windows this isn't the local time.
+#[cfg(not(target_os = "windows"))]
+pub fn timestamped_file_name(extension: &str) -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let time_of_day = seconds % 86_400;
+    format!(
+        "loki-{:04}-{:02}-{:02}_{:02}-{:02}-{:02}.{}",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60,
+        extension
+    )
+}
+
+/// The date of a day counted from 1970-01-01, in the proleptic Gregorian calendar.
+#[cfg(not(target_os = "windows"))]
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Counts from 0000-03-01 so leap days end the year, in eras of 400 years which all have the same length.
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month =
+        if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// A timestamped file in the Pictures folder of the user, for saving a snapshot without asking where.
+#[cfg(target_os = "windows")]
+pub fn default_snapshot_path(format: ImageFileFormat) -> std::io::Result<PathBuf> {
+    Ok(known_folder(&FOLDERID_Pictures)?.join(timestamped_file_name(format.extension())))
+}
+
+/// A timestamped file in the Pictures folder of the user, for saving a snapshot without asking where.
+#[cfg(not(target_os = "windows"))]
+pub fn default_snapshot_path(format: ImageFileFormat) -> std::io::Result<PathBuf> {
+    let pictures = dirs::picture_dir()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No pictures folder"))?;
+    Ok(pictures.join(timestamped_file_name(format.extension())))
+}
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_map_to_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(59), (1970, 3, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(20_119), (2025, 1, 31));
+    }
+}
@@ -0,0 +1,632 @@
+use std::{cell::Cell, fmt::Display, sync::Arc, time::Instant};
+
+use bytes::Bytes;
+use iced::{
+    Element, Event, Length, Point, Rectangle, Size, Theme, Vector, advanced,
+    advanced::{
+        Clipboard, Shell, Widget,
+        image::FilterMethod,
+        layout::{self, Layout},
+        mouse, renderer,
+        widget::{Tree, tree},
+    },
+};
+
+use crate::{
+    capture_providers::shared::{FrameTracer, PixelFormat, Rect, Stage, Vector2},
+    utils::image_utils::pack_rows,
+};
+
+/// Draws are counted and logged every this many, along with the number of uploads and their bytes per second.
+const STATS_LOG_INTERVAL: u64 = 600;
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 8.0;
+/// Zoom factor per line scrolled.
+const ZOOM_STEP: f32 = 1.1;
+/// Pixel scroll deltas, e.g. from touchpads, are converted to lines with this many pixels per line.
+const PIXELS_PER_LINE: f32 = 50.0;
+
+/// How the frame is placed in the space the viewer is given. Space the frame doesn't cover is filled with the
+/// background color of the theme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameFit {
+    /// Scaled to fit entirely, leaving bars on the sides it doesn't reach.
+    #[default]
+    Fit,
+    /// Scaled to cover the space entirely, cropping what sticks out.
+    Fill,
+    /// Scaled to the space, ignoring the aspect ratio.
+    Stretch,
+    /// One frame pixel per physical pixel. Frames larger than the space scroll with the mouse wheel and by
+    /// dragging.
+    ActualSize,
+}
+
+impl FrameFit {
+    pub const ALL: [FrameFit; 4] =
+        [FrameFit::Fit, FrameFit::Fill, FrameFit::Stretch, FrameFit::ActualSize];
+
+    /// Where a frame of `size` pixels goes within `bounds`, centered on the axes it doesn't fill.
+    fn place(self, size: Size, bounds: Rectangle, scale_factor: f32) -> Rectangle {
+        if size.width <= 0.0 || size.height <= 0.0 {
+            return Rectangle::new(bounds.center(), Size::ZERO);
+        }
+        let scaled = |scale: f32| Size::new(size.width * scale, size.height * scale);
+        let placed = match self {
+            Self::Fit => scaled((bounds.width / size.width).min(bounds.height / size.height)),
+            Self::Fill => scaled((bounds.width / size.width).max(bounds.height / size.height)),
+            Self::Stretch => return bounds,
+            Self::ActualSize => {
+                let placed = scaled(1.0 / scale_factor);
+                // Larger frames start at their top left corner and are scrolled from there.
+                let x = if placed.width > bounds.width {
+                    bounds.x
+                } else {
+                    bounds.center_x() - placed.width / 2.0
+                };
+                let y = if placed.height > bounds.height {
+                    bounds.y
+                } else {
+                    bounds.center_y() - placed.height / 2.0
+                };
+                return Rectangle::new(Point::new(x, y), placed);
+            }
+        };
+        Rectangle::new(
+            Point::new(
+                bounds.center_x() - placed.width / 2.0,
+                bounds.center_y() - placed.height / 2.0,
+            ),
+            placed,
+        )
+    }
+}
+
+impl Display for FrameFit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Fit => "Fit",
+            Self::Fill => "Fill",
+            Self::Stretch => "Stretch",
+            Self::ActualSize => "Actual size",
+        })
+    }
+}
+
+/// Rounds the edges of the rectangle to physical pixels, so the image isn't resampled at half-pixel offsets.
+fn snap_to_pixels(rect: Rectangle, scale_factor: f32) -> Rectangle {
+    let snap = |value: f32| (value * scale_factor).round() / scale_factor;
+    let (x, y) = (snap(rect.x), snap(rect.y));
+    Rectangle::new(
+        Point::new(x, y),
+        Size::new(snap(rect.x + rect.width) - x, snap(rect.y + rect.height) - y),
+    )
+}
+
+pub struct FrameViewer<'a, Message> {
+    frame_data: Bytes,
+    width: u32,
+    height: u32,
+    /// Bytes from the start of one row to the next, `None` if the rows are tightly packed.
+    stride: Option<usize>,
+    /// Changes whenever the frame data does, so unchanged frames aren't uploaded again.
+    generation: u64,
+    dirty_rects: Option<Vec<Rect<i32>>>,
+    zoom_enabled: bool,
+    fit: FrameFit,
+    /// Physical pixels per logical pixel of the window.
+    scale_factor: f32,
+    /// Told when the frame with the sequence number is uploaded and drawn.
+    tracer: Option<(Arc<dyn FrameTracer>, u64)>,
+    on_zoom: Option<Box<dyn Fn(f32) -> Message + 'a>>,
+}
+
+impl<'a, Message> FrameViewer<'a, Message> {
+    pub fn new(frame_data: Bytes, width: u32, height: u32, generation: u64) -> Self {
+        Self {
+            frame_data,
+            width,
+            height,
+            stride: None,
+            generation,
+            dirty_rects: None,
+            zoom_enabled: false,
+            fit: FrameFit::default(),
+            scale_factor: 1.0,
+            tracer: None,
+            on_zoom: None,
+        }
+    }
+
+    /// Bytes from the start of one row of the frame data to the next, for frames whose rows are padded, e.g. as
+    /// read back with `ReadbackMode::Strided`. The padding is cropped before upload, as the renderer only takes
+    /// tightly packed rows. Defaults to 4 bytes per pixel of the width.
+    pub fn stride(mut self, stride: usize) -> Self {
+        self.stride = Some(stride);
+        self
+    }
+
+    /// The regions that changed since the frame of the previous generation, `None` if unknown. An empty list
+    /// means nothing changed, in which case the uploaded image is kept.
+    /// The renderer has no way to update part of an image, so any change still uploads the whole frame.
+    pub fn dirty_rects(mut self, dirty_rects: Option<Vec<Rect<i32>>>) -> Self {
+        self.dirty_rects = dirty_rects;
+        self
+    }
+
+    /// Enables zooming around the cursor with the mouse wheel, from a quarter to eight times the fit size, and
+    /// panning by dragging. Double-clicking fits the frame again. The zoom is kept across frames.
+    /// Without this, the frame is always fit to the available space.
+    pub fn zoom_enabled(mut self, enabled: bool) -> Self {
+        self.zoom_enabled = enabled;
+        self
+    }
+
+    /// Called with the new zoom factor whenever the user zooms, including back to 1 by double-clicking. Changing the
+    /// fit resets the zoom without a call.
+    pub fn on_zoom(mut self, on_zoom: impl Fn(f32) -> Message + 'a) -> Self {
+        self.on_zoom = Some(Box::new(on_zoom));
+        self
+    }
+
+    /// Changing the fit resets the zoom.
+    pub fn fit(mut self, fit: FrameFit) -> Self {
+        self.fit = fit;
+        self
+    }
+
+    /// The scale factor of the window, which `ActualSize` and the pixel snapping of the frame depend on.
+    pub fn scale_factor(mut self, scale_factor: f32) -> Self {
+        if scale_factor > 0.0 {
+            self.scale_factor = scale_factor;
+        }
+        self
+    }
+
+    fn publish_zoom(&self, zoom: f32, shell: &mut Shell<'_, Message>) {
+        if let Some(on_zoom) = &self.on_zoom {
+            shell.publish(on_zoom(zoom));
+        }
+    }
+
+    /// Where the frame goes within the layout bounds at a zoom of 1.
+    fn base_bounds(&self, bounds: Rectangle) -> Rectangle {
+        let size = Size::new(self.width as f32, self.height as f32);
+        self.fit.place(size, bounds, self.scale_factor)
+    }
+
+    /// The frame data without its row padding, copied only if there is any.
+    fn packed_data(&self) -> Bytes {
+        let row_bytes = PixelFormat::RGBA8.row_bytes(self.width as usize);
+        match self.stride {
+            Some(stride) if stride > row_bytes => {
+                pack_rows(&self.frame_data, row_bytes, stride, self.height as usize).into()
+            }
+            _ => self.frame_data.clone(),
+        }
+    }
+
+    /// Records the `Uploaded` and `Drawn` stages of the frame with the given sequence number.
+    pub fn traced(mut self, tracer: Arc<dyn FrameTracer>, sequence: u64) -> Self {
+        self.tracer = Some((tracer, sequence));
+        self
+    }
+}
+
+pub fn frame_viewer<'a, Message>(
+    frame_data: Bytes,
+    width: u32,
+    height: u32,
+    generation: u64,
+    fit: FrameFit,
+) -> FrameViewer<'a, Message> {
+    FrameViewer::new(frame_data, width, height, generation).fit(fit)
+}
+
+/// Keeps the image handle between draws. Redraws, e.g. from cursor movement or resizing, reuse the handle,
+/// which the renderer has already uploaded.
+#[derive(Default)]
+struct State {
+    handle: Option<(u64, advanced::image::Handle)>,
+    /// Size of the uploaded image, dirty rects only apply to frames of the same size.
+    size: (u32, u32),
+    uploads: u64,
+    /// Generations that were dropped because nothing changed.
+    skipped: u64,
+    draws: Cell<u64>,
+    /// The frame that was uploaded and is yet to be drawn, if it is traced.
+    undrawn: Cell<Option<(Arc<dyn FrameTracer>, u64)>>,
+    transfer: TransferStats,
+    view: ViewState,
+    fit: FrameFit,
+}
+
+/// What an upload per changed frame costs, compared to uploading only the dirty rects.
+struct TransferStats {
+    uploaded_bytes: u64,
+    /// Bytes covered by the dirty rects, the whole frame for uploads without them.
+    dirty_bytes: u64,
+    /// Time and byte counts at the last log, to report rates since then.
+    last_log: Cell<(Instant, u64, u64)>,
+}
+
+impl Default for TransferStats {
+    fn default() -> Self {
+        Self { uploaded_bytes: 0, dirty_bytes: 0, last_log: Cell::new((Instant::now(), 0, 0)) }
+    }
+}
+
+impl TransferStats {
+    /// Megabytes per second uploaded and dirty since the last call.
+    fn rates(&self) -> (f64, f64) {
+        let (since, uploaded_bytes, dirty_bytes) = self.last_log.get();
+        self.last_log.set((Instant::now(), self.uploaded_bytes, self.dirty_bytes));
+        let seconds = since.elapsed().as_secs_f64().max(f64::EPSILON);
+        let rate = |bytes: u64| bytes as f64 / seconds / 1_000_000.0;
+        (rate(self.uploaded_bytes - uploaded_bytes), rate(self.dirty_bytes - dirty_bytes))
+    }
+}
+
+/// Zoom and pan of the frame, on top of its placement by the `FrameFit`. Scrolling an `ActualSize` frame pans it.
+struct ViewState {
+    zoom: f32,
+    /// Position of the top left corner of the frame, relative to where the fit places it.
+    offset: Vector,
+    /// Last cursor position while dragging.
+    drag_origin: Option<Point>,
+    last_click: Option<mouse::Click>,
+}
+
+impl Default for ViewState {
+    fn default() -> Self {
+        Self { zoom: 1.0, offset: Vector::ZERO, drag_origin: None, last_click: None }
+    }
+}
+
+impl ViewState {
+    fn reset(&mut self) {
+        *self = Self { last_click: self.last_click, ..Self::default() };
+    }
+
+    /// Where the frame is drawn, given where the fit places it.
+    fn image_bounds(&self, base: Rectangle) -> Rectangle {
+        Rectangle::new(
+            base.position() + self.offset,
+            Size::new(base.width * self.zoom, base.height * self.zoom),
+        )
+    }
+
+    /// Zooms by the factor while keeping the point under the cursor in place. Returns whether the zoom changed,
+    /// which it doesn't at the limits.
+    fn zoom_at(&mut self, factor: f32, cursor: Point, base: Rectangle, bounds: Rectangle) -> bool {
+        let zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        if zoom == self.zoom {
+            return false;
+        }
+
+        let anchor = cursor - base.position();
+        let scale = zoom / self.zoom;
+        self.offset = anchor - (anchor - self.offset) * scale;
+        self.zoom = zoom;
+        self.clamp_offset(base, bounds);
+        true
+    }
+
+    fn pan(&mut self, delta: Vector, base: Rectangle, bounds: Rectangle) {
+        self.offset = self.offset + delta;
+        self.clamp_offset(base, bounds);
+    }
+
+    /// Whether the frame can be dragged around, as it sticks out of the bounds.
+    fn pannable(&self, base: Rectangle, bounds: Rectangle) -> bool {
+        let size = self.image_bounds(base).size();
+        size.width > bounds.width || size.height > bounds.height
+    }
+
+    /// Keeps the frame over the center of the bounds, so its edges can't be dragged past it.
+    fn clamp_offset(&mut self, base: Rectangle, bounds: Rectangle) {
+        let size = self.image_bounds(base).size();
+        let center = bounds.center() - base.position();
+        self.offset = Vector::new(
+            self.offset.x.clamp(center.x - size.width, center.x),
+            self.offset.y.clamp(center.y - size.height, center.y),
+        );
+    }
+
+    /// Keeps the bounds covered on the axes the frame is larger on, like a scrolled view, and the frame where the
+    /// fit placed it on the others.
+    fn clamp_scroll(&mut self, base: Rectangle, bounds: Rectangle) {
+        let size = self.image_bounds(base).size();
+        let min = bounds.position() - base.position();
+        let clamp = |offset: f32, min: f32, size: f32, space: f32| {
+            if size > space { offset.clamp(min + space - size, min) } else { 0.0 }
+        };
+        self.offset = Vector::new(
+            clamp(self.offset.x, min.x, size.width, bounds.width),
+            clamp(self.offset.y, min.y, size.height, bounds.height),
+        );
+    }
+}
+
+impl State {
+    fn update(&mut self, viewer: &FrameViewer) {
+        if self.fit != viewer.fit {
+            self.fit = viewer.fit;
+            self.view.reset();
+        }
+        let size = (viewer.width, viewer.height);
+        // An empty frame has nothing to upload, and the layout leaves no room to draw the previous one.
+        if viewer.width == 0 || viewer.height == 0 {
+            self.handle = None;
+            return;
+        }
+        let dirty_bytes = match (&mut self.handle, &viewer.dirty_rects) {
+            (Some((generation, _)), _) if *generation == viewer.generation => return,
+            (Some((generation, _)), Some(dirty_rects)) if self.size == size => {
+                let dirty_bytes = Self::dirty_bytes(dirty_rects, size);
+                if dirty_bytes == 0 {
+                    *generation = viewer.generation;
+                    self.skipped += 1;
+                    return;
+                }
+                dirty_bytes
+            }
+            _ => {
+                PixelFormat::RGBA8.frame_bytes(viewer.width as usize, viewer.height as usize) as u64
+            }
+        };
+
+        let frame_data = viewer.packed_data();
+        let uploaded_bytes = frame_data.len() as u64;
+        let handle = advanced::image::Handle::from_rgba(viewer.width, viewer.height, frame_data);
+        self.handle = Some((viewer.generation, handle));
+        self.size = size;
+        self.uploads += 1;
+        if let Some((tracer, sequence)) = &viewer.tracer {
+            tracer.record(*sequence, Stage::Uploaded, Instant::now());
+            self.undrawn.set(Some((tracer.clone(), *sequence)));
+        }
+        self.transfer.uploaded_bytes += uploaded_bytes;
+        self.transfer.dirty_bytes += dirty_bytes;
+    }
+
+    /// Bytes of RGBA pixels covered by the rects within the frame. Overlaps are counted twice, which is what
+    /// uploading each rect would cost.
+    fn dirty_bytes(dirty_rects: &[Rect<i32>], (width, height): (u32, u32)) -> u64 {
+        let bounds = Vector2::new(width, height).cast::<i32>();
+        let bytes_per_pixel = PixelFormat::RGBA8.row_bytes(1) as u64;
+        dirty_rects
+            .iter()
+            .filter_map(|rect| rect.clamp_to(bounds))
+            .map(|rect| rect.area() * bytes_per_pixel)
+            .sum()
+    }
+}
+
+// The theme is concrete so the letterbox bars can take its background color.
+impl<Message, Renderer> Widget<Message, Theme, Renderer> for FrameViewer<'_, Message>
+where
+    Renderer: advanced::Renderer
+        + iced::advanced::image::Renderer<Handle = iced::advanced::image::Handle>,
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn size(&self) -> iced::Size<Length> {
+        iced::Size::new(Length::Fill, Length::Fill)
+    }
+
+    fn update(
+        &mut self,
+        tree: &mut Tree,
+        event: &Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _renderer: &Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        _viewport: &Rectangle,
+    ) {
+        let scrolls = self.fit == FrameFit::ActualSize;
+        if !self.zoom_enabled && !scrolls {
+            return;
+        }
+
+        let view = &mut tree.state.downcast_mut::<State>().view;
+        let bounds = layout.bounds();
+        let base = self.base_bounds(bounds);
+        let Event::Mouse(event) = event else {
+            return;
+        };
+
+        match event {
+            mouse::Event::WheelScrolled { delta } => {
+                let Some(position) = cursor.position_over(bounds) else {
+                    return;
+                };
+                if scrolls {
+                    let delta = match delta {
+                        mouse::ScrollDelta::Lines { x, y } => Vector::new(*x, *y) * PIXELS_PER_LINE,
+                        mouse::ScrollDelta::Pixels { x, y } => Vector::new(*x, *y),
+                    };
+                    view.offset = view.offset + delta;
+                    view.clamp_scroll(base, bounds);
+                } else {
+                    let lines = match delta {
+                        mouse::ScrollDelta::Lines { y, .. } => *y,
+                        mouse::ScrollDelta::Pixels { y, .. } => *y / PIXELS_PER_LINE,
+                    };
+                    if view.zoom_at(ZOOM_STEP.powf(lines), position, base, bounds) {
+                        self.publish_zoom(view.zoom, shell);
+                    }
+                }
+                shell.capture_event();
+                shell.request_redraw();
+            }
+            mouse::Event::ButtonPressed(mouse::Button::Left) => {
+                let Some(position) = cursor.position_over(bounds) else {
+                    return;
+                };
+                let click = mouse::Click::new(position, mouse::Button::Left, view.last_click);
+                view.last_click = Some(click);
+                if click.kind() == mouse::click::Kind::Double && !scrolls {
+                    view.reset();
+                    self.publish_zoom(view.zoom, shell);
+                    shell.request_redraw();
+                } else if view.zoom != 1.0 || view.pannable(base, bounds) {
+                    view.drag_origin = Some(position);
+                }
+                shell.capture_event();
+            }
+            mouse::Event::ButtonReleased(mouse::Button::Left) => {
+                view.drag_origin = None;
+            }
+            mouse::Event::CursorMoved { position } => {
+                if let Some(origin) = view.drag_origin {
+                    if scrolls {
+                        view.offset = view.offset + (*position - origin);
+                        view.clamp_scroll(base, bounds);
+                    } else {
+                        view.pan(*position - origin, base, bounds);
+                    }
+                    view.drag_origin = Some(*position);
+                    shell.request_redraw();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &Renderer,
+    ) -> mouse::Interaction {
+        let view = &tree.state.downcast_ref::<State>().view;
+        let bounds = layout.bounds();
+        let draggable = match self.fit {
+            FrameFit::ActualSize => view.pannable(self.base_bounds(bounds), bounds),
+            _ => self.zoom_enabled && view.zoom != 1.0,
+        };
+        if view.drag_origin.is_some() {
+            mouse::Interaction::Grabbing
+        } else if draggable && cursor.is_over(bounds) {
+            mouse::Interaction::Grab
+        } else {
+            mouse::Interaction::None
+        }
+    }
+
+    fn layout(
+        &mut self,
+        tree: &mut Tree,
+        _renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        // Layout runs whenever the view is rebuilt, which is the only time the frame can change.
+        tree.state.downcast_mut::<State>().update(self);
+
+        if self.width == 0 || self.height == 0 {
+            return layout::Node::new(Size::ZERO);
+        }
+
+        // The viewer takes all the space it's given and places the frame within it. Unbounded axes get the size
+        // of the frame.
+        let max_size = limits.max();
+        let frame_size = Size::new(
+            self.width as f32 / self.scale_factor,
+            self.height as f32 / self.scale_factor,
+        );
+        let size = Size::new(
+            if max_size.width.is_finite() { max_size.width } else { frame_size.width },
+            if max_size.height.is_finite() { max_size.height } else { frame_size.height },
+        );
+
+        layout::Node::new(size)
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        theme: &Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+    ) {
+        let state = tree.state.downcast_ref::<State>();
+        let draws = state.draws.get() + 1;
+        state.draws.set(draws);
+        if draws.is_multiple_of(STATS_LOG_INTERVAL) {
+            let (uploaded, dirty) = state.transfer.rates();
+            tracing::debug!(
+                "Frame viewer: {} uploads, {} unchanged frames in {} draws. Uploading {:.1} MB/s, of which \
+                 {:.1} MB/s are dirty",
+                state.uploads,
+                state.skipped,
+                draws,
+                uploaded,
+                dirty
+            );
+        }
+
+        let img_handle = match &state.handle {
+            Some((_, handle)) => handle,
+            None => return,
+        };
+        let alloc = match renderer.load_image(img_handle) {
+            Ok(alloc) => alloc,
+            Err(err) => {
+                tracing::error!("Failed to allocate image: {}", err);
+                return;
+            }
+        };
+        let bounds = layout.bounds();
+        let base = self.base_bounds(bounds);
+        let image_bounds = if self.zoom_enabled || self.fit == FrameFit::ActualSize {
+            state.view.image_bounds(base)
+        } else {
+            base
+        };
+        let image_bounds = snap_to_pixels(image_bounds, self.scale_factor);
+        if !image_bounds.contains(bounds.position())
+            || !image_bounds.contains(Point::new(bounds.x + bounds.width, bounds.y + bounds.height))
+        {
+            renderer.fill_quad(
+                renderer::Quad { bounds, ..renderer::Quad::default() },
+                theme.palette().background,
+            );
+        }
+        let mut img = iced_core::Image::new(alloc.handle());
+        // Zoomed in past 100%, individual pixels should stay visible. At 100% they map to physical pixels exactly.
+        if image_bounds.width * self.scale_factor >= self.width as f32 {
+            img = img.filter_method(FilterMethod::Nearest);
+        }
+        // Whatever lies outside the layout bounds is clipped away.
+        renderer.draw_image(img, image_bounds, bounds);
+        if let Some((tracer, sequence)) = state.undrawn.take() {
+            tracer.record(sequence, Stage::Drawn, Instant::now());
+        }
+    }
+}
+
+impl<'a, Message, Renderer> From<FrameViewer<'a, Message>> for Element<'a, Message, Theme, Renderer>
+where
+    Renderer: advanced::Renderer
+        + iced::advanced::image::Renderer<Handle = iced::advanced::image::Handle>
+        + 'a,
+    Message: 'a,
+{
+    fn from(widget: FrameViewer<'a, Message>) -> Self {
+        Self::new(widget)
+    }
+}
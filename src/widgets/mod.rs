@@ -0,0 +1,3 @@
+//! iced widgets for showing captured frames, as used by the app and the examples.
+
+pub mod frame_viewer;